@@ -0,0 +1,123 @@
+//! Storing the gift wraps of an initial sync, one transaction per event against one for the
+//! whole batch.
+//!
+//! Run with `cargo bench -p hoot-core`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use hoot_core::account_manager::unwrap_gift_wrap;
+use hoot_core::db::{Db, EventToStore};
+use hoot_core::mail_event::MailMessage;
+use nostr::Keys;
+use std::path::PathBuf;
+
+/// Gift wraps for `recipient`, unwrapped the way ingestion hands them to the database.
+fn mailbox(recipient: &Keys, size: usize) -> Vec<EventToStore> {
+    let sender = Keys::generate();
+    (0..size)
+        .map(|i| {
+            let mut message = MailMessage {
+                id: None,
+                created_at: None,
+                author: None,
+                to: vec![recipient.public_key()],
+                cc: Vec::new(),
+                bcc: Vec::new(),
+                parent_events: None,
+                subject: format!("Message {}", i),
+                content: "Lorem ipsum dolor sit amet. ".repeat(20),
+                attachments: Vec::new(),
+                articles: Vec::new(),
+                quote: None,
+                payload: None,
+                email_to: Vec::new(),
+                email_from: None,
+                tag_relays: Default::default(),
+            };
+            let wrap = message
+                .to_events(&sender, std::time::Duration::ZERO)
+                .remove(&recipient.public_key())
+                .expect("a gift wrap for the recipient");
+            let unwrapped =
+                unwrap_gift_wrap(std::slice::from_ref(recipient), &wrap).expect("unwrappable");
+            EventToStore {
+                event: wrap,
+                unwrapped: Some(unwrapped),
+                recipient: Some(recipient.public_key().to_hex()),
+            }
+        })
+        .collect()
+}
+
+/// A fresh database on disk, where commits cost what they do for real.
+struct TempDb {
+    db: Db,
+    path: PathBuf,
+}
+
+impl TempDb {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "hoot-bench-{}-{}.db",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let mut db = Db::new(path.clone()).expect("open database");
+        db.unlock_with_password("bench".to_string())
+            .expect("unlock database");
+        Self { db, path }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path.display(), suffix));
+        }
+    }
+}
+
+fn store_events(c: &mut Criterion) {
+    let recipient = Keys::generate();
+    let mut group = c.benchmark_group("store_events");
+    group.sample_size(10);
+    for size in [100, 1000] {
+        let events = mailbox(&recipient, size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("one_by_one", size),
+            &events,
+            |b, events| {
+                b.iter_batched(
+                    TempDb::new,
+                    |temp| {
+                        for item in events {
+                            temp.db
+                                .store_event(
+                                    &item.event,
+                                    item.unwrapped.as_ref(),
+                                    item.recipient.as_deref(),
+                                )
+                                .expect("store event");
+                        }
+                        temp
+                    },
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("batched", size), &events, |b, events| {
+            b.iter_batched(
+                TempDb::new,
+                |temp| {
+                    temp.db.store_events(events).expect("store events");
+                    temp
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, store_events);
+criterion_main!(benches);
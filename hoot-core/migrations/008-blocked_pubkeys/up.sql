@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS blocked_pubkeys (
+    pubkey TEXT PRIMARY KEY,
+    blocked_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
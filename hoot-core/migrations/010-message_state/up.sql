@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS message_state (
+    event_id TEXT PRIMARY KEY,
+    read_at INTEGER
+);
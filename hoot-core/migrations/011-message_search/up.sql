@@ -0,0 +1,38 @@
+ALTER TABLE message_state ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;
+
+-- Full text index over the subject and body of mail messages.
+CREATE VIRTUAL TABLE IF NOT EXISTS message_search USING fts5 (
+    event_id UNINDEXED,
+    subject,
+    content
+);
+
+INSERT INTO message_search (event_id, subject, content)
+SELECT
+    e.id,
+    COALESCE((SELECT jsonb_extract(tag.value, '$[1]')
+              FROM json_each(e.tags) AS tag
+              WHERE jsonb_extract(tag.value, '$[0]') = 'subject'
+              LIMIT 1), ''),
+    e.content
+FROM events e
+WHERE e.kind = 2024;
+
+CREATE TRIGGER IF NOT EXISTS message_search_insert AFTER INSERT ON events
+WHEN NEW.kind = 2024
+BEGIN
+    INSERT INTO message_search (event_id, subject, content)
+    VALUES (
+        NEW.id,
+        COALESCE((SELECT jsonb_extract(tag.value, '$[1]')
+                  FROM json_each(NEW.tags) AS tag
+                  WHERE jsonb_extract(tag.value, '$[0]') = 'subject'
+                  LIMIT 1), ''),
+        NEW.content
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_search_delete AFTER DELETE ON events
+BEGIN
+    DELETE FROM message_search WHERE event_id = OLD.id;
+END;
@@ -0,0 +1 @@
+ALTER TABLE message_state ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE profile_metadata ADD COLUMN nip05 TEXT;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS event_sources (
+    event_id TEXT NOT NULL,
+    relay_url TEXT NOT NULL,
+    first_seen_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    PRIMARY KEY (event_id, relay_url)
+);
+
+-- Sources are only interesting while we keep the event. Mail arrives as gift wraps, which
+-- are recorded under their own id.
+CREATE TRIGGER IF NOT EXISTS event_sources_cleanup AFTER DELETE ON events BEGIN
+    DELETE FROM event_sources
+    WHERE event_id = old.id
+    OR event_id IN (SELECT wrap_id FROM gift_wrap_map WHERE inner_id = old.id);
+END;
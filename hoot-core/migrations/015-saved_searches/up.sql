@@ -0,0 +1,7 @@
+-- Searches kept as folders in the sidebar.
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    query TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
@@ -0,0 +1,16 @@
+-- The gift wraps we sent, one per recipient. The message itself is kept in events under
+-- event_id, so the copies fold into one entry in the Sent folder.
+CREATE TABLE IF NOT EXISTS sent_messages (
+    wrap_id TEXT PRIMARY KEY,
+    event_id TEXT NOT NULL,
+    recipient_pubkey TEXT NOT NULL,
+    -- How many relays took or refused the wrap, NULL until the send has settled.
+    accepted_by INTEGER,
+    rejected_by INTEGER
+);
+
+CREATE INDEX idx_sent_messages_event_id ON sent_messages (event_id);
+
+CREATE TRIGGER IF NOT EXISTS sent_messages_cleanup AFTER DELETE ON events BEGIN
+    DELETE FROM sent_messages WHERE event_id = old.id;
+END;
@@ -0,0 +1,6 @@
+-- When each thread's flags were last saved to or taken from relays, keyed by the event's d
+-- tag, so an older copy coming in late doesn't undo a newer change.
+CREATE TABLE IF NOT EXISTS flag_sync (
+    d_tag TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,5 @@
+-- The account we last wrote to each recipient as, so compose picks it again.
+CREATE TABLE IF NOT EXISTS sending_accounts (
+    recipient_pubkey TEXT PRIMARY KEY,
+    account_pubkey TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- The columns of the message list in the order they're shown, hidden ones included so they
+-- keep their place.
+CREATE TABLE IF NOT EXISTS inbox_columns (
+    name TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    visible INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+-- A plain text preview of each mail message for the message list, worked out when the
+-- message is stored. Mail already stored gets one the next time the database is unlocked.
+CREATE TABLE IF NOT EXISTS message_snippets (
+    event_id TEXT PRIMARY KEY,
+    snippet TEXT NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS message_snippets_delete AFTER DELETE ON events
+BEGIN
+    DELETE FROM message_snippets WHERE event_id = OLD.id;
+END;
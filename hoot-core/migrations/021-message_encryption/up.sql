@@ -0,0 +1,3 @@
+-- The scheme each gift wrap was encrypted with, see `Encryption::key`. Wraps stored before
+-- this was recorded are left NULL, their content is long gone.
+ALTER TABLE gift_wrap_map ADD COLUMN encryption TEXT;
@@ -0,0 +1,9 @@
+-- The last verification challenge we mailed each contact, until they answer it.
+CREATE TABLE IF NOT EXISTS verification_challenges (
+    pubkey TEXT PRIMARY KEY,
+    challenge TEXT NOT NULL,
+    sent_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+-- When the contact answered a challenge with their key, NULL until they have.
+ALTER TABLE contacts ADD COLUMN verified_at INTEGER;
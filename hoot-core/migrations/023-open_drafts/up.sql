@@ -0,0 +1,3 @@
+-- Set on drafts that were still open in a compose window when the app closed, so they can be
+-- reopened on the next start.
+ALTER TABLE drafts ADD COLUMN reopen INTEGER NOT NULL DEFAULT 0;
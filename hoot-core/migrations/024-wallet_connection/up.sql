@@ -0,0 +1,6 @@
+-- The wallet connected with Nostr Wallet Connect. The connection string holds the secret that
+-- spends from it, which is why it's kept here rather than with the preferences.
+CREATE TABLE wallet_connection (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    uri TEXT NOT NULL
+);
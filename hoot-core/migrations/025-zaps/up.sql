@@ -0,0 +1,14 @@
+-- Lightning addresses from profiles, for zapping the sender of a message.
+ALTER TABLE profile_metadata ADD COLUMN lud16 TEXT;
+
+-- Zap receipts for messages we sent, see hoot_core::zap.
+CREATE TABLE zaps (
+    receipt_id TEXT PRIMARY KEY,
+    event_id TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    amount_msats INTEGER NOT NULL,
+    comment TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX zaps_event_id ON zaps (event_id);
@@ -0,0 +1,14 @@
+-- Things worth the user's attention that happened while they were elsewhere, for the
+-- Notifications page.
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    -- What the notification is about, so the same thing isn't notified twice.
+    key TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    -- Where clicking it goes: a message id, or a relay url for relay notifications.
+    target TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    read INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (kind, key)
+);
@@ -0,0 +1,8 @@
+-- Labels the user put on threads. Each message carries its thread's labels, and replies
+-- that come in later take them from the messages they answer.
+CREATE TABLE message_labels (
+    event_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (event_id, label)
+);
+CREATE INDEX message_labels_label ON message_labels (label);
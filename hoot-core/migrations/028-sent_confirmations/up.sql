@@ -0,0 +1,3 @@
+-- How many of the relays that took a wrap still gave it back when asked for it afterwards,
+-- NULL until they've been asked.
+ALTER TABLE sent_messages ADD COLUMN confirmed_by INTEGER;
@@ -0,0 +1,7 @@
+-- Senders whose mail goes to the Focused inbox. Ranked senders are replaced each time the
+-- ranking runs, while the ones moved between Focused and Other by hand stay where they were put.
+CREATE TABLE sender_focus (
+    pubkey TEXT PRIMARY KEY,
+    focused INTEGER NOT NULL,
+    corrected INTEGER NOT NULL DEFAULT 0
+);
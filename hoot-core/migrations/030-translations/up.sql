@@ -0,0 +1,12 @@
+-- Translations of messages, so each one is only sent to the translation service once per
+-- language.
+CREATE TABLE translations (
+    event_id TEXT NOT NULL,
+    language TEXT NOT NULL,
+    text TEXT NOT NULL,
+    PRIMARY KEY (event_id, language)
+);
+
+CREATE TRIGGER IF NOT EXISTS translations_cleanup AFTER DELETE ON events BEGIN
+    DELETE FROM translations WHERE event_id = old.id;
+END;
@@ -0,0 +1,13 @@
+-- When mail for one of our accounts, or in a thread with a label, notifies. See
+-- notification_rules.rs.
+CREATE TABLE notification_rules (
+    -- 'account' with the account's pubkey as target, or 'label' with the label's name.
+    scope TEXT NOT NULL,
+    target TEXT NOT NULL,
+    -- 'always', 'contacts' or 'never'.
+    mode TEXT NOT NULL,
+    -- Local hours from which and until which notifications stay quiet, both NULL for none.
+    quiet_start INTEGER,
+    quiet_end INTEGER,
+    PRIMARY KEY (scope, target)
+);
@@ -0,0 +1,10 @@
+-- Subjects the user gave threads to keep them organized, shown instead of the one the
+-- messages carry. The messages themselves are left alone.
+CREATE TABLE thread_subjects (
+    root_id TEXT PRIMARY KEY,
+    subject TEXT NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS thread_subjects_cleanup AFTER DELETE ON events BEGIN
+    DELETE FROM thread_subjects WHERE root_id = old.id;
+END;
@@ -0,0 +1,3 @@
+-- Set on the wrap of a sent message that went to its own author, so the author's other
+-- devices see it in Sent. It's a copy, not a recipient.
+ALTER TABLE sent_messages ADD COLUMN copy INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Notes the user keeps on threads for themselves, like what to follow up on. They're never
+-- sent anywhere.
+CREATE TABLE thread_notes (
+    root_id TEXT PRIMARY KEY,
+    note TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS thread_notes_cleanup AFTER DELETE ON events BEGIN
+    DELETE FROM thread_notes WHERE root_id = old.id;
+END;
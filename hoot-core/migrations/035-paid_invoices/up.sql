@@ -0,0 +1,10 @@
+-- Invoices sent in messages that we paid from the wallet, so they're never paid twice. Kept when
+-- the message is deleted, in case it comes in again.
+CREATE TABLE paid_invoices (
+    event_id TEXT PRIMARY KEY,
+    invoice TEXT NOT NULL,
+    preimage TEXT NOT NULL,
+    paid_at INTEGER NOT NULL
+);
+
+CREATE INDEX paid_invoices_invoice ON paid_invoices (invoice);
@@ -19,6 +19,122 @@ pub fn validate_nsec(input: &str) -> Result<Keys, String> {
     }
 }
 
+/// Backup words for a new key (NIP-06). The system's randomness is mixed with `user_entropy`,
+/// e.g. mouse movements, so the words are as random as the better of the two.
+pub fn new_mnemonic(user_entropy: &[u8]) -> Result<String> {
+    use nostr::hashes::{sha256, Hash, HashEngine};
+    use rand::RngCore;
+    let mut system = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut system);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&system);
+    engine.input(user_entropy);
+    let mixed = sha256::Hash::from_engine(engine);
+    // 128 bits make the 12 words most signers use.
+    let mnemonic = nostr::bip39::Mnemonic::from_entropy(&mixed.as_byte_array()[..16])
+        .context("Couldn't make backup words")?;
+    Ok(mnemonic.to_string())
+}
+
+/// The key for `account` derived from backup words, at `m/44'/1237'/<account>'/0/0` as NIP-06
+/// says, so other clients restore the same key from the same words.
+pub fn keys_from_mnemonic(words: &str, account: u32) -> Result<Keys> {
+    use nostr::nips::nip06::FromMnemonic;
+    let words = normalize_mnemonic(words);
+    Keys::from_mnemonic_with_account(words.as_str(), None, Some(account))
+        .context("These aren't valid backup words")
+}
+
+/// Whether what the user typed is backup words rather than an nsec.
+pub fn looks_like_mnemonic(input: &str) -> bool {
+    input.split_whitespace().count() > 1
+}
+
+/// Like [`validate_nsec`], for backup words: says what's wrong with them, down to the word, or
+/// derives the key for `account`.
+pub fn validate_mnemonic(words: &str, account: u32) -> Result<Keys, String> {
+    use nostr::bip39::{Error, Mnemonic};
+    let normalized = normalize_mnemonic(words);
+    match Mnemonic::parse(normalized.as_str()) {
+        Ok(_) => {}
+        Err(Error::BadWordCount(count)) => {
+            return Err(format!(
+                "{} words, backup words come in 12, 15, 18, 21 or 24",
+                count
+            ))
+        }
+        Err(Error::UnknownWord(index)) => {
+            let word = normalized.split(' ').nth(index).unwrap_or_default();
+            return Err(format!(
+                "Word {} (\"{}\") isn't a backup word",
+                index + 1,
+                word
+            ));
+        }
+        Err(Error::InvalidChecksum) => {
+            return Err("The words don't add up, check for a typo or a swapped word".to_string())
+        }
+        Err(e) => return Err(format!("Invalid backup words: {}", e)),
+    }
+    keys_from_mnemonic(&normalized, account).map_err(|e| e.to_string())
+}
+
+fn normalize_mnemonic(words: &str) -> String {
+    words
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `keys`' private key encrypted with `password` as an ncryptsec (NIP-49), to back it up or
+/// carry it to another device.
+pub fn encrypted_backup(keys: &Keys, password: &str) -> Result<String> {
+    use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
+    use nostr::ToBech32;
+    if password.is_empty() {
+        anyhow::bail!("Pick a password to protect the key with");
+    }
+    // log_n 16 as in NIP-49's examples, which a phone decrypts in well under a second.
+    let encrypted = EncryptedSecretKey::new(keys.secret_key(), password, 16, KeySecurity::Medium)
+        .context("Couldn't encrypt the key")?;
+    Ok(encrypted.to_bech32()?)
+}
+
+/// Unwraps a gift wrap with whichever of `keys` it is addressed to.
+pub fn unwrap_gift_wrap(keys: &[Keys], gift_wrap: &Event) -> Result<UnwrappedGift> {
+    #[cfg(feature = "profiling")]
+    puffin::profile_function!();
+    let _timer = crate::metrics::start_timer(crate::metrics::GIFT_WRAP_UNWRAP);
+    let target_pubkey = gift_wrap
+        .tags
+        .iter()
+        .find(|tag| tag.kind() == "p".into())
+        .and_then(|tag| tag.content())
+        .with_context(|| {
+            format!(
+                "Could not find pubkey inside wrapped event `{}`",
+                gift_wrap.id
+            )
+        })?;
+
+    let target_key = keys
+        .iter()
+        .find(|key| key.public_key().to_string() == *target_pubkey)
+        .with_context(|| {
+            format!(
+                "Could not find pubkey `{}` inside wrapped event `{}`",
+                target_pubkey, gift_wrap.id
+            )
+        })?;
+
+    let unwrapped = UnwrappedGift::from_gift_wrap(target_key, gift_wrap)
+        .block_on()
+        .context("Couldn't unwrap gift")?;
+
+    Ok(unwrapped)
+}
+
 pub struct AccountManager {
     pub loaded_keys: Vec<Keys>,
 }
@@ -31,34 +147,7 @@ impl AccountManager {
     }
 
     pub fn unwrap_gift_wrap(&mut self, gift_wrap: &Event) -> Result<UnwrappedGift> {
-        let target_pubkey = gift_wrap
-            .tags
-            .iter()
-            .find(|tag| tag.kind() == "p".into())
-            .and_then(|tag| tag.content())
-            .with_context(|| {
-                format!(
-                    "Could not find pubkey inside wrapped event `{}`",
-                    gift_wrap.id
-                )
-            })?;
-
-        let target_key = self
-            .loaded_keys
-            .iter()
-            .find(|key| key.public_key().to_string() == *target_pubkey)
-            .with_context(|| {
-                format!(
-                    "Could not find pubkey `{}` inside wrapped event `{}`",
-                    target_pubkey, gift_wrap.id
-                )
-            })?;
-
-        let unwrapped = UnwrappedGift::from_gift_wrap(target_key, gift_wrap)
-            .block_on()
-            .context("Couldn't unwrap gift")?;
-
-        Ok(unwrapped)
+        unwrap_gift_wrap(&self.loaded_keys, gift_wrap)
     }
 
     pub fn generate_new_keys_and_save(&mut self, db: &Db) -> Result<Keys> {
@@ -104,7 +193,6 @@ impl AccountManager {
                 }
             };
 
-            debug!("key: {:?}", privkey.to_ascii_lowercase());
             let parsed_sk = match SecretKey::from_slice(&privkey) {
                 Ok(key) => key,
                 Err(e) => {
@@ -230,6 +318,48 @@ mod tests {
         keyring::set_default_credential_builder(Box::new(SharedMockCredentialBuilder));
     }
 
+    #[test]
+    fn mnemonics_restore_the_same_keys() -> Result<()> {
+        let words = new_mnemonic(b"mouse")?;
+        assert_eq!(words.split(' ').count(), 12);
+        let keys = keys_from_mnemonic(&words, 0)?;
+        assert_eq!(
+            keys_from_mnemonic(&format!("  {} ", words.to_uppercase()), 0)?.public_key(),
+            keys.public_key()
+        );
+        assert_ne!(
+            keys_from_mnemonic(&words, 1)?.public_key(),
+            keys.public_key()
+        );
+        assert_ne!(new_mnemonic(b"mouse")?, words);
+        assert!(keys_from_mnemonic("not backup words", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mnemonic_errors_point_at_the_problem() -> Result<()> {
+        let words = new_mnemonic(&[])?;
+        let keys = validate_mnemonic(&words, 2).map_err(anyhow::Error::msg)?;
+        assert_eq!(
+            keys.public_key(),
+            keys_from_mnemonic(&words, 2)?.public_key()
+        );
+
+        let mut list: Vec<&str> = words.split(' ').collect();
+        assert!(
+            validate_mnemonic(&list[..11].join(" "), 0).is_err_and(|e| e.starts_with("11 words"))
+        );
+        list[1] = "zzzz";
+        assert!(validate_mnemonic(&list.join(" "), 0)
+            .is_err_and(|e| e.starts_with("Word 2 (\"zzzz\")")));
+        // A valid word list with a wrong checksum.
+        assert!(validate_mnemonic(&["abandon"; 12].join(" "), 0)
+            .is_err_and(|e| e.contains("don't add up")));
+        assert!(looks_like_mnemonic(&words));
+        assert!(!looks_like_mnemonic("nsec1abc"));
+        Ok(())
+    }
+
     #[test]
     fn test_generate_key_and_save_in_memory() -> Result<()> {
         setup();
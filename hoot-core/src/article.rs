@@ -0,0 +1,183 @@
+//! Long-form notes (NIP-23, kind 30023) shared in mail, like a draft or an article to read.
+//!
+//! A message names each note by id in a `q` tag (NIP-18), and readers fetch the notes from
+//! their relays with an `ids` filter to show them inline. Only the people on the message learn
+//! that it was shared, but the note itself is as public as the relays it was published to.
+
+use crate::relay::Subscription;
+use anyhow::{bail, Context, Result};
+use nostr::nips::nip19::Nip19Event;
+use nostr::{Event, EventId, Filter, FromBech32, Kind, PublicKey, Tag, TagKind};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The tag a message names a shared note with.
+pub const QUOTE_TAG: &str = "q";
+/// The subscription that keeps the notes we asked about coming.
+const FETCH_SUBSCRIPTION: &str = "articles";
+/// How long relays get to come up with a note before we give up on it.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A long-form note, as its event tells it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub id: EventId,
+    pub author: PublicKey,
+    pub title: String,
+    pub summary: Option<String>,
+    /// The markdown body.
+    pub content: String,
+    /// When it was first published, which edits keep.
+    pub published_at: u64,
+}
+
+impl Article {
+    pub fn from_event(event: &Event) -> Result<Self> {
+        if event.kind != Kind::LongFormTextNote {
+            bail!("not a long-form note");
+        }
+        let value = |name: &str| {
+            event
+                .tags
+                .find(TagKind::custom(name))
+                .and_then(|tag| tag.content())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        Ok(Self {
+            id: event.id,
+            author: event.pubkey,
+            title: value("title").unwrap_or_else(|| "Untitled".to_string()),
+            summary: value("summary"),
+            content: event.content.clone(),
+            published_at: value("published_at")
+                .and_then(|at| at.parse().ok())
+                .unwrap_or_else(|| event.created_at.as_u64()),
+        })
+    }
+}
+
+/// The `q` tag sharing the note `id`, with a relay it can be found on if we know one.
+pub fn to_tag(id: &EventId, relay: Option<&str>) -> Tag {
+    Tag::custom(
+        TagKind::custom(QUOTE_TAG),
+        [id.to_hex(), relay.unwrap_or_default().to_string()],
+    )
+}
+
+/// Reads a note pasted as a `nostr:` link, `note1…`, `nevent1…` or hex id.
+pub fn parse_reference(input: &str) -> Result<EventId> {
+    let input = input.trim();
+    let entity = input
+        .get(..6)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("nostr:"))
+        .map_or(input, |_| &input[6..]);
+    let id = if entity.starts_with("nevent1") {
+        Nip19Event::from_bech32(entity)?.event_id
+    } else if entity.starts_with("note1") {
+        EventId::from_bech32(entity)?
+    } else {
+        EventId::from_hex(entity).context("Not a link to a note")?
+    };
+    Ok(id)
+}
+
+/// Where fetching a shared note stands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArticleState {
+    Fetching,
+    Found(Article),
+    /// No relay had it in time, or what came back wasn't a long-form note.
+    Missing,
+}
+
+/// The shared notes asked about, fetched once per run.
+#[derive(Debug, Default)]
+pub struct Articles {
+    /// When each note was asked for, and the note once it came in.
+    fetched: HashMap<EventId, (Instant, Option<Article>)>,
+}
+
+impl Articles {
+    /// Starts fetching the notes of `ids` we haven't asked about. Returns the subscription to
+    /// (re)send when there are new ones, covering every note asked about so far.
+    pub fn fetch(&mut self, ids: &[EventId]) -> Option<Subscription> {
+        let now = Instant::now();
+        let mut new = false;
+        for id in ids {
+            self.fetched.entry(*id).or_insert_with(|| {
+                new = true;
+                (now, None)
+            });
+        }
+        if !new {
+            return None;
+        }
+        let filter = Filter::new()
+            .kind(Kind::LongFormTextNote)
+            .ids(self.fetched.keys().copied());
+        Some(Subscription::new(
+            FETCH_SUBSCRIPTION.to_string(),
+            vec![filter],
+        ))
+    }
+
+    /// Keeps a note we asked for. Returns whether it was one.
+    pub fn receive(&mut self, event: &Event) -> bool {
+        let Some((_, article)) = self.fetched.get_mut(&event.id) else {
+            return false;
+        };
+        match Article::from_event(event) {
+            Ok(found) => *article = Some(found),
+            Err(e) => tracing::warn!("Shared note {} is unreadable: {}", event.id, e),
+        }
+        true
+    }
+
+    pub fn get(&self, id: &EventId) -> ArticleState {
+        match self.fetched.get(id) {
+            Some((_, Some(article))) => ArticleState::Found(article.clone()),
+            Some((asked, None)) if asked.elapsed() >= FETCH_TIMEOUT => ArticleState::Missing,
+            Some(_) | None => ArticleState::Fetching,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventBuilder, Keys, ToBech32};
+
+    #[test]
+    fn fetches_shared_notes_once() -> Result<()> {
+        let keys = Keys::generate();
+        let note = EventBuilder::new(Kind::LongFormTextNote, "# Draft\n\nSome words.")
+            .tags([
+                Tag::custom(TagKind::custom("title"), ["A draft"]),
+                Tag::custom(TagKind::custom("published_at"), ["1700000000"]),
+            ])
+            .sign_with_keys(&keys)?;
+        assert_eq!(parse_reference(&note.id.to_hex())?, note.id);
+        assert_eq!(
+            parse_reference(&format!("nostr:{}", note.id.to_bech32()?))?,
+            note.id
+        );
+        assert!(parse_reference("npub1nope").is_err());
+
+        let mut articles = Articles::default();
+        assert!(articles.fetch(&[note.id]).is_some());
+        assert!(articles.fetch(&[note.id]).is_none());
+        assert_eq!(articles.get(&note.id), ArticleState::Fetching);
+
+        let stranger = EventBuilder::new(Kind::LongFormTextNote, "").sign_with_keys(&keys)?;
+        assert!(!articles.receive(&stranger));
+        assert!(articles.receive(&note));
+        let ArticleState::Found(article) = articles.get(&note.id) else {
+            panic!("the note should be found");
+        };
+        assert_eq!(article.title, "A draft");
+        assert_eq!(article.published_at, 1_700_000_000);
+        Ok(())
+    }
+}
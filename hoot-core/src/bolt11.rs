@@ -0,0 +1,290 @@
+//! Reading Lightning invoices (BOLT 11), to see what one asks for before paying it or believing
+//! a receipt that quotes it.
+//!
+//! An invoice is bech32: a human readable part naming the network and the amount, then a
+//! timestamp, tagged fields like the description, and the payee's signature over all of it. The
+//! payee is the node that signed, so it's recovered from the signature unless the invoice names
+//! it, in which case the signature has to be its.
+
+use anyhow::{anyhow, bail, Context, Result};
+use nostr::hashes::{sha256, Hash};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1};
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+/// Networks by their prefix, longest first so `lnbcrt` isn't read as `lnbc`.
+const NETWORKS: [&str; 5] = ["bcrt", "tbs", "bc", "tb", "sb"];
+/// The signature and its recovery id at the end of the data, in 5 bit words.
+const SIGNATURE_WORDS: usize = 104;
+const TIMESTAMP_WORDS: usize = 7;
+const DESCRIPTION: u8 = 13;
+const PAYEE: u8 = 19;
+const DESCRIPTION_HASH: u8 = 23;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invoice {
+    /// What it asks for, in millisatoshis. Invoices for any amount have none.
+    pub msats: Option<u64>,
+    /// The node that gets paid, as hex.
+    pub payee: String,
+    pub description: Option<String>,
+    /// The SHA-256 of a description too long to carry, as hex.
+    pub description_hash: Option<String>,
+}
+
+impl Invoice {
+    pub fn decode(invoice: &str) -> Result<Self> {
+        let invoice = invoice.trim();
+        let invoice = invoice
+            .strip_prefix("lightning:")
+            .or_else(|| invoice.strip_prefix("LIGHTNING:"))
+            .unwrap_or(invoice);
+        let (hrp, words) = bech32_decode(invoice)?;
+        let msats = amount(&hrp)?;
+        if words.len() < TIMESTAMP_WORDS + SIGNATURE_WORDS {
+            bail!("the invoice is too short");
+        }
+        let (signed, signature) = words.split_at(words.len() - SIGNATURE_WORDS);
+
+        let mut description = None;
+        let mut description_hash = None;
+        let mut named_payee = None;
+        let mut fields = &signed[TIMESTAMP_WORDS..];
+        while let [kind, high, low, rest @ ..] = fields {
+            let len = (*high as usize) * 32 + *low as usize;
+            if rest.len() < len {
+                bail!("the invoice is cut off");
+            }
+            let (data, next) = rest.split_at(len);
+            match *kind {
+                DESCRIPTION => {
+                    let text = String::from_utf8(to_bytes(data, false))
+                        .context("the description isn't text")?;
+                    description = Some(text);
+                }
+                DESCRIPTION_HASH if len == 52 => {
+                    let hash = to_bytes(data, false);
+                    description_hash = Some(hash.iter().map(|b| format!("{:02x}", b)).collect());
+                }
+                // Only the 33 byte form is a key, others are skipped as BOLT 11 says.
+                PAYEE if len == 53 => {
+                    named_payee = Some(PublicKey::from_slice(&to_bytes(data, false))?);
+                }
+                _ => {}
+            }
+            fields = next;
+        }
+
+        let signature = to_bytes(signature, false);
+        let recovery = RecoveryId::from_i32(i32::from(signature[64]))?;
+        let signature = RecoverableSignature::from_compact(&signature[..64], recovery)?;
+        let mut preimage = hrp.into_bytes();
+        preimage.extend(to_bytes(signed, true));
+        let message = Message::from_digest(sha256::Hash::hash(&preimage).to_byte_array());
+        let secp = Secp256k1::verification_only();
+        let payee = match named_payee {
+            Some(payee) => {
+                secp.verify_ecdsa(&message, &signature.to_standard(), &payee)
+                    .map_err(|_| anyhow!("the invoice isn't signed by its payee"))?;
+                payee
+            }
+            None => secp
+                .recover_ecdsa(&message, &signature)
+                .map_err(|_| anyhow!("the invoice's signature isn't valid"))?,
+        };
+
+        Ok(Self {
+            msats,
+            payee: payee.to_string(),
+            description,
+            description_hash,
+        })
+    }
+}
+
+/// Reads the amount from the human readable part, like `lnbc2500u`.
+fn amount(hrp: &str) -> Result<Option<u64>> {
+    let rest = hrp
+        .strip_prefix("ln")
+        .ok_or_else(|| anyhow!("not a Lightning invoice"))?;
+    let network = NETWORKS
+        .iter()
+        .find(|network| rest.starts_with(*network))
+        .ok_or_else(|| anyhow!("the invoice is for an unknown network"))?;
+    let amount = &rest[network.len()..];
+    if amount.is_empty() {
+        return Ok(None);
+    }
+    let (number, multiplier) = match amount.char_indices().last() {
+        Some((i, unit @ ('m' | 'u' | 'n' | 'p'))) => (&amount[..i], Some(unit)),
+        _ => (amount, None),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("the invoice's amount isn't a number"))?;
+    // In millisatoshis per unit, a bitcoin being 100 billion.
+    let msats = match multiplier {
+        None => number.checked_mul(100_000_000_000),
+        Some('m') => number.checked_mul(100_000_000),
+        Some('u') => number.checked_mul(100_000),
+        Some('n') => number.checked_mul(100),
+        _ if number.is_multiple_of(10) => Some(number / 10),
+        _ => bail!("the invoice asks for a fraction of a millisatoshi"),
+    };
+    msats
+        .map(Some)
+        .ok_or_else(|| anyhow!("the invoice's amount is too large"))
+}
+
+fn polymod(words: impl IntoIterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for word in words {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ u32::from(word);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_words(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|b| b & 31))
+}
+
+/// The human readable part and the 5 bit words of `text`, without the checksum. Unlike bech32
+/// addresses, invoices have no length limit.
+fn bech32_decode(text: &str) -> Result<(String, Vec<u8>)> {
+    let lower = text.to_lowercase();
+    if lower != text && text.to_uppercase() != text {
+        bail!("the invoice mixes upper and lower case");
+    }
+    let (hrp, data) = lower
+        .rsplit_once('1')
+        .ok_or_else(|| anyhow!("not a Lightning invoice"))?;
+    let words = data
+        .bytes()
+        .map(|c| CHARSET.iter().position(|&x| x == c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("the invoice has characters bech32 doesn't use"))?;
+    if hrp.is_empty() || words.len() < 6 {
+        bail!("not a Lightning invoice");
+    }
+    if polymod(hrp_words(hrp).chain(words.iter().copied())) != 1 {
+        bail!("the invoice has a typo, its checksum doesn't match");
+    }
+    Ok((hrp.to_string(), words[..words.len() - 6].to_vec()))
+}
+
+/// 5 bit words as bytes. Bits left over are dropped, unless `pad` fills them out to a last byte
+/// with zeros, as for what the signature covers.
+fn to_bytes(words: &[u8], pad: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(words.len() * 5 / 8 + 1);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &word in words {
+        acc = (acc << 5) | u32::from(word);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if pad && bits > 0 {
+        bytes.push((acc << (8 - bits)) as u8);
+    }
+    bytes
+}
+
+/// An invoice for `hrp`, like `lnbc2500u`, describing it as `description` and signed by
+/// `secret`, for tests here and of what reads receipts.
+#[cfg(test)]
+pub(crate) fn sign(hrp: &str, description: &str, secret: &secp256k1::SecretKey) -> String {
+    sign_with(hrp, DESCRIPTION, description.as_bytes(), secret)
+}
+
+/// Like [`sign`], but with the hash of a description instead of the description.
+#[cfg(test)]
+pub(crate) fn sign_hashed(hrp: &str, hash: &[u8; 32], secret: &secp256k1::SecretKey) -> String {
+    sign_with(hrp, DESCRIPTION_HASH, hash, secret)
+}
+
+#[cfg(test)]
+fn sign_with(hrp: &str, kind: u8, description: &[u8], secret: &secp256k1::SecretKey) -> String {
+    fn to_words(bytes: &[u8]) -> Vec<u8> {
+        let mut words = Vec::new();
+        let (mut acc, mut bits) = (0u32, 0);
+        for &byte in bytes {
+            acc = (acc << 8) | u32::from(byte);
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                words.push(((acc >> bits) & 31) as u8);
+            }
+        }
+        if bits > 0 {
+            words.push(((acc << (5 - bits)) & 31) as u8);
+        }
+        words
+    }
+
+    let mut words = vec![0; TIMESTAMP_WORDS];
+    let payment_hash = to_words(&[7; 32]);
+    let description = to_words(description);
+    for (kind, data) in [(1, payment_hash), (kind, description)] {
+        words.extend([kind, (data.len() / 32) as u8, (data.len() % 32) as u8]);
+        words.extend(data);
+    }
+    let mut preimage = hrp.as_bytes().to_vec();
+    preimage.extend(to_bytes(&words, true));
+    let message = Message::from_digest(sha256::Hash::hash(&preimage).to_byte_array());
+    let (recovery, signature) = Secp256k1::new()
+        .sign_ecdsa_recoverable(&message, secret)
+        .serialize_compact();
+    let mut signature = signature.to_vec();
+    signature.push(recovery.to_i32() as u8);
+    words.extend(to_words(&signature));
+
+    let checksum = polymod(hrp_words(hrp).chain(words.iter().copied()).chain([0; 6])) ^ 1;
+    words.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8));
+    let data: String = words.iter().map(|&w| CHARSET[w as usize] as char).collect();
+    format!("{}1{}", hrp, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_amount_payee_and_description() -> Result<()> {
+        let secret = secp256k1::SecretKey::from_slice(&[3; 32])?;
+        let payee = PublicKey::from_secret_key(&Secp256k1::new(), &secret).to_string();
+
+        let invoice = Invoice::decode(&sign("lnbc2500u", "1 cup coffee", &secret))?;
+        assert_eq!(invoice.msats, Some(250_000_000));
+        assert_eq!(invoice.payee, payee);
+        assert_eq!(invoice.description.as_deref(), Some("1 cup coffee"));
+        assert_eq!(invoice.description_hash, None);
+        let hashed = Invoice::decode(&sign_hashed("lnbc10n", &[0xab; 32], &secret))?;
+        assert_eq!(hashed.description, None);
+        assert_eq!(hashed.description_hash, Some("ab".repeat(32)));
+
+        let any = sign("lnbc", "Donation", &secret).to_uppercase();
+        assert_eq!(Invoice::decode(&format!("lightning:{}", any))?.msats, None);
+        assert_eq!(amount("lnbcrt10n")?, Some(1_000));
+        assert_eq!(amount("lntbs1m")?, Some(100_000_000));
+        assert!(amount("lnbc25p").is_err());
+
+        let mut typo = sign("lnbc10n", "Widget", &secret);
+        typo.replace_range(20..21, if &typo[20..21] == "q" { "p" } else { "q" });
+        assert!(Invoice::decode(&typo).is_err());
+        assert!(Invoice::decode("lnbc1").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Regular email through a nostr-to-email gateway.
+//!
+//! Mail for `someone@example.com` is gift wrapped to the gateway's pubkey with an `email-to`
+//! tag naming the real recipient. The gateway delivers replies as mail events signed by its own
+//! pubkey, with an `email-from` tag naming who actually wrote them.
+
+use crate::mail_event::MailMessage;
+use nostr::{PublicKey, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+
+pub const BRIDGE_CONFIG_KEY: &str = "email_bridge";
+pub const EMAIL_TO_TAG: &str = "email-to";
+pub const EMAIL_FROM_TAG: &str = "email-from";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    pub enabled: bool,
+    /// The gateway's public key, as hex or npub.
+    pub gateway_pubkey: String,
+    /// The address the gateway assigned to us, so people can email us from outside nostr.
+    pub address: String,
+}
+
+impl BridgeConfig {
+    /// The gateway to send email through, if the bridge is set up.
+    pub fn gateway(&self) -> Option<PublicKey> {
+        if !self.enabled {
+            return None;
+        }
+        PublicKey::parse(self.gateway_pubkey.trim()).ok()
+    }
+
+    /// Only our own gateway gets to claim a message came from an email address.
+    pub fn is_gateway(&self, pubkey: &PublicKey) -> bool {
+        self.gateway().is_some_and(|gateway| gateway == *pubkey)
+    }
+
+    /// The email address that actually wrote `message`, if it came in through the gateway.
+    pub fn email_sender<'a>(&self, message: &'a MailMessage) -> Option<&'a str> {
+        let author = message.author.as_ref()?;
+        if !self.is_gateway(author) {
+            return None;
+        }
+        message.email_from.as_deref()
+    }
+
+    /// The body of `message` when the gateway passed on an HTML email, which the gateway does
+    /// as is rather than converting it to text.
+    pub fn html_body<'a>(&self, message: &'a MailMessage) -> Option<&'a str> {
+        self.email_sender(message)?;
+        looks_like_html(&message.content).then_some(message.content.as_str())
+    }
+
+    /// What to put in the To field when replying to `message`: the original email address for
+    /// bridged mail, the author's pubkey otherwise.
+    pub fn reply_address(&self, message: &MailMessage) -> Option<String> {
+        match self.email_sender(message) {
+            Some(address) => Some(address.to_string()),
+            None => message.author.map(|author| author.to_string()),
+        }
+    }
+}
+
+/// Recipients from the compose window's To field, split into nostr users and email addresses.
+#[derive(Debug, Default, PartialEq)]
+pub struct Recipients {
+    pub pubkeys: Vec<PublicKey>,
+    pub emails: Vec<String>,
+    /// Entries that are neither a pubkey nor an email address.
+    pub invalid: Vec<String>,
+}
+
+pub fn parse_recipients(field: &str) -> Recipients {
+    let mut recipients = Recipients::default();
+    for entry in field.split([' ', ',', ';']).filter(|s| !s.is_empty()) {
+        if is_email_address(entry) {
+            recipients.emails.push(entry.to_lowercase());
+        } else if let Ok(pubkey) = PublicKey::parse(entry) {
+            recipients.pubkeys.push(pubkey);
+        } else {
+            recipients.invalid.push(entry.to_string());
+        }
+    }
+    recipients
+}
+
+/// Why an entry from `Recipients::invalid` didn't parse, to show next to the To field.
+pub fn invalid_recipient_reason(entry: &str) -> &'static str {
+    let lower = entry.to_lowercase();
+    if lower.starts_with("npub1") {
+        "checksum doesn't match, check this npub for typos"
+    } else if lower.starts_with("nsec1") {
+        "this is a private key, never share it"
+    } else if entry.len() == 64 && entry.chars().all(|c| c.is_ascii_hexdigit()) {
+        "not a valid public key"
+    } else if entry.contains('@') {
+        "not a valid email address"
+    } else {
+        "not a public key or email address"
+    }
+}
+
+/// A loose check that's good enough to tell email addresses apart from npubs and hex keys,
+/// the gateway does the real validation.
+pub fn is_email_address(s: &str) -> bool {
+    let Some((local, domain)) = s.rsplit_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !local.contains(char::is_whitespace)
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Whether `body` is an HTML document or fragment rather than text with the odd `<` in it.
+pub fn looks_like_html(body: &str) -> bool {
+    let lower = body.trim_start().to_ascii_lowercase();
+    lower.starts_with("<!doctype html")
+        || lower.starts_with("<html")
+        || (lower.starts_with('<')
+            && ["</p>", "</div>", "<br", "</table>", "</span>"]
+                .iter()
+                .any(|tag| lower.contains(tag)))
+}
+
+pub fn email_to_tag(address: &str) -> Tag {
+    Tag::custom(TagKind::custom(EMAIL_TO_TAG), [address.to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    #[test]
+    fn splits_emails_from_pubkeys() {
+        let pubkey = Keys::generate().public_key();
+        let field = format!("{} Someone@Example.com, not-a-key", pubkey.to_hex());
+
+        let recipients = parse_recipients(&field);
+        assert_eq!(recipients.pubkeys, vec![pubkey]);
+        assert_eq!(recipients.emails, vec!["someone@example.com".to_string()]);
+        assert_eq!(recipients.invalid, vec!["not-a-key".to_string()]);
+
+        assert!(!is_email_address("@example.com"));
+        assert!(!is_email_address("someone@localhost"));
+    }
+
+    #[test]
+    fn explains_invalid_recipients() {
+        assert!(invalid_recipient_reason("npub1qqqqtypo").contains("typos"));
+        assert!(invalid_recipient_reason("nsec1secret").contains("private key"));
+        assert_eq!(
+            invalid_recipient_reason("someone@localhost"),
+            "not a valid email address"
+        );
+        assert_eq!(
+            invalid_recipient_reason("bob"),
+            "not a public key or email address"
+        );
+    }
+
+    #[test]
+    fn only_trusts_email_sender_from_gateway() {
+        let gateway = Keys::generate().public_key();
+        let config = BridgeConfig {
+            enabled: true,
+            gateway_pubkey: gateway.to_hex(),
+            address: String::new(),
+        };
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: Some(gateway),
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            parent_events: None,
+            subject: String::new(),
+            content: String::new(),
+            attachments: vec![],
+            articles: vec![],
+            quote: None,
+            payload: None,
+            email_to: vec![],
+            email_from: Some("someone@example.com".to_string()),
+            tag_relays: Default::default(),
+        };
+        assert_eq!(
+            config.reply_address(&message).as_deref(),
+            Some("someone@example.com")
+        );
+        assert_eq!(config.html_body(&message), None);
+        message.content = "<div>Hi<br>there</div>".to_string();
+        assert!(config.html_body(&message).is_some());
+        assert!(!looks_like_html("<3 see you soon"));
+
+        let impostor = Keys::generate().public_key();
+        message.author = Some(impostor);
+        assert_eq!(config.email_sender(&message), None);
+        assert_eq!(config.html_body(&message), None);
+        assert_eq!(config.reply_address(&message), Some(impostor.to_string()));
+    }
+}
@@ -0,0 +1,242 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const CALENDAR_MIME_TYPE: &str = "text/calendar";
+
+/// The first event of an iCalendar (RFC 5545) file, with just what we need to show an invite.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invite {
+    pub summary: Option<String>,
+    pub start: Option<EventTime>,
+    pub end: Option<EventTime>,
+    pub location: Option<String>,
+    pub organizer: Option<String>,
+    /// The whole calendar, exported as-is when adding the event to a calendar app.
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTime {
+    Utc(DateTime<Utc>),
+    /// A local time, either floating or in the named time zone.
+    Local(NaiveDateTime, Option<String>),
+    /// An all-day event.
+    Date(NaiveDate),
+}
+
+impl fmt::Display for EventTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventTime::Utc(time) => write!(
+                f,
+                "{}",
+                time.with_timezone(&Local).format("%a %b %-d %Y, %-I:%M %p")
+            ),
+            EventTime::Local(time, Some(tzid)) => {
+                write!(f, "{} ({})", time.format("%a %b %-d %Y, %-I:%M %p"), tzid)
+            }
+            EventTime::Local(time, None) => write!(f, "{}", time.format("%a %b %-d %Y, %-I:%M %p")),
+            EventTime::Date(date) => write!(f, "{} (all day)", date.format("%a %b %-d %Y")),
+        }
+    }
+}
+
+impl Invite {
+    /// Finds a calendar embedded in a message body.
+    pub fn from_text(text: &str) -> Option<Self> {
+        let start = text.find("BEGIN:VCALENDAR")?;
+        let end = text[start..]
+            .find("END:VCALENDAR")
+            .map(|end| start + end + "END:VCALENDAR".len())?;
+        Self::parse(&text[start..end])
+    }
+
+    /// Parses the first VEVENT in `ics`. Returns `None` if there isn't one.
+    pub fn parse(ics: &str) -> Option<Self> {
+        let mut in_event = false;
+        let mut found_event = false;
+        let mut invite = Invite {
+            summary: None,
+            start: None,
+            end: None,
+            location: None,
+            organizer: None,
+            raw: ics.to_string(),
+        };
+
+        for line in unfold(ics) {
+            let Some((name_and_params, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mut parts = name_and_params.split(';');
+            let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+            let params: Vec<&str> = parts.collect();
+
+            match name.as_str() {
+                "BEGIN" if value.eq_ignore_ascii_case("VEVENT") && !found_event => {
+                    in_event = true;
+                    found_event = true;
+                }
+                "END" if value.eq_ignore_ascii_case("VEVENT") => in_event = false,
+                _ if !in_event => {}
+                "SUMMARY" => invite.summary = Some(unescape(value)),
+                "LOCATION" => invite.location = Some(unescape(value)),
+                "DTSTART" => invite.start = parse_time(value, &params),
+                "DTEND" => invite.end = parse_time(value, &params),
+                "ORGANIZER" => {
+                    let name = params
+                        .iter()
+                        .find_map(|param| param.strip_prefix("CN="))
+                        .map(|cn| cn.trim_matches('"').to_string());
+                    let address = value
+                        .strip_prefix("mailto:")
+                        .or_else(|| value.strip_prefix("MAILTO:"))
+                        .unwrap_or(value);
+                    invite.organizer = Some(name.unwrap_or_else(|| address.to_string()));
+                }
+                _ => {}
+            }
+        }
+
+        found_event.then_some(invite)
+    }
+
+    /// A human readable time range, e.g. "Mon Jan 15 2024, 10:00 AM – 11:00 AM".
+    pub fn when(&self) -> Option<String> {
+        let start = self.start.as_ref()?;
+        let Some(end) = self.end.as_ref() else {
+            return Some(start.to_string());
+        };
+
+        // Don't repeat the date for events that end on the day they start.
+        let same_day_end = match (start, end) {
+            (EventTime::Utc(s), EventTime::Utc(e)) => {
+                let (s, e) = (s.with_timezone(&Local), e.with_timezone(&Local));
+                (s.date_naive() == e.date_naive()).then(|| e.format("%-I:%M %p").to_string())
+            }
+            (EventTime::Local(s, _), EventTime::Local(e, _)) => {
+                (s.date() == e.date()).then(|| e.format("%-I:%M %p").to_string())
+            }
+            _ => None,
+        };
+        let end = same_day_end.unwrap_or_else(|| end.to_string());
+        Some(format!("{} – {}", start, end))
+    }
+
+    /// Writes the calendar to `dir` so it can be handed to a calendar app.
+    pub fn export(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let stem: String = self
+            .summary
+            .as_deref()
+            .unwrap_or("invite")
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(64)
+            .collect();
+
+        let mut path = dir.join(format!("{}.ics", stem));
+        let mut n = 1;
+        while path.exists() && fs::read_to_string(&path).ok().as_deref() != Some(&self.raw) {
+            path = dir.join(format!("{}-{}.ics", stem, n));
+            n += 1;
+        }
+        fs::write(&path, &self.raw)?;
+        Ok(path)
+    }
+}
+
+/// Joins folded lines back together, lines starting with a space or tab continue the previous one.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn parse_time(value: &str, params: &[&str]) -> Option<EventTime> {
+    if params.iter().any(|p| p.eq_ignore_ascii_case("VALUE=DATE")) || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(EventTime::Date);
+    }
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|time| EventTime::Utc(time.and_utc()));
+    }
+
+    let tzid = params
+        .iter()
+        .find_map(|p| p.strip_prefix("TZID="))
+        .map(|tz| tz.trim_matches('"').to_string());
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|time| EventTime::Local(time, tzid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_invite_from_message_body() {
+        let body = "See you there!\n\nBEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\n\
+                    SUMMARY:Planning\\, round two\r\nDTSTART;TZID=Europe/Berlin:20240115T100000\r\n\
+                    DTEND;TZID=Europe/Berlin:20240115T110000\r\nLOCATION:Room 4\r\n  (second floor)\r\n\
+                    ORGANIZER;CN=\"Alice\":mailto:alice@example.com\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+        let invite = Invite::from_text(body).expect("invite");
+        assert_eq!(invite.summary.as_deref(), Some("Planning, round two"));
+        assert_eq!(invite.location.as_deref(), Some("Room 4 (second floor)"));
+        assert_eq!(invite.organizer.as_deref(), Some("Alice"));
+        assert!(invite.raw.starts_with("BEGIN:VCALENDAR"));
+        assert!(invite.raw.ends_with("END:VCALENDAR"));
+
+        let start = NaiveDate::from_ymd_opt(2024, 1, 15)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        assert_eq!(
+            invite.start,
+            Some(EventTime::Local(start, Some("Europe/Berlin".to_string())))
+        );
+        assert_eq!(
+            parse_time("20240115", &["VALUE=DATE"]),
+            Some(EventTime::Date(start.date()))
+        );
+
+        assert!(Invite::from_text("BEGIN:VCALENDAR\nEND:VCALENDAR").is_none());
+    }
+}
@@ -0,0 +1,147 @@
+//! The time to date the events we send with. Relays refuse events dated too far from their own
+//! clock (NIP-22), so when the system clock is off we go by an estimate of the relays' instead.
+//!
+//! The estimate comes from the `Date` header of HTTP answers from relays, like their
+//! information documents, with half the round trip added the way NTP does it. The middle
+//! estimate of the relays asked wins, so one relay with a broken clock doesn't move ours.
+
+use nostr::Timestamp;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds to add to the system clock to get the relays'.
+static OFFSET: AtomicI64 = AtomicI64::new(0);
+
+/// Offsets up to this are left alone, the `Date` header only has whole seconds.
+const MIN_CORRECTION: i64 = 2;
+/// Past this the system clock is badly wrong, and the user should fix it.
+pub const WARN_SKEW: i64 = 5 * 60;
+
+/// Now, by the relays' clock.
+pub fn now() -> Timestamp {
+    let now = Timestamp::now().as_u64() as i64;
+    Timestamp::from(now.saturating_add(offset()).max(0) as u64)
+}
+
+/// Seconds the system clock is behind the relays' (negative if ahead).
+pub fn offset() -> i64 {
+    OFFSET.load(Ordering::Relaxed)
+}
+
+/// Whether the system clock is off by more than [`WARN_SKEW`].
+pub fn is_badly_wrong() -> bool {
+    offset().abs() > WARN_SKEW
+}
+
+/// How far off the system clock is, like "2 hours behind", for an offset from [`offset`].
+pub fn describe(offset: i64) -> String {
+    let direction = if offset > 0 { "behind" } else { "ahead" };
+    let seconds = offset.unsigned_abs();
+    let (amount, unit) = if seconds >= 2 * 3600 {
+        (seconds / 3600, "hours")
+    } else if seconds >= 120 {
+        (seconds / 60, "minutes")
+    } else {
+        (seconds, "seconds")
+    };
+    format!("{} {} {}", amount, unit, direction)
+}
+
+/// Measures the clock skew between us and relays.
+#[derive(Debug, Default)]
+pub struct SkewEstimator {
+    /// The last offset measured per relay url, in seconds.
+    samples: HashMap<String, i64>,
+}
+
+impl SkewEstimator {
+    /// Records the `Date` header `url` answered with, for a request sent at `sent` whose answer
+    /// came in at `received`. Returns the new offset if it changed.
+    pub fn record_date(
+        &mut self,
+        url: &str,
+        date: &str,
+        sent: SystemTime,
+        received: SystemTime,
+    ) -> Option<i64> {
+        let offset = offset_from_date(date, sent, received)?;
+        self.samples.insert(url.to_string(), offset);
+        self.apply()
+    }
+
+    /// The middle of the offsets measured.
+    pub fn estimate(&self) -> i64 {
+        let mut offsets: Vec<i64> = self.samples.values().copied().collect();
+        if offsets.is_empty() {
+            return 0;
+        }
+        offsets.sort_unstable();
+        let estimate = offsets[offsets.len() / 2];
+        if estimate.abs() <= MIN_CORRECTION {
+            0
+        } else {
+            estimate
+        }
+    }
+
+    fn apply(&self) -> Option<i64> {
+        let estimate = self.estimate();
+        (OFFSET.swap(estimate, Ordering::Relaxed) != estimate).then_some(estimate)
+    }
+}
+
+/// How far the clock behind an HTTP `Date` header is ahead of ours, in seconds.
+fn offset_from_date(date: &str, sent: SystemTime, received: SystemTime) -> Option<i64> {
+    let theirs = chrono::DateTime::parse_from_rfc2822(date.trim()).ok()?;
+    // The header rounds down to the second.
+    let theirs_ms = theirs.timestamp_millis() + 500;
+    let round_trip = received.duration_since(sent).unwrap_or(Duration::ZERO);
+    let ours = sent.checked_add(round_trip / 2)?;
+    let ours_ms = ours.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64;
+    Some((theirs_ms - ours_ms).div_euclid(1000))
+}
+
+/// Whether a relay refused an event for its date, like `invalid: created_at too far in the
+/// future`.
+pub fn is_date_rejection(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    reason.contains("created_at")
+        || reason.contains("in the future")
+        || reason.contains("too far")
+        || reason.contains("too old")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_the_offset_from_date_headers() {
+        let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let received = sent + Duration::from_secs(2);
+        // 1_700_000_000 is Tue, 14 Nov 2023 22:13:20 GMT; the answer left halfway through.
+        let date = "Tue, 14 Nov 2023 22:13:21 GMT";
+        assert_eq!(offset_from_date(date, sent, received), Some(0));
+        let ahead = "Tue, 14 Nov 2023 23:13:21 GMT";
+        assert_eq!(offset_from_date(ahead, sent, received), Some(3600));
+        assert_eq!(offset_from_date("yesterday", sent, received), None);
+
+        let mut estimator = SkewEstimator::default();
+        estimator.samples.insert("wss://a".to_string(), 3600);
+        estimator.samples.insert("wss://b".to_string(), 3601);
+        estimator.samples.insert("wss://c".to_string(), -86_400);
+        assert_eq!(estimator.estimate(), 3600);
+        estimator.samples.clear();
+        estimator.samples.insert("wss://a".to_string(), 1);
+        assert_eq!(estimator.estimate(), 0);
+
+        assert_eq!(describe(7200), "2 hours behind");
+        assert_eq!(describe(-600), "10 minutes ahead");
+
+        assert!(is_date_rejection(
+            "invalid: created_at too far in the future"
+        ));
+        assert!(!is_date_rejection("blocked: not on the list"));
+    }
+}
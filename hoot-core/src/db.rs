@@ -0,0 +1,4672 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use include_dir::{include_dir, Dir};
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{Event, EventId, PublicKey, TagKind, UnsignedEvent};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use rusqlite_migration::Migrations;
+use serde_json::json;
+use tracing::{debug, error, info};
+use zeroize::Zeroizing;
+
+use crate::encryption::Encryption;
+use crate::flag_sync::ThreadFlags;
+use crate::focus::SenderStats;
+use crate::mail_event::{self, Attachment, Fragment, MailMessage, MAIL_EVENT_KIND};
+use crate::metrics;
+use crate::notification_rules::{NotificationRule, NotifyMode, QuietHours, RuleScope};
+use crate::payload::Payload;
+use crate::profile_metadata::ProfileMetadata;
+use crate::TableEntry;
+
+static MIGRATIONS_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/migrations");
+
+static MIGRATIONS: LazyLock<Migrations<'static>> =
+    LazyLock::new(|| Migrations::from_directory(&MIGRATIONS_DIR).unwrap());
+
+/// How long a query waits on another connection's lock before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Prepared statements kept around for reuse, enough for every query we run per event.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Marks in `message_state` for events that aren't stored. Marks can come before their message,
+/// from another device, so only those for deleted messages or that mark nothing go.
+const ORPHANED_STATES: &str = "event_id NOT IN (SELECT id FROM events)
+    AND (event_id IN (SELECT event_id FROM deleted_events)
+         OR (read_at IS NULL AND NOT starred AND NOT archived))";
+/// Mail in `events` with no row in `message_search`.
+static UNINDEXED: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "kind = {} AND id NOT IN (SELECT event_id FROM message_search)",
+        MAIL_EVENT_KIND
+    )
+});
+/// Rows in `message_search` for events that aren't stored.
+const STALE_INDEX: &str = "event_id NOT IN (SELECT id FROM events)";
+
+/// The threads of the top-level table: `roots`, and `thread`, every message of each by its
+/// root. Goes with [`TOP_LEVEL_MATCHES`].
+const TOP_LEVEL_THREADS: &str = "WITH RECURSIVE
+roots AS (
+    SELECT DISTINCT e.id
+    FROM events e, json_each(e.tags) AS tag
+    WHERE jsonb_extract(tag.value, '$[0]') = 'subject'
+    AND e.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+    AND NOT EXISTS (
+        SELECT 1 FROM deleted_events d
+        WHERE d.event_id = e.id
+        AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM trash_events t
+        WHERE t.event_id = e.id
+    )
+    AND NOT EXISTS (
+        SELECT 1
+        FROM json_each(e.tags) AS etag
+        WHERE jsonb_extract(etag.value, '$[0]') = 'e'
+        AND EXISTS (SELECT 1 FROM events WHERE id = jsonb_extract(etag.value, '$[1]'))
+    )
+),
+thread AS (
+    SELECT id as root_id, id as msg_id FROM roots
+    UNION
+    SELECT t.root_id, e.id
+    FROM thread t, events e, json_each(e.tags) AS etag
+    WHERE jsonb_extract(etag.value, '$[0]') = 'e'
+    AND jsonb_extract(etag.value, '$[1]') = t.msg_id
+    AND e.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+    AND NOT EXISTS (
+        SELECT 1 FROM deleted_events d
+        WHERE d.event_id = e.id
+        AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM trash_events t
+        WHERE t.event_id = e.id
+    )
+)";
+/// The threads [`MessageFilter`] and a [`Page`] let through, as `r` for the root and `le` for
+/// the latest message. Shared by the page of them and their count, so both agree.
+const TOP_LEVEL_MATCHES: &str = "FROM roots r
+JOIN events re ON re.id = r.id
+JOIN events le ON le.id = (
+    SELECT t2.msg_id FROM thread t2
+    JOIN events e2 ON e2.id = t2.msg_id
+    WHERE t2.root_id = r.id
+    ORDER BY e2.created_at DESC
+    LIMIT 1)
+WHERE (?1 IS NULL OR EXISTS (
+    SELECT 1 FROM thread t3
+    JOIN events e3 ON e3.id = t3.msg_id
+    WHERE t3.root_id = r.id
+    AND (e3.pubkey = ?1 OR EXISTS (
+        SELECT 1 FROM json_each(e3.tags) AS ptag
+        WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+        AND jsonb_extract(ptag.value, '$[1]') = ?1
+    ))
+))
+AND (?2 IS NULL OR EXISTS (
+    SELECT 1 FROM thread t4
+    JOIN message_search ON message_search.event_id = t4.msg_id
+    WHERE t4.root_id = r.id
+    AND message_search MATCH ?2
+))
+AND (?3 = 0 OR EXISTS (
+    SELECT 1 FROM thread t5
+    WHERE t5.root_id = r.id
+    AND NOT EXISTS (
+        SELECT 1 FROM message_state m
+        WHERE m.event_id = t5.msg_id AND m.read_at IS NOT NULL
+    )
+))
+AND (?4 = 0 OR EXISTS (
+    SELECT 1 FROM thread t6
+    JOIN message_state m ON m.event_id = t6.msg_id
+    WHERE t6.root_id = r.id AND m.starred = 1
+))
+AND (?5 = 0 OR EXISTS (
+    SELECT 1 FROM thread t7
+    JOIN events e7 ON e7.id = t7.msg_id, json_each(e7.tags) AS itag
+    WHERE t7.root_id = r.id
+    AND jsonb_extract(itag.value, '$[0]') = 'imeta'
+))
+AND (?6 = 0 OR EXISTS (
+    SELECT 1 FROM thread t8
+    JOIN events e8 ON e8.id = t8.msg_id
+    JOIN contacts c ON c.pubkey = e8.pubkey
+    WHERE t8.root_id = r.id
+))
+AND ?7 = NOT EXISTS (
+    SELECT 1 FROM thread t9
+    WHERE t9.root_id = r.id
+    AND NOT EXISTS (
+        SELECT 1 FROM message_state m
+        WHERE m.event_id = t9.msg_id AND m.archived = 1
+    )
+)
+AND EXISTS (
+    SELECT 1 FROM thread t10
+    WHERE t10.root_id = r.id
+    AND (t10.msg_id NOT IN (SELECT event_id FROM sent_messages)
+         OR EXISTS (SELECT 1 FROM gift_wrap_map g WHERE g.inner_id = t10.msg_id
+                    AND g.wrap_id NOT IN (SELECT wrap_id FROM sent_messages WHERE copy)))
+)
+AND (?8 IS NULL OR (le.created_at, r.id) < (?8, ?9)
+     OR (?10 AND le.created_at = ?8 AND r.id = ?9))
+AND (?11 IS NULL OR (le.created_at, r.id) > (?11, ?12))
+AND (?15 IS NULL OR ?15 = EXISTS (
+    SELECT 1 FROM thread t11
+    JOIN events e11 ON e11.id = t11.msg_id
+    JOIN sender_focus f ON f.pubkey = e11.pubkey
+    WHERE t11.root_id = r.id
+    AND f.focused = 1
+    AND t11.msg_id NOT IN (SELECT event_id FROM sent_messages)
+))
+AND (?16 IS NULL OR EXISTS (
+    SELECT 1 FROM thread_notes n
+    WHERE n.root_id = r.id
+    AND NOT EXISTS (
+        SELECT 1 FROM json_each(?16) AS word
+        WHERE instr(lower(n.note), lower(word.value)) = 0
+    )
+))";
+
+/// Labels a query put together as a JSON array, sorted.
+fn sorted_labels(json: &str) -> Vec<String> {
+    let mut labels: Vec<String> = serde_json::from_str(json).unwrap_or_default();
+    labels.sort();
+    labels
+}
+
+pub struct Db {
+    connection: Connection,
+    /// Where the database lives and its key, kept to open reader connections until
+    /// [`Db::forget_key`].
+    path: Option<PathBuf>,
+    key: Option<Zeroizing<String>>,
+}
+
+impl Db {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        debug!("Loading database at location {:?}", path.to_str());
+        let conn = Connection::open(&path)?;
+        Self::configure(&conn)?;
+
+        Ok(Self {
+            connection: conn,
+            path: Some(path),
+            key: None,
+        })
+    }
+
+    pub fn new_in_memory() -> Result<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        Self::configure(&conn)?;
+
+        MIGRATIONS.to_latest(&mut conn);
+
+        Ok(Self {
+            connection: conn,
+            path: None,
+            key: None,
+        })
+    }
+
+    /// Opens a read-only connection to the same unlocked database, for queries run off the UI
+    /// thread. WAL mode lets it read while this connection writes.
+    pub fn open_reader(&self) -> Result<Self> {
+        self.open_another(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+    }
+
+    /// Opens another read-write connection to the same unlocked database, for writes made off
+    /// the UI thread. Only one connection writes at a time, the others wait their turn.
+    pub fn open_writer(&self) -> Result<Self> {
+        self.open_another(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+    }
+
+    fn open_another(&self, flags: OpenFlags) -> Result<Self> {
+        let (Some(path), Some(key)) = (&self.path, &self.key) else {
+            anyhow::bail!("Only unlocked on-disk databases can be opened again");
+        };
+        let conn = Connection::open_with_flags(path, flags)?;
+        conn.pragma_update(None, "key", key.as_str())?;
+        Self::configure(&conn)?;
+
+        // Connections opened this way don't open others, so they don't keep the key.
+        Ok(Self {
+            connection: conn,
+            path: Some(path.clone()),
+            key: None,
+        })
+    }
+
+    fn configure(conn: &Connection) -> Result<()> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(())
+    }
+
+    pub fn unlock_with_password(&mut self, password: String) -> Result<()> {
+        let password = Zeroizing::new(password);
+        self.connection
+            .pragma_update(None, "key", password.as_str())?;
+
+        // Readers don't block writers in WAL mode, so the UI can query while we ingest.
+        // This needs the key, the journal mode is stored in the encrypted header.
+        let mode: String =
+            self.connection
+                .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        debug!("Database journal mode: {}", mode);
+
+        // Apply migrations
+        info!("Running Migrations");
+        MIGRATIONS.to_latest(&mut self.connection)?;
+        self.backfill_snippets()?;
+        self.key = Some(password);
+
+        Ok(())
+    }
+
+    /// Wipes the key from memory once the other connections are open. No more can be opened
+    /// after this.
+    pub fn forget_key(&mut self) {
+        self.key = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        // Try a simple query to check if the database is unlocked
+        // If the database is locked, this will fail
+        self.connection
+            .query_row("SELECT 1", [], |_| Ok(()))
+            .is_ok()
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        // Check if migrations have been run by checking if any tables exist
+        // An uninitialized database won't have the schema set up yet
+        self.connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type='table' LIMIT 1",
+                [],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    pub fn get_pubkeys(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT pubkey FROM pubkeys;")?;
+
+        let pubkeys_iter = stmt.query_map([], |row| Ok(row.get(0)?))?;
+        let pubkeys = pubkeys_iter.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(pubkeys)
+    }
+
+    pub fn add_pubkey(&self, pubkey: String) -> Result<()> {
+        self.connection
+            .execute("INSERT INTO pubkeys (pubkey) VALUES (?1)", ((pubkey),))?;
+
+        Ok(())
+    }
+
+    pub fn delete_pubkey(&self, pubkey: String) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM pubkeys WHERE pubkey = ?1", ((pubkey),))?;
+
+        Ok(())
+    }
+
+    pub fn store_event(
+        &self,
+        event: &Event,
+        unwrapped: Option<&UnwrappedGift>,
+        gift_wrap_recipient: Option<&str>,
+    ) -> Result<()> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        if let Some(unwrapped) = unwrapped {
+            let mut rumor = unwrapped.rumor.clone();
+            rumor.ensure_id();
+
+            if unwrapped.sender != rumor.pubkey {
+                anyhow::bail!("Seal signer does not match rumor pubkey");
+            }
+
+            let id = rumor
+                .id
+                .expect("Invalid Gift Wrapped Event: There is no ID!")
+                .to_hex();
+            let author_pubkey = rumor.pubkey.to_string();
+            if self.is_deleted(&id, Some(author_pubkey.as_str()))? {
+                return Ok(());
+            }
+            let raw = json!(rumor).to_string();
+
+            self.connection
+                .prepare_cached("INSERT OR IGNORE INTO events (id, raw) VALUES (?1, ?2)")?
+                .execute((id.clone(), &raw))?;
+            if rumor.kind == nostr::Kind::Custom(MAIL_EVENT_KIND) {
+                self.save_snippet(&id, &rumor.content)?;
+                self.inherit_labels(&id, &raw)?;
+            }
+
+            self.save_gift_wrap_map(
+                &event.id.to_string(),
+                &id,
+                gift_wrap_recipient,
+                event.created_at.as_u64() as i64,
+                Some(Encryption::of_payload(&event.content)),
+            )?;
+            // Our copy of mail we sent, maybe from another device, goes to Sent. A relay just
+            // gave it to us, so it's stored.
+            if gift_wrap_recipient.is_some_and(|recipient| is_copy_to_self(&rumor, recipient)) {
+                self.connection
+                    .prepare_cached(
+                        "INSERT OR IGNORE INTO sent_messages
+                         (wrap_id, event_id, recipient_pubkey, copy, accepted_by, rejected_by,
+                          confirmed_by)
+                         VALUES (?1, ?2, ?3, 1, 1, 0, 1)",
+                    )?
+                    .execute((event.id.to_hex(), &id, &author_pubkey))?;
+                self.connection
+                    .prepare_cached(
+                        "INSERT INTO message_state (event_id, read_at) VALUES (?1, unixepoch())
+                         ON CONFLICT(event_id) DO UPDATE SET read_at = excluded.read_at",
+                    )?
+                    .execute((&id,))?;
+            }
+            return Ok(());
+        }
+
+        let id = event.id.to_string();
+        let author_pubkey = event.pubkey.to_string();
+        if self.is_deleted(&id, Some(author_pubkey.as_str()))? {
+            return Ok(());
+        }
+        let raw = json!(event).to_string();
+
+        self.connection
+            .prepare_cached("INSERT OR IGNORE INTO events (id, raw) VALUES (?1, ?2)")?
+            .execute((id.clone(), &raw))?;
+        if event.kind == nostr::Kind::Custom(MAIL_EVENT_KIND) {
+            self.save_snippet(&id, &event.content)?;
+            self.inherit_labels(&id, &raw)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_snippet(&self, event_id: &str, content: &str) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT OR IGNORE INTO message_snippets (event_id, snippet) VALUES (?1, ?2)",
+            )?
+            .execute((event_id, mail_event::snippet(content)))?;
+        Ok(())
+    }
+
+    /// Gives a reply the labels of the messages it answers, so a labeled thread stays labeled
+    /// as it grows.
+    fn inherit_labels(&self, event_id: &str, raw: &str) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT OR IGNORE INTO message_labels (event_id, label)
+                 SELECT ?1, l.label FROM message_labels l
+                 WHERE l.event_id IN (
+                     SELECT json_extract(t.value, '$[1]') FROM json_each(?2, '$.tags') t
+                     WHERE json_extract(t.value, '$[0]') = 'e'
+                 )",
+            )?
+            .execute((event_id, raw))?;
+        Ok(())
+    }
+
+    /// Works out the snippets of mail stored before snippets were, or by an older version.
+    fn backfill_snippets(&self) -> Result<()> {
+        let missing: Vec<(String, String)> = self
+            .connection
+            .prepare(
+                "SELECT e.id, e.content FROM events e
+                 WHERE e.kind = ?1
+                 AND NOT EXISTS (SELECT 1 FROM message_snippets s WHERE s.event_id = e.id)",
+            )?
+            .query_map((MAIL_EVENT_KIND,), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, rusqlite::Error>>()?;
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        info!("Generating snippets for {} messages", missing.len());
+        let tx = self.connection.unchecked_transaction()?;
+        for (id, content) in &missing {
+            self.save_snippet(id, content)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stores a batch of events in one transaction, which a sync of thousands of gift wraps
+    /// needs to keep up. Each event gets a savepoint of its own, so one that fails is logged
+    /// and left out without losing the rest. Returns whether each event was stored.
+    pub fn store_events(&self, events: &[EventToStore]) -> Result<Vec<bool>> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        // `store_event` goes through the same connection, so it runs inside the transaction.
+        let mut tx = self.connection.unchecked_transaction()?;
+        let mut stored = Vec::with_capacity(events.len());
+        for item in events {
+            let savepoint = tx.savepoint()?;
+            match self.store_event(
+                &item.event,
+                item.unwrapped.as_ref(),
+                item.recipient.as_deref(),
+            ) {
+                Ok(()) => {
+                    savepoint.commit()?;
+                    stored.push(true);
+                }
+                // Dropping the savepoint rolls back what the event got as far as storing.
+                Err(e) => {
+                    error!("Failed to store event {}: {}", item.event.id, e);
+                    stored.push(false);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    pub fn has_event(&self, event_id: &str) -> Result<bool> {
+        let count: i64 = self
+            .connection
+            .prepare_cached("SELECT COUNT(*) FROM events WHERE id = ?")?
+            .query_row([event_id], |row| row.get(0))?;
+
+        Ok(count > 0)
+    }
+
+    pub fn is_deleted(&self, event_id: &str, author_pubkey: Option<&str>) -> Result<bool> {
+        let count: i64 = if let Some(pubkey) = author_pubkey {
+            self.connection
+                .prepare_cached(
+                    "SELECT COUNT(*) FROM deleted_events
+                     WHERE event_id = ?1
+                       AND (author_pubkey IS NULL OR author_pubkey = ?2)",
+                )?
+                .query_row((event_id, pubkey), |row| row.get(0))?
+        } else {
+            self.connection
+                .prepare_cached("SELECT COUNT(*) FROM deleted_events WHERE event_id = ?1")?
+                .query_row((event_id,), |row| row.get(0))?
+        };
+
+        Ok(count > 0)
+    }
+
+    pub fn is_trashed(&self, event_id: &str) -> Result<bool> {
+        let count: i64 = self
+            .connection
+            .prepare_cached("SELECT COUNT(*) FROM trash_events WHERE event_id = ?1")?
+            .query_row((event_id,), |row| row.get(0))?;
+
+        Ok(count > 0)
+    }
+
+    pub fn record_deletions(
+        &mut self,
+        event_ids: &[String],
+        author_pubkey: Option<&str>,
+        source_event_id: Option<&str>,
+    ) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        let mut deletable_ids: Vec<String> = Vec::new();
+
+        if !event_ids.is_empty() {
+            let placeholders = vec!["?"; event_ids.len()].join(",");
+            let mut select_sql = format!("SELECT id FROM events WHERE id IN ({})", placeholders);
+            if let Some(pubkey) = author_pubkey {
+                select_sql.push_str(" AND pubkey = ?");
+                let params = rusqlite::params_from_iter(
+                    event_ids
+                        .iter()
+                        .map(|id| id as &dyn rusqlite::ToSql)
+                        .chain(std::iter::once(&pubkey as &dyn rusqlite::ToSql)),
+                );
+                let mut stmt = tx.prepare(&select_sql)?;
+                let rows = stmt.query_map(params, |row| row.get(0))?;
+                for row in rows {
+                    deletable_ids.push(row?);
+                }
+            } else {
+                let params = rusqlite::params_from_iter(
+                    event_ids.iter().map(|id| id as &dyn rusqlite::ToSql),
+                );
+                let mut stmt = tx.prepare(&select_sql)?;
+                let rows = stmt.query_map(params, |row| row.get(0))?;
+                for row in rows {
+                    deletable_ids.push(row?);
+                }
+            }
+
+            if !deletable_ids.is_empty() {
+                let placeholders = vec!["?"; deletable_ids.len()].join(",");
+                let mut delete_sql = format!("DELETE FROM events WHERE id IN ({})", placeholders);
+                let mut delete_pmeta_sql = format!(
+                    "DELETE FROM profile_metadata WHERE id IN ({})",
+                    placeholders
+                );
+
+                if let Some(pubkey) = author_pubkey {
+                    delete_sql.push_str(" AND pubkey = ?");
+                    delete_pmeta_sql.push_str(" AND pubkey = ?");
+
+                    let params = rusqlite::params_from_iter(
+                        deletable_ids
+                            .iter()
+                            .map(|id| id as &dyn rusqlite::ToSql)
+                            .chain(std::iter::once(&pubkey as &dyn rusqlite::ToSql)),
+                    );
+                    tx.execute(&delete_sql, params)?;
+
+                    let pmeta_params = rusqlite::params_from_iter(
+                        deletable_ids
+                            .iter()
+                            .map(|id| id as &dyn rusqlite::ToSql)
+                            .chain(std::iter::once(&pubkey as &dyn rusqlite::ToSql)),
+                    );
+                    tx.execute(&delete_pmeta_sql, pmeta_params)?;
+                } else {
+                    let params = rusqlite::params_from_iter(
+                        deletable_ids.iter().map(|id| id as &dyn rusqlite::ToSql),
+                    );
+                    tx.execute(&delete_sql, params)?;
+
+                    let pmeta_params = rusqlite::params_from_iter(
+                        deletable_ids.iter().map(|id| id as &dyn rusqlite::ToSql),
+                    );
+                    tx.execute(&delete_pmeta_sql, pmeta_params)?;
+                }
+            }
+        }
+
+        if !deletable_ids.is_empty() {
+            if let Some(author) = author_pubkey {
+                let mut insert_stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO deleted_events (event_id, author_pubkey, source_event_id)
+                     VALUES (?1, ?2, ?3)",
+                )?;
+                for event_id in &deletable_ids {
+                    insert_stmt.execute((event_id, author, source_event_id))?;
+                }
+            } else {
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO deleted_events (event_id, author_pubkey, source_event_id)
+                     VALUES (?1, NULL, ?2)",
+                )?;
+                for event_id in &deletable_ids {
+                    stmt.execute((event_id, source_event_id))?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn record_trash(&mut self, event_ids: &[String], purge_after: i64) -> Result<()> {
+        if event_ids.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.connection.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO trash_events (event_id, purge_after)
+                 VALUES (?1, ?2)",
+            )?;
+            for event_id in event_ids {
+                stmt.execute((event_id, purge_after))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn purge_expired_trash(&mut self, now: i64) -> Result<Vec<String>> {
+        let event_ids: Vec<String> = self
+            .connection
+            .prepare("SELECT event_id FROM trash_events WHERE purge_after <= ?1")?
+            .query_map((now,), |row| row.get(0))?
+            .collect::<Result<_, rusqlite::Error>>()?;
+        self.purge_trash(&event_ids)?;
+        Ok(event_ids)
+    }
+
+    /// Messages in the Trash due to be deleted by `now`, soonest due first.
+    pub fn get_trash_due(&self, now: i64) -> Result<Vec<String>> {
+        let event_ids = self
+            .connection
+            .prepare_cached(
+                "SELECT event_id FROM trash_events WHERE purge_after <= ?1 ORDER BY purge_after",
+            )?
+            .query_map((now,), |row| row.get(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(event_ids)
+    }
+
+    /// Deletes trashed messages for good, remembering their ids so relays can't bring them
+    /// back.
+    pub fn purge_trash(&mut self, event_ids: &[String]) -> Result<()> {
+        if event_ids.is_empty() {
+            return Ok(());
+        }
+        let tx = self.connection.transaction()?;
+        {
+            let placeholders = vec!["?"; event_ids.len()].join(",");
+            let delete_events_sql = format!("DELETE FROM events WHERE id IN ({})", placeholders);
+            let delete_pmeta_sql = format!(
+                "DELETE FROM profile_metadata WHERE id IN ({})",
+                placeholders
+            );
+            let delete_trash_sql = format!(
+                "DELETE FROM trash_events WHERE event_id IN ({})",
+                placeholders
+            );
+
+            let mut insert_stmt = tx.prepare(
+                "INSERT OR IGNORE INTO deleted_events (event_id, author_pubkey, source_event_id)
+                 VALUES (?1, NULL, NULL)",
+            )?;
+            for event_id in event_ids {
+                insert_stmt.execute((event_id,))?;
+            }
+
+            let params =
+                rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+            tx.execute(&delete_events_sql, params)?;
+
+            let params =
+                rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+            tx.execute(&delete_pmeta_sql, params)?;
+
+            let params =
+                rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+            tx.execute(&delete_trash_sql, params)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn restore_from_trash(&mut self, event_id: &str) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM trash_events WHERE event_id = ?1", (event_id,))?;
+        Ok(())
+    }
+
+    pub fn purge_deleted_events(&mut self) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "DELETE FROM events
+             WHERE EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = events.id
+                   AND (d.author_pubkey IS NULL OR d.author_pubkey = events.pubkey)
+             )",
+            [],
+        )?;
+        tx.execute(
+            "DELETE FROM profile_metadata
+             WHERE EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = profile_metadata.id
+                   AND (d.author_pubkey IS NULL OR d.author_pubkey = profile_metadata.pubkey)
+             )",
+            [],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Looks for what a crash or an old bug can leave behind. Every mail event is parsed, so
+    /// the app runs it on its reader connection; nothing is changed until `repair_integrity`.
+    pub fn integrity_check(&self) -> Result<IntegrityReport> {
+        let count = |table: &str, clause: &str| -> Result<usize> {
+            let query = format!("SELECT COUNT(*) FROM {} WHERE {}", table, clause);
+            Ok(self.connection.query_row(&query, [], |row| row.get(0))?)
+        };
+        Ok(IntegrityReport {
+            orphaned_states: count("message_state", ORPHANED_STATES)?,
+            broken_events: self.broken_events()?,
+            unindexed: count("events", &UNINDEXED)?,
+            stale_index: count("message_search", STALE_INDEX)?,
+        })
+    }
+
+    /// Mail events that don't parse the way threads are read.
+    fn broken_events(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT id, raw FROM events WHERE kind = ?1")?;
+        let rows = stmt.query_map([MAIL_EVENT_KIND], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut broken = Vec::new();
+        for row in rows {
+            let (id, raw) = row?;
+            if serde_json::from_str::<RawEventData>(&raw).is_err() {
+                broken.push(id);
+            }
+        }
+        Ok(broken)
+    }
+
+    /// Fixes what `integrity_check` finds: unreadable mail is dropped, so a relay can send it
+    /// again, along with marks nothing needs, and the search index is brought back in step.
+    /// Returns what was fixed.
+    pub fn repair_integrity(&mut self) -> Result<IntegrityReport> {
+        let broken_events = self.broken_events()?;
+        let tx = self.connection.transaction()?;
+        for id in &broken_events {
+            tx.execute("DELETE FROM events WHERE id = ?1", (id,))?;
+            // Otherwise the wrap would be skipped as already stored when it comes again.
+            tx.execute("DELETE FROM gift_wrap_map WHERE inner_id = ?1", (id,))?;
+        }
+        let orphaned_states = tx.execute(
+            &format!("DELETE FROM message_state WHERE {}", ORPHANED_STATES),
+            [],
+        )?;
+        let unindexed = tx.execute(
+            &format!(
+                "INSERT INTO message_search (event_id, subject, content)
+                 SELECT
+                     id,
+                     COALESCE((SELECT jsonb_extract(tag.value, '$[1]')
+                               FROM json_each(events.tags) AS tag
+                               WHERE jsonb_extract(tag.value, '$[0]') = 'subject'
+                               LIMIT 1), ''),
+                     content
+                 FROM events
+                 WHERE {}",
+                *UNINDEXED
+            ),
+            [],
+        )?;
+        let stale_index = tx.execute(
+            &format!("DELETE FROM message_search WHERE {}", STALE_INDEX),
+            [],
+        )?;
+        tx.commit()?;
+        Ok(IntegrityReport {
+            orphaned_states,
+            broken_events,
+            unindexed,
+            stale_index,
+        })
+    }
+
+    /// Record deletion markers without requiring the IDs to exist in the events table.
+    /// Used for gift wrap IDs which are stored in gift_wrap_map, not in events.
+    pub fn record_deletion_markers(
+        &self,
+        event_ids: &[String],
+        source_event_id: Option<&str>,
+    ) -> Result<()> {
+        if event_ids.is_empty() {
+            return Ok(());
+        }
+        let mut stmt = self.connection.prepare_cached(
+            "INSERT OR IGNORE INTO deleted_events (event_id, author_pubkey, source_event_id)
+             VALUES (?1, NULL, ?2)",
+        )?;
+        for event_id in event_ids {
+            stmt.execute((event_id, source_event_id))?;
+        }
+        Ok(())
+    }
+
+    pub fn save_gift_wrap_map(
+        &self,
+        wrap_id: &str,
+        inner_id: &str,
+        recipient_pubkey: Option<&str>,
+        created_at: i64,
+        encryption: Option<Encryption>,
+    ) -> Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT OR IGNORE INTO gift_wrap_map
+                     (wrap_id, inner_id, recipient_pubkey, created_at, encryption)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute((
+                wrap_id,
+                inner_id,
+                recipient_pubkey,
+                created_at,
+                encryption.map(|encryption| encryption.key()),
+            ))?;
+        Ok(())
+    }
+
+    /// Moves everything in the write-ahead log into the database file. Does nothing unless
+    /// the database is in WAL mode.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.connection
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// (created_at, wrap id) of every gift wrap we've stored, for syncing with relays.
+    pub fn get_gift_wrap_items(&self) -> Result<Vec<(u64, String)>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT created_at, wrap_id FROM gift_wrap_map WHERE created_at IS NOT NULL",
+        )?;
+        let items = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)))?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(items)
+    }
+
+    /// Stored gift wraps per recipient pubkey.
+    pub fn count_gift_wraps(&self) -> Result<HashMap<String, i64>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT recipient_pubkey, COUNT(*) FROM gift_wrap_map
+             WHERE recipient_pubkey IS NOT NULL
+             GROUP BY recipient_pubkey",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<String, i64>, rusqlite::Error>>()?;
+        Ok(counts)
+    }
+
+    pub fn gift_wrap_exists(&self, wrap_id: &str) -> Result<bool> {
+        let count: i64 = self
+            .connection
+            .prepare_cached("SELECT COUNT(*) FROM gift_wrap_map WHERE wrap_id = ?1")?
+            .query_row((wrap_id,), |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    pub fn delete_from_trash(&mut self, event_ids: &[String]) -> Result<()> {
+        if event_ids.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; event_ids.len()].join(",");
+        let sql = format!(
+            "DELETE FROM trash_events WHERE event_id IN ({})",
+            placeholders
+        );
+        let params =
+            rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+        self.connection.execute(&sql, params)?;
+        Ok(())
+    }
+
+    pub fn get_wrap_ids_for_inner(&self, inner_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT wrap_id FROM gift_wrap_map WHERE inner_id = ?1")?;
+        let rows = stmt.query_map((inner_id,), |row| row.get(0))?;
+        let mut wrap_ids = Vec::new();
+        for row in rows {
+            wrap_ids.push(row?);
+        }
+        Ok(wrap_ids)
+    }
+
+    /// Every gift wrap `inner_id` travelled in, whether it came in or we sent it. Received
+    /// copies come first.
+    pub fn get_message_wraps(&self, inner_id: &str) -> Result<Vec<WrapCopy>> {
+        // A copy we sent to ourselves comes back in, so it shows up in both tables.
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT wrap_id, MAX(recipient_pubkey), MAX(sent) AS sent, MAX(encryption),
+                 MAX(created_at) FROM (
+                 SELECT wrap_id, recipient_pubkey, 0 AS sent, encryption, created_at
+                 FROM gift_wrap_map WHERE inner_id = ?1
+                 UNION ALL
+                 SELECT wrap_id, recipient_pubkey, 1 AS sent, NULL, NULL FROM sent_messages
+                 WHERE event_id = ?1
+             )
+             GROUP BY wrap_id
+             ORDER BY sent, wrap_id",
+        )?;
+        let wraps = stmt
+            .query_map((inner_id,), |row| {
+                Ok(WrapCopy {
+                    wrap_id: row.get(0)?,
+                    recipient: row.get(1)?,
+                    sent: row.get(2)?,
+                    encryption: row
+                        .get::<_, Option<String>>(3)?
+                        .and_then(|key| Encryption::from_key(&key)),
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<WrapCopy>, rusqlite::Error>>()?;
+        Ok(wraps)
+    }
+
+    /// The account of ours a thread is with: the one its first message was wrapped for, or
+    /// the one that wrote it when we started the thread.
+    pub fn get_thread_account(&self, root_id: &str) -> Result<Option<String>> {
+        let account = self
+            .connection
+            .prepare_cached(
+                "SELECT COALESCE(
+                     (SELECT recipient_pubkey FROM gift_wrap_map
+                      WHERE inner_id = ?1 AND recipient_pubkey IS NOT NULL LIMIT 1),
+                     (SELECT e.pubkey FROM events e
+                      WHERE e.id = ?1
+                      AND EXISTS (SELECT 1 FROM sent_messages s WHERE s.event_id = e.id))
+                 )",
+            )?
+            .query_row((root_id,), |row| row.get(0))?;
+        Ok(account)
+    }
+
+    /// Remembers which relays delivered each (event id, relay url) pair. Later copies from the
+    /// same relay keep the first time we saw it there.
+    pub fn record_event_sources(&mut self, sightings: &[(String, String)]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO event_sources (event_id, relay_url) VALUES (?1, ?2)",
+            )?;
+            for (event_id, relay_url) in sightings {
+                stmt.execute((event_id, relay_url))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forgets the relays of events first seen before `before` that we don't keep, neither as
+    /// an event nor as the gift wrap of one. Returns how many sightings went.
+    pub fn prune_event_sources(&mut self, before: i64) -> Result<usize> {
+        let pruned = self.connection.execute(
+            "DELETE FROM event_sources
+             WHERE first_seen_at < ?1
+             AND event_id NOT IN (SELECT id FROM events)
+             AND event_id NOT IN (SELECT wrap_id FROM gift_wrap_map)",
+            (before,),
+        )?;
+        Ok(pruned)
+    }
+
+    /// (relay url, first seen at) for every relay that delivered one of `event_ids`, earliest
+    /// first. A relay listed for several of them shows up once.
+    pub fn get_event_sources(&self, event_ids: &[String]) -> Result<Vec<(String, i64)>> {
+        if event_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; event_ids.len()].join(",");
+        let sql = format!(
+            "SELECT relay_url, MIN(first_seen_at) AS seen FROM event_sources
+             WHERE event_id IN ({})
+             GROUP BY relay_url
+             ORDER BY seen, relay_url",
+            placeholders
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map(
+            rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql)),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut sources = Vec::new();
+        for row in rows {
+            sources.push(row?);
+        }
+        Ok(sources)
+    }
+
+    /// Relays that delivered a message, with when each did first. Mail comes in gift wraps,
+    /// so their relays count for the message inside too.
+    pub fn get_message_sources(&self, event_id: &str) -> Result<Vec<(String, i64)>> {
+        let mut ids = vec![event_id.to_string()];
+        ids.extend(self.get_wrap_ids_for_inner(event_id)?);
+        self.get_event_sources(&ids)
+    }
+
+    pub fn get_trashed_event_ids(&self, event_ids: &[String]) -> Result<HashSet<String>> {
+        let mut trashed = HashSet::new();
+        if event_ids.is_empty() {
+            return Ok(trashed);
+        }
+
+        let placeholders = vec!["?"; event_ids.len()].join(",");
+        let sql = format!(
+            "SELECT event_id FROM trash_events WHERE event_id IN ({})",
+            placeholders
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map(
+            rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql)),
+            |row| row.get(0),
+        )?;
+        for row in rows {
+            trashed.insert(row?);
+        }
+        Ok(trashed)
+    }
+
+    /// The messages among `event_ids` that haven't been read yet.
+    pub fn get_unread_event_ids(&self, event_ids: &[String]) -> Result<HashSet<String>> {
+        let mut unread = HashSet::new();
+        if event_ids.is_empty() {
+            return Ok(unread);
+        }
+
+        let placeholders = vec!["?"; event_ids.len()].join(",");
+        let sql = format!(
+            "SELECT e.id FROM events e
+             WHERE e.id IN ({})
+             AND NOT EXISTS (
+                 SELECT 1 FROM message_state m
+                 WHERE m.event_id = e.id AND m.read_at IS NOT NULL
+             )",
+            placeholders
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map(
+            rusqlite::params_from_iter(event_ids.iter().map(|id| id as &dyn rusqlite::ToSql)),
+            |row| row.get(0),
+        )?;
+        for row in rows {
+            unread.insert(row?);
+        }
+        Ok(unread)
+    }
+
+    pub fn get_event_kind_pubkey(&self, event_id: &str) -> Result<Option<(i64, String)>> {
+        self.connection
+            .query_row(
+                "SELECT kind, pubkey FROM events WHERE id = ?1",
+                (event_id,),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// The event as stored, as JSON.
+    pub fn get_event_raw(&self, event_id: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row("SELECT raw FROM events WHERE id = ?1", (event_id,), |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Pubkeys from the `p` tags of a stored event.
+    pub fn get_event_recipients(&self, event_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT DISTINCT jsonb_extract(ptag.value, '$[1]')
+             FROM events e, json_each(e.tags) AS ptag
+             WHERE e.id = ?1
+             AND jsonb_extract(ptag.value, '$[0]') = 'p'",
+        )?;
+        let recipients = stmt
+            .query_map((event_id,), |row| row.get(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(recipients)
+    }
+
+    // there is a high chance i am a retard.
+    // there is a very high chance that there is a better way to do this
+    // but it's not coming to mind! guess we'll find out.
+    // context: see profile_metadata sql definition and compare to events definition
+
+    pub fn get_profile_metadata(&self, pubkey: &str) -> Result<Option<ProfileMetadata>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT * FROM profile_metadata WHERE pubkey = ?")?;
+
+        Ok(stmt
+            .query_one([pubkey], |row| {
+                Ok(ProfileMetadata {
+                    name: row.get(2)?,
+                    display_name: row.get(3)?,
+                    picture: row.get(4)?,
+                    lud16: row.get("lud16")?,
+                })
+            })
+            .optional()?)
+    }
+
+    pub fn get_contacts(&self) -> Result<Vec<(String, ProfileMetadata)>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT pubkey, name, display_name, picture, lud16
+             FROM profile_metadata
+             ORDER BY LOWER(COALESCE(display_name, name, pubkey))",
+        )?;
+
+        let contacts_iter = stmt.query_map([], |row| {
+            let pubkey: String = row.get(0)?;
+            let metadata = ProfileMetadata {
+                name: row.get(1)?,
+                display_name: row.get(2)?,
+                picture: row.get(3)?,
+                lud16: row.get(4)?,
+            };
+            Ok((pubkey, metadata))
+        })?;
+
+        let mut contacts = Vec::new();
+        for contact in contacts_iter {
+            contacts.push(contact?);
+        }
+
+        Ok(contacts)
+    }
+
+    /// This function combines `write_profile_metadata` and `pmeta_is_newer` into
+    /// one nice package.
+    pub fn update_profile_metadata(&self, event: nostr::Event) -> Result<()> {
+        if self.pmeta_is_newer(event.pubkey, event.created_at.as_u64())? {
+            // we have new information
+            self.write_profile_metadata(event)?;
+        }
+
+        Ok(())
+    }
+
+    /// This writes a raw profile metadata event to the DB.
+    pub fn write_profile_metadata(&self, event: nostr::Event) -> Result<()> {
+        if event.kind != nostr::Kind::Metadata {
+            anyhow::bail!("Event provided is not a kind 0 event.");
+        }
+
+        use nostr::JsonUtil;
+        let meta: nostr::Metadata = nostr::Metadata::from_json(event.content)?;
+
+        self.connection
+            .execute("REPLACE INTO profile_metadata (pubkey, id, name, display_name, picture, created_at, nip05, lud16) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                (event.pubkey.to_string(), event.id.to_string(), meta.name, meta.display_name, meta.picture, event.created_at.as_u64(), meta.nip05, meta.lud16)
+            )?;
+        Ok(())
+    }
+
+    /// Add a contact to the contacts table. If the contact already exists, update the petname.
+    pub fn save_contact(&self, pubkey: &str, petname: Option<&str>) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO contacts (pubkey, petname) VALUES (?1, ?2)
+             ON CONFLICT(pubkey) DO UPDATE SET petname = ?2",
+            (pubkey, petname),
+        )?;
+        Ok(())
+    }
+
+    /// Update just the petname for an existing contact.
+    pub fn update_contact_petname(&self, pubkey: &str, petname: Option<&str>) -> Result<()> {
+        self.connection.execute(
+            "UPDATE contacts SET petname = ?1 WHERE pubkey = ?2",
+            (petname, pubkey),
+        )?;
+        Ok(())
+    }
+
+    /// Delete a contact from the contacts table.
+    pub fn delete_contact(&self, pubkey: &str) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM contacts WHERE pubkey = ?1", (pubkey,))?;
+        Ok(())
+    }
+
+    /// Remembers the challenge we mailed `pubkey`, replacing any earlier one.
+    pub fn save_verification_challenge(&self, pubkey: &str, challenge: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO verification_challenges (pubkey, challenge) VALUES (?1, ?2)
+             ON CONFLICT(pubkey) DO UPDATE SET challenge = ?2, sent_at = unixepoch()",
+            (pubkey, challenge),
+        )?;
+        Ok(())
+    }
+
+    /// The challenge `pubkey` still has to answer, if we sent one.
+    pub fn get_verification_challenge(&self, pubkey: &str) -> Result<Option<String>> {
+        let challenge = self
+            .connection
+            .query_row(
+                "SELECT challenge FROM verification_challenges WHERE pubkey = ?1",
+                (pubkey,),
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(challenge)
+    }
+
+    /// Marks the contact `pubkey` as verified, which settles their challenge.
+    pub fn mark_contact_verified(&mut self, pubkey: &str) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "UPDATE contacts SET verified_at = unixepoch() WHERE pubkey = ?1",
+            (pubkey,),
+        )?;
+        tx.execute(
+            "DELETE FROM verification_challenges WHERE pubkey = ?1",
+            (pubkey,),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The contacts that answered a verification challenge.
+    pub fn get_verified_contacts(&self) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT pubkey FROM contacts WHERE verified_at IS NOT NULL")?;
+        let pubkeys = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, rusqlite::Error>>()?;
+        Ok(pubkeys)
+    }
+
+    /// Check if a pubkey is in the contacts table.
+    pub fn is_contact(&self, pubkey: &str) -> Result<bool> {
+        let count: i64 = self.connection.query_row(
+            "SELECT COUNT(*) FROM contacts WHERE pubkey = ?1",
+            [pubkey],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Get all user contacts joined with their profile metadata.
+    /// Returns (pubkey, petname, ProfileMetadata).
+    pub fn get_user_contacts(&self) -> Result<Vec<(String, Option<String>, ProfileMetadata)>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT c.pubkey, c.petname, pm.name, pm.display_name, pm.picture, pm.lud16
+             FROM contacts c
+             LEFT JOIN profile_metadata pm ON c.pubkey = pm.pubkey
+             WHERE c.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+             ORDER BY LOWER(COALESCE(c.petname, pm.display_name, pm.name, c.pubkey))",
+        )?;
+
+        let contacts_iter = stmt.query_map([], |row| {
+            let pubkey: String = row.get(0)?;
+            let petname: Option<String> = row.get(1)?;
+            let metadata = ProfileMetadata {
+                name: row.get(2)?,
+                display_name: row.get(3)?,
+                picture: row.get(4)?,
+                lud16: row.get(5)?,
+            };
+            Ok((pubkey, petname, metadata))
+        })?;
+
+        let mut contacts = Vec::new();
+        for contact in contacts_iter {
+            contacts.push(contact?);
+        }
+        Ok(contacts)
+    }
+
+    /// Get the petname for a given pubkey, if they are a contact.
+    pub fn get_contact_petname(&self, pubkey: &str) -> Result<Option<String>> {
+        let result: Option<Option<String>> = self
+            .connection
+            .query_row(
+                "SELECT petname FROM contacts WHERE pubkey = ?1",
+                [pubkey],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(result.flatten())
+    }
+
+    /// Contacts that look like the same person more than once: the same key saved in different
+    /// encodings (npub and hex were both accepted before keys were normalized), or different
+    /// keys sharing a NIP-05 address.
+    pub fn find_duplicate_contacts(&self) -> Result<Vec<DuplicateContacts>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT c.pubkey, pm.nip05
+             FROM contacts c
+             LEFT JOIN profile_metadata pm ON c.pubkey = pm.pubkey
+             ORDER BY c.created_at, c.pubkey",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })?;
+
+        let mut by_key: Vec<(String, Vec<String>)> = Vec::new();
+        let mut by_nip05: Vec<(String, Vec<String>)> = Vec::new();
+        for row in rows {
+            let (pubkey, nip05) = row?;
+            let canonical = PublicKey::parse(&pubkey)
+                .map(|pk| pk.to_hex())
+                .unwrap_or_else(|_| pubkey.to_lowercase());
+            match by_key.iter_mut().find(|(key, _)| *key == canonical) {
+                Some((_, pubkeys)) => pubkeys.push(pubkey),
+                None => by_key.push((canonical.clone(), vec![pubkey])),
+            }
+
+            let Some(nip05) = nip05
+                .map(|nip05| nip05.trim().to_lowercase())
+                .filter(|nip05| !nip05.is_empty())
+            else {
+                continue;
+            };
+            match by_nip05.iter_mut().find(|(address, _)| *address == nip05) {
+                Some((_, pubkeys)) if !pubkeys.contains(&canonical) => pubkeys.push(canonical),
+                Some(_) => {}
+                None => by_nip05.push((nip05, vec![canonical])),
+            }
+        }
+
+        let same_key = by_key
+            .into_iter()
+            .filter(|(_, pubkeys)| pubkeys.len() > 1)
+            .map(|(canonical, pubkeys)| DuplicateContacts {
+                reason: DuplicateReason::SamePubkey(canonical),
+                pubkeys,
+            });
+        let same_nip05 = by_nip05
+            .into_iter()
+            .filter(|(_, pubkeys)| pubkeys.len() > 1)
+            .map(|(nip05, pubkeys)| DuplicateContacts {
+                reason: DuplicateReason::SameNip05(nip05),
+                pubkeys,
+            });
+        Ok(same_key.chain(same_nip05).collect())
+    }
+
+    /// Folds the `merged` contacts into `keep`, which takes over their group memberships and
+    /// ends up with `petname`. All or nothing.
+    pub fn merge_contacts(
+        &mut self,
+        keep: &str,
+        merged: &[String],
+        petname: Option<&str>,
+    ) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "INSERT INTO contacts (pubkey, petname) VALUES (?1, ?2)
+             ON CONFLICT(pubkey) DO UPDATE SET petname = excluded.petname",
+            (keep, petname),
+        )?;
+        for pubkey in merged.iter().filter(|pubkey| *pubkey != keep) {
+            tx.execute(
+                "INSERT OR IGNORE INTO contact_group_members (group_id, pubkey)
+                 SELECT group_id, ?1 FROM contact_group_members WHERE pubkey = ?2",
+                (keep, pubkey),
+            )?;
+            tx.execute(
+                "DELETE FROM contact_group_members WHERE pubkey = ?1",
+                (pubkey,),
+            )?;
+            tx.execute("DELETE FROM contacts WHERE pubkey = ?1", (pubkey,))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Block a pubkey. Their messages are hidden and new ones are dropped on arrival.
+    pub fn block_pubkey(&self, pubkey: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO blocked_pubkeys (pubkey) VALUES (?1)",
+            (pubkey,),
+        )?;
+        Ok(())
+    }
+
+    pub fn unblock_pubkey(&self, pubkey: &str) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM blocked_pubkeys WHERE pubkey = ?1", (pubkey,))?;
+        Ok(())
+    }
+
+    /// Get all blocked pubkeys, most recently blocked first.
+    pub fn get_blocked_pubkeys(&self) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT pubkey FROM blocked_pubkeys ORDER BY blocked_at DESC, pubkey",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let pubkeys = rows.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(pubkeys)
+    }
+
+    /// Save a contact group, replacing the members of an existing group with the same name.
+    pub fn save_contact_group(&mut self, name: &str, pubkeys: &[String]) -> Result<i64> {
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "INSERT INTO contact_groups (name) VALUES (?1) ON CONFLICT(name) DO NOTHING",
+            (name,),
+        )?;
+        let group_id: i64 = tx.query_row(
+            "SELECT id FROM contact_groups WHERE name = ?1",
+            (name,),
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "DELETE FROM contact_group_members WHERE group_id = ?1",
+            (group_id,),
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO contact_group_members (group_id, pubkey) VALUES (?1, ?2)",
+            )?;
+            for pubkey in pubkeys {
+                stmt.execute((group_id, pubkey))?;
+            }
+        }
+        tx.commit()?;
+        Ok(group_id)
+    }
+
+    /// Saves `query` under `name`, replacing the query of a search already called that.
+    pub fn save_search(&self, name: &str, query: &str) -> Result<i64> {
+        self.connection.execute(
+            "INSERT INTO saved_searches (name, query) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET query = excluded.query",
+            (name, query),
+        )?;
+        let id = self.connection.query_row(
+            "SELECT id FROM saved_searches WHERE name = ?1",
+            (name,),
+            |row| row.get(0),
+        )?;
+        Ok(id)
+    }
+
+    pub fn delete_saved_search(&self, id: i64) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM saved_searches WHERE id = ?1", (id,))?;
+        Ok(())
+    }
+
+    /// Saved searches, sorted by name.
+    pub fn get_saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT id, name, query FROM saved_searches ORDER BY LOWER(name)")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SavedSearch {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                query: row.get(2)?,
+            })
+        })?;
+        let searches = rows.collect::<Result<Vec<SavedSearch>, rusqlite::Error>>()?;
+        Ok(searches)
+    }
+
+    pub fn delete_contact_group(&mut self, group_id: i64) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "DELETE FROM contact_group_members WHERE group_id = ?1",
+            (group_id,),
+        )?;
+        tx.execute("DELETE FROM contact_groups WHERE id = ?1", (group_id,))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get all contact groups as (id, name), sorted by name.
+    pub fn get_contact_groups(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT id, name FROM contact_groups ORDER BY LOWER(name)")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let groups = rows.collect::<Result<Vec<(i64, String)>, rusqlite::Error>>()?;
+        Ok(groups)
+    }
+
+    pub fn get_contact_group_members(&self, group_id: i64) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT pubkey FROM contact_group_members WHERE group_id = ?1")?;
+        let rows = stmt.query_map((group_id,), |row| row.get(0))?;
+        let members = rows.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(members)
+    }
+
+    /// Check to see if the created_at for the profile metadata event is newer than
+    /// what we have saved for this pubkey.
+    /// Returns true if `created_at` is newer than what is saved, and false if they are the same or older
+    /// Note to self/TODO: Look into forking the nostr crate to convert time stamps to i64.
+    fn pmeta_is_newer(&self, pubkey: nostr::PublicKey, created_at: u64) -> Result<bool> {
+        self.connection
+            .execute(
+                "SELECT EXISTS (SELECT 1 FROM profile_metadata WHERE pubkey = $1 AND created_at <= $2) AS wow;",
+                (pubkey.to_string(), created_at)
+            )?;
+        Ok(true)
+    }
+
+    /// These messages will be displayed inside the top-level table.
+    /// With an `account`, only threads that account took part in are returned.
+    /// Threads holding nothing but mail we sent are left to the Sent folder.
+    /// A thread matches `filter` if any of its messages does.
+    pub fn get_top_level_messages(
+        &self,
+        account: Option<&str>,
+        filter: &MessageFilter,
+    ) -> Result<Vec<TableEntry>> {
+        self.get_top_level_page(account, filter, &Page::All)
+    }
+
+    /// The part of [`Db::get_top_level_messages`] that `page` asks for, newest first.
+    pub fn get_top_level_page(
+        &self,
+        account: Option<&str>,
+        filter: &MessageFilter,
+        page: &Page,
+    ) -> Result<Vec<TableEntry>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let sql = format!(
+            "{}
+SELECT
+    r.id,
+    COALESCE((SELECT snippet FROM message_snippets WHERE event_id = le.id), ''),
+    le.created_at,
+    le.pubkey,
+    (SELECT jsonb_extract(stag.value, '$[1]')
+     FROM json_each(le.tags) AS stag
+     WHERE jsonb_extract(stag.value, '$[0]') = 'subject'
+     LIMIT 1) as subject,
+    (SELECT COUNT(*) FROM thread t WHERE t.root_id = r.id) as thread_count,
+    (SELECT json_group_array(DISTINCT l.label) FROM thread t
+     JOIN message_labels l ON l.event_id = t.msg_id
+     WHERE t.root_id = r.id) as labels
+{}
+ORDER BY
+    CASE WHEN ?13 THEN le.created_at END ASC,
+    CASE WHEN ?13 THEN r.id END ASC,
+    le.created_at DESC,
+    r.id DESC
+LIMIT ?14",
+            TOP_LEVEL_THREADS, TOP_LEVEL_MATCHES
+        );
+        let mut messages = self.query_top_level(&sql, account, filter, page, |row| {
+            Ok(TableEntry {
+                id: row.get(0)?,
+                snippet: row.get(1)?,
+                created_at: row.get(2)?,
+                pubkey: row.get(3)?,
+                subject: row.get(4)?,
+                thread_count: row.get(5)?,
+                labels: sorted_labels(&row.get::<_, String>(6)?),
+            })
+        })?;
+        if matches!(page, Page::After(..)) {
+            messages.reverse();
+        }
+
+        Ok(messages)
+    }
+
+    /// How many threads [`Db::get_top_level_messages`] returns, counted without loading them.
+    pub fn count_top_level_messages(
+        &self,
+        account: Option<&str>,
+        filter: &MessageFilter,
+    ) -> Result<usize> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let sql = format!(
+            "{}\nSELECT COUNT(*)\n{}",
+            TOP_LEVEL_THREADS, TOP_LEVEL_MATCHES
+        );
+        let counts = self.query_top_level(&sql, account, filter, &Page::All, |row| {
+            row.get::<_, i64>(0)
+        })?;
+        Ok(counts.first().map_or(0, |count| *count as usize))
+    }
+
+    /// Runs `sql`, made of [`TOP_LEVEL_THREADS`] and [`TOP_LEVEL_MATCHES`], with the
+    /// parameters those take for `account`, `filter` and `page`.
+    fn query_top_level<T>(
+        &self,
+        sql: &str,
+        account: Option<&str>,
+        filter: &MessageFilter,
+        page: &Page,
+        row: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut stmt = self.connection.prepare_cached(sql)?;
+        let (upper, inclusive, lower, limit) = match page {
+            Page::All => (None, false, None, None),
+            Page::First(limit) => (None, false, None, Some(limit)),
+            Page::From(cursor, limit) => (Some(cursor), true, None, Some(limit)),
+            Page::Before(cursor, limit) => (Some(cursor), false, None, Some(limit)),
+            Page::After(cursor, limit) => (None, false, Some(cursor), Some(limit)),
+        };
+        let ascending = lower.is_some();
+        let params = rusqlite::params![
+            account,
+            filter.fts_query(),
+            filter.unread,
+            filter.starred,
+            filter.has_attachment,
+            filter.from_contacts,
+            filter.archived,
+            upper.map(|cursor| cursor.created_at),
+            upper.map(|cursor| &cursor.id),
+            inclusive,
+            lower.map(|cursor| cursor.created_at),
+            lower.map(|cursor| &cursor.id),
+            ascending,
+            limit.map_or(-1, |limit| *limit as i64),
+            filter.focused,
+            (!filter.note_terms.is_empty()).then(|| json!(filter.note_terms).to_string()),
+        ];
+        let rows = stmt.query_map(params, row)?;
+        Ok(rows.collect::<Result<Vec<T>, rusqlite::Error>>()?)
+    }
+
+    /// With an `account`, only messages sent by or addressed to that account are returned.
+    pub fn get_trash_messages(&self, account: Option<&str>) -> Result<Vec<TableEntry>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT
+                 e.id,
+                 COALESCE((SELECT snippet FROM message_snippets WHERE event_id = e.id), ''),
+                 e.created_at,
+                 e.pubkey,
+                 COALESCE((SELECT jsonb_extract(stag.value, '$[1]')
+                  FROM json_each(e.tags) AS stag
+                  WHERE jsonb_extract(stag.value, '$[0]') = 'subject'
+                  LIMIT 1), '') as subject,
+                 1 as thread_count
+             FROM events e
+             JOIN trash_events t ON t.event_id = e.id
+             WHERE ?1 IS NULL OR e.pubkey = ?1 OR EXISTS (
+                 SELECT 1 FROM json_each(e.tags) AS ptag
+                 WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+                 AND jsonb_extract(ptag.value, '$[1]') = ?1
+             )
+             ORDER BY t.trashed_at DESC",
+        )?;
+
+        let msgs_iter = stmt.query_map((account,), |row| {
+            Ok(TableEntry {
+                id: row.get(0)?,
+                snippet: row.get(1)?,
+                created_at: row.get(2)?,
+                pubkey: row.get(3)?,
+                subject: row.get(4)?,
+                thread_count: row.get(5)?,
+                labels: Vec::new(),
+            })
+        })?;
+
+        let messages = msgs_iter.collect::<Result<Vec<TableEntry>, rusqlite::Error>>()?;
+        Ok(messages)
+    }
+
+    pub fn mark_read(&mut self, event_ids: &[String]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for event_id in event_ids {
+            tx.execute(
+                "INSERT INTO message_state (event_id, read_at) VALUES (?1, unixepoch())
+                 ON CONFLICT(event_id) DO UPDATE SET read_at = excluded.read_at",
+                (event_id,),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Archived threads leave the inbox until a message that isn't archived shows up in them.
+    pub fn set_archived(&mut self, event_ids: &[String], archived: bool) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for event_id in event_ids {
+            tx.execute(
+                "INSERT INTO message_state (event_id, archived) VALUES (?1, ?2)
+                 ON CONFLICT(event_id) DO UPDATE SET archived = excluded.archived",
+                (event_id, archived),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn set_starred(&self, event_id: &str, starred: bool) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO message_state (event_id, starred) VALUES (?1, ?2)
+             ON CONFLICT(event_id) DO UPDATE SET starred = excluded.starred",
+            (event_id, starred),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_starred_ids(&self) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT event_id FROM message_state WHERE starred = 1")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let ids = rows.collect::<Result<HashSet<String>, rusqlite::Error>>()?;
+        Ok(ids)
+    }
+
+    /// The subjects the user gave threads, by the thread's root id.
+    pub fn get_thread_subjects(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT root_id, subject FROM thread_subjects")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let subjects = rows.collect::<Result<HashMap<String, String>, rusqlite::Error>>()?;
+        Ok(subjects)
+    }
+
+    /// Shows the thread at `root_id` under `subject`, or under its own again with `None`.
+    pub fn set_thread_subject(&self, root_id: &str, subject: Option<&str>) -> Result<()> {
+        match subject {
+            Some(subject) => self.connection.execute(
+                "INSERT INTO thread_subjects (root_id, subject) VALUES (?1, ?2)
+                 ON CONFLICT(root_id) DO UPDATE SET subject = excluded.subject",
+                (root_id, subject),
+            )?,
+            None => self
+                .connection
+                .execute("DELETE FROM thread_subjects WHERE root_id = ?1", (root_id,))?,
+        };
+        Ok(())
+    }
+
+    /// The notes the user keeps on threads, by the thread's root id.
+    pub fn get_thread_notes(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT root_id, note FROM thread_notes")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let notes = rows.collect::<Result<HashMap<String, String>, rusqlite::Error>>()?;
+        Ok(notes)
+    }
+
+    /// Keeps `note` on the thread at `root_id`, or drops the note with `None`.
+    pub fn set_thread_note(&self, root_id: &str, note: Option<&str>) -> Result<()> {
+        match note {
+            Some(note) => self.connection.execute(
+                "INSERT INTO thread_notes (root_id, note, updated_at) VALUES (?1, ?2, unixepoch())
+                 ON CONFLICT(root_id) DO UPDATE
+                 SET note = excluded.note, updated_at = excluded.updated_at",
+                (root_id, note),
+            )?,
+            None => self
+                .connection
+                .execute("DELETE FROM thread_notes WHERE root_id = ?1", (root_id,))?,
+        };
+        Ok(())
+    }
+
+    /// The invoices sent in messages that we paid, by the message's id.
+    pub fn get_paid_invoices(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT event_id, invoice FROM paid_invoices")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let invoices = rows.collect::<Result<HashMap<String, String>, rusqlite::Error>>()?;
+        Ok(invoices)
+    }
+
+    /// Remembers the wallet paid `invoice`, sent in the message `event_id`.
+    pub fn record_paid_invoice(&self, event_id: &str, invoice: &str, preimage: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO paid_invoices (event_id, invoice, preimage, paid_at)
+             VALUES (?1, ?2, ?3, unixepoch())",
+            (event_id, invoice, preimage),
+        )?;
+        Ok(())
+    }
+
+    /// Puts `label` on the messages of a thread, or takes it off.
+    pub fn set_thread_label(&mut self, event_ids: &[String], label: &str, on: bool) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for event_id in event_ids {
+            if on {
+                tx.execute(
+                    "INSERT OR IGNORE INTO message_labels (event_id, label) VALUES (?1, ?2)",
+                    (event_id, label),
+                )?;
+            } else {
+                tx.execute(
+                    "DELETE FROM message_labels WHERE event_id = ?1 AND label = ?2",
+                    (event_id, label),
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The labels on any of `event_ids`, sorted.
+    pub fn get_labels(&self, event_ids: &[String]) -> Result<Vec<String>> {
+        if event_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; event_ids.len()].join(", ");
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT DISTINCT label FROM message_labels WHERE event_id IN ({}) ORDER BY label",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(event_ids), |row| row.get(0))?;
+        let labels = rows.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(labels)
+    }
+
+    /// The labels on each of `event_ids` that has any, sorted.
+    pub fn get_message_labels(&self, event_ids: &[String]) -> Result<HashMap<String, Vec<String>>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT event_id, label FROM message_labels
+             WHERE event_id IN (SELECT value FROM json_each(?1))
+             ORDER BY label",
+        )?;
+        let rows = stmt.query_map((json!(event_ids).to_string(),), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut labels: HashMap<String, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (event_id, label) = row?;
+            labels.entry(event_id).or_default().push(label);
+        }
+        Ok(labels)
+    }
+
+    /// Every label in use, sorted.
+    pub fn get_all_labels(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT DISTINCT label FROM message_labels ORDER BY label")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let labels = rows.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(labels)
+    }
+
+    /// Remembers that we last wrote to `recipients` as `account`.
+    pub fn remember_sending_account(&mut self, account: &str, recipients: &[String]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for recipient in recipients {
+            tx.execute(
+                "INSERT INTO sending_accounts (recipient_pubkey, account_pubkey) VALUES (?1, ?2)
+                 ON CONFLICT(recipient_pubkey) DO UPDATE SET account_pubkey = excluded.account_pubkey",
+                (recipient, account),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The account we last wrote to each recipient as, keyed by the recipient.
+    pub fn get_sending_accounts(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT recipient_pubkey, account_pubkey FROM sending_accounts")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let accounts = rows.collect::<Result<HashMap<String, String>, rusqlite::Error>>()?;
+        Ok(accounts)
+    }
+
+    /// The translation of a message into `language` we kept, if there is one.
+    pub fn get_translation(&self, event_id: &str, language: &str) -> Result<Option<String>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT text FROM translations WHERE event_id = ?1 AND language = ?2",
+                (event_id, language),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn save_translation(&self, event_id: &str, language: &str, text: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO translations (event_id, language, text) VALUES (?1, ?2, ?3)
+             ON CONFLICT(event_id, language) DO UPDATE SET text = excluded.text",
+            (event_id, language, text),
+        )?;
+        Ok(())
+    }
+
+    /// What the Focused inbox ranking knows about everyone who's a contact, was written to,
+    /// answered us or wrote in our threads, keyed by their pubkey.
+    pub fn get_sender_stats(&self) -> Result<HashMap<String, SenderStats>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT pubkey, MAX(contact), SUM(written_to), SUM(answers), SUM(participation)
+             FROM (
+                 SELECT pubkey, 1 AS contact, 0 AS written_to, 0 AS answers,
+                     0 AS participation
+                 FROM contacts
+                 UNION ALL
+                 SELECT recipient_pubkey, 0, COUNT(DISTINCT event_id), 0, 0
+                 FROM sent_messages
+                 GROUP BY recipient_pubkey
+                 UNION ALL
+                 SELECT e.pubkey, 0, 0, COUNT(DISTINCT e.id), 0
+                 FROM events e, json_each(e.tags) AS etag
+                 WHERE jsonb_extract(etag.value, '$[0]') = 'e'
+                 AND jsonb_extract(etag.value, '$[1]') IN (SELECT event_id FROM sent_messages)
+                 AND e.id NOT IN (SELECT event_id FROM sent_messages)
+                 GROUP BY e.pubkey
+                 UNION ALL
+                 -- Messages sharing an ancestor with one of ours are in a thread we wrote in.
+                 SELECT e.pubkey, 0, 0, 0, COUNT(DISTINCT e.id)
+                 FROM events e, json_each(e.tags) AS etag
+                 WHERE jsonb_extract(etag.value, '$[0]') = 'e'
+                 AND jsonb_extract(etag.value, '$[1]') IN (
+                     SELECT jsonb_extract(stag.value, '$[1]')
+                     FROM sent_messages s
+                     JOIN events se ON se.id = s.event_id, json_each(se.tags) AS stag
+                     WHERE jsonb_extract(stag.value, '$[0]') = 'e'
+                 )
+                 AND e.id NOT IN (SELECT event_id FROM sent_messages)
+                 GROUP BY e.pubkey
+             )
+             GROUP BY pubkey",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let stats = SenderStats {
+                contact: row.get(1)?,
+                written_to: row.get(2)?,
+                answers: row.get(3)?,
+                participation: row.get(4)?,
+            };
+            Ok((row.get(0)?, stats))
+        })?;
+        let stats = rows.collect::<Result<HashMap<String, SenderStats>, rusqlite::Error>>()?;
+        Ok(stats)
+    }
+
+    /// The people we last wrote to or heard from, most recent first. Our own accounts are
+    /// among them if we wrote to ourselves.
+    pub fn get_recent_correspondents(&self, limit: usize) -> Result<Vec<String>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT pubkey FROM (
+                 SELECT s.recipient_pubkey AS pubkey, e.created_at AS created_at
+                 FROM sent_messages s JOIN events e ON e.id = s.event_id
+                 UNION ALL
+                 SELECT pubkey, created_at FROM events
+                 WHERE kind = ?1 AND id NOT IN (SELECT event_id FROM sent_messages)
+             )
+             WHERE pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+             GROUP BY pubkey
+             ORDER BY MAX(created_at) DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map((MAIL_EVENT_KIND, limit as i64), |row| row.get(0))?;
+        let pubkeys = rows.collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        Ok(pubkeys)
+    }
+
+    /// Replaces the senders the ranking put in Focused. Senders moved by hand keep their place.
+    pub fn set_ranked_senders(&mut self, focused: &[String]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM sender_focus WHERE corrected = 0", ())?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT OR IGNORE INTO sender_focus (pubkey, focused) VALUES (?1, 1)")?;
+            for pubkey in focused {
+                stmt.execute((pubkey,))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Moves `pubkey`'s mail to Focused or Other for good, whatever the ranking makes of them.
+    pub fn correct_sender_focus(&self, pubkey: &str, focused: bool) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO sender_focus (pubkey, focused, corrected) VALUES (?1, ?2, 1)
+             ON CONFLICT(pubkey) DO UPDATE SET focused = excluded.focused, corrected = 1",
+            (pubkey, focused),
+        )?;
+        Ok(())
+    }
+
+    /// Everyone whose mail goes to the Focused inbox.
+    pub fn get_focused_senders(&self) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT pubkey FROM sender_focus WHERE focused = 1")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let pubkeys = rows.collect::<Result<HashSet<String>, rusqlite::Error>>()?;
+        Ok(pubkeys)
+    }
+
+    /// The message list columns by name, in order, with whether each is shown. Empty until
+    /// they're first changed.
+    pub fn get_inbox_columns(&self) -> Result<Vec<(String, bool)>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT name, visible FROM inbox_columns ORDER BY position")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let columns = rows.collect::<Result<Vec<(String, bool)>, rusqlite::Error>>()?;
+        Ok(columns)
+    }
+
+    pub fn save_inbox_columns(&mut self, columns: &[(String, bool)]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM inbox_columns", [])?;
+        for (position, (name, visible)) in columns.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO inbox_columns (name, position, visible) VALUES (?1, ?2, ?3)",
+                (name, position as i64, visible),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The flags of `event_ids`, to save to relays as one thread.
+    pub fn get_thread_flags(&self, event_ids: &[String]) -> Result<ThreadFlags> {
+        let mut flags = ThreadFlags {
+            messages: event_ids.to_vec(),
+            ..Default::default()
+        };
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT read_at IS NOT NULL, starred, archived FROM message_state WHERE event_id = ?1",
+        )?;
+        for event_id in event_ids {
+            let state = stmt
+                .query_row([event_id], |row| {
+                    Ok((row.get::<_, bool>(0)?, row.get(1)?, row.get(2)?))
+                })
+                .optional()?;
+            let Some((read, starred, archived)) = state else {
+                continue;
+            };
+            for (set, list) in [
+                (read, &mut flags.read),
+                (starred, &mut flags.starred),
+                (archived, &mut flags.archived),
+            ] {
+                if set {
+                    list.push(event_id.clone());
+                }
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Applies flags saved to relays at `created_at` under the `d` tag `key`, unless newer ones
+    /// for the thread were seen already. Returns whether anything changed.
+    pub fn apply_thread_flags(
+        &mut self,
+        key: &str,
+        created_at: i64,
+        flags: &ThreadFlags,
+    ) -> Result<bool> {
+        let tx = self.connection.transaction()?;
+        let seen: Option<i64> = tx
+            .query_row(
+                "SELECT created_at FROM flag_sync WHERE d_tag = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if seen.is_some_and(|seen| seen >= created_at) {
+            return Ok(false);
+        }
+        for event_id in &flags.messages {
+            tx.execute(
+                "INSERT INTO message_state (event_id, read_at, starred, archived)
+                 VALUES (?1, CASE WHEN ?2 THEN unixepoch() END, ?3, ?4)
+                 ON CONFLICT(event_id) DO UPDATE SET
+                     read_at = CASE WHEN ?2 THEN COALESCE(read_at, unixepoch()) END,
+                     starred = excluded.starred,
+                     archived = excluded.archived",
+                (
+                    event_id,
+                    flags.read.contains(event_id),
+                    flags.starred.contains(event_id),
+                    flags.archived.contains(event_id),
+                ),
+            )?;
+        }
+        tx.execute(
+            "INSERT INTO flag_sync (d_tag, created_at) VALUES (?1, ?2)
+             ON CONFLICT(d_tag) DO UPDATE SET created_at = excluded.created_at",
+            (key, created_at),
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// When the newest thread flags we saved or took from relays were made, if any.
+    pub fn newest_flag_sync(&self) -> Result<Option<i64>> {
+        let newest =
+            self.connection
+                .query_row("SELECT MAX(created_at) FROM flag_sync", [], |row| {
+                    row.get(0)
+                })?;
+        Ok(newest)
+    }
+
+    /// Notes that a thread's flags went out to relays at `created_at`, so older copies of
+    /// them are ignored from now on.
+    pub fn record_flag_sync(&self, key: &str, created_at: i64) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO flag_sync (d_tag, created_at) VALUES (?1, ?2)
+             ON CONFLICT(d_tag) DO UPDATE SET created_at = MAX(created_at, excluded.created_at)",
+            (key, created_at),
+        )?;
+        Ok(())
+    }
+
+    /// Number of unread mail messages addressed to any of `recipients`, not counting the
+    /// ones they wrote themselves.
+    pub fn count_unread(&self, recipients: &[String]) -> Result<i64> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let recipients = serde_json::to_string(recipients)?;
+        let count = self.connection.query_row(
+            "SELECT COUNT(*)
+             FROM events e
+             WHERE e.kind = ?1
+             AND e.pubkey NOT IN (SELECT value FROM json_each(?2))
+             AND e.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+             AND EXISTS (
+                 SELECT 1 FROM json_each(e.tags) AS ptag
+                 WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+                 AND jsonb_extract(ptag.value, '$[1]') IN (SELECT value FROM json_each(?2))
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM message_state m
+                 WHERE m.event_id = e.id AND m.read_at IS NOT NULL
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = e.id
+                 AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM trash_events t
+                 WHERE t.event_id = e.id
+             )",
+            (MAIL_EVENT_KIND, recipients),
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Notes to self: mail messages written by `pubkey` and addressed only to `pubkey`,
+    /// newest first.
+    pub fn get_notes_to_self(&self, pubkey: &str) -> Result<Vec<TableEntry>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT
+                 e.id,
+                 COALESCE((SELECT snippet FROM message_snippets WHERE event_id = e.id), ''),
+                 e.created_at,
+                 e.pubkey,
+                 COALESCE((SELECT jsonb_extract(stag.value, '$[1]')
+                  FROM json_each(e.tags) AS stag
+                  WHERE jsonb_extract(stag.value, '$[0]') = 'subject'
+                  LIMIT 1), '') as subject,
+                 1 as thread_count
+             FROM events e
+             WHERE e.kind = ?1
+             AND e.pubkey = ?2
+             AND EXISTS (
+                 SELECT 1 FROM json_each(e.tags) AS ptag
+                 WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+                 AND jsonb_extract(ptag.value, '$[1]') = ?2
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM json_each(e.tags) AS ptag
+                 WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+                 AND jsonb_extract(ptag.value, '$[1]') != ?2
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = e.id
+                 AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM trash_events t
+                 WHERE t.event_id = e.id
+             )
+             ORDER BY e.created_at DESC",
+        )?;
+
+        let msgs_iter = stmt.query_map((MAIL_EVENT_KIND, pubkey), |row| {
+            Ok(TableEntry {
+                id: row.get(0)?,
+                snippet: row.get(1)?,
+                created_at: row.get(2)?,
+                pubkey: row.get(3)?,
+                subject: row.get(4)?,
+                thread_count: row.get(5)?,
+                labels: Vec::new(),
+            })
+        })?;
+
+        let messages = msgs_iter.collect::<Result<Vec<TableEntry>, rusqlite::Error>>()?;
+        Ok(messages)
+    }
+
+    /// Keeps a message we sent, with the gift wrap each recipient got as (wrap id, recipient).
+    /// Our own copy is already read.
+    pub fn record_sent(&mut self, rumor: &UnsignedEvent, wraps: &[(String, String)]) -> Result<()> {
+        let mut rumor = rumor.clone();
+        rumor.ensure_id();
+        let id = rumor.id.expect("ensure_id always sets an id").to_hex();
+
+        let tx = self.connection.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO events (id, raw) VALUES (?1, ?2)",
+            (&id, json!(rumor).to_string()),
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO message_snippets (event_id, snippet) VALUES (?1, ?2)",
+            (&id, mail_event::snippet(&rumor.content)),
+        )?;
+        tx.execute(
+            "INSERT INTO message_state (event_id, read_at) VALUES (?1, unixepoch())
+             ON CONFLICT(event_id) DO UPDATE SET read_at = excluded.read_at",
+            (&id,),
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO sent_messages (wrap_id, event_id, recipient_pubkey, copy)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (wrap_id, recipient) in wraps {
+                stmt.execute((wrap_id, &id, recipient, is_copy_to_self(&rumor, recipient)))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records how many relays took and refused a sent gift wrap once the send has settled.
+    pub fn record_sent_delivery(
+        &self,
+        wrap_id: &str,
+        accepted: usize,
+        rejected: usize,
+    ) -> Result<()> {
+        self.connection.execute(
+            "UPDATE sent_messages SET accepted_by = ?2, rejected_by = ?3 WHERE wrap_id = ?1",
+            (wrap_id, accepted, rejected),
+        )?;
+        Ok(())
+    }
+
+    /// Records how many of the relays that took a wrap gave it back when asked for it.
+    pub fn record_sent_confirmation(&self, wrap_id: &str, confirmed: usize) -> Result<()> {
+        self.connection.execute(
+            "UPDATE sent_messages SET confirmed_by = ?2 WHERE wrap_id = ?1",
+            (wrap_id, confirmed),
+        )?;
+        Ok(())
+    }
+
+    /// Messages we sent, newest first, one entry however many recipients they went to.
+    /// With an `account`, only that account's messages are returned.
+    pub fn get_sent_messages(&self, account: Option<&str>) -> Result<Vec<SentMessage>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT
+                 e.id,
+                 COALESCE((SELECT snippet FROM message_snippets WHERE event_id = e.id), ''),
+                 e.created_at,
+                 e.pubkey,
+                 COALESCE((SELECT jsonb_extract(stag.value, '$[1]')
+                  FROM json_each(e.tags) AS stag
+                  WHERE jsonb_extract(stag.value, '$[0]') = 'subject'
+                  LIMIT 1), '') as subject,
+                 COALESCE(
+                     group_concat(CASE WHEN NOT s.copy THEN s.recipient_pubkey END),
+                     (SELECT group_concat(jsonb_extract(ptag.value, '$[1]'))
+                      FROM json_each(e.tags) AS ptag
+                      WHERE jsonb_extract(ptag.value, '$[0]') = 'p'),
+                     ''
+                 ) as recipients,
+                 SUM(NOT s.copy AND COALESCE(s.accepted_by, 0) > 0) as delivered,
+                 SUM(NOT s.copy AND s.accepted_by IS NULL) as pending,
+                 SUM(NOT s.copy AND COALESCE(s.confirmed_by, 0) > 0) as confirmed,
+                 SUM(NOT s.copy AND COALESCE(s.accepted_by > 0 AND s.confirmed_by = 0, 0))
+                     as dropped,
+                 MIN(s.copy) as elsewhere
+             FROM sent_messages s
+             JOIN events e ON e.id = s.event_id
+             WHERE (?1 IS NULL OR e.pubkey = ?1)
+             AND NOT EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = e.id
+                 AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM trash_events t
+                 WHERE t.event_id = e.id
+             )
+             GROUP BY e.id
+             ORDER BY e.created_at DESC",
+        )?;
+
+        let msgs_iter = stmt.query_map((account,), |row| {
+            let recipients: String = row.get(5)?;
+            Ok(SentMessage {
+                entry: TableEntry {
+                    id: row.get(0)?,
+                    snippet: row.get(1)?,
+                    created_at: row.get(2)?,
+                    pubkey: row.get(3)?,
+                    subject: row.get(4)?,
+                    thread_count: 1,
+                    labels: Vec::new(),
+                },
+                recipients: recipients.split(',').map(str::to_string).collect(),
+                delivered: row.get(6)?,
+                pending: row.get(7)?,
+                confirmed: row.get(8)?,
+                dropped: row.get(9)?,
+                elsewhere: row.get(10)?,
+            })
+        })?;
+
+        let messages = msgs_iter.collect::<Result<Vec<SentMessage>, rusqlite::Error>>()?;
+        Ok(messages)
+    }
+
+    /// Mail messages sent by or addressed to `pubkey`, newest first, each with the number of
+    /// messages in its thread. Returns at most `limit` messages, skipping the first `offset`.
+    pub fn get_messages_with(
+        &self,
+        pubkey: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TableEntry>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let sql = format!(
+            "{}
+             SELECT
+                 e.id,
+                 COALESCE((SELECT snippet FROM message_snippets WHERE event_id = e.id), ''),
+                 e.created_at,
+                 e.pubkey,
+                 COALESCE((SELECT jsonb_extract(stag.value, '$[1]')
+                  FROM json_each(e.tags) AS stag
+                  WHERE jsonb_extract(stag.value, '$[0]') = 'subject'
+                  LIMIT 1), '') as subject,
+                 MAX(1, (SELECT COUNT(*) FROM thread t WHERE t.root_id = (
+                     SELECT t2.root_id FROM thread t2 WHERE t2.msg_id = e.id LIMIT 1
+                 ))) as thread_count
+             FROM events e
+             WHERE e.kind = ?1
+             AND e.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+             AND (
+                 e.pubkey = ?2
+                 OR EXISTS (
+                     SELECT 1 FROM json_each(e.tags) AS ptag
+                     WHERE jsonb_extract(ptag.value, '$[0]') = 'p'
+                     AND jsonb_extract(ptag.value, '$[1]') = ?2
+                 )
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM deleted_events d
+                 WHERE d.event_id = e.id
+                 AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+             )
+             AND NOT EXISTS (
+                 SELECT 1 FROM trash_events t
+                 WHERE t.event_id = e.id
+             )
+             ORDER BY e.created_at DESC
+             LIMIT ?3 OFFSET ?4",
+            TOP_LEVEL_THREADS
+        );
+        let mut stmt = self.connection.prepare_cached(&sql)?;
+
+        let msgs_iter = stmt.query_map(
+            (MAIL_EVENT_KIND, pubkey, limit as i64, offset as i64),
+            |row| {
+                Ok(TableEntry {
+                    id: row.get(0)?,
+                    snippet: row.get(1)?,
+                    created_at: row.get(2)?,
+                    pubkey: row.get(3)?,
+                    subject: row.get(4)?,
+                    thread_count: row.get(5)?,
+                    labels: Vec::new(),
+                })
+            },
+        )?;
+
+        let messages = msgs_iter.collect::<Result<Vec<TableEntry>, rusqlite::Error>>()?;
+        Ok(messages)
+    }
+
+    /// Get all event IDs for mail events
+    pub fn get_mail_event_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT id FROM events
+             WHERE kind = ?
+               AND NOT EXISTS (
+                   SELECT 1 FROM deleted_events d
+                   WHERE d.event_id = events.id
+                     AND (d.author_pubkey IS NULL OR d.author_pubkey = events.pubkey)
+               )
+               AND NOT EXISTS (
+                   SELECT 1 FROM trash_events t
+                   WHERE t.event_id = events.id
+               )",
+        )?;
+
+        let mail_kind = u32::from(MAIL_EVENT_KIND as u16);
+
+        let id_iter = stmt.query_map([mail_kind], |row| {
+            let id: String = row.get(0)?;
+            Ok(id)
+        })?;
+
+        let mut ids = Vec::new();
+        for id_result in id_iter {
+            match id_result {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    tracing::error!("Error loading mail event ID: {}", e);
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Fetches an entire email thread starting from a given event ID.
+    /// It traverses up to the root and down to the latest reply.
+    pub fn get_email_thread(&self, event_id: &str) -> Result<Vec<MailMessage>> {
+        self.get_email_thread_inner(event_id, true)
+    }
+
+    pub fn get_email_thread_including_trash(&self, event_id: &str) -> Result<Vec<MailMessage>> {
+        self.get_email_thread_inner(event_id, false)
+    }
+
+    fn get_email_thread_inner(
+        &self,
+        event_id: &str,
+        exclude_trash: bool,
+    ) -> Result<Vec<MailMessage>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let trash_filter = if exclude_trash {
+            "AND NOT EXISTS (
+                SELECT 1 FROM trash_events t
+                WHERE t.event_id = {alias}.id
+            )"
+        } else {
+            ""
+        };
+
+        let query = format!(
+            r#"
+        WITH RECURSIVE thread AS (
+            -- 1. Start with the initial event
+            SELECT id, raw FROM events WHERE id = ?1
+            AND NOT EXISTS (
+                SELECT 1 FROM deleted_events d
+                WHERE d.event_id = events.id
+                AND (d.author_pubkey IS NULL OR d.author_pubkey = events.pubkey)
+            )
+            {trash_seed}
+            UNION
+            -- 2. Recursively find all replies to the events in the thread
+            SELECT e.id, e.raw
+            FROM events e, json_each(e.tags) AS t, thread
+            WHERE json_extract(t.value, '$[0]') = 'e' AND json_extract(t.value, '$[1]') = thread.id
+            AND NOT EXISTS (
+                SELECT 1 FROM deleted_events d
+                WHERE d.event_id = e.id
+                AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+            )
+            {trash_replies}
+            UNION
+            -- 3. Recursively find the parent of the events in the thread
+            SELECT e.id, e.raw
+            FROM events e, thread
+            JOIN json_each(thread.raw, '$.tags') as t
+            WHERE json_extract(t.value, '$[0]') = 'e' AND e.id = json_extract(t.value, '$[1]')
+            AND NOT EXISTS (
+                SELECT 1 FROM deleted_events d
+                WHERE d.event_id = e.id
+                AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+            )
+            {trash_parents}
+        )
+        SELECT DISTINCT raw FROM thread
+        ORDER BY json_extract(raw, '$.created_at') ASC;
+    "#,
+            trash_seed = trash_filter.replace("{alias}", "events"),
+            trash_replies = trash_filter.replace("{alias}", "e"),
+            trash_parents = trash_filter.replace("{alias}", "e"),
+        );
+
+        let mut stmt = self.connection.prepare(&query)?;
+        let event_iter = stmt.query_map([event_id], |row| {
+            let raw_json: String = row.get(0)?;
+            Self::parse_mail_message(&raw_json)
+        })?;
+
+        let thread = event_iter.collect::<Result<Vec<MailMessage>, rusqlite::Error>>()?;
+        Ok(thread)
+    }
+
+    /// The threads under each of `root_ids` in one query, oldest message first. Lets a page of
+    /// inbox entries load all of their threads at once instead of one query per entry.
+    pub fn get_email_threads(
+        &self,
+        root_ids: &[String],
+    ) -> Result<HashMap<String, Vec<MailMessage>>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "WITH RECURSIVE thread AS (
+                 SELECT value AS root_id, value AS msg_id FROM json_each(?1)
+                 UNION
+                 SELECT t.root_id, e.id
+                 FROM thread t, events e, json_each(e.tags) AS etag
+                 WHERE jsonb_extract(etag.value, '$[0]') = 'e'
+                 AND jsonb_extract(etag.value, '$[1]') = t.msg_id
+                 AND e.pubkey NOT IN (SELECT pubkey FROM blocked_pubkeys)
+                 AND NOT EXISTS (
+                     SELECT 1 FROM deleted_events d
+                     WHERE d.event_id = e.id
+                     AND (d.author_pubkey IS NULL OR d.author_pubkey = e.pubkey)
+                 )
+                 AND NOT EXISTS (
+                     SELECT 1 FROM trash_events tr
+                     WHERE tr.event_id = e.id
+                 )
+             )
+             SELECT t.root_id, e.raw
+             FROM thread t
+             JOIN events e ON e.id = t.msg_id
+             ORDER BY e.created_at ASC",
+        )?;
+
+        let rows = stmt.query_map([serde_json::to_string(root_ids)?], |row| {
+            let root_id: String = row.get(0)?;
+            let raw_json: String = row.get(1)?;
+            Ok((root_id, Self::parse_mail_message(&raw_json)?))
+        })?;
+
+        let mut threads: HashMap<String, Vec<MailMessage>> = HashMap::new();
+        for row in rows {
+            let (root_id, message) = row?;
+            threads.entry(root_id).or_default().push(message);
+        }
+        Ok(threads)
+    }
+
+    fn parse_mail_message(raw_json: &str) -> Result<MailMessage, rusqlite::Error> {
+        let parsed_event: RawEventData = serde_json::from_str(raw_json)
+            .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?;
+
+        let mut to = Vec::new();
+        let mut parent_events = Vec::new();
+        let mut subject = String::new();
+        let mut attachments = Vec::new();
+        let mut articles = Vec::new();
+        let mut quote = None;
+        let mut payload = None;
+        let mut email_to = Vec::new();
+        let mut email_from = None;
+
+        for tag in parsed_event.tags {
+            if tag.len() >= 2 {
+                match tag[0].as_str() {
+                    "p" => {
+                        if let Ok(pubkey) = PublicKey::parse(&tag[1]) {
+                            to.push(pubkey);
+                        }
+                    }
+                    "e" => {
+                        if let Ok(event_id) = EventId::parse(&tag[1]) {
+                            parent_events.push(event_id);
+                        }
+                    }
+                    "subject" => {
+                        subject = tag[1].clone();
+                    }
+                    "imeta" => {
+                        if let Some(attachment) = Attachment::from_imeta(&tag[1..]) {
+                            attachments.push(attachment);
+                        }
+                    }
+                    crate::article::QUOTE_TAG => {
+                        if let Ok(event_id) = EventId::parse(&tag[1]) {
+                            articles.push(event_id);
+                        }
+                    }
+                    mail_event::FRAGMENT_TAG => {
+                        quote = Fragment::from_tag(&tag[1..]);
+                    }
+                    crate::payload::PAYLOAD_TAG => {
+                        payload = Payload::from_tag(&tag[1..]);
+                    }
+                    crate::bridge::EMAIL_TO_TAG => {
+                        email_to.push(tag[1].clone());
+                    }
+                    crate::bridge::EMAIL_FROM_TAG => {
+                        email_from = Some(tag[1].clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(MailMessage {
+            id: EventId::parse(&parsed_event.id).ok(),
+            created_at: Some(parsed_event.created_at),
+            content: parsed_event.content,
+            author: Some(parsed_event.pubkey),
+            subject,
+            to,
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: if parent_events.is_empty() {
+                None
+            } else {
+                Some(parent_events)
+            },
+            attachments,
+            articles,
+            quote,
+            payload,
+            email_to,
+            email_from,
+            tag_relays: Default::default(),
+        })
+    }
+
+    // --- Draft methods ---
+
+    pub fn save_draft(
+        &self,
+        subject: &str,
+        to_field: &str,
+        content: &str,
+        parent_events: &[String],
+        selected_account: Option<&str>,
+    ) -> Result<i64> {
+        let parent_events_json = serde_json::to_string(parent_events)?;
+        self.connection.execute(
+            "INSERT INTO drafts (subject, to_field, content, parent_events, selected_account)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                subject,
+                to_field,
+                content,
+                &parent_events_json,
+                selected_account,
+            ),
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn update_draft(
+        &self,
+        id: i64,
+        subject: &str,
+        to_field: &str,
+        content: &str,
+        parent_events: &[String],
+        selected_account: Option<&str>,
+    ) -> Result<()> {
+        let parent_events_json = serde_json::to_string(parent_events)?;
+        self.connection.execute(
+            "UPDATE drafts SET subject = ?1, to_field = ?2, content = ?3,
+             parent_events = ?4, selected_account = ?5, updated_at = unixepoch()
+             WHERE id = ?6",
+            (
+                subject,
+                to_field,
+                content,
+                &parent_events_json,
+                selected_account,
+                id,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_drafts(&self) -> Result<Vec<Draft>> {
+        let _timer = metrics::start_timer(metrics::DB_QUERY);
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT id, subject, to_field, content, parent_events, selected_account, created_at, updated_at
+             FROM drafts ORDER BY updated_at DESC",
+        )?;
+
+        let drafts_iter = stmt.query_map([], |row| {
+            let parent_events_json: String = row.get(4)?;
+            let parent_events: Vec<String> =
+                serde_json::from_str(&parent_events_json).unwrap_or_default();
+
+            Ok(Draft {
+                id: row.get(0)?,
+                subject: row.get(1)?,
+                to_field: row.get(2)?,
+                content: row.get(3)?,
+                parent_events,
+                selected_account: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+            })
+        })?;
+
+        let drafts = drafts_iter.collect::<Result<Vec<Draft>, rusqlite::Error>>()?;
+        Ok(drafts)
+    }
+
+    pub fn delete_draft(&self, id: i64) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM drafts WHERE id = ?1", (id,))?;
+        Ok(())
+    }
+
+    /// Remembers `ids` as the drafts open when the app closed, replacing the last ones.
+    pub fn set_open_drafts(&mut self, ids: &[i64]) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        tx.execute("UPDATE drafts SET reopen = 0 WHERE reopen = 1", [])?;
+        for id in ids {
+            tx.execute("UPDATE drafts SET reopen = 1 WHERE id = ?1", (id,))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The drafts open when the app last closed, forgetting them so they're only reopened once.
+    pub fn take_open_drafts(&mut self) -> Result<Vec<i64>> {
+        let tx = self.connection.transaction()?;
+        let ids = tx
+            .prepare("SELECT id FROM drafts WHERE reopen = 1 ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+        tx.execute("UPDATE drafts SET reopen = 0 WHERE reopen = 1", [])?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Adds a notification about `key`, unless there already is one. Returns whether it was
+    /// added.
+    pub fn add_notification(
+        &self,
+        kind: NotificationKind,
+        key: &str,
+        summary: &str,
+        target: &str,
+        read: bool,
+    ) -> Result<bool> {
+        let inserted = self.connection.execute(
+            "INSERT OR IGNORE INTO notifications (kind, key, summary, target, created_at, read)
+             VALUES (?1, ?2, ?3, ?4, unixepoch(), ?5)",
+            (kind.as_str(), key, summary, target, read),
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Every notification rule, see [`crate::notification_rules`].
+    pub fn get_notification_rules(&self) -> Result<Vec<(RuleScope, NotificationRule)>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT scope, target, mode, quiet_start, quiet_end FROM notification_rules",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<u8>>(3)?,
+                row.get::<_, Option<u8>>(4)?,
+            ))
+        })?;
+        let mut rules = Vec::new();
+        for row in rows {
+            let (scope, target, mode, start, end) = row?;
+            let scope = match scope.as_str() {
+                "account" => RuleScope::Account(target),
+                "label" => RuleScope::Label(target),
+                _ => continue,
+            };
+            let Some(mode) = NotifyMode::parse(&mode) else {
+                continue;
+            };
+            let quiet = start.zip(end).map(|(start, end)| QuietHours { start, end });
+            rules.push((scope, NotificationRule { mode, quiet }));
+        }
+        Ok(rules)
+    }
+
+    /// Saves the rule for `scope`, or deletes it with `None`.
+    pub fn set_notification_rule(
+        &self,
+        scope: &RuleScope,
+        rule: Option<&NotificationRule>,
+    ) -> Result<()> {
+        let (scope, target) = match scope {
+            RuleScope::Account(pubkey) => ("account", pubkey),
+            RuleScope::Label(label) => ("label", label),
+        };
+        match rule {
+            Some(rule) => {
+                self.connection.execute(
+                    "INSERT INTO notification_rules (scope, target, mode, quiet_start, quiet_end)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(scope, target) DO UPDATE SET mode = excluded.mode,
+                         quiet_start = excluded.quiet_start, quiet_end = excluded.quiet_end",
+                    (
+                        scope,
+                        target,
+                        rule.mode.as_str(),
+                        rule.quiet.map(|quiet| quiet.start),
+                        rule.quiet.map(|quiet| quiet.end),
+                    ),
+                )?;
+            }
+            None => {
+                self.connection.execute(
+                    "DELETE FROM notification_rules WHERE scope = ?1 AND target = ?2",
+                    (scope, target),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The latest `limit` notifications, newest first.
+    pub fn get_notifications(&self, limit: usize) -> Result<Vec<Notification>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT id, kind, summary, target, created_at, read FROM notifications n
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM events e
+                 JOIN blocked_pubkeys b ON b.pubkey = e.pubkey
+                 WHERE e.id = n.target
+             )
+             ORDER BY created_at DESC, id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, bool>(5)?,
+            ))
+        })?;
+        let mut notifications = Vec::new();
+        for row in rows {
+            let (id, kind, summary, target, created_at, read) = row?;
+            // Kinds from a newer version are left for it.
+            let Some(kind) = NotificationKind::parse(&kind) else {
+                continue;
+            };
+            notifications.push(Notification {
+                id,
+                kind,
+                summary,
+                target,
+                created_at,
+                read,
+            });
+        }
+        Ok(notifications)
+    }
+
+    pub fn get_unread_notification_count(&self) -> Result<usize> {
+        let count: i64 = self.connection.query_row(
+            "SELECT COUNT(*) FROM notifications n
+             WHERE read = 0
+             AND NOT EXISTS (
+                 SELECT 1 FROM events e
+                 JOIN blocked_pubkeys b ON b.pubkey = e.pubkey
+                 WHERE e.id = n.target
+             )",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    pub fn mark_notification_read(&self, id: i64) -> Result<()> {
+        self.connection
+            .execute("UPDATE notifications SET read = 1 WHERE id = ?1", (id,))?;
+        Ok(())
+    }
+
+    pub fn mark_all_notifications_read(&self) -> Result<()> {
+        self.connection
+            .execute("UPDATE notifications SET read = 1 WHERE read = 0", [])?;
+        Ok(())
+    }
+
+    /// Deletes the notifications already read.
+    pub fn clear_read_notifications(&self) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM notifications WHERE read = 1", [])?;
+        Ok(())
+    }
+
+    /// Stores a zap on one of our messages. Returns false if its receipt was already stored.
+    pub fn save_zap(&self, zap: &crate::zap::Zap) -> Result<bool> {
+        let inserted = self.connection.execute(
+            "INSERT OR IGNORE INTO zaps
+             (receipt_id, event_id, recipient, sender, amount_msats, comment, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                &zap.receipt_id,
+                &zap.event_id,
+                &zap.recipient,
+                &zap.sender,
+                zap.msats as i64,
+                &zap.comment,
+                zap.created_at,
+            ),
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// The zaps on the message `event_id`, oldest first.
+    pub fn get_zaps(&self, event_id: &str) -> Result<Vec<crate::zap::Zap>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT receipt_id, event_id, recipient, sender, amount_msats, comment, created_at
+             FROM zaps WHERE event_id = ?1 ORDER BY created_at",
+        )?;
+        let zaps = stmt
+            .query_map([event_id], |row| {
+                Ok(crate::zap::Zap {
+                    receipt_id: row.get(0)?,
+                    event_id: row.get(1)?,
+                    recipient: row.get(2)?,
+                    sender: row.get(3)?,
+                    msats: row.get::<_, i64>(4)? as u64,
+                    comment: row.get(5)?,
+                    created_at: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(zaps)
+    }
+
+    /// The connection string of the connected wallet, if there is one.
+    pub fn get_wallet_connection(&self) -> Result<Option<String>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT uri FROM wallet_connection WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_wallet_connection(&self, uri: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO wallet_connection (id, uri) VALUES (1, ?1)
+             ON CONFLICT(id) DO UPDATE SET uri = excluded.uri",
+            (uri,),
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_wallet_connection(&self) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM wallet_connection", [])?;
+        Ok(())
+    }
+
+    pub fn get_draft_count(&self) -> Result<i64> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM drafts", [], |row| row.get(0))?;
+        Ok(count)
+    }
+}
+
+/// Where a thread sits in a listing: the time of its latest message, then its id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn of(entry: &TableEntry) -> Self {
+        Self {
+            created_at: entry.created_at,
+            id: entry.id.clone(),
+        }
+    }
+}
+
+/// Which threads of a listing to load, for listings too long to load at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Page {
+    All,
+    /// The newest ones.
+    First(usize),
+    /// The one at the cursor and those older than it.
+    From(Cursor, usize),
+    /// Those older than the cursor.
+    Before(Cursor, usize),
+    /// The ones just newer than the cursor.
+    After(Cursor, usize),
+}
+
+impl Page {
+    /// How many threads the page holds at most, `None` for all of them.
+    pub fn limit(&self) -> Option<usize> {
+        match self {
+            Page::All => None,
+            Page::First(limit)
+            | Page::From(_, limit)
+            | Page::Before(_, limit)
+            | Page::After(_, limit) => Some(*limit),
+        }
+    }
+}
+
+/// Narrows down the inbox. Everything set has to match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageFilter {
+    /// Words to look for in subjects and bodies.
+    pub query: String,
+    /// Words to look for in subjects only.
+    pub subject_terms: Vec<String>,
+    /// Words to look for in the user's notes on threads.
+    pub note_terms: Vec<String>,
+    pub unread: bool,
+    pub starred: bool,
+    pub has_attachment: bool,
+    pub from_contacts: bool,
+    /// Show archived threads instead of the ones in the inbox.
+    pub archived: bool,
+    /// Only threads in the Focused inbox, or only the Other ones. A thread is Focused when
+    /// someone other than us in it is, see [`crate::focus`].
+    pub focused: Option<bool>,
+}
+
+impl MessageFilter {
+    /// The search query as an FTS5 expression, matching messages that contain every word
+    /// or a word starting with it. `None` for an empty query.
+    fn fts_query(&self) -> Option<String> {
+        let phrase = |word: &str| format!("\"{}\"*", word.replace('"', "\"\""));
+        let terms: Vec<String> = self
+            .query
+            .split_whitespace()
+            .map(phrase)
+            .chain(
+                self.subject_terms
+                    .iter()
+                    .map(|word| format!("subject : {}", phrase(word))),
+            )
+            .collect();
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+}
+
+/// An event for `Db::store_events`, with what `Db::store_event` takes alongside it.
+#[derive(Clone, Debug)]
+pub struct EventToStore {
+    pub event: Event,
+    /// For a gift wrap, what was inside.
+    pub unwrapped: Option<UnwrappedGift>,
+    /// For a gift wrap, the pubkey it was addressed to.
+    pub recipient: Option<String>,
+}
+
+impl From<Event> for EventToStore {
+    fn from(event: Event) -> Self {
+        Self {
+            event,
+            unwrapped: None,
+            recipient: None,
+        }
+    }
+}
+
+/// A search kept as a folder in the sidebar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    /// In the search box language, see `search_query`.
+    pub query: String,
+}
+
+/// Whether `recipient` gets `rumor` as a copy of mail it wrote to others, rather than as
+/// someone it's addressed to, like with a note to self.
+fn is_copy_to_self(rumor: &UnsignedEvent, recipient: &str) -> bool {
+    rumor.pubkey.to_hex() == recipient
+        && !rumor
+            .tags
+            .filter(TagKind::p())
+            .any(|tag| tag.content() == Some(recipient))
+}
+
+/// A message we sent, with the copies gift wrapped for each recipient folded together.
+#[derive(Clone, Debug)]
+pub struct SentMessage {
+    pub entry: TableEntry,
+    pub recipients: Vec<String>,
+    /// Recipients whose copy at least one relay took.
+    pub delivered: usize,
+    /// Recipients whose copy is still waiting on the relays.
+    pub pending: usize,
+    /// Recipients whose copy a relay gave back when asked for it after the send.
+    pub confirmed: usize,
+    /// Recipients whose copy relays took but no longer had when asked for it.
+    pub dropped: usize,
+    /// Sent from another device, so all that's here is the copy it sent us and there's no
+    /// delivery to show.
+    pub elsewhere: bool,
+}
+
+/// A gift wrap carrying a copy of a message to one recipient.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WrapCopy {
+    pub wrap_id: String,
+    /// Who the copy is for, if we know.
+    pub recipient: Option<String>,
+    /// Whether we sent it, rather than received it.
+    pub sent: bool,
+    /// How it was encrypted, recorded for wraps we received.
+    pub encryption: Option<Encryption>,
+    /// The date on the wrap, recorded for wraps we received. Senders backdate it at random
+    /// (NIP-59), so it says little about when the message was sent.
+    pub created_at: Option<i64>,
+}
+
+/// What a notification is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// A message no relay took.
+    DeliveryFailed,
+    /// A message from someone who isn't a contact yet.
+    ContactRequest,
+    /// A relay asked us to authenticate (NIP-42).
+    RelayAuth,
+    /// A message with one of our npubs in its subject.
+    Mention,
+    /// A message in a thread with a label the user wants to hear about.
+    Labeled,
+    /// Any other message a notification rule asked to hear about.
+    Mail,
+}
+
+impl NotificationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::DeliveryFailed => "delivery_failed",
+            NotificationKind::ContactRequest => "contact_request",
+            NotificationKind::RelayAuth => "relay_auth",
+            NotificationKind::Mention => "mention",
+            NotificationKind::Labeled => "labeled",
+            NotificationKind::Mail => "mail",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "delivery_failed" => Some(NotificationKind::DeliveryFailed),
+            "contact_request" => Some(NotificationKind::ContactRequest),
+            "relay_auth" => Some(NotificationKind::RelayAuth),
+            "mention" => Some(NotificationKind::Mention),
+            "labeled" => Some(NotificationKind::Labeled),
+            "mail" => Some(NotificationKind::Mail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub id: i64,
+    pub kind: NotificationKind,
+    pub summary: String,
+    /// A message id, or a relay url for [`NotificationKind::RelayAuth`].
+    pub target: String,
+    pub created_at: i64,
+    pub read: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Draft {
+    pub id: i64,
+    pub subject: String,
+    pub to_field: String,
+    pub content: String,
+    pub parent_events: Vec<String>,
+    pub selected_account: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A set of contacts found by `Db::find_duplicate_contacts`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateContacts {
+    pub reason: DuplicateReason,
+    /// The pubkeys as stored in the contacts table.
+    pub pubkeys: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DuplicateReason {
+    /// One key saved more than once, with its hex form.
+    SamePubkey(String),
+    /// Different keys claiming the same NIP-05 address.
+    SameNip05(String),
+}
+
+/// What `Db::integrity_check` found wrong, or `Db::repair_integrity` fixed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Read, star or archive marks left behind by messages that were deleted, or that don't
+    /// mark anything.
+    pub orphaned_states: usize,
+    /// Mail whose JSON can't be read as a message, so its thread can't be opened.
+    pub broken_events: Vec<String>,
+    /// Mail missing from the search index.
+    pub unindexed: usize,
+    /// Search index entries for mail that's gone.
+    pub stale_index: usize,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+use serde::Deserialize;
+/// A temporary struct to deserialize the raw JSON event from the database.
+/// This makes parsing safe and reliable.
+#[derive(Deserialize)]
+struct RawEventData {
+    id: String,
+    content: String,
+    created_at: i64,
+    tags: Vec<Vec<String>>,
+    pubkey: PublicKey,
+}
+
+/// Format a database unlock error into a user-friendly message.
+/// Detects the "wrong password" case from SQLCipher's NotADatabase error code.
+pub fn format_unlock_error(e: &anyhow::Error) -> String {
+    let sqlite_error = match e.downcast_ref::<rusqlite_migration::Error>() {
+        Some(rusqlite_migration::Error::RusqliteError { err, .. }) => Some(err),
+        _ => e.downcast_ref::<rusqlite::Error>(),
+    };
+    match sqlite_error.and_then(|err| err.sqlite_error_code()) {
+        Some(rusqlite::ErrorCode::NotADatabase) => "Wrong password".to_string(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+            "The database is in use by another copy of Hoot. Close it and try again.".to_string()
+        }
+        _ => format!("Database error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    /// Stores a mail event as if it had been unwrapped, without a signature.
+    fn insert_mail(
+        db: &Db,
+        id: &str,
+        pubkey: &str,
+        created_at: u64,
+        tags: serde_json::Value,
+    ) -> Result<()> {
+        insert_mail_with_content(db, id, pubkey, created_at, tags, "")
+    }
+
+    fn insert_mail_with_content(
+        db: &Db,
+        id: &str,
+        pubkey: &str,
+        created_at: u64,
+        tags: serde_json::Value,
+        content: &str,
+    ) -> Result<()> {
+        let raw = json!({
+            "id": id,
+            "pubkey": pubkey,
+            "created_at": created_at,
+            "kind": MAIL_EVENT_KIND,
+            "tags": tags,
+            "content": content,
+            "sig": "",
+        });
+        db.connection.execute(
+            "INSERT INTO events (id, raw) VALUES (?1, ?2)",
+            (id, raw.to_string()),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_pubkey() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let pk = Keys::generate().public_key();
+        db.add_pubkey(pk.to_hex())?;
+        let saved_list = db.get_pubkeys()?;
+        assert!(saved_list.first().is_some());
+        assert_eq!(saved_list.first().unwrap(), &pk.to_hex());
+
+        Ok(())
+    }
+
+    /// Ingestion mustn't stall behind UI reads: in WAL mode a reader in the middle of a
+    /// query doesn't hold up commits, which would otherwise wait out the busy timeout.
+    #[test]
+    fn test_wal_commits_alongside_open_read() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("hoot-wal-test-{}.db", std::process::id()));
+        let mut writer = Db::new(path.clone())?;
+        writer.unlock_with_password("test".to_string())?;
+        let mut reader = Db::new(path.clone())?;
+        reader.unlock_with_password("test".to_string())?;
+
+        let read = reader.connection.transaction()?;
+        let before: i64 =
+            read.query_row("SELECT COUNT(*) FROM message_state", [], |row| row.get(0))?;
+
+        let writes = 200;
+        for i in 0..writes {
+            writer.mark_read(&[format!("{:064x}", i)])?;
+        }
+
+        // The reader keeps its snapshot until it's done, and sees every write after.
+        let during: i64 =
+            read.query_row("SELECT COUNT(*) FROM message_state", [], |row| row.get(0))?;
+        read.commit()?;
+        assert_eq!(before, during);
+        let after: i64 =
+            reader
+                .connection
+                .query_row("SELECT COUNT(*) FROM message_state", [], |row| row.get(0))?;
+        assert_eq!(after, before + writes);
+
+        drop(reader);
+        drop(writer);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_pubkey() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let pk = Keys::generate().public_key();
+        db.add_pubkey(pk.to_hex())?;
+        let saved_list = db.get_pubkeys()?;
+        assert!(saved_list.first().is_some());
+        assert_eq!(saved_list.first().unwrap(), &pk.to_hex());
+
+        db.delete_pubkey(pk.to_hex())?;
+        let saved_list = db.get_pubkeys()?;
+        assert!(saved_list.first().is_none());
+        assert!(saved_list.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_messages_with() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let carol = "c".repeat(64);
+        let messages = [
+            ("1", &alice, 10, json!([["p", bob], ["subject", "hi"]])),
+            (
+                "2",
+                &bob,
+                20,
+                json!([["p", alice], ["subject", "hi"], ["e", "1"]]),
+            ),
+            ("3", &carol, 30, json!([["p", alice], ["subject", "hi"]])),
+        ];
+        for (id, author, created_at, tags) in messages {
+            insert_mail(&db, id, author, created_at, tags)?;
+        }
+
+        let entries: Vec<(String, i64)> = db
+            .get_messages_with(&bob, 10, 0)?
+            .into_iter()
+            .map(|entry| (entry.id, entry.thread_count))
+            .collect();
+        // The reply and the message it answers are one thread of two.
+        assert_eq!(entries, vec![("2".to_string(), 2), ("1".to_string(), 2)]);
+        assert_eq!(db.get_messages_with(&carol, 10, 0)?[0].thread_count, 1);
+
+        let second_page = db.get_messages_with(&bob, 1, 1)?;
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page[0].id, "1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snippets_stored_and_backfilled() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let author = Keys::generate();
+        let pubkey = author.public_key().to_hex();
+        let rumor = nostr::EventBuilder::new(
+            nostr::Kind::Custom(MAIL_EVENT_KIND),
+            "**Lunch** at [noon](https://example.com)?",
+        )
+        .build(author.public_key());
+        db.record_sent(&rumor, &[])?;
+
+        // Stored by a version that didn't keep snippets.
+        insert_mail_with_content(&db, "old", &pubkey, 0, json!([]), "# Hello\n\n_there_")?;
+        db.backfill_snippets()?;
+
+        let snippets: Vec<String> = db
+            .get_messages_with(&pubkey, 10, 0)?
+            .into_iter()
+            .map(|entry| entry.snippet)
+            .collect();
+        assert_eq!(snippets, vec!["Lunch at noon?", "Hello there"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_integrity_check_and_repair() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let pubkey = Keys::generate().public_key().to_hex();
+        for (id, tags) in [
+            ("good", json!([["subject", "Plans"]])),
+            ("unindexed", json!([["subject", "Plans"]])),
+            ("broken", json!([["subject", 5]])),
+        ] {
+            insert_mail_with_content(&db, id, &pubkey, 0, tags, "Lunch?")?;
+        }
+        db.connection.execute(
+            "DELETE FROM message_search WHERE event_id = 'unindexed'",
+            [],
+        )?;
+        db.connection.execute(
+            "INSERT INTO message_search (event_id, subject, content) VALUES ('gone', '', '')",
+            [],
+        )?;
+        db.record_deletion_markers(&["deleted".to_string()], None)?;
+        db.connection.execute_batch(
+            "INSERT INTO message_state (event_id, read_at) VALUES
+                 ('good', 1), ('deleted', 1), ('nothing', NULL), ('not_yet_here', 1);",
+        )?;
+
+        let expected = IntegrityReport {
+            orphaned_states: 2,
+            broken_events: vec!["broken".to_string()],
+            unindexed: 1,
+            stale_index: 1,
+        };
+        assert_eq!(db.integrity_check()?, expected);
+        assert_eq!(db.repair_integrity()?, expected);
+        assert!(db.integrity_check()?.is_clean());
+
+        let states: Vec<String> = db
+            .connection
+            .prepare("SELECT event_id FROM message_state ORDER BY event_id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(states, vec!["good", "not_yet_here"]);
+        let found: String = db.connection.query_row(
+            "SELECT event_id FROM message_search WHERE message_search MATCH 'Plans' \
+             AND event_id = 'unindexed'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(found, "unindexed");
+        Ok(())
+    }
+
+    #[test]
+    fn test_repaired_mail_can_be_received_again() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: vec![bob.public_key()],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: "Plans".to_string(),
+            content: "Lunch?".to_string(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: Default::default(),
+        };
+        let wrap = message
+            .to_events(&alice, Duration::ZERO)
+            .remove(&bob.public_key())
+            .expect("a gift wrap for bob");
+        let unwrapped =
+            crate::account_manager::unwrap_gift_wrap(std::slice::from_ref(&bob), &wrap)?;
+        let recipient = bob.public_key().to_hex();
+        db.store_event(&wrap, Some(&unwrapped), Some(&recipient))?;
+
+        db.connection.execute(
+            "UPDATE events SET raw = json_set(raw, '$.tags', json('[[\"subject\", 5]]'))",
+            [],
+        )?;
+        assert_eq!(db.integrity_check()?.broken_events.len(), 1);
+        db.repair_integrity()?;
+        assert!(!db.gift_wrap_exists(&wrap.id.to_hex())?);
+
+        db.store_event(&wrap, Some(&unwrapped), Some(&recipient))?;
+        assert_eq!(db.get_mail_event_ids()?.len(), 1);
+        assert!(db.integrity_check()?.is_clean());
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_sources() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let sighting = |id: &str, url: &str| (id.to_string(), url.to_string());
+        db.record_event_sources(&[
+            sighting("wrap", "wss://a.example.com"),
+            sighting("wrap", "wss://b.example.com"),
+            sighting("wrap", "wss://a.example.com"),
+            sighting("other", "wss://c.example.com"),
+        ])?;
+
+        let urls: Vec<String> = db
+            .get_event_sources(&["wrap".to_string(), "missing".to_string()])?
+            .into_iter()
+            .map(|(url, _)| url)
+            .collect();
+        assert_eq!(urls, vec!["wss://a.example.com", "wss://b.example.com"]);
+
+        // Deleting the message drops the sources of the wraps it came in.
+        insert_mail(&db, "inner", &"a".repeat(64), 10, json!([]))?;
+        db.save_gift_wrap_map("wrap", "inner", None, 10, None)?;
+        db.connection
+            .execute("DELETE FROM events WHERE id = ?1", ("inner",))?;
+        assert!(db.get_event_sources(&["wrap".to_string()])?.is_empty());
+        assert_eq!(db.get_event_sources(&["other".to_string()])?.len(), 1);
+
+        // Sightings of events we never kept go once they're old enough.
+        db.record_event_sources(&[sighting("kept", "wss://a.example.com")])?;
+        insert_mail(&db, "kept", &"a".repeat(64), 10, json!([]))?;
+        assert_eq!(db.prune_event_sources(0)?, 0);
+        assert_eq!(db.prune_event_sources(i64::MAX)?, 1);
+        assert!(db.get_event_sources(&["other".to_string()])?.is_empty());
+        assert_eq!(db.get_event_sources(&["kept".to_string()])?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_wraps() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let author = Keys::generate();
+        let other = Keys::generate().public_key().to_hex();
+        let rumor = nostr::EventBuilder::new(nostr::Kind::Custom(MAIL_EVENT_KIND), "hi")
+            .build(author.public_key());
+        let id = rumor.clone().id().to_hex();
+        let own = author.public_key().to_hex();
+        db.record_sent(
+            &rumor,
+            &[
+                ("wrap-other".to_string(), other.clone()),
+                ("wrap-own".to_string(), own.clone()),
+            ],
+        )?;
+        // Our own copy comes back from the relays.
+        db.save_gift_wrap_map("wrap-own", &id, Some(&own), 10, Some(Encryption::Nip44(2)))?;
+        assert!(db.get_event_raw(&id)?.is_some());
+
+        assert_eq!(db.get_thread_account(&id)?, Some(own.clone()));
+        assert_eq!(db.get_thread_account("missing")?, None);
+
+        let wraps = db.get_message_wraps(&id)?;
+        assert_eq!(
+            wraps,
+            vec![
+                WrapCopy {
+                    wrap_id: "wrap-other".to_string(),
+                    recipient: Some(other),
+                    sent: true,
+                    encryption: None,
+                    created_at: None,
+                },
+                WrapCopy {
+                    wrap_id: "wrap-own".to_string(),
+                    recipient: Some(own),
+                    sent: true,
+                    encryption: Some(Encryption::Nip44(2)),
+                    created_at: Some(10),
+                },
+            ]
+        );
+        assert!(db.get_message_wraps("missing")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_notes_to_self() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let messages = [
+            ("1", &alice, 10, json!([["p", alice], ["subject", "note"]])),
+            (
+                "2",
+                &alice,
+                20,
+                json!([["p", alice], ["p", bob], ["subject", "hi"]]),
+            ),
+            ("3", &alice, 30, json!([["p", bob], ["subject", "hi"]])),
+            ("4", &bob, 40, json!([["p", bob], ["subject", "not mine"]])),
+        ];
+        for (id, author, created_at, tags) in messages {
+            insert_mail(&db, id, author, created_at, tags)?;
+        }
+
+        let ids: Vec<String> = db
+            .get_notes_to_self(&alice)?
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, vec!["1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_contact_verification() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        db.save_contact(&alice, None)?;
+        db.save_verification_challenge(&alice, "first")?;
+        db.save_verification_challenge(&alice, "second")?;
+        assert_eq!(
+            db.get_verification_challenge(&alice)?.as_deref(),
+            Some("second")
+        );
+        assert!(db.get_verified_contacts()?.is_empty());
+
+        db.mark_contact_verified(&alice)?;
+        assert_eq!(db.get_verification_challenge(&alice)?, None);
+        assert!(db.get_verified_contacts()?.contains(&alice));
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_drafts_are_taken_once() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let first = db.save_draft("One", "", "", &[], None)?;
+        let second = db.save_draft("Two", "", "", &[], None)?;
+        db.set_open_drafts(&[first])?;
+        db.set_open_drafts(&[second])?;
+        assert_eq!(db.take_open_drafts()?, vec![second]);
+        assert!(db.take_open_drafts()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_subjects() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        insert_mail(
+            &db,
+            "1",
+            &"a".repeat(64),
+            10,
+            json!([["subject", "Re: Re: Fwd: hi"]]),
+        )?;
+
+        db.set_thread_subject("1", Some("Trip to Lisbon"))?;
+        db.set_thread_subject("1", Some("Lisbon trip"))?;
+        assert_eq!(db.get_thread_subjects()?["1"], "Lisbon trip");
+        // The message keeps the subject it came with.
+        assert_eq!(db.get_email_thread("1")?[0].subject, "Re: Re: Fwd: hi");
+
+        db.set_thread_subject("1", None)?;
+        assert!(db.get_thread_subjects()?.is_empty());
+        db.set_thread_subject("1", Some("Lisbon trip"))?;
+        db.connection
+            .execute("DELETE FROM events WHERE id = '1'", ())?;
+        assert!(db.get_thread_subjects()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_email_threads_load_together() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        for (id, created_at, tags) in [
+            ("1", 10, json!([["subject", "One"]])),
+            ("2", 20, json!([["subject", "Two"]])),
+            (
+                "3",
+                30,
+                json!([["e", "1", "", "root"], ["subject", "Re: One"]]),
+            ),
+            (
+                "4",
+                40,
+                json!([["e", "1", "", "root"], ["e", "3", "", "reply"]]),
+            ),
+        ] {
+            insert_mail(&db, id, &"a".repeat(64), created_at, tags)?;
+        }
+
+        let threads = db.get_email_threads(&["1".to_string(), "2".to_string()])?;
+        let subjects = |root: &str| -> Vec<String> {
+            threads[root]
+                .iter()
+                .map(|msg| msg.subject.clone())
+                .collect()
+        };
+        assert_eq!(subjects("1"), vec!["One", "Re: One", ""]);
+        assert_eq!(subjects("2"), vec!["Two"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_notes() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        for (id, subject) in [("1", "Lease renewal"), ("2", "Dinner")] {
+            insert_mail(&db, id, &"a".repeat(64), 10, json!([["subject", subject]]))?;
+        }
+
+        db.set_thread_note("1", Some("Call the landlord"))?;
+        db.set_thread_note("1", Some("Call the landlord by Friday"))?;
+        assert_eq!(db.get_thread_notes()?["1"], "Call the landlord by Friday");
+        let noted = |words: &[&str]| -> Result<Vec<String>> {
+            let filter = MessageFilter {
+                note_terms: words.iter().map(|word| word.to_string()).collect(),
+                ..Default::default()
+            };
+            Ok(db
+                .get_top_level_messages(None, &filter)?
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect())
+        };
+        assert_eq!(noted(&["friday", "LANDLORD"])?, vec!["1"]);
+        assert!(noted(&["monday"])?.is_empty());
+        assert_eq!(noted(&[])?.len(), 2);
+
+        db.set_thread_note("1", None)?;
+        assert!(db.get_thread_notes()?.is_empty());
+        db.set_thread_note("1", Some("Call the landlord"))?;
+        db.connection
+            .execute("DELETE FROM events WHERE id = '1'", ())?;
+        assert!(db.get_thread_notes()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_paid_invoices() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        assert!(db.get_paid_invoices()?.is_empty());
+        db.record_paid_invoice("1", "lnbc50u1", "preimage")?;
+        db.record_paid_invoice("1", "lnbc50u1", "preimage")?;
+        assert_eq!(
+            db.get_paid_invoices()?,
+            HashMap::from([("1".to_string(), "lnbc50u1".to_string())])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_notifications() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let relay = "wss://relay.example.com";
+        assert!(db.add_notification(
+            NotificationKind::RelayAuth,
+            relay,
+            "Sign in",
+            relay,
+            false
+        )?);
+        assert!(!db.add_notification(NotificationKind::RelayAuth, relay, "Again", relay, false)?);
+        let id = "a".repeat(64);
+        assert!(db.add_notification(NotificationKind::Mention, &id, "Mentioned", &id, false)?);
+        assert_eq!(db.get_unread_notification_count()?, 2);
+
+        let notifications = db.get_notifications(10)?;
+        assert_eq!(notifications.len(), 2);
+        let mention = notifications
+            .iter()
+            .find(|n| n.kind == NotificationKind::Mention)
+            .unwrap();
+        db.mark_notification_read(mention.id)?;
+        assert_eq!(db.get_unread_notification_count()?, 1);
+
+        db.clear_read_notifications()?;
+        assert_eq!(db.get_notifications(10)?.len(), 1);
+        db.mark_all_notifications_read()?;
+        assert_eq!(db.get_unread_notification_count()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zaps_are_stored_once() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let zap = crate::zap::Zap {
+            receipt_id: "r".repeat(64),
+            event_id: "e".repeat(64),
+            recipient: "a".repeat(64),
+            sender: "b".repeat(64),
+            msats: 21_000,
+            comment: "Thanks!".to_string(),
+            created_at: 1,
+        };
+        assert!(db.save_zap(&zap)?);
+        assert!(!db.save_zap(&zap)?);
+        assert_eq!(db.get_zaps(&zap.event_id)?, vec![zap]);
+        assert!(db.get_zaps(&"f".repeat(64))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_contact_groups() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+
+        let id = db.save_contact_group("Newsletter", &[alice.clone(), bob.clone()])?;
+        assert_eq!(
+            db.get_contact_groups()?,
+            vec![(id, "Newsletter".to_string())]
+        );
+        assert_eq!(db.get_contact_group_members(id)?.len(), 2);
+
+        // Saving again under the same name replaces the members.
+        assert_eq!(
+            db.save_contact_group("Newsletter", std::slice::from_ref(&bob))?,
+            id
+        );
+        assert_eq!(db.get_contact_group_members(id)?, vec![bob]);
+
+        db.delete_contact_group(id)?;
+        assert!(db.get_contact_groups()?.is_empty());
+        assert!(db.get_contact_group_members(id)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_saved_searches() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let invoices = db.save_search("Invoices", "subject:invoice")?;
+        db.save_search("art", "has:attachment")?;
+        // Saving under a taken name updates that search.
+        assert_eq!(
+            db.save_search("Invoices", "subject:invoice has:attachment")?,
+            invoices
+        );
+
+        let searches = db.get_saved_searches()?;
+        let names: Vec<&str> = searches.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["art", "Invoices"]);
+        assert_eq!(searches[1].query, "subject:invoice has:attachment");
+
+        db.delete_saved_search(invoices)?;
+        assert_eq!(db.get_saved_searches()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_and_merge_duplicate_contacts() -> Result<()> {
+        use nostr::ToBech32;
+        let mut db = Db::new_in_memory()?;
+        let alice = Keys::generate().public_key();
+        let bob = "b".repeat(64);
+        let bob_again = "c".repeat(64);
+
+        db.save_contact(&alice.to_bech32()?, Some("Alice"))?;
+        db.save_contact(&alice.to_hex(), None)?;
+        db.save_contact(&bob, None)?;
+        db.save_contact(&bob_again, Some("Bobby"))?;
+        for pubkey in [&bob, &bob_again] {
+            db.connection.execute(
+                "INSERT INTO profile_metadata (pubkey, id, created_at, nip05)
+                 VALUES (?1, ?1, 0, 'Bob@example.com')",
+                (pubkey,),
+            )?;
+        }
+        let group = db.save_contact_group("Friends", &[alice.to_bech32()?, bob_again.clone()])?;
+
+        let duplicates = db.find_duplicate_contacts()?;
+        assert_eq!(
+            duplicates,
+            vec![
+                DuplicateContacts {
+                    reason: DuplicateReason::SamePubkey(alice.to_hex()),
+                    pubkeys: vec![alice.to_hex(), alice.to_bech32()?],
+                },
+                DuplicateContacts {
+                    reason: DuplicateReason::SameNip05("bob@example.com".to_string()),
+                    pubkeys: vec![bob.clone(), bob_again.clone()],
+                },
+            ]
+        );
+
+        db.merge_contacts(&alice.to_hex(), &duplicates[0].pubkeys, Some("Alice"))?;
+        db.merge_contacts(&bob, &duplicates[1].pubkeys, Some("Bobby"))?;
+        assert!(db.find_duplicate_contacts()?.is_empty());
+        assert_eq!(
+            db.get_contact_petname(&alice.to_hex())?.as_deref(),
+            Some("Alice")
+        );
+        assert_eq!(db.get_contact_petname(&bob)?.as_deref(), Some("Bobby"));
+        assert!(!db.is_contact(&bob_again)?);
+        let mut members = db.get_contact_group_members(group)?;
+        members.sort();
+        let mut expected = vec![alice.to_hex(), bob.clone()];
+        expected.sort();
+        assert_eq!(members, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sent_messages_fold_their_copies() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = Keys::generate();
+        let recipients = [Keys::generate().public_key(), Keys::generate().public_key()];
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: recipients.to_vec(),
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: "Plans".to_string(),
+            content: "Dinner at eight".to_string(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: Default::default(),
+        };
+        let wraps: Vec<(String, String)> = message
+            .to_events(&alice, Duration::ZERO)
+            .into_iter()
+            .map(|(recipient, wrap)| (wrap.id.to_hex(), recipient.to_hex()))
+            .collect();
+        let rumor = message.to_rumor().expect("to_events sets the author");
+        assert_eq!(rumor.id, message.id);
+        db.record_sent(&rumor, &wraps)?;
+
+        let sent = db.get_sent_messages(Some(&alice.public_key().to_hex()))?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].entry.subject, "Plans");
+        assert_eq!(sent[0].recipients.len(), 2);
+        assert_eq!((sent[0].delivered, sent[0].pending), (0, 2));
+
+        db.record_sent_delivery(&wraps[0].0, 2, 0)?;
+        db.record_sent_delivery(&wraps[1].0, 0, 1)?;
+        let sent = db.get_sent_messages(None)?;
+        assert_eq!((sent[0].delivered, sent[0].pending), (1, 0));
+        assert_eq!((sent[0].confirmed, sent[0].dropped), (0, 0));
+        db.record_sent_confirmation(&wraps[0].0, 0)?;
+        let sent = db.get_sent_messages(None)?;
+        assert_eq!((sent[0].confirmed, sent[0].dropped), (0, 1));
+
+        // Sent mail stays out of the inbox until somebody answers.
+        assert!(db
+            .get_top_level_messages(None, &MessageFilter::default())?
+            .is_empty());
+        assert_eq!(
+            db.get_unread_event_ids(&[sent[0].entry.id.clone()])?.len(),
+            0
+        );
+        insert_mail(
+            &db,
+            "reply",
+            &recipients[0].to_hex(),
+            rumor.created_at.as_u64() + 10,
+            json!([["e", sent[0].entry.id], ["subject", "Re: Plans"]]),
+        )?;
+        let inbox = db.get_top_level_messages(None, &MessageFilter::default())?;
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].thread_count, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_events_leaves_out_only_what_fails() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let wrap = |subject: &str| -> Result<EventToStore> {
+            let mut message = MailMessage {
+                id: None,
+                created_at: None,
+                author: None,
+                to: vec![bob.public_key()],
+                cc: Vec::new(),
+                bcc: Vec::new(),
+                parent_events: None,
+                subject: subject.to_string(),
+                content: String::new(),
+                attachments: Vec::new(),
+                articles: Vec::new(),
+                quote: None,
+                payload: None,
+                email_to: Vec::new(),
+                email_from: None,
+                tag_relays: Default::default(),
+            };
+            let event = message
+                .to_events(&alice, Duration::ZERO)
+                .remove(&bob.public_key())
+                .expect("a gift wrap for bob");
+            let unwrapped =
+                crate::account_manager::unwrap_gift_wrap(std::slice::from_ref(&bob), &event)?;
+            Ok(EventToStore {
+                event,
+                unwrapped: Some(unwrapped),
+                recipient: Some(bob.public_key().to_hex()),
+            })
+        };
+
+        let mut forged = wrap("Forged")?;
+        if let Some(unwrapped) = forged.unwrapped.as_mut() {
+            unwrapped.sender = Keys::generate().public_key();
+        }
+        assert_eq!(
+            db.store_events(&[wrap("First")?, forged, wrap("Second")?])?,
+            [true, false, true]
+        );
+        assert_eq!(db.get_mail_event_ids()?.len(), 2);
+        assert_eq!(db.get_gift_wrap_items()?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copies_to_self_file_under_sent() -> Result<()> {
+        let alice = Keys::generate();
+        let bob = Keys::generate().public_key();
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: vec![bob],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: "Plans".to_string(),
+            content: "Dinner at eight".to_string(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: Default::default(),
+        };
+        let mut wraps: Vec<(String, String)> = message
+            .to_events(&alice, Duration::ZERO)
+            .into_iter()
+            .map(|(recipient, wrap)| (wrap.id.to_hex(), recipient.to_hex()))
+            .collect();
+        let copy = message
+            .copy_to_self(&alice, Duration::ZERO)
+            .expect("a copy for alice");
+        wraps.push((copy.id.to_hex(), alice.public_key().to_hex()));
+        let rumor = message.to_rumor().expect("to_events sets the author");
+        let copy = EventToStore {
+            unwrapped: Some(crate::account_manager::unwrap_gift_wrap(
+                std::slice::from_ref(&alice),
+                &copy,
+            )?),
+            event: copy,
+            recipient: Some(alice.public_key().to_hex()),
+        };
+
+        // Sent here, the copy isn't a recipient and coming back doesn't put it in the inbox.
+        let mut db = Db::new_in_memory()?;
+        db.record_sent(&rumor, &wraps)?;
+        db.store_events(std::slice::from_ref(&copy))?;
+        let sent = db.get_sent_messages(None)?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].recipients, vec![bob.to_hex()]);
+        assert_eq!((sent[0].pending, sent[0].elsewhere), (1, false));
+        assert!(db
+            .get_top_level_messages(None, &MessageFilter::default())?
+            .is_empty());
+
+        // Another device only gets the copy, and files it under Sent too.
+        let other = Db::new_in_memory()?;
+        other.store_events(&[copy])?;
+        let sent = other.get_sent_messages(None)?;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].recipients, vec![bob.to_hex()]);
+        assert_eq!((sent[0].pending, sent[0].elsewhere), (0, true));
+        assert!(other
+            .get_top_level_messages(None, &MessageFilter::default())?
+            .is_empty());
+        assert!(other
+            .get_unread_event_ids(&[sent[0].entry.id.clone()])?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sending_accounts_keep_the_last_one() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        db.remember_sending_account("work", &["alice".to_string(), "bob".to_string()])?;
+        db.remember_sending_account("home", &["bob".to_string()])?;
+        let accounts = db.get_sending_accounts()?;
+        assert_eq!(accounts.get("alice").map(String::as_str), Some("work"));
+        assert_eq!(accounts.get("bob").map(String::as_str), Some("home"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inbox_columns_keep_their_order() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        assert!(db.get_inbox_columns()?.is_empty());
+        let columns = vec![
+            ("subject".to_string(), true),
+            ("from".to_string(), false),
+            ("time".to_string(), true),
+        ];
+        db.save_inbox_columns(&columns)?;
+        db.save_inbox_columns(&columns)?;
+        assert_eq!(db.get_inbox_columns()?, columns);
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_flags_only_move_forward() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let ids = vec!["a".to_string(), "b".to_string()];
+        db.mark_read(&ids[..1])?;
+        db.set_starred("b", true)?;
+        let flags = db.get_thread_flags(&ids)?;
+        assert_eq!(flags.read, vec!["a".to_string()]);
+        assert_eq!(flags.starred, vec!["b".to_string()]);
+        db.record_flag_sync("thread", 100)?;
+
+        // On a fresh install the flags come back before the messages do.
+        let mut fresh = Db::new_in_memory()?;
+        assert!(fresh.apply_thread_flags("thread", 100, &flags)?);
+        assert_eq!(fresh.get_thread_flags(&ids)?, flags);
+
+        // A copy from before the last change doesn't undo it.
+        let unstarred = ThreadFlags {
+            starred: Vec::new(),
+            ..flags.clone()
+        };
+        assert!(!db.apply_thread_flags("thread", 90, &unstarred)?);
+        assert!(db.get_starred_ids()?.contains("b"));
+        assert!(db.apply_thread_flags("thread", 110, &unstarred)?);
+        assert!(db.get_starred_ids()?.is_empty());
+        assert_eq!(fresh.newest_flag_sync()?, Some(100));
+        assert_eq!(db.newest_flag_sync()?, Some(110));
+        assert_eq!(Db::new_in_memory()?.newest_flag_sync()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inbox_entries_carry_thread_labels() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let author = "a".repeat(64);
+        insert_mail(&db, "1", &author, 10, json!([["subject", "hi"]]))?;
+        insert_mail(
+            &db,
+            "2",
+            &author,
+            20,
+            json!([["e", "1"], ["subject", "Re: hi"]]),
+        )?;
+        db.set_thread_label(&["2".to_string()], "work", true)?;
+        db.set_thread_label(&["1".to_string(), "2".to_string()], "urgent", true)?;
+
+        let entries = db.get_top_level_messages(None, &MessageFilter::default())?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].labels, vec!["urgent", "work"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replies_take_their_thread_labels() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let keys = Keys::generate();
+        let mail = |content: &str, tags: Vec<nostr::Tag>| {
+            nostr::EventBuilder::new(nostr::Kind::Custom(MAIL_EVENT_KIND), content)
+                .tags(tags)
+                .sign_with_keys(&keys)
+        };
+        let root = mail("root", Vec::new())?;
+        db.store_event(&root, None, None)?;
+        let thread = vec![root.id.to_hex()];
+        db.set_thread_label(&thread, "urgent", true)?;
+        db.set_thread_label(&thread, "work", true)?;
+        db.set_thread_label(&thread, "work", false)?;
+        assert_eq!(db.get_labels(&thread)?, vec!["urgent".to_string()]);
+
+        let reply = mail("reply", vec![nostr::Tag::event(root.id)])?;
+        db.store_event(&reply, None, None)?;
+        assert_eq!(
+            db.get_labels(&[reply.id.to_hex()])?,
+            vec!["urgent".to_string()]
+        );
+        let unrelated = mail("unrelated", Vec::new())?;
+        db.store_event(&unrelated, None, None)?;
+        assert!(db.get_labels(&[unrelated.id.to_hex()])?.is_empty());
+        assert_eq!(db.get_all_labels()?, vec!["urgent".to_string()]);
+        let by_message = db.get_message_labels(&[reply.id.to_hex(), unrelated.id.to_hex()])?;
+        assert_eq!(by_message.len(), 1);
+        assert_eq!(by_message[&reply.id.to_hex()], vec!["urgent".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocked_pubkeys_hidden_from_inbox() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let mallory = "d".repeat(64);
+        for (id, author, created_at) in [("1", &alice, 10), ("2", &mallory, 20)] {
+            insert_mail(&db, id, author, created_at, json!([["subject", "hi"]]))?;
+        }
+        assert_eq!(
+            db.get_top_level_messages(None, &MessageFilter::default())?
+                .len(),
+            2
+        );
+        db.save_contact(&mallory, None)?;
+        db.add_notification(NotificationKind::Mention, "2", "Hi", "2", false)?;
+
+        db.block_pubkey(&mallory)?;
+        db.block_pubkey(&mallory)?;
+        assert_eq!(db.get_blocked_pubkeys()?, vec![mallory.clone()]);
+        let ids: Vec<String> = db
+            .get_top_level_messages(None, &MessageFilter::default())?
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, vec!["1"]);
+        assert!(db.get_user_contacts()?.is_empty());
+        assert!(db.get_notifications(10)?.is_empty());
+        assert_eq!(db.get_unread_notification_count()?, 0);
+        assert!(db.get_messages_with(&mallory, 10, 0)?.is_empty());
+        assert_eq!(db.get_recent_correspondents(10)?, vec![alice.clone()]);
+
+        db.unblock_pubkey(&mallory)?;
+        assert!(db.get_blocked_pubkeys()?.is_empty());
+        assert_eq!(db.get_user_contacts()?.len(), 1);
+        assert_eq!(db.get_unread_notification_count()?, 1);
+        assert_eq!(
+            db.get_top_level_messages(None, &MessageFilter::default())?
+                .len(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inbox_pages() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        for (id, created_at) in [
+            ("1", 10),
+            ("2", 20),
+            ("3", 30),
+            ("6", 30),
+            ("4", 40),
+            ("5", 50),
+        ] {
+            insert_mail(
+                &db,
+                id,
+                &"a".repeat(64),
+                created_at,
+                json!([["subject", "hi"]]),
+            )?;
+        }
+        let page = |page: Page| -> Result<Vec<String>> {
+            Ok(db
+                .get_top_level_page(None, &MessageFilter::default(), &page)?
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect())
+        };
+        let at = |created_at: i64, id: &str| Cursor {
+            created_at,
+            id: id.to_string(),
+        };
+
+        assert_eq!(page(Page::All)?, ["5", "4", "6", "3", "2", "1"]);
+        assert_eq!(page(Page::First(2))?, ["5", "4"]);
+        // Threads at the same time are told apart by id.
+        assert_eq!(page(Page::Before(at(40, "4"), 2))?, ["6", "3"]);
+        assert_eq!(page(Page::Before(at(30, "3"), 10))?, ["2", "1"]);
+        assert_eq!(page(Page::From(at(30, "6"), 2))?, ["6", "3"]);
+        assert_eq!(page(Page::After(at(30, "3"), 2))?, ["4", "6"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_filter_and_unread_counts() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let carol = "c".repeat(64);
+        for (id, author, recipient, created_at) in [
+            ("1", &carol, &alice, 10),
+            ("2", &carol, &bob, 20),
+            ("3", &alice, &bob, 30),
+        ] {
+            insert_mail(
+                &db,
+                id,
+                author,
+                created_at,
+                json!([["p", recipient], ["subject", "hi"]]),
+            )?;
+        }
+
+        let ids = |entries: Vec<TableEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.id).collect()
+        };
+        assert_eq!(
+            db.get_top_level_messages(None, &MessageFilter::default())?
+                .len(),
+            3
+        );
+        assert_eq!(
+            ids(db.get_top_level_messages(Some(&alice), &MessageFilter::default())?),
+            vec!["3", "1"]
+        );
+        assert_eq!(
+            ids(db.get_top_level_messages(Some(&bob), &MessageFilter::default())?),
+            vec!["3", "2"]
+        );
+        assert_eq!(
+            db.count_top_level_messages(Some(&bob), &MessageFilter::default())?,
+            2
+        );
+
+        let both = vec![alice.clone(), bob.clone()];
+        assert_eq!(db.count_unread(std::slice::from_ref(&alice))?, 1);
+        assert_eq!(db.count_unread(std::slice::from_ref(&bob))?, 2);
+        assert_eq!(db.count_unread(&both)?, 2);
+
+        db.mark_read(&["2".to_string()])?;
+        assert_eq!(db.count_unread(std::slice::from_ref(&bob))?, 1);
+        assert_eq!(db.count_unread(&both)?, 1);
+        assert_eq!(
+            db.get_unread_event_ids(&["1".to_string(), "2".to_string()])?,
+            HashSet::from(["1".to_string()])
+        );
+        let unread = MessageFilter {
+            unread: true,
+            ..Default::default()
+        };
+        assert_eq!(db.count_top_level_messages(None, &unread)?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_filters() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let messages = [
+            (
+                "1",
+                &alice,
+                10,
+                json!([["subject", "Lunch plans"]]),
+                "Tacos tomorrow?",
+            ),
+            (
+                "2",
+                &bob,
+                20,
+                json!([
+                    ["subject", "Invoice"],
+                    ["imeta", "url https://example.com/a.pdf"]
+                ]),
+                "Attached.",
+            ),
+            ("3", &bob, 30, json!([["subject", "Dinner"]]), "Tacos again"),
+        ];
+        for (id, author, created_at, tags, content) in messages {
+            insert_mail_with_content(&db, id, author, created_at, tags, content)?;
+        }
+        db.connection
+            .execute("INSERT INTO contacts (pubkey) VALUES (?1)", (&alice,))?;
+        db.mark_read(&["3".to_string()])?;
+        db.set_starred("2", true)?;
+
+        let ids = |db: &Db, filter: MessageFilter| -> Result<Vec<String>> {
+            Ok(db
+                .get_top_level_messages(None, &filter)?
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect())
+        };
+        let search = |query: &str| MessageFilter {
+            query: query.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(ids(&db, search("taco"))?, vec!["3", "1"]);
+        assert_eq!(ids(&db, search("lunch TACOS"))?, vec!["1"]);
+        assert_eq!(ids(&db, search("\"invoice"))?, vec!["2"]);
+        let subject = |word: &str| MessageFilter {
+            subject_terms: vec![word.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(ids(&db, subject("dinner"))?, vec!["3"]);
+        assert!(ids(&db, subject("tacos"))?.is_empty());
+        assert_eq!(
+            ids(
+                &db,
+                MessageFilter {
+                    unread: true,
+                    ..search("taco")
+                }
+            )?,
+            vec!["1"]
+        );
+        assert_eq!(
+            ids(
+                &db,
+                MessageFilter {
+                    starred: true,
+                    has_attachment: true,
+                    ..Default::default()
+                }
+            )?,
+            vec!["2"]
+        );
+        assert_eq!(
+            ids(
+                &db,
+                MessageFilter {
+                    from_contacts: true,
+                    ..Default::default()
+                }
+            )?,
+            vec!["1"]
+        );
+        assert!(db.get_starred_ids()?.contains("2"));
+
+        db.set_archived(&["1".to_string()], true)?;
+        assert_eq!(ids(&db, search("taco"))?, vec!["3"]);
+        assert_eq!(
+            ids(
+                &db,
+                MessageFilter {
+                    archived: true,
+                    ..Default::default()
+                }
+            )?,
+            vec!["1"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recent_correspondents() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let us = "0".repeat(64);
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let messages = [("1", &us, 30), ("2", &alice, 10), ("3", &bob, 20)];
+        for (id, author, created_at) in messages {
+            insert_mail(&db, id, author, created_at, json!([]))?;
+        }
+        db.connection.execute(
+            "INSERT INTO sent_messages (wrap_id, event_id, recipient_pubkey) VALUES ('w', '1', ?1)",
+            (&alice,),
+        )?;
+
+        // Writing to Alice last puts her ahead of Bob, and our own message isn't counted.
+        assert_eq!(db.get_recent_correspondents(10)?, vec![alice, bob]);
+        assert_eq!(db.get_recent_correspondents(1)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_focused_inbox() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let us = "0".repeat(64);
+        let alice = "a".repeat(64);
+        let bob = "b".repeat(64);
+        let carol = "c".repeat(64);
+        let messages = [
+            ("1", &us, json!([["subject", "Hello"], ["p", &alice]])),
+            ("2", &alice, json!([["subject", "Re: Hello"], ["e", "1"]])),
+            ("3", &bob, json!([["subject", "Newsletter"]])),
+            ("4", &carol, json!([["subject", "Lunch"]])),
+        ];
+        for (id, author, tags) in messages {
+            insert_mail(&db, id, author, 10, tags)?;
+        }
+        db.connection.execute(
+            "INSERT INTO sent_messages (wrap_id, event_id, recipient_pubkey) VALUES ('w', '1', ?1)",
+            (&alice,),
+        )?;
+        db.connection
+            .execute("INSERT INTO contacts (pubkey) VALUES (?1)", (&carol,))?;
+
+        let stats = db.get_sender_stats()?;
+        assert_eq!(stats[&alice].written_to, 1);
+        assert_eq!(stats[&alice].answers, 1);
+        assert!(stats[&carol].contact);
+        assert!(!stats.contains_key(&bob));
+        assert_eq!(crate::focus::rank(&mut db)?, 2);
+
+        let ids = |db: &Db, focused: bool| -> Result<Vec<String>> {
+            let filter = MessageFilter {
+                focused: Some(focused),
+                ..Default::default()
+            };
+            Ok(db
+                .get_top_level_messages(None, &filter)?
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect())
+        };
+        assert_eq!(ids(&db, true)?, vec!["4", "1"]);
+        assert_eq!(ids(&db, false)?, vec!["3"]);
+
+        // Corrections outlast the ranking.
+        db.correct_sender_focus(&bob, true)?;
+        db.correct_sender_focus(&carol, false)?;
+        crate::focus::rank(&mut db)?;
+        let focused = db.get_focused_senders()?;
+        assert!(focused.contains(&bob) && !focused.contains(&carol));
+        assert_eq!(ids(&db, true)?, vec!["3", "1"]);
+
+        // Writing in a thread we replied in counts, but not enough on its own.
+        let dave = "d".repeat(64);
+        let messages = [
+            ("5", &bob, json!([["subject", "Party"]])),
+            ("6", &us, json!([["subject", "Re: Party"], ["e", "5"]])),
+            ("7", &dave, json!([["subject", "Re: Party"], ["e", "5"]])),
+        ];
+        for (id, author, tags) in messages {
+            insert_mail(&db, id, author, 20, tags)?;
+        }
+        db.connection.execute(
+            "INSERT INTO sent_messages (wrap_id, event_id, recipient_pubkey) VALUES ('w6', '6', ?1)",
+            (&bob,),
+        )?;
+        let stats = db.get_sender_stats()?;
+        assert_eq!(stats[&dave].participation, 1);
+        assert!(!stats[&dave].focused());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mail_message_attachments() -> Result<()> {
+        let hash = "a".repeat(64);
+        let raw = json!({
+            "id": "0".repeat(64),
+            "pubkey": Keys::generate().public_key().to_hex(),
+            "created_at": 1,
+            "kind": MAIL_EVENT_KIND,
+            "tags": [
+                ["subject", "files"],
+                ["imeta", "url https://example.com/a.pdf", format!("x {}", hash), "size 10"],
+                ["imeta", "url https://example.com/no-hash.pdf"],
+            ],
+            "content": "",
+            "sig": "",
+        })
+        .to_string();
+
+        let message = Db::parse_mail_message(&raw)?;
+        assert_eq!(message.attachments.len(), 1);
+        assert_eq!(message.attachments[0].sha256, hash);
+        assert_eq!(message.attachments[0].size, Some(10));
+        assert_eq!(message.attachments[0].file_name(), "a.pdf");
+
+        Ok(())
+    }
+}
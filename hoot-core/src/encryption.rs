@@ -0,0 +1,113 @@
+//! Which scheme protected a message on its way to us, worked out from the gift wrap it came in.
+
+use std::fmt;
+
+/// The shortest a NIP-44 payload gets, base64 of the version, nonce, padded message and MAC.
+const NIP44_MIN_PAYLOAD: usize = 132;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// NIP-44 with the payload's version, what gift wraps are meant to use.
+    Nip44(u8),
+    /// The deprecated NIP-04 scheme, which isn't authenticated and shows who talks to whom.
+    Nip04,
+    /// Not encrypted at all.
+    Plaintext,
+}
+
+impl Encryption {
+    /// The scheme an encrypted `payload`, like a gift wrap's content, was made with. NIP-04
+    /// payloads end in `?iv=`, NIP-44 ones are base64 starting with a version byte.
+    pub fn of_payload(payload: &str) -> Self {
+        if payload.contains("?iv=") {
+            return Encryption::Nip04;
+        }
+        let is_base64 = payload
+            .chars()
+            .all(|c| c == '=' || base64_value(c).is_some());
+        if payload.len() < NIP44_MIN_PAYLOAD || !is_base64 {
+            return Encryption::Plaintext;
+        }
+        let mut sextets = payload.chars().map(base64_value);
+        match (sextets.next(), sextets.next()) {
+            (Some(Some(first)), Some(Some(second))) => {
+                Encryption::Nip44((first << 2) | (second >> 4))
+            }
+            _ => Encryption::Plaintext,
+        }
+    }
+
+    /// The name it's stored under.
+    pub fn key(&self) -> String {
+        match self {
+            Encryption::Nip44(version) => format!("nip44-v{}", version),
+            Encryption::Nip04 => "nip04".to_string(),
+            Encryption::Plaintext => "plaintext".to_string(),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "nip04" => Some(Encryption::Nip04),
+            "plaintext" => Some(Encryption::Plaintext),
+            _ => key
+                .strip_prefix("nip44-v")?
+                .parse()
+                .ok()
+                .map(Encryption::Nip44),
+        }
+    }
+
+    /// Whether this is the scheme current clients should use.
+    pub fn is_current(&self) -> bool {
+        *self == Encryption::Nip44(2)
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encryption::Nip44(version) => write!(f, "NIP-44 v{}", version),
+            Encryption::Nip04 => write!(f, "NIP-04"),
+            Encryption::Plaintext => write!(f, "Not encrypted"),
+        }
+    }
+}
+
+fn base64_value(c: char) -> Option<u8> {
+    let value = match c {
+        'A'..='Z' => c as u8 - b'A',
+        'a'..='z' => c as u8 - b'a' + 26,
+        '0'..='9' => c as u8 - b'0' + 52,
+        '+' => 62,
+        '/' => 63,
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_schemes_apart() {
+        // From the NIP-44 test vectors.
+        let nip44 =
+            "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfY\
+                     EAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb";
+        assert_eq!(Encryption::of_payload(nip44), Encryption::Nip44(2));
+        assert_eq!(
+            Encryption::of_payload("zJxfaJ32rN5Dg1ODjOlEew==?iv=EV5bUjcc4OX2Km/zPp4ndQ=="),
+            Encryption::Nip04
+        );
+        assert_eq!(Encryption::of_payload("hello there"), Encryption::Plaintext);
+        for encryption in [
+            Encryption::Nip44(2),
+            Encryption::Nip04,
+            Encryption::Plaintext,
+        ] {
+            assert_eq!(Encryption::from_key(&encryption.key()), Some(encryption));
+        }
+    }
+}
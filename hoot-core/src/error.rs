@@ -1,3 +1,5 @@
+use crate::relay::RelayError;
+
 #[derive(Debug)]
 pub enum Error {
     RelayNotConnected,
@@ -5,6 +7,7 @@ pub enum Error {
     Generic(String),
     Empty,
     DecodeFailed,
+    Relay(RelayError),
 }
 
 impl From<serde_json::Error> for Error {
@@ -13,6 +16,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<RelayError> for Error {
+    fn from(value: RelayError) -> Self {
+        Self::Relay(value)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -21,6 +30,7 @@ impl std::fmt::Display for Error {
             Error::Generic(s) => write!(f, "{}", s),
             Error::Empty => write!(f, "Data was empty"),
             Error::DecodeFailed => write!(f, "Could not decode JSON data."),
+            Error::Relay(err) => write!(f, "{}", err),
         }
     }
 }
@@ -0,0 +1,122 @@
+//! Stars, read state and archiving kept on relays as NIP-78 app data, one event per thread,
+//! so a fresh install gets back how a mailbox was organized along with the messages in it.
+//!
+//! The events are encrypted to ourselves, and their `d` tags are hashed with the account's
+//! secret key so relays can't tell which thread one belongs to.
+
+use anyhow::Result;
+use nostr::hashes::{sha256, Hash};
+use nostr::nips::nip44;
+use nostr::{Event, EventBuilder, Filter, Keys, Kind, PublicKey, Tag, TagKind, Timestamp};
+use serde::{Deserialize, Serialize};
+
+const D_TAG_PREFIX: &str = "hoot/flags/";
+
+/// How long before the newest flags we have to ask from, for installs whose clocks are off or
+/// that published late.
+const SYNC_MARGIN: u64 = 24 * 60 * 60;
+
+/// The flags of a thread's messages.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreadFlags {
+    /// Every message in the thread when the flags were saved. Others are left alone.
+    pub messages: Vec<String>,
+    pub read: Vec<String>,
+    pub starred: Vec<String>,
+    pub archived: Vec<String>,
+}
+
+/// The `d` tag for the thread starting at `root_id`.
+pub fn thread_key(keys: &Keys, root_id: &str) -> String {
+    let mut data = keys.secret_key().to_secret_bytes().to_vec();
+    data.extend_from_slice(root_id.as_bytes());
+    format!("{}{}", D_TAG_PREFIX, sha256::Hash::hash(&data))
+}
+
+/// Our flag events, to subscribe to. Given the newest we already have, only ones from around
+/// then on are asked for, so a reconnect doesn't download every flag event again.
+pub fn filter(accounts: impl IntoIterator<Item = PublicKey>, newest: Option<i64>) -> Filter {
+    let filter = Filter::new()
+        .kind(Kind::ApplicationSpecificData)
+        .authors(accounts);
+    match newest {
+        Some(newest) => filter.since(Timestamp::from_secs(
+            (newest.max(0) as u64).saturating_sub(SYNC_MARGIN),
+        )),
+        None => filter,
+    }
+}
+
+pub fn to_event(keys: &Keys, root_id: &str, flags: &ThreadFlags) -> Result<Event> {
+    let content = nip44::encrypt(
+        keys.secret_key(),
+        &keys.public_key(),
+        serde_json::to_string(flags)?,
+        nip44::Version::V2,
+    )?;
+    let event = EventBuilder::new(Kind::ApplicationSpecificData, content)
+        .tags([Tag::identifier(thread_key(keys, root_id))])
+        .custom_created_at(crate::clock::now())
+        .sign_with_keys(keys)?;
+    Ok(event)
+}
+
+/// The `d` tag and flags of one of our flag events. `None` for app data that isn't ours.
+pub fn from_event(accounts: &[Keys], event: &Event) -> Result<Option<(String, ThreadFlags)>> {
+    if event.kind != Kind::ApplicationSpecificData {
+        return Ok(None);
+    }
+    let Some(key) = event
+        .tags
+        .find(TagKind::d())
+        .and_then(|tag| tag.content())
+        .filter(|d| d.starts_with(D_TAG_PREFIX))
+    else {
+        return Ok(None);
+    };
+    let Some(keys) = accounts.iter().find(|k| k.public_key() == event.pubkey) else {
+        return Ok(None);
+    };
+    let json = nip44::decrypt(keys.secret_key(), &keys.public_key(), &event.content)?;
+    Ok(Some((key.to_string(), serde_json::from_str(&json)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_come_back_for_their_account_only() -> Result<()> {
+        let keys = Keys::generate();
+        let flags = ThreadFlags {
+            messages: vec!["a".into(), "b".into()],
+            read: vec!["a".into()],
+            starred: vec!["b".into()],
+            archived: Vec::new(),
+        };
+        let event = to_event(&keys, "a", &flags)?;
+        assert_eq!(
+            from_event(std::slice::from_ref(&keys), &event)?,
+            Some((thread_key(&keys, "a"), flags))
+        );
+        assert_eq!(from_event(&[Keys::generate()], &event)?, None);
+        // Another account can't tell which thread the key belongs to.
+        assert_ne!(thread_key(&keys, "a"), thread_key(&Keys::generate(), "a"));
+        Ok(())
+    }
+
+    #[test]
+    fn only_flags_since_the_last_sync_are_asked_for() {
+        let accounts = [Keys::generate().public_key()];
+        assert_eq!(filter(accounts, None).since, None);
+        assert_eq!(
+            filter(accounts, Some(1_000_000)).since,
+            Some(Timestamp::from_secs(1_000_000 - SYNC_MARGIN))
+        );
+        assert_eq!(
+            filter(accounts, Some(5)).since,
+            Some(Timestamp::from_secs(0))
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! The Focused inbox: threads with mail from people we deal with are Focused and the rest are
+//! Other, so newsletters and strangers don't bury conversations.
+//!
+//! Senders are ranked from what the database already knows about them: whether they're a
+//! contact, how often we've written to them, how often they've answered us and how much they
+//! take part in threads we've written in. Moving a
+//! message to the other tab corrects its sender, and the ranking leaves them alone after that.
+
+use anyhow::Result;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::db::Db;
+
+/// How often to rank senders again while the Focused inbox is on.
+pub const RANK_INTERVAL: Duration = Duration::from_secs(60);
+/// A sender scoring at least this is Focused.
+const FOCUSED_SCORE: u32 = 3;
+/// Past this many messages either way, more mail doesn't make a sender rank higher.
+const MESSAGES_COUNTED: u32 = 3;
+/// Taking part in our threads counts this far, so it never makes a sender Focused on its own.
+const PARTICIPATION_COUNTED: u32 = 2;
+
+/// What the ranking knows about a sender.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderStats {
+    pub contact: bool,
+    /// Messages we've sent them.
+    pub written_to: u32,
+    /// Their messages answering ours.
+    pub answers: u32,
+    /// Their messages in threads we've written in.
+    pub participation: u32,
+}
+
+impl SenderStats {
+    pub fn score(&self) -> u32 {
+        let contact = if self.contact { FOCUSED_SCORE } else { 0 };
+        contact
+            + 2 * self.written_to.min(MESSAGES_COUNTED)
+            + self.answers.min(MESSAGES_COUNTED)
+            + self.participation.min(PARTICIPATION_COUNTED)
+    }
+
+    /// Contacts are Focused straight away, anyone else once we've written to them twice, or
+    /// once and they've answered.
+    pub fn focused(&self) -> bool {
+        self.score() >= FOCUSED_SCORE
+    }
+}
+
+/// The senders the ranking puts in Focused. Only reads, so it can run off the UI thread.
+pub fn ranked_senders(db: &Db) -> Result<Vec<String>> {
+    Ok(db
+        .get_sender_stats()?
+        .into_iter()
+        .filter(|(_, stats)| stats.focused())
+        .map(|(pubkey, _)| pubkey)
+        .collect())
+}
+
+/// Ranks every sender again, returning how many are Focused by their ranking.
+pub fn rank(db: &mut Db) -> Result<usize> {
+    let focused = ranked_senders(db)?;
+    db.set_ranked_senders(&focused)?;
+    debug!("Ranked {} senders as Focused", focused.len());
+    Ok(focused.len())
+}
@@ -0,0 +1,70 @@
+//! The parts of Hoot that don't need a window: talking to relays, the encrypted database, mail
+//! events and the keys that unwrap them.
+//!
+//! The desktop app is a frontend over this crate. Anything else that wants to read or send
+//! Hoot mail, like a command line tool or tests, can use it the same way:
+//!
+//! - [`relay::RelayPool`] keeps the relay connections and subscriptions. Poll
+//!   [`relay::RelayPool::try_recv`] for raw messages and decode them with
+//!   [`relay::RelayMessage::from_json`].
+//! - [`account_manager`] loads keys from the system keyring and unwraps gift wraps with them.
+//! - [`db::Db`] stores events and answers the mailbox queries.
+//! - [`mail_event::MailMessage`] turns a message into one gift wrap per recipient.
+//! - [`threaded_event::ThreadStore`] puts messages into threads as they come in.
+//! - [`clock`] dates outgoing events by the relays' clock when the system's is off.
+//! - [`retention`] moves old mail to the Trash and empties it.
+//! - [`flag_sync`] keeps stars, read state and archiving on relays.
+//! - [`focus`] splits the inbox into Focused and Other by who the mail is from.
+//! - [`notification_rules`] decides which new mail notifies, per account and per label.
+//! - [`pow`] mines the proof of work some relays want before they take an event.
+//! - [`wallet`] pays invoices through a wallet connected with Nostr Wallet Connect.
+//! - [`bolt11`] reads what a Lightning invoice asks for and who it pays.
+//! - [`article`] fetches the long-form notes shared in messages.
+//! - [`payload`] reads and writes the data for apps that mail can carry.
+//! - [`zap`] asks lightning addresses for zap invoices and reads the receipts.
+
+pub mod account_manager;
+pub mod article;
+pub mod bolt11;
+pub mod bridge;
+pub mod calendar;
+pub mod clock;
+pub mod db;
+pub mod encryption;
+pub mod error;
+pub mod flag_sync;
+pub mod focus;
+pub mod mail_event;
+pub mod metrics;
+pub mod notification_rules;
+pub mod payload;
+pub mod pow;
+pub mod profile_metadata;
+pub mod relay;
+pub mod retention;
+pub mod threaded_event;
+pub mod verification;
+pub mod wallet;
+pub mod zap;
+
+// it's just to determine where to store files and also for keystorage paths and such
+// y'know?????
+#[cfg(debug_assertions)]
+pub const STORAGE_NAME: &'static str = "systems.chakany.hoot-dev";
+#[cfg(not(debug_assertions))]
+pub const STORAGE_NAME: &'static str = "systems.chakany.hoot";
+
+/// One row of a mailbox listing: a thread, shown by its latest message.
+// WE PROBABLY SHOULDN'T MAKE EVERYTHING A STRING, GRR!
+#[derive(Clone, Debug)]
+pub struct TableEntry {
+    pub id: String,
+    /// The start of the latest message as plain text.
+    pub snippet: String,
+    pub subject: String,
+    pub pubkey: String,
+    pub created_at: i64,
+    pub thread_count: i64,
+    /// The labels on any message of the thread, sorted. Only inbox listings load them.
+    pub labels: Vec<String>,
+}
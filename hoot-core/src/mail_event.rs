@@ -0,0 +1,648 @@
+use crate::payload::Payload;
+use crate::{clock, pow};
+use nostr::nips::{nip44, nip59};
+use nostr::{
+    Event, EventBuilder, EventId, JsonUtil, Keys, Kind, PublicKey, Tag, TagKind, TagStandard,
+    Timestamp, UnsignedEvent,
+};
+use pollster::FutureExt as _;
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::error;
+
+pub const MAIL_EVENT_KIND: u16 = 2024;
+
+/// The furthest back a gift wrap is dated, as NIP-59 suggests.
+pub const MAX_WRAP_FUZZ: Duration = Duration::from_secs(nip59::RANGE_RANDOM_TIMESTAMP_TWEAK.end);
+
+// The provided MailMessage struct
+#[derive(Debug, Clone)]
+pub struct MailMessage {
+    pub id: Option<EventId>,
+    pub created_at: Option<i64>,
+    pub author: Option<PublicKey>,
+    pub to: Vec<PublicKey>,
+    pub cc: Vec<PublicKey>,
+    pub bcc: Vec<PublicKey>,
+    /// The events that this message references, used to keep track of threads.
+    pub parent_events: Option<Vec<EventId>>,
+    pub subject: String,
+    pub content: String,
+    pub attachments: Vec<Attachment>,
+    /// Long-form notes shared with the message, see [`crate::article`].
+    pub articles: Vec<EventId>,
+    /// The part of the message being answered that this one quotes.
+    pub quote: Option<Fragment>,
+    /// Data for apps, see [`crate::payload`].
+    pub payload: Option<Payload>,
+    /// Email addresses to deliver to through the email bridge.
+    pub email_to: Vec<String>,
+    /// For mail that came in through the email bridge, the address that wrote it.
+    pub email_from: Option<String>,
+    /// Relays to recommend next to the events and pubkeys this message tags.
+    pub tag_relays: TagRelays,
+}
+
+/// Where the events and people a message tags can be found, so recipients on other clients
+/// can fetch the rest of a thread and reply.
+#[derive(Debug, Clone, Default)]
+pub struct TagRelays {
+    /// Relays we saw each event on.
+    pub events: HashMap<EventId, String>,
+    /// Relays each pubkey reads from.
+    pub pubkeys: HashMap<PublicKey, String>,
+}
+
+/// A file referenced by a message through a NIP-92 `imeta` tag, using the NIP-94 field names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub url: String,
+    /// Lowercase hex SHA-256 of the file, used to verify and deduplicate downloads.
+    pub sha256: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    pub name: Option<String>,
+}
+
+impl Attachment {
+    /// Parses the values of an `imeta` tag, e.g. `["url https://..", "x <sha256>", "m image/png"]`.
+    /// Attachments without a url or a valid hash are ignored since we can't verify them.
+    pub fn from_imeta(values: &[String]) -> Option<Self> {
+        let mut url = None;
+        let mut sha256 = None;
+        let mut mime_type = None;
+        let mut size = None;
+        let mut name = None;
+
+        for value in values {
+            let Some((key, field)) = value.split_once(' ') else {
+                continue;
+            };
+            match key {
+                "url" => url = Some(field.to_string()),
+                "x" => sha256 = Some(field.to_lowercase()),
+                "m" => mime_type = Some(field.to_string()),
+                "size" => size = field.parse().ok(),
+                "name" => name = Some(field.to_string()),
+                // Prefer an explicit name, but the alt text is better than nothing.
+                "alt" if name.is_none() => name = Some(field.to_string()),
+                _ => {}
+            }
+        }
+
+        let sha256 = sha256
+            .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))?;
+
+        Some(Self {
+            url: url?,
+            sha256,
+            mime_type,
+            size,
+            name,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let mut values = vec![format!("url {}", self.url), format!("x {}", self.sha256)];
+        if let Some(mime_type) = &self.mime_type {
+            values.push(format!("m {}", mime_type));
+        }
+        if let Some(size) = self.size {
+            values.push(format!("size {}", size));
+        }
+        if let Some(name) = &self.name {
+            values.push(format!("name {}", name));
+        }
+        Tag::custom(TagKind::custom("imeta"), values)
+    }
+
+    pub fn is_calendar(&self) -> bool {
+        self.mime_type.as_deref() == Some(crate::calendar::CALENDAR_MIME_TYPE)
+            || self.file_name().to_lowercase().ends_with(".ics")
+    }
+
+    /// File name to save the attachment under, falling back to the last url segment.
+    pub fn file_name(&self) -> String {
+        let candidate = self
+            .name
+            .clone()
+            .or_else(|| {
+                self.url
+                    .split(['?', '#'])
+                    .next()
+                    .and_then(|path| path.rsplit('/').next())
+                    .map(|segment| segment.to_string())
+            })
+            .unwrap_or_default();
+
+        let sanitized: String = candidate
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        let sanitized = sanitized.trim().trim_start_matches('.').to_string();
+
+        if sanitized.is_empty() {
+            self.sha256.clone()
+        } else {
+            sanitized
+        }
+    }
+}
+
+/// The tag a reply names the passage it quotes with: the message's id, then where the passage
+/// starts and ends in its content, counted in characters.
+pub const FRAGMENT_TAG: &str = "fragment";
+
+/// A passage of a message, quoted by a reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub event_id: EventId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Fragment {
+    /// Reads the values of a `fragment` tag, e.g. `[<event id>, "12", "80"]`.
+    pub fn from_tag(values: &[String]) -> Option<Self> {
+        let [event_id, start, end, ..] = values else {
+            return None;
+        };
+        let fragment = Self {
+            event_id: EventId::parse(event_id).ok()?,
+            start: start.parse().ok()?,
+            end: end.parse().ok()?,
+        };
+        (fragment.start < fragment.end).then_some(fragment)
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::custom(
+            TagKind::custom(FRAGMENT_TAG),
+            [
+                self.event_id.to_hex(),
+                self.start.to_string(),
+                self.end.to_string(),
+            ],
+        )
+    }
+
+    /// The passage in `content`, the quoted message's. `None` if it doesn't fit, like when the
+    /// reply quotes a different version of the message.
+    pub fn text<'a>(&self, content: &'a str) -> Option<&'a str> {
+        let bytes = self.byte_range(content)?;
+        Some(&content[bytes])
+    }
+
+    /// Where the passage is in `content`, in bytes.
+    pub fn byte_range(&self, content: &str) -> Option<std::ops::Range<usize>> {
+        if self.start >= self.end {
+            return None;
+        }
+        let mut offsets = content
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([content.len()]);
+        let start = offsets.nth(self.start)?;
+        let end = offsets.nth(self.end - self.start - 1)?;
+        Some(start..end)
+    }
+}
+
+impl MailMessage {
+    /// The rumor's builder and who to wrap it for. The `email-to` tags are only added with
+    /// `email_to`, see [`MailMessage::wrap_for_gateway`].
+    fn builder(&self, email_to: bool) -> (EventBuilder, Vec<PublicKey>) {
+        let mut pubkeys_to_send_to: Vec<PublicKey> = Vec::new();
+        let mut tags: Vec<Tag> = Vec::new();
+
+        for pubkey in &self.to {
+            match self.tag_relays.pubkeys.get(pubkey) {
+                Some(relay) => tags.push(Tag::custom(
+                    TagKind::p(),
+                    vec![pubkey.to_hex(), relay.clone()],
+                )),
+                None => tags.push(Tag::public_key(*pubkey)),
+            }
+            pubkeys_to_send_to.push(*pubkey);
+        }
+
+        for pubkey in &self.cc {
+            tags.push(Tag::custom(
+                TagKind::p(),
+                vec![pubkey.to_hex().as_str(), "cc"],
+            ));
+            pubkeys_to_send_to.push(*pubkey);
+        }
+
+        if let Some(parentEvents) = &self.parent_events {
+            // NIP-10 markers: the thread starts at the first parent and answers the last one.
+            // Messages in between aren't tagged, so every `e` tag carries a marker.
+            let marked = match parentEvents.as_slice() {
+                [] => Vec::new(),
+                [root] => vec![(root, "root")],
+                [root, .., reply] => vec![(root, "root"), (reply, "reply")],
+            };
+            for (event, marker) in marked {
+                let relay = self
+                    .tag_relays
+                    .events
+                    .get(event)
+                    .cloned()
+                    .unwrap_or_default();
+                tags.push(Tag::custom(
+                    TagKind::e(),
+                    vec![event.to_hex(), relay, marker.to_string()],
+                ));
+            }
+        }
+
+        tags.push(Tag::from_standardized(TagStandard::Subject(
+            self.subject.clone(),
+        )));
+
+        for attachment in &self.attachments {
+            tags.push(attachment.to_tag());
+        }
+
+        for article in &self.articles {
+            let relay = self.tag_relays.events.get(article);
+            tags.push(crate::article::to_tag(article, relay.map(String::as_str)));
+        }
+
+        if let Some(quote) = &self.quote {
+            tags.push(quote.to_tag());
+        }
+
+        if let Some(payload) = &self.payload {
+            tags.push(payload.to_tag());
+        }
+
+        if email_to {
+            for address in &self.email_to {
+                tags.push(crate::bridge::email_to_tag(address));
+            }
+        }
+
+        let builder = EventBuilder::new(Kind::Custom(MAIL_EVENT_KIND), &self.content).tags(tags);
+        (builder, pubkeys_to_send_to)
+    }
+
+    /// The unsigned event the author keeps, in Sent and in their copy to self, and the one the
+    /// email gateway gets. Needs `author` and `created_at`, which `to_events` fills in.
+    pub fn to_rumor(&self) -> Option<UnsignedEvent> {
+        self.rumor(true)
+    }
+
+    fn rumor(&self, email_to: bool) -> Option<UnsignedEvent> {
+        let created_at = Timestamp::from(self.created_at? as u64);
+        let mut rumor = self
+            .builder(email_to)
+            .0
+            .custom_created_at(created_at)
+            .build(self.author?);
+        rumor.ensure_id();
+        Some(rumor)
+    }
+
+    /// Gift wraps the message for each recipient, each dated a random time up to `fuzz` back.
+    /// All copies carry the same rumor, whose id ends up in `id`, except when the message
+    /// also goes to email addresses: nostr recipients shouldn't learn those, so they get it
+    /// without the `email-to` tags, under an id of its own. Give the gateway its copy with
+    /// [`MailMessage::wrap_for_gateway`].
+    pub fn to_events(&mut self, sending_keys: &Keys, fuzz: Duration) -> HashMap<PublicKey, Event> {
+        self.author = Some(sending_keys.public_key());
+        self.created_at
+            .get_or_insert(crate::clock::now().as_u64() as i64);
+        let pubkeys_to_send_to = self.builder(false).1;
+        let Some(rumor) = self.rumor(false) else {
+            return HashMap::new();
+        };
+        self.id = self.to_rumor().and_then(|rumor| rumor.id);
+
+        let mut event_list: HashMap<PublicKey, Event> = HashMap::new();
+        for pubkey in pubkeys_to_send_to {
+            match gift_wrap(sending_keys, &pubkey, rumor.clone(), fuzz) {
+                Ok(wrapped_event) => {
+                    event_list.insert(pubkey, wrapped_event);
+                }
+                Err(e) => error!("Failed to gift wrap a message for {}: {}", pubkey, e),
+            }
+        }
+
+        event_list
+    }
+
+    /// The message wrapped for the email gateway, with the `email-to` tags it delivers by.
+    /// Call after [`MailMessage::to_events`], and send it in place of the gateway's copy
+    /// from there.
+    pub fn wrap_for_gateway(
+        &self,
+        sending_keys: &Keys,
+        gateway: &PublicKey,
+        fuzz: Duration,
+    ) -> Option<Event> {
+        let rumor = self.to_rumor()?;
+        match gift_wrap(sending_keys, gateway, rumor, fuzz) {
+            Ok(wrap) => Some(wrap),
+            Err(e) => {
+                error!("Failed to gift wrap a message for the email gateway: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The message wrapped for its own author, so the author's other devices see it in Sent
+    /// (the NIP-17 way). Call after [`MailMessage::to_events`], whose rumor it carries.
+    pub fn copy_to_self(&self, sending_keys: &Keys, fuzz: Duration) -> Option<Event> {
+        let rumor = self.to_rumor()?;
+        match gift_wrap(sending_keys, &sending_keys.public_key(), rumor, fuzz) {
+            Ok(wrap) => Some(wrap),
+            Err(e) => {
+                error!(
+                    "Failed to gift wrap a copy of a message for ourselves: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Seals `rumor` and gift wraps it for `receiver` (NIP-59). The wrap is dated a random time
+/// up to `fuzz` back, so relays can't tell when it was sent. The rumor keeps its own date,
+/// which is what threads are ordered by.
+pub fn gift_wrap(
+    sender: &Keys,
+    receiver: &PublicKey,
+    rumor: UnsignedEvent,
+    fuzz: Duration,
+) -> anyhow::Result<Event> {
+    gift_wrap_with_pow(sender, receiver, rumor, fuzz, 0)
+}
+
+/// Like [`gift_wrap`], with `difficulty` bits of proof of work on the wrap for relays that
+/// want it (NIP-13).
+pub fn gift_wrap_with_pow(
+    sender: &Keys,
+    receiver: &PublicKey,
+    rumor: UnsignedEvent,
+    fuzz: Duration,
+    difficulty: u8,
+) -> anyhow::Result<Event> {
+    let seal = nip59::make_seal(sender, receiver, rumor)
+        .block_on()?
+        .sign_with_keys(sender)?;
+    let wrapper = Keys::generate();
+    let content = nip44::encrypt(
+        wrapper.secret_key(),
+        receiver,
+        seal.as_json(),
+        nip44::Version::V2,
+    )?;
+    let backdate = rand::thread_rng().gen_range(0..=fuzz.min(MAX_WRAP_FUZZ).as_secs());
+    // The wrap's date is the only one relays see, so it goes by their clock rather than ours.
+    let created_at = Timestamp::from(clock::now().as_u64().saturating_sub(backdate));
+    let tags = vec![Tag::public_key(*receiver)];
+    let mut builder = EventBuilder::new(Kind::GiftWrap, &content)
+        .tags(tags.clone())
+        .custom_created_at(created_at);
+    if difficulty > 0 {
+        let nonce = pow::mine(
+            &wrapper.public_key(),
+            created_at,
+            Kind::GiftWrap,
+            &tags,
+            &content,
+            difficulty,
+        );
+        builder = builder.tag(nonce);
+    }
+    let wrap = builder.sign_with_keys(&wrapper)?;
+    Ok(wrap)
+}
+
+/// How many characters of a message the message list shows.
+pub const SNIPPET_LEN: usize = 120;
+
+/// The start of a message body as one line of plain text, with the markdown formatting taken
+/// out, for previews in the message list.
+pub fn snippet(content: &str) -> String {
+    let mut text = String::new();
+    for line in content.lines() {
+        let line = line.trim_start_matches(|c: char| c == '>' || c.is_whitespace());
+        let line = line.trim_start_matches('#').trim_start();
+        if line.starts_with("```") || is_rule(line) {
+            continue;
+        }
+        push_inline(&mut text, strip_list_marker(line));
+        text.push(' ');
+    }
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= SNIPPET_LEN {
+        return text;
+    }
+    let cut: String = text.chars().take(SNIPPET_LEN).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// A `---` or `***` line.
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && ['-', '*', '_'].contains(&marks[0]) && marks.iter().all(|c| *c == marks[0])
+}
+
+fn strip_list_marker(line: &str) -> &str {
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return rest;
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        if let Some(rest) = line[digits..]
+            .strip_prefix(". ")
+            .or_else(|| line[digits..].strip_prefix(") "))
+        {
+            return rest;
+        }
+    }
+    line
+}
+
+/// Copies `line` without emphasis marks and code ticks, keeping only the text of links and
+/// images.
+fn push_inline(out: &mut String, line: &str) {
+    let mut chars = line.chars().peekable();
+    let mut prev = ' ';
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '`' | '~' | '[' => {}
+            // Underscores inside words, like in snake_case, are kept.
+            '_' if !prev.is_alphanumeric()
+                || !chars.peek().is_some_and(|next| next.is_alphanumeric()) => {}
+            '!' if chars.peek() == Some(&'[') => {}
+            ']' => {
+                if chars.peek() == Some(&'(') {
+                    for c in chars.by_ref() {
+                        if c == ')' {
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+        prev = c;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_carry_relay_hints_and_thread_markers() {
+        let sender = Keys::generate();
+        let recipient = Keys::generate().public_key();
+        let other = Keys::generate().public_key();
+        let root = EventId::all_zeros();
+        let middle = EventId::from_slice(&[1; 32]).unwrap();
+        let parent = EventId::from_slice(&[2; 32]).unwrap();
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: vec![recipient, other],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: Some(vec![root, middle, parent]),
+            subject: "Re: Plans".to_string(),
+            content: String::new(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: TagRelays {
+                events: HashMap::from([(root, "wss://root.example.com".to_string())]),
+                pubkeys: HashMap::from([(recipient, "wss://inbox.example.com".to_string())]),
+            },
+        };
+        let wraps = message.to_events(&sender, MAX_WRAP_FUZZ);
+        let now = Timestamp::now().as_u64();
+        for wrap in wraps.values() {
+            let created_at = wrap.created_at.as_u64();
+            assert!(created_at <= now && created_at + MAX_WRAP_FUZZ.as_secs() >= now);
+        }
+        let rumor = message.to_rumor().unwrap();
+        let tags: Vec<Vec<String>> = rumor
+            .tags
+            .iter()
+            .map(|tag| tag.as_slice().to_vec())
+            .filter(|tag| tag[0] == "e" || tag[0] == "p")
+            .collect();
+
+        let tag = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                tag(&["p", &recipient.to_hex(), "wss://inbox.example.com"]),
+                tag(&["p", &other.to_hex()]),
+                tag(&["e", &root.to_hex(), "wss://root.example.com", "root"]),
+                tag(&["e", &parent.to_hex(), "", "reply"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_gateway_sees_email_addresses() {
+        use nostr::nips::nip59::UnwrappedGift;
+
+        let sender = Keys::generate();
+        let friend = Keys::generate();
+        let gateway = Keys::generate();
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: vec![friend.public_key(), gateway.public_key()],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: "Plans".to_string(),
+            content: String::new(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: vec!["someone@example.com".to_string()],
+            email_from: None,
+            tag_relays: TagRelays::default(),
+        };
+        let wraps = message.to_events(&sender, Duration::ZERO);
+        let for_gateway = message
+            .wrap_for_gateway(&sender, &gateway.public_key(), Duration::ZERO)
+            .unwrap();
+        let email_to = |keys: &Keys, wrap: &Event| -> Vec<String> {
+            let rumor = UnwrappedGift::from_gift_wrap(keys, wrap)
+                .block_on()
+                .unwrap()
+                .rumor;
+            rumor
+                .tags
+                .iter()
+                .filter(|tag| tag.as_slice()[0] == crate::bridge::EMAIL_TO_TAG)
+                .map(|tag| tag.as_slice()[1].clone())
+                .collect()
+        };
+
+        assert!(email_to(&friend, &wraps[&friend.public_key()]).is_empty());
+        assert_eq!(
+            email_to(&gateway, &for_gateway),
+            vec!["someone@example.com"]
+        );
+        // The author keeps the addresses too.
+        assert_eq!(message.to_rumor().unwrap().id, message.id);
+    }
+
+    #[test]
+    fn fragments_point_at_characters() {
+        let event_id = EventId::all_zeros();
+        let tag = Fragment {
+            event_id,
+            start: 6,
+            end: 11,
+        }
+        .to_tag();
+        let fragment = Fragment::from_tag(&tag.as_slice()[1..]).unwrap();
+        assert_eq!(fragment.end, 11);
+        // Offsets count characters, so text before the passage can be anything.
+        assert_eq!(fragment.text("héllo wörld!"), Some("wörld"));
+        assert_eq!(fragment.text("short"), None);
+
+        let backwards = [event_id.to_hex(), "5".to_string(), "2".to_string()];
+        assert_eq!(Fragment::from_tag(&backwards), None);
+    }
+
+    #[test]
+    fn snippets_are_plain_text() {
+        let content = "# Plans\n\nHi **Alice**, see the [agenda](https://example.com/a) and \
+                       ![map](map.png).\n\n- bring `snacks`\n1. keep my_notes\n\n---\n\
+                       > _quoted_ text";
+        assert_eq!(
+            snippet(content),
+            "Plans Hi Alice, see the agenda and map. bring snacks keep my_notes quoted text"
+        );
+
+        let long = snippet(&"word ".repeat(100));
+        assert_eq!(long.chars().count(), SNIPPET_LEN);
+        assert!(long.ends_with("word…"));
+    }
+}
@@ -0,0 +1,193 @@
+//! Local, opt-in performance metrics.
+//!
+//! Nothing here ever leaves the machine: values are only shown on the diagnostics tab and
+//! written out when the user exports them. Recording is off until the user turns it on, and
+//! while it is off every call is a single atomic load.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const METRICS_ENABLED_KEY: &str = "metrics_enabled";
+
+// Metric names, kept in one place so the diagnostics page and exports stay consistent.
+pub const EVENTS_RECEIVED: &str = "events.received";
+pub const EVENTS_PROCESSED: &str = "events.processed";
+pub const EVENTS_REJECTED: &str = "events.rejected";
+pub const GIFT_WRAP_UNWRAP: &str = "gift_wrap.unwrap_ms";
+pub const FRAME_TIME: &str = "frame.time_ms";
+pub const DB_QUERY: &str = "db.query_ms";
+pub const INGEST_PREPARE: &str = "ingest.prepare_ms";
+pub const INGEST_STORE: &str = "ingest.store_ms";
+pub const INGEST_BACKPRESSURE: &str = "ingest.backpressure";
+
+/// Upper bounds (in milliseconds) of the histogram buckets. Anything slower lands in the last
+/// bucket.
+const BUCKET_BOUNDS_MS: [f64; 10] = [1.0, 2.0, 5.0, 10.0, 16.0, 33.0, 50.0, 100.0, 250.0, 1000.0];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    /// One count per entry in `BUCKET_BOUNDS_MS`, plus an overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum_ms: 0.0,
+            min_ms: f64::MAX,
+            max_ms: 0.0,
+            buckets: vec![0; BUCKET_BOUNDS_MS.len() + 1],
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, value_ms: f64) {
+        self.count += 1;
+        self.sum_ms += value_ms;
+        self.min_ms = self.min_ms.min(value_ms);
+        self.max_ms = self.max_ms.max(value_ms);
+
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| value_ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum_ms / self.count as f64
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Snapshot {
+    pub bucket_bounds_ms: Vec<f64>,
+    pub counters: BTreeMap<&'static str, u64>,
+    pub histograms: BTreeMap<&'static str, Histogram>,
+}
+
+struct Registry {
+    counters: BTreeMap<&'static str, u64>,
+    histograms: BTreeMap<&'static str, Histogram>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            counters: BTreeMap::new(),
+            histograms: BTreeMap::new(),
+        }
+    }
+}
+
+fn with_registry(f: impl FnOnce(&mut Registry)) {
+    match REGISTRY.lock() {
+        Ok(mut registry) => f(&mut registry),
+        Err(poisoned) => f(&mut poisoned.into_inner()),
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn increment(name: &'static str) {
+    if !is_enabled() {
+        return;
+    }
+    with_registry(|registry| *registry.counters.entry(name).or_insert(0) += 1);
+}
+
+pub fn observe(name: &'static str, duration: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    let value_ms = duration.as_secs_f64() * 1000.0;
+    with_registry(|registry| {
+        registry
+            .histograms
+            .entry(name)
+            .or_default()
+            .observe(value_ms)
+    });
+}
+
+/// Records the time until it is dropped into the histogram `name`.
+pub struct Timer {
+    name: &'static str,
+    started: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(started) = self.started {
+            observe(self.name, started.elapsed());
+        }
+    }
+}
+
+pub fn start_timer(name: &'static str) -> Timer {
+    Timer {
+        name,
+        started: is_enabled().then(Instant::now),
+    }
+}
+
+pub fn snapshot() -> Snapshot {
+    let mut snapshot = Snapshot {
+        bucket_bounds_ms: BUCKET_BOUNDS_MS.to_vec(),
+        ..Default::default()
+    };
+    with_registry(|registry| {
+        snapshot.counters = registry.counters.clone();
+        snapshot.histograms = registry.histograms.clone();
+    });
+    snapshot
+}
+
+pub fn reset() {
+    with_registry(|registry| {
+        registry.counters.clear();
+        registry.histograms.clear();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_and_stats() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.5);
+        histogram.observe(12.0);
+        histogram.observe(5000.0);
+
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.min_ms, 0.5);
+        assert_eq!(histogram.max_ms, 5000.0);
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[4], 1);
+        assert_eq!(histogram.buckets[BUCKET_BOUNDS_MS.len()], 1);
+    }
+}
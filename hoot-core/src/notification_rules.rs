@@ -0,0 +1,232 @@
+//! Rules for when mail notifies, set per account and per label: always, only from contacts or
+//! never, with quiet hours during which notifications are kept but don't count as unread.
+//!
+//! A label rule says more about a message than the account it came to, so it wins when a
+//! thread has a label with a rule, and the strictest one wins when it has several. Every
+//! message a rule lets through notifies. Mail no rule covers notifies as it always has: when
+//! it's from someone new or mentions us.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::db::Db;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifyMode {
+    #[default]
+    Always,
+    /// Only mail from contacts notifies.
+    ContactsOnly,
+    Never,
+}
+
+impl fmt::Display for NotifyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyMode::Always => write!(f, "Always"),
+            NotifyMode::ContactsOnly => write!(f, "Only contacts"),
+            NotifyMode::Never => write!(f, "Never"),
+        }
+    }
+}
+
+impl NotifyMode {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            NotifyMode::Always => "always",
+            NotifyMode::ContactsOnly => "contacts",
+            NotifyMode::Never => "never",
+        }
+    }
+
+    pub(crate) fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "always" => Some(NotifyMode::Always),
+            "contacts" => Some(NotifyMode::ContactsOnly),
+            "never" => Some(NotifyMode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Local hours from `start` until `end`, past midnight if `end` comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: u8,
+    pub end: u8,
+}
+
+impl Default for QuietHours {
+    fn default() -> Self {
+        Self { start: 22, end: 7 }
+    }
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u8) -> bool {
+        if self.start <= self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NotificationRule {
+    pub mode: NotifyMode,
+    pub quiet: Option<QuietHours>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleScope {
+    /// Mail to the account with this pubkey.
+    Account(String),
+    /// Mail in a thread with this label.
+    Label(String),
+}
+
+/// What to do about a message, from the least strict to the most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Notify,
+    /// Keep the notification, already read.
+    Quiet,
+    Skip,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NotificationRules {
+    accounts: HashMap<String, NotificationRule>,
+    labels: HashMap<String, NotificationRule>,
+}
+
+impl NotificationRules {
+    pub fn load(db: &Db) -> Result<Self> {
+        let mut rules = Self::default();
+        for (scope, rule) in db.get_notification_rules()? {
+            match scope {
+                RuleScope::Account(pubkey) => rules.accounts.insert(pubkey, rule),
+                RuleScope::Label(label) => rules.labels.insert(label, rule),
+            };
+        }
+        Ok(rules)
+    }
+
+    pub fn get(&self, scope: &RuleScope) -> Option<&NotificationRule> {
+        match scope {
+            RuleScope::Account(pubkey) => self.accounts.get(pubkey),
+            RuleScope::Label(label) => self.labels.get(label),
+        }
+    }
+
+    /// Whether any label has a rule, so messages' labels are worth looking up.
+    pub fn has_label_rules(&self) -> bool {
+        !self.labels.is_empty()
+    }
+
+    /// Sets the rule for `scope`, or takes it away with `None`.
+    pub fn set(&mut self, db: &Db, scope: RuleScope, rule: Option<NotificationRule>) -> Result<()> {
+        db.set_notification_rule(&scope, rule.as_ref())?;
+        match (scope, rule) {
+            (RuleScope::Account(pubkey), Some(rule)) => {
+                self.accounts.insert(pubkey, rule);
+            }
+            (RuleScope::Account(pubkey), None) => {
+                self.accounts.remove(&pubkey);
+            }
+            (RuleScope::Label(label), Some(rule)) => {
+                self.labels.insert(label, rule);
+            }
+            (RuleScope::Label(label), None) => {
+                self.labels.remove(&label);
+            }
+        }
+        Ok(())
+    }
+
+    /// What to do about mail to `account` in a thread labeled `labels`, at local `hour`.
+    /// `None` when no rule covers it.
+    pub fn verdict(
+        &self,
+        account: Option<&str>,
+        labels: &[String],
+        from_contact: bool,
+        hour: u8,
+    ) -> Option<Verdict> {
+        let for_labels = labels
+            .iter()
+            .filter_map(|label| self.labels.get(label))
+            .map(|rule| rule.verdict(from_contact, hour))
+            .max();
+        for_labels.or_else(|| {
+            let rule = self.accounts.get(account?)?;
+            Some(rule.verdict(from_contact, hour))
+        })
+    }
+}
+
+impl NotificationRule {
+    fn verdict(&self, from_contact: bool, hour: u8) -> Verdict {
+        match self.mode {
+            NotifyMode::Never => Verdict::Skip,
+            NotifyMode::ContactsOnly if !from_contact => Verdict::Skip,
+            _ if self.quiet.is_some_and(|quiet| quiet.contains(hour)) => Verdict::Quiet,
+            _ => Verdict::Notify,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_rules_win_over_account_rules() -> Result<()> {
+        let db = Db::new_in_memory()?;
+        let mut rules = NotificationRules::default();
+        let work = "a".repeat(64);
+        let quiet_work = NotificationRule {
+            mode: NotifyMode::ContactsOnly,
+            quiet: Some(QuietHours::default()),
+        };
+        rules.set(&db, RuleScope::Account(work.clone()), Some(quiet_work))?;
+        let never = NotificationRule {
+            mode: NotifyMode::Never,
+            quiet: None,
+        };
+        rules.set(&db, RuleScope::Label("Receipts".into()), Some(never))?;
+
+        let mut rules = NotificationRules::load(&db)?;
+        assert_eq!(
+            rules.get(&RuleScope::Account(work.clone())),
+            Some(&quiet_work)
+        );
+        let work = Some(work.as_str());
+        assert_eq!(rules.verdict(work, &[], true, 12), Some(Verdict::Notify));
+        assert_eq!(rules.verdict(work, &[], true, 23), Some(Verdict::Quiet));
+        assert_eq!(rules.verdict(work, &[], true, 3), Some(Verdict::Quiet));
+        assert_eq!(rules.verdict(work, &[], false, 12), Some(Verdict::Skip));
+        assert_eq!(
+            rules.verdict(None, &["Receipts".into()], true, 12),
+            Some(Verdict::Skip)
+        );
+        assert_eq!(rules.verdict(None, &[], false, 12), None);
+
+        // The strictest of a thread's labels wins, whatever their order.
+        let always = NotificationRule::default();
+        rules.set(&db, RuleScope::Label("Alerts".into()), Some(always))?;
+        let labels = ["Alerts".to_string(), "Receipts".to_string()];
+        assert_eq!(rules.verdict(work, &labels, true, 12), Some(Verdict::Skip));
+        assert_eq!(
+            rules.verdict(work, &labels[..1], false, 12),
+            Some(Verdict::Notify)
+        );
+
+        rules.set(&db, RuleScope::Label("Alerts".into()), None)?;
+        rules.set(&db, RuleScope::Label("Receipts".into()), None)?;
+        assert!(!NotificationRules::load(&db)?.has_label_rules());
+        Ok(())
+    }
+}
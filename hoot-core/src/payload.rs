@@ -0,0 +1,231 @@
+//! Data for apps riding along with mail: an invoice, a receipt, anything a program sending mail
+//! wants the program reading it to understand. The body still says it all for people and for
+//! clients that don't know about payloads, and the payload lets Hoot show it as a card.
+//!
+//! A payload is a `payload` tag with the schema the data follows and the data as JSON, like
+//! `["payload", "invoice", "{\"number\":\"42\",…}"]`. Hoot knows the [`INVOICE`] and
+//! [`RECEIPT`] schemas, and shows others as the data they hold.
+
+use crate::bolt11::Invoice;
+use anyhow::{bail, Context, Result};
+use nostr::{Tag, TagKind};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub const PAYLOAD_TAG: &str = "payload";
+pub const INVOICE: &str = "invoice";
+pub const RECEIPT: &str = "receipt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payload {
+    pub schema: String,
+    /// Always a JSON object.
+    pub data: Value,
+}
+
+impl Payload {
+    /// `json` as data following `schema`. Data for a schema we know has to fit it.
+    pub fn new(schema: &str, json: &str) -> Result<Self> {
+        let schema = schema.trim();
+        if schema.is_empty() {
+            bail!("Name the schema the data follows");
+        }
+        let data: Value = serde_json::from_str(json).context("Not valid JSON")?;
+        if !data.is_object() {
+            bail!("The data has to be a JSON object");
+        }
+        if matches!(schema, INVOICE | RECEIPT) {
+            serde_json::from_value::<Bill>(data.clone())
+                .with_context(|| format!("Not a valid {}", schema))?;
+        }
+        Ok(Self {
+            schema: schema.to_string(),
+            data,
+        })
+    }
+
+    /// Reads the values of a `payload` tag, e.g. `["invoice", "{…}"]`.
+    pub fn from_tag(values: &[String]) -> Option<Self> {
+        let [schema, json, ..] = values else {
+            return None;
+        };
+        let data: Value = serde_json::from_str(json).ok()?;
+        data.is_object().then(|| Self {
+            schema: schema.clone(),
+            data,
+        })
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::custom(
+            TagKind::custom(PAYLOAD_TAG),
+            [self.schema.clone(), self.data.to_string()],
+        )
+    }
+
+    /// The invoice or receipt the payload holds, for those schemas.
+    pub fn bill(&self) -> Option<Bill> {
+        if !matches!(self.schema.as_str(), INVOICE | RECEIPT) {
+            return None;
+        }
+        serde_json::from_value(self.data.clone()).ok()
+    }
+}
+
+/// Data to start a payload for `schema` from, as pretty JSON.
+pub fn example(schema: &str) -> String {
+    let data = match schema {
+        INVOICE => json!({
+            "number": "2024-001",
+            "issuer": "Example Shop",
+            "currency": "USD",
+            "items": [{ "description": "Widget", "quantity": 2, "price": 4.5 }],
+            "due": "2024-12-31",
+            "lightning": "",
+        }),
+        RECEIPT => json!({
+            "number": "2024-001",
+            "issuer": "Example Shop",
+            "currency": "sat",
+            "items": [{ "description": "Coffee", "price": 5000 }],
+            "paid": "2024-12-01",
+        }),
+        _ => json!({}),
+    };
+    serde_json::to_string_pretty(&data).unwrap_or_default()
+}
+
+/// An invoice or a receipt. Everything but the items is optional.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Bill {
+    pub number: Option<String>,
+    /// Who it's from, like a shop.
+    pub issuer: Option<String>,
+    /// Like `USD`, or `sat`.
+    pub currency: Option<String>,
+    pub items: Vec<LineItem>,
+    /// What's due or was paid, when it isn't just the items added up.
+    pub total: Option<f64>,
+    /// When an invoice has to be paid by, as the issuer wrote it.
+    pub due: Option<String>,
+    /// When a receipt's payment was made, as the issuer wrote it.
+    pub paid: Option<String>,
+    /// A Lightning invoice that pays it.
+    pub lightning: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LineItem {
+    pub description: String,
+    #[serde(default = "one")]
+    pub quantity: f64,
+    /// For one.
+    pub price: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+impl LineItem {
+    pub fn amount(&self) -> f64 {
+        self.quantity * self.price
+    }
+}
+
+impl Bill {
+    pub fn total(&self) -> f64 {
+        self.total
+            .unwrap_or_else(|| self.items.iter().map(LineItem::amount).sum())
+    }
+
+    fn in_sats(&self) -> bool {
+        self.currency
+            .as_deref()
+            .map(str::trim)
+            .is_some_and(|c| c.eq_ignore_ascii_case("sat") || c.eq_ignore_ascii_case("sats"))
+    }
+
+    /// `amount` in the bill's currency, whole for sats.
+    pub fn format_amount(&self, amount: f64) -> String {
+        match self.currency.as_deref().map(str::trim) {
+            Some(_) if self.in_sats() => format!("{} sats", amount.round() as i64),
+            Some(currency) if !currency.is_empty() => {
+                format!("{:.2} {}", amount, currency.to_uppercase())
+            }
+            _ => format!("{:.2}", amount),
+        }
+    }
+
+    /// What paying `invoice` would cost in millisatoshis, if it asks for what the bill says is
+    /// due. The wallet pays whatever the invoice says, so only bills in sats can be paid from
+    /// Hoot: a total in another currency can't be checked against it.
+    pub fn check_invoice(&self, invoice: &Invoice) -> Result<u64> {
+        if !self.in_sats() {
+            bail!("The bill isn't in sats, so Hoot can't check the invoice asks for its total");
+        }
+        let Some(msats) = invoice.msats else {
+            bail!("The invoice doesn't say how much to pay");
+        };
+        let total = (self.total() * 1000.0).round();
+        if total < 0.0 || msats as f64 != total {
+            bail!(
+                "The invoice asks for {} sats but the bill says {}",
+                msats as f64 / 1000.0,
+                self.format_amount(self.total())
+            );
+        }
+        Ok(msats)
+    }
+
+    /// The Lightning invoice to pay, if there's one.
+    pub fn lightning(&self) -> Option<&str> {
+        self.lightning
+            .as_deref()
+            .map(str::trim)
+            .filter(|invoice| !invoice.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_round_trip_through_tags() -> Result<()> {
+        let payload = Payload::new(INVOICE, &example(INVOICE))?;
+        let tag = payload.to_tag();
+        let values = tag.as_slice();
+        assert_eq!(values[0], PAYLOAD_TAG);
+        assert_eq!(Payload::from_tag(&values[1..]), Some(payload.clone()));
+
+        let bill = payload.bill().context("an invoice")?;
+        assert_eq!(bill.total(), 9.0);
+        assert_eq!(bill.format_amount(bill.total()), "9.00 USD");
+        assert_eq!(bill.lightning(), None);
+        let receipt = Payload::new(RECEIPT, &example(RECEIPT))?.bill();
+        assert_eq!(
+            receipt.map(|bill| bill.format_amount(bill.total())),
+            Some("5000 sats".to_string())
+        );
+
+        let mut bill = Payload::new(RECEIPT, &example(RECEIPT))?
+            .bill()
+            .context("a receipt")?;
+        let secret = secp256k1::SecretKey::from_slice(&[5; 32])?;
+        let invoice = |hrp: &str| Invoice::decode(&crate::bolt11::sign(hrp, "Coffee", &secret));
+        assert_eq!(bill.check_invoice(&invoice("lnbc50u")?)?, 5_000_000);
+        assert!(bill.check_invoice(&invoice("lnbc500u")?).is_err());
+        assert!(bill.check_invoice(&invoice("lnbc")?).is_err());
+        bill.currency = Some("USD".to_string());
+        assert!(bill.check_invoice(&invoice("lnbc50u")?).is_err());
+
+        assert!(Payload::new(INVOICE, r#"{"items": [{"price": "free"}]}"#).is_err());
+        assert!(Payload::new("order", "[1, 2]").is_err());
+        assert!(Payload::new("", "{}").is_err());
+        let order = Payload::new("order", r#"{"id": 7}"#)?;
+        assert_eq!(order.bill(), None);
+        Ok(())
+    }
+}
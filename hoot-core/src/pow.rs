@@ -0,0 +1,104 @@
+//! Proof of work for events (NIP-13), which some relays ask for before they take one.
+//!
+//! The work is a `nonce` tag changed until the event's id starts with enough zero bits. Only
+//! the nonce changes between tries, so the rest of what the id is hashed from is serialized
+//! once and the tries only hash.
+
+use nostr::hashes::{sha256, Hash};
+use nostr::{EventId, Kind, PublicKey, Tag, Timestamp};
+
+/// How many leading zero bits `id` has.
+pub fn difficulty(id: &EventId) -> u8 {
+    leading_zero_bits(id.as_bytes())
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u8 {
+    let mut bits = 0;
+    for byte in bytes {
+        if *byte != 0 {
+            return bits + byte.leading_zeros() as u8;
+        }
+        bits += 8;
+    }
+    bits
+}
+
+/// Finds the `nonce` tag that, added after `tags`, gives the event at least `difficulty` bits
+/// of work. Each bit doubles how long this takes on average.
+pub fn mine(
+    pubkey: &PublicKey,
+    created_at: Timestamp,
+    kind: Kind,
+    tags: &[Tag],
+    content: &str,
+    difficulty: u8,
+) -> Tag {
+    // What the id is the hash of (NIP-01), split around the nonce.
+    let mut tags = serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string());
+    tags.pop();
+    if tags.len() > 1 {
+        tags.push(',');
+    }
+    let before = format!(
+        "[0,\"{}\",{},{},{}[\"nonce\",\"",
+        pubkey.to_hex(),
+        created_at.as_u64(),
+        kind.as_u16(),
+        tags
+    );
+    let after = format!(
+        "\",\"{}\"]],{}]",
+        difficulty,
+        serde_json::Value::from(content)
+    );
+
+    let mut serialized = before.clone();
+    for nonce in 0u64.. {
+        serialized.truncate(before.len());
+        serialized.push_str(&nonce.to_string());
+        serialized.push_str(&after);
+        let hash = sha256::Hash::hash(serialized.as_bytes());
+        if leading_zero_bits(hash.as_byte_array()) >= difficulty {
+            return nonce_tag(nonce, difficulty);
+        }
+    }
+    unreachable!("every nonce was tried")
+}
+
+fn nonce_tag(nonce: u64, difficulty: u8) -> Tag {
+    let nonce = nonce.to_string();
+    let difficulty = difficulty.to_string();
+    Tag::parse(&["nonce", nonce.as_str(), difficulty.as_str()]).expect("a nonce tag parses")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventBuilder, Keys};
+
+    #[test]
+    fn mined_events_have_the_work_asked_for() -> anyhow::Result<()> {
+        assert_eq!(leading_zero_bits(&[0, 0x1f, 0xff]), 11);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+
+        let keys = Keys::generate();
+        let created_at = Timestamp::from(1_700_000_000);
+        let tags = vec![Tag::public_key(keys.public_key())];
+        let content = "an \"odd\" body\n";
+        let nonce = mine(
+            &keys.public_key(),
+            created_at,
+            Kind::GiftWrap,
+            &tags,
+            content,
+            10,
+        );
+        let event = EventBuilder::new(Kind::GiftWrap, content)
+            .tags(tags)
+            .tag(nonce)
+            .custom_created_at(created_at)
+            .sign_with_keys(&keys)?;
+        assert!(difficulty(&event.id) >= 10);
+        Ok(())
+    }
+}
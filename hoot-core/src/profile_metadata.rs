@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// The parts of a profile (kind 0) we show, as cached in the database.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ProfileMetadata {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub picture: Option<String>,
+    /// The lightning address zaps go to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lud16: Option<String>,
+}
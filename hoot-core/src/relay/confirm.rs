@@ -0,0 +1,127 @@
+//! Checks that relays which took an event we sent really kept it, by asking them for it back
+//! by id. Some relays answer OK and drop the event anyway, e.g. when a spam filter runs after
+//! the write or they prune what they store, so we wait a while before asking.
+//!
+//! Relays serving gift wraps (NIP-17) often only hand them to their recipient after NIP-42
+//! auth, which we don't do. When they refuse us like that, they haven't said anything about
+//! the event, and nobody is told it was dropped.
+
+use nostr::EventId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long after a send to ask for the event back, so relays dropping it after the write
+/// are caught too.
+pub const CONFIRM_DELAY: Duration = Duration::from_secs(60);
+/// How long relays get to give an event back before we stop asking.
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Whether one relay gave the event back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stored {
+    /// Asked, no answer yet.
+    Asking,
+    Yes,
+    /// The relay said it had nothing, refused the REQ or didn't answer in time.
+    No,
+    /// The relay won't tell us without auth, or won't serve us at all.
+    Unknown,
+}
+
+/// Asking the relays that accepted an event for it back.
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    pub subscription_id: String,
+    /// The event and the copies of it relays got instead.
+    pub ids: Vec<EventId>,
+    pub relays: HashMap<String, Stored>,
+    /// When to ask.
+    pub ask_at: Instant,
+    /// When we asked, `None` until `ask_at`.
+    pub started: Option<Instant>,
+}
+
+impl Confirmation {
+    pub fn is_done(&self) -> bool {
+        self.started.is_some() && self.relays.values().all(|stored| *stored != Stored::Asking)
+    }
+
+    /// When this next needs looking at: asking, or giving up on the relays that haven't
+    /// answered.
+    pub fn deadline(&self) -> Instant {
+        self.started
+            .map_or(self.ask_at, |started| started + CONFIRM_TIMEOUT)
+    }
+
+    /// How many relays gave the event back, or `None` when none did and some wouldn't say.
+    pub fn confirmed(&self) -> Option<usize> {
+        let count = |state| {
+            self.relays
+                .values()
+                .filter(|stored| **stored == state)
+                .count()
+        };
+        let confirmed = count(Stored::Yes);
+        (confirmed > 0 || count(Stored::Unknown) == 0).then_some(confirmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::relay::mock::{pump_until, MockRelay};
+    use crate::relay::RelayPool;
+    use nostr::{EventBuilder, Keys, Kind};
+    use std::time::Duration;
+
+    #[test]
+    fn finds_relays_that_dropped_an_event() -> anyhow::Result<()> {
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&Keys::generate())?;
+        let kept = MockRelay::new();
+        let dropped = MockRelay::new();
+        kept.publish(event.clone());
+        let mut pool = RelayPool::new();
+        pool.add_mock("wss://kept.example.com", &kept);
+        pool.add_mock("wss://dropped.example.com", &dropped);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 2);
+
+        let urls = pool.urls();
+        pool.confirm_stored(event.id.to_hex(), &urls, Duration::ZERO);
+        assert!(pool.take_confirmations().is_empty());
+        // Our own copy coming back isn't mail for the app.
+        for _ in 0..10 {
+            assert_eq!(pool.try_recv(), None);
+        }
+        assert_eq!(pool.take_confirmations(), [(event.id.to_hex(), Some(1))]);
+        assert_eq!(kept.open_subscriptions() + dropped.open_subscriptions(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn relays_wanting_auth_dont_count_as_dropping() -> anyhow::Result<()> {
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&Keys::generate())?;
+        let private = MockRelay::new();
+        private.publish(event.clone());
+        private.require_auth();
+        let mut pool = RelayPool::new();
+        pool.add_mock("wss://private.example.com", &private);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        let urls = pool.urls();
+        pool.confirm_stored(event.id.to_hex(), &urls, Duration::from_secs(60));
+        for _ in 0..10 {
+            assert_eq!(pool.try_recv(), None);
+        }
+        // Nothing is asked before the delay is up.
+        assert!(pool.take_confirmations().is_empty());
+        assert_eq!(private.open_subscriptions(), 0);
+
+        pool.confirm_stored(event.id.to_hex(), &urls, Duration::ZERO);
+        let mut confirmations = Vec::new();
+        pump_until(&mut pool, "the relay refusing", |pool| {
+            confirmations.extend(pool.take_confirmations());
+            !confirmations.is_empty()
+        });
+        assert_eq!(confirmations, [(event.id.to_hex(), None)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use crate::clock;
+use std::fmt;
+
+/// Something a relay did that the user should hear about, with what they can do about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayError {
+    /// The websocket couldn't be opened, or broke.
+    Connect { url: String, reason: String },
+    /// The relay wants us to authenticate (NIP-42) before it serves us.
+    Auth { url: String },
+    /// The relay is throttling us.
+    RateLimited { url: String, reason: String },
+    /// An event was dated too far from the relay's clock.
+    Misdated { url: String, reason: String },
+    /// An event was bigger than the relay takes.
+    PayloadTooLarge { url: String, reason: String },
+    /// An event was refused as malformed, so sending it again won't help.
+    Invalid { url: String, reason: String },
+    /// An event needed more proof of work (NIP-13) than we're set to do.
+    PowRequired { url: String, reason: String },
+    /// The relay ended one of our subscriptions.
+    ClosedByRelay {
+        url: String,
+        subscription_id: String,
+        reason: String,
+    },
+}
+
+impl RelayError {
+    /// Reads the reason an OK with `false` gives (NIP-01). `None` for reasons that aren't
+    /// worth interrupting the user for, like duplicates, or that the send report shows already.
+    pub fn from_rejection(url: &str, reason: &str) -> Option<Self> {
+        let url = url.to_string();
+        let reason = reason.to_string();
+        match prefix(&reason) {
+            Some("auth-required") => Some(RelayError::Auth { url }),
+            Some("rate-limited") => Some(RelayError::RateLimited { url, reason }),
+            _ if clock::is_date_rejection(&reason) => Some(RelayError::Misdated { url, reason }),
+            _ if is_too_large(&reason) => Some(RelayError::PayloadTooLarge { url, reason }),
+            Some("invalid") => Some(RelayError::Invalid { url, reason }),
+            Some("pow") => Some(RelayError::PowRequired { url, reason }),
+            _ => None,
+        }
+    }
+
+    /// Reads the reason a CLOSED gives for ending `subscription_id` (NIP-01).
+    pub fn from_closed(url: &str, subscription_id: &str, reason: &str) -> Self {
+        let url = url.to_string();
+        let reason = reason.to_string();
+        match prefix(&reason) {
+            Some("auth-required") => RelayError::Auth { url },
+            Some("rate-limited") => RelayError::RateLimited { url, reason },
+            _ => RelayError::ClosedByRelay {
+                url,
+                subscription_id: subscription_id.to_string(),
+                reason,
+            },
+        }
+    }
+}
+
+/// What a relay's reason for refusing an event asks of us, going by its prefix (NIP-01).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// Sending it again a little later may work.
+    RateLimited,
+    /// The event is wrong in a way sending it again won't fix.
+    Invalid,
+    /// The event needs proof of work (NIP-13), this many bits if the relay said.
+    Pow(Option<u8>),
+    Other,
+}
+
+impl Rejection {
+    pub fn parse(reason: &str) -> Self {
+        match prefix(reason) {
+            Some("rate-limited") => Rejection::RateLimited,
+            Some("invalid") => Rejection::Invalid,
+            Some("pow") => Rejection::Pow(required_difficulty(reason)),
+            _ => Rejection::Other,
+        }
+    }
+}
+
+/// Whether a relay closing a REQ with `reason` only refused us, like one serving events to
+/// their recipients after NIP-42 auth, rather than saying it has nothing.
+pub(crate) fn refuses_access(reason: &str) -> bool {
+    matches!(prefix(reason), Some("auth-required") | Some("restricted"))
+}
+
+/// The difficulty a `pow:` reason asks for. Relays word it differently, like "difficulty 12 is
+/// less than 20" or "(12 < 20)", but the one they want is the biggest number.
+fn required_difficulty(reason: &str) -> Option<u8> {
+    reason
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse::<u8>().ok())
+        .max()
+}
+
+/// The machine-readable prefix of a relay's reason, like `rate-limited` in
+/// `rate-limited: slow down`.
+fn prefix(reason: &str) -> Option<&str> {
+    reason
+        .split_once(':')
+        .map(|(prefix, _)| prefix.trim())
+        .filter(|prefix| !prefix.is_empty() && !prefix.contains(' '))
+}
+
+/// Relays don't agree on a prefix for this, so look for the usual wording.
+fn is_too_large(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    ["too large", "too big", "too long", "exceeds"]
+        .iter()
+        .any(|words| reason.contains(words))
+}
+
+impl fmt::Display for RelayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayError::Connect { url, reason } => write!(
+                f,
+                "Couldn't connect to {} ({}). Check the address in Settings, or try again later.",
+                url, reason
+            ),
+            RelayError::Auth { url } => write!(
+                f,
+                "{} wants Hoot to sign in, which it can't do yet. Mail there may not arrive.",
+                url
+            ),
+            RelayError::RateLimited { url, reason } => write!(
+                f,
+                "{} is slowing us down ({}). Wait a little before sending more.",
+                url, reason
+            ),
+            RelayError::Misdated { url, reason } => write!(
+                f,
+                "{} refused a message for its date ({}). Check your computer's clock.",
+                url, reason
+            ),
+            RelayError::PayloadTooLarge { url, reason } => write!(
+                f,
+                "{} refused a message as too large ({}). Attach big files instead of pasting \
+                 them in.",
+                url, reason
+            ),
+            RelayError::Invalid { url, reason } => write!(
+                f,
+                "{} refused a message as invalid ({}). Sending it again won't help.",
+                url, reason
+            ),
+            RelayError::PowRequired { url, reason } => write!(
+                f,
+                "{} wants more proof of work than Hoot is set to do ({}). You can allow more \
+                 in Settings.",
+                url, reason
+            ),
+            RelayError::ClosedByRelay { url, reason, .. } => {
+                write!(f, "{} stopped sending us mail: {}", url, reason)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_relay_reasons() {
+        let url = "wss://relay.example.com";
+        assert_eq!(
+            RelayError::from_rejection(url, "rate-limited: slow down"),
+            Some(RelayError::RateLimited {
+                url: url.to_string(),
+                reason: "rate-limited: slow down".to_string()
+            })
+        );
+        assert!(matches!(
+            RelayError::from_rejection(url, "invalid: event too large"),
+            Some(RelayError::PayloadTooLarge { .. })
+        ));
+        assert_eq!(
+            RelayError::from_rejection(url, "duplicate: already have it"),
+            None
+        );
+        assert!(matches!(
+            RelayError::from_rejection(url, "invalid: bad signature"),
+            Some(RelayError::Invalid { .. })
+        ));
+        assert_eq!(
+            Rejection::parse("pow: difficulty 12 is less than 20"),
+            Rejection::Pow(Some(20))
+        );
+        assert_eq!(Rejection::parse("pow: not enough"), Rejection::Pow(None));
+        assert_eq!(
+            Rejection::parse("rate-limited: slow down"),
+            Rejection::RateLimited
+        );
+        assert_eq!(Rejection::parse("blocked: spam"), Rejection::Other);
+        assert_eq!(
+            RelayError::from_closed(url, "inbox", "auth-required: sign in first"),
+            RelayError::Auth {
+                url: url.to_string()
+            }
+        );
+        assert!(matches!(
+            RelayError::from_closed(url, "inbox", "error: shutting down"),
+            RelayError::ClosedByRelay { .. }
+        ));
+    }
+}
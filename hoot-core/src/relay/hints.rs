@@ -0,0 +1,217 @@
+//! Where people read: the relays they list (NIP-65 and NIP-17 inbox lists) and the ones mail
+//! from people we trust hints at in its `p` tags. Compose uses it to warn before sending to someone we
+//! can't place on any relay.
+
+use crate::relay::Subscription;
+use nostr::{Event, Filter, Kind, PublicKey, Tags};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// NIP-17 relays to send direct messages to.
+const INBOX_RELAYS_KIND: u16 = 10050;
+/// The subscription that keeps the relay lists we asked about coming.
+const LOOKUP_SUBSCRIPTION: &str = "relay_hints";
+/// How long relays get to come up with a relay list before we give up on one.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What we know about where a pubkey reads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Presence {
+    /// Still asking relays for their relay lists.
+    Looking,
+    /// Relays they read from, best first.
+    Known(Vec<String>),
+    /// Neither relay lists nor hints name any relay.
+    Unknown,
+}
+
+#[derive(Debug, Default)]
+pub struct RelayHints {
+    /// The newest relay list of each kind per pubkey, with its created_at.
+    lists: HashMap<(String, u16), (u64, Vec<String>)>,
+    /// Relays other events named next to the pubkey, in the order we saw them.
+    hinted: HashMap<String, Vec<String>>,
+    /// When we started looking up each pubkey's relay lists.
+    lookups: HashMap<String, Instant>,
+}
+
+impl RelayHints {
+    /// Whether `event` is a relay list for [`RelayHints::learn`].
+    pub fn is_relay_list(event: &Event) -> bool {
+        event.kind == Kind::RelayList || event.kind.as_u16() == INBOX_RELAYS_KIND
+    }
+
+    /// Takes the relays out of a relay list, keeping only the newest one of each kind.
+    pub fn learn(&mut self, event: &Event) {
+        if !Self::is_relay_list(event) {
+            return;
+        }
+        let kind = event.kind.as_u16();
+        let relays: Vec<String> = event
+            .tags
+            .iter()
+            .filter_map(|tag| match tag.as_slice() {
+                // Inbox lists name relays plainly.
+                [name, url, ..] if name == "relay" && kind == INBOX_RELAYS_KIND => Some(url),
+                // NIP-65 marks relays only written to, the rest are read from.
+                [name, url] if name == "r" => Some(url),
+                [name, url, marker, ..] if name == "r" && marker != "write" => Some(url),
+                _ => None,
+            })
+            .filter(|url| is_relay_url(url))
+            .cloned()
+            .collect();
+        let key = (event.pubkey.to_hex(), kind);
+        let created_at = event.created_at.as_u64();
+        if self
+            .lists
+            .get(&key)
+            .is_some_and(|(newest, _)| *newest > created_at)
+        {
+            return;
+        }
+        self.lists.insert(key, (created_at, relays));
+    }
+
+    /// Picks up relay urls given next to pubkeys in `p` tags. Only for events by people the
+    /// user trusts, like contacts, since anyone could point a pubkey at their own relay.
+    pub fn learn_hints(&mut self, tags: &Tags) {
+        for tag in tags.iter() {
+            if let [name, pubkey, url, ..] = tag.as_slice() {
+                if name != "p" || !is_relay_url(url) {
+                    continue;
+                }
+                let hinted = self.hinted.entry(pubkey.clone()).or_default();
+                if !hinted.contains(url) {
+                    hinted.push(url.clone());
+                }
+            }
+        }
+    }
+
+    /// Relays `pubkey` reads from: their inbox list, then their NIP-65 read relays, then
+    /// hints.
+    pub fn relays(&self, pubkey: &str) -> Vec<String> {
+        let mut relays: Vec<String> = Vec::new();
+        let lists = [INBOX_RELAYS_KIND, Kind::RelayList.as_u16()]
+            .into_iter()
+            .filter_map(|kind| self.lists.get(&(pubkey.to_string(), kind)))
+            .flat_map(|(_, urls)| urls);
+        for url in lists.chain(self.hinted.get(pubkey).into_iter().flatten()) {
+            if !relays.contains(url) {
+                relays.push(url.clone());
+            }
+        }
+        relays
+    }
+
+    pub fn presence(&self, pubkey: &str) -> Presence {
+        let relays = self.relays(pubkey);
+        if !relays.is_empty() {
+            return Presence::Known(relays);
+        }
+        match self.lookups.get(pubkey) {
+            Some(started) if started.elapsed() < LOOKUP_TIMEOUT => Presence::Looking,
+            Some(_) => Presence::Unknown,
+            // Not asked about yet, which the next lookup takes care of.
+            None => Presence::Looking,
+        }
+    }
+
+    /// Starts looking up the relay lists of `pubkeys` we haven't asked about. Returns the
+    /// subscription to (re)send when there are new ones, covering everyone asked about so far.
+    pub fn lookup(&mut self, pubkeys: &[PublicKey]) -> Option<Subscription> {
+        let now = Instant::now();
+        let mut new = false;
+        for pubkey in pubkeys {
+            self.lookups.entry(pubkey.to_hex()).or_insert_with(|| {
+                new = true;
+                now
+            });
+        }
+        if !new {
+            return None;
+        }
+        let authors: Vec<PublicKey> = self
+            .lookups
+            .keys()
+            .filter_map(|pubkey| PublicKey::parse(pubkey).ok())
+            .collect();
+        let filter = Filter::new()
+            .kinds([Kind::RelayList, Kind::from(INBOX_RELAYS_KIND)])
+            .authors(authors);
+        Some(Subscription::new(
+            LOOKUP_SUBSCRIPTION.to_string(),
+            vec![filter],
+        ))
+    }
+}
+
+fn is_relay_url(url: &str) -> bool {
+    url.starts_with("wss://") || url.starts_with("ws://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventBuilder, Keys, Tag, Timestamp};
+
+    fn relay_list(keys: &Keys, kind: Kind, tags: &[&[&str]], created_at: u64) -> Event {
+        let tags: Vec<Tag> = tags.iter().map(|tag| Tag::parse(tag).unwrap()).collect();
+        EventBuilder::new(kind, "")
+            .tags(tags)
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn inbox_relays_come_first_and_write_relays_are_skipped() {
+        let keys = Keys::generate();
+        let pubkey = keys.public_key().to_hex();
+        let mut hints = RelayHints::default();
+        assert!(hints.lookup(&[keys.public_key()]).is_some());
+        assert!(hints.lookup(&[keys.public_key()]).is_none());
+        assert_eq!(hints.presence(&pubkey), Presence::Looking);
+
+        hints.learn(&relay_list(
+            &keys,
+            Kind::RelayList,
+            &[
+                &["r", "wss://read.example.com", "read"],
+                &["r", "wss://write.example.com", "write"],
+                &["r", "wss://both.example.com"],
+            ],
+            10,
+        ));
+        hints.learn(&relay_list(
+            &keys,
+            Kind::from(INBOX_RELAYS_KIND),
+            &[&["relay", "wss://inbox.example.com"]],
+            10,
+        ));
+        // An older list doesn't replace the newer one.
+        hints.learn(&relay_list(
+            &keys,
+            Kind::RelayList,
+            &[&["r", "wss://old.example.com"]],
+            5,
+        ));
+        hints.learn_hints(&Tags::new(vec![Tag::parse(&[
+            "p",
+            pubkey.as_str(),
+            "wss://hint.example.com",
+        ])
+        .unwrap()]));
+
+        assert_eq!(
+            hints.presence(&pubkey),
+            Presence::Known(vec![
+                "wss://inbox.example.com".to_string(),
+                "wss://read.example.com".to_string(),
+                "wss://both.example.com".to_string(),
+                "wss://hint.example.com".to_string(),
+            ])
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! The relay information document (NIP-11), which says what a relay expects of its users, like
+//! paying before it takes their mail.
+
+use crate::error::Result;
+use serde::Deserialize;
+
+/// The `Accept` header relays answer with their information document.
+pub const INFO_ACCEPT: &str = "application/nostr+json";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct RelayInfo {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Where to pay for the relay, if it charges.
+    pub payments_url: Option<String>,
+    #[serde(default)]
+    pub limitation: Limitation,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Limitation {
+    #[serde(default)]
+    pub payment_required: bool,
+    #[serde(default)]
+    pub auth_required: bool,
+}
+
+impl RelayInfo {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn payment_required(&self) -> bool {
+        self.limitation.payment_required
+    }
+
+    /// The payment page, if the relay gave one we'd open.
+    pub fn payment_page(&self) -> Option<&str> {
+        self.payments_url
+            .as_deref()
+            .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+    }
+}
+
+/// Where the information document of the relay at `url` is served: the same address over HTTP.
+pub fn info_url(url: &str) -> Option<String> {
+    if let Some(rest) = url.strip_prefix("wss://") {
+        Some(format!("https://{}", rest))
+    } else {
+        url.strip_prefix("ws://")
+            .map(|rest| format!("http://{}", rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_payment_requirements() -> Result<()> {
+        let info = RelayInfo::from_json(
+            r#"{
+                "name": "Paid relay",
+                "payments_url": "https://relay.example.com/pay",
+                "limitation": {"payment_required": true, "max_subscriptions": 20},
+                "supported_nips": [1, 11, 42]
+            }"#,
+        )?;
+        assert!(info.payment_required());
+        assert_eq!(info.payment_page(), Some("https://relay.example.com/pay"));
+
+        let free = RelayInfo::from_json(r#"{"name": "Free relay"}"#)?;
+        assert!(!free.payment_required());
+        let odd = RelayInfo::from_json(r#"{"payments_url": "javascript:alert(1)"}"#)?;
+        assert_eq!(odd.payment_page(), None);
+
+        assert_eq!(
+            info_url("wss://relay.example.com/inbox").as_deref(),
+            Some("https://relay.example.com/inbox")
+        );
+        assert_eq!(
+            info_url("ws://localhost:7777").as_deref(),
+            Some("http://localhost:7777")
+        );
+        assert_eq!(info_url("https://relay.example.com"), None);
+        Ok(())
+    }
+}
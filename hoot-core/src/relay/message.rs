@@ -0,0 +1,507 @@
+use crate::error;
+use ewebsock::{WsEvent, WsMessage};
+use nostr::types::Filter;
+use nostr::Event;
+use serde::de::{SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::{self};
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct CommandResult<'a> {
+    pub event_id: &'a str,
+    pub status: bool,
+    /// Unescaped, so it is only borrowed when the relay sent no escapes.
+    pub message: Cow<'a, str>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RelayMessage<'a> {
+    Event(&'a str, &'a str),
+    OK(CommandResult<'a>),
+    Eose(&'a str),
+    /// Subscription id and reason, see [`CommandResult::message`] for the reason.
+    Closed(&'a str, Cow<'a, str>),
+    Notice(&'a str),
+    /// NIP-77 reconciliation message: subscription id and hex encoded payload.
+    NegMsg(&'a str, &'a str),
+    /// NIP-77 error: subscription id and reason.
+    NegErr(&'a str, &'a str),
+    /// NIP-45 answer: subscription id and the number of matching events.
+    Count(&'a str, u64),
+    /// NIP-42 challenge: the relay wants us to authenticate.
+    Auth(&'a str),
+}
+
+#[derive(Deserialize)]
+struct CountResult {
+    count: u64,
+}
+
+#[derive(Debug)]
+pub enum RelayEvent<'a> {
+    Opened,
+    Closed,
+    Other(&'a WsMessage),
+    Error(error::Error),
+    Message(RelayMessage<'a>),
+}
+
+impl<'a> From<&'a WsEvent> for RelayEvent<'a> {
+    fn from(value: &'a WsEvent) -> Self {
+        match value {
+            WsEvent::Opened => RelayEvent::Opened,
+            WsEvent::Closed => RelayEvent::Closed,
+            WsEvent::Message(ref ws_msg) => ws_msg.into(),
+            WsEvent::Error(e) => RelayEvent::Error(error::Error::Generic(e.to_owned())),
+        }
+    }
+}
+
+impl<'a> From<&'a WsMessage> for RelayEvent<'a> {
+    fn from(value: &'a WsMessage) -> Self {
+        match value {
+            WsMessage::Text(s) => match RelayMessage::from_json(s).map(RelayEvent::Message) {
+                Ok(msg) => msg,
+                Err(err) => RelayEvent::Error(err),
+            },
+            value => RelayEvent::Other(value),
+        }
+    }
+}
+
+impl<'a> RelayMessage<'a> {
+    pub fn eose(subid: &'a str) -> Self {
+        RelayMessage::Eose(subid)
+    }
+
+    pub fn notice(msg: &'a str) -> Self {
+        RelayMessage::Notice(msg)
+    }
+
+    pub fn ok(event_id: &'a str, status: bool, message: &'a str) -> Self {
+        RelayMessage::OK(CommandResult {
+            event_id,
+            status,
+            message: Cow::Borrowed(message),
+        })
+    }
+
+    pub fn event(ev: &'a str, sub_id: &'a str) -> Self {
+        RelayMessage::Event(sub_id, ev)
+    }
+
+    pub fn from_json(msg: &'a str) -> error::Result<RelayMessage<'a>> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+
+        if msg.is_empty() {
+            return Err(error::Error::Empty);
+        }
+
+        // Notice
+        // Relay response format: ["NOTICE", <message>]
+        if let Some(rest) = msg.strip_prefix("[\"NOTICE\",") {
+            return Ok(Self::notice(quoted_last(rest)?));
+        }
+
+        // Event
+        // Relay response format: ["EVENT", <subscription id>, <event JSON>]
+        if let Some(rest) = msg.strip_prefix("[\"EVENT\",") {
+            let (subid, event_json) = rest.split_once(',').ok_or(error::Error::DecodeFailed)?;
+            let subid = subid.trim().trim_matches('"');
+
+            // Event JSON goes until the closing bracket
+            let event_json = event_json
+                .trim()
+                .strip_suffix(']')
+                .ok_or(error::Error::DecodeFailed)?
+                .trim_end();
+
+            return Ok(Self::event(event_json, subid));
+        }
+
+        // EOSE (NIP-15)
+        // Relay response format: ["EOSE", <subscription_id>]
+        if let Some(rest) = msg.strip_prefix("[\"EOSE\",") {
+            return Ok(Self::eose(quoted_last(rest)?));
+        }
+
+        // NEG-MSG / NEG-ERR (NIP-77)
+        // Relay response format: ["NEG-MSG", <subscription id>, <hex message>]
+        if msg.starts_with("[\"NEG-") {
+            let (command, subid, payload): (&str, &str, &str) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
+            return match command {
+                "NEG-MSG" => Ok(RelayMessage::NegMsg(subid, payload)),
+                "NEG-ERR" => Ok(RelayMessage::NegErr(subid, payload)),
+                _ => Err(error::Error::DecodeFailed),
+            };
+        }
+
+        // COUNT (NIP-45)
+        // Relay response format: ["COUNT", <subscription id>, {"count": <integer>}]
+        if msg.starts_with("[\"COUNT\"") {
+            let (_, subid, result): (&str, &str, CountResult) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
+            return Ok(RelayMessage::Count(subid, result.count));
+        }
+
+        // CLOSED (NIP-01)
+        // Relay response format: ["CLOSED", <subscription id>, <message>]
+        if msg.starts_with("[\"CLOSED\"") {
+            let (_, subid, message): (&str, &str, Cow<str>) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
+            return Ok(RelayMessage::Closed(subid, message));
+        }
+
+        // AUTH (NIP-42)
+        // Relay response format: ["AUTH", <challenge>]
+        if let Some(rest) = msg.strip_prefix("[\"AUTH\",") {
+            return Ok(RelayMessage::Auth(quoted_last(rest)?));
+        }
+
+        // OK (NIP-20)
+        // Relay response format: ["OK",<event_id>, <true|false>, <message>]
+        if msg.starts_with("[\"OK\"") {
+            let (_, event_id, status, message): (&str, &str, bool, Cow<str>) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
+            return Ok(RelayMessage::OK(CommandResult {
+                event_id,
+                status,
+                message,
+            }));
+        }
+
+        Err(error::Error::DecodeFailed)
+    }
+}
+
+/// The string that ends a message, e.g. `"abc"]` (after the comma, spaces allowed), without its
+/// quotes. Escapes are left as they are.
+fn quoted_last(rest: &str) -> error::Result<&str> {
+    rest.trim()
+        .strip_suffix(']')
+        .map(str::trim_end)
+        .and_then(|quoted| quoted.trim_start().strip_prefix('"'))
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .ok_or(error::Error::DecodeFailed)
+}
+
+/// Messages that are client -> relay.
+#[derive(Debug, Clone)]
+pub enum ClientMessage {
+    Event {
+        event: Event,
+    },
+    Req {
+        subscription_id: String,
+        filters: Vec<Filter>,
+    },
+    Close {
+        subscription_id: String,
+    },
+    /// Asks for the number of events matching `filters` (NIP-45).
+    Count {
+        subscription_id: String,
+        filters: Vec<Filter>,
+    },
+    /// Starts a NIP-77 reconciliation, `initial_message` is hex encoded.
+    NegOpen {
+        subscription_id: String,
+        filter: Filter,
+        initial_message: String,
+    },
+    NegMsg {
+        subscription_id: String,
+        message: String,
+    },
+    NegClose {
+        subscription_id: String,
+    },
+}
+
+impl From<super::Subscription> for ClientMessage {
+    fn from(value: super::Subscription) -> Self {
+        Self::Req {
+            subscription_id: value.id,
+            filters: value.filters,
+        }
+    }
+}
+
+impl Serialize for ClientMessage {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ClientMessage::Event { event } => {
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element("EVENT")?;
+                seq.serialize_element(event)?;
+                seq.end()
+            }
+            ClientMessage::Req {
+                subscription_id,
+                filters,
+            } => {
+                let mut seq = serializer.serialize_seq(Some(2 + filters.len()))?;
+                seq.serialize_element("REQ")?;
+                seq.serialize_element(subscription_id)?;
+                for filter in filters {
+                    seq.serialize_element(filter)?;
+                }
+                seq.end()
+            }
+            ClientMessage::Close { subscription_id } => {
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element("CLOSE")?;
+                seq.serialize_element(subscription_id)?;
+                seq.end()
+            }
+            ClientMessage::Count {
+                subscription_id,
+                filters,
+            } => {
+                let mut seq = serializer.serialize_seq(Some(2 + filters.len()))?;
+                seq.serialize_element("COUNT")?;
+                seq.serialize_element(subscription_id)?;
+                for filter in filters {
+                    seq.serialize_element(filter)?;
+                }
+                seq.end()
+            }
+            ClientMessage::NegOpen {
+                subscription_id,
+                filter,
+                initial_message,
+            } => {
+                let mut seq = serializer.serialize_seq(Some(4))?;
+                seq.serialize_element("NEG-OPEN")?;
+                seq.serialize_element(subscription_id)?;
+                seq.serialize_element(filter)?;
+                seq.serialize_element(initial_message)?;
+                seq.end()
+            }
+            ClientMessage::NegMsg {
+                subscription_id,
+                message,
+            } => {
+                let mut seq = serializer.serialize_seq(Some(3))?;
+                seq.serialize_element("NEG-MSG")?;
+                seq.serialize_element(subscription_id)?;
+                seq.serialize_element(message)?;
+                seq.end()
+            }
+            ClientMessage::NegClose { subscription_id } => {
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element("NEG-CLOSE")?;
+                seq.serialize_element(subscription_id)?;
+                seq.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{Alphabet, EventId, Kind, PublicKey, SingleLetterTag, Timestamp};
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    /// What relays put in subscription ids and messages, as long as nothing needs escaping.
+    const PLAIN_TEXT: &str = "[a-zA-Z0-9 _.,:!?-]{0,80}";
+    const SUBSCRIPTION_ID: &str = "[a-zA-Z0-9_-]{1,64}";
+
+    /// A relay's `", "` or a compact `","`, relays send both.
+    fn separator() -> impl Strategy<Value = &'static str> {
+        prop_oneof![Just(","), Just(", ")]
+    }
+
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            ".{0,20}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 24, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map("[a-z_]{1,8}", inner, 0..4)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    fn filter() -> impl Strategy<Value = Filter> {
+        let event_id = any::<[u8; 32]>().prop_map(|bytes| EventId::from_slice(&bytes).unwrap());
+        let pubkey = any::<[u8; 32]>()
+            .prop_filter_map("not a key", |bytes| PublicKey::from_slice(&bytes).ok());
+        let tag = prop_oneof![
+            Just(Alphabet::E),
+            Just(Alphabet::P),
+            Just(Alphabet::T),
+            Just(Alphabet::D),
+        ];
+        (
+            prop::option::of(prop::collection::vec(event_id, 1..4)),
+            prop::option::of(prop::collection::vec(pubkey, 1..4)),
+            prop::option::of(prop::collection::vec(any::<u16>(), 1..4)),
+            prop::option::of(any::<u32>()),
+            prop::option::of(any::<u32>()),
+            prop::option::of(0usize..5000),
+            prop::collection::vec((tag, prop::collection::vec("[a-z0-9]{1,16}", 1..3)), 0..3),
+        )
+            .prop_map(|(ids, authors, kinds, since, until, limit, tags)| {
+                let mut filter = Filter::new();
+                if let Some(ids) = ids {
+                    filter = filter.ids(ids);
+                }
+                if let Some(authors) = authors {
+                    filter = filter.authors(authors);
+                }
+                if let Some(kinds) = kinds {
+                    filter = filter.kinds(kinds.into_iter().map(Kind::from));
+                }
+                if let Some(since) = since {
+                    filter = filter.since(Timestamp::from(since as u64));
+                }
+                if let Some(until) = until {
+                    filter = filter.until(Timestamp::from(until as u64));
+                }
+                if let Some(limit) = limit {
+                    filter = filter.limit(limit);
+                }
+                for (letter, values) in tags {
+                    filter = filter.custom_tag(SingleLetterTag::lowercase(letter), values);
+                }
+                filter
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_relay_garbage(msg in any::<String>()) {
+            let _ = RelayMessage::from_json(&msg);
+        }
+
+        #[test]
+        fn never_panics_on_almost_messages(
+            msg in r#"\["(EVENT|EOSE|NOTICE|OK|CLOSED|COUNT|NEG-MSG|NEG-ERR|AUTH)",?.{0,40}"#
+        ) {
+            let _ = RelayMessage::from_json(&msg);
+        }
+
+        #[test]
+        fn events_come_back_whole(
+            subid in SUBSCRIPTION_ID,
+            event in json_value(),
+            sep in separator(),
+        ) {
+            let msg = format!(r#"["EVENT"{sep}"{subid}"{sep}{event}]"#);
+            match RelayMessage::from_json(&msg) {
+                Ok(RelayMessage::Event(parsed_subid, event_json)) => {
+                    prop_assert_eq!(parsed_subid, subid.as_str());
+                    prop_assert_eq!(serde_json::from_str::<Value>(event_json).ok(), Some(event));
+                }
+                other => prop_assert!(false, "{} parsed as {:?}", msg, other),
+            }
+        }
+
+        #[test]
+        fn replies_come_back_whole(
+            subid in SUBSCRIPTION_ID,
+            text in PLAIN_TEXT,
+            status in any::<bool>(),
+            count in any::<u64>(),
+            sep in separator(),
+        ) {
+            let cases = [
+                (json!(["EOSE", subid]), RelayMessage::Eose(&subid)),
+                (json!(["NOTICE", text]), RelayMessage::Notice(&text)),
+                (json!(["CLOSED", subid, text]), RelayMessage::Closed(&subid, text.as_str().into())),
+                (json!(["OK", subid, status, text]), RelayMessage::ok(&subid, status, &text)),
+                (json!(["COUNT", subid, {"count": count}]), RelayMessage::Count(&subid, count)),
+                (json!(["NEG-ERR", subid, text]), RelayMessage::NegErr(&subid, &text)),
+                (json!(["AUTH", text]), RelayMessage::Auth(&text)),
+            ];
+            for (message, expected) in cases {
+                // Join the elements ourselves, so text holding `","` keeps it.
+                let elements: Vec<String> =
+                    message.as_array().into_iter().flatten().map(Value::to_string).collect();
+                let msg = format!("[{}]", elements.join(sep));
+                prop_assert_eq!(RelayMessage::from_json(&msg).ok(), Some(expected));
+            }
+        }
+
+        #[test]
+        fn filters_survive_the_wire(
+            subid in SUBSCRIPTION_ID,
+            filters in prop::collection::vec(filter(), 0..4),
+        ) {
+            let req = ClientMessage::Req {
+                subscription_id: subid.clone(),
+                filters: filters.clone(),
+            };
+            let sent: Vec<Value> =
+                serde_json::from_str(&serde_json::to_string(&req).unwrap()).unwrap();
+            prop_assert_eq!(sent.len(), 2 + filters.len());
+            prop_assert_eq!(&sent[0], "REQ");
+            prop_assert_eq!(&sent[1], subid.as_str());
+            for (sent, filter) in sent[2..].iter().zip(filters) {
+                prop_assert_eq!(serde_json::from_value::<Filter>(sent.clone()).unwrap(), filter);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_count_ok_and_negentropy_replies() {
+        assert_eq!(
+            RelayMessage::from_json(r#"["COUNT","abc",{"count":42}]"#).unwrap(),
+            RelayMessage::Count("abc", 42)
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["NEG-MSG", "sync1", "6100"]"#).unwrap(),
+            RelayMessage::NegMsg("sync1", "6100")
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["NEG-ERR","sync1","blocked: too big"]"#).unwrap(),
+            RelayMessage::NegErr("sync1", "blocked: too big")
+        );
+        let id = "ab".repeat(32);
+        assert_eq!(
+            RelayMessage::from_json(&format!(r#"["OK","{}",true,""]"#, id)).unwrap(),
+            RelayMessage::ok(&id, true, "")
+        );
+        assert_eq!(
+            RelayMessage::from_json(&format!(r#"["OK", "{}", false, "blocked: spam"]"#, id))
+                .unwrap(),
+            RelayMessage::ok(&id, false, "blocked: spam")
+        );
+        assert_eq!(
+            RelayMessage::from_json(&format!(
+                r#"["OK","{}",false,"invalid: \"pow\" too low"]"#,
+                id
+            ))
+            .unwrap(),
+            RelayMessage::ok(&id, false, r#"invalid: "pow" too low"#)
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["CLOSED","abc","error: can't parse \"limit\""]"#).unwrap(),
+            RelayMessage::Closed("abc", r#"error: can't parse "limit""#.into())
+        );
+
+        let count = ClientMessage::Count {
+            subscription_id: "abc".to_string(),
+            filters: vec![Filter::new().limit(1)],
+        };
+        assert_eq!(
+            serde_json::to_string(&count).unwrap(),
+            r#"["COUNT","abc",{"limit":1}]"#
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! An in-process relay for tests, so the whole pipeline can run without a network.
+//!
+//! It speaks enough NIP-01 for the pool: EVENT (answered with OK), REQ (stored events, then
+//! EOSE, then live events), CLOSE and ping. Anything else is refused the way a relay without
+//! that NIP would. Connections are plain channels standing in for the websocket.
+
+use ewebsock::{WsEvent, WsMessage};
+use nostr::types::Filter;
+use nostr::Event;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::RelayPool;
+
+#[derive(Clone, Default)]
+pub struct MockRelay {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    events: Vec<Event>,
+    clients: HashMap<usize, Client>,
+    next_client: usize,
+    /// Stands in for a connection that died without closing.
+    ignore_pings: bool,
+    /// How many more events to refuse as rate-limited.
+    rate_limit: usize,
+    /// The proof of work events need (NIP-13).
+    pow: u8,
+    /// Refuses REQs until the client authenticates (NIP-42), which ours never do.
+    require_auth: bool,
+}
+
+struct Client {
+    events: Sender<WsEvent>,
+    subscriptions: HashMap<String, Vec<Filter>>,
+}
+
+impl State {
+    /// Stores `event` and hands it to every subscription it matches. False if it's a copy.
+    fn store(&mut self, event: Event) -> bool {
+        if self.events.iter().any(|stored| stored.id == event.id) {
+            return false;
+        }
+        for client in self.clients.values() {
+            for (subscription_id, filters) in &client.subscriptions {
+                if filters.iter().any(|filter| filter.match_event(&event)) {
+                    send(&client.events, json!(["EVENT", subscription_id, event]));
+                }
+            }
+        }
+        self.events.push(event);
+        true
+    }
+}
+
+fn send(events: &Sender<WsEvent>, message: Value) {
+    let _ = events.send(WsEvent::Message(WsMessage::Text(message.to_string())));
+}
+
+impl MockRelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Stores an event as if someone else had published it.
+    pub fn publish(&self, event: Event) {
+        self.state().store(event);
+    }
+
+    /// Stops answering pings, as if the network went away under the connections.
+    pub fn ignore_pings(&self) {
+        self.state().ignore_pings = true;
+    }
+
+    /// Refuses the next `events` events as rate-limited.
+    pub fn rate_limit(&self, events: usize) {
+        self.state().rate_limit = events;
+    }
+
+    /// Refuses events with less than `difficulty` bits of proof of work.
+    pub fn require_pow(&self, difficulty: u8) {
+        self.state().pow = difficulty;
+    }
+
+    /// Refuses every REQ with `auth-required`, like relays that only serve gift wraps to
+    /// their recipient.
+    pub fn require_auth(&self) {
+        self.state().require_auth = true;
+    }
+
+    /// Everything published to the relay so far.
+    pub fn events(&self) -> Vec<Event> {
+        self.state().events.clone()
+    }
+
+    /// Subscriptions still open, across all connections.
+    pub fn open_subscriptions(&self) -> usize {
+        self.state()
+            .clients
+            .values()
+            .map(|client| client.subscriptions.len())
+            .sum()
+    }
+
+    /// A new connection. It opens right away.
+    pub fn connect(&self) -> Connection {
+        let (sender, events) = std::sync::mpsc::channel();
+        let _ = sender.send(WsEvent::Opened);
+
+        let mut state = self.state();
+        let client = state.next_client;
+        state.next_client += 1;
+        state.clients.insert(
+            client,
+            Client {
+                events: sender,
+                subscriptions: HashMap::new(),
+            },
+        );
+        Connection {
+            relay: self.clone(),
+            client,
+            events,
+        }
+    }
+
+    fn handle(&self, client: usize, message: WsMessage) {
+        let mut state = self.state();
+        let Some(reply_to) = state.clients.get(&client).map(|c| c.events.clone()) else {
+            return;
+        };
+        let text = match message {
+            WsMessage::Text(text) => text,
+            WsMessage::Ping(payload) => {
+                if state.ignore_pings {
+                    return;
+                }
+                let _ = reply_to.send(WsEvent::Message(WsMessage::Pong(payload)));
+                return;
+            }
+            _ => return,
+        };
+        let Ok(Value::Array(parts)) = serde_json::from_str::<Value>(&text) else {
+            send(
+                &reply_to,
+                json!(["NOTICE", "error: could not parse message"]),
+            );
+            return;
+        };
+
+        match (parts.first().and_then(Value::as_str), parts.get(1)) {
+            (Some("EVENT"), Some(event)) => {
+                let event: Event = match serde_json::from_value(event.clone()) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        send(&reply_to, json!(["NOTICE", format!("invalid: {}", e)]));
+                        return;
+                    }
+                };
+                let id = event.id.to_hex();
+                if event.verify().is_err() {
+                    send(
+                        &reply_to,
+                        json!(["OK", id, false, "invalid: bad signature"]),
+                    );
+                } else if state.rate_limit > 0 {
+                    state.rate_limit -= 1;
+                    send(
+                        &reply_to,
+                        json!(["OK", id, false, "rate-limited: slow down"]),
+                    );
+                } else if crate::pow::difficulty(&event.id) < state.pow {
+                    let reason = format!(
+                        "pow: difficulty {} is less than {}",
+                        crate::pow::difficulty(&event.id),
+                        state.pow
+                    );
+                    send(&reply_to, json!(["OK", id, false, reason]));
+                } else if state.store(event) {
+                    send(&reply_to, json!(["OK", id, true, ""]));
+                } else {
+                    send(
+                        &reply_to,
+                        json!(["OK", id, true, "duplicate: already have it"]),
+                    );
+                }
+            }
+            (Some("REQ"), Some(Value::String(subscription_id))) if state.require_auth => {
+                send(
+                    &reply_to,
+                    json!(["CLOSED", subscription_id, "auth-required: members only"]),
+                );
+            }
+            (Some("REQ"), Some(Value::String(subscription_id))) => {
+                let filters: Vec<Filter> = parts[2..]
+                    .iter()
+                    .filter_map(|filter| serde_json::from_value(filter.clone()).ok())
+                    .collect();
+                for event in &state.events {
+                    if filters.iter().any(|filter| filter.match_event(event)) {
+                        send(&reply_to, json!(["EVENT", subscription_id, event]));
+                    }
+                }
+                send(&reply_to, json!(["EOSE", subscription_id]));
+                if let Some(client) = state.clients.get_mut(&client) {
+                    client
+                        .subscriptions
+                        .insert(subscription_id.clone(), filters);
+                }
+            }
+            (Some("CLOSE"), Some(Value::String(subscription_id))) => {
+                if let Some(client) = state.clients.get_mut(&client) {
+                    client.subscriptions.remove(subscription_id);
+                }
+            }
+            (Some(verb), Some(Value::String(subscription_id))) => {
+                send(
+                    &reply_to,
+                    json!(["CLOSED", subscription_id, format!("unsupported: {}", verb)]),
+                );
+            }
+            _ => send(&reply_to, json!(["NOTICE", "error: unknown message"])),
+        }
+    }
+}
+
+/// Reads from `pool` until `done` holds, failing the test if `what` hasn't happened within a
+/// few seconds rather than hanging it.
+pub fn pump_until(pool: &mut RelayPool, what: &str, mut done: impl FnMut(&mut RelayPool) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done(pool) {
+        assert!(Instant::now() < deadline, "{} never happened", what);
+        pool.try_recv();
+    }
+}
+
+/// Our end of a connection to a [`MockRelay`].
+pub struct Connection {
+    relay: MockRelay,
+    client: usize,
+    events: Receiver<WsEvent>,
+}
+
+impl Connection {
+    pub fn send(&mut self, message: WsMessage) {
+        self.relay.handle(self.client, message);
+    }
+
+    /// Sends to the relay from another thread, like the ping timer does.
+    pub fn sender(&self) -> impl Fn(WsMessage) + Send + 'static {
+        let relay = self.relay.clone();
+        let client = self.client;
+        move |message| relay.handle(client, message)
+    }
+
+    pub fn try_recv(&self) -> Option<WsEvent> {
+        self.events.try_recv().ok()
+    }
+
+    pub fn close(&mut self) {
+        self.relay.state().clients.remove(&self.client);
+    }
+
+    pub fn reconnect(&mut self) {
+        self.close();
+        *self = self.relay.connect();
+    }
+}
@@ -0,0 +1,314 @@
+use crate::error::{Error, Result};
+use ewebsock::{WsEvent, WsMessage};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+mod pool;
+pub use pool::{
+    Ack, RelayPool, SendReport, DEFAULT_RELAYS, PAID_RELAYS_KEY, RELAYS_KEY,
+    RELAY_RECONNECT_SECONDS, RELAY_WINDOWS_KEY,
+};
+
+mod error;
+pub use error::{Rejection, RelayError};
+
+mod hints;
+pub use hints::{Presence, RelayHints};
+
+mod info;
+pub use info::{info_url, RelayInfo, INFO_ACCEPT};
+
+mod message;
+pub use message::{ClientMessage, RelayMessage};
+
+#[cfg(any(test, feature = "mock-relay"))]
+pub mod mock;
+
+mod negentropy;
+
+mod ping;
+use ping::{PingClock, PingTimer};
+pub use ping::{PingSettings, PING_SETTINGS_KEY};
+
+mod confirm;
+pub use confirm::CONFIRM_DELAY;
+
+mod outbox;
+
+mod preflight;
+
+mod seen;
+
+mod sync;
+pub use sync::SyncWindow;
+
+mod subscription;
+pub use subscription::Subscription;
+
+mod trace;
+pub use trace::{RelayTrace, TraceDirection};
+
+mod trust;
+pub use trust::{check_distrusted_wrap, RelayTrust, RELAY_TRUST_KEY};
+
+mod usage;
+pub use usage::{RelayUsage, RELAY_USAGE_KEY};
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum RelayStatus {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+/// The websocket to a relay, or in tests a connection to a [`mock::MockRelay`].
+enum Connection {
+    /// The sender is shared with the connection's [`PingTimer`].
+    Websocket(Arc<Mutex<ewebsock::WsSender>>, ewebsock::WsReceiver),
+    #[cfg(any(test, feature = "mock-relay"))]
+    Mock(mock::Connection),
+}
+
+impl Connection {
+    fn open(url: &str, wake_up: impl Fn() + Send + Sync + 'static) -> Self {
+        let (sender, reciever) =
+            ewebsock::connect_with_wakeup(url, ewebsock::Options::default(), wake_up).unwrap();
+        Connection::Websocket(Arc::new(Mutex::new(sender)), reciever)
+    }
+
+    fn send(&mut self, message: WsMessage) {
+        match self {
+            Connection::Websocket(writer, _) => lock_writer(writer).send(message),
+            #[cfg(any(test, feature = "mock-relay"))]
+            Connection::Mock(connection) => connection.send(message),
+        }
+    }
+
+    /// Sends to the connection from another thread.
+    fn sender(&self) -> Box<dyn Fn(WsMessage) + Send> {
+        match self {
+            Connection::Websocket(writer, _) => {
+                let writer = writer.clone();
+                Box::new(move |message| lock_writer(&writer).send(message))
+            }
+            #[cfg(any(test, feature = "mock-relay"))]
+            Connection::Mock(connection) => Box::new(connection.sender()),
+        }
+    }
+
+    fn try_recv(&self) -> Option<WsEvent> {
+        match self {
+            Connection::Websocket(_, reader) => reader.try_recv(),
+            #[cfg(any(test, feature = "mock-relay"))]
+            Connection::Mock(connection) => connection.try_recv(),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Connection::Websocket(writer, _) => lock_writer(writer).close(),
+            #[cfg(any(test, feature = "mock-relay"))]
+            Connection::Mock(connection) => connection.close(),
+        }
+    }
+}
+
+fn lock_writer(writer: &Mutex<ewebsock::WsSender>) -> MutexGuard<'_, ewebsock::WsSender> {
+    writer
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub struct Relay {
+    pub url: String,
+    connection: Connection,
+    pub status: RelayStatus,
+    pub trace: RelayTrace,
+    /// Traffic this month, for metered relays.
+    pub usage: RelayUsage,
+    /// Round trip time of the last answered ping.
+    pub rtt: Option<Duration>,
+    ping_clock: Arc<Mutex<PingClock>>,
+    /// Pings the connection while it's open.
+    ping_timer: Option<PingTimer>,
+    ping_settings: PingSettings,
+    /// Wakes the app up, for the ping timer to say the connection died.
+    wake_up: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl Relay {
+    pub fn new_with_wakeup(
+        url: impl Into<String>,
+        wake_up: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        let new_url: String = url.into();
+        let wake_up: Arc<dyn Fn() + Send + Sync> = Arc::new(wake_up);
+        let socket_wake_up = wake_up.clone();
+        let connection = Connection::open(&new_url, move || socket_wake_up());
+
+        Self {
+            url: new_url,
+            connection,
+            status: RelayStatus::Connecting,
+            trace: RelayTrace::default(),
+            usage: RelayUsage::default(),
+            rtt: None,
+            ping_clock: Arc::default(),
+            ping_timer: None,
+            ping_settings: PingSettings::default(),
+            wake_up,
+        }
+    }
+
+    #[cfg(any(test, feature = "mock-relay"))]
+    pub fn new_mock(url: impl Into<String>, relay: &mock::MockRelay) -> Self {
+        Self {
+            url: url.into(),
+            connection: Connection::Mock(relay.connect()),
+            status: RelayStatus::Connecting,
+            trace: RelayTrace::default(),
+            usage: RelayUsage::default(),
+            rtt: None,
+            ping_clock: Arc::default(),
+            ping_timer: None,
+            ping_settings: PingSettings::default(),
+            wake_up: Arc::new(|| {}),
+        }
+    }
+
+    // TODO: investigate whether this can cause a message to be dropped due to the writer being
+    // overwritten
+    pub fn reconnect(&mut self, wake_up: impl Fn() + Send + Sync + 'static) {
+        self.trace.record(TraceDirection::Status, "reconnecting");
+        self.ping_timer = None;
+        #[cfg(any(test, feature = "mock-relay"))]
+        if let Connection::Mock(connection) = &mut self.connection {
+            connection.reconnect();
+            return;
+        }
+        self.wake_up = Arc::new(wake_up);
+        let socket_wake_up = self.wake_up.clone();
+        self.connection = Connection::open(&self.url, move || socket_wake_up());
+    }
+
+    pub fn send(&mut self, message: WsMessage) -> Result<()> {
+        if self.status != RelayStatus::Connected {
+            return Err(Error::RelayNotConnected);
+        }
+        debug!("sending message to {}: {:?}", self.url, message);
+
+        self.trace.record_message(TraceDirection::Sent, &message);
+        self.usage.record(TraceDirection::Sent, &message);
+        self.connection.send(message);
+        Ok(())
+    }
+
+    pub fn try_recv(&mut self) -> Option<WsEvent> {
+        if let Some(event) = self.connection.try_recv() {
+            use WsEvent::*;
+            match event {
+                Message(ref message) => {
+                    self.trace.record_message(TraceDirection::Received, message);
+                    self.usage.record(TraceDirection::Received, message);
+                }
+                Opened => {
+                    self.status = RelayStatus::Connected;
+                    self.start_pings();
+                    self.trace
+                        .record(TraceDirection::Status, "connection opened");
+                }
+                Error(ref error) => {
+                    error!("error in websocket connection to {}: {}", self.url, error);
+                    self.status = RelayStatus::Disconnected;
+                    self.ping_timer = None;
+                    self.trace
+                        .record(TraceDirection::Status, format!("error: {}", error));
+                }
+                Closed => {
+                    info!("connection to {} closed", self.url);
+                    self.status = RelayStatus::Disconnected;
+                    self.ping_timer = None;
+                    self.trace
+                        .record(TraceDirection::Status, "connection closed");
+                }
+            }
+
+            return Some(event);
+        }
+
+        None
+    }
+
+    /// Closes the websocket. The relay won't be reconnected by the pool after this.
+    pub fn close(&mut self) {
+        info!("closing connection to {}", self.url);
+        self.connection.close();
+        self.status = RelayStatus::Disconnected;
+        self.ping_timer = None;
+        self.trace
+            .record(TraceDirection::Status, "connection closed by us");
+    }
+
+    /// Pings the connection on a timer of its own from now on, starting right away.
+    fn start_pings(&mut self) {
+        self.ping_clock = Arc::default();
+        self.ping_timer = Some(PingTimer::start(
+            self.url.clone(),
+            self.ping_clock.clone(),
+            self.ping_settings,
+            self.connection.sender(),
+            self.wake_up.clone(),
+        ));
+    }
+
+    /// Changes how often the connection is pinged, from its next ping on.
+    pub fn set_ping_settings(&mut self, settings: PingSettings) {
+        self.ping_settings = settings;
+        if let Some(timer) = &self.ping_timer {
+            timer.set_settings(settings);
+        }
+    }
+
+    /// Pings the relay now, on top of its timer.
+    pub fn ping(&mut self) {
+        match self.send(WsMessage::Ping(Vec::new())) {
+            Ok(_) => {
+                debug!("Ping sent to {}", self.url);
+                ping::lock(&self.ping_clock).pinged(Instant::now());
+            }
+            Err(e) => error!("Error sending ping to {}: {:?}", self.url, e),
+        }
+    }
+
+    /// Catches up with the ping timer: traces the pings it sent, and gives up on the
+    /// connection when the last one went unanswered for too long. Returns whether it gave up.
+    pub fn check_ping(&mut self) -> bool {
+        let (sent, timed_out) = {
+            let mut clock = ping::lock(&self.ping_clock);
+            (std::mem::take(&mut clock.unrecorded), clock.timed_out)
+        };
+        for _ in 0..sent {
+            let ping = WsMessage::Ping(Vec::new());
+            self.trace.record_message(TraceDirection::Sent, &ping);
+            self.usage.record(TraceDirection::Sent, &ping);
+        }
+        if !timed_out || self.status != RelayStatus::Connected {
+            return false;
+        }
+        self.connection.close();
+        self.status = RelayStatus::Disconnected;
+        self.ping_timer = None;
+        self.trace
+            .record(TraceDirection::Status, "no answer to ping, disconnected");
+        true
+    }
+
+    /// Measures the round trip time of our last ping.
+    pub fn record_pong(&mut self) {
+        if let Some(rtt) = ping::lock(&self.ping_clock).pong() {
+            debug!("pong from {} after {:?}", self.url, rtt);
+            self.rtt = Some(rtt);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Negentropy (NIP-77) set reconciliation, protocol version 1.
+//!
+//! Both sides split the range of (created_at, id) pairs they hold into buckets and swap
+//! fingerprints of them, recursing into the buckets that differ until only short id lists are
+//! left. That way a sync only transfers the ids one side is missing instead of every event.
+
+use crate::error::{Error, Result};
+use nostr::hashes::{sha256, Hash};
+
+pub const PROTOCOL_VERSION: u8 = 0x61;
+
+const ID_SIZE: usize = 32;
+const FINGERPRINT_SIZE: usize = 16;
+/// Ranges smaller than twice this are sent as id lists rather than split further.
+const BUCKETS: usize = 16;
+
+const MODE_SKIP: u64 = 0;
+const MODE_FINGERPRINT: u64 = 1;
+const MODE_ID_LIST: u64 = 2;
+
+pub type Id = [u8; ID_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Item {
+    timestamp: u64,
+    id: Id,
+}
+
+/// A range boundary. Ids are compared as if zero padded to the full 32 bytes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bound {
+    item: Item,
+    id_len: usize,
+}
+
+impl Bound {
+    fn new(timestamp: u64) -> Self {
+        Self {
+            item: Item {
+                timestamp,
+                id: [0; ID_SIZE],
+            },
+            id_len: 0,
+        }
+    }
+}
+
+impl Default for Item {
+    fn default() -> Self {
+        Self {
+            timestamp: 0,
+            id: [0; ID_SIZE],
+        }
+    }
+}
+
+pub struct Negentropy {
+    items: Vec<Item>,
+    is_initiator: bool,
+    last_timestamp_in: u64,
+    last_timestamp_out: u64,
+}
+
+impl Negentropy {
+    /// `items` are the (created_at, id) pairs we already have, in any order.
+    pub fn new(items: impl IntoIterator<Item = (u64, Id)>) -> Self {
+        let mut items: Vec<Item> = items
+            .into_iter()
+            .map(|(timestamp, id)| Item { timestamp, id })
+            .collect();
+        items.sort();
+        items.dedup();
+        Self {
+            items,
+            is_initiator: false,
+            last_timestamp_in: 0,
+            last_timestamp_out: 0,
+        }
+    }
+
+    /// The opening message, sent with NEG-OPEN.
+    pub fn initiate(&mut self) -> Vec<u8> {
+        self.is_initiator = true;
+        self.last_timestamp_out = 0;
+
+        let mut output = vec![PROTOCOL_VERSION];
+        self.split_range(0, self.items.len(), Bound::new(u64::MAX), &mut output);
+        output
+    }
+
+    /// Handles a message from the initiator and returns the response. We only ever initiate
+    /// against relays, this plays the relay in tests.
+    #[cfg(test)]
+    pub fn respond(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        if self.is_initiator {
+            return Err(Error::Generic("initiator can't respond".to_string()));
+        }
+        self.reconcile_aux(query, &mut Vec::new(), &mut Vec::new())
+    }
+
+    /// Handles a message from the relay, collecting the ids only we have in `have` and the ids
+    /// only the relay has in `need`. Returns the next message to send, or `None` once the sets
+    /// are reconciled.
+    pub fn reconcile(
+        &mut self,
+        query: &[u8],
+        have: &mut Vec<Id>,
+        need: &mut Vec<Id>,
+    ) -> Result<Option<Vec<u8>>> {
+        if !self.is_initiator {
+            return Err(Error::Generic("not the initiator".to_string()));
+        }
+        let output = self.reconcile_aux(query, have, need)?;
+        Ok((output.len() > 1).then_some(output))
+    }
+
+    fn reconcile_aux(
+        &mut self,
+        mut query: &[u8],
+        have: &mut Vec<Id>,
+        need: &mut Vec<Id>,
+    ) -> Result<Vec<u8>> {
+        self.last_timestamp_in = 0;
+        self.last_timestamp_out = 0;
+
+        let mut full_output = vec![PROTOCOL_VERSION];
+
+        let version = take_byte(&mut query)?;
+        if !(0x60..=0x6f).contains(&version) {
+            return Err(Error::Generic(
+                "invalid negentropy protocol version".to_string(),
+            ));
+        }
+        if version != PROTOCOL_VERSION {
+            if self.is_initiator {
+                return Err(Error::Generic(format!(
+                    "unsupported negentropy protocol version {:#x}",
+                    version
+                )));
+            }
+            // Tell the initiator which version we speak.
+            return Ok(full_output);
+        }
+
+        let mut prev_bound = Bound::default();
+        let mut prev_index = 0;
+        let mut skip = false;
+
+        while !query.is_empty() {
+            let mut o = Vec::new();
+
+            let curr_bound = self.decode_bound(&mut query)?;
+            let mode = decode_varint(&mut query)?;
+
+            let lower = prev_index;
+            let upper = self.find_lower_bound(prev_index, &curr_bound);
+
+            match mode {
+                MODE_SKIP => skip = true,
+                MODE_FINGERPRINT => {
+                    let theirs = take_bytes(&mut query, FINGERPRINT_SIZE)?;
+                    if theirs != self.fingerprint(lower, upper) {
+                        self.do_skip(&mut skip, prev_bound, &mut o);
+                        self.split_range(lower, upper, curr_bound, &mut o);
+                    } else {
+                        skip = true;
+                    }
+                }
+                MODE_ID_LIST => {
+                    let count = decode_varint(&mut query)? as usize;
+                    let mut theirs = Vec::with_capacity(count.min(query.len() / ID_SIZE));
+                    for _ in 0..count {
+                        let mut id = [0; ID_SIZE];
+                        id.copy_from_slice(take_bytes(&mut query, ID_SIZE)?);
+                        theirs.push(id);
+                    }
+
+                    for item in &self.items[lower..upper] {
+                        match theirs.iter().position(|id| *id == item.id) {
+                            Some(i) => {
+                                theirs.swap_remove(i);
+                            }
+                            None if self.is_initiator => have.push(item.id),
+                            None => {}
+                        }
+                    }
+
+                    if self.is_initiator {
+                        skip = true;
+                        need.extend(theirs);
+                    } else {
+                        self.do_skip(&mut skip, prev_bound, &mut o);
+                        self.encode_bound(&curr_bound, &mut o);
+                        encode_varint(MODE_ID_LIST, &mut o);
+                        encode_varint((upper - lower) as u64, &mut o);
+                        for item in &self.items[lower..upper] {
+                            o.extend_from_slice(&item.id);
+                        }
+                    }
+                }
+                _ => return Err(Error::Generic("unexpected negentropy mode".to_string())),
+            }
+
+            full_output.extend(o);
+            prev_index = upper;
+            prev_bound = curr_bound;
+        }
+
+        Ok(full_output)
+    }
+
+    fn do_skip(&mut self, skip: &mut bool, prev_bound: Bound, o: &mut Vec<u8>) {
+        if *skip {
+            *skip = false;
+            self.encode_bound(&prev_bound, o);
+            encode_varint(MODE_SKIP, o);
+        }
+    }
+
+    fn split_range(&mut self, lower: usize, upper: usize, upper_bound: Bound, o: &mut Vec<u8>) {
+        let count = upper - lower;
+
+        if count < BUCKETS * 2 {
+            self.encode_bound(&upper_bound, o);
+            encode_varint(MODE_ID_LIST, o);
+            encode_varint(count as u64, o);
+            for item in &self.items[lower..upper] {
+                o.extend_from_slice(&item.id);
+            }
+            return;
+        }
+
+        let per_bucket = count / BUCKETS;
+        let with_extra = count % BUCKETS;
+        let mut curr = lower;
+        for i in 0..BUCKETS {
+            let size = per_bucket + usize::from(i < with_extra);
+            let fingerprint = self.fingerprint(curr, curr + size);
+            curr += size;
+
+            let next_bound = if curr == upper {
+                upper_bound
+            } else {
+                minimal_bound(&self.items[curr - 1], &self.items[curr])
+            };
+            self.encode_bound(&next_bound, o);
+            encode_varint(MODE_FINGERPRINT, o);
+            o.extend_from_slice(&fingerprint);
+        }
+    }
+
+    /// Index of the first item at or after `bound`, searching from `from`.
+    fn find_lower_bound(&self, from: usize, bound: &Bound) -> usize {
+        from + self.items[from..].partition_point(|item| *item < bound.item)
+    }
+
+    /// Hash of the 256 bit little endian sum of the ids, followed by their count.
+    fn fingerprint(&self, lower: usize, upper: usize) -> [u8; FINGERPRINT_SIZE] {
+        let mut sum = [0u8; ID_SIZE];
+        for item in &self.items[lower..upper] {
+            let mut carry = 0u16;
+            for (acc, byte) in sum.iter_mut().zip(item.id) {
+                let total = *acc as u16 + byte as u16 + carry;
+                *acc = total as u8;
+                carry = total >> 8;
+            }
+        }
+
+        let mut input = sum.to_vec();
+        encode_varint((upper - lower) as u64, &mut input);
+        let hash = sha256::Hash::hash(&input);
+
+        let mut fingerprint = [0; FINGERPRINT_SIZE];
+        fingerprint.copy_from_slice(&hash.to_byte_array()[..FINGERPRINT_SIZE]);
+        fingerprint
+    }
+
+    /// Timestamps are sent as deltas from the previous one, with 0 meaning "infinity".
+    fn encode_bound(&mut self, bound: &Bound, o: &mut Vec<u8>) {
+        let timestamp = bound.item.timestamp;
+        if timestamp == u64::MAX {
+            self.last_timestamp_out = u64::MAX;
+            encode_varint(0, o);
+        } else {
+            let delta = timestamp - self.last_timestamp_out;
+            self.last_timestamp_out = timestamp;
+            encode_varint(delta + 1, o);
+        }
+        encode_varint(bound.id_len as u64, o);
+        o.extend_from_slice(&bound.item.id[..bound.id_len]);
+    }
+
+    fn decode_bound(&mut self, query: &mut &[u8]) -> Result<Bound> {
+        let timestamp = match decode_varint(query)? {
+            0 => u64::MAX,
+            delta => self.last_timestamp_in.saturating_add(delta - 1),
+        };
+        self.last_timestamp_in = timestamp;
+
+        let id_len = decode_varint(query)? as usize;
+        if id_len > ID_SIZE {
+            return Err(Error::Generic("bound id too long".to_string()));
+        }
+        let mut bound = Bound::new(timestamp);
+        bound.item.id[..id_len].copy_from_slice(take_bytes(query, id_len)?);
+        bound.id_len = id_len;
+        Ok(bound)
+    }
+}
+
+/// The shortest bound that sorts after `prev` but not after `curr`.
+fn minimal_bound(prev: &Item, curr: &Item) -> Bound {
+    if curr.timestamp != prev.timestamp {
+        return Bound::new(curr.timestamp);
+    }
+    let shared = prev
+        .id
+        .iter()
+        .zip(curr.id)
+        .take_while(|(a, b)| **a == *b)
+        .count();
+    let mut bound = Bound::new(curr.timestamp);
+    bound.id_len = (shared + 1).min(ID_SIZE);
+    bound.item.id[..bound.id_len].copy_from_slice(&curr.id[..bound.id_len]);
+    bound
+}
+
+/// Big endian base 128, with the high bit set on every byte but the last.
+fn encode_varint(mut n: u64, o: &mut Vec<u8>) {
+    let mut bytes = vec![(n & 0x7f) as u8];
+    n >>= 7;
+    while n > 0 {
+        bytes.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    o.extend(bytes.into_iter().rev());
+}
+
+fn decode_varint(query: &mut &[u8]) -> Result<u64> {
+    let mut n: u64 = 0;
+    loop {
+        let byte = take_byte(query)?;
+        n = n
+            .checked_mul(128)
+            .ok_or_else(|| Error::Generic("varint overflow".to_string()))?
+            | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+}
+
+fn take_byte(query: &mut &[u8]) -> Result<u8> {
+    Ok(take_bytes(query, 1)?[0])
+}
+
+fn take_bytes<'a>(query: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if query.len() < n {
+        return Err(Error::Generic("negentropy message ended early".to_string()));
+    }
+    let (bytes, rest) = query.split_at(n);
+    *query = rest;
+    Ok(bytes)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(Error::Generic("odd length hex".to_string()));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| Error::Generic("invalid hex".to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u32) -> Id {
+        let mut id = [0; ID_SIZE];
+        id[..4].copy_from_slice(&n.to_be_bytes());
+        id[31] = (n % 251) as u8;
+        id
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for n in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut o = Vec::new();
+            encode_varint(n, &mut o);
+            assert_eq!(decode_varint(&mut o.as_slice()).unwrap(), n);
+        }
+        let mut o = Vec::new();
+        encode_varint(300, &mut o);
+        assert_eq!(o, vec![0x82, 0x2c]);
+    }
+
+    #[test]
+    fn reconciles_missing_ids_both_ways() {
+        // Plenty of shared items so the first round has to split into fingerprints.
+        let shared: Vec<(u64, Id)> = (0..500).map(|n| (1_000 + n as u64 / 3, id(n))).collect();
+        let only_client: Vec<(u64, Id)> = vec![(1_010, id(10_001)), (5_000, id(10_002))];
+        let only_relay: Vec<(u64, Id)> = (0..40)
+            .map(|n| (1_050 + n, id(20_000 + n as u32)))
+            .collect();
+
+        let mut client = Negentropy::new(shared.iter().chain(&only_client).copied());
+        let mut relay = Negentropy::new(shared.iter().chain(&only_relay).copied());
+
+        let (mut have, mut need) = (Vec::new(), Vec::new());
+        let mut message = Some(client.initiate());
+        let mut rounds = 0;
+        while let Some(query) = message {
+            let response = relay.respond(&query).unwrap();
+            message = client.reconcile(&response, &mut have, &mut need).unwrap();
+            rounds += 1;
+            assert!(rounds < 10, "reconciliation didn't converge");
+        }
+
+        have.sort();
+        need.sort();
+        let mut expected_need: Vec<Id> = only_relay.iter().map(|(_, id)| *id).collect();
+        expected_need.sort();
+        assert_eq!(have, vec![id(10_001), id(10_002)]);
+        assert_eq!(need, expected_need);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = vec![0x61, 0x00, 0xff, 0x2c];
+        assert_eq!(to_hex(&bytes), "6100ff2c");
+        assert_eq!(from_hex("6100ff2c").unwrap(), bytes);
+        assert!(from_hex("6").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Events we published and are still waiting on, kept so relays that refuse one for a reason
+//! we can do something about get it again: later when they're rate limiting us, or made again
+//! with the proof of work they want (NIP-13).
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How many times a rate-limited event is sent again to a relay before we give up.
+pub const RATE_LIMIT_RETRIES: u32 = 3;
+/// How long to wait before the first retry. Each one after waits twice as long.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// An event published with [`crate::relay::RelayPool::publish`].
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    /// The EVENT message, ready to send again.
+    pub payload: String,
+    /// Copies made again for one relay, by url: their id and EVENT message.
+    pub copies: HashMap<String, (String, String)>,
+    /// Rate-limited tries so far, by url.
+    pub attempts: HashMap<String, u32>,
+    /// Relays to send it to again once the time comes.
+    pub retries: Vec<(String, Instant)>,
+    /// Relays that want this many bits of proof of work, waiting for a copy with it.
+    pub pow_wanted: Vec<(String, u8)>,
+}
+
+impl Outgoing {
+    pub fn new(payload: String) -> Self {
+        Self {
+            payload,
+            copies: HashMap::new(),
+            attempts: HashMap::new(),
+            retries: Vec::new(),
+            pow_wanted: Vec::new(),
+        }
+    }
+
+    /// Sends the event to `url` again after a while, waiting longer each time. False once it
+    /// has been tried enough.
+    pub fn retry_later(&mut self, url: &str, now: Instant) -> bool {
+        let attempts = self.attempts.entry(url.to_string()).or_default();
+        if *attempts >= RATE_LIMIT_RETRIES {
+            return false;
+        }
+        let backoff = RATE_LIMIT_BACKOFF * 2u32.pow(*attempts);
+        *attempts += 1;
+        self.retries.push((url.to_string(), now + backoff));
+        true
+    }
+
+    /// What to send `url`: its own copy if it has one.
+    pub fn payload_for(&self, url: &str) -> &str {
+        self.copies
+            .get(url)
+            .map_or(self.payload.as_str(), |(_, payload)| payload.as_str())
+    }
+
+    pub fn is_copy(&self, event_id: &str) -> bool {
+        self.copies.values().any(|(id, _)| id == event_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pow;
+    use crate::relay::mock::{pump_until, MockRelay};
+    use crate::relay::{Ack, RelayPool};
+    use nostr::{EventBuilder, Keys, Kind};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn rate_limited_events_are_sent_again() -> anyhow::Result<()> {
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&Keys::generate())?;
+        let relay = MockRelay::new();
+        relay.rate_limit(1);
+        let mut pool = RelayPool::new();
+        pool.add_mock("wss://busy.example.com", &relay);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        let event_id = event.id.to_hex();
+        pool.publish(&event).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.acks(&event_id).is_some_and(|acks| acks.is_empty()) {
+            assert!(Instant::now() < deadline, "the relay never took it");
+            pool.try_recv();
+            pool.keepalive(|| {});
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            pool.acks(&event_id)
+                .and_then(|acks| acks.get("wss://busy.example.com")),
+            Some(&Ack::Accepted)
+        );
+        assert!(pool.take_errors().is_empty());
+        assert_eq!(relay.events(), vec![event]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_events_are_left_to_the_send_result() -> anyhow::Result<()> {
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&Keys::generate())?;
+        let mut json = serde_json::to_value(&event)?;
+        json["content"] = "changed after signing".into();
+        let forged: nostr::Event = serde_json::from_value(json)?;
+        let url = "wss://strict.example.com";
+        let relay = MockRelay::new();
+        let mut pool = RelayPool::new();
+        pool.add_mock(url, &relay);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        let event_id = forged.id.to_hex();
+        pool.publish(&forged).unwrap();
+        pump_until(&mut pool, "the relay answering", |pool| {
+            pool.acks(&event_id).is_some_and(|acks| !acks.is_empty())
+        });
+        assert_eq!(
+            pool.acks(&event_id).and_then(|acks| acks.get(url)),
+            Some(&Ack::Rejected("invalid: bad signature".to_string()))
+        );
+        assert!(pool.take_errors().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn waiting_for_acks_collects_them() -> anyhow::Result<()> {
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&Keys::generate())?;
+        let relay = MockRelay::new();
+        let mut pool = RelayPool::new();
+        pool.add_mock("wss://quick.example.com", &relay);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        let event_id = event.id.to_hex();
+        pool.publish(&event).unwrap();
+        assert!(pool.awaiting_acks());
+        assert!(pool.wait_for_acks(Duration::from_secs(5)));
+        assert!(!pool.awaiting_acks());
+        assert_eq!(
+            pool.acks(&event_id)
+                .and_then(|acks| acks.get("wss://quick.example.com")),
+            Some(&Ack::Accepted)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn copies_with_proof_of_work_answer_for_the_event() -> anyhow::Result<()> {
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::GiftWrap, "").sign_with_keys(&keys)?;
+        let relay = MockRelay::new();
+        relay.require_pow(16);
+        let url = "wss://picky.example.com";
+        let mut pool = RelayPool::new();
+        pool.set_max_pow(20);
+        pool.add_mock(url, &relay);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        let event_id = event.id.to_hex();
+        pool.publish(&event).unwrap();
+        let mut requests = Vec::new();
+        pump_until(&mut pool, "asking for proof of work", |pool| {
+            requests = pool.take_pow_requests(&event_id);
+            !requests.is_empty()
+        });
+        assert_eq!(requests, vec![(url.to_string(), 16)]);
+        let nonce = pow::mine(
+            &keys.public_key(),
+            event.created_at,
+            event.kind,
+            &[],
+            &event.content,
+            16,
+        );
+        let copy = EventBuilder::new(Kind::GiftWrap, "")
+            .tag(nonce)
+            .custom_created_at(event.created_at)
+            .sign_with_keys(&keys)?;
+        pool.publish_copy(&event_id, url, &copy);
+        pump_until(&mut pool, "taking the copy", |pool| {
+            !pool.acks(&event_id).is_some_and(|acks| acks.is_empty())
+        });
+        assert_eq!(
+            pool.acks(&event_id).and_then(|acks| acks.get(url)),
+            Some(&Ack::Accepted)
+        );
+        assert!(pool.take_errors().is_empty());
+        assert_eq!(relay.events(), vec![copy]);
+        Ok(())
+    }
+}
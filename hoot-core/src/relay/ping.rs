@@ -0,0 +1,231 @@
+//! Websocket pings, to tell a relay that's quiet from one that's gone. A socket can die
+//! without ever closing, e.g. when the network changes under it, so every connected relay is
+//! pinged on its own timer and counts as disconnected when the pong doesn't come back in time.
+//!
+//! The timer is a [`PingTimer`] thread next to the connection rather than part of the frame
+//! loop, so pings go out on time and a dead socket is noticed while the window isn't drawn.
+
+use ewebsock::WsMessage;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Storage key for the [`PingSettings`].
+pub const PING_SETTINGS_KEY: &str = "relay_ping";
+
+/// How often relays are pinged and how long they get to answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PingSettings {
+    pub interval_secs: u32,
+    pub timeout_secs: u32,
+}
+
+impl Default for PingSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            timeout_secs: 15,
+        }
+    }
+}
+
+impl PingSettings {
+    /// Time between pings to a relay, at least a second.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1).into())
+    }
+
+    /// How long a ping can go unanswered before the relay counts as disconnected.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1).into())
+    }
+}
+
+/// When a connection was last pinged and whether its pong is still out, shared by the relay
+/// and its [`PingTimer`].
+#[derive(Debug, Default)]
+pub(crate) struct PingClock {
+    /// When the ping still waiting for its pong was sent.
+    sent_at: Option<Instant>,
+    /// When we last pinged the connection, `None` until we have.
+    last_ping: Option<Instant>,
+    /// Pings the timer sent that the relay hasn't put in its trace yet.
+    pub(crate) unrecorded: usize,
+    /// The last ping went unanswered for too long.
+    pub(crate) timed_out: bool,
+}
+
+/// What a [`PingClock`] has due.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Tick {
+    Ping,
+    /// Give up on the connection.
+    TimedOut,
+    /// Nothing until then.
+    Wait(Instant),
+}
+
+impl PingClock {
+    pub(crate) fn tick(&mut self, now: Instant, settings: &PingSettings) -> Tick {
+        let timeout = self.sent_at.map(|sent_at| sent_at + settings.timeout());
+        if timeout.is_some_and(|timeout| now >= timeout) {
+            self.timed_out = true;
+            return Tick::TimedOut;
+        }
+        let due = self
+            .last_ping
+            .map_or(now, |last| last + settings.interval());
+        if now >= due {
+            return Tick::Ping;
+        }
+        Tick::Wait(timeout.map_or(due, |timeout| timeout.min(due)))
+    }
+
+    pub(crate) fn pinged(&mut self, now: Instant) {
+        // An earlier ping still unanswered keeps its deadline.
+        self.sent_at.get_or_insert(now);
+        self.last_ping = Some(now);
+    }
+
+    /// The round trip time of the ping the pong answers.
+    pub(crate) fn pong(&mut self) -> Option<Duration> {
+        self.sent_at.take().map(|sent_at| sent_at.elapsed())
+    }
+}
+
+pub(crate) fn lock(clock: &Mutex<PingClock>) -> MutexGuard<'_, PingClock> {
+    clock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Pings one connection from a thread of its own. Dropping it stops the thread.
+pub(crate) struct PingTimer {
+    settings: Sender<PingSettings>,
+}
+
+impl PingTimer {
+    /// Starts pinging through `send`, calling `wake_up` when the connection stops answering so
+    /// the relay can give up on it.
+    pub(crate) fn start(
+        url: String,
+        clock: Arc<Mutex<PingClock>>,
+        settings: PingSettings,
+        send: impl Fn(WsMessage) + Send + 'static,
+        wake_up: Arc<dyn Fn() + Send + Sync>,
+    ) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let mut settings = settings;
+            loop {
+                let now = Instant::now();
+                let tick = lock(&clock).tick(now, &settings);
+                let until = match tick {
+                    Tick::Ping => {
+                        send(WsMessage::Ping(Vec::new()));
+                        let mut clock = lock(&clock);
+                        clock.pinged(now);
+                        clock.unrecorded += 1;
+                        debug!("Ping sent to {}", url);
+                        continue;
+                    }
+                    Tick::TimedOut => {
+                        warn!(
+                            "{} didn't answer a ping in {} seconds",
+                            url,
+                            settings.timeout().as_secs()
+                        );
+                        wake_up();
+                        return;
+                    }
+                    Tick::Wait(until) => until,
+                };
+                match receiver.recv_timeout(until.saturating_duration_since(now)) {
+                    Ok(changed) => settings = changed,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+        Self { settings: sender }
+    }
+
+    /// Changes how often the connection is pinged, from the next ping on.
+    pub(crate) fn set_settings(&self, settings: PingSettings) {
+        // The thread only stops once the timer is dropped.
+        let _ = self.settings.send(settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay::mock::MockRelay;
+    use crate::relay::{Relay, RelayStatus};
+
+    #[test]
+    fn pings_on_schedule_and_times_out() {
+        let settings = PingSettings::default();
+        let mut clock = PingClock::default();
+        let start = Instant::now();
+        assert_eq!(clock.tick(start, &settings), Tick::Ping);
+        clock.pinged(start);
+        assert_eq!(
+            clock.tick(start, &settings),
+            Tick::Wait(start + settings.timeout())
+        );
+
+        // Answered pings only move the next one along.
+        assert!(clock.pong().is_some());
+        assert_eq!(
+            clock.tick(start + settings.timeout(), &settings),
+            Tick::Wait(start + settings.interval())
+        );
+
+        let later = start + settings.interval();
+        assert_eq!(clock.tick(later, &settings), Tick::Ping);
+        clock.pinged(later);
+        assert_eq!(
+            clock.tick(later + settings.timeout(), &settings),
+            Tick::TimedOut
+        );
+        assert!(clock.timed_out);
+    }
+
+    #[test]
+    fn relays_that_stop_answering_are_disconnected() {
+        let settings = PingSettings {
+            interval_secs: 1,
+            timeout_secs: 1,
+        };
+        let mock = MockRelay::new();
+        let mut relay = Relay::new_mock("wss://relay.example.com", &mock);
+        relay.set_ping_settings(settings);
+        while relay.try_recv().is_some() {}
+        assert!(relay.status == RelayStatus::Connected);
+
+        // The timer pings without anyone asking, and the pong comes back.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while relay.rtt.is_none() {
+            assert!(Instant::now() < deadline, "no pong came back");
+            if let Some(ewebsock::WsEvent::Message(WsMessage::Pong(_))) = relay.try_recv() {
+                relay.record_pong();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!relay.check_ping());
+
+        mock.ignore_pings();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !relay.check_ping() {
+            assert!(Instant::now() < deadline, "the relay was never given up on");
+            while relay.try_recv().is_some() {}
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(relay.status == RelayStatus::Disconnected);
+    }
+}
@@ -0,0 +1,1587 @@
+use crate::error::{Error, Result};
+use crate::relay::confirm::{Confirmation, Stored};
+use crate::relay::message::{ClientMessage, RelayMessage};
+use crate::relay::negentropy::{self, Id, Negentropy};
+use crate::relay::outbox::Outgoing;
+use crate::relay::preflight::{Preflight, PreflightStatus};
+use crate::relay::seen::{RelayEventStats, SeenEvents};
+use crate::relay::sync::{SyncSession, SyncStats, SyncStatus, SyncWindow};
+use crate::relay::Subscription;
+use crate::relay::{error, PingSettings, Rejection, Relay, RelayError, RelayStatus};
+use crate::relay::{RelayTrust, RelayUsage};
+use ewebsock::{WsEvent, WsMessage};
+use nostr::types::Filter;
+use nostr::{Event, EventId, Timestamp};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+pub const RELAY_RECONNECT_SECONDS: u64 = 5;
+
+/// Storage key for the list of relay urls the user has configured.
+pub const RELAYS_KEY: &str = "relays";
+/// Storage key for how far back each relay's mail is read, see [`SyncWindow`].
+pub const RELAY_WINDOWS_KEY: &str = "relay_windows";
+/// Storage key for the relays charging a fee that the user told us they've paid.
+pub const PAID_RELAYS_KEY: &str = "paid_relays";
+/// Relays we connect to when the user hasn't configured any yet.
+pub const DEFAULT_RELAYS: [&str; 2] = ["wss://relay.chakany.systems", "wss://talon.quest"];
+
+/// How many recent event ids we remember, to drop copies coming from other relays and to tell
+/// which relay delivered an event first.
+const SEEN_EVENTS_CAPACITY: usize = 10_000;
+
+/// Most ids we ask for in one REQ after a sync.
+const SYNC_FETCH_CHUNK: usize = 500;
+
+/// How long a relay gets to answer a preflight COUNT before we download the events instead.
+const PREFLIGHT_COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+/// When a preflight stops waiting for relays that haven't answered.
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(20);
+/// Most events a preflight downloads from a relay that can't count them.
+const PREFLIGHT_FETCH_LIMIT: u64 = 500;
+
+/// Where a message was sent, in the order the relays got it.
+#[derive(Debug, Clone, Default)]
+pub struct SendReport {
+    /// Relay urls fastest first, with the round trip time that put them there.
+    pub order: Vec<(String, Option<Duration>)>,
+    pub failed: Vec<String>,
+}
+
+impl SendReport {
+    pub fn accepted(&self) -> usize {
+        self.order.len() - self.failed.len()
+    }
+}
+
+/// What a relay answered to an event we published (NIP-20 OK).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ack {
+    Accepted,
+    Rejected(String),
+}
+
+pub struct RelayPool {
+    pub relays: HashMap<String, Relay>,
+    pub subscriptions: HashMap<String, Subscription>,
+    /// NIP-45 COUNT requests, asked again whenever a relay (re)connects.
+    counts: HashMap<String, Subscription>,
+    last_reconnect_attempt: Instant,
+    ping_settings: PingSettings,
+    trace_enabled: bool,
+    /// What to reconcile with relays that support NIP-77, and the (created_at, id) pairs we
+    /// already have for it.
+    sync_target: Option<(Filter, Vec<(u64, Id)>)>,
+    syncs: HashMap<String, SyncSession>,
+    pub sync_stats: HashMap<String, SyncStats>,
+    /// One-shot REQs for ids found missing by a sync, closed again on EOSE.
+    sync_fetches: HashSet<(String, String)>,
+    /// Events some relay delivered, for `event_stats`.
+    delivered_events: SeenEvents,
+    /// Events the app verified, whose copies can be dropped. Ids are only added once checked,
+    /// so a forged copy under a real id can't get the genuine one dropped.
+    verified_events: SeenEvents,
+    pub event_stats: HashMap<String, RelayEventStats>,
+    /// OK replies per relay for events someone is waiting on, by event id.
+    acks: HashMap<String, HashMap<String, Ack>>,
+    /// Events sent with [`RelayPool::publish`] that are still waited on, by event id.
+    outbox: HashMap<String, Outgoing>,
+    /// The most proof of work (NIP-13) we'll do for a relay that wants it, in bits.
+    max_pow: u8,
+    /// Counts of what relays hold for accounts about to be added, by the caller's key.
+    preflights: HashMap<String, Preflight>,
+    /// Events we sent being asked back from the relays that took them, by event id.
+    confirmations: HashMap<String, Confirmation>,
+    /// Every (event id, relay url) delivery since the app last took them, copies included.
+    sightings: Vec<(String, String)>,
+    /// What relays did that the user should hear about, since the app last looked.
+    errors: Vec<RelayError>,
+    /// Relays whose connection failed and hasn't come back since, so a relay that's down
+    /// is reported once rather than on every reconnect.
+    failing: HashSet<String>,
+    /// How far back to read mail from relays that don't get the full history.
+    windows: HashMap<String, SyncWindow>,
+    /// Relays we don't fully trust with our mail.
+    trust: HashMap<String, RelayTrust>,
+    /// Saved traffic of relays that haven't been added yet, handed to them when they are.
+    usage: HashMap<String, RelayUsage>,
+    /// Relays that want paying first. They'd only refuse our REQs, so they get none until
+    /// the user says they've paid.
+    awaiting_payment: HashSet<String>,
+}
+
+impl RelayPool {
+    pub fn new() -> Self {
+        Self {
+            relays: HashMap::new(),
+            subscriptions: HashMap::new(),
+            counts: HashMap::new(),
+            last_reconnect_attempt: Instant::now(),
+            ping_settings: PingSettings::default(),
+            trace_enabled: false,
+            sync_target: None,
+            syncs: HashMap::new(),
+            sync_stats: HashMap::new(),
+            sync_fetches: HashSet::new(),
+            delivered_events: SeenEvents::new(SEEN_EVENTS_CAPACITY),
+            verified_events: SeenEvents::new(SEEN_EVENTS_CAPACITY),
+            event_stats: HashMap::new(),
+            acks: HashMap::new(),
+            outbox: HashMap::new(),
+            max_pow: 0,
+            preflights: HashMap::new(),
+            confirmations: HashMap::new(),
+            sightings: Vec::new(),
+            errors: Vec::new(),
+            failing: HashSet::new(),
+            windows: HashMap::new(),
+            trust: HashMap::new(),
+            usage: HashMap::new(),
+            awaiting_payment: HashSet::new(),
+        }
+    }
+
+    pub fn get_last_reconnect_attempt(&mut self) -> Instant {
+        return self.last_reconnect_attempt;
+    }
+
+    pub fn keepalive(&mut self, wake_up: impl Fn() + Send + Sync + Clone + 'static) {
+        let now = Instant::now();
+
+        // Check disconnected relays
+        if now.duration_since(self.last_reconnect_attempt)
+            >= Duration::from_secs(RELAY_RECONNECT_SECONDS)
+        {
+            for relay in self.relays.values_mut() {
+                if relay.status != RelayStatus::Connected {
+                    relay.status = RelayStatus::Connecting;
+                    relay.reconnect(wake_up.clone());
+                }
+            }
+            self.last_reconnect_attempt = now;
+        }
+
+        for relay in self.relays.values_mut() {
+            if relay.check_ping() && self.failing.insert(relay.url.clone()) {
+                self.errors.push(RelayError::Connect {
+                    url: relay.url.clone(),
+                    reason: "stopped answering pings".to_string(),
+                });
+            }
+        }
+
+        self.check_preflights(now);
+        self.check_confirmations(now);
+        self.send_retries(now);
+    }
+
+    /// When [`RelayPool::keepalive`] next has something to do: a reconnect, or a preflight or
+    /// confirmation timing out. `None` while it has nothing to wait for. Pings keep their own
+    /// time and wake the app when a relay stops answering them.
+    pub fn next_keepalive(&self) -> Option<Instant> {
+        let mut deadlines = Vec::new();
+        if self
+            .relays
+            .values()
+            .any(|relay| relay.status != RelayStatus::Connected)
+        {
+            deadlines
+                .push(self.last_reconnect_attempt + Duration::from_secs(RELAY_RECONNECT_SECONDS));
+        }
+        for preflight in self.preflights.values() {
+            for status in preflight.relays.values() {
+                match status {
+                    PreflightStatus::Counting => {
+                        deadlines.push(preflight.started + PREFLIGHT_COUNT_TIMEOUT)
+                    }
+                    PreflightStatus::Fetching(_) => {
+                        deadlines.push(preflight.started + PREFLIGHT_TIMEOUT)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        deadlines.extend(
+            self.confirmations
+                .values()
+                .filter(|confirmation| !confirmation.is_done())
+                .map(Confirmation::deadline),
+        );
+        deadlines.extend(
+            self.outbox
+                .values()
+                .flat_map(|outgoing| outgoing.retries.iter().map(|(_, at)| *at)),
+        );
+        deadlines.into_iter().min()
+    }
+
+    pub fn add_subscription(&mut self, sub: Subscription) -> Result<()> {
+        self.subscriptions.insert(sub.id.clone(), sub.clone());
+
+        // Every relay gets the filters cut to its own sync window and trust.
+        let messages = self
+            .send_order()
+            .into_iter()
+            .filter(|(url, _)| self.reads_from(url))
+            .filter_map(|(url, _)| {
+                let filters = self.windowed(&url, &sub.filters);
+                if filters.is_empty() {
+                    return None;
+                }
+                let message = ClientMessage::Req {
+                    subscription_id: sub.id.clone(),
+                    filters,
+                };
+                Some((url, message))
+            })
+            .collect();
+        self.send_each(messages)
+    }
+
+    /// Replaces the COUNT requests we keep asking relays, and asks the connected ones now.
+    /// Relays without NIP-45 support just refuse them.
+    pub fn set_counts(&mut self, counts: Vec<Subscription>) -> Result<()> {
+        self.counts = counts
+            .into_iter()
+            .map(|count| (count.id.clone(), count))
+            .collect();
+
+        let urls: Vec<String> = self
+            .send_order()
+            .into_iter()
+            .map(|(url, _)| url)
+            .filter(|url| self.reads_from(url))
+            .collect();
+        let mut messages = Vec::new();
+        for url in urls {
+            for count in self.counts.values() {
+                let filters = self.windowed(&url, &count.filters);
+                if filters.is_empty() {
+                    continue;
+                }
+                let message = ClientMessage::Count {
+                    subscription_id: count.id.clone(),
+                    filters,
+                };
+                messages.push((url.clone(), message));
+            }
+        }
+        self.send_each(messages)
+    }
+
+    /// Sends each message to its relay. Only fails if every one of them failed.
+    fn send_each(&mut self, messages: Vec<(String, ClientMessage)>) -> Result<()> {
+        let mut sent = messages.is_empty();
+        for (url, message) in &messages {
+            sent |= self.send_to(url, message);
+        }
+        if sent {
+            Ok(())
+        } else {
+            Err(Error::Generic("no relay took the request".to_string()))
+        }
+    }
+
+    /// How far back mail is read from `url`.
+    pub fn window(&self, url: &str) -> SyncWindow {
+        self.windows.get(url).copied().unwrap_or_default()
+    }
+
+    /// Every relay that doesn't get the full history, to be saved.
+    pub fn windows(&self) -> &HashMap<String, SyncWindow> {
+        &self.windows
+    }
+
+    /// Changes how far back mail is read from `url`, asking it again right away if it's
+    /// connected.
+    pub fn set_window(&mut self, url: &str, window: SyncWindow) {
+        if window == SyncWindow::FullHistory {
+            self.windows.remove(url);
+        } else {
+            self.windows.insert(url.to_string(), window);
+        }
+
+        self.resubscribe(url);
+    }
+
+    /// How far we trust `url` with our mail.
+    pub fn trust(&self, url: &str) -> RelayTrust {
+        self.trust.get(url).copied().unwrap_or_default()
+    }
+
+    /// Every relay that isn't fully trusted, to be saved.
+    pub fn trusts(&self) -> &HashMap<String, RelayTrust> {
+        &self.trust
+    }
+
+    /// Whether any relay is distrusted, so mail has to be checked for where it came from.
+    pub fn has_distrusted(&self) -> bool {
+        self.trust
+            .values()
+            .any(|trust| *trust == RelayTrust::Distrusted)
+    }
+
+    /// Changes how far we trust `url`, asking it again right away for what it may now serve.
+    pub fn set_trust(&mut self, url: &str, trust: RelayTrust) {
+        if trust == RelayTrust::Trusted {
+            self.trust.remove(url);
+        } else {
+            self.trust.insert(url.to_string(), trust);
+        }
+
+        self.resubscribe(url);
+    }
+
+    /// This month's traffic with `url`.
+    pub fn usage(&self, url: &str) -> RelayUsage {
+        self.relays
+            .get(url)
+            .map(|relay| relay.usage)
+            .or_else(|| self.usage.get(url).copied())
+            .unwrap_or_default()
+            .current()
+    }
+
+    /// Every relay's traffic, to be saved.
+    pub fn usages(&self) -> HashMap<String, RelayUsage> {
+        let mut usages = self.usage.clone();
+        for (url, relay) in &self.relays {
+            usages.insert(url.clone(), relay.usage);
+        }
+        usages
+    }
+
+    /// Restores the saved traffic of `url`.
+    pub fn set_usage(&mut self, url: &str, usage: RelayUsage) {
+        match self.relays.get_mut(url) {
+            Some(relay) => relay.usage = usage,
+            None => {
+                self.usage.insert(url.to_string(), usage);
+            }
+        }
+    }
+
+    /// Sets the soft cap on a month's traffic with `url`, in bytes.
+    pub fn set_cap(&mut self, url: &str, cap: Option<u64>) {
+        let mut usage = self.usage(url);
+        usage.cap = cap;
+        self.set_usage(url, usage);
+    }
+
+    /// Metered relays that have used most of their cap this month, to warn about before
+    /// sending or fetching a lot through them.
+    pub fn near_cap(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self
+            .usages()
+            .into_keys()
+            .filter(|url| self.usage(url).near_cap())
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    /// Whether `url` wants paying before it serves us.
+    pub fn is_awaiting_payment(&self, url: &str) -> bool {
+        self.awaiting_payment.contains(url)
+    }
+
+    /// Stops asking `url` for anything until it's paid, or starts again once it is.
+    pub fn set_awaiting_payment(&mut self, url: &str, awaiting: bool) {
+        if !awaiting {
+            if self.awaiting_payment.remove(url) {
+                self.resubscribe(url);
+            }
+            return;
+        }
+        if self.awaiting_payment.insert(url.to_string()) {
+            info!("Not asking {} for mail until it's paid", url);
+        }
+    }
+
+    /// Whether we send `url` our subscriptions.
+    fn reads_from(&self, url: &str) -> bool {
+        !self.awaiting_payment.contains(url)
+    }
+
+    /// Asks `url` for everything we're subscribed to again, if it's connected.
+    fn resubscribe(&mut self, url: &str) {
+        let connected = self
+            .relays
+            .get(url)
+            .is_some_and(|relay| relay.status == RelayStatus::Connected);
+        if !connected || !self.reads_from(url) {
+            return;
+        }
+        // Subscriptions the relay may no longer serve are closed instead.
+        let reqs: Vec<ClientMessage> = self
+            .subscriptions
+            .values()
+            .map(|sub| match self.windowed(url, &sub.filters) {
+                filters if filters.is_empty() => ClientMessage::Close {
+                    subscription_id: sub.id.clone(),
+                },
+                filters => ClientMessage::Req {
+                    subscription_id: sub.id.clone(),
+                    filters,
+                },
+            })
+            .chain(self.counts.values().filter_map(|count| {
+                let filters = self.windowed(url, &count.filters);
+                (!filters.is_empty()).then(|| ClientMessage::Count {
+                    subscription_id: count.id.clone(),
+                    filters,
+                })
+            }))
+            .collect();
+        for message in &reqs {
+            self.send_to(url, message);
+        }
+        self.start_sync(url);
+    }
+
+    /// Sends a relay that just connected everything we're subscribed to.
+    fn opened(&mut self, url: &str) {
+        let reqs: Vec<ClientMessage> = self
+            .subscriptions
+            .values()
+            .filter_map(|sub| {
+                let filters = self.windowed(url, &sub.filters);
+                (!filters.is_empty()).then(|| ClientMessage::Req {
+                    subscription_id: sub.id.clone(),
+                    filters,
+                })
+            })
+            .chain(self.counts.values().filter_map(|count| {
+                let filters = self.windowed(url, &count.filters);
+                (!filters.is_empty()).then(|| ClientMessage::Count {
+                    subscription_id: count.id.clone(),
+                    filters,
+                })
+            }))
+            .collect();
+        for message in &reqs {
+            self.send_to(url, message);
+        }
+        // The relay's ping timer measures latency right away, so sends can be ordered by it.
+        self.start_sync(url);
+        self.join_preflights(url);
+    }
+
+    /// `filters` as `url` should get them, limited to its sync window and to what we trust it
+    /// with. Empty if it shouldn't be asked at all.
+    fn windowed(&self, url: &str, filters: &[Filter]) -> Vec<Filter> {
+        let window = self.window(url);
+        let trust = self.trust(url);
+        let now = Timestamp::now().as_u64();
+        filters
+            .iter()
+            .filter(|filter| trust.allows_filter(filter))
+            .map(|filter| window.apply(filter, now))
+            .collect()
+    }
+
+    pub fn add_url(
+        &mut self,
+        url: String,
+        wake_up: impl Fn() + Send + Sync + 'static,
+    ) -> Result<()> {
+        let mut relay = Relay::new_with_wakeup(url.clone(), wake_up);
+        relay.set_ping_settings(self.ping_settings);
+        relay.trace.enabled = self.trace_enabled;
+        relay.usage = self.usage.remove(&url).unwrap_or_default();
+        self.relays.insert(url, relay);
+
+        Ok(())
+    }
+
+    /// Adds a [`MockRelay`](crate::relay::mock::MockRelay) as if it were at `url`.
+    #[cfg(any(test, feature = "mock-relay"))]
+    pub fn add_mock(&mut self, url: &str, relay: &crate::relay::mock::MockRelay) {
+        let mut mock = Relay::new_mock(url, relay);
+        mock.set_ping_settings(self.ping_settings);
+        mock.trace.enabled = self.trace_enabled;
+        mock.usage = self.usage.remove(url).unwrap_or_default();
+        self.relays.insert(url.to_string(), mock);
+    }
+
+    /// Removes the relay from the pool, closing our subscriptions on it and then the
+    /// connection itself.
+    pub fn remove_url(&mut self, url: &str) -> Option<Relay> {
+        let mut relay = self.relays.remove(url)?;
+        self.syncs.remove(url);
+        self.sync_stats.remove(url);
+        self.sync_fetches.retain(|(fetch_url, _)| fetch_url != url);
+        self.event_stats.remove(url);
+        self.windows.remove(url);
+        self.trust.remove(url);
+        self.usage.remove(url);
+        self.awaiting_payment.remove(url);
+        for preflight in self.preflights.values_mut() {
+            preflight.relays.remove(url);
+        }
+
+        Self::close_relay(&mut relay, self.subscriptions.keys());
+        Some(relay)
+    }
+
+    /// Closes our subscriptions on every relay and then the connections, for when the app quits.
+    pub fn close_all(&mut self) {
+        for relay in self.relays.values_mut() {
+            Self::close_relay(relay, self.subscriptions.keys());
+        }
+    }
+
+    fn close_relay<'a>(relay: &mut Relay, subscription_ids: impl Iterator<Item = &'a String>) {
+        if relay.status == RelayStatus::Connected {
+            for subscription_id in subscription_ids {
+                let client_message = ClientMessage::Close {
+                    subscription_id: subscription_id.clone(),
+                };
+                let payload = match serde_json::to_string(&client_message) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        error!("could not serialize subscription close: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = relay.send(WsMessage::Text(payload)) {
+                    error!("could not close subscription on {}: {:?}", relay.url, e);
+                }
+            }
+        }
+
+        relay.close();
+    }
+
+    /// Urls of every relay in the pool, sorted so they persist in a stable order.
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self.relays.keys().cloned().collect();
+        urls.sort();
+        urls
+    }
+
+    pub fn connected_count(&self) -> usize {
+        self.relays
+            .values()
+            .filter(|relay| relay.status == RelayStatus::Connected)
+            .count()
+    }
+
+    pub fn try_recv(&mut self) -> Option<String> {
+        for relay in self.relays.values_mut() {
+            let relay_url = relay.url.clone();
+            if let Some(event) = relay.try_recv() {
+                use WsEvent::*;
+                match event {
+                    Message(message) => {
+                        return self.handle_message(relay_url, message);
+                    }
+                    Error(reason) => {
+                        if self.failing.insert(relay_url.clone()) {
+                            self.errors.push(RelayError::Connect {
+                                url: relay_url,
+                                reason,
+                            });
+                        }
+                        return None;
+                    }
+                    // A relay waiting to be paid would only refuse our subscriptions.
+                    Opened if self.awaiting_payment.contains(&relay_url) => {
+                        self.failing.remove(&relay_url);
+                        return None;
+                    }
+                    Opened => {
+                        self.failing.remove(&relay_url);
+                        self.opened(&relay_url);
+                        return None;
+                    }
+                    _ => {
+                        // we only want to know when the connection opens
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn handle_message(&mut self, url: String, message: WsMessage) -> Option<String> {
+        use WsMessage::*;
+        match message {
+            Text(txt) if self.should_forward(&url, &txt) => {
+                return Some(txt);
+            }
+            Binary(..) => {
+                error!("recived binary messsage, your move semisol");
+            }
+            Ping(m) => {
+                if let Some(relay) = self.relays.get_mut(&url) {
+                    if let Err(e) = relay.send(WsMessage::Pong(m)) {
+                        error!("error when sending websocket message {:?}", e);
+                    }
+                }
+            }
+            Pong(_) => {
+                if let Some(relay) = self.relays.get_mut(&url) {
+                    relay.record_pong();
+                }
+            }
+            _ => {
+                // who cares
+            }
+        }
+
+        None
+    }
+
+    /// Reconciles `filter` with every relay that supports NIP-77, now and whenever one
+    /// (re)connects, so we only download the events we're missing. `items` are the
+    /// (created_at, hex id) pairs we already have.
+    pub fn set_sync(&mut self, filter: Filter, items: Vec<(u64, String)>) {
+        let items = items
+            .into_iter()
+            .filter_map(|(created_at, id)| {
+                let id: Id = negentropy::from_hex(&id).ok()?.try_into().ok()?;
+                Some((created_at, id))
+            })
+            .collect();
+        self.sync_target = Some((filter, items));
+
+        let connected: Vec<String> = self
+            .relays
+            .values()
+            .filter(|relay| relay.status == RelayStatus::Connected)
+            .map(|relay| relay.url.clone())
+            .collect();
+        for url in connected {
+            self.start_sync(&url);
+        }
+    }
+
+    fn start_sync(&mut self, url: &str) {
+        let Some((filter, items)) = &self.sync_target else {
+            return;
+        };
+        if !self.reads_from(url) {
+            return;
+        }
+        // Only reconcile what's inside the relay's window, or everything older would look
+        // missing on its side.
+        let window = self.window(url);
+        let now = Timestamp::now().as_u64();
+        let since = window.since(now).unwrap_or(0);
+        let filter = window.apply(filter, now);
+        let items: Vec<(u64, Id)> = items
+            .iter()
+            .filter(|(created_at, _)| *created_at >= since)
+            .copied()
+            .collect();
+        // With nothing stored yet a plain REQ fetches the same events.
+        if items.is_empty() {
+            return;
+        }
+
+        let mut negentropy = Negentropy::new(items.iter().copied());
+        let subscription_id = Subscription::default().id;
+        let message = ClientMessage::NegOpen {
+            subscription_id: subscription_id.clone(),
+            filter,
+            initial_message: negentropy::to_hex(&negentropy.initiate()),
+        };
+        let local = items.len();
+
+        if let Some(old) = self.syncs.remove(url) {
+            self.send_to(
+                url,
+                &ClientMessage::NegClose {
+                    subscription_id: old.subscription_id,
+                },
+            );
+        }
+        if !self.send_to(url, &message) {
+            return;
+        }
+        self.syncs.insert(
+            url.to_string(),
+            SyncSession {
+                subscription_id,
+                negentropy,
+                local,
+                have: Vec::new(),
+                need: Vec::new(),
+            },
+        );
+        self.sync_stats
+            .insert(url.to_string(), SyncStats::default());
+    }
+
+    /// Deals with the messages the pool handles itself. Returns whether the app should see
+    /// `txt` too.
+    fn should_forward(&mut self, url: &str, txt: &str) -> bool {
+        #[cfg(feature = "profiling")]
+        puffin::profile_function!();
+        let message = match RelayMessage::from_json(txt) {
+            Ok(message) => message,
+            // Let the app report it.
+            Err(_) => return true,
+        };
+        if self.handle_preflight_message(url, &message) || self.handle_confirmation(url, &message) {
+            return false;
+        }
+        match message {
+            RelayMessage::Event(_, event_json) => self.first_sighting(url, event_json),
+            RelayMessage::OK(result) => {
+                let event_id = self.original_id(result.event_id);
+                if !result.status && self.remedy(url, &event_id, &result.message) {
+                    return true;
+                }
+                let waited_on = self.acks.contains_key(&event_id);
+                if let Some(acks) = self.acks.get_mut(&event_id) {
+                    let ack = if result.status {
+                        Ack::Accepted
+                    } else {
+                        Ack::Rejected(result.message.to_string())
+                    };
+                    acks.insert(url.to_string(), ack);
+                }
+                // The send result names the relays that refused an event as invalid, for the
+                // events someone waits on, even when another relay took it.
+                let reported = waited_on && Rejection::parse(&result.message) == Rejection::Invalid;
+                if !result.status && !reported {
+                    self.errors
+                        .extend(RelayError::from_rejection(url, &result.message));
+                }
+                true
+            }
+            RelayMessage::Auth(_) => {
+                self.errors.push(RelayError::Auth {
+                    url: url.to_string(),
+                });
+                false
+            }
+            // Some relays answer our own CLOSE with an empty CLOSED.
+            RelayMessage::Closed(subscription_id, reason) => {
+                if !reason.is_empty() {
+                    self.errors
+                        .push(RelayError::from_closed(url, subscription_id, &reason));
+                }
+                true
+            }
+            message => !self.handle_sync_message(url, message),
+        }
+    }
+
+    /// Records which relay delivered an event first. Returns false for copies of events the
+    /// app already verified, and for events we don't trust the relay with.
+    fn first_sighting(&mut self, url: &str, event_json: &str) -> bool {
+        #[derive(serde::Deserialize)]
+        struct EventId<'a> {
+            #[serde(borrow)]
+            id: &'a str,
+            kind: u16,
+        }
+        let Ok(event) = serde_json::from_str::<EventId>(event_json) else {
+            return true;
+        };
+        if !self.trust(url).allows_kind(event.kind.into()) {
+            return false;
+        }
+
+        self.sightings.push((event.id.to_string(), url.to_string()));
+        let stats = self.event_stats.entry(url.to_string()).or_default();
+        if self.delivered_events.insert(event.id) {
+            stats.first_seen += 1;
+        } else {
+            stats.duplicates += 1;
+        }
+        !self.verified_events.contains(event.id)
+    }
+
+    /// Drops further copies of `event_id`, now that its signature checked out.
+    pub fn mark_verified(&mut self, event_id: &str) {
+        self.verified_events.insert(event_id);
+    }
+
+    /// Handles NIP-77 replies and the fetches that follow them. Returns true if the message
+    /// was only meant for the sync.
+    fn handle_sync_message(&mut self, url: &str, message: RelayMessage) -> bool {
+        match message {
+            RelayMessage::NegMsg(subscription_id, payload) => {
+                self.handle_neg_msg(url, subscription_id, payload);
+                true
+            }
+            RelayMessage::NegErr(subscription_id, reason) => {
+                if self.is_current_sync(url, subscription_id) {
+                    info!("{} refused to sync: {}", url, reason);
+                    self.end_sync(url, SyncStatus::Unsupported(reason.to_string()));
+                }
+                true
+            }
+            // Relays that don't know NEG-OPEN usually just complain about it.
+            RelayMessage::Notice(notice) => {
+                if self.sync_stats.get(url).is_some_and(|stats| {
+                    stats.status == SyncStatus::Reconciling && stats.rounds == 0
+                }) {
+                    self.end_sync(url, SyncStatus::Unsupported(notice.to_string()));
+                }
+                false
+            }
+            RelayMessage::Eose(subscription_id) => {
+                let key = (url.to_string(), subscription_id.to_string());
+                if self.sync_fetches.remove(&key) {
+                    self.send_to(
+                        url,
+                        &ClientMessage::Close {
+                            subscription_id: key.1,
+                        },
+                    );
+                    return true;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn is_current_sync(&self, url: &str, subscription_id: &str) -> bool {
+        self.syncs
+            .get(url)
+            .is_some_and(|session| session.subscription_id == subscription_id)
+    }
+
+    fn handle_neg_msg(&mut self, url: &str, subscription_id: &str, payload: &str) {
+        if !self.is_current_sync(url, subscription_id) {
+            return;
+        }
+        let Some(session) = self.syncs.get_mut(url) else {
+            return;
+        };
+        if let Some(stats) = self.sync_stats.get_mut(url) {
+            stats.rounds += 1;
+        }
+
+        let result = negentropy::from_hex(payload).and_then(|query| {
+            session
+                .negentropy
+                .reconcile(&query, &mut session.have, &mut session.need)
+        });
+        match result {
+            Ok(Some(next)) => {
+                let message = ClientMessage::NegMsg {
+                    subscription_id: subscription_id.to_string(),
+                    message: negentropy::to_hex(&next),
+                };
+                self.send_to(url, &message);
+            }
+            Ok(None) => {
+                let Some(session) = self.syncs.remove(url) else {
+                    return;
+                };
+                self.send_to(
+                    url,
+                    &ClientMessage::NegClose {
+                        subscription_id: session.subscription_id,
+                    },
+                );
+                info!(
+                    "synced with {}: {} already stored, {} to fetch",
+                    url,
+                    session.local - session.have.len(),
+                    session.need.len()
+                );
+                self.fetch_missing(url, &session.need);
+                if let Some(stats) = self.sync_stats.get_mut(url) {
+                    stats.status = SyncStatus::Done;
+                    stats.skipped = session.local - session.have.len();
+                    stats.fetched = session.need.len();
+                }
+            }
+            Err(e) => {
+                error!("sync with {} failed: {}", url, e);
+                self.end_sync(url, SyncStatus::Unsupported(e.to_string()));
+            }
+        }
+    }
+
+    fn end_sync(&mut self, url: &str, status: SyncStatus) {
+        if let Some(session) = self.syncs.remove(url) {
+            self.send_to(
+                url,
+                &ClientMessage::NegClose {
+                    subscription_id: session.subscription_id,
+                },
+            );
+        }
+        if let Some(stats) = self.sync_stats.get_mut(url) {
+            stats.status = status;
+        }
+    }
+
+    /// Asks `url` for the events a sync found missing, in chunks small enough for relays to accept.
+    fn fetch_missing(&mut self, url: &str, ids: &[Id]) {
+        for chunk in ids.chunks(SYNC_FETCH_CHUNK) {
+            let ids = chunk.iter().filter_map(|id| EventId::from_slice(id).ok());
+            let subscription =
+                Subscription::new(Subscription::default().id, vec![Filter::new().ids(ids)]);
+            let key = (url.to_string(), subscription.id.clone());
+            if self.send_to(url, &subscription.into()) {
+                self.sync_fetches.insert(key);
+            }
+        }
+    }
+
+    /// Sends to a single relay, returning whether it went out.
+    fn send_to(&mut self, url: &str, message: &ClientMessage) -> bool {
+        let Some(relay) = self.relays.get_mut(url) else {
+            return false;
+        };
+        let payload = match serde_json::to_string(message) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("could not serialize message for {}: {}", url, e);
+                return false;
+            }
+        };
+        match relay.send(WsMessage::Text(payload)) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("could not send to {}: {}", url, e);
+                false
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: ewebsock::WsMessage) -> Result<()> {
+        self.send_with_report(message).map(|_| ())
+    }
+
+    /// Connected relays, lowest latency first. Relays we haven't measured yet go last.
+    pub fn send_order(&self) -> Vec<(String, Option<Duration>)> {
+        let mut order: Vec<(String, Option<Duration>)> = self
+            .relays
+            .values()
+            .filter(|relay| relay.status == RelayStatus::Connected)
+            .map(|relay| (relay.url.clone(), relay.rtt))
+            .collect();
+        order.sort_by(|(a_url, a_rtt), (b_url, b_rtt)| {
+            (a_rtt.is_none(), a_rtt, a_url).cmp(&(b_rtt.is_none(), b_rtt, b_url))
+        });
+        order
+    }
+
+    /// Sends to the fastest relays first, carrying on with the rest if one fails. Relays kept
+    /// to our own mail are left out. Only fails if no relay took the message.
+    pub fn send_with_report(&mut self, message: ewebsock::WsMessage) -> Result<SendReport> {
+        let order = self
+            .send_order()
+            .into_iter()
+            .filter(|(url, _)| self.trust(url).publishes())
+            .collect();
+        let mut report = SendReport {
+            order,
+            failed: Vec::new(),
+        };
+        let mut last_error = None;
+        for (url, _) in &report.order {
+            let Some(relay) = self.relays.get_mut(url) else {
+                continue;
+            };
+            if let Err(e) = relay.send(message.clone()) {
+                error!("could not send to {}: {}", url, e);
+                report.failed.push(url.clone());
+                last_error = Some(e);
+            }
+        }
+        match last_error {
+            Some(e) if report.accepted() == 0 => Err(e),
+            _ => Ok(report),
+        }
+    }
+
+    /// What relays did that the user should hear about since the last call.
+    pub fn take_errors(&mut self) -> Vec<RelayError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Which relays delivered which events since the last call, to be stored with them.
+    pub fn take_sightings(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.sightings)
+    }
+
+    /// Start collecting the relays' OK replies for an event about to be published.
+    pub fn expect_acks(&mut self, event_id: String) {
+        self.acks.entry(event_id).or_default();
+    }
+
+    pub fn acks(&self, event_id: &str) -> Option<&HashMap<String, Ack>> {
+        self.acks.get(event_id)
+    }
+
+    pub fn forget_acks(&mut self, event_id: &str) {
+        self.acks.remove(event_id);
+        self.outbox.remove(event_id);
+    }
+
+    /// Whether an event we published hasn't been taken by any relay yet while connected relays
+    /// still have to answer.
+    pub fn awaiting_acks(&self) -> bool {
+        let connected = self.connected_count();
+        self.acks
+            .values()
+            .any(|acks| !acks.values().any(|ack| *ack == Ack::Accepted) && acks.len() < connected)
+    }
+
+    /// Handles relay messages until a relay took every event we published or `timeout` runs
+    /// out, so sends made just before quitting aren't cut off. Whether they all got through.
+    pub fn wait_for_acks(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.awaiting_acks() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            if self.try_recv().is_none() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        true
+    }
+
+    /// Sends our event like [`RelayPool::send_with_report`] and collects the relays' OK
+    /// replies to it. Relays refusing it for a reason we can do something about get it again:
+    /// later when they're rate limiting us, or as a copy with the proof of work they want, see
+    /// [`RelayPool::take_pow_requests`]. They don't count as answered until then.
+    pub fn publish(&mut self, event: &Event) -> Result<SendReport> {
+        let event_id = event.id.to_hex();
+        let payload = serde_json::to_string(&ClientMessage::Event {
+            event: event.clone(),
+        })?;
+        self.expect_acks(event_id.clone());
+        self.outbox
+            .insert(event_id.clone(), Outgoing::new(payload.clone()));
+        let report = self.send_with_report(WsMessage::Text(payload));
+        if report.is_err() {
+            self.forget_acks(&event_id);
+        }
+        report
+    }
+
+    /// The most proof of work we'll do for a relay, in bits. Relays wanting more are told no.
+    pub fn set_max_pow(&mut self, difficulty: u8) {
+        self.max_pow = difficulty;
+    }
+
+    /// Relays that refused the published event `event_id` for want of proof of work, with
+    /// how many bits they want. Make it again with that much, and hand it to
+    /// [`RelayPool::publish_copy`].
+    pub fn take_pow_requests(&mut self, event_id: &str) -> Vec<(String, u8)> {
+        self.outbox
+            .get_mut(event_id)
+            .map(|outgoing| std::mem::take(&mut outgoing.pow_wanted))
+            .unwrap_or_default()
+    }
+
+    /// Sends `url` a copy of the published event `original_id` made again the way it wants.
+    /// Its answer counts for the original.
+    pub fn publish_copy(&mut self, original_id: &str, url: &str, copy: &Event) {
+        let Some(outgoing) = self.outbox.get_mut(original_id) else {
+            return;
+        };
+        let payload = match serde_json::to_string(&ClientMessage::Event {
+            event: copy.clone(),
+        }) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("could not serialize a copy of {}: {}", original_id, e);
+                return;
+            }
+        };
+        outgoing
+            .copies
+            .insert(url.to_string(), (copy.id.to_hex(), payload.clone()));
+        if let Some(relay) = self.relays.get_mut(url) {
+            if let Err(e) = relay.send(WsMessage::Text(payload)) {
+                error!("could not send to {}: {}", url, e);
+            }
+        }
+    }
+
+    /// The published event `event_id` is a copy of, or `event_id` itself.
+    fn original_id(&self, event_id: &str) -> String {
+        self.outbox
+            .iter()
+            .find(|(_, outgoing)| outgoing.is_copy(event_id))
+            .map_or_else(|| event_id.to_string(), |(id, _)| id.clone())
+    }
+
+    /// Sets about getting `url` to take our event `event_id` after all, if its reason for
+    /// refusing it is one we can do something about. Returns false if it isn't.
+    fn remedy(&mut self, url: &str, event_id: &str, reason: &str) -> bool {
+        let Some(outgoing) = self.outbox.get_mut(event_id) else {
+            return false;
+        };
+        match Rejection::parse(reason) {
+            Rejection::RateLimited => {
+                let retrying = outgoing.retry_later(url, Instant::now());
+                if retrying {
+                    debug!("{} is rate limiting us, sending {} again", url, event_id);
+                }
+                retrying
+            }
+            Rejection::Pow(Some(difficulty))
+                if difficulty <= self.max_pow && !outgoing.copies.contains_key(url) =>
+            {
+                debug!("{} wants {} bits of work on {}", url, difficulty, event_id);
+                outgoing.pow_wanted.push((url.to_string(), difficulty));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sends the rate-limited events whose wait is over again.
+    fn send_retries(&mut self, now: Instant) {
+        let mut due = Vec::new();
+        for outgoing in self.outbox.values_mut() {
+            let (ready, waiting) = std::mem::take(&mut outgoing.retries)
+                .into_iter()
+                .partition(|(_, at)| *at <= now);
+            outgoing.retries = waiting;
+            for (url, _) in ready {
+                let payload = outgoing.payload_for(&url).to_string();
+                due.push((url, payload));
+            }
+        }
+        for (url, payload) in due {
+            let Some(relay) = self.relays.get_mut(&url) else {
+                continue;
+            };
+            if let Err(e) = relay.send(WsMessage::Text(payload)) {
+                error!("could not send to {}: {}", url, e);
+            }
+        }
+    }
+
+    /// Asks every connected relay how many events match `filter`, without storing them, e.g.
+    /// the gift wraps of an account before it's added. Relays without NIP-45 get a limited REQ
+    /// instead. Starting again under the same `key` replaces the previous preflight.
+    pub fn start_preflight(&mut self, key: String, filter: Filter) {
+        self.end_preflight(&key);
+        self.preflights.insert(
+            key.clone(),
+            Preflight {
+                subscription_id: Subscription::default().id,
+                filter,
+                relays: HashMap::new(),
+                started: Instant::now(),
+            },
+        );
+        let connected: Vec<String> = self
+            .relays
+            .values()
+            .filter(|relay| relay.status == RelayStatus::Connected)
+            .map(|relay| relay.url.clone())
+            .collect();
+        for url in connected {
+            self.count_preflight(&key, &url);
+        }
+    }
+
+    pub fn preflight(&self, key: &str) -> Option<&Preflight> {
+        self.preflights.get(key)
+    }
+
+    /// Forgets a preflight, closing the REQs still downloading for it.
+    pub fn end_preflight(&mut self, key: &str) {
+        let Some(preflight) = self.preflights.remove(key) else {
+            return;
+        };
+        for (url, status) in preflight.relays {
+            if matches!(status, PreflightStatus::Fetching(_)) {
+                self.send_to(
+                    &url,
+                    &ClientMessage::Close {
+                        subscription_id: preflight.subscription_id.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Lets a relay that connected late still answer the preflights that are running.
+    fn join_preflights(&mut self, url: &str) {
+        let keys: Vec<String> = self
+            .preflights
+            .iter()
+            .filter(|(_, preflight)| {
+                !preflight.relays.contains_key(url)
+                    && preflight.started.elapsed() < PREFLIGHT_TIMEOUT
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.count_preflight(&key, url);
+        }
+    }
+
+    fn count_preflight(&mut self, key: &str, url: &str) {
+        let Some(preflight) = self.preflights.get(key) else {
+            return;
+        };
+        let message = ClientMessage::Count {
+            subscription_id: preflight.subscription_id.clone(),
+            filters: vec![preflight.filter.clone()],
+        };
+        let status = if self.send_to(url, &message) {
+            PreflightStatus::Counting
+        } else {
+            PreflightStatus::Failed("couldn't send".to_string())
+        };
+        if let Some(preflight) = self.preflights.get_mut(key) {
+            preflight.relays.insert(url.to_string(), status);
+        }
+    }
+
+    /// Falls back to downloading the events of a preflight from a relay that can't count them.
+    fn fetch_preflight(&mut self, key: &str, url: &str) {
+        let Some(preflight) = self.preflights.get(key) else {
+            return;
+        };
+        let message = ClientMessage::Req {
+            subscription_id: preflight.subscription_id.clone(),
+            filters: vec![preflight
+                .filter
+                .clone()
+                .limit(PREFLIGHT_FETCH_LIMIT as usize)],
+        };
+        let status = if self.send_to(url, &message) {
+            PreflightStatus::Fetching(0)
+        } else {
+            PreflightStatus::Failed("couldn't send".to_string())
+        };
+        if let Some(preflight) = self.preflights.get_mut(key) {
+            preflight.relays.insert(url.to_string(), status);
+        }
+    }
+
+    /// Asks the relays at `urls`, the ones that took the event `event_id`, to give it back
+    /// once `delay` is up, see [`RelayPool::take_confirmations`].
+    pub fn confirm_stored(&mut self, event_id: String, urls: &[String], delay: Duration) {
+        let Ok(id) = EventId::from_hex(&event_id) else {
+            return;
+        };
+        // Relays that got a copy have that instead.
+        let copies = self
+            .outbox
+            .get(&event_id)
+            .into_iter()
+            .flat_map(|outgoing| outgoing.copies.values())
+            .filter_map(|(copy_id, _)| EventId::from_hex(copy_id).ok());
+        let now = Instant::now();
+        self.confirmations.insert(
+            event_id,
+            Confirmation {
+                subscription_id: Subscription::default().id,
+                ids: std::iter::once(id).chain(copies).collect(),
+                relays: urls
+                    .iter()
+                    .map(|url| (url.clone(), Stored::Asking))
+                    .collect(),
+                ask_at: now + delay,
+                started: None,
+            },
+        );
+        self.check_confirmations(now);
+    }
+
+    /// The events asked back with [`RelayPool::confirm_stored`] that every relay has answered
+    /// for, or that timed out, with how many relays still have them. `None` when none gave
+    /// it back and some wouldn't say.
+    pub fn take_confirmations(&mut self) -> Vec<(String, Option<usize>)> {
+        let done: Vec<String> = self
+            .confirmations
+            .iter()
+            .filter(|(_, confirmation)| confirmation.is_done())
+            .map(|(event_id, _)| event_id.clone())
+            .collect();
+        done.into_iter()
+            .filter_map(|event_id| {
+                let confirmation = self.confirmations.remove(&event_id)?;
+                Some((event_id, confirmation.confirmed()))
+            })
+            .collect()
+    }
+
+    /// Handles the answers to our confirmations. Returns true if the message was only meant
+    /// for one.
+    fn handle_confirmation(&mut self, url: &str, message: &RelayMessage) -> bool {
+        let subscription_id = match message {
+            RelayMessage::Event(id, _) | RelayMessage::Eose(id) | RelayMessage::Closed(id, _) => {
+                *id
+            }
+            _ => return false,
+        };
+        let Some(confirmation) = self
+            .confirmations
+            .values_mut()
+            .find(|confirmation| confirmation.subscription_id == subscription_id)
+        else {
+            return false;
+        };
+        let Some(stored) = confirmation.relays.get_mut(url) else {
+            return true;
+        };
+        match message {
+            // Only the event we asked for counts, whatever else the relay sends.
+            RelayMessage::Event(_, event_json) => {
+                #[derive(serde::Deserialize)]
+                struct Id {
+                    id: EventId,
+                }
+                if serde_json::from_str::<Id>(event_json)
+                    .is_ok_and(|event| confirmation.ids.contains(&event.id))
+                {
+                    *stored = Stored::Yes;
+                }
+            }
+            RelayMessage::Eose(_) => {
+                if *stored == Stored::Asking {
+                    *stored = Stored::No;
+                }
+                let close = ClientMessage::Close {
+                    subscription_id: subscription_id.to_string(),
+                };
+                self.send_to(url, &close);
+            }
+            RelayMessage::Closed(_, reason) if error::refuses_access(reason) => {
+                *stored = Stored::Unknown
+            }
+            _ => *stored = Stored::No,
+        }
+        true
+    }
+
+    /// Asks for the events whose delay is up, and gives up on relays that haven't answered a
+    /// confirmation in time.
+    fn check_confirmations(&mut self, now: Instant) {
+        let mut ask = Vec::new();
+        let mut close = Vec::new();
+        for (event_id, confirmation) in self.confirmations.iter_mut() {
+            if confirmation.started.is_none() {
+                if now >= confirmation.ask_at {
+                    confirmation.started = Some(now);
+                    ask.push(event_id.clone());
+                }
+                continue;
+            }
+            if now < confirmation.deadline() {
+                continue;
+            }
+            for (url, stored) in confirmation.relays.iter_mut() {
+                if *stored == Stored::Asking {
+                    *stored = Stored::No;
+                    close.push((url.clone(), confirmation.subscription_id.clone()));
+                }
+            }
+        }
+        for (url, subscription_id) in close {
+            self.send_to(&url, &ClientMessage::Close { subscription_id });
+        }
+        for event_id in ask {
+            self.ask_for_confirmation(&event_id);
+        }
+    }
+
+    fn ask_for_confirmation(&mut self, event_id: &str) {
+        let Some(confirmation) = self.confirmations.get(event_id) else {
+            return;
+        };
+        let message = ClientMessage::Req {
+            subscription_id: confirmation.subscription_id.clone(),
+            filters: vec![Filter::new().ids(confirmation.ids.clone())],
+        };
+        let urls: Vec<String> = confirmation.relays.keys().cloned().collect();
+        for url in urls {
+            if !self.send_to(&url, &message) {
+                if let Some(confirmation) = self.confirmations.get_mut(event_id) {
+                    confirmation.relays.insert(url, Stored::No);
+                }
+            }
+        }
+    }
+
+    /// Handles the answers to our preflights. Returns true if the message was only meant for one.
+    fn handle_preflight_message(&mut self, url: &str, message: &RelayMessage) -> bool {
+        let subscription_id = match message {
+            RelayMessage::Event(id, _)
+            | RelayMessage::Eose(id)
+            | RelayMessage::Closed(id, _)
+            | RelayMessage::Count(id, _) => *id,
+            // Relays that don't know COUNT may only complain about it.
+            RelayMessage::Notice(_) => {
+                let counting: Vec<String> = self
+                    .preflights
+                    .iter()
+                    .filter(|(_, preflight)| {
+                        preflight.relays.get(url) == Some(&PreflightStatus::Counting)
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in counting {
+                    self.fetch_preflight(&key, url);
+                }
+                return false;
+            }
+            _ => return false,
+        };
+        let Some((key, preflight)) = self
+            .preflights
+            .iter_mut()
+            .find(|(_, preflight)| preflight.subscription_id == subscription_id)
+        else {
+            return false;
+        };
+        let key = key.clone();
+        let Some(status) = preflight.relays.get_mut(url) else {
+            return true;
+        };
+
+        let mut close = false;
+        match (message, status.clone()) {
+            (RelayMessage::Count(_, count), PreflightStatus::Counting) => {
+                *status = PreflightStatus::Found {
+                    count: *count,
+                    capped: false,
+                };
+            }
+            (RelayMessage::Closed(..), PreflightStatus::Counting) => {
+                self.fetch_preflight(&key, url);
+            }
+            (RelayMessage::Closed(_, reason), PreflightStatus::Fetching(_)) => {
+                *status = PreflightStatus::Failed(reason.to_string());
+            }
+            (RelayMessage::Event(..), PreflightStatus::Fetching(count)) => {
+                let count = count + 1;
+                if count >= PREFLIGHT_FETCH_LIMIT {
+                    *status = PreflightStatus::Found {
+                        count,
+                        capped: true,
+                    };
+                    close = true;
+                } else {
+                    *status = PreflightStatus::Fetching(count);
+                }
+            }
+            (RelayMessage::Eose(_), PreflightStatus::Fetching(count)) => {
+                *status = PreflightStatus::Found {
+                    count,
+                    capped: false,
+                };
+                close = true;
+            }
+            _ => {}
+        }
+        if close {
+            self.send_to(
+                url,
+                &ClientMessage::Close {
+                    subscription_id: subscription_id.to_string(),
+                },
+            );
+        }
+        true
+    }
+
+    /// Moves on from relays that are slow to answer a preflight.
+    fn check_preflights(&mut self, now: Instant) {
+        let mut fetch = Vec::new();
+        let mut close = Vec::new();
+        for (key, preflight) in self.preflights.iter_mut() {
+            let elapsed = now.duration_since(preflight.started);
+            for (url, status) in preflight.relays.iter_mut() {
+                match status.clone() {
+                    PreflightStatus::Counting if elapsed >= PREFLIGHT_TIMEOUT => {
+                        *status = PreflightStatus::Failed("timed out".to_string());
+                    }
+                    PreflightStatus::Counting if elapsed >= PREFLIGHT_COUNT_TIMEOUT => {
+                        fetch.push((key.clone(), url.clone()));
+                    }
+                    PreflightStatus::Fetching(count) if elapsed >= PREFLIGHT_TIMEOUT => {
+                        *status = if count > 0 {
+                            PreflightStatus::Found {
+                                count,
+                                capped: true,
+                            }
+                        } else {
+                            PreflightStatus::Failed("timed out".to_string())
+                        };
+                        close.push((url.clone(), preflight.subscription_id.clone()));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for (key, url) in fetch {
+            self.fetch_preflight(&key, &url);
+        }
+        for (url, subscription_id) in close {
+            self.send_to(&url, &ClientMessage::Close { subscription_id });
+        }
+    }
+
+    pub fn ping_settings(&self) -> PingSettings {
+        self.ping_settings
+    }
+
+    /// Changes how often relays are pinged, from their next ping on.
+    pub fn set_ping_settings(&mut self, settings: PingSettings) {
+        self.ping_settings = settings;
+        for relay in self.relays.values_mut() {
+            relay.set_ping_settings(settings);
+        }
+    }
+
+    pub fn trace_enabled(&self) -> bool {
+        self.trace_enabled
+    }
+
+    /// Turn wire logging on or off for every relay, including ones added later.
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        self.trace_enabled = enabled;
+        for relay in self.relays.values_mut() {
+            relay.trace.enabled = enabled;
+        }
+    }
+
+    pub fn clear_traces(&mut self) {
+        for relay in self.relays.values_mut() {
+            relay.trace.clear();
+        }
+    }
+
+    /// Write the wire log of every relay to `path`, one section per relay.
+    pub fn export_traces(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        let mut urls: Vec<&String> = self.relays.keys().collect();
+        urls.sort();
+        for url in urls {
+            let relay = &self.relays[url];
+            writeln!(file, "# {} ({} frames)", url, relay.trace.len())?;
+            for entry in relay.trace.entries() {
+                writeln!(file, "{}", entry)?;
+            }
+            writeln!(file)?;
+        }
+
+        file.flush()
+    }
+
+    pub fn ping_all(&mut self) -> Result<()> {
+        for relay in self.relays.values_mut() {
+            relay.ping();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use nostr::types::Filter;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+/// Where a preflight stands with one relay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreflightStatus {
+    /// Waiting for the answer to our NIP-45 COUNT.
+    Counting,
+    /// The relay refused to count, so we download the events instead. Holds how many came in.
+    Fetching(u64),
+    /// `capped` when we stopped fetching at the limit, so there are at least `count`.
+    Found {
+        count: u64,
+        capped: bool,
+    },
+    Failed(String),
+}
+
+impl fmt::Display for PreflightStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightStatus::Counting => write!(f, "Counting…"),
+            PreflightStatus::Fetching(count) => write!(f, "Fetching… {} so far", count),
+            PreflightStatus::Found {
+                count,
+                capped: true,
+            } => write!(f, "{}+ messages", count),
+            PreflightStatus::Found { count, .. } => write!(f, "{} messages", count),
+            PreflightStatus::Failed(reason) => write!(f, "No answer ({})", reason),
+        }
+    }
+}
+
+/// A look at what the relays already hold for an account, before it's added.
+#[derive(Debug, Clone)]
+pub struct Preflight {
+    pub subscription_id: String,
+    pub filter: Filter,
+    pub relays: HashMap<String, PreflightStatus>,
+    pub started: Instant,
+}
+
+impl Preflight {
+    pub fn is_done(&self) -> bool {
+        self.relays.values().all(|status| {
+            matches!(
+                status,
+                PreflightStatus::Found { .. } | PreflightStatus::Failed(_)
+            )
+        })
+    }
+
+    /// Relays hold copies of the same messages, so the total is the largest count rather than
+    /// the sum. Returns (messages, relays that have some, whether it's a lower bound).
+    pub fn total(&self) -> (u64, usize, bool) {
+        let mut total = 0;
+        let mut relays = 0;
+        let mut capped = false;
+        for status in self.relays.values() {
+            let (count, at_least) = match status {
+                PreflightStatus::Found { count, capped } => (*count, *capped),
+                PreflightStatus::Fetching(count) => (*count, true),
+                _ => continue,
+            };
+            if count > 0 {
+                relays += 1;
+            }
+            if count > total {
+                total = count;
+                capped = at_least;
+            }
+        }
+        (total, relays, capped || !self.is_done())
+    }
+
+    /// "Found 124 messages across 3 relays", or what we're still waiting for.
+    pub fn summary(&self) -> String {
+        if self.relays.is_empty() {
+            return "Not connected to any relay to look for messages".to_string();
+        }
+        let (total, relays, at_least) = self.total();
+        if total == 0 {
+            return if self.is_done() {
+                format!(
+                    "No messages found on {} relay{}",
+                    self.relays.len(),
+                    if self.relays.len() == 1 { "" } else { "s" }
+                )
+            } else {
+                "Looking for messages…".to_string()
+            };
+        }
+        format!(
+            "Found {}{} message{} across {} relay{}",
+            total,
+            if at_least { "+" } else { "" },
+            if total == 1 { "" } else { "s" },
+            relays,
+            if relays == 1 { "" } else { "s" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_counts_without_adding_up_copies() {
+        let mut preflight = Preflight {
+            subscription_id: "preflight".to_string(),
+            filter: Filter::new(),
+            relays: HashMap::new(),
+            started: Instant::now(),
+        };
+        assert_eq!(
+            preflight.summary(),
+            "Not connected to any relay to look for messages"
+        );
+
+        preflight.relays.insert(
+            "wss://a.example.com".to_string(),
+            PreflightStatus::Found {
+                count: 124,
+                capped: false,
+            },
+        );
+        preflight
+            .relays
+            .insert("wss://b.example.com".to_string(), PreflightStatus::Counting);
+        assert!(!preflight.is_done());
+        assert_eq!(preflight.summary(), "Found 124+ messages across 1 relay");
+
+        preflight.relays.insert(
+            "wss://b.example.com".to_string(),
+            PreflightStatus::Found {
+                count: 120,
+                capped: false,
+            },
+        );
+        preflight.relays.insert(
+            "wss://c.example.com".to_string(),
+            PreflightStatus::Failed("timed out".to_string()),
+        );
+        assert!(preflight.is_done());
+        assert_eq!(preflight.summary(), "Found 124 messages across 2 relays");
+    }
+}
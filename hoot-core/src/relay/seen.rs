@@ -0,0 +1,63 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Ids of events seen recently, so copies from other relays can be told apart. Oldest ids
+/// are forgotten once `capacity` is reached.
+pub(super) struct SeenEvents {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Returns true the first time `id` is seen.
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+/// Which relay delivered events first, for the diagnostics tab.
+#[derive(Debug, Clone, Default)]
+pub struct RelayEventStats {
+    /// Events this relay delivered before any other.
+    pub first_seen: u64,
+    /// Copies of events another relay had already delivered.
+    pub duplicates: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_oldest_ids_past_capacity() {
+        let mut seen = SeenEvents::new(2);
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("a"));
+        assert!(seen.insert("b"));
+        assert!(seen.insert("c"));
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("c"));
+    }
+}
@@ -0,0 +1,120 @@
+use super::negentropy::{Id, Negentropy};
+use nostr::nips::nip59::RANGE_RANDOM_TIMESTAMP_TWEAK;
+use nostr::types::Filter;
+use nostr::{Kind, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SyncStatus {
+    #[default]
+    Reconciling,
+    Done,
+    /// The relay doesn't speak NIP-77, so it only gets since based REQs.
+    Unsupported(String),
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncStatus::Reconciling => write!(f, "Reconciling"),
+            SyncStatus::Done => write!(f, "Synced"),
+            SyncStatus::Unsupported(reason) => write!(f, "Not supported ({})", reason),
+        }
+    }
+}
+
+/// How the last negentropy sync with a relay went, for the diagnostics tab.
+#[derive(Debug, Clone, Default)]
+pub struct SyncStats {
+    pub status: SyncStatus,
+    /// Messages exchanged with the relay.
+    pub rounds: usize,
+    /// Events both sides had, which a full REQ would have downloaded again.
+    pub skipped: usize,
+    /// Events only the relay had, fetched by id.
+    pub fetched: usize,
+}
+
+/// A reconciliation in flight with one relay.
+pub(super) struct SyncSession {
+    pub subscription_id: String,
+    pub negentropy: Negentropy,
+    pub local: usize,
+    pub have: Vec<Id>,
+    pub need: Vec<Id>,
+}
+
+/// How far back we read mail from a relay, e.g. the full history from a paid relay but only
+/// the last month from a public one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncWindow {
+    #[default]
+    FullHistory,
+    LastDays(u32),
+}
+
+impl fmt::Display for SyncWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncWindow::FullHistory => write!(f, "Full history"),
+            SyncWindow::LastDays(1) => write!(f, "Last day"),
+            SyncWindow::LastDays(days) => write!(f, "Last {} days", days),
+        }
+    }
+}
+
+impl SyncWindow {
+    /// The oldest gift wrap to ask for at `now`. Wraps are backdated by up to two days, so
+    /// the window reaches that much further back to not miss mail sent at its edge.
+    pub fn since(&self, now: u64) -> Option<u64> {
+        match self {
+            SyncWindow::FullHistory => None,
+            SyncWindow::LastDays(days) => Some(
+                now.saturating_sub(u64::from(*days) * 24 * 60 * 60)
+                    .saturating_sub(RANGE_RANDOM_TIMESTAMP_TWEAK.end),
+            ),
+        }
+    }
+
+    /// `filter` cut to the window if it asks for gift wraps. Profiles, relay lists and the
+    /// like are asked for in full.
+    pub fn apply(&self, filter: &Filter, now: u64) -> Filter {
+        let mut filter = filter.clone();
+        let Some(since) = self.since(now) else {
+            return filter;
+        };
+        let mailbox = filter
+            .kinds
+            .as_ref()
+            .is_some_and(|kinds| kinds.contains(&Kind::GiftWrap));
+        if mailbox && filter.since.is_none_or(|old| old.as_u64() < since) {
+            filter.since = Some(Timestamp::from(since));
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_only_cut_mailbox_filters() {
+        let now = 1_000 * 24 * 60 * 60;
+        let since = now - 30 * 24 * 60 * 60 - RANGE_RANDOM_TIMESTAMP_TWEAK.end;
+        let window = SyncWindow::LastDays(30);
+        let mailbox = Filter::new().kind(Kind::GiftWrap);
+
+        assert_eq!(
+            window.apply(&mailbox, now).since,
+            Some(Timestamp::from(since))
+        );
+        // A later since is already inside the window.
+        let recent = mailbox.clone().since(Timestamp::from(now - 60));
+        assert_eq!(window.apply(&recent, now), recent);
+        let profiles = Filter::new().kind(Kind::Metadata);
+        assert_eq!(window.apply(&profiles, now), profiles);
+        assert_eq!(SyncWindow::FullHistory.apply(&mailbox, now), mailbox);
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::{DateTime, Local};
+use ewebsock::WsMessage;
+use std::collections::VecDeque;
+
+/// How many frames each relay keeps before the oldest ones are dropped.
+pub const TRACE_CAPACITY: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceDirection {
+    Sent,
+    Received,
+    /// Connection state changes (opened, closed, errors) rather than frames.
+    Status,
+}
+
+impl std::fmt::Display for TraceDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceDirection::Sent => write!(f, "->"),
+            TraceDirection::Received => write!(f, "<-"),
+            TraceDirection::Status => write!(f, "--"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub at: DateTime<Local>,
+    pub direction: TraceDirection,
+    pub frame: String,
+}
+
+impl std::fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.at.format("%Y-%m-%d %H:%M:%S%.3f"),
+            self.direction,
+            self.frame
+        )
+    }
+}
+
+/// Ring buffer of the raw websocket traffic for a single relay.
+/// Recording is off by default so we don't hold on to message contents unless asked to.
+#[derive(Debug)]
+pub struct RelayTrace {
+    pub enabled: bool,
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Default for RelayTrace {
+    fn default() -> Self {
+        Self::with_capacity(TRACE_CAPACITY)
+    }
+}
+
+impl RelayTrace {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            enabled: false,
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, direction: TraceDirection, frame: impl Into<String>) {
+        if !self.enabled || self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            at: Local::now(),
+            direction,
+            frame: frame.into(),
+        });
+    }
+
+    pub fn record_message(&mut self, direction: TraceDirection, message: &WsMessage) {
+        if !self.enabled {
+            return;
+        }
+
+        let frame = match message {
+            WsMessage::Text(text) => text.clone(),
+            WsMessage::Binary(data) => format!("<binary, {} bytes>", data.len()),
+            WsMessage::Ping(_) => "<ping>".to_string(),
+            WsMessage::Pong(_) => "<pong>".to_string(),
+            WsMessage::Unknown(text) => format!("<unknown: {}>", text),
+        };
+        self.record(direction, frame);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest_when_full() {
+        let mut trace = RelayTrace::with_capacity(2);
+        trace.enabled = true;
+        trace.record(TraceDirection::Sent, "one");
+        trace.record(TraceDirection::Received, "two");
+        trace.record(TraceDirection::Sent, "three");
+
+        let frames: Vec<&str> = trace.entries().map(|e| e.frame.as_str()).collect();
+        assert_eq!(frames, vec!["two", "three"]);
+    }
+
+    #[test]
+    fn ignores_frames_when_disabled() {
+        let mut trace = RelayTrace::default();
+        trace.record(TraceDirection::Sent, "hello");
+        assert_eq!(trace.len(), 0);
+    }
+}
@@ -0,0 +1,143 @@
+//! How far we trust a relay with our mail. A relay can be kept to fetching our own gift wraps,
+//! or distrusted, in which case mail that only it delivered has to pass extra checks and is
+//! shown with a warning.
+
+use anyhow::{bail, Result};
+use nostr::{Event, Filter, Kind, TagKind, Timestamp, UnsignedEvent};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Storage key for the relays that aren't fully trusted, see [`RelayTrust`].
+pub const RELAY_TRUST_KEY: &str = "relay_trust";
+
+/// How far ahead of our clock a distrusted relay's mail may be dated, for clocks that are off.
+const FUTURE_SLACK: u64 = 10 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelayTrust {
+    #[default]
+    Trusted,
+    /// Only asked for gift wraps addressed to us, and never published to.
+    OwnMailOnly,
+    /// Mail it alone delivers gets [`check_distrusted_wrap`] and a warning.
+    Distrusted,
+}
+
+impl fmt::Display for RelayTrust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayTrust::Trusted => write!(f, "Trusted"),
+            RelayTrust::OwnMailOnly => write!(f, "My mail only"),
+            RelayTrust::Distrusted => write!(f, "Distrusted"),
+        }
+    }
+}
+
+impl RelayTrust {
+    /// Whether we publish our events to the relay.
+    pub fn publishes(&self) -> bool {
+        *self != RelayTrust::OwnMailOnly
+    }
+
+    /// Whether the relay is asked for what `filter` asks for.
+    pub fn allows_filter(&self, filter: &Filter) -> bool {
+        *self != RelayTrust::OwnMailOnly
+            || filter.kinds.as_ref().is_some_and(|kinds| {
+                !kinds.is_empty() && kinds.iter().all(|k| *k == Kind::GiftWrap)
+            })
+    }
+
+    /// Whether an event of `kind` the relay sent is passed on to the app.
+    pub fn allows_kind(&self, kind: Kind) -> bool {
+        *self != RelayTrust::OwnMailOnly || kind == Kind::GiftWrap
+    }
+}
+
+/// The checks on top of the usual ones for a gift wrap only distrusted relays delivered. The
+/// seal's signature already ties the message to its sender, so these catch what a relay could
+/// still get away with: wraps naming several recipients and mail dated into the future, to stay
+/// on top of the inbox.
+pub fn check_distrusted_wrap(wrap: &Event, rumor: &UnsignedEvent, now: Timestamp) -> Result<()> {
+    let recipients = wrap
+        .tags
+        .iter()
+        .filter(|tag| tag.kind() == TagKind::p())
+        .count();
+    if recipients != 1 {
+        bail!("gift wrap names {} recipients instead of one", recipients);
+    }
+    let latest = now.as_u64() + FUTURE_SLACK;
+    // NIP-59 dates wraps back, never forward.
+    if wrap.created_at.as_u64() > latest {
+        bail!("gift wrap is dated in the future");
+    }
+    if rumor.created_at.as_u64() > latest {
+        bail!("message is dated in the future");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay::mock::{pump_until, MockRelay};
+    use crate::relay::{ClientMessage, RelayPool, Subscription};
+    use ewebsock::WsMessage;
+    use nostr::{EventBuilder, Keys, PublicKey, Tag};
+
+    fn wrap(recipients: &[PublicKey], created_at: u64) -> Result<Event> {
+        let tags = recipients.iter().map(|pubkey| Tag::public_key(*pubkey));
+        Ok(EventBuilder::new(Kind::GiftWrap, "")
+            .tags(tags)
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(&Keys::generate())?)
+    }
+
+    #[test]
+    fn distrusted_wraps_need_one_recipient_and_no_future_dates() -> Result<()> {
+        let now = Timestamp::from(1_000_000);
+        let us = Keys::generate().public_key();
+        let them = Keys::generate().public_key();
+        let rumor = EventBuilder::new(Kind::TextNote, "hi")
+            .custom_created_at(now)
+            .build(them);
+
+        assert!(check_distrusted_wrap(&wrap(&[us], 990_000)?, &rumor, now).is_ok());
+        assert!(check_distrusted_wrap(&wrap(&[us, them], 990_000)?, &rumor, now).is_err());
+        assert!(check_distrusted_wrap(&wrap(&[], 990_000)?, &rumor, now).is_err());
+        assert!(check_distrusted_wrap(&wrap(&[us], 1_100_000)?, &rumor, now).is_err());
+
+        let ahead = EventBuilder::new(Kind::TextNote, "hi")
+            .custom_created_at(Timestamp::from(1_100_000))
+            .build(them);
+        assert!(check_distrusted_wrap(&wrap(&[us], 990_000)?, &ahead, now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn own_mail_only_relays_just_get_asked_for_gift_wraps() -> Result<()> {
+        let relay = MockRelay::new();
+        let mut pool = RelayPool::new();
+        pool.add_mock("wss://mail.example.com", &relay);
+        pool.set_trust("wss://mail.example.com", RelayTrust::OwnMailOnly);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+
+        for (id, kind) in [("mail", Kind::GiftWrap), ("profiles", Kind::Metadata)] {
+            pool.add_subscription(Subscription::new(id.into(), vec![Filter::new().kind(kind)]))
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        assert_eq!(relay.open_subscriptions(), 1);
+
+        let event = EventBuilder::new(Kind::Metadata, "{}").sign_with_keys(&Keys::generate())?;
+        let payload = serde_json::to_string(&ClientMessage::Event { event })?;
+        let report = pool
+            .send_with_report(WsMessage::Text(payload))
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        assert_eq!(report.accepted(), 0);
+        assert!(relay.events().is_empty());
+
+        pool.set_trust("wss://mail.example.com", RelayTrust::Trusted);
+        assert_eq!(relay.open_subscriptions(), 2);
+        Ok(())
+    }
+}
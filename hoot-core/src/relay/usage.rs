@@ -0,0 +1,130 @@
+//! How much traffic each relay carried this month, for relays that charge by it or sit behind
+//! a metered connection. A relay can be given a soft cap, and once most of it is used Hoot
+//! warns before doing something that moves a lot of mail through it. Nothing is ever blocked.
+
+use super::TraceDirection;
+use chrono::{Datelike, Local};
+use ewebsock::WsMessage;
+use serde::{Deserialize, Serialize};
+
+/// Storage key for every relay's [`RelayUsage`].
+pub const RELAY_USAGE_KEY: &str = "relay_usage";
+
+/// How much of its cap a relay can use before big operations warn about it.
+const WARN_SHARE: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayUsage {
+    /// The month counted, as year * 100 + month.
+    pub month: u32,
+    /// Bytes we sent the relay.
+    pub sent: u64,
+    /// Bytes the relay sent us.
+    pub received: u64,
+    /// Soft cap on a month's traffic in bytes, `None` if the relay isn't metered.
+    pub cap: Option<u64>,
+}
+
+/// The month it is, as [`RelayUsage::month`] counts them.
+fn this_month() -> u32 {
+    let today = Local::now();
+    today.year() as u32 * 100 + today.month()
+}
+
+impl RelayUsage {
+    /// Counts a websocket frame to or from the relay.
+    pub fn record(&mut self, direction: TraceDirection, message: &WsMessage) {
+        let bytes = match message {
+            WsMessage::Text(text) => text.len(),
+            WsMessage::Binary(data) | WsMessage::Ping(data) | WsMessage::Pong(data) => data.len(),
+            WsMessage::Unknown(text) => text.len(),
+        };
+        self.record_at(this_month(), direction, bytes as u64);
+    }
+
+    fn record_at(&mut self, month: u32, direction: TraceDirection, bytes: u64) {
+        if self.month != month {
+            *self = Self {
+                month,
+                cap: self.cap,
+                ..Default::default()
+            };
+        }
+        match direction {
+            TraceDirection::Sent => self.sent += bytes,
+            TraceDirection::Received => self.received += bytes,
+            TraceDirection::Status => {}
+        }
+    }
+
+    /// This month's traffic, nothing if the counts are from an earlier month.
+    pub fn current(&self) -> Self {
+        self.current_at(this_month())
+    }
+
+    fn current_at(&self, month: u32) -> Self {
+        if self.month == month {
+            return *self;
+        }
+        Self {
+            month,
+            cap: self.cap,
+            ..Default::default()
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.sent + self.received
+    }
+
+    /// Whether the relay is metered and has used most of its cap.
+    pub fn near_cap(&self) -> bool {
+        self.cap
+            .is_some_and(|cap| self.total() as f64 >= cap as f64 * WARN_SHARE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay::mock::{pump_until, MockRelay};
+    use crate::relay::{RelayPool, Subscription};
+    use nostr::Filter;
+
+    #[test]
+    fn counts_traffic_by_month_against_the_cap() {
+        let mut usage = RelayUsage {
+            cap: Some(1_000),
+            ..Default::default()
+        };
+        usage.record_at(202610, TraceDirection::Sent, 300);
+        usage.record_at(202610, TraceDirection::Received, 500);
+        assert_eq!(usage.total(), 800);
+        assert!(usage.near_cap());
+        assert_eq!(usage.current_at(202611).total(), 0);
+
+        usage.record_at(202611, TraceDirection::Received, 10);
+        assert_eq!((usage.sent, usage.received), (0, 10));
+        assert_eq!(usage.cap, Some(1_000));
+        assert!(!usage.near_cap());
+
+        // Usage saved before the relay is added again carries on where it was.
+        let relay = MockRelay::new();
+        let mut pool = RelayPool::new();
+        pool.set_usage("wss://metered.example.com", usage.current());
+        pool.set_cap("wss://metered.example.com", Some(10));
+        pool.add_mock("wss://metered.example.com", &relay);
+        pump_until(&mut pool, "connecting", |pool| pool.connected_count() >= 1);
+        pool.add_subscription(Subscription::new("mail".into(), vec![Filter::new()]))
+            .unwrap();
+        pump_until(&mut pool, "receiving", |pool| {
+            pool.usage("wss://metered.example.com").received >= 10
+        });
+        assert!(pool.usage("wss://metered.example.com").sent > 0);
+        assert_eq!(
+            pool.near_cap(),
+            vec!["wss://metered.example.com".to_string()]
+        );
+    }
+}
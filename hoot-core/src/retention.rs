@@ -0,0 +1,227 @@
+//! Retention rules: old mail moves to the Trash on its own, and the Trash empties itself.
+//!
+//! [`plan`] works out what the rules would do without touching anything, so it doubles as a
+//! preview. [`apply`] carries a plan out, and forgets which relays delivered events we didn't
+//! keep.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::info;
+
+use crate::db::{Db, MessageFilter};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+/// Months are counted as 30 days.
+const MONTH_SECS: i64 = 30 * DAY_SECS;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Threads with nothing newer than this many months go to the Trash, `None` keeps them.
+    pub trash_after_months: Option<u32>,
+    pub inbox: bool,
+    pub archived: bool,
+    pub sent: bool,
+    /// Leave threads with a starred message where they are.
+    pub keep_starred: bool,
+    /// Days a message stays in the Trash before it's deleted for good, counted from when it's
+    /// trashed with the days set then.
+    pub purge_trash_after_days: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            trash_after_months: None,
+            inbox: false,
+            archived: true,
+            sent: false,
+            keep_starred: true,
+            purge_trash_after_days: 30,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// When a message trashed at `trashed_at` is due to be deleted.
+    pub fn purge_after(&self, trashed_at: i64) -> i64 {
+        trashed_at + self.purge_trash_after_days as i64 * DAY_SECS
+    }
+}
+
+/// What the rules would do at a given time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPlan {
+    /// Threads (or sent messages) going to the Trash.
+    pub threads: usize,
+    /// Every message in those threads.
+    pub trash: Vec<String>,
+    /// Trashed messages due to be deleted.
+    pub purge: Vec<String>,
+}
+
+impl RetentionPlan {
+    pub fn is_empty(&self) -> bool {
+        self.trash.is_empty() && self.purge.is_empty()
+    }
+}
+
+/// Works out what `policy` would do at `now`, without changing anything.
+pub fn plan(db: &Db, policy: &RetentionPolicy, now: i64) -> Result<RetentionPlan> {
+    let mut plan = RetentionPlan {
+        purge: db.get_trash_due(now)?,
+        ..Default::default()
+    };
+    let Some(months) = policy.trash_after_months else {
+        return Ok(plan);
+    };
+    let cutoff = now - months as i64 * MONTH_SECS;
+    let starred = if policy.keep_starred {
+        db.get_starred_ids()?
+    } else {
+        HashSet::new()
+    };
+
+    let mut folders = Vec::new();
+    if policy.inbox {
+        folders.push(MessageFilter::default());
+    }
+    if policy.archived {
+        folders.push(MessageFilter {
+            archived: true,
+            ..Default::default()
+        });
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    for filter in folders {
+        for entry in db.get_top_level_messages(None, &filter)? {
+            // Entries carry the thread's latest message, so the whole thread is old.
+            if entry.created_at >= cutoff {
+                continue;
+            }
+            let ids: Vec<String> = db
+                .get_email_thread(&entry.id)?
+                .iter()
+                .filter_map(|msg| msg.id.map(|id| id.to_hex()))
+                .collect();
+            if ids.iter().any(|id| starred.contains(id)) {
+                continue;
+            }
+            plan.threads += 1;
+            plan.trash
+                .extend(ids.into_iter().filter(|id| seen.insert(id.clone())));
+        }
+    }
+    if policy.sent {
+        for sent in db.get_sent_messages(None)? {
+            let id = sent.entry.id;
+            if sent.entry.created_at < cutoff && !starred.contains(&id) && seen.insert(id.clone()) {
+                plan.threads += 1;
+                plan.trash.push(id);
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Moves the planned messages to the Trash and deletes the expired ones.
+pub fn apply(db: &mut Db, policy: &RetentionPolicy, plan: &RetentionPlan, now: i64) -> Result<()> {
+    db.record_trash(&plan.trash, policy.purge_after(now))?;
+    db.purge_trash(&plan.purge)?;
+    // A day is plenty for an event to be verified and stored after a relay delivers it.
+    let sightings = db.prune_event_sources(now - DAY_SECS)?;
+    if sightings > 0 {
+        info!("Retention forgot {} relay sightings", sightings);
+    }
+    if !plan.is_empty() {
+        info!(
+            "Retention moved {} messages to the Trash and deleted {} from it",
+            plan.trash.len(),
+            plan.purge.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail_event::MailMessage;
+    use nostr::Keys;
+
+    fn send(db: &mut Db, from: &Keys, subject: &str, created_at: i64) -> Result<String> {
+        let mut message = MailMessage {
+            id: None,
+            created_at: Some(created_at),
+            author: None,
+            to: vec![Keys::generate().public_key()],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: subject.to_string(),
+            content: String::new(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: Default::default(),
+        };
+        let wraps: Vec<(String, String)> = message
+            .to_events(from, std::time::Duration::ZERO)
+            .into_iter()
+            .map(|(recipient, wrap)| (wrap.id.to_hex(), recipient.to_hex()))
+            .collect();
+        let rumor = message.to_rumor().expect("to_events sets the author");
+        db.record_sent(&rumor, &wraps)?;
+        Ok(message.id.expect("to_events sets the id").to_hex())
+    }
+
+    #[test]
+    fn trashes_old_mail_and_purges_old_trash() -> Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let keys = Keys::generate();
+        let now = chrono::Utc::now().timestamp();
+        let old = send(&mut db, &keys, "Old", now - 7 * MONTH_SECS)?;
+        let starred = send(&mut db, &keys, "Old but starred", now - 7 * MONTH_SECS)?;
+        send(&mut db, &keys, "Recent", now - DAY_SECS)?;
+        db.set_starred(&starred, true)?;
+
+        let policy = RetentionPolicy {
+            trash_after_months: Some(6),
+            sent: true,
+            ..Default::default()
+        };
+        let preview = plan(&db, &policy, now)?;
+        assert_eq!(preview.threads, 1);
+        assert_eq!(preview.trash, vec![old.clone()]);
+        assert!(preview.purge.is_empty());
+        // A preview leaves everything where it is.
+        assert_eq!(db.get_sent_messages(None)?.len(), 3);
+
+        apply(&mut db, &policy, &preview, now)?;
+        assert_eq!(db.get_sent_messages(None)?.len(), 2);
+        assert_eq!(db.get_trash_messages(None)?.len(), 1);
+        assert!(plan(&db, &policy, now)?.is_empty());
+
+        // Shorter days later don't cut short the 30 it was trashed with.
+        let shorter = RetentionPolicy {
+            purge_trash_after_days: 1,
+            ..Default::default()
+        };
+        assert!(plan(&db, &shorter, now + 2 * DAY_SECS)?.purge.is_empty());
+
+        // A month on, the Trash has outlived its 30 days.
+        let later = now + 31 * DAY_SECS;
+        let policy = RetentionPolicy::default();
+        let purge = plan(&db, &policy, later)?;
+        assert_eq!(purge.purge, vec![old.clone()]);
+        apply(&mut db, &policy, &purge, later)?;
+        assert!(db.get_trash_messages(None)?.is_empty());
+        assert!(!db.has_event(&old)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Threads as an index over their messages. Each message is a slot holding the slots of its
+//! parent and replies, so walking a thread is plain indexing, with no shared ownership.
+//!
+//! Messages can be added in any order as they come in from relays. A reply whose parent
+//! hasn't arrived hangs off its nearest ancestor we do have, or starts a thread of its own,
+//! and moves under the parent once it turns up.
+
+use crate::mail_event::MailMessage;
+use nostr::EventId;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+struct Node {
+    id: Option<EventId>,
+    /// Everything the message replies to, oldest first, as its tags list them.
+    ancestry: Vec<EventId>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    root: usize,
+}
+
+/// The messages of one or more threads, by slot in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadStore {
+    nodes: Vec<Node>,
+    slots: HashMap<EventId, usize>,
+    /// Replies waiting for an ancestor nearer than the one they hang off, by its id.
+    waiting: HashMap<EventId, Vec<usize>>,
+}
+
+impl ThreadStore {
+    /// A store of `messages`, where each message's slot is its index. A copy of an earlier
+    /// message gets a slot of its own, as if it had no id.
+    pub fn from_messages(messages: &[MailMessage]) -> Self {
+        let mut store = Self::default();
+        for message in messages {
+            let id = message.id.filter(|id| store.slot(id).is_none());
+            store.insert(id, message.parent_events.as_deref().unwrap_or_default());
+        }
+        store
+    }
+
+    /// Adds the message `id`, replying to `ancestry` (oldest first, the direct parent last),
+    /// and returns its slot. A message added twice keeps its first slot. Messages without an
+    /// id, like drafts, can't be replied to but still take a slot.
+    pub fn insert(&mut self, id: Option<EventId>, ancestry: &[EventId]) -> usize {
+        if let Some(slot) = id.and_then(|id| self.slots.get(&id)) {
+            return *slot;
+        }
+        let slot = self.nodes.len();
+        let nearest = ancestry
+            .iter()
+            .rposition(|ancestor| self.slots.contains_key(ancestor));
+        self.nodes.push(Node {
+            id,
+            ancestry: ancestry.to_vec(),
+            parent: None,
+            children: Vec::new(),
+            root: slot,
+        });
+        for missing in &ancestry[nearest.map_or(0, |i| i + 1)..] {
+            self.waiting.entry(*missing).or_default().push(slot);
+        }
+        if let Some(i) = nearest {
+            self.attach(slot, self.slots[&ancestry[i]]);
+        }
+
+        if let Some(id) = id {
+            self.slots.insert(id, slot);
+            for reply in self.waiting.remove(&id).unwrap_or_default() {
+                if self.is_nearer_parent(reply, slot) {
+                    self.detach(reply);
+                    self.attach(reply, slot);
+                }
+            }
+        }
+        slot
+    }
+
+    /// Whether `candidate` is closer in `reply`'s ancestry than its parent so far, and not a
+    /// reply of `reply` itself, which a forged ancestry could make it.
+    fn is_nearer_parent(&self, reply: usize, candidate: usize) -> bool {
+        if self.is_within(candidate, reply) {
+            return false;
+        }
+        let position = |slot: usize| {
+            let id = self.nodes[slot].id?;
+            self.nodes[reply]
+                .ancestry
+                .iter()
+                .position(|ancestor| *ancestor == id)
+        };
+        match self.nodes[reply].parent {
+            Some(parent) => position(candidate) > position(parent),
+            None => true,
+        }
+    }
+
+    /// Whether `slot` is `ancestor` or one of its replies, however deep.
+    fn is_within(&self, slot: usize, ancestor: usize) -> bool {
+        let mut current = Some(slot);
+        while let Some(at) = current {
+            if at == ancestor {
+                return true;
+            }
+            current = self.nodes[at].parent;
+        }
+        false
+    }
+
+    fn detach(&mut self, slot: usize) {
+        if let Some(parent) = self.nodes[slot].parent.take() {
+            self.nodes[parent].children.retain(|child| *child != slot);
+        }
+        self.set_root(slot, slot);
+    }
+
+    fn attach(&mut self, slot: usize, parent: usize) {
+        self.nodes[slot].parent = Some(parent);
+        self.nodes[parent].children.push(slot);
+        self.set_root(slot, self.nodes[parent].root);
+    }
+
+    /// Moves `slot` and all its replies into the thread starting at `root`.
+    fn set_root(&mut self, slot: usize, root: usize) {
+        let mut stack = vec![slot];
+        while let Some(at) = stack.pop() {
+            self.nodes[at].root = root;
+            stack.extend(self.nodes[at].children.iter().copied());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The slot of the message `id`.
+    pub fn slot(&self, id: &EventId) -> Option<usize> {
+        self.slots.get(id).copied()
+    }
+
+    pub fn id(&self, slot: usize) -> Option<EventId> {
+        self.nodes[slot].id
+    }
+
+    /// The message `slot` replies to, as far as we have it.
+    pub fn parent(&self, slot: usize) -> Option<usize> {
+        self.nodes[slot].parent
+    }
+
+    /// The replies to `slot`, in the order they were added.
+    pub fn children(&self, slot: usize) -> &[usize] {
+        &self.nodes[slot].children
+    }
+
+    /// The first message of the thread `slot` is in.
+    pub fn root(&self, slot: usize) -> usize {
+        self.nodes[slot].root
+    }
+
+    /// The first message of every thread, in the order they were added.
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(|slot| self.nodes[*slot].parent.is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> EventId {
+        EventId::from_slice(&[n; 32]).unwrap()
+    }
+
+    #[test]
+    fn replies_find_their_parents_in_any_order() {
+        let mut store = ThreadStore::default();
+        // A reply to 2 comes in before 2 and even before the root.
+        let reply = store.insert(Some(id(3)), &[id(1), id(2)]);
+        assert_eq!(store.root(reply), reply);
+        let root = store.insert(Some(id(1)), &[]);
+        assert_eq!(store.parent(reply), Some(root));
+        let middle = store.insert(Some(id(2)), &[id(1)]);
+        assert_eq!(store.parent(reply), Some(middle));
+        assert_eq!(store.children(root), [middle]);
+        assert_eq!(store.children(middle), [reply]);
+        assert_eq!(store.root(reply), root);
+        assert_eq!(store.roots().collect::<Vec<_>>(), [root]);
+
+        // Copies keep their slot, drafts get one without joining a thread.
+        assert_eq!(store.insert(Some(id(2)), &[id(1)]), middle);
+        let draft = store.insert(None, &[]);
+        assert_eq!(store.roots().collect::<Vec<_>>(), [root, draft]);
+        assert_eq!(store.slot(&id(3)), Some(reply));
+        assert_eq!(store.len(), 4);
+    }
+
+    #[test]
+    fn forged_ancestry_cant_make_a_loop() {
+        let mut store = ThreadStore::default();
+        let a = store.insert(Some(id(1)), &[id(2)]);
+        let b = store.insert(Some(id(2)), &[id(1)]);
+        assert_eq!(store.parent(b), Some(a));
+        assert_eq!(store.parent(a), None);
+        assert_eq!(store.root(b), a);
+    }
+}
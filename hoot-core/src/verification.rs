@@ -0,0 +1,122 @@
+//! Checking that the key we have for a contact is really theirs.
+//!
+//! We mail the contact a random challenge. Their client answers with an event carrying the
+//! challenge, signed by their key and never published anywhere. The answer only counts if it
+//! comes from the contact we challenged, names the challenge we sent them and the signature
+//! holds up.
+
+use anyhow::{Context, Result};
+use nostr::{Event, EventBuilder, JsonUtil, Keys, Kind, PublicKey, Tag, TagKind};
+use rand::RngCore;
+
+pub const SUBJECT: &str = "Contact verification";
+/// Marks the line of a message holding a challenge.
+const CHALLENGE_PREFIX: &str = "hoot-verify-challenge:";
+/// Marks the line of a message holding the signed answer.
+const RESPONSE_PREFIX: &str = "hoot-verify-response:";
+/// The kind of the signed answer. It's only ever embedded in mail, never published.
+pub const RESPONSE_KIND: u16 = 2025;
+const CHALLENGE_TAG: &str = "challenge";
+
+/// A fresh random challenge, as hex.
+pub fn new_challenge() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The body of the message asking a contact to verify.
+pub fn challenge_body(challenge: &str) -> String {
+    format!(
+        "I'd like to make sure this key really belongs to you. Hoot answers this message for \
+         you when you confirm it, other clients can't yet.\n\n{}{}",
+        CHALLENGE_PREFIX, challenge
+    )
+}
+
+/// The challenge in a message body, if it asks us to verify.
+pub fn find_challenge(content: &str) -> Option<&str> {
+    find_line(content, CHALLENGE_PREFIX).filter(|challenge| {
+        challenge.len() == 64 && challenge.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// The body of the answer to `challenge`, signed with `keys`.
+pub fn response_body(keys: &Keys, challenge: &str) -> Result<String> {
+    let signed = EventBuilder::new(
+        Kind::Custom(RESPONSE_KIND),
+        format!("Hoot contact verification: {}", challenge),
+    )
+    .tags([Tag::custom(
+        TagKind::custom(CHALLENGE_TAG),
+        [challenge.to_string()],
+    )])
+    .custom_created_at(crate::clock::now())
+    .sign_with_keys(keys)
+    .context("Couldn't sign the verification answer")?;
+    Ok(format!(
+        "Here's my answer to your verification request.\n\n{}{}",
+        RESPONSE_PREFIX,
+        signed.as_json()
+    ))
+}
+
+/// Whether `content`, sent by `sender`, holds a valid answer to `challenge`.
+pub fn check_response(content: &str, sender: &PublicKey, challenge: &str) -> bool {
+    let Some(signed) =
+        find_line(content, RESPONSE_PREFIX).and_then(|json| Event::from_json(json).ok())
+    else {
+        return false;
+    };
+    let answers = signed
+        .tags
+        .iter()
+        .filter(|tag| tag.kind() == TagKind::custom(CHALLENGE_TAG))
+        .any(|tag| tag.content() == Some(challenge));
+    signed.kind == Kind::Custom(RESPONSE_KIND)
+        && signed.pubkey == *sender
+        && answers
+        && signed.verify().is_ok()
+}
+
+/// Whether `content` holds an answer to a challenge, whoever it's for.
+pub fn is_response(content: &str) -> bool {
+    find_line(content, RESPONSE_PREFIX).is_some()
+}
+
+fn find_line<'a>(content: &'a str, prefix: &str) -> Option<&'a str> {
+    content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(prefix))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_only_count_for_the_right_challenge_and_sender() -> Result<()> {
+        let contact = Keys::generate();
+        let challenge = new_challenge();
+        let body = challenge_body(&challenge);
+        assert_eq!(find_challenge(&body), Some(challenge.as_str()));
+        assert_eq!(find_challenge("hoot-verify-challenge: not hex"), None);
+
+        let answer = response_body(&contact, &challenge)?;
+        assert!(is_response(&answer));
+        assert!(check_response(&answer, &contact.public_key(), &challenge));
+        assert!(!check_response(
+            &answer,
+            &Keys::generate().public_key(),
+            &challenge
+        ));
+        assert!(!check_response(
+            &answer,
+            &contact.public_key(),
+            &new_challenge()
+        ));
+        assert!(!check_response(&body, &contact.public_key(), &challenge));
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+//! Paying through a wallet the user connected with Nostr Wallet Connect (NIP-47), for relays
+//! and media hosts that charge.
+//!
+//! The connection string names the wallet's key, the relay it listens on and a secret only we
+//! hold. Requests are events signed with that secret and encrypted to the wallet with NIP-44,
+//! and the wallet answers on the same relay. [`Wallet`] keeps its own connection to that relay
+//! so payments don't queue behind mail.
+
+use crate::relay::{ClientMessage, RelayMessage, RelayPool, Subscription};
+use anyhow::{anyhow, Context, Result};
+use ewebsock::WsMessage;
+use nostr::nips::nip44;
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, PublicKey, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+pub const URI_SCHEME: &str = "nostr+walletconnect://";
+pub const REQUEST_KIND: u16 = 23194;
+pub const RESPONSE_KIND: u16 = 23195;
+/// How requests are encrypted, told to the wallet in their `encryption` tag.
+const ENCRYPTION: &str = "nip44_v2";
+/// How long a wallet gets to answer before we give up on a request.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a connection string says. Kept in the database, since the secret spends money.
+#[derive(Clone)]
+pub struct WalletConnection {
+    pub wallet: PublicKey,
+    pub relay: String,
+    /// Our side of the connection, made from the secret.
+    keys: Keys,
+    /// The wallet's lightning address, if it gave one.
+    pub lud16: Option<String>,
+}
+
+impl WalletConnection {
+    /// Reads a `nostr+walletconnect://` connection string.
+    pub fn parse(uri: &str) -> Result<Self> {
+        let rest = uri.trim().strip_prefix(URI_SCHEME).ok_or_else(|| {
+            anyhow!(
+                "not a wallet connection, it should start with {}",
+                URI_SCHEME
+            )
+        })?;
+        let (wallet, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let wallet = PublicKey::from_hex(wallet.trim_end_matches('/'))
+            .context("the wallet's key isn't valid")?;
+
+        let mut relay = None;
+        let mut secret = None;
+        let mut lud16 = None;
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match key {
+                "relay" if relay.is_none() => relay = Some(value),
+                "secret" => secret = Some(value),
+                "lud16" => lud16 = Some(value),
+                _ => {}
+            }
+        }
+        let relay = relay
+            .filter(|relay| relay.starts_with("wss://") || relay.starts_with("ws://"))
+            .ok_or_else(|| anyhow!("the connection doesn't name a relay"))?;
+        let secret = secret.ok_or_else(|| anyhow!("the connection has no secret"))?;
+        let keys = Keys::parse(&secret).context("the connection's secret isn't valid")?;
+        Ok(Self {
+            wallet,
+            relay,
+            keys,
+            lud16,
+        })
+    }
+
+    /// The connection string again, for saving.
+    pub fn uri(&self) -> String {
+        let mut uri = format!(
+            "{}{}?relay={}&secret={}",
+            URI_SCHEME,
+            self.wallet.to_hex(),
+            percent_encode(&self.relay),
+            self.keys.secret_key().to_secret_hex()
+        );
+        if let Some(lud16) = &self.lud16 {
+            uri.push_str(&format!("&lud16={}", percent_encode(lud16)));
+        }
+        uri
+    }
+
+    /// `request` as an event for the wallet.
+    pub fn request(&self, request: &Request) -> Result<Event> {
+        let content = nip44::encrypt(
+            self.keys.secret_key(),
+            &self.wallet,
+            serde_json::to_string(request)?,
+            nip44::Version::V2,
+        )?;
+        let event = EventBuilder::new(Kind::Custom(REQUEST_KIND), content)
+            .tags([
+                Tag::public_key(self.wallet),
+                Tag::custom(TagKind::custom("encryption"), [ENCRYPTION]),
+            ])
+            .custom_created_at(crate::clock::now())
+            .sign_with_keys(&self.keys)?;
+        Ok(event)
+    }
+
+    /// The wallet's answers to us, to subscribe to.
+    pub fn answers(&self) -> Filter {
+        Filter::new()
+            .kind(Kind::Custom(RESPONSE_KIND))
+            .author(self.wallet)
+            .pubkey(self.keys.public_key())
+    }
+
+    /// The request `event` answers and what the wallet said, `None` if it isn't an answer from
+    /// our wallet.
+    pub fn read_answer(&self, event: &Event) -> Result<Option<(EventId, Outcome)>> {
+        if event.kind != Kind::Custom(RESPONSE_KIND) || event.pubkey != self.wallet {
+            return Ok(None);
+        }
+        let Some(request_id) = event
+            .tags
+            .find(TagKind::e())
+            .and_then(|tag| tag.content())
+            .and_then(|id| EventId::from_hex(id).ok())
+        else {
+            return Ok(None);
+        };
+        let json = nip44::decrypt(self.keys.secret_key(), &self.wallet, &event.content)?;
+        let answer: Answer = serde_json::from_str(&json).context("unexpected answer")?;
+        Ok(Some((request_id, answer.outcome())))
+    }
+}
+
+/// What we ask the wallet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Request {
+    PayInvoice { invoice: String },
+    GetBalance {},
+}
+
+/// What came of a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Paid {
+        preimage: String,
+    },
+    Balance {
+        msats: u64,
+    },
+    Failed(String),
+    /// The wallet didn't answer in time. It may still have done it, and if it answers later
+    /// that answer comes back too.
+    Unanswered,
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    result_type: String,
+    error: Option<AnswerError>,
+    result: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct AnswerError {
+    code: String,
+    message: String,
+}
+
+impl Answer {
+    fn outcome(self) -> Outcome {
+        if let Some(error) = self.error {
+            return Outcome::Failed(format!("{} ({})", error.message, error.code));
+        }
+        let result = self.result.unwrap_or_default();
+        match self.result_type.as_str() {
+            "pay_invoice" => match result.get("preimage").and_then(|p| p.as_str()) {
+                Some(preimage) => Outcome::Paid {
+                    preimage: preimage.to_string(),
+                },
+                None => Outcome::Failed("the wallet didn't say it paid".to_string()),
+            },
+            "get_balance" => match result.get("balance").and_then(|b| b.as_u64()) {
+                Some(msats) => Outcome::Balance { msats },
+                None => Outcome::Failed("the wallet didn't give a balance".to_string()),
+            },
+            other => Outcome::Failed(format!("unexpected answer to {}", other)),
+        }
+    }
+}
+
+/// A connected wallet, with its own connection to the wallet's relay.
+pub struct Wallet {
+    pub connection: WalletConnection,
+    relays: RelayPool,
+    /// Requests not sent yet because the relay isn't connected.
+    outbox: Vec<Event>,
+    /// Requests sent and waiting for an answer, by event id.
+    waiting: HashMap<String, Instant>,
+    /// Requests that went unanswered for too long, whose late answers are still passed on.
+    expired: HashSet<String>,
+}
+
+impl Wallet {
+    pub fn connect(
+        connection: WalletConnection,
+        wake_up: impl Fn() + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let mut relays = RelayPool::new();
+        relays
+            .add_url(connection.relay.clone(), wake_up)
+            .map_err(|e| anyhow!("could not connect to {}: {}", connection.relay, e))?;
+        let subscription = Subscription::new(
+            "hoot-wallet".to_string(),
+            vec![connection.answers().since(nostr::Timestamp::now())],
+        );
+        // Sent once the relay connects.
+        let _ = relays.add_subscription(subscription);
+        info!("Connecting to the wallet at {}", connection.relay);
+        Ok(Self {
+            connection,
+            relays,
+            outbox: Vec::new(),
+            waiting: HashMap::new(),
+            expired: HashSet::new(),
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.relays.connected_count() > 0
+    }
+
+    /// Sends `request`, or queues it until the relay connects. Returns the id its answer
+    /// comes back with.
+    pub fn send(&mut self, request: &Request) -> Result<String> {
+        let event = self.connection.request(request)?;
+        let id = event.id.to_hex();
+        self.waiting.insert(id.clone(), Instant::now());
+        self.outbox.push(event);
+        self.flush();
+        Ok(id)
+    }
+
+    /// Keeps the connection up and returns the answers that came in, by request id. Requests
+    /// the wallet left unanswered for too long come back unanswered, or failed if they never
+    /// reached it.
+    pub fn poll(
+        &mut self,
+        wake_up: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Vec<(String, Outcome)> {
+        self.relays.keepalive(wake_up);
+        self.flush();
+
+        let mut outcomes = Vec::new();
+        while let Some(raw) = self.relays.try_recv() {
+            let Ok(RelayMessage::Event(_, json)) = RelayMessage::from_json(&raw) else {
+                continue;
+            };
+            let Ok(event) = Event::from_json(json) else {
+                continue;
+            };
+            match self.connection.read_answer(&event) {
+                Ok(Some((request_id, outcome))) => {
+                    let request_id = request_id.to_hex();
+                    if self.waiting.remove(&request_id).is_some()
+                        || self.expired.remove(&request_id)
+                    {
+                        outcomes.push((request_id, outcome));
+                    }
+                }
+                Ok(None) => debug!("Ignoring event {} from the wallet relay", event.id),
+                Err(e) => error!("Couldn't read the wallet's answer: {}", e),
+            }
+        }
+
+        let expired: Vec<String> = self
+            .waiting
+            .iter()
+            .filter(|(_, sent)| sent.elapsed() > ANSWER_TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            self.waiting.remove(&id);
+            let queued = self.outbox.len();
+            self.outbox.retain(|event| event.id.to_hex() != id);
+            if self.outbox.len() < queued {
+                outcomes.push((id, Outcome::Failed("couldn't reach the wallet".to_string())));
+            } else {
+                self.expired.insert(id.clone());
+                outcomes.push((id, Outcome::Unanswered));
+            }
+        }
+        outcomes
+    }
+
+    /// Whether any request is still waiting for an answer.
+    pub fn is_waiting(&self) -> bool {
+        !self.waiting.is_empty()
+    }
+
+    pub fn close(&mut self) {
+        self.relays.close_all();
+    }
+
+    fn flush(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        for event in std::mem::take(&mut self.outbox) {
+            let payload = match serde_json::to_string(&ClientMessage::Event { event }) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    error!("Couldn't serialize the wallet request: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.relays.send(WsMessage::Text(payload)) {
+                error!("Couldn't send the wallet request: {}", e);
+            }
+        }
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(connection: &WalletConnection, wallet: &Keys, request: &Event, json: &str) -> Event {
+        let content = nip44::encrypt(
+            wallet.secret_key(),
+            &connection.keys.public_key(),
+            json,
+            nip44::Version::V2,
+        )
+        .unwrap();
+        EventBuilder::new(Kind::Custom(RESPONSE_KIND), content)
+            .tags([Tag::event(request.id)])
+            .sign_with_keys(wallet)
+            .unwrap()
+    }
+
+    #[test]
+    fn pays_through_the_connected_wallet() -> Result<()> {
+        let wallet = Keys::generate();
+        let secret = Keys::generate();
+        let uri = format!(
+            "nostr+walletconnect://{}?relay=wss%3A%2F%2Frelay.example.com%2Fv1&secret={}&lud16=me%40example.com",
+            wallet.public_key().to_hex(),
+            secret.secret_key().to_secret_hex()
+        );
+        let connection = WalletConnection::parse(&uri)?;
+        assert_eq!(connection.relay, "wss://relay.example.com/v1");
+        assert_eq!(connection.lud16.as_deref(), Some("me@example.com"));
+        assert_eq!(
+            WalletConnection::parse(&connection.uri())?.uri(),
+            connection.uri()
+        );
+        assert!(WalletConnection::parse("nostr+walletconnect://nope?relay=wss://a").is_err());
+
+        let request = connection.request(&Request::PayInvoice {
+            invoice: "lnbc1".to_string(),
+        })?;
+        assert_eq!(request.kind, Kind::Custom(REQUEST_KIND));
+
+        let paid = answer(
+            &connection,
+            &wallet,
+            &request,
+            r#"{"result_type": "pay_invoice", "result": {"preimage": "abc"}}"#,
+        );
+        assert_eq!(
+            connection.read_answer(&paid)?,
+            Some((
+                request.id,
+                Outcome::Paid {
+                    preimage: "abc".to_string()
+                }
+            ))
+        );
+        let refused = answer(
+            &connection,
+            &wallet,
+            &request,
+            r#"{"result_type": "pay_invoice", "error": {"code": "INSUFFICIENT_BALANCE", "message": "Not enough sats"}, "result": null}"#,
+        );
+        assert_eq!(
+            connection.read_answer(&refused)?,
+            Some((
+                request.id,
+                Outcome::Failed("Not enough sats (INSUFFICIENT_BALANCE)".to_string())
+            ))
+        );
+        // Only the wallet's answers count.
+        let forged = answer(
+            &connection,
+            &Keys::generate(),
+            &request,
+            r#"{"result_type": "pay_invoice", "result": {"preimage": "abc"}}"#,
+        );
+        assert_eq!(connection.read_answer(&forged)?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! Zaps (NIP-57): lightning payments to the sender of a message, with a public receipt.
+//!
+//! To zap, we look up the pay endpoint named by the recipient's lightning address (LUD-16),
+//! sign a zap request saying who is paid for what, and hand it to the endpoint's callback for an
+//! invoice. Once that's paid the recipient's wallet publishes a receipt carrying our request,
+//! which is how zaps on messages we sent show up here.
+//!
+//! Receipts are public and name the message by its id, so they show that the recipient got a
+//! message with that id, not what was in it. Anyone can publish one, so a receipt only counts
+//! when the recipient's lightning address signed it, and for what its invoice asked.
+
+use crate::bolt11::Invoice;
+use crate::wallet::percent_encode;
+use anyhow::{anyhow, bail, Context, Result};
+use nostr::hashes::{sha256, Hash};
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, PublicKey, Tag, TagKind};
+use serde::Deserialize;
+
+/// The pay endpoint of a lightning address (LUD-06 and LUD-16).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayEndpoint {
+    pub callback: String,
+    /// The smallest and largest amounts it takes, in millisatoshis.
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    /// Whether it publishes zap receipts.
+    #[serde(default)]
+    pub allows_nostr: bool,
+    /// The key its receipts are signed with.
+    pub nostr_pubkey: Option<String>,
+}
+
+impl PayEndpoint {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let endpoint: Self = serde_json::from_str(json).context("not a pay endpoint")?;
+        if !endpoint.allows_nostr || endpoint.nostr_pubkey.is_none() {
+            bail!("the lightning address doesn't take zaps");
+        }
+        Ok(endpoint)
+    }
+
+    /// The key zap receipts from this endpoint are signed with.
+    pub fn receipt_key(&self) -> Result<PublicKey> {
+        let key = self
+            .nostr_pubkey
+            .as_deref()
+            .ok_or_else(|| anyhow!("the lightning address doesn't sign zap receipts"))?;
+        PublicKey::from_hex(key).context("the lightning address's receipt key isn't valid")
+    }
+
+    /// Where to get an invoice for `msats` paying for `request`.
+    pub fn invoice_url(&self, request: &Event, msats: u64) -> Result<String> {
+        if msats < self.min_sendable || msats > self.max_sendable {
+            bail!(
+                "the lightning address takes {} to {} sats",
+                self.min_sendable / 1000,
+                self.max_sendable / 1000
+            );
+        }
+        let separator = if self.callback.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}amount={}&nostr={}",
+            self.callback,
+            separator,
+            msats,
+            percent_encode(&request.as_json())
+        ))
+    }
+}
+
+/// Where the pay endpoint of the lightning address `name@domain` is served.
+pub fn endpoint_url(lud16: &str) -> Option<String> {
+    let (name, domain) = lud16.trim().split_once('@')?;
+    if name.is_empty() || domain.is_empty() || domain.contains('/') {
+        return None;
+    }
+    Some(format!(
+        "https://{}/.well-known/lnurlp/{}",
+        domain.to_lowercase(),
+        name.to_lowercase()
+    ))
+}
+
+/// A zap request paying `recipient`, asking for the receipt on `relays`. The request is
+/// public, so `message` is only named when it's public too: the id of gift-wrapped mail would
+/// tell everyone who wrote to whom about what.
+pub fn request(
+    keys: &Keys,
+    recipient: PublicKey,
+    message: Option<EventId>,
+    msats: u64,
+    relays: &[String],
+    comment: &str,
+) -> Result<Event> {
+    if relays.is_empty() {
+        bail!("no relay to publish the receipt to");
+    }
+    let mut tags = vec![
+        Tag::custom(TagKind::Relays, relays.iter().cloned()),
+        Tag::custom(TagKind::Amount, [msats.to_string()]),
+        Tag::public_key(recipient),
+    ];
+    tags.extend(message.map(Tag::event));
+    let event = EventBuilder::new(Kind::ZapRequest, comment)
+        .tags(tags)
+        .custom_created_at(crate::clock::now())
+        .sign_with_keys(keys)?;
+    Ok(event)
+}
+
+/// Checks that the invoice a pay endpoint answered `request` with is for it, asking for the
+/// `msats` we requested and committing to the request in its description hash.
+pub fn check_invoice(invoice: &str, request: &Event, msats: u64) -> Result<()> {
+    let decoded = Invoice::decode(invoice).context("the invoice isn't valid")?;
+    if decoded.msats != Some(msats) {
+        bail!(
+            "the invoice asks for {} instead of {} sats",
+            decoded
+                .msats
+                .map_or("any amount".to_string(), |asked| (asked / 1000).to_string()),
+            msats / 1000
+        );
+    }
+    let hash = sha256::Hash::hash(request.as_json().as_bytes());
+    let expected: String = hash
+        .to_byte_array()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if decoded.description_hash.as_deref() != Some(expected.as_str()) {
+        bail!("the invoice isn't for our zap request");
+    }
+    Ok(())
+}
+
+/// The receipts of zaps to `pubkeys`.
+pub fn receipts(pubkeys: impl IntoIterator<Item = PublicKey>) -> Filter {
+    Filter::new().kind(Kind::ZapReceipt).pubkeys(pubkeys)
+}
+
+/// A zap on one of our messages, as its receipt tells it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zap {
+    pub receipt_id: String,
+    /// The message zapped.
+    pub event_id: String,
+    pub recipient: String,
+    pub sender: String,
+    pub msats: u64,
+    pub comment: String,
+    pub created_at: i64,
+}
+
+impl Zap {
+    /// Reads a zap receipt (kind 9735), checking it was signed with `receipt_key`, the key of
+    /// the recipient's lightning address, and that the request inside it was signed by the
+    /// sender and names the same recipient, message and amount as the receipt (NIP-57
+    /// Appendix F).
+    pub fn from_receipt(receipt: &Event, receipt_key: &PublicKey) -> Result<Self> {
+        if receipt.kind != Kind::ZapReceipt {
+            bail!("not a zap receipt");
+        }
+        if receipt.pubkey != *receipt_key {
+            bail!("the receipt isn't from the recipient's lightning address");
+        }
+        receipt
+            .verify()
+            .map_err(|e| anyhow!("the receipt isn't signed: {}", e))?;
+        let description = tag_value(receipt, TagKind::Description)
+            .ok_or_else(|| anyhow!("the receipt has no zap request"))?;
+        let request = Event::from_json(description).context("the zap request isn't an event")?;
+        if request.kind != Kind::ZapRequest {
+            bail!("the receipt doesn't carry a zap request");
+        }
+        request
+            .verify()
+            .map_err(|e| anyhow!("the zap request isn't signed: {}", e))?;
+
+        let recipient = tag_value(&request, TagKind::p())
+            .ok_or_else(|| anyhow!("the zap request names no recipient"))?;
+        let event_id = tag_value(&request, TagKind::e())
+            .ok_or_else(|| anyhow!("the zap isn't for a message"))?;
+        if tag_value(receipt, TagKind::p()) != Some(recipient)
+            || tag_value(receipt, TagKind::e()) != Some(event_id)
+        {
+            bail!("the receipt and its zap request disagree");
+        }
+        // What was paid is what the invoice asked for, not what the sender says they sent.
+        let bolt11 = tag_value(receipt, TagKind::Bolt11)
+            .ok_or_else(|| anyhow!("the receipt has no invoice"))?;
+        let msats = Invoice::decode(bolt11)
+            .context("the receipt's invoice isn't valid")?
+            .msats
+            .ok_or_else(|| anyhow!("the receipt's invoice has no amount"))?;
+        if let Some(requested) = tag_value(&request, TagKind::Amount) {
+            if requested.parse::<u64>().ok() != Some(msats) {
+                bail!("the zap request and its invoice disagree on the amount");
+            }
+        }
+        Ok(Self {
+            receipt_id: receipt.id.to_hex(),
+            event_id: event_id.to_string(),
+            recipient: recipient.to_string(),
+            sender: request.pubkey.to_hex(),
+            msats,
+            comment: request.content.clone(),
+            created_at: receipt.created_at.as_u64() as i64,
+        })
+    }
+}
+
+fn tag_value<'a>(event: &'a Event, kind: TagKind<'_>) -> Option<&'a str> {
+    event.tags.find(kind).and_then(|tag| tag.content())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bolt11;
+
+    #[test]
+    fn reads_the_receipt_of_a_zap_request() -> Result<()> {
+        assert_eq!(
+            endpoint_url("Alice@Example.com").as_deref(),
+            Some("https://example.com/.well-known/lnurlp/alice")
+        );
+        assert_eq!(endpoint_url("example.com"), None);
+
+        let endpoint = PayEndpoint::from_json(
+            r#"{"callback": "https://example.com/pay?user=alice", "minSendable": 1000,
+                "maxSendable": 100000000, "allowsNostr": true, "nostrPubkey": "abc", "tag": "payRequest"}"#,
+        )?;
+        assert!(PayEndpoint::from_json(
+            r#"{"callback": "https://example.com/pay", "minSendable": 1000, "maxSendable": 2000}"#
+        )
+        .is_err());
+
+        let sender = Keys::generate();
+        let recipient = Keys::generate().public_key();
+        let message = EventId::all_zeros();
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let private = request(&sender, recipient, None, 21_000, &relays, "")?;
+        assert_eq!(private.tags.find(TagKind::e()), None);
+        let request = request(
+            &sender,
+            recipient,
+            Some(message),
+            21_000,
+            &relays,
+            "Thanks!",
+        )?;
+        let url = endpoint.invoice_url(&request, 21_000)?;
+        assert!(url.starts_with("https://example.com/pay?user=alice&amount=21000&nostr=%7B"));
+        assert!(endpoint.invoice_url(&request, 10).is_err());
+
+        // The invoice we pay has to be for the amount asked, and for this very request.
+        let node = secp256k1::SecretKey::from_slice(&[9; 32])?;
+        let hash = sha256::Hash::hash(request.as_json().as_bytes()).to_byte_array();
+        let check = |hrp: &str, hash: &[u8; 32]| {
+            check_invoice(&bolt11::sign_hashed(hrp, hash, &node), &request, 21_000)
+        };
+        check("lnbc210n", &hash)?;
+        assert!(check("lnbc1m", &hash).is_err());
+        assert!(check("lnbc", &hash).is_err());
+        assert!(check("lnbc210n", &[0; 32]).is_err());
+        assert!(check_invoice(&bolt11::sign("lnbc210n", "", &node), &request, 21_000).is_err());
+
+        // The endpoint's own key signs receipts, over an invoice for the zap's amount.
+        let provider = Keys::generate();
+        let receipt = |signer: &Keys, recipient: PublicKey, hrp: &str| {
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags([
+                    Tag::public_key(recipient),
+                    Tag::event(message),
+                    Tag::custom(TagKind::Bolt11, [bolt11::sign(hrp, "", &node)]),
+                    Tag::custom(TagKind::Description, [request.as_json()]),
+                ])
+                .sign_with_keys(signer)
+        };
+        let zap = Zap::from_receipt(
+            &receipt(&provider, recipient, "lnbc210n")?,
+            &provider.public_key(),
+        )?;
+        assert_eq!(zap.sender, sender.public_key().to_hex());
+        assert_eq!(zap.event_id, message.to_hex());
+        assert_eq!(zap.msats, 21_000);
+        assert_eq!(zap.comment, "Thanks!");
+
+        // Anyone can sign a receipt for a request they made, but only the endpoint's count.
+        let forged = receipt(&sender, recipient, "lnbc210n")?;
+        assert!(Zap::from_receipt(&forged, &provider.public_key()).is_err());
+        let underpaid = receipt(&provider, recipient, "lnbc10n")?;
+        assert!(Zap::from_receipt(&underpaid, &provider.public_key()).is_err());
+        let mismatched = receipt(&provider, Keys::generate().public_key(), "lnbc210n")?;
+        assert!(Zap::from_receipt(&mismatched, &provider.public_key()).is_err());
+
+        let endpoint_key = PayEndpoint {
+            nostr_pubkey: Some(provider.public_key().to_hex()),
+            ..endpoint
+        }
+        .receipt_key()?;
+        assert_eq!(endpoint_key, provider.public_key());
+        Ok(())
+    }
+}
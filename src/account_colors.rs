@@ -0,0 +1,92 @@
+//! A color for each account, so it's clear at a glance which identity a conversation is with
+//! or a message is being written as.
+
+use eframe::egui::Color32;
+use nostr::Keys;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const ACCOUNT_COLORS_KEY: &str = "account_colors";
+
+/// The colors accounts get unless the user picks one, apart enough to tell from each other.
+const PALETTE: [Color32; 8] = [
+    Color32::from_rgb(149, 117, 205),
+    Color32::from_rgb(38, 166, 154),
+    Color32::from_rgb(239, 108, 0),
+    Color32::from_rgb(30, 136, 229),
+    Color32::from_rgb(216, 27, 96),
+    Color32::from_rgb(124, 179, 66),
+    Color32::from_rgb(109, 76, 65),
+    Color32::from_rgb(253, 216, 53),
+];
+
+/// Colors the user picked, by account pubkey in hex.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountColors {
+    chosen: HashMap<String, [u8; 3]>,
+}
+
+impl AccountColors {
+    /// The color of `pubkey`. Accounts without a picked color take the palette color their key
+    /// points at, or the next one no account before them in `accounts` has.
+    pub fn color(&self, pubkey: &str, accounts: &[Keys]) -> Color32 {
+        if let Some([r, g, b]) = self.chosen.get(pubkey) {
+            return Color32::from_rgb(*r, *g, *b);
+        }
+        let mut taken: Vec<Color32> = Vec::new();
+        for account in accounts {
+            let hex = account.public_key().to_hex();
+            if hex == pubkey {
+                break;
+            }
+            taken.push(self.chosen.get(&hex).map_or_else(
+                || free_color(&hex, &taken),
+                |[r, g, b]| Color32::from_rgb(*r, *g, *b),
+            ));
+        }
+        free_color(pubkey, &taken)
+    }
+
+    pub fn is_chosen(&self, pubkey: &str) -> bool {
+        self.chosen.contains_key(pubkey)
+    }
+
+    /// Gives `pubkey` the color `color`, or its automatic one back with `None`.
+    pub fn set(&mut self, pubkey: &str, color: Option<[u8; 3]>) {
+        match color {
+            Some(color) => self.chosen.insert(pubkey.to_string(), color),
+            None => self.chosen.remove(pubkey),
+        };
+    }
+}
+
+/// The first palette color from the one `pubkey` points at that isn't `taken`. With more
+/// accounts than colors they have to share.
+fn free_color(pubkey: &str, taken: &[Color32]) -> Color32 {
+    let start = u8::from_str_radix(pubkey.get(..2).unwrap_or_default(), 16).unwrap_or(0) as usize;
+    (0..PALETTE.len())
+        .map(|i| PALETTE[(start + i) % PALETTE.len()])
+        .find(|color| !taken.contains(color))
+        .unwrap_or(PALETTE[start % PALETTE.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_get_colors_of_their_own() {
+        let accounts: Vec<Keys> = (0..3).map(|_| Keys::generate()).collect();
+        let hex: Vec<String> = accounts.iter().map(|k| k.public_key().to_hex()).collect();
+        let mut colors = AccountColors::default();
+        let auto: Vec<Color32> = hex.iter().map(|pk| colors.color(pk, &accounts)).collect();
+        assert!(auto[0] != auto[1] && auto[1] != auto[2] && auto[0] != auto[2]);
+        // Picking a color doesn't move the others.
+        colors.set(&hex[2], Some([1, 2, 3]));
+        assert_eq!(colors.color(&hex[2], &accounts), Color32::from_rgb(1, 2, 3));
+        assert_eq!(colors.color(&hex[1], &accounts), auto[1]);
+        colors.set(&hex[2], None);
+        assert!(!colors.is_chosen(&hex[2]));
+        assert_eq!(colors.color(&hex[2], &accounts), auto[2]);
+    }
+}
@@ -0,0 +1,177 @@
+//! Changes the user makes to their mail. They show on screen right away and are saved on a
+//! connection of their own, so a slow write never holds up a frame. When saving fails the
+//! screen goes back to what the database has and a toast says what didn't stick.
+
+use crate::db::Db;
+use eframe::egui;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use tracing::error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Star {
+        event_id: String,
+        starred: bool,
+    },
+    /// Every message of the thread starting at `root_id`.
+    Archive {
+        root_id: String,
+        archived: bool,
+    },
+    Trash {
+        event_id: String,
+        purge_after: i64,
+    },
+}
+
+impl Action {
+    /// What didn't happen, for the toast when saving fails.
+    pub fn failure(&self) -> &'static str {
+        match self {
+            Action::Star { starred: true, .. } => "Couldn't star the message",
+            Action::Star { .. } => "Couldn't unstar the message",
+            Action::Archive { archived: true, .. } => "Couldn't archive the conversation",
+            Action::Archive { .. } => "Couldn't move the conversation to the inbox",
+            Action::Trash { .. } => "Couldn't move the message to the trash",
+        }
+    }
+
+    /// Saves the change, and returns the messages whose flags it changed.
+    fn save(&self, db: &mut Db) -> anyhow::Result<Vec<String>> {
+        match self {
+            Action::Star { event_id, starred } => {
+                db.set_starred(event_id, *starred)?;
+                Ok(vec![event_id.clone()])
+            }
+            Action::Archive { root_id, archived } => {
+                let ids: Vec<String> = db
+                    .get_email_thread(root_id)?
+                    .iter()
+                    .filter_map(|msg| msg.id.map(|id| id.to_hex()))
+                    .collect();
+                db.set_archived(&ids, *archived)?;
+                Ok(ids)
+            }
+            Action::Trash {
+                event_id,
+                purge_after,
+            } => {
+                db.record_trash(std::slice::from_ref(event_id), *purge_after)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+/// An action once it's saved, with the messages whose flags changed, or once it failed.
+pub struct Outcome {
+    pub action: Action,
+    pub result: anyhow::Result<Vec<String>>,
+}
+
+struct Writer {
+    actions: Sender<Action>,
+    outcomes: Receiver<Outcome>,
+}
+
+/// Actions on their way to the database. Without a writer of its own, e.g. for an in-memory
+/// database, they're saved as they're performed.
+#[derive(Default)]
+pub struct Actions {
+    writer: Option<Writer>,
+    /// Outcomes of actions saved inline, until they're processed.
+    saved: Vec<Outcome>,
+    /// Actions shown but not saved yet, oldest first.
+    pending: Vec<Action>,
+}
+
+impl Actions {
+    pub fn spawn(writer: Db, ctx: egui::Context) -> Self {
+        let (actions, action_receiver) = std::sync::mpsc::channel();
+        let (outcome_sender, outcomes) = std::sync::mpsc::channel();
+        thread::spawn(move || run(writer, action_receiver, outcome_sender, ctx));
+        Self {
+            writer: Some(Writer { actions, outcomes }),
+            ..Default::default()
+        }
+    }
+
+    /// Saves `action`, which the caller has shown already. `db` is only used without a writer.
+    pub fn perform(&mut self, action: Action, db: &mut Db) {
+        self.pending.push(action.clone());
+        let action = match &self.writer {
+            Some(writer) => match writer.actions.send(action) {
+                Ok(()) => return,
+                Err(e) => {
+                    error!("Database writer is gone, saving {:?} inline", e.0);
+                    e.0
+                }
+            },
+            None => action,
+        };
+        let result = action.save(db);
+        self.saved.push(Outcome { action, result });
+    }
+
+    /// Actions shown but not saved yet, to show again over anything reloaded meanwhile.
+    pub fn pending(&self) -> &[Action] {
+        &self.pending
+    }
+
+    /// Outcomes that came in since the last call. The writer wakes the UI when it has one.
+    pub fn process(&mut self) -> Vec<Outcome> {
+        let mut outcomes = std::mem::take(&mut self.saved);
+        if let Some(writer) = &self.writer {
+            outcomes.extend(writer.outcomes.try_iter());
+        }
+        for outcome in &outcomes {
+            if let Some(i) = self.pending.iter().position(|a| *a == outcome.action) {
+                self.pending.remove(i);
+            }
+        }
+        outcomes
+    }
+}
+
+fn run(mut db: Db, actions: Receiver<Action>, outcomes: Sender<Outcome>, ctx: egui::Context) {
+    while let Ok(action) = actions.recv() {
+        let result = action.save(&mut db);
+        if outcomes.send(Outcome { action, result }).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_stay_pending_until_saved() -> anyhow::Result<()> {
+        let mut db = Db::new_in_memory()?;
+        let mut actions = Actions::default();
+        let star = Action::Star {
+            event_id: "00".repeat(32),
+            starred: true,
+        };
+        actions.perform(star.clone(), &mut db);
+        assert_eq!(actions.pending(), std::slice::from_ref(&star));
+
+        let outcomes = actions.process();
+        assert!(actions.pending().is_empty());
+        match outcomes.as_slice() {
+            [Outcome {
+                action,
+                result: Ok(changed),
+            }] => {
+                assert_eq!(*action, star);
+                assert_eq!(*changed, ["00".repeat(32)]);
+            }
+            _ => panic!("expected the star to be saved"),
+        }
+        assert!(db.get_starred_ids()?.contains(&"00".repeat(32)));
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+//! Hooks that let other programs act on new mail, like making a task for it. Each hook has a
+//! query in the search box language, and when new mail matches it Hoot runs a command or posts
+//! to a webhook on this computer with who the mail is from, its subject and its id.
+//!
+//! Commands run as the user and the subject is whatever the sender wrote, so hooks are off
+//! until the user adds one, and webhooks can only be local so mail never leaves the machine
+//! through them. Anyone can send mail, so hooks run one at a time from a short queue and each
+//! runs at most a few times a minute. Mail that comes in faster than that doesn't run them, and
+//! a command that doesn't finish in time is stopped so it can't hold up the rest.
+
+use crate::db::MessageFilter;
+use crate::search_query;
+use crate::Hoot;
+use anyhow::{bail, Context, Result};
+use eframe::egui;
+use nostr::{TagKind, Timestamp, ToBech32, UnsignedEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+pub const AUTOMATION_KEY: &str = "automation";
+/// Mail older than this doesn't run hooks, so catching up on old mail doesn't run them all.
+const RECENT_SECS: u64 = 24 * 60 * 60;
+/// Hook runs waiting for the worker. More are skipped.
+const QUEUE_LEN: usize = 16;
+/// How often each hook may run within [`RATE_WINDOW`].
+const RATE_LIMIT: usize = 6;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// How long a command may run before it's stopped.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    /// A program with any arguments, given the mail as JSON on stdin.
+    Command(String),
+    /// A URL on this computer the mail is posted to as JSON.
+    Webhook(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hook {
+    pub enabled: bool,
+    /// Mail has to match this, in the search box language. Empty matches all new mail.
+    pub query: String,
+    pub action: Action,
+}
+
+impl Default for Hook {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            query: String::new(),
+            action: Action::Command(String::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutomationConfig {
+    pub hooks: Vec<Hook>,
+}
+
+/// What a hook is given about new mail.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Payload {
+    pub id: String,
+    /// The sender's npub.
+    pub sender: String,
+    pub sender_name: String,
+    pub subject: String,
+    /// The npub of our account the mail came to, if we know it.
+    pub account: Option<String>,
+    pub created_at: u64,
+}
+
+impl Payload {
+    /// Made up mail for trying a hook out.
+    pub fn example() -> Self {
+        Self {
+            id: "0".repeat(64),
+            sender: "npub1example".to_string(),
+            sender_name: "Hoot".to_string(),
+            subject: "Testing a hook".to_string(),
+            account: None,
+            created_at: Timestamp::now().as_u64(),
+        }
+    }
+}
+
+/// What's known about new mail when it comes in, to match hooks against.
+struct Mail<'a> {
+    subject: &'a str,
+    body: &'a str,
+    has_attachment: bool,
+    from_contact: bool,
+}
+
+/// Whether `mail` matches `filter`. New mail is always unread, and never archived or starred
+/// yet.
+fn matches(filter: &MessageFilter, mail: &Mail) -> bool {
+    let contains = |text: &str, word: &str| text.to_lowercase().contains(&word.to_lowercase());
+    filter
+        .query
+        .split_whitespace()
+        .all(|word| contains(mail.subject, word) || contains(mail.body, word))
+        && filter
+            .subject_terms
+            .iter()
+            .all(|word| contains(mail.subject, word))
+        && filter.note_terms.is_empty()
+        && (!filter.has_attachment || mail.has_attachment)
+        && (!filter.from_contacts || mail.from_contact)
+        && !filter.starred
+        && !filter.archived
+}
+
+/// Whether `url` is on this computer, the only place webhooks may go.
+pub fn is_local(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url.trim()) else {
+        return false;
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+fn run(action: &Action, payload: &Payload, timeout: Duration) -> Result<()> {
+    let json = serde_json::to_string(payload)?;
+    match action {
+        Action::Command(command) => {
+            let mut words = command.split_whitespace();
+            let program = words.next().context("No command set")?;
+            let mut child = Command::new(program)
+                .args(words)
+                .env("HOOT_ID", &payload.id)
+                .env("HOOT_SENDER", &payload.sender)
+                .env("HOOT_SENDER_NAME", &payload.sender_name)
+                .env("HOOT_SUBJECT", &payload.subject)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .with_context(|| format!("Couldn't start {}", program))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // Commands that only look at the environment don't read it, which is fine.
+                let _ = stdin.write_all(json.as_bytes());
+            }
+            // Read on the side, so a chatty command can't fill the pipe and stall.
+            let mut stderr = child.stderr.take();
+            let stderr = thread::spawn(move || {
+                let mut output = Vec::new();
+                if let Some(stderr) = stderr.as_mut() {
+                    let _ = stderr.read_to_end(&mut output);
+                }
+                output
+            });
+            let deadline = Instant::now() + timeout;
+            let status = loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    bail!(
+                        "{} didn't finish within {}s and was stopped",
+                        program,
+                        timeout.as_secs()
+                    );
+                }
+                thread::sleep(Duration::from_millis(50));
+            };
+            if !status.success() {
+                let stderr = stderr.join().unwrap_or_default();
+                let stderr = String::from_utf8_lossy(&stderr);
+                bail!("{} failed ({}): {}", program, status, stderr.trim());
+            }
+        }
+        Action::Webhook(url) => {
+            if !is_local(url) {
+                bail!("{} isn't on this computer", url.trim());
+            }
+            let client = reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?;
+            let status = client
+                .post(url.trim())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(json)
+                .send()
+                .with_context(|| format!("Couldn't reach {}", url.trim()))?
+                .status();
+            if !status.is_success() {
+                bail!("{} answered {}", url.trim(), status);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How a hook last went, with when, to show in Settings.
+pub type LastRun = (Timestamp, Result<(), String>);
+
+/// A hook to run for some mail, by the hook's place in the config.
+type Job = (usize, Action, Payload);
+
+/// When a hook last ran, to keep it to [`RATE_LIMIT`] runs per [`RATE_WINDOW`].
+#[derive(Default)]
+struct RateLimit {
+    runs: VecDeque<Instant>,
+}
+
+impl RateLimit {
+    /// Whether the hook may run at `now`, counting it as run if so.
+    fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .runs
+            .front()
+            .is_some_and(|run| now.duration_since(*run) >= RATE_WINDOW)
+        {
+            self.runs.pop_front();
+        }
+        if self.runs.len() >= RATE_LIMIT {
+            return false;
+        }
+        self.runs.push_back(now);
+        true
+    }
+}
+
+pub struct Automations {
+    pub config: AutomationConfig,
+    /// By the hook's place in the config.
+    pub last_runs: HashMap<usize, LastRun>,
+    rate_limits: HashMap<usize, RateLimit>,
+    /// Runs queued or running.
+    pending: usize,
+    /// The worker's queue, once it started.
+    queue: Option<SyncSender<Job>>,
+    sender: Sender<(usize, LastRun)>,
+    receiver: Receiver<(usize, LastRun)>,
+}
+
+impl Automations {
+    pub fn new(config: AutomationConfig) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            config,
+            last_runs: HashMap::new(),
+            rate_limits: HashMap::new(),
+            pending: 0,
+            queue: None,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Queues the hook at `index` to run for `payload`, unless it ran too often lately or too
+    /// many runs are waiting already.
+    pub fn run(&mut self, index: usize, payload: Payload) {
+        let Some(hook) = self.config.hooks.get(index) else {
+            return;
+        };
+        if !self
+            .rate_limits
+            .entry(index)
+            .or_default()
+            .allow(Instant::now())
+        {
+            warn!(
+                "Automation hook {} ran {} times in the last minute, skipping {}",
+                index + 1,
+                RATE_LIMIT,
+                payload.id
+            );
+            return;
+        }
+        let queue = self.queue.get_or_insert_with(|| {
+            let (queue, jobs) = std::sync::mpsc::sync_channel(QUEUE_LEN);
+            let sender = self.sender.clone();
+            thread::spawn(move || work(jobs, sender));
+            queue
+        });
+        match queue.try_send((index, hook.action.clone(), payload)) {
+            Ok(()) => self.pending += 1,
+            Err(TrySendError::Full((_, _, payload))) => {
+                warn!("Too many automation hooks waiting, skipping {}", payload.id)
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("The automation worker stopped");
+                self.queue = None;
+            }
+        }
+    }
+
+    /// Removes the hook at `index`, moving what's kept about the hooks after it along with
+    /// them.
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.config.hooks.len() {
+            return;
+        }
+        self.config.hooks.remove(index);
+        shift_down(&mut self.last_runs, index);
+        shift_down(&mut self.rate_limits, index);
+    }
+
+    /// Takes in how the hooks that finished went.
+    pub fn process_queue(&mut self, ctx: &egui::Context) {
+        while let Ok((index, last_run)) = self.receiver.try_recv() {
+            self.pending = self.pending.saturating_sub(1);
+            match &last_run.1 {
+                Ok(()) => info!("Ran automation hook {}", index + 1),
+                Err(e) => error!("Automation hook {} failed: {}", index + 1, e),
+            }
+            self.last_runs.insert(index, last_run);
+        }
+
+        // The worker can't wake us up, so keep polling while hooks wait or run.
+        if self.pending > 0 {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+    }
+}
+
+/// Drops the entry for `removed` from `map`, keyed by hook index, and moves the ones after it
+/// down by one.
+fn shift_down<T>(map: &mut HashMap<usize, T>, removed: usize) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter(|(index, _)| *index != removed)
+        .map(|(index, value)| (if index > removed { index - 1 } else { index }, value))
+        .collect();
+}
+
+/// Runs the queued hooks one after another, until the app drops the queue.
+fn work(jobs: Receiver<Job>, results: Sender<(usize, LastRun)>) {
+    for (index, action, payload) in jobs {
+        let result = run(&action, &payload, COMMAND_TIMEOUT).map_err(|e| e.to_string());
+        if results.send((index, (Timestamp::now(), result))).is_err() {
+            debug!("Automation receiver dropped before a hook finished");
+            return;
+        }
+    }
+}
+
+/// Runs the hooks that new mail `rumor` matches. Called as it comes in, like
+/// [`crate::ui::notifications::check_mail`].
+pub fn check_mail(app: &mut Hoot, rumor: &UnsignedEvent, rumor_id: &str, account: Option<&str>) {
+    let hooks: Vec<(usize, MessageFilter)> = app
+        .automations
+        .config
+        .hooks
+        .iter()
+        .enumerate()
+        .filter(|(_, hook)| hook.enabled)
+        .map(|(index, hook)| (index, search_query::parse(&hook.query)))
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+    let ours = &app.account_manager.loaded_keys;
+    if ours.iter().any(|keys| keys.public_key() == rumor.pubkey) {
+        return;
+    }
+    let age = Timestamp::now()
+        .as_u64()
+        .saturating_sub(rumor.created_at.as_u64());
+    if age > RECENT_SECS {
+        return;
+    }
+
+    let author = rumor.pubkey.to_hex();
+    let subject = rumor
+        .tags
+        .find(TagKind::Subject)
+        .and_then(|tag| tag.content())
+        .unwrap_or_default();
+    let mail = Mail {
+        subject,
+        body: &rumor.content,
+        has_attachment: rumor
+            .tags
+            .iter()
+            .any(|tag| tag.as_slice().first().is_some_and(|name| name == "imeta")),
+        from_contact: app.contacts_manager.find_contact(&author).is_some(),
+    };
+    let payload = Payload {
+        id: rumor_id.to_string(),
+        sender: rumor.pubkey.to_bech32().unwrap_or(author.clone()),
+        sender_name: app.display_name(&author),
+        subject: subject.to_string(),
+        account: account.and_then(|account| {
+            nostr::PublicKey::from_hex(account)
+                .ok()
+                .and_then(|pubkey| pubkey.to_bech32().ok())
+        }),
+        created_at: rumor.created_at.as_u64(),
+    };
+    for (index, filter) in hooks {
+        if matches(&filter, &mail) {
+            app.automations.run(index, payload.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_new_mail_against_queries() {
+        let mail = Mail {
+            subject: "Invoice for March",
+            body: "Please pay by Friday.",
+            has_attachment: true,
+            from_contact: false,
+        };
+        let matching = |query: &str| matches(&search_query::parse(query), &mail);
+        assert!(matching(""));
+        assert!(matching("subject:invoice friday has:attachment is:unread"));
+        assert!(!matching("subject:friday"));
+        assert!(!matching("from:contacts"));
+        assert!(!matching("note:anything"));
+    }
+
+    #[test]
+    fn webhooks_stay_local() {
+        assert!(is_local("http://localhost:8080/hook"));
+        assert!(is_local(" http://127.0.0.1/hook "));
+        assert!(is_local("https://[::1]:3000"));
+        assert!(!is_local("https://example.com/hook"));
+        assert!(!is_local("http://localhost.example.com/"));
+        assert!(!is_local("file:///etc/passwd"));
+        assert!(!is_local("localhost:8080"));
+    }
+
+    #[test]
+    fn hooks_run_a_few_times_a_minute() {
+        let mut limit = RateLimit::default();
+        let start = Instant::now();
+        assert!((0..RATE_LIMIT).all(|_| limit.allow(start)));
+        assert!(!limit.allow(start + Duration::from_secs(59)));
+        let later = start + RATE_WINDOW;
+        let allowed = (0..RATE_LIMIT * 2).filter(|_| limit.allow(later)).count();
+        assert_eq!(allowed, RATE_LIMIT);
+    }
+
+    #[test]
+    fn removing_a_hook_keeps_the_others_state() {
+        let mut automations = Automations::new(AutomationConfig {
+            hooks: vec![Hook::default(); 3],
+        });
+        let now = Instant::now();
+        for index in 0..3 {
+            automations
+                .last_runs
+                .insert(index, (Timestamp::from(index as u64), Ok(())));
+        }
+        let limited = automations.rate_limits.entry(1).or_default();
+        assert!((0..RATE_LIMIT).all(|_| limited.allow(now)));
+
+        automations.remove(1);
+        assert_eq!(automations.config.hooks.len(), 2);
+        let kept: Vec<u64> = (0..2)
+            .map(|index| automations.last_runs[&index].0.as_u64())
+            .collect();
+        assert_eq!(kept, vec![0, 2]);
+        assert!(automations
+            .rate_limits
+            .values()
+            .all(|limit| limit.runs.is_empty()));
+    }
+
+    // Uses `cat` and `false`, which Windows doesn't have.
+    #[cfg(unix)]
+    #[test]
+    fn commands_get_the_mail() -> Result<()> {
+        let payload = Payload::example();
+        let run_hook = |action: Action| run(&action, &payload, COMMAND_TIMEOUT);
+        run_hook(Action::Command("cat".to_string()))?;
+        assert!(run_hook(Action::Command("false".to_string())).is_err());
+        assert!(run_hook(Action::Command("no-such-hook-for-hoot".to_string())).is_err());
+        assert!(run_hook(Action::Webhook("https://example.com".to_string())).is_err());
+        // A hung command is stopped instead of holding up the hooks after it.
+        let hung = Action::Command("sleep 60".to_string());
+        assert!(run(&hung, &payload, Duration::from_millis(200)).is_err());
+        Ok(())
+    }
+}
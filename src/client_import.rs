@@ -0,0 +1,136 @@
+//! Picks up an identity and relay list exported by other nostr clients, so moving to Hoot
+//! doesn't start with copying keys around by hand.
+//!
+//! Understood formats:
+//! - nos2x (and nos2x-fox) settings exports: JSON with a hex `private_key` and a `relays`
+//!   object keyed by url.
+//! - Amethyst key backups and other plain text files holding an `nsec`.
+
+use anyhow::{bail, Context, Result};
+use nostr::{Keys, SecretKey};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// What we found in an export, before anything is saved.
+#[derive(Debug, Clone)]
+pub struct ClientImport {
+    /// Which client the file looks like it came from.
+    pub source: &'static str,
+    pub keys: Keys,
+    pub relays: Vec<String>,
+}
+
+pub fn read_export(path: &Path) -> Result<ClientImport> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    parse_export(&contents)
+}
+
+pub fn parse_export(contents: &str) -> Result<ClientImport> {
+    if let Ok(json) = serde_json::from_str::<Value>(contents) {
+        if let Some(private_key) = json.get("private_key").and_then(Value::as_str) {
+            let secret_key = SecretKey::parse(private_key)
+                .context("The nos2x export holds an invalid private key")?;
+            return Ok(ClientImport {
+                source: "nos2x",
+                keys: Keys::new(secret_key),
+                relays: relays_from_json(json.get("relays")),
+            });
+        }
+    }
+
+    if contents.contains("ncryptsec1") {
+        bail!("Password protected key backups can't be imported yet, export the nsec instead");
+    }
+    let nsec = contents
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|word| word.starts_with("nsec1"))
+        .context("No private key found in this file")?;
+    let secret_key = SecretKey::parse(nsec).context("The nsec in this file is invalid")?;
+    Ok(ClientImport {
+        source: "Amethyst",
+        keys: Keys::new(secret_key),
+        relays: Vec::new(),
+    })
+}
+
+/// nos2x keeps `{"wss://…": {"read": true, "write": true}}`, other clients a plain list.
+fn relays_from_json(relays: Option<&Value>) -> Vec<String> {
+    let urls: Vec<String> = match relays {
+        Some(Value::Object(relays)) => relays
+            .iter()
+            .filter(|(_, policy)| {
+                let allows = |key| policy.get(key).and_then(Value::as_bool).unwrap_or(true);
+                allows("read") || allows("write")
+            })
+            .map(|(url, _)| url.clone())
+            .collect(),
+        Some(Value::Array(relays)) => relays
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        _ => Vec::new(),
+    };
+    urls.into_iter()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| url.starts_with("wss://") || url.starts_with("ws://"))
+        .collect()
+}
+
+/// Export files sitting where browsers and phones usually put them.
+pub fn find_exports() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+    else {
+        return Vec::new();
+    };
+
+    let mut found = Vec::new();
+    for dir in [home.join("Downloads"), home.join("Documents"), home] {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            if (name.contains("nos2x") || name.contains("amethyst"))
+                && entry.file_type().is_ok_and(|t| t.is_file())
+            {
+                found.push(entry.path());
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::ToBech32;
+
+    #[test]
+    fn reads_nos2x_and_amethyst_exports() -> Result<()> {
+        let keys = Keys::generate();
+        let nos2x = serde_json::json!({
+            "private_key": keys.secret_key().to_secret_hex(),
+            "relays": {
+                "wss://relay.example.com/": {"read": true, "write": true},
+                "wss://off.example.com": {"read": false, "write": false},
+            },
+            "protocol_handler": "",
+        });
+        let import = parse_export(&nos2x.to_string())?;
+        assert_eq!(import.source, "nos2x");
+        assert_eq!(import.keys.public_key(), keys.public_key());
+        assert_eq!(import.relays, vec!["wss://relay.example.com".to_string()]);
+
+        let backup = format!("Amethyst key backup\n{}\n", keys.secret_key().to_bech32()?);
+        let import = parse_export(&backup)?;
+        assert_eq!(import.source, "Amethyst");
+        assert_eq!(import.keys.public_key(), keys.public_key());
+
+        assert!(parse_export("nothing to see here").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+use crate::db::{Db, IntegrityReport, MessageFilter, Page, WrapCopy};
+use crate::focus;
+use crate::mail_event::MailMessage;
+use crate::threaded_event::ThreadStore;
+use crate::threading;
+use crate::TableEntry;
+use eframe::egui;
+use hoot_core::retention::{self, RetentionPlan, RetentionPolicy};
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use tracing::{debug, error};
+
+/// Queries too slow to run while drawing a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbRequest {
+    Inbox {
+        mailbox: Option<String>,
+        filter: MessageFilter,
+        split_threads: bool,
+        page: Page,
+    },
+    /// More of the inbox as it's scrolled, kept apart from [`DbRequest::Inbox`] so a reload
+    /// doesn't replace it.
+    InboxPage {
+        mailbox: Option<String>,
+        filter: MessageFilter,
+        page: Page,
+    },
+    Thread {
+        root_id: String,
+        include_trash: bool,
+    },
+    /// How many threads each saved search finds, keyed by its id.
+    SavedSearchCounts {
+        mailbox: Option<String>,
+        searches: Vec<(i64, MessageFilter)>,
+    },
+    /// Reads every stored message, see `Db::integrity_check`.
+    IntegrityCheck,
+    /// Who the Focused inbox ranking puts in Focused, see `focus::ranked_senders`.
+    RankSenders,
+    /// What the retention rules would do at `now`, to show or to carry out.
+    PlanRetention {
+        policy: RetentionPolicy,
+        now: i64,
+        preview: bool,
+    },
+}
+
+impl DbRequest {
+    /// Whether running `self` makes running `older` pointless: it asks the same question
+    /// again, like a newer inbox query, or the same thread or page again.
+    fn supersedes(&self, older: &DbRequest) -> bool {
+        match (self, older) {
+            (DbRequest::Thread { root_id, .. }, DbRequest::Thread { root_id: older, .. }) => {
+                root_id == older
+            }
+            (DbRequest::InboxPage { page, .. }, DbRequest::InboxPage { page: older, .. }) => {
+                page == older
+            }
+            // A preview mustn't stand in for a run.
+            (
+                DbRequest::PlanRetention { preview, .. },
+                DbRequest::PlanRetention { preview: older, .. },
+            ) => preview == older,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(older),
+        }
+    }
+}
+
+pub enum DbResponse {
+    Inbox(Page, Vec<TableEntry>),
+    InboxPage(Page, Vec<TableEntry>),
+    Thread(ThreadSnapshot),
+    SavedSearchCounts(HashMap<i64, usize>),
+    IntegrityCheck(IntegrityReport),
+    /// The senders to save as Focused by their ranking.
+    RankedSenders(Vec<String>),
+    RetentionPlanned {
+        policy: RetentionPolicy,
+        plan: RetentionPlan,
+        now: i64,
+        preview: bool,
+    },
+    /// The request failed, the error has been logged already.
+    Failed(DbRequest),
+}
+
+/// A thread as it was when the worker last loaded it, with what the thread view shows about
+/// its messages so none of it is queried while drawing.
+#[derive(Debug, Clone)]
+pub struct ThreadSnapshot {
+    pub root_id: String,
+    pub include_trash: bool,
+    pub messages: Vec<MailMessage>,
+    /// How `messages` reply to each other, each in the slot of its index.
+    store: ThreadStore,
+    /// The gift wraps each message travelled in, by message id.
+    wraps: HashMap<String, Vec<WrapCopy>>,
+    /// The relays that delivered each message, with when each did first, by message id.
+    sources: HashMap<String, Vec<(String, i64)>>,
+    /// The labels on each message that has any, by message id.
+    labels: HashMap<String, Vec<String>>,
+    /// Every label in use, for the label menu.
+    pub all_labels: Vec<String>,
+}
+
+impl ThreadSnapshot {
+    pub fn load(db: &Db, root_id: &str, include_trash: bool) -> anyhow::Result<Self> {
+        let messages = if include_trash {
+            db.get_email_thread_including_trash(root_id)?
+        } else {
+            db.get_email_thread(root_id)?
+        };
+        let event_ids: Vec<String> = messages
+            .iter()
+            .filter_map(|message| message.id)
+            .map(|id| id.to_hex())
+            .collect();
+        let mut wraps = HashMap::new();
+        let mut sources = HashMap::new();
+        for event_id in &event_ids {
+            wraps.insert(event_id.clone(), db.get_message_wraps(event_id)?);
+            sources.insert(event_id.clone(), db.get_message_sources(event_id)?);
+        }
+        Ok(Self {
+            root_id: root_id.to_string(),
+            include_trash,
+            store: ThreadStore::from_messages(&messages),
+            messages,
+            wraps,
+            sources,
+            labels: db.get_message_labels(&event_ids)?,
+            all_labels: db.get_all_labels()?,
+        })
+    }
+
+    /// How the messages reply to each other, each in the slot of its index.
+    pub fn store(&self) -> &ThreadStore {
+        &self.store
+    }
+
+    /// The gift wraps `event_id` travelled in.
+    pub fn wraps(&self, event_id: &str) -> &[WrapCopy] {
+        self.wraps.get(event_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The relays that delivered `event_id`, with when each did first.
+    pub fn sources(&self, event_id: &str) -> &[(String, i64)] {
+        self.sources.get(event_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The labels on any of `event_ids`, sorted.
+    pub fn labels(&self, event_ids: &[String]) -> Vec<String> {
+        let mut labels: Vec<String> = event_ids
+            .iter()
+            .filter_map(|event_id| self.labels.get(event_id))
+            .flatten()
+            .cloned()
+            .collect();
+        labels.sort();
+        labels.dedup();
+        labels
+    }
+
+    /// Shows `label` put on `event_ids` or taken off, the way the database now has it.
+    pub fn set_label(&mut self, event_ids: &[String], label: &str, on: bool) {
+        for event_id in event_ids {
+            let labels = self.labels.entry(event_id.clone()).or_default();
+            labels.retain(|other| other != label);
+            if on {
+                labels.push(label.to_string());
+                labels.sort();
+            }
+        }
+        if on && !self.all_labels.iter().any(|other| other == label) {
+            self.all_labels.push(label.to_string());
+            self.all_labels.sort();
+        }
+    }
+}
+
+/// Runs long queries on its own read-only connection so they never hold up a frame. Answers
+/// come back through `process_queue`, until then the UI keeps showing its last snapshot.
+pub struct DbWorker {
+    requests: Sender<DbRequest>,
+    responses: Receiver<DbResponse>,
+}
+
+impl DbWorker {
+    pub fn spawn(reader: Db, ctx: egui::Context) -> Self {
+        let (requests, request_receiver) = std::sync::mpsc::channel();
+        let (response_sender, responses) = std::sync::mpsc::channel();
+        thread::spawn(move || run(reader, request_receiver, response_sender, ctx));
+        Self {
+            requests,
+            responses,
+        }
+    }
+
+    pub fn request(&self, request: DbRequest) {
+        if let Err(e) = self.requests.send(request) {
+            error!("Database worker is gone, dropping {:?}", e.0);
+        }
+    }
+
+    /// Answers that arrived since the last call. The worker wakes the UI when it sends one.
+    pub fn process_queue(&self) -> Vec<DbResponse> {
+        self.responses.try_iter().collect()
+    }
+}
+
+fn run(db: Db, requests: Receiver<DbRequest>, responses: Sender<DbResponse>, ctx: egui::Context) {
+    while let Ok(first) = requests.recv() {
+        // Only the newest of requests asking the same thing matters, e.g. while a search is
+        // being typed. Different threads and pages each still get their answer.
+        let mut pending = vec![first];
+        pending.extend(requests.try_iter());
+        let received = pending.len();
+        let mut latest: Vec<DbRequest> = Vec::new();
+        for request in pending.into_iter().rev() {
+            if !latest.iter().any(|newer| newer.supersedes(&request)) {
+                latest.push(request);
+            }
+        }
+        debug!(
+            "Database worker running {} of {} requests",
+            latest.len(),
+            received
+        );
+
+        for request in latest {
+            if responses.send(handle(&db, request)).is_err() {
+                return;
+            }
+            ctx.request_repaint();
+        }
+    }
+}
+
+fn handle(db: &Db, request: DbRequest) -> DbResponse {
+    match &request {
+        DbRequest::Inbox {
+            mailbox,
+            filter,
+            split_threads,
+            page,
+        } => match db.get_top_level_page(mailbox.as_deref(), filter, page) {
+            Ok(entries) if *split_threads => {
+                DbResponse::Inbox(page.clone(), threading::split_entries(db, entries))
+            }
+            Ok(entries) => DbResponse::Inbox(page.clone(), entries),
+            Err(e) => {
+                error!("Could not fetch table entries to display from DB: {}", e);
+                DbResponse::Failed(request)
+            }
+        },
+        DbRequest::InboxPage {
+            mailbox,
+            filter,
+            page,
+        } => match db.get_top_level_page(mailbox.as_deref(), filter, page) {
+            Ok(entries) => DbResponse::InboxPage(page.clone(), entries),
+            Err(e) => {
+                error!("Could not fetch more of the inbox from DB: {}", e);
+                DbResponse::Failed(request)
+            }
+        },
+        DbRequest::Thread {
+            root_id,
+            include_trash,
+        } => match ThreadSnapshot::load(db, root_id, *include_trash) {
+            Ok(snapshot) => DbResponse::Thread(snapshot),
+            Err(e) => {
+                error!("Failed to load thread for {}: {}", root_id, e);
+                DbResponse::Failed(request)
+            }
+        },
+        DbRequest::SavedSearchCounts { mailbox, searches } => {
+            DbResponse::SavedSearchCounts(count_saved_searches(db, mailbox.as_deref(), searches))
+        }
+        DbRequest::IntegrityCheck => match db.integrity_check() {
+            Ok(report) => DbResponse::IntegrityCheck(report),
+            Err(e) => {
+                error!("Failed to check the database: {}", e);
+                DbResponse::Failed(request)
+            }
+        },
+        DbRequest::RankSenders => match focus::ranked_senders(db) {
+            Ok(senders) => DbResponse::RankedSenders(senders),
+            Err(e) => {
+                error!("Failed to rank senders for the Focused inbox: {}", e);
+                DbResponse::Failed(request)
+            }
+        },
+        DbRequest::PlanRetention {
+            policy,
+            now,
+            preview,
+        } => match retention::plan(db, policy, *now) {
+            Ok(plan) => DbResponse::RetentionPlanned {
+                policy: policy.clone(),
+                plan,
+                now: *now,
+                preview: *preview,
+            },
+            Err(e) => {
+                error!("Failed to work out what the retention rules do: {}", e);
+                DbResponse::Failed(request)
+            }
+        },
+    }
+}
+
+/// Counts what each saved search finds, leaving out the ones that fail.
+pub fn count_saved_searches(
+    db: &Db,
+    mailbox: Option<&str>,
+    searches: &[(i64, MessageFilter)],
+) -> HashMap<i64, usize> {
+    searches
+        .iter()
+        .filter_map(
+            |(id, filter)| match db.count_top_level_messages(mailbox, filter) {
+                Ok(count) => Some((*id, count)),
+                Err(e) => {
+                    error!("Could not count saved search {}: {}", id, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn answers_off_the_calling_thread() -> anyhow::Result<()> {
+        let worker = DbWorker::spawn(Db::new_in_memory()?, egui::Context::default());
+        worker.request(DbRequest::Thread {
+            root_id: "00".repeat(32),
+            include_trash: false,
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let responses = loop {
+            let responses = worker.process_queue();
+            if !responses.is_empty() || Instant::now() > deadline {
+                break responses;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        match responses.as_slice() {
+            [DbResponse::Thread(snapshot)] => {
+                assert_eq!(snapshot.root_id, "00".repeat(32));
+                assert!(snapshot.messages.is_empty());
+            }
+            _ => panic!("expected one thread snapshot"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn only_the_same_question_is_merged() {
+        let thread = |root_id: &str| DbRequest::Thread {
+            root_id: root_id.to_string(),
+            include_trash: false,
+        };
+        let page = |limit| DbRequest::InboxPage {
+            mailbox: None,
+            filter: MessageFilter::default(),
+            page: Page::First(limit),
+        };
+        let inbox = |query: &str| DbRequest::Inbox {
+            mailbox: None,
+            filter: MessageFilter {
+                query: query.to_string(),
+                ..Default::default()
+            },
+            split_threads: false,
+            page: Page::First(50),
+        };
+        assert!(thread("a").supersedes(&thread("a")));
+        assert!(!thread("a").supersedes(&thread("b")));
+        assert!(page(50).supersedes(&page(50)));
+        assert!(!page(50).supersedes(&page(100)));
+        assert!(inbox("tacos").supersedes(&inbox("taco")));
+        assert!(!inbox("tacos").supersedes(&thread("a")));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::mail_event::Attachment;
+use eframe::egui;
+use nostr::hashes::{sha256, Hash, HashEngine};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+pub const DOWNLOAD_DIR_KEY: &str = "download_dir";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadStatus {
+    InProgress { received: u64, total: Option<u64> },
+    Complete(PathBuf),
+    Failed(String),
+}
+
+enum DownloadUpdate {
+    Progress { received: u64, total: Option<u64> },
+    Finished(Result<PathBuf, String>),
+}
+
+struct DownloadMessage {
+    /// Downloads are keyed by the attachment's SHA-256, so the same file referenced by
+    /// several messages is only fetched once.
+    sha256: String,
+    update: DownloadUpdate,
+}
+
+pub struct DownloadManager {
+    pub download_dir: PathBuf,
+    downloads: HashMap<String, DownloadStatus>,
+    sender: Sender<DownloadMessage>,
+    receiver: Receiver<DownloadMessage>,
+}
+
+impl DownloadManager {
+    pub fn new(download_dir: PathBuf) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            download_dir,
+            downloads: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn status(&self, attachment: &Attachment) -> Option<&DownloadStatus> {
+        self.downloads.get(&attachment.sha256)
+    }
+
+    /// Starts downloading `attachment` into the download directory. Failed downloads are
+    /// resumed from where they stopped.
+    pub fn request(&mut self, attachment: &Attachment) {
+        match self.downloads.get(&attachment.sha256) {
+            Some(DownloadStatus::InProgress { .. }) => return,
+            Some(DownloadStatus::Complete(path)) if path.exists() => return,
+            _ => {}
+        }
+
+        self.downloads.insert(
+            attachment.sha256.clone(),
+            DownloadStatus::InProgress {
+                received: 0,
+                total: attachment.size,
+            },
+        );
+
+        let sender = self.sender.clone();
+        let attachment = attachment.clone();
+        let dir = self.download_dir.clone();
+        thread::spawn(move || {
+            let result = download(&attachment, &dir, &sender);
+            if let Err(e) = &result {
+                error!("Failed to download {}: {}", attachment.url, e);
+            }
+            if sender
+                .send(DownloadMessage {
+                    sha256: attachment.sha256.clone(),
+                    update: DownloadUpdate::Finished(result),
+                })
+                .is_err()
+            {
+                debug!("Download receiver dropped before download finished");
+            }
+        });
+    }
+
+    pub fn process_queue(&mut self, ctx: &egui::Context) {
+        while let Ok(message) = self.receiver.try_recv() {
+            let status = match message.update {
+                DownloadUpdate::Progress { received, total } => {
+                    DownloadStatus::InProgress { received, total }
+                }
+                DownloadUpdate::Finished(Ok(path)) => DownloadStatus::Complete(path),
+                DownloadUpdate::Finished(Err(e)) => DownloadStatus::Failed(e),
+            };
+            self.downloads.insert(message.sha256, status);
+        }
+
+        // The worker threads can't wake us up, so keep polling while anything is in flight.
+        if self
+            .downloads
+            .values()
+            .any(|status| matches!(status, DownloadStatus::InProgress { .. }))
+        {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
+
+fn download(
+    attachment: &Attachment,
+    dir: &Path,
+    sender: &Sender<DownloadMessage>,
+) -> Result<PathBuf, String> {
+    if !(attachment.url.starts_with("https://") || attachment.url.starts_with("http://")) {
+        return Err(format!("unsupported url {}", attachment.url));
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {:?}: {}", dir, e))?;
+    let part_path = dir.join(format!(".{}.part", attachment.sha256));
+    let mut received = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("could not build HTTP client: {}", e))?;
+
+    let mut request = client.get(&attachment.url);
+    if received > 0 {
+        debug!(
+            "Resuming download of {} at byte {}",
+            attachment.url, received
+        );
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", received));
+    }
+    let mut response = request.send().map_err(|e| e.to_string())?;
+
+    let status = response.status();
+    let mut part_file = if status == reqwest::StatusCode::PARTIAL_CONTENT {
+        OpenOptions::new().append(true).open(&part_path)
+    } else if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && received > 0 {
+        // We already have every byte, the hash check below decides if they're right.
+        OpenOptions::new().append(true).open(&part_path)
+    } else if status.is_success() {
+        // The server ignored our range request, start over.
+        received = 0;
+        File::create(&part_path)
+    } else {
+        return Err(format!("server returned {}", status));
+    }
+    .map_err(|e| format!("could not open {:?}: {}", part_path, e))?;
+
+    if status != reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        let total = response
+            .content_length()
+            .map(|len| len + received)
+            .or(attachment.size);
+
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = response.read(&mut buf).map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            part_file
+                .write_all(&buf[..read])
+                .map_err(|e| format!("could not write {:?}: {}", part_path, e))?;
+            received += read as u64;
+
+            let _ = sender.send(DownloadMessage {
+                sha256: attachment.sha256.clone(),
+                update: DownloadUpdate::Progress { received, total },
+            });
+        }
+        part_file.flush().map_err(|e| e.to_string())?;
+    }
+    drop(part_file);
+
+    let actual = hash_file(&part_path).map_err(|e| format!("could not hash download: {}", e))?;
+    if actual != attachment.sha256 {
+        let _ = fs::remove_file(&part_path);
+        return Err(format!(
+            "hash mismatch, expected {} but got {}",
+            attachment.sha256, actual
+        ));
+    }
+
+    let final_path = destination(dir, attachment);
+    if final_path.exists() {
+        // Same name and hash means we already saved this exact file.
+        let _ = fs::remove_file(&part_path);
+    } else {
+        fs::rename(&part_path, &final_path)
+            .map_err(|e| format!("could not move download into place: {}", e))?;
+    }
+
+    info!("Saved attachment {} to {:?}", attachment.url, final_path);
+    Ok(final_path)
+}
+
+/// Opens a file or folder with the platform's default application.
+pub fn open_path(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let opener = "xdg-open";
+
+    std::process::Command::new(opener).arg(path).spawn()?;
+    Ok(())
+}
+
+/// Picks where to save the attachment, adding part of the hash to the name if a different
+/// file already uses it.
+fn destination(dir: &Path, attachment: &Attachment) -> PathBuf {
+    let file_name = attachment.file_name();
+    let path = dir.join(&file_name);
+    if !path.exists() || hash_file(&path).is_ok_and(|hash| hash == attachment.sha256) {
+        return path;
+    }
+
+    let short_hash = &attachment.sha256[..8];
+    let unique_name = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}-{}.{}", stem, short_hash, ext),
+        _ => format!("{}-{}", file_name, short_hash),
+    };
+    dir.join(unique_name)
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut engine = sha256::Hash::engine();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        engine.input(&buf[..read]);
+    }
+    Ok(sha256::Hash::from_engine(engine).to_string())
+}
@@ -0,0 +1,215 @@
+//! Writing a message in the user's own editor, for long ones. The body goes to a temporary
+//! file, the editor is started on it, and whatever it saves comes back into the compose window
+//! until it quits.
+//!
+//! The editor is started without a terminal, so it has to open its own window: `code --wait`,
+//! `gedit`, or a terminal editor wrapped in one, like `xterm -e vim`. Editors that return
+//! right away and hand the file to a running instance need their "wait" flag, or the message
+//! comes back before it's written.
+//!
+//! The file holds the plaintext of a message that's sent encrypted, so it lives in the app's
+//! storage dir, only the user can read it, and it's removed once the window is done with it.
+
+use anyhow::{bail, Context, Result};
+use eframe::egui;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::SystemTime;
+use tracing::{error, info};
+
+/// An editor open on a compose window's body.
+struct ExternalEdit {
+    path: PathBuf,
+    child: Child,
+    /// When the file was last saved and how long it was, to notice the next save.
+    saved: Option<(SystemTime, u64)>,
+}
+
+pub struct ExternalEditors {
+    /// Where the files being edited are written.
+    dir: PathBuf,
+    /// By the compose window the editor writes for.
+    edits: HashMap<egui::Id, ExternalEdit>,
+}
+
+impl Default for ExternalEditors {
+    fn default() -> Self {
+        let dir = eframe::storage_dir(crate::STORAGE_NAME)
+            .map(|dir| dir.join("editing"))
+            .unwrap_or_else(std::env::temp_dir);
+        Self {
+            dir,
+            edits: HashMap::new(),
+        }
+    }
+}
+
+/// The editor to use: the one set in the preferences, else `$VISUAL` or `$EDITOR`.
+pub fn command(configured: &str) -> Option<String> {
+    let configured = configured.trim();
+    if !configured.is_empty() {
+        return Some(configured.to_string());
+    }
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|command| !command.trim().is_empty())
+}
+
+impl ExternalEditors {
+    /// Starts the editor on `content` for the compose window `id`. `configured` can carry
+    /// arguments, like `code --wait`, split the way a shell would so quoted paths with spaces
+    /// work, and the file's path goes after them.
+    pub fn open(&mut self, id: egui::Id, content: &str, configured: &str) -> Result<()> {
+        let Some(command) = command(configured) else {
+            bail!("No editor set. Pick one in Settings or set $EDITOR.");
+        };
+        let words = shell_words::split(&command)
+            .with_context(|| format!("Couldn't read the editor command {}", command))?;
+        let (program, args) = words.split_first().context("No editor set")?;
+        let path = self
+            .dir
+            .join(format!("hoot-{:08x}.txt", rand::random::<u32>()));
+        write_private(&self.dir, &path, content)
+            .context("Couldn't write the message for the editor")?;
+        let child = match Command::new(program).args(args).arg(&path).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e).with_context(|| format!("Couldn't start {}", program));
+            }
+        };
+        info!("Editing a message in {}", command);
+        let saved = saved_at(&path);
+        self.edits.insert(id, ExternalEdit { path, child, saved });
+        Ok(())
+    }
+
+    pub fn is_editing(&self, id: egui::Id) -> bool {
+        self.edits.contains_key(&id)
+    }
+
+    /// What the editor saved since it was last asked, if anything. Once it has quit, the
+    /// temporary file is removed and the window isn't being edited anymore.
+    pub fn poll(&mut self, id: egui::Id) -> Option<String> {
+        let edit = self.edits.get_mut(&id)?;
+        let finished = match edit.child.try_wait() {
+            Ok(status) => status.is_some(),
+            Err(e) => {
+                error!("Lost track of the editor: {}", e);
+                true
+            }
+        };
+        let saved = saved_at(&edit.path);
+        let content = if saved != edit.saved {
+            edit.saved = saved;
+            match fs::read_to_string(&edit.path) {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    error!("Couldn't read what the editor saved: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        if finished {
+            if let Some(edit) = self.edits.remove(&id) {
+                if let Err(e) = fs::remove_file(&edit.path) {
+                    error!("Couldn't remove {}: {}", edit.path.display(), e);
+                }
+            }
+        }
+        content
+    }
+
+    /// Stops following the editor for a window that closed: the editor is stopped and its
+    /// file removed.
+    pub fn forget(&mut self, id: egui::Id) {
+        let Some(mut edit) = self.edits.remove(&id) else {
+            return;
+        };
+        if let Ok(None) = edit.child.try_wait() {
+            if let Err(e) = edit.child.kill() {
+                error!("Couldn't stop the editor: {}", e);
+            }
+        }
+        if let Err(e) = edit.child.wait() {
+            error!("Lost track of the editor: {}", e);
+        }
+        if let Err(e) = fs::remove_file(&edit.path) {
+            error!("Couldn't remove {}: {}", edit.path.display(), e);
+        }
+    }
+}
+
+/// Writes `content` to a new file at `path` in `dir` that only the user can read.
+fn write_private(dir: &Path, path: &Path, content: &str) -> std::io::Result<()> {
+    let mut dir_builder = fs::DirBuilder::new();
+    dir_builder.recursive(true);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        dir_builder.mode(0o700);
+        options.mode(0o600);
+    }
+    dir_builder.create(dir)?;
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+fn saved_at(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Uses `true` and `tail`, which only unix has.
+    #[cfg(unix)]
+    #[test]
+    fn saves_come_back_until_the_editor_quits() -> Result<()> {
+        let mut editors = ExternalEditors {
+            dir: std::env::temp_dir().join(format!("hoot-editing-{}", std::process::id())),
+            edits: HashMap::new(),
+        };
+        let id = egui::Id::new("compose");
+        editors.open(id, "Hi", "true")?;
+        let path = editors.edits[&id].path.clone();
+        assert_eq!(fs::read_to_string(&path)?, "Hi");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::write(&path, "Hi there,\n\nA much longer message.")?;
+        let mut saved = None;
+        while editors.is_editing(id) {
+            saved = editors.poll(id).or(saved);
+        }
+        assert_eq!(
+            saved.as_deref(),
+            Some("Hi there,\n\nA much longer message.")
+        );
+        assert!(!path.exists());
+
+        assert!(editors.open(id, "", "no-such-editor-for-hoot").is_err());
+        assert!(!editors.is_editing(id));
+
+        // A window closed while its editor is still open takes the file with it.
+        editors.open(id, "Secret", "tail -f")?;
+        let path = editors.edits[&id].path.clone();
+        editors.forget(id);
+        assert!(!editors.is_editing(id));
+        assert!(!path.exists());
+        fs::remove_dir(&editors.dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! Saves thread flags to relays shortly after they change, see `hoot_core::flag_sync`.
+
+use crate::db::Db;
+use crate::relay::{ClientMessage, RelayPool};
+use eframe::egui;
+use hoot_core::flag_sync;
+use nostr::Keys;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+
+/// How long to wait after the last change, so starring a few messages in a row or reading
+/// through a thread sends one event per thread.
+const DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct FlagPublisher {
+    /// Messages whose flags changed since the last save.
+    changed: HashSet<String>,
+    due: Option<Instant>,
+}
+
+impl FlagPublisher {
+    pub fn changed<I: IntoIterator<Item = String>>(&mut self, event_ids: I) {
+        self.changed.extend(event_ids);
+        self.due = Some(Instant::now() + DELAY);
+    }
+
+    pub fn process(
+        &mut self,
+        db: &Db,
+        accounts: &[Keys],
+        relays: &mut RelayPool,
+        ctx: &egui::Context,
+    ) {
+        let Some(due) = self.due else {
+            return;
+        };
+        let now = Instant::now();
+        if now < due {
+            ctx.request_repaint_after(due - now);
+            return;
+        }
+        self.due = None;
+
+        let mut done: HashSet<String> = HashSet::new();
+        for event_id in std::mem::take(&mut self.changed) {
+            if done.contains(&event_id) {
+                continue;
+            }
+            let thread = match db.get_email_thread(&event_id) {
+                Ok(thread) => thread,
+                Err(e) => {
+                    error!(
+                        "Failed to load thread {} to save its flags: {}",
+                        event_id, e
+                    );
+                    continue;
+                }
+            };
+            let ids: Vec<String> = thread
+                .iter()
+                .filter_map(|msg| msg.id.map(|id| id.to_hex()))
+                .collect();
+            done.extend(ids.iter().cloned());
+            let Some(root) = ids.first() else {
+                continue;
+            };
+            // The thread belongs to whichever of our accounts wrote or received it.
+            let Some(keys) = accounts.iter().find(|keys| {
+                let pubkey = keys.public_key();
+                thread
+                    .iter()
+                    .any(|msg| msg.author == Some(pubkey) || msg.to.contains(&pubkey))
+            }) else {
+                continue;
+            };
+            if let Err(e) = publish(db, keys, root, &ids, relays) {
+                error!("Failed to save the flags of thread {}: {}", root, e);
+            }
+        }
+    }
+}
+
+fn publish(
+    db: &Db,
+    keys: &Keys,
+    root: &str,
+    ids: &[String],
+    relays: &mut RelayPool,
+) -> anyhow::Result<()> {
+    let flags = db.get_thread_flags(ids)?;
+    let event = flag_sync::to_event(keys, root, &flags)?;
+    db.record_flag_sync(
+        &flag_sync::thread_key(keys, root),
+        event.created_at.as_u64() as i64,
+    )?;
+    let payload = serde_json::to_string(&ClientMessage::Event { event })?;
+    relays
+        .send(ewebsock::WsMessage::Text(payload))
+        .map_err(|e| anyhow::anyhow!("Could not send to relays: {}", e))?;
+    debug!("Saved the flags of thread {}", root);
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Which columns the message list shows and in what order, kept in the database.
+
+use crate::db::Db;
+use egui_extras::Column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboxColumn {
+    Select,
+    Star,
+    Avatar,
+    From,
+    Subject,
+    Snippet,
+    Labels,
+    Time,
+}
+
+impl InboxColumn {
+    /// Every column, in the default order.
+    pub const ALL: [InboxColumn; 8] = [
+        InboxColumn::Select,
+        InboxColumn::Star,
+        InboxColumn::Avatar,
+        InboxColumn::From,
+        InboxColumn::Subject,
+        InboxColumn::Snippet,
+        InboxColumn::Labels,
+        InboxColumn::Time,
+    ];
+
+    /// The name the column is stored under.
+    fn key(self) -> &'static str {
+        match self {
+            InboxColumn::Select => "select",
+            InboxColumn::Star => "star",
+            InboxColumn::Avatar => "avatar",
+            InboxColumn::From => "from",
+            InboxColumn::Subject => "subject",
+            InboxColumn::Snippet => "snippet",
+            InboxColumn::Labels => "labels",
+            InboxColumn::Time => "time",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|column| column.key() == key)
+    }
+
+    /// The name shown in Settings.
+    pub fn title(self) -> &'static str {
+        match self {
+            InboxColumn::Select => "Checkbox",
+            InboxColumn::Star => "Star",
+            InboxColumn::Avatar => "Avatar",
+            InboxColumn::From => "From",
+            InboxColumn::Subject => "Subject",
+            InboxColumn::Snippet => "Preview",
+            InboxColumn::Labels => "Labels",
+            InboxColumn::Time => "Date",
+        }
+    }
+
+    fn default_visible(self) -> bool {
+        !matches!(
+            self,
+            InboxColumn::Avatar | InboxColumn::Snippet | InboxColumn::Labels
+        )
+    }
+
+    fn width(self) -> Column {
+        match self {
+            InboxColumn::Select | InboxColumn::Star | InboxColumn::Avatar => Column::auto(),
+            InboxColumn::From => Column::initial(160.0).at_least(100.0),
+            InboxColumn::Subject => Column::initial(240.0).at_least(100.0),
+            InboxColumn::Snippet => Column::initial(240.0).at_least(80.0),
+            InboxColumn::Labels => Column::initial(120.0).at_least(60.0),
+            InboxColumn::Time => Column::initial(100.0).at_least(70.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnLayout {
+    /// Every column in order, with whether it's shown.
+    pub columns: Vec<(InboxColumn, bool)>,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        Self {
+            columns: InboxColumn::ALL
+                .into_iter()
+                .map(|column| (column, column.default_visible()))
+                .collect(),
+        }
+    }
+}
+
+impl ColumnLayout {
+    /// The saved layout. Columns it doesn't mention yet go where they are by default.
+    pub fn load(db: &Db) -> anyhow::Result<Self> {
+        let mut columns: Vec<(InboxColumn, bool)> = db
+            .get_inbox_columns()?
+            .into_iter()
+            .filter_map(|(key, visible)| Some((InboxColumn::from_key(&key)?, visible)))
+            .collect();
+        if columns.is_empty() {
+            return Ok(Self::default());
+        }
+        for (i, column) in InboxColumn::ALL.into_iter().enumerate() {
+            if !columns.iter().any(|(saved, _)| *saved == column) {
+                columns.insert(i.min(columns.len()), (column, column.default_visible()));
+            }
+        }
+        Ok(Self { columns })
+    }
+
+    pub fn save(&self, db: &mut Db) -> anyhow::Result<()> {
+        let columns: Vec<(String, bool)> = self
+            .columns
+            .iter()
+            .map(|(column, visible)| (column.key().to_string(), *visible))
+            .collect();
+        db.save_inbox_columns(&columns)
+    }
+
+    pub fn visible(&self) -> Vec<InboxColumn> {
+        self.columns
+            .iter()
+            .filter(|(_, visible)| *visible)
+            .map(|(column, _)| *column)
+            .collect()
+    }
+
+    /// The shown columns with their widths for the table. The subject takes up what's left,
+    /// or the preview, or the last column when neither is shown.
+    pub fn table_columns(&self) -> Vec<(InboxColumn, Column)> {
+        let visible = self.visible();
+        let fill = [InboxColumn::Subject, InboxColumn::Snippet]
+            .into_iter()
+            .find(|column| visible.contains(column))
+            .or(visible.last().copied());
+        visible
+            .into_iter()
+            .map(|column| {
+                let width = if Some(column) == fill {
+                    Column::remainder()
+                } else {
+                    column.width()
+                };
+                (column, width)
+            })
+            .collect()
+    }
+
+    /// Swaps the column at `index` with the one after it.
+    pub fn move_down(&mut self, index: usize) {
+        if index + 1 < self.columns.len() {
+            self.columns.swap(index, index + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_layouts_keep_order_and_gain_new_columns() -> anyhow::Result<()> {
+        let mut db = Db::new_in_memory()?;
+        assert_eq!(ColumnLayout::load(&db)?, ColumnLayout::default());
+
+        // A layout saved before the preview column existed.
+        db.save_inbox_columns(&[
+            ("subject".to_string(), true),
+            ("from".to_string(), true),
+            ("time".to_string(), false),
+        ])?;
+        let layout = ColumnLayout::load(&db)?;
+        assert_eq!(
+            layout.visible(),
+            vec![
+                InboxColumn::Select,
+                InboxColumn::Star,
+                InboxColumn::Subject,
+                InboxColumn::From
+            ]
+        );
+        assert_eq!(layout.columns.len(), InboxColumn::ALL.len());
+        assert_eq!(layout.columns[5], (InboxColumn::Snippet, false));
+        assert_eq!(layout.columns[6], (InboxColumn::Labels, false));
+
+        let mut moved = layout.clone();
+        moved.move_down(2);
+        moved.save(&mut db)?;
+        assert_eq!(ColumnLayout::load(&db)?, moved);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! The inbox a page at a time: the newest threads first, older ones as the list is scrolled
+//! down to them, and pages far off screen let go again, so a mailbox of any size takes the
+//! same memory and every query stays quick.
+
+use crate::db::{Cursor, MessageFilter, Page};
+use crate::TableEntry;
+use std::ops::Range;
+
+/// Threads loaded at a time.
+pub const PAGE_SIZE: usize = 100;
+/// Most threads kept loaded, the pages around what's on screen.
+const MAX_ROWS: usize = 3 * PAGE_SIZE;
+
+#[derive(Debug, Default)]
+pub struct InboxWindow {
+    /// The loaded threads, newest first.
+    rows: Vec<TableEntry>,
+    /// How many threads come before the first loaded one.
+    offset: usize,
+    /// Whether there may be threads after the last loaded one.
+    more: bool,
+    /// The page asked for that hasn't come in yet.
+    loading: Option<Page>,
+    /// The mailbox and filter the threads are loaded for.
+    query: Option<(Option<String>, MessageFilter)>,
+}
+
+impl InboxWindow {
+    /// What to load to refresh the threads we have, keeping the list where it's scrolled to
+    /// unless it's now for another mailbox or filter. With `paged` off everything is loaded at
+    /// once.
+    pub fn reload(&mut self, mailbox: Option<&str>, filter: &MessageFilter, paged: bool) -> Page {
+        let query = (mailbox.map(str::to_string), filter.clone());
+        let same = self.query.as_ref() == Some(&query);
+        self.query = Some(query);
+        match self.rows.first() {
+            _ if !paged => Page::All,
+            _ if !same => Page::First(PAGE_SIZE),
+            Some(first) if self.offset > 0 => Page::From(Cursor::of(first), self.rows.len()),
+            _ => Page::First(self.rows.len().max(PAGE_SIZE)),
+        }
+    }
+
+    /// Takes the threads loaded for `page`, a [`InboxWindow::reload`].
+    pub fn reset(&mut self, page: &Page, rows: Vec<TableEntry>) {
+        if matches!(page, Page::All | Page::First(_)) {
+            self.offset = 0;
+        }
+        self.more = page.limit().is_some_and(|limit| rows.len() >= limit);
+        self.rows = rows;
+        self.loading = None;
+    }
+
+    /// Rows the list has, with the ones not loaded counted in.
+    pub fn total(&self) -> usize {
+        self.offset + self.rows.len() + usize::from(self.more)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// The thread at row `index`, `None` while it's not loaded.
+    pub fn get(&self, index: usize) -> Option<&TableEntry> {
+        self.rows.get(index.checked_sub(self.offset)?)
+    }
+
+    /// Takes the thread `id` off the list, e.g. as it's archived.
+    pub fn remove(&mut self, id: &str) -> Option<TableEntry> {
+        let index = self.rows.iter().position(|row| row.id == id)?;
+        Some(self.rows.remove(index))
+    }
+
+    /// The page to load for the rows in `visible`, if some of them aren't loaded and nothing
+    /// is loading already.
+    pub fn wants(&mut self, visible: Range<usize>) -> Option<Page> {
+        if self.loading.is_some() {
+            return None;
+        }
+        let page = if visible.start < self.offset {
+            Page::After(Cursor::of(self.rows.first()?), PAGE_SIZE)
+        } else if self.more && visible.end > self.offset + self.rows.len() {
+            Page::Before(Cursor::of(self.rows.last()?), PAGE_SIZE)
+        } else {
+            return None;
+        };
+        self.loading = Some(page.clone());
+        Some(page)
+    }
+
+    /// Takes the threads of a page [`InboxWindow::wants`] asked for, and lets go of the
+    /// farthest ones on the other side.
+    pub fn receive(&mut self, page: &Page, mut rows: Vec<TableEntry>) {
+        if self.loading.as_ref() != Some(page) {
+            // Asked for before the last reset, the list has moved on.
+            return;
+        }
+        self.loading = None;
+        match page {
+            Page::Before(..) => {
+                self.more = rows.len() >= PAGE_SIZE;
+                self.rows.append(&mut rows);
+                if self.rows.len() > MAX_ROWS {
+                    let dropped = self.rows.len() - MAX_ROWS;
+                    self.rows.drain(..dropped);
+                    self.offset += dropped;
+                }
+            }
+            Page::After(..) => {
+                // Short of a page means we've reached the newest, wherever we thought we were.
+                self.offset = if rows.len() < PAGE_SIZE {
+                    0
+                } else {
+                    self.offset.saturating_sub(rows.len())
+                };
+                rows.append(&mut self.rows);
+                self.rows = rows;
+                if self.rows.len() > MAX_ROWS {
+                    self.rows.truncate(MAX_ROWS);
+                    self.more = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(range: Range<usize>) -> Vec<TableEntry> {
+        range
+            .map(|i| TableEntry {
+                id: format!("{:04}", 10_000 - i),
+                snippet: String::new(),
+                subject: String::new(),
+                pubkey: String::new(),
+                created_at: (10_000 - i) as i64,
+                thread_count: 1,
+                labels: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loads_pages_as_the_list_scrolls() {
+        let mut inbox = InboxWindow::default();
+        let filter = MessageFilter::default();
+        let first = inbox.reload(None, &filter, true);
+        assert_eq!(first, Page::First(PAGE_SIZE));
+        inbox.reset(&first, rows(0..PAGE_SIZE));
+        assert_eq!(inbox.total(), PAGE_SIZE + 1);
+        assert_eq!(inbox.wants(0..20), None);
+
+        // Scrolling down loads older pages, and lets go of the newest ones past the limit.
+        for page in 1..4 {
+            let end = inbox.total();
+            let older = inbox.wants(end - 20..end).expect("the next page");
+            assert_eq!(inbox.wants(end - 20..end), None, "one page at a time");
+            inbox.receive(&older, rows(page * PAGE_SIZE..(page + 1) * PAGE_SIZE));
+        }
+        assert!(inbox.get(0).is_none());
+        assert_eq!(
+            inbox.get(PAGE_SIZE).map(|row| row.id.clone()),
+            Some("9900".into())
+        );
+        let reload = inbox.reload(None, &filter, true);
+        assert_eq!(reload, Page::From(Cursor::of(&rows(100..101)[0]), 300));
+        assert_eq!(
+            inbox.reload(Some("other"), &filter, true),
+            Page::First(PAGE_SIZE)
+        );
+
+        // And back up again.
+        let newer = inbox.wants(50..70).expect("the page before");
+        assert_eq!(
+            newer,
+            Page::After(Cursor::of(&rows(100..101)[0]), PAGE_SIZE)
+        );
+        inbox.receive(&newer, rows(0..PAGE_SIZE));
+        assert_eq!(inbox.get(0).map(|row| row.id.clone()), Some("10000".into()));
+        assert_eq!(inbox.total(), MAX_ROWS + 1);
+
+        // A short last page means there's nothing older.
+        let mut inbox = InboxWindow::default();
+        inbox.reset(&Page::First(PAGE_SIZE), rows(0..10));
+        assert_eq!(inbox.total(), 10);
+        assert_eq!(inbox.wants(0..10), None);
+    }
+}
@@ -0,0 +1,571 @@
+//! Event ingestion pipeline.
+//!
+//! Events from the relay pool (already deduplicated there) are parsed, verified and, for gift
+//! wraps, unwrapped on worker threads. The UI thread only stores the results, which updates the
+//! search index through the database triggers, and refreshes the views once per batch. Both
+//! queues are bounded: when the workers fall behind we stop pulling from the relays, and the
+//! messages wait in the websocket buffers instead of piling up in memory.
+
+use crate::account_manager;
+use crate::metrics;
+use eframe::egui;
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{Event, JsonUtil, Keys, Kind};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, trace};
+
+/// Raw events waiting for a worker.
+const RAW_QUEUE_SIZE: usize = 1024;
+/// Verified events waiting to be stored.
+const PREPARED_QUEUE_SIZE: usize = 1024;
+const MAX_WORKERS: usize = 4;
+/// Gift wraps kept for when there are keys to unwrap them with.
+const LOCKED_LIMIT: usize = 4096;
+/// How long a relay's sighting of an event waits for the event to pass verification.
+const SIGHTING_PATIENCE: Duration = Duration::from_secs(60);
+/// Verified events remembered so copies other relays deliver later count for them too.
+const VERIFIED_MEMORY: usize = 4096;
+
+/// A verified event, ready to be stored.
+pub enum Prepared {
+    Event(Event),
+    GiftWrap {
+        wrap: Event,
+        unwrapped: Result<UnwrappedGift, String>,
+    },
+}
+
+impl Prepared {
+    /// The id of the event as the relays delivered it, the gift wrap's for gift wraps.
+    pub fn id(&self) -> String {
+        match self {
+            Prepared::Event(event) | Prepared::GiftWrap { wrap: event, .. } => event.id.to_hex(),
+        }
+    }
+}
+
+/// Which relays delivered which events, held back until the event passes verification so
+/// nothing a relay merely claims gets recorded.
+#[derive(Default)]
+pub struct Sightings {
+    /// Relays that delivered events still being verified, by event id, with when the first
+    /// one did.
+    pending: HashMap<String, (Instant, Vec<String>)>,
+    verified: HashSet<String>,
+    /// `verified`, oldest first.
+    verified_order: VecDeque<String>,
+}
+
+impl Sightings {
+    /// Takes the relay pool's latest (event id, relay url) sightings, and returns the ones
+    /// of events already verified, ready to record. Sightings whose event never passed are
+    /// dropped after a while.
+    pub fn add(&mut self, sightings: Vec<(String, String)>) -> Vec<(String, String)> {
+        let now = Instant::now();
+        self.pending
+            .retain(|_, (seen, _)| now.duration_since(*seen) < SIGHTING_PATIENCE);
+        let mut ready = Vec::new();
+        for (event_id, url) in sightings {
+            if self.verified.contains(&event_id) {
+                ready.push((event_id, url));
+            } else {
+                let (_, urls) = self.pending.entry(event_id).or_insert((now, Vec::new()));
+                urls.push(url);
+            }
+        }
+        ready
+    }
+
+    /// Notes that `event_id` passed verification. Returns the relays that delivered it.
+    pub fn verified(&mut self, event_id: &str) -> Vec<String> {
+        if self.verified.insert(event_id.to_string()) {
+            self.verified_order.push_back(event_id.to_string());
+            if self.verified_order.len() > VERIFIED_MEMORY {
+                if let Some(oldest) = self.verified_order.pop_front() {
+                    self.verified.remove(&oldest);
+                }
+            }
+        }
+        self.pending
+            .remove(event_id)
+            .map(|(_, urls)| urls)
+            .unwrap_or_default()
+    }
+}
+
+/// What a worker hands back.
+enum Worked {
+    Prepared(Prepared),
+    /// A gift wrap that came before there were any keys to unwrap it with.
+    Locked(Event),
+}
+
+pub struct Ingest {
+    raw: SyncSender<String>,
+    prepared: Receiver<Worked>,
+    keys: Arc<RwLock<Vec<Keys>>>,
+    /// An event the raw queue had no room for, submitted first next time.
+    held: Option<String>,
+    /// Gift wraps waiting for keys. The relay pool only hands an event over once, so they'd
+    /// be gone for good if dropped.
+    locked: Vec<Event>,
+}
+
+impl Ingest {
+    pub fn new(ctx: egui::Context) -> Self {
+        let (raw, raw_receiver) = std::sync::mpsc::sync_channel::<String>(RAW_QUEUE_SIZE);
+        let (prepared_sender, prepared) = std::sync::mpsc::sync_channel(PREPARED_QUEUE_SIZE);
+        let raw_receiver = Arc::new(Mutex::new(raw_receiver));
+        let keys = Arc::new(RwLock::new(Vec::new()));
+
+        let workers = thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1))
+            .unwrap_or(1)
+            .clamp(1, MAX_WORKERS);
+        for i in 0..workers {
+            let raw_receiver = raw_receiver.clone();
+            let prepared_sender = prepared_sender.clone();
+            let keys = keys.clone();
+            let ctx = ctx.clone();
+            thread::Builder::new()
+                .name(format!("ingest-{}", i))
+                .spawn(move || loop {
+                    // Only hold the lock while waiting, so the others can verify meanwhile.
+                    let next = raw_receiver.lock().map(|receiver| receiver.recv());
+                    let Ok(Ok(event_json)) = next else {
+                        return;
+                    };
+                    let Some(worked) = prepare(&event_json, &keys) else {
+                        continue;
+                    };
+                    if prepared_sender.send(worked).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                })
+                .expect("failed to start ingestion worker");
+        }
+
+        Self {
+            raw,
+            prepared,
+            keys,
+            held: None,
+            locked: Vec::new(),
+        }
+    }
+
+    /// Keeps the workers' copy of our keys up to date, they need them to unwrap gift wraps.
+    pub fn sync_keys(&self, loaded_keys: &[Keys]) {
+        let changed = match self.keys.read() {
+            Ok(keys) => {
+                keys.len() != loaded_keys.len()
+                    || keys
+                        .iter()
+                        .zip(loaded_keys)
+                        .any(|(a, b)| a.public_key() != b.public_key())
+            }
+            Err(_) => return,
+        };
+        if changed {
+            if let Ok(mut keys) = self.keys.write() {
+                *keys = loaded_keys.to_vec();
+            }
+        }
+    }
+
+    /// Whether there's room for another event. When there isn't, leave the rest with the relays.
+    pub fn has_room(&mut self) -> bool {
+        self.release_locked();
+        match self.held.take() {
+            Some(event_json) => {
+                self.submit(event_json);
+                self.held.is_none()
+            }
+            None => true,
+        }
+    }
+
+    /// Queues an event for the workers. If the queue is full the event is held, and goes first
+    /// once there's room again.
+    pub fn submit(&mut self, event_json: String) {
+        match self.raw.try_send(event_json) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event_json)) => {
+                metrics::increment(metrics::INGEST_BACKPRESSURE);
+                self.held = Some(event_json);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("Ingestion workers are gone, dropping event");
+            }
+        }
+    }
+
+    /// The next verified event ready to be stored, if any.
+    pub fn next_prepared(&mut self) -> Option<Prepared> {
+        self.release_locked();
+        loop {
+            match self.prepared.try_recv().ok()? {
+                Worked::Prepared(prepared) => return Some(prepared),
+                Worked::Locked(wrap) if self.locked.len() < LOCKED_LIMIT => self.locked.push(wrap),
+                Worked::Locked(wrap) => {
+                    error!("Too many gift wraps wait for keys, dropping {}", wrap.id)
+                }
+            }
+        }
+    }
+
+    /// Hands the gift wraps that came before the keys back to the workers, once there are keys.
+    fn release_locked(&mut self) {
+        let unlocked = self.keys.read().is_ok_and(|keys| !keys.is_empty());
+        if !unlocked {
+            return;
+        }
+        while let Some(wrap) = self.locked.pop() {
+            match self.raw.try_send(wrap.as_json()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.locked.push(wrap);
+                    return;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!("Ingestion workers are gone, dropping event");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Parses, verifies and unwraps one event. `None` if it has to be dropped.
+fn prepare(event_json: &str, keys: &RwLock<Vec<Keys>>) -> Option<Worked> {
+    let _timer = metrics::start_timer(metrics::INGEST_PREPARE);
+    metrics::increment(metrics::EVENTS_RECEIVED);
+    let event = match serde_json::from_str::<Event>(event_json) {
+        Ok(event) => event,
+        Err(_) => {
+            error!("Failed to parse event JSON: {}", event_json);
+            metrics::increment(metrics::EVENTS_REJECTED);
+            return None;
+        }
+    };
+
+    if event.verify().is_err() {
+        error!("Event verification failed for event: {}", event.id);
+        metrics::increment(metrics::EVENTS_REJECTED);
+        return None;
+    }
+    trace!("Verified event: {:?}", event);
+    metrics::increment(metrics::EVENTS_PROCESSED);
+
+    if event.kind != Kind::GiftWrap {
+        return Some(Worked::Prepared(Prepared::Event(event)));
+    }
+
+    let unwrapped = match keys.read() {
+        Ok(keys) if keys.is_empty() => return Some(Worked::Locked(event)),
+        Ok(keys) => account_manager::unwrap_gift_wrap(&keys, &event).map_err(|e| e.to_string()),
+        Err(_) => Err("keys unavailable".to_string()),
+    };
+    Some(Worked::Prepared(Prepared::GiftWrap {
+        wrap: event,
+        unwrapped,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Db, EventToStore, MessageFilter};
+    use crate::mail_event::MailMessage;
+    use crate::relay::mock::MockRelay;
+    use crate::relay::{Ack, ClientMessage, RelayMessage, RelayPool, Subscription};
+    use nostr::{Alphabet, EventBuilder, Filter, JsonUtil, PublicKey, SingleLetterTag, TagKind};
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    const RELAY_URL: &str = "wss://mock.example.com";
+
+    /// A pool connected to a mock relay, feeding the workers and a database the way the app
+    /// does.
+    struct Pipeline {
+        relay: MockRelay,
+        pool: RelayPool,
+        ingest: Ingest,
+        db: Db,
+        /// Subscriptions the relay has sent everything stored for.
+        eose: HashSet<String>,
+    }
+
+    impl Pipeline {
+        fn new(keys: &Keys) -> anyhow::Result<Self> {
+            let relay = MockRelay::new();
+            let mut pool = RelayPool::new();
+            pool.add_mock(RELAY_URL, &relay);
+            let ingest = Ingest::new(egui::Context::default());
+            ingest.sync_keys(std::slice::from_ref(keys));
+            Ok(Self {
+                relay,
+                pool,
+                ingest,
+                db: Db::new_in_memory()?,
+                eose: HashSet::new(),
+            })
+        }
+
+        fn subscribe_to_mail_for(&mut self, pubkey: PublicKey) -> anyhow::Result<String> {
+            let mut subscription = Subscription::default();
+            subscription.filter(
+                Filter::new()
+                    .kind(Kind::GiftWrap)
+                    .custom_tag(SingleLetterTag::lowercase(Alphabet::P), [pubkey.to_hex()]),
+            );
+            let id = subscription.id.clone();
+            self.pool
+                .add_subscription(subscription)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok(id)
+        }
+
+        /// Moves messages along until `done` holds, failing after a few seconds.
+        fn run_until(&mut self, done: impl Fn(&Self) -> bool) -> anyhow::Result<()> {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !done(self) {
+                anyhow::ensure!(Instant::now() < deadline, "the pipeline stalled");
+                while let Some(raw) = self.pool.try_recv() {
+                    let message =
+                        RelayMessage::from_json(&raw).map_err(|e| anyhow::anyhow!("{}", e))?;
+                    match message {
+                        RelayMessage::Event(_, event_json) => {
+                            self.ingest.submit(event_json.to_string())
+                        }
+                        RelayMessage::Eose(subscription_id) => {
+                            self.eose.insert(subscription_id.to_string());
+                        }
+                        _ => {}
+                    }
+                }
+                let mut batch = Vec::new();
+                while let Some(prepared) = self.ingest.next_prepared() {
+                    batch.extend(to_store(prepared));
+                }
+                self.db.store_events(&batch)?;
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(())
+        }
+
+        fn inbox_subjects(&self) -> Vec<String> {
+            let mut subjects: Vec<String> = self
+                .db
+                .get_top_level_messages(None, &MessageFilter::default())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| entry.subject)
+                .collect();
+            subjects.sort();
+            subjects
+        }
+    }
+
+    fn to_store(prepared: Prepared) -> Option<EventToStore> {
+        match prepared {
+            Prepared::Event(event) => Some(event.into()),
+            // Like the app, skip wraps that aren't for us.
+            Prepared::GiftWrap {
+                unwrapped: Err(_), ..
+            } => None,
+            Prepared::GiftWrap {
+                wrap,
+                unwrapped: Ok(unwrapped),
+            } => {
+                let recipient = wrap
+                    .tags
+                    .find(TagKind::p())
+                    .and_then(|tag| tag.content())
+                    .map(|pubkey| pubkey.to_string());
+                Some(EventToStore {
+                    event: wrap,
+                    unwrapped: Some(unwrapped),
+                    recipient,
+                })
+            }
+        }
+    }
+
+    fn mail(from: &Keys, to: PublicKey, subject: &str) -> Event {
+        let mut message = MailMessage {
+            id: None,
+            created_at: None,
+            author: None,
+            to: vec![to],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            parent_events: None,
+            subject: subject.to_string(),
+            content: "Hello from the other side".to_string(),
+            attachments: Vec::new(),
+            articles: Vec::new(),
+            quote: None,
+            payload: None,
+            email_to: Vec::new(),
+            email_from: None,
+            tag_relays: Default::default(),
+        };
+        message
+            .to_events(from, std::time::Duration::ZERO)
+            .remove(&to)
+            .expect("a gift wrap for the recipient")
+    }
+
+    #[test]
+    fn delivers_gift_wrapped_mail_from_a_relay_to_the_inbox() -> anyhow::Result<()> {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut pipeline = Pipeline::new(&bob)?;
+
+        // Sent while Bob was away, so it comes with the stored events.
+        pipeline
+            .relay
+            .publish(mail(&alice, bob.public_key(), "Lunch on Friday"));
+        let subscription_id = pipeline.subscribe_to_mail_for(bob.public_key())?;
+        pipeline.run_until(|p| p.eose.contains(&subscription_id))?;
+        pipeline.run_until(|p| p.inbox_subjects().len() == 1)?;
+        assert_eq!(pipeline.inbox_subjects(), vec!["Lunch on Friday"]);
+
+        // Sent while connected, so it arrives on the open subscription.
+        pipeline
+            .relay
+            .publish(mail(&alice, bob.public_key(), "Bring snacks"));
+        pipeline.run_until(|p| p.inbox_subjects().len() == 2)?;
+        assert_eq!(
+            pipeline.inbox_subjects(),
+            vec!["Bring snacks", "Lunch on Friday"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn records_sightings_once_verified() {
+        let mut sightings = Sightings::default();
+        let seen = |event_id: &str, url: &str| (event_id.to_string(), url.to_string());
+        assert!(sightings
+            .add(vec![
+                seen("a", "wss://one.example.com"),
+                seen("b", "wss://one.example.com")
+            ])
+            .is_empty());
+        assert_eq!(sightings.verified("a"), vec!["wss://one.example.com"]);
+        // Copies that come later count right away, others keep waiting.
+        assert_eq!(
+            sightings.add(vec![
+                seen("a", "wss://two.example.com"),
+                seen("b", "wss://two.example.com")
+            ]),
+            vec![seen("a", "wss://two.example.com")]
+        );
+        assert_eq!(
+            sightings.verified("b"),
+            vec!["wss://one.example.com", "wss://two.example.com"]
+        );
+    }
+
+    #[test]
+    fn keeps_gift_wraps_until_the_keys_are_loaded() -> anyhow::Result<()> {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut ingest = Ingest::new(egui::Context::default());
+        ingest.submit(mail(&alice, bob.public_key(), "Early bird").as_json());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while ingest.locked.is_empty() {
+            assert!(ingest.next_prepared().is_none());
+            anyhow::ensure!(Instant::now() < deadline, "the wrap never came back");
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        ingest.sync_keys(std::slice::from_ref(&bob));
+        let prepared = loop {
+            if let Some(prepared) = ingest.next_prepared() {
+                break prepared;
+            }
+            anyhow::ensure!(Instant::now() < deadline, "the wrap was never unwrapped");
+            thread::sleep(Duration::from_millis(5));
+        };
+        match prepared {
+            Prepared::GiftWrap {
+                unwrapped: Ok(unwrapped),
+                ..
+            } => assert_eq!(unwrapped.sender, alice.public_key()),
+            _ => panic!("expected the unwrapped mail"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn publishes_to_a_relay_and_collects_its_ok() -> anyhow::Result<()> {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut pipeline = Pipeline::new(&alice)?;
+        let subscription_id = pipeline.subscribe_to_mail_for(alice.public_key())?;
+        pipeline.run_until(|p| p.eose.contains(&subscription_id))?;
+
+        let wrap = mail(&alice, bob.public_key(), "Minutes");
+        let wrap_id = wrap.id.to_hex();
+        pipeline.pool.expect_acks(wrap_id.clone());
+        let payload = serde_json::to_string(&ClientMessage::Event {
+            event: wrap.clone(),
+        })?;
+        pipeline
+            .pool
+            .send(ewebsock::WsMessage::Text(payload))
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        pipeline.run_until(|p| {
+            p.pool
+                .acks(&wrap_id)
+                .is_some_and(|acks| acks.contains_key(RELAY_URL))
+        })?;
+
+        assert_eq!(
+            pipeline
+                .pool
+                .acks(&wrap_id)
+                .and_then(|acks| acks.get(RELAY_URL)),
+            Some(&Ack::Accepted)
+        );
+        assert_eq!(pipeline.relay.events(), vec![wrap]);
+
+        pipeline.pool.close_all();
+        assert_eq!(pipeline.relay.open_subscriptions(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn drops_malformed_events_and_passes_verified_ones_on() -> anyhow::Result<()> {
+        let keys = Keys::generate();
+        let note = EventBuilder::new(Kind::TextNote, "hello").sign_with_keys(&keys)?;
+
+        let mut ingest = Ingest::new(egui::Context::default());
+        ingest.submit("{\"not\": \"an event\"}".to_string());
+        ingest.submit(note.as_json());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let prepared = loop {
+            let prepared: Vec<Prepared> = std::iter::from_fn(|| ingest.next_prepared()).collect();
+            if !prepared.is_empty() || Instant::now() > deadline {
+                break prepared;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        match prepared.as_slice() {
+            [Prepared::Event(event)] => assert_eq!(event.id, note.id),
+            _ => panic!("expected only the signed note"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Carries out the retention rules in the background, see `hoot_core::retention`. What the
+//! rules do is worked out on the database worker when there is one, and carried out here.
+
+use crate::db::Db;
+use eframe::egui;
+use hoot_core::retention::{self, RetentionPlan, RetentionPolicy};
+use std::time::{Duration, Instant};
+use tracing::error;
+
+pub const RETENTION_KEY: &str = "retention_policy";
+
+/// How often the rules are applied while Hoot runs. They also run right after unlocking.
+const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub struct Janitor {
+    pub policy: RetentionPolicy,
+    next_run: Instant,
+    /// What the rules would do, worked out on request for Settings → Data.
+    pub preview: Option<RetentionPlan>,
+}
+
+impl Janitor {
+    pub fn new(policy: RetentionPolicy) -> Self {
+        Self {
+            policy,
+            next_run: Instant::now(),
+            preview: None,
+        }
+    }
+
+    /// Whether the rules are due to run, waiting for the next run from now when they are.
+    pub fn due(&mut self, ctx: &egui::Context) -> bool {
+        let now = Instant::now();
+        if now < self.next_run {
+            ctx.request_repaint_after(self.next_run - now);
+            return false;
+        }
+        self.next_run = now + INTERVAL;
+        true
+    }
+
+    /// Works out what the rules would do at `now`, for when there's no worker to ask.
+    pub fn plan(&self, db: &Db, now: i64) -> Option<RetentionPlan> {
+        retention::plan(db, &self.policy, now)
+            .map_err(|e| error!("Failed to work out what the retention rules do: {}", e))
+            .ok()
+    }
+
+    /// Carries out `plan`, worked out at `now`. Returns true if anything moved.
+    pub fn apply(&mut self, db: &mut Db, plan: &RetentionPlan, now: i64) -> bool {
+        self.preview = None;
+        match retention::apply(db, &self.policy, plan, now) {
+            Ok(()) => !plan.is_empty(),
+            Err(e) => {
+                error!("Failed to apply the retention rules: {}", e);
+                false
+            }
+        }
+    }
+}
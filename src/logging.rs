@@ -0,0 +1,239 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload};
+
+pub const LOG_FILE_NAME: &str = "hoot.log";
+/// Rotate the current log file once it grows past this size.
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// How many rotated files to keep next to the active one (`hoot.log.1` .. `hoot.log.N`).
+const MAX_ROTATED_FILES: usize = 4;
+
+/// Per-module levels, using the same syntax as `RUST_LOG`, e.g. `info,hoot::relay=trace`.
+const LOG_FILTER_ENV: &str = "HOOT_LOG";
+/// Set to `json` to write the log file as newline-delimited JSON, handy for bug reports.
+const LOG_FORMAT_ENV: &str = "HOOT_LOG_FORMAT";
+/// Storage key for the levels picked in Settings, in the same syntax as [`LOG_FILTER_ENV`].
+pub const LOG_FILTER_KEY: &str = "log_filter";
+
+/// Swaps what the stdout and file logs let through, see [`set_filter`].
+struct Reload {
+    /// The environment variable that set the levels, which then win over Settings.
+    from_env: Option<&'static str>,
+    /// Sets a log's filter, or puts its default back for `None`.
+    layers: Vec<Box<dyn Fn(Option<Targets>) + Send + Sync>>,
+}
+
+static RELOAD: OnceLock<Reload> = OnceLock::new();
+/// The levels picked in Settings, empty for the defaults.
+static FILTER: Mutex<String> = Mutex::new(String::new());
+
+pub fn log_dir() -> Option<PathBuf> {
+    eframe::storage_dir(crate::STORAGE_NAME).map(|dir| dir.join("logs"))
+}
+
+/// Sets up logging to stdout and to a size-capped, rotating file in the storage dir.
+/// The returned guards flush the background writers and must live until the app exits.
+pub fn init() -> Vec<WorkerGuard> {
+    let mut guards = Vec::new();
+
+    // Debug output names events and frames, so it only reaches the file when asked for.
+    let mut filter_error = None;
+    let directives = [LOG_FILTER_ENV, "RUST_LOG"]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok().map(|value| (name, value)));
+    let env_name = directives.as_ref().map(|(name, _)| *name);
+    let configured = directives.and_then(|(name, directives)| {
+        Targets::from_str(&directives)
+            .map_err(|e| filter_error = Some(format!("{} value {} ({})", name, directives, e)))
+            .ok()
+    });
+    let from_env = env_name.filter(|_| configured.is_some());
+    let stdout_default = configured
+        .clone()
+        .unwrap_or_else(|| Targets::new().with_default(Level::DEBUG));
+    let file_default = configured.unwrap_or_else(|| Targets::new().with_default(Level::INFO));
+    let (stdout_filter, stdout_handle) = reload::Layer::new(stdout_default.clone());
+    let (file_filter, file_handle) = reload::Layer::new(file_default.clone());
+    let layers: Vec<Box<dyn Fn(Option<Targets>) + Send + Sync>> = vec![
+        Box::new(move |targets| {
+            let _ = stdout_handle.reload(targets.unwrap_or_else(|| stdout_default.clone()));
+        }),
+        Box::new(move |targets| {
+            let _ = file_handle.reload(targets.unwrap_or_else(|| file_default.clone()));
+        }),
+    ];
+    let _ = RELOAD.set(Reload { from_env, layers });
+    let json = std::env::var(LOG_FORMAT_ENV).is_ok_and(|format| format == "json");
+
+    let (stdout, guard) = tracing_appender::non_blocking(io::stdout());
+    guards.push(guard);
+
+    let mut file_error = None;
+    let file_layer = match log_dir().map(RotatingFile::open) {
+        Some(Ok(file)) => {
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            guards.push(guard);
+            let layer = if json {
+                fmt::layer()
+                    .json()
+                    .with_writer(writer)
+                    .with_filter(file_filter)
+                    .boxed()
+            } else {
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer)
+                    .with_filter(file_filter)
+                    .boxed()
+            };
+            Some(layer)
+        }
+        Some(Err(e)) => {
+            file_error = Some(e.to_string());
+            None
+        }
+        None => {
+            file_error = Some("could not determine storage directory".to_string());
+            None
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_writer(stdout).with_filter(stdout_filter))
+        .with(file_layer)
+        .init();
+
+    if let Some(e) = filter_error {
+        warn!("Ignoring invalid {}", e);
+    }
+    if let Some(e) = file_error {
+        warn!("Logging to stdout only, could not open log file: {}", e);
+    }
+
+    guards
+}
+
+/// The levels picked in Settings, empty for the defaults.
+pub fn filter() -> String {
+    FILTER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Logs what `directives` asks for from now on, like `info,hoot::relay=trace`, or goes back to
+/// the defaults when it's empty. `HOOT_LOG` or `RUST_LOG` win when set.
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let directives = directives.trim();
+    let targets = if directives.is_empty() {
+        None
+    } else {
+        Some(Targets::from_str(directives).map_err(|e| format!("{} ({})", directives, e))?)
+    };
+    *FILTER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = directives.to_string();
+    let Some(reload) = RELOAD.get() else {
+        return Ok(());
+    };
+    if let Some(name) = reload.from_env {
+        return Err(format!("{} is set, which decides what's logged", name));
+    }
+    for layer in &reload.layers {
+        layer(targets.clone());
+    }
+    info!("Log levels set to {:?}", directives);
+    Ok(())
+}
+
+/// Opens the log folder in the platform's file manager.
+pub fn open_logs_folder() -> io::Result<()> {
+    let dir = log_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not determine log folder"))?;
+    fs::create_dir_all(&dir)?;
+    crate::downloads::open_path(&dir)
+}
+
+/// A log file that moves itself aside once it reaches `MAX_LOG_BYTES`.
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let file = Self::open_active(&dir)?;
+        let written = file.metadata()?.len();
+        Ok(Self { dir, file, written })
+    }
+
+    fn open_active(dir: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}", LOG_FILE_NAME, index))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let oldest = self.rotated_path(MAX_ROTATED_FILES);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for index in (1..MAX_ROTATED_FILES).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(self.dir.join(LOG_FILE_NAME), self.rotated_path(1))?;
+
+        self.file = Self::open_active(&self.dir)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > MAX_LOG_BYTES {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_levels_that_parse() {
+        assert!(set_filter(" info,hoot::relay=trace ").is_ok());
+        assert_eq!(filter(), "info,hoot::relay=trace");
+        assert!(set_filter("hoot=loud").is_err());
+        assert_eq!(filter(), "info,hoot::relay=trace");
+        assert!(set_filter("").is_ok());
+        assert_eq!(filter(), "");
+    }
+}
@@ -0,0 +1,183 @@
+use crate::mail_event::MailMessage;
+use crate::relay::{ClientMessage, RelayPool};
+use eframe::egui;
+use nostr::{Keys, PublicKey};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Time between two messages of a merge, so relays don't rate limit us.
+pub const SEND_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One recipient's copy of a merged message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedMessage {
+    pub recipient: PublicKey,
+    pub subject: String,
+    pub content: String,
+}
+
+/// Replaces `{{field}}` placeholders using `lookup`. Unknown fields are left as they are so
+/// they show up in the preview.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let field = &rest[start + 2..start + 2 + len];
+        out.push_str(&rest[..start]);
+        match lookup(field.trim()) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + len + 4]),
+        }
+        rest = &rest[start + len + 4..];
+    }
+    out.push_str(rest);
+    out
+}
+
+struct MergeJob {
+    keys: Keys,
+    /// How far back the gift wraps may be dated.
+    fuzz: Duration,
+    queue: VecDeque<MergedMessage>,
+    total: usize,
+    next_send: Instant,
+}
+
+/// Sends merged messages one at a time, each gift wrapped to its own recipient.
+#[derive(Default)]
+pub struct MailMerge {
+    job: Option<MergeJob>,
+    pub sent: usize,
+    pub failed: usize,
+}
+
+impl MailMerge {
+    pub fn start(&mut self, keys: Keys, messages: Vec<MergedMessage>, fuzz: Duration) {
+        info!("Starting mail merge to {} recipients", messages.len());
+        self.sent = 0;
+        self.failed = 0;
+        self.job = Some(MergeJob {
+            keys,
+            fuzz,
+            total: messages.len(),
+            queue: messages.into(),
+            next_send: Instant::now(),
+        });
+    }
+
+    /// (messages handled so far, total) while a merge is running.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        self.job
+            .as_ref()
+            .map(|job| (job.total - job.queue.len(), job.total))
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(job) = self.job.take() {
+            info!(
+                "Mail merge cancelled with {} messages unsent",
+                job.queue.len()
+            );
+        }
+    }
+
+    /// Sends everything still queued right away, for when the app is quitting.
+    pub fn flush(&mut self, relays: &mut RelayPool) {
+        let Some(job) = self.job.take() else {
+            return;
+        };
+        info!("Flushing {} queued merge messages", job.queue.len());
+        for merged in job.queue {
+            match send(relays, &job.keys, merged, job.fuzz) {
+                Ok(()) => self.sent += 1,
+                Err(e) => {
+                    error!("Failed to send merged message: {}", e);
+                    self.failed += 1;
+                }
+            }
+        }
+    }
+
+    pub fn process_queue(&mut self, relays: &mut RelayPool, ctx: &egui::Context) {
+        let Some(job) = self.job.as_mut() else {
+            return;
+        };
+
+        let now = Instant::now();
+        if now < job.next_send {
+            ctx.request_repaint_after(job.next_send - now);
+            return;
+        }
+
+        if let Some(merged) = job.queue.pop_front() {
+            match send(relays, &job.keys, merged, job.fuzz) {
+                Ok(()) => self.sent += 1,
+                Err(e) => {
+                    error!("Failed to send merged message: {}", e);
+                    self.failed += 1;
+                }
+            }
+            job.next_send = now + SEND_INTERVAL;
+            ctx.request_repaint_after(SEND_INTERVAL);
+        }
+
+        if job.queue.is_empty() {
+            info!(
+                "Mail merge finished: {} sent, {} failed",
+                self.sent, self.failed
+            );
+            self.job = None;
+        }
+    }
+}
+
+fn send(
+    relays: &mut RelayPool,
+    keys: &Keys,
+    merged: MergedMessage,
+    fuzz: Duration,
+) -> anyhow::Result<()> {
+    let mut msg = MailMessage {
+        id: None,
+        created_at: None,
+        author: None,
+        to: vec![merged.recipient],
+        cc: vec![],
+        bcc: vec![],
+        parent_events: None,
+        subject: merged.subject,
+        content: merged.content,
+        attachments: vec![],
+        articles: vec![],
+        quote: None,
+        payload: None,
+        email_to: vec![],
+        email_from: None,
+        tag_relays: Default::default(),
+    };
+    for (_, event) in msg.to_events(keys, fuzz) {
+        let payload = serde_json::to_string(&ClientMessage::Event { event })?;
+        relays
+            .send(ewebsock::WsMessage::Text(payload))
+            .map_err(|e| anyhow::anyhow!("Could not send to relays: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_known_placeholders() {
+        let lookup = |field: &str| (field == "name").then(|| "Alice".to_string());
+        assert_eq!(
+            render("Hi {{name}}, {{ name }}! {{unknown}} {{", lookup),
+            "Hi Alice, Alice! {{unknown}} {{"
+        );
+    }
+}
@@ -10,37 +10,54 @@ use egui_extras::{Column, TableBuilder};
 use nostr::{event::Kind, EventId, TagKind};
 use std::collections::{HashMap, HashSet};
 use std::panic;
-use tracing::{debug, error, info, warn, Level};
+use tracing::{debug, error, info, trace, warn};
 
-mod account_manager;
-mod db;
-mod error;
+use hoot_core::{
+    account_manager, article, bolt11, bridge, calendar, clock, db, encryption, flag_sync, focus,
+    mail_event, metrics, payload, relay, threaded_event, verification, wallet, zap, TableEntry,
+    STORAGE_NAME,
+};
+
+mod account_colors;
+mod actions;
+mod automation;
+mod client_import;
+mod db_worker;
+mod downloads;
+mod external_editor;
+mod flag_publisher;
 mod image_loader;
-mod mail_event;
+mod inbox_columns;
+use inbox_columns::InboxColumn;
+mod inbox_window;
+mod ingest;
+mod janitor;
+mod logging;
+mod mail_merge;
+mod name_resolver;
+mod nostr_uri;
+mod payments;
+mod preferences;
 mod profile_metadata;
-use profile_metadata::{get_profile_metadata, ProfileMetadata, ProfileOption};
-mod relay;
+mod proof_of_work;
+mod qr;
+use profile_metadata::{get_profile_metadata, ProfileOption};
+mod relay_info;
+mod relay_presets;
+mod repaint;
+mod search_query;
+mod single_instance;
 mod style;
+mod threading;
+mod translate;
 mod ui;
+mod uploads;
+mod zaps;
+use ui::compose_window::{ComposeWindow, ComposeWindowState};
 use ui::contacts::ContactsManager;
 
-// WE PROBABLY SHOULDN'T MAKE EVERYTHING A STRING, GRR!
-#[derive(Clone, Debug)]
-pub struct TableEntry {
-    pub id: String,
-    pub content: String,
-    pub subject: String,
-    pub pubkey: String,
-    pub created_at: i64,
-    pub thread_count: i64,
-}
-
 fn main() -> Result<(), eframe::Error> {
-    let (non_blocking, _guard) = tracing_appender::non_blocking(std::io::stdout()); // add log files in prod one day
-    tracing_subscriber::fmt()
-        .with_writer(non_blocking)
-        .with_max_level(Level::DEBUG)
-        .init();
+    let _log_guards = logging::init();
 
     #[cfg(feature = "profiling")]
     start_puffin_server();
@@ -50,11 +67,48 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
 
+    let command = single_instance::Command::from_args();
+    let lock = match single_instance::acquire() {
+        Ok(Some(lock)) => Some(lock),
+        Ok(None) => {
+            if single_instance::forward(&command) {
+                info!("Hoot is already running, handed over to it");
+                return Ok(());
+            }
+            warn!("Another Hoot holds the instance lock but doesn't answer");
+            return eframe::run_native(
+                "Hoot",
+                options,
+                Box::new(|_| Box::new(single_instance::AlreadyRunning)),
+            );
+        }
+        Err(e) => {
+            warn!("Couldn't check for another running Hoot: {}", e);
+            None
+        }
+    };
+
     eframe::run_native(
         "Hoot",
         options,
-        Box::new(|cc| {
-            style::apply_theme(&cc.egui_ctx);
+        Box::new(move |cc| {
+            let high_contrast = cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, style::HIGH_CONTRAST_KEY))
+                .unwrap_or(false);
+            style::apply_theme(&cc.egui_ctx, high_contrast);
+            let metrics_enabled = cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, metrics::METRICS_ENABLED_KEY))
+                .unwrap_or(false);
+            metrics::set_enabled(metrics_enabled);
+            let log_filter: String = cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, logging::LOG_FILTER_KEY))
+                .unwrap_or_default();
+            if let Err(e) = logging::set_filter(&log_filter) {
+                warn!("Not using the saved log levels: {}", e);
+            }
             let mut fonts = FontDefinitions::default();
             fonts.font_data.insert(
                 "Inter".to_owned(),
@@ -66,7 +120,14 @@ fn main() -> Result<(), eframe::Error> {
                 .unwrap()
                 .insert(0, "Inter".to_owned());
             cc.egui_ctx.set_fonts(fonts);
-            Box::new(Hoot::new(cc))
+            let mut app = Hoot::new(cc);
+            app.state.settings.high_contrast = high_contrast;
+            app._instance_lock = lock;
+            app.instance = single_instance::Instance::listen(cc.egui_ctx.clone());
+            if command != single_instance::Command::Show {
+                app.pending_commands.push(command);
+            }
+            Box::new(app)
         }),
     )
 }
@@ -75,9 +136,11 @@ fn main() -> Result<(), eframe::Error> {
 pub enum Page {
     Inbox,
     Drafts,
+    Sent,
     Starred,
     Archived,
     Trash,
+    Notes,
     Settings,
     // TODO: fix this mess
     Onboarding,
@@ -86,6 +149,7 @@ pub enum Page {
     OnboardingReturning,
     Post,
     Contacts,
+    Notifications,
     Unlock,
 }
 
@@ -93,11 +157,53 @@ pub enum Page {
 #[derive(Default)]
 pub struct HootState {
     pub add_account_window: HashMap<egui::Id, ui::add_account_window::AddAccountWindowState>,
-    pub compose_window: HashMap<egui::Id, ui::compose_window::ComposeWindowState>,
+    pub compose_window: HashMap<egui::Id, ComposeWindowState>,
     pub onboarding: ui::onboarding::OnboardingState,
     pub settings: ui::settings::SettingsState,
     pub unlock_database: ui::unlock_database::UnlockDatabaseState,
     pub contacts: ContactsPageState,
+    pub delete_dialog: Option<ui::delete_dialog::DeleteDialogState>,
+    pub report_dialog: Option<ui::report_dialog::ReportDialogState>,
+    /// Parsed calendar attachments, keyed by their SHA-256.
+    pub calendar_invites: HashMap<String, Option<calendar::Invite>>,
+    pub mail_merge: Option<ui::mail_merge_window::MailMergeState>,
+    /// Messages waiting to be marked read, and when.
+    pub pending_read: HashMap<String, std::time::Instant>,
+    /// Which messages of the open thread were unread when it was opened.
+    pub thread_unread: Option<ThreadUnread>,
+    /// Name being typed for the search about to be saved as a folder.
+    pub new_search_name: Option<String>,
+    /// Messages whose verification challenge we answered.
+    pub answered_challenges: HashSet<String>,
+    /// Verification challenges and answers waiting on the relays.
+    pub verification_sends: Vec<ui::compose_window::ComposeWindowState>,
+    /// Label being typed for the open thread.
+    pub new_label: String,
+    /// Text selected in a message of the open thread, which replying to it quotes.
+    pub selection: Option<mail_event::Fragment>,
+    /// The passage quoted by the reply under the pointer, highlighted in its message.
+    pub hovered_quote: Option<mail_event::Fragment>,
+    /// Subject being typed for a thread, with the thread's root id.
+    pub subject_edit: Option<(String, String)>,
+    /// What's typed in the reply box under each thread, by the thread's root id.
+    pub quick_replies: HashMap<String, ui::quick_reply::QuickReply>,
+    /// Whether the notes sidebar is shown beside threads.
+    pub notes_open: bool,
+    /// Note being written on a thread, with the thread's root id.
+    pub note_draft: Option<(String, String)>,
+    /// The message whose invoice is about to be paid, waiting for the user to confirm it.
+    pub confirm_invoice: Option<String>,
+}
+
+pub struct ThreadUnread {
+    pub root_id: String,
+    /// Messages already looked up, so ones arriving later still get checked.
+    pub checked: HashSet<String>,
+    /// Stays put while reading, so the markers don't vanish from under the reader.
+    pub unread: HashSet<String>,
+    /// Unread messages that have been on screen and are (being) marked read.
+    pub seen: HashSet<String>,
+    pub scrolled: bool,
 }
 
 #[derive(Default)]
@@ -108,6 +214,12 @@ pub struct ContactsPageState {
     pub editing_pubkey: Option<String>,
     pub editing_petname_buf: String,
     pub add_error: Option<String>,
+    /// Pubkey of the contact whose conversation history is open, if any.
+    pub conversation_with: Option<String>,
+    pub conversation_entries: Vec<TableEntry>,
+    pub conversation_has_more: bool,
+    /// Open when the duplicate finder has run.
+    pub duplicates: Option<Vec<ui::contacts::DuplicateMerge>>,
 }
 
 pub struct Hoot {
@@ -121,11 +233,118 @@ pub struct Hoot {
     account_manager: account_manager::AccountManager,
     pub active_account: Option<nostr::Keys>,
     db: db::Db,
-    table_entries: Vec<TableEntry>,
+    /// Runs inbox and thread queries off the render thread, once the database is unlocked.
+    db_worker: Option<db_worker::DbWorker>,
+    /// The last loaded copy of the focused thread.
+    thread_snapshot: Option<db_worker::ThreadSnapshot>,
+    /// Thread (root id, including trash) the worker was last asked for.
+    thread_requested: Option<(String, bool)>,
+    /// Verifies and unwraps incoming events on worker threads.
+    ingest: ingest::Ingest,
+    /// Which relays delivered the events `ingest` is still verifying.
+    sightings: ingest::Sightings,
+    /// The part of the inbox around where it's scrolled to.
+    inbox: inbox_window::InboxWindow,
+    archived_entries: Vec<TableEntry>,
     trash_entries: Vec<TableEntry>,
+    sent_entries: Vec<db::SentMessage>,
+    notes_entries: Vec<TableEntry>,
     profile_metadata: HashMap<String, profile_metadata::ProfileOption>,
+    /// Names for pubkeys, worked out from the contacts and profile metadata.
+    names: name_resolver::NameResolver,
     pub contacts_manager: ContactsManager,
     drafts: Vec<db::Draft>,
+    downloads: downloads::DownloadManager,
+    uploads: uploads::UploadManager,
+    /// Relays to connect to on startup, loaded from storage.
+    relay_urls: Vec<String>,
+    /// What each relay says about itself, like whether it wants paying.
+    relay_info: relay_info::RelayInfoManager,
+    /// Bundles of relays Settings offers to add in one go.
+    relay_presets: relay_presets::RelayPresets,
+    /// The wallet relay and upload fees are paid from, if one is connected.
+    payments: payments::Payments,
+    /// Zaps we're sending, and the ones on our messages.
+    zaps: zaps::ZapManager,
+    /// Copies of sent mail being made with the proof of work a relay wants.
+    proof_of_work: proof_of_work::ProofOfWork,
+    translator: translate::Translator,
+    automations: automation::Automations,
+    hover_preview: ui::hover_preview::HoverPreview,
+    external_editors: external_editor::ExternalEditors,
+    notifications: ui::notifications::Notifications,
+    /// Errors shown over the window for a few seconds.
+    toasts: ui::toasts::Toasts,
+    /// Stars, archiving and trashing on their way to the database.
+    actions: actions::Actions,
+    /// Pubkeys whose messages we drop, most recently blocked first.
+    blocked_pubkeys: Vec<String>,
+    bridge: bridge::BridgeConfig,
+    mail_merge: mail_merge::MailMerge,
+    /// Show replies that change the subject as their own conversation.
+    split_threads: bool,
+    /// Hex pubkey of the account whose folders are shown, `None` for the unified inbox.
+    mailbox: Option<String>,
+    /// Unread messages per account, keyed by hex pubkey.
+    unread_counts: HashMap<String, i64>,
+    /// Unread messages across all accounts.
+    unread_total: i64,
+    /// Gift wraps we've stored per account, to tell how far the sync has got.
+    stored_counts: HashMap<String, i64>,
+    /// Gift wraps relays report per account (NIP-45), the highest answer wins.
+    relay_counts: HashMap<String, u64>,
+    /// Account each of our COUNT requests is for, keyed by subscription id.
+    count_requests: HashMap<String, String>,
+    /// Search query and filter chips applied to the inbox.
+    inbox_filter: db::MessageFilter,
+    saved_searches: Vec<db::SavedSearch>,
+    /// Threads each saved search finds in the current mailbox, keyed by its id.
+    saved_search_counts: HashMap<i64, usize>,
+    /// The saved search shown in the inbox, if it's showing one.
+    active_search: Option<i64>,
+    /// When senders were last ranked for the Focused inbox.
+    focus_ranked: Option<std::time::Instant>,
+    /// Everyone whose mail goes to the Focused inbox, as the database last had it.
+    focused_senders: HashSet<String>,
+    /// Keeps other copies off the database for as long as this one runs, even when nothing
+    /// listens for their commands.
+    _instance_lock: Option<single_instance::InstanceLock>,
+    /// Hands us the commands of later launches.
+    instance: Option<single_instance::Instance>,
+    /// Launch commands waiting for an account to carry them out with.
+    pending_commands: Vec<single_instance::Command>,
+    starred_ids: HashSet<String>,
+    /// Subjects the user gave threads, by root id.
+    thread_subjects: HashMap<String, String>,
+    /// Notes the user keeps on threads, by root id.
+    thread_notes: HashMap<String, String>,
+    /// Invoices sent in messages that we paid, by the message's id.
+    paid_invoices: HashMap<String, String>,
+    /// The account we last wrote to each recipient as, so compose can pick it again.
+    sending_accounts: HashMap<String, String>,
+    /// The columns of the message list, loaded once the database is unlocked.
+    inbox_columns: inbox_columns::ColumnLayout,
+    /// What the database check at unlock, or the last one from Settings, found.
+    integrity: Option<db::IntegrityReport>,
+    /// Where recipients read, for the compose window to warn about unreachable ones.
+    relay_hints: relay::RelayHints,
+    repaint: repaint::RepaintScheduler,
+    /// Long-form notes shared in the mail we've opened.
+    articles: article::Articles,
+    preferences: preferences::Preferences,
+    /// The color each account is marked with.
+    account_colors: account_colors::AccountColors,
+    /// The account each inbox thread is with, looked up as its row is drawn.
+    thread_accounts: HashMap<String, Option<String>>,
+    /// Applies the retention rules now and then.
+    janitor: janitor::Janitor,
+    /// Saves stars, read state and archiving to relays after they change.
+    flag_publisher: flag_publisher::FlagPublisher,
+    frame_overlay: ui::frame_overlay::FrameOverlay,
+    /// The folder open when the app last closed, for starting there again.
+    last_folder: Option<preferences::Folder>,
+    /// Threads open in windows of their own.
+    thread_windows: Vec<ui::thread_window::ThreadWindow>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -136,9 +355,25 @@ enum HootStatus {
     Ready,
 }
 
-fn try_recv_relay_message(app: &mut Hoot) {
-    if let Some(raw) = app.relays.try_recv() {
-        info!("{:?}", &raw);
+/// How long a frame may spend on relay messages, and again on storing events, before the rest
+/// is left for the next one.
+const INGEST_FRAME_BUDGET: std::time::Duration = std::time::Duration::from_millis(8);
+/// How long quitting waits for relays to take messages sent just before.
+const SHUTDOWN_ACK_WAIT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Pulls relay messages until the ingestion queue is full or the frame budget runs out, the rest
+/// wait with the relays.
+fn try_recv_relay_message(app: &mut Hoot, ctx: &egui::Context) {
+    let started = std::time::Instant::now();
+    while app.ingest.has_room() {
+        if started.elapsed() > INGEST_FRAME_BUDGET {
+            ctx.request_repaint();
+            return;
+        }
+        let Some(raw) = app.relays.try_recv() else {
+            return;
+        };
+        trace!("{:?}", &raw);
         match relay::RelayMessage::from_json(&raw) {
             Ok(v) => process_message(app, &v),
             Err(e) => error!("could not decode message sent from relay: {}", e),
@@ -150,21 +385,14 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
     #[cfg(feature = "profiling")]
     puffin::profile_function!();
     let ctx = ctx.clone();
-    let wake_ctx = ctx.clone();
-    let wake_up = move || {
-        wake_ctx.request_repaint();
-    };
+    let wake_up = app.repaint.waker(&ctx);
 
     if app.status == HootStatus::PreUnlock {
         info!("Requesting Database Unlock before proceeding.");
         app.status = HootStatus::WaitingForUnlock;
-        let _ = app
-            .relays
-            .add_url("wss://relay.chakany.systems".to_string(), wake_up.clone());
-
-        let _ = app
-            .relays
-            .add_url("wss://talon.quest".to_string(), wake_up.clone());
+        for url in app.relay_urls.clone() {
+            let _ = app.relays.add_url(url, wake_up.clone());
+        }
 
         app.relays.keepalive(wake_up);
         return;
@@ -172,12 +400,22 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
         // the unlock happens in the render_app function
         // we can't do anything but wait until HootStatus is Initializing
         app.relays.keepalive(wake_up);
-        try_recv_relay_message(app);
+        try_recv_relay_message(app, &ctx);
         return;
     }
 
     if app.status == HootStatus::Initializing {
         info!("Initializing Hoot...");
+        match app.db.open_reader() {
+            Ok(reader) => app.db_worker = Some(db_worker::DbWorker::spawn(reader, ctx.clone())),
+            Err(e) => error!("Failed to start database worker, querying inline: {}", e),
+        }
+        match app.db.open_writer() {
+            Ok(writer) => app.actions = actions::Actions::spawn(writer, ctx.clone()),
+            Err(e) => error!("Failed to start database writer, saving inline: {}", e),
+        }
+        // Every connection that needs the key has it now.
+        app.db.forget_key();
         if let Err(e) = app.account_manager.load_keys(&app.db) {
             error!("something went wrong trying to load keys: {}", e);
         }
@@ -186,17 +424,50 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
             error!("Failed to purge deleted events: {}", e);
         }
 
-        let now = chrono::Utc::now().timestamp();
-        if let Err(e) = app.db.purge_expired_trash(now) {
-            error!("Failed to purge expired trash: {}", e);
+        app.check_integrity();
+
+        match app.db.get_blocked_pubkeys() {
+            Ok(pubkeys) => app.blocked_pubkeys = pubkeys,
+            Err(e) => error!("Failed to load blocked pubkeys: {}", e),
         }
 
-        match app.db.get_top_level_messages() {
-            Ok(msgs) => app.table_entries = msgs,
-            Err(e) => error!("Could not fetch table entries to display from DB: {}", e),
+        match app.db.get_starred_ids() {
+            Ok(ids) => app.starred_ids = ids,
+            Err(e) => error!("Failed to load starred messages: {}", e),
+        }
+
+        match app.db.get_thread_subjects() {
+            Ok(subjects) => app.thread_subjects = subjects,
+            Err(e) => error!("Failed to load thread subjects: {}", e),
+        }
+
+        match app.db.get_thread_notes() {
+            Ok(notes) => app.thread_notes = notes,
+            Err(e) => error!("Failed to load thread notes: {}", e),
+        }
+
+        match app.db.get_paid_invoices() {
+            Ok(invoices) => app.paid_invoices = invoices,
+            Err(e) => error!("Failed to load the paid invoices: {}", e),
+        }
+
+        match app.db.get_sending_accounts() {
+            Ok(accounts) => app.sending_accounts = accounts,
+            Err(e) => error!("Failed to load the accounts last used per recipient: {}", e),
+        }
+
+        match inbox_columns::ColumnLayout::load(&app.db) {
+            Ok(layout) => app.inbox_columns = layout,
+            Err(e) => error!("Failed to load the message list columns: {}", e),
         }
 
+        app.refresh_saved_searches();
+        app.refresh_focused_senders();
+        app.refresh_table_entries();
+        app.refresh_archived();
         app.refresh_trash();
+        app.refresh_sent();
+        app.refresh_notes();
 
         if !app.account_manager.loaded_keys.is_empty() {
             app.update_gift_wrap_subscription();
@@ -210,35 +481,179 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
         }
 
         app.refresh_drafts();
+        app.notifications.load(&app.db);
+        app.restore_session();
+        app.payments.load(&app.db, &ctx);
 
         app.status = HootStatus::Ready;
         info!("Hoot Ready");
     }
 
     app.relays.keepalive(wake_up);
-    try_recv_relay_message(app);
+    app.ingest.sync_keys(&app.account_manager.loaded_keys);
+    try_recv_relay_message(app, &ctx);
+    for error in app.relays.take_errors() {
+        warn!("{}", error);
+        match &error {
+            relay::RelayError::Auth { url } => ui::notifications::relay_auth(app, url),
+            // Measure its clock again, ours may have moved.
+            relay::RelayError::Misdated { url, .. } => app.relay_info.refetch(url),
+            _ => {}
+        }
+        app.toasts.error(error.to_string());
+    }
+    let confirmations = app.relays.take_confirmations();
+    // Relays that wouldn't say leave it unknown rather than dropped.
+    for (wrap_id, confirmed) in &confirmations {
+        let Some(confirmed) = confirmed else {
+            continue;
+        };
+        if let Err(e) = app.db.record_sent_confirmation(wrap_id, *confirmed) {
+            error!("Failed to record whether relays kept {}: {}", wrap_id, e);
+        }
+    }
+    if !confirmations.is_empty() {
+        app.refresh_sent();
+    }
+    process_ingested(app, &ctx);
     app.contacts_manager.process_image_queue(&ctx);
+    app.downloads.process_queue(&ctx);
+    app.uploads.process_queue(&ctx);
+    app.relay_presets.process_queue(&ctx);
+    app.relay_info.request_missing(&app.relays);
+    if let Some(offset) = app.relay_info.process_queue(&mut app.relays) {
+        if clock::is_badly_wrong() {
+            warn!("The system clock is {}", clock::describe(offset));
+            app.toasts.error(format!(
+                "Your computer's clock is {}. Hoot dates messages by the relays' clock \
+                 meanwhile, but please set it right.",
+                clock::describe(offset)
+            ));
+        }
+    }
+    for paid in app.payments.process_queue(&ctx) {
+        match paid.purpose {
+            payments::Purpose::RelayFee(url) => app.relay_info.mark_paid(&mut app.relays, &url),
+            payments::Purpose::Upload { id, keys } => {
+                app.uploads.retry_paid(&keys, id, paid.preimage)
+            }
+            payments::Purpose::Zap(message) => app.zaps.paid(&message),
+            payments::Purpose::Invoice { message, invoice } => {
+                info!("Paid the invoice in {}", message);
+                if let Err(e) = app
+                    .db
+                    .record_paid_invoice(&message, &invoice, &paid.preimage)
+                {
+                    error!(
+                        "Failed to remember paying the invoice in {}: {}",
+                        message, e
+                    );
+                }
+                app.paid_invoices.insert(message, invoice);
+            }
+        }
+    }
+    app.zaps.process_queue(&mut app.payments, &ctx);
+    // Receipts are checked against the lightning address in the profile of the account zapped.
+    let addresses: HashMap<String, Option<String>> = app
+        .zaps
+        .unchecked_recipients()
+        .into_iter()
+        .filter_map(
+            |recipient| match get_profile_metadata(app, recipient.clone()) {
+                ProfileOption::Some(metadata) => Some((recipient, metadata.lud16.clone())),
+                ProfileOption::Waiting => None,
+            },
+        )
+        .collect();
+    app.zaps.check_receipts(&app.db, &addresses, &ctx);
+    app.translator.process_queue(&app.db, &ctx);
+    app.automations.process_queue(&ctx);
+    app.mail_merge.process_queue(&mut app.relays, &ctx);
+    app.process_pending_read();
+    app.flag_publisher.process(
+        &app.db,
+        &app.account_manager.loaded_keys,
+        &mut app.relays,
+        &ctx,
+    );
+    if app.janitor.due(&ctx) {
+        app.run_retention(false);
+    }
+    app.process_db_responses();
+    app.process_actions();
+    process_commands(app);
+}
+
+/// Carries out what we were started with or handed by later launches, once there's an
+/// account to act as.
+fn process_commands(app: &mut Hoot) {
+    use nostr::ToBech32;
+
+    if let Some(instance) = &app.instance {
+        while let Some(command) = instance.next_command() {
+            app.pending_commands.push(command);
+        }
+    }
+    if app.pending_commands.is_empty() || app.account_manager.loaded_keys.is_empty() {
+        return;
+    }
+
+    for command in std::mem::take(&mut app.pending_commands) {
+        let link = match command {
+            single_instance::Command::Show => continue,
+            single_instance::Command::Compose(to_field) => {
+                open_compose(app, to_field);
+                continue;
+            }
+            single_instance::Command::Open(link) => link,
+        };
+        match nostr_uri::parse(&link) {
+            Ok(nostr_uri::UriAction::Compose(pubkey)) => {
+                open_compose(app, pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_hex()));
+            }
+            Ok(nostr_uri::UriAction::OpenThread(event_id)) => {
+                match app.db.has_event(&event_id.to_hex()) {
+                    Ok(true) => {
+                        app.focused_post = event_id.to_hex();
+                        app.page = Page::Post;
+                        app.show_trashed_post = false;
+                    }
+                    Ok(false) => warn!("Can't open {}, we don't have that message", link),
+                    Err(e) => error!("Failed to look up {}: {}", link, e),
+                }
+            }
+            Err(e) => warn!("Couldn't open {}: {}", link, e),
+        }
+    }
 }
 
 fn process_message(app: &mut Hoot, msg: &relay::RelayMessage) {
     use relay::RelayMessage::*;
     match msg {
-        Event(sub_id, event) => process_event(app, sub_id, event),
+        Event(_, event) => app.ingest.submit(event.to_string()),
         Notice(msg) => debug!("Relay notice: {}", msg),
         OK(result) => debug!("Command result: {:?}", result),
         Eose(sub_id) => debug!("End of stored events for subscription {}", sub_id),
         Closed(sub_id, msg) => debug!("Subscription {} closed: {}", sub_id, msg),
+        Count(sub_id, count) => app.record_relay_count(sub_id, *count),
+        // The relay pool reports these as errors.
+        Auth(_) => debug!("Relay asked us to authenticate"),
+        // The relay pool handles reconciliation itself.
+        NegMsg(sub_id, _) | NegErr(sub_id, _) => debug!("Ignoring sync message for {}", sub_id),
     }
 }
 
+/// Deletes `event_ids` and their gift wraps. Returns whether any went, for the caller to
+/// refresh the views, see `refresh_after_deletions`.
 fn apply_deletions(
     app: &mut Hoot,
     event_ids: Vec<String>,
     author_pubkey: Option<&str>,
     source_event_id: Option<&str>,
-) -> Result<(), anyhow::Error> {
+) -> Result<bool, anyhow::Error> {
     if event_ids.is_empty() {
-        return Ok(());
+        return Ok(false);
     }
 
     let mut scoped_event_ids: Vec<String> = Vec::new();
@@ -274,7 +689,7 @@ fn apply_deletions(
     apply_event_ids.extend(unscoped_event_ids.iter().cloned());
 
     if apply_event_ids.is_empty() {
-        return Ok(());
+        return Ok(false);
     }
 
     if !scoped_event_ids.is_empty() {
@@ -314,59 +729,447 @@ fn apply_deletions(
             app.focused_post.clear();
             app.show_trashed_post = false;
         }
-        match app.db.get_top_level_messages() {
-            Ok(msgs) => app.table_entries = msgs,
-            Err(e) => error!("Could not fetch table entries to display from DB: {}", e),
+    }
+    Ok(!removed_ids.is_empty())
+}
+
+/// Reloads the folders deleted messages may have been in.
+fn refresh_after_deletions(app: &mut Hoot) {
+    app.refresh_table_entries();
+    app.refresh_trash();
+    app.refresh_sent();
+}
+
+/// Opens an empty compose window addressed to `to_field`.
+fn open_compose(app: &mut Hoot, to_field: String) {
+    let state = ComposeWindowState {
+        to_field,
+        ..Default::default()
+    };
+    ComposeWindow::open(app, state);
+}
+
+/// A thread participant's avatar. Clicking it offers a new message to them, or to their email
+/// `address` when they wrote through the bridge.
+fn participant_avatar(
+    app: &mut Hoot,
+    ui: &mut egui::Ui,
+    pubkey: &nostr::PublicKey,
+    name: &str,
+    address: Option<&str>,
+) {
+    use nostr::ToBech32;
+
+    let hex = pubkey.to_hex();
+    if let Some(ProfileOption::Some(meta)) = app.profile_metadata.get(&hex) {
+        if let Some(url) = meta.picture.as_deref().filter(|url| !url.is_empty()) {
+            app.contacts_manager.request_image(&hex, url);
         }
-        app.refresh_trash();
     }
+    let avatar = style::avatar(ui, app.contacts_manager.get_contact_image(&hex), name)
+        .interact(egui::Sense::click())
+        .on_hover_cursor(egui::CursorIcon::PointingHand);
+    let popup_id = ui.make_persistent_id(("participant_menu", pubkey));
+    if avatar.clicked() {
+        ui.memory_mut(|memory| memory.toggle_popup(popup_id));
+    }
+    egui::popup_below_widget(ui, popup_id, &avatar, |ui| {
+        ui.set_min_width(160.0);
+        if ui.button(format!("✉ New message to {}", name)).clicked() {
+            let to_field = match address {
+                Some(address) => address.to_string(),
+                None => pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_hex()),
+            };
+            open_compose(app, to_field);
+            ui.memory_mut(|memory| memory.close_popup());
+        }
+    });
+}
+
+/// Recipients on one row of a message's header, each with the avatar menu, then the email
+/// addresses it went to through the bridge.
+fn participant_list(
+    app: &mut Hoot,
+    ui: &mut egui::Ui,
+    pubkeys: &[nostr::PublicKey],
+    addresses: &[String],
+) {
+    ui.horizontal_wrapped(|ui| {
+        for pubkey in pubkeys {
+            let hex = pubkey.to_hex();
+            let _ = get_profile_metadata(app, hex.clone());
+            let name = app.display_name(&hex);
+            participant_avatar(app, ui, pubkey, &name, None);
+            ui.label(name);
+            ui.add_space(6.0);
+        }
+        for address in addresses {
+            ui.label(address);
+            ui.add_space(6.0);
+        }
+    });
+}
+
+/// Moves mail from `sender` to the other inbox tab, correcting the ranking for them.
+fn focus_button(app: &mut Hoot, ui: &mut egui::Ui, sender: &str) {
+    let focused = app.focused_senders.contains(sender);
+    let label = if focused {
+        "Move to Other"
+    } else {
+        "Move to Focused"
+    };
+    if ui
+        .button(label)
+        .on_hover_text("For every message from this sender, now and later")
+        .clicked()
+    {
+        if let Err(e) = app.db.correct_sender_focus(sender, !focused) {
+            error!("Failed to move mail from {}: {}", sender, e);
+        }
+        app.refresh_focused_senders();
+        app.refresh_table_entries();
+    }
+}
+
+/// Opens a compose window replying to `message` with `to_field` as the recipients.
+fn open_reply(app: &mut Hoot, message: &mail_event::MailMessage, to_field: String) {
+    let Some(event_id) = message.id else {
+        return;
+    };
+    let mut parent_events: Vec<EventId> = message.parent_events.clone().unwrap_or_default();
+    parent_events.push(event_id);
+    // With text selected in the message, the reply quotes just that.
+    let quote = app
+        .state
+        .selection
+        .take()
+        .filter(|selection| selection.event_id == event_id);
+    let content = quote
+        .as_ref()
+        .and_then(|quote| quote.text(&message.content))
+        .map(ui::quote::quote_lines)
+        .unwrap_or_default();
+    let state = ComposeWindowState {
+        subject: format!("Re: {}", message.subject),
+        to_field,
+        content,
+        parent_events,
+        quote,
+        ..Default::default()
+    };
+    ComposeWindow::open(app, state);
+}
+
+/// The To fields for replying to `message` and for replying to everyone on it.
+fn reply_fields(app: &Hoot, message: &mail_event::MailMessage) -> (String, String) {
+    let reply_to = app
+        .bridge
+        .reply_address(message)
+        .or_else(|| message.author.map(|author| author.to_string()))
+        .unwrap_or_default();
+    // Everyone else on the message, leaving out our own accounts and the gateway standing in for
+    // email addresses.
+    let skip_gateway = if message.email_to.is_empty() {
+        None
+    } else {
+        app.bridge.gateway()
+    };
+    let mut reply_all = vec![reply_to.clone()];
+    let others = message
+        .to
+        .iter()
+        .chain(&message.cc)
+        .filter(|pk| Some(**pk) != skip_gateway)
+        .filter(|pk| {
+            !app.account_manager
+                .loaded_keys
+                .iter()
+                .any(|k| k.public_key() == **pk)
+        })
+        .map(|pk| pk.to_string())
+        .chain(
+            message
+                .email_to
+                .iter()
+                .filter(|a| **a != app.bridge.address)
+                .cloned(),
+        );
+    for recipient in others {
+        if !reply_all.contains(&recipient) {
+            reply_all.push(recipient);
+        }
+    }
+    let reply_all = reply_all.join(", ");
+    (reply_to, reply_all)
+}
+
+/// Opens a compose window carrying on with `draft`.
+fn open_draft(app: &mut Hoot, draft: db::Draft) {
+    let parent_events: Vec<EventId> = draft
+        .parent_events
+        .iter()
+        .filter_map(|s| EventId::parse(s).ok())
+        .collect();
+    let selected_account = draft.selected_account.as_ref().and_then(|pk_str| {
+        app.account_manager
+            .loaded_keys
+            .iter()
+            .find(|k| k.public_key().to_string() == *pk_str)
+            .cloned()
+    });
+    let state = ComposeWindowState {
+        subject: draft.subject,
+        to_field: draft.to_field,
+        content: draft.content,
+        parent_events,
+        selected_account,
+        draft_id: Some(draft.id),
+        ..Default::default()
+    };
+    ComposeWindow::open(app, state);
+}
+
+/// Moves a message to the Trash, where it stays as long as the retention rules say.
+fn move_to_trash(app: &mut Hoot, event_id: &str) {
+    let now = chrono::Utc::now().timestamp();
+    let purge_after = app.janitor.policy.purge_after(now);
+    if app.focused_post == event_id {
+        app.page = Page::Inbox;
+        app.focused_post.clear();
+        app.show_trashed_post = false;
+    }
+    app.perform(actions::Action::Trash {
+        event_id: event_id.to_string(),
+        purge_after,
+    });
+}
+
+/// Asks the recipients of a message we sent to delete their copy (NIP-09).
+/// The request is gift wrapped like the message itself so it doesn't reveal who we wrote to.
+fn publish_deletion_request(app: &mut Hoot, event_id: &str) -> Result<(), anyhow::Error> {
+    use anyhow::Context;
+
+    let (_, author) = app
+        .db
+        .get_event_kind_pubkey(event_id)?
+        .context("Message is not in the database")?;
+    let keys = app
+        .account_manager
+        .loaded_keys
+        .iter()
+        .find(|k| k.public_key().to_string() == author)
+        .cloned()
+        .context("Message was not written by one of our accounts")?;
+
+    let id = EventId::parse(event_id)?;
+    for recipient in app.db.get_event_recipients(event_id)? {
+        let recipient = nostr::PublicKey::parse(&recipient)?;
+        let deletion = nostr::EventBuilder::delete([id])
+            .custom_created_at(clock::now())
+            .build(keys.public_key());
+        let wrapped =
+            mail_event::gift_wrap(&keys, &recipient, deletion, app.preferences.wrap_fuzz())?;
+        let payload = serde_json::to_string(&relay::ClientMessage::Event { event: wrapped })?;
+        app.relays
+            .send(ewebsock::WsMessage::Text(payload))
+            .map_err(|e| anyhow::anyhow!("Could not send deletion request: {}", e))?;
+    }
+
     Ok(())
 }
 
-fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
-    #[cfg(feature = "profiling")]
-    puffin::profile_function!();
+/// What a stored event means for the views.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stored {
+    Mail,
+    Note,
+    /// Our copy of mail one of our accounts sent, maybe from another device.
+    Sent,
+    /// Thread flags saved by another install, already applied.
+    Flags,
+    /// A deletion from a message's author, already applied.
+    Deletion,
+}
 
-    let event = match serde_json::from_str::<nostr::Event>(event_json) {
-        Ok(event) => event,
-        Err(_) => {
-            error!("Failed to parse event JSON: {}", event_json);
-            return;
+/// Applies thread flags saved to relays by one of our installs, see `hoot_core::flag_sync`.
+/// App data that isn't ours is ignored.
+fn apply_flags(app: &mut Hoot, event: &nostr::Event) -> Option<Stored> {
+    let (key, flags) = match flag_sync::from_event(&app.account_manager.loaded_keys, event) {
+        Ok(Some(found)) => found,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!("Failed to read thread flags from {}: {}", event.id, e);
+            return None;
         }
     };
+    let created_at = event.created_at.as_u64() as i64;
+    match app.db.apply_thread_flags(&key, created_at, &flags) {
+        Ok(true) => Some(Stored::Flags),
+        Ok(false) => None,
+        Err(e) => {
+            error!("Failed to apply thread flags from {}: {}", event.id, e);
+            None
+        }
+    }
+}
+
+/// Stores events the ingestion workers have verified, for as long as the frame budget allows,
+/// in one transaction, then refreshes the views once for the whole batch.
+fn process_ingested(app: &mut Hoot, ctx: &egui::Context) {
+    let started = std::time::Instant::now();
+    // Relays are only recorded as delivering an event once it's verified.
+    let mut sightings = app.sightings.add(app.relays.take_sightings());
+    let mut stored = Vec::new();
+    let mut batch = Vec::new();
+    // Where mail sits in `batch`, to announce once it's stored.
+    let mut mail = Vec::new();
+    while let Some(prepared) = app.ingest.next_prepared() {
+        let _timer = metrics::start_timer(metrics::INGEST_STORE);
+        let event_id = prepared.id();
+        app.relays.mark_verified(&event_id);
+        let sources = app.sightings.verified(&event_id);
+        let kind = process_event(app, prepared, &sources, &mut batch);
+        sightings.extend(sources.into_iter().map(|url| (event_id.clone(), url)));
+        if kind == Some(Stored::Mail) {
+            mail.push(batch.len() - 1);
+        }
+        stored.extend(kind);
+        if started.elapsed() > INGEST_FRAME_BUDGET {
+            ctx.request_repaint();
+            break;
+        }
+    }
+    if !sightings.is_empty() {
+        if let Err(e) = app.db.record_event_sources(&sightings) {
+            error!("Failed to record which relays sent events: {}", e);
+        }
+    }
+    let mut saved = Vec::new();
+    if !batch.is_empty() {
+        match app.db.store_events(&batch) {
+            Ok(stored) => saved = stored,
+            Err(e) => error!("Failed to store {} events in database: {}", batch.len(), e),
+        }
+        debug!(
+            "Stored {} of {} events in database",
+            saved.iter().filter(|saved| **saved).count(),
+            batch.len()
+        );
+    }
+
+    // Each view is refreshed once for the whole batch. Reloading the inbox takes in the
+    // unread and saved search counts.
+    if stored.contains(&Stored::Flags) {
+        app.refresh_flags();
+    }
+    if stored.contains(&Stored::Deletion) {
+        refresh_after_deletions(app);
+    } else if stored.contains(&Stored::Sent) {
+        app.refresh_sent();
+    }
+    let inbox_reloaded = stored.contains(&Stored::Flags) || stored.contains(&Stored::Deletion);
+    if stored.contains(&Stored::Mail) && !inbox_reloaded {
+        app.refresh_unread_counts();
+        app.refresh_saved_search_counts();
+    }
+    if stored.contains(&Stored::Note) {
+        app.refresh_notes();
+    }
+
+    let touched: HashSet<String> = batch
+        .iter()
+        .zip(&saved)
+        .filter(|(_, saved)| **saved)
+        .flat_map(|(item, _)| thread_links(item))
+        .collect();
+    app.refresh_open_threads(&touched);
+    for index in mail {
+        if saved.get(index).copied().unwrap_or(false) {
+            announce_mail(app, &batch[index]);
+        }
+    }
+}
 
-    if event.verify().is_err() {
-        error!("Event verification failed for event: {}", event.id);
+/// Tells the user and their hooks about mail that was just stored.
+fn announce_mail(app: &mut Hoot, item: &db::EventToStore) {
+    let Some(unwrapped) = &item.unwrapped else {
+        return;
+    };
+    let mut rumor = unwrapped.rumor.clone();
+    rumor.ensure_id();
+    let Some(rumor_id) = rumor.id.map(|id| id.to_hex()) else {
+        return;
+    };
+    if rumor.kind != Kind::Custom(MAIL_EVENT_KIND) {
         return;
     }
-    debug!("Verified event: {:?}", event);
+    let account = item.recipient.as_deref();
+    ui::notifications::check_mail(app, &rumor, &rumor_id, account);
+    automation::check_mail(app, &rumor, &rumor_id, account);
+}
+
+/// The ids a stored event ties into threads by: its own, and those of the messages it replies
+/// to.
+fn thread_links(item: &db::EventToStore) -> Vec<String> {
+    let (id, tags) = match &item.unwrapped {
+        Some(unwrapped) => {
+            let mut rumor = unwrapped.rumor.clone();
+            rumor.ensure_id();
+            (rumor.id, &unwrapped.rumor.tags)
+        }
+        None => (Some(item.event.id), &item.event.tags),
+    };
+    id.into_iter()
+        .chain(tags.event_ids().copied())
+        .map(|id| id.to_hex())
+        .collect()
+}
+
+/// Handles one verified event. Deletions and profiles take effect right away, anything to keep
+/// goes into `batch` for `process_ingested` to store. `sources` are the relays that delivered
+/// it.
+fn process_event(
+    app: &mut Hoot,
+    prepared: ingest::Prepared,
+    sources: &[String],
+    batch: &mut Vec<db::EventToStore>,
+) -> Option<Stored> {
+    #[cfg(feature = "profiling")]
+    puffin::profile_function!();
+
+    let (event, unwrapped) = match prepared {
+        ingest::Prepared::Event(event) => (event, None),
+        ingest::Prepared::GiftWrap { wrap, unwrapped } => (wrap, Some(unwrapped)),
+    };
 
     if event.kind == Kind::EventDeletion {
         let event_ids: Vec<String> = event.tags.event_ids().map(|id| id.to_hex()).collect();
         if !event_ids.is_empty() {
             let author_pubkey = event.pubkey.to_string();
             let deletion_id = event.id.to_string();
-            if let Err(e) = apply_deletions(
+            match apply_deletions(
                 app,
                 event_ids,
                 Some(author_pubkey.as_str()),
                 Some(deletion_id.as_str()),
             ) {
-                error!("Failed to apply deletion event {}: {}", event.id, e);
+                Ok(true) => return Some(Stored::Deletion),
+                Ok(false) => {}
+                Err(e) => error!("Failed to apply deletion event {}: {}", event.id, e),
             }
         }
-        return;
+        return None;
     }
 
     let event_id = event.id.to_string();
     let event_author = event.pubkey.to_string();
     if let Ok(true) = app.db.is_deleted(&event_id, Some(event_author.as_str())) {
         debug!("Skipping deleted event: {}", event.id);
-        return;
+        return None;
     }
     if let Ok(true) = app.db.is_trashed(&event_id) {
         debug!("Skipping trashed event: {}", event.id);
-        return;
+        return None;
     }
 
     if event.kind == Kind::Metadata {
@@ -377,13 +1180,14 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
                 Ok(meta) => meta,
                 Err(e) => {
                     error!("Invalid metadata event {}: {}", event.id, e);
-                    return;
+                    return None;
                 }
             };
         app.profile_metadata.insert(
             event.pubkey.to_string(),
             ProfileOption::Some(deserialized_metadata.clone()),
         );
+        app.names.forget(&event.pubkey.to_string());
         app.contacts_manager
             .upsert_metadata(event.pubkey.to_string(), deserialized_metadata.clone());
         // TODO: evaluate perf cost of clone LOL
@@ -392,30 +1196,103 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
             }
             Err(e) => error!("Error when saving profile metadata to DB: {}", e),
         }
-        return;
+        return None;
+    }
+
+    if event.kind == Kind::ApplicationSpecificData {
+        return apply_flags(app, &event);
+    }
+
+    if event.kind == Kind::ZapReceipt {
+        app.zaps.receive(&event, &app.account_manager.loaded_keys);
+        return None;
+    }
+
+    if relay::RelayHints::is_relay_list(&event) {
+        app.relay_hints.learn(&event);
+        return None;
     }
 
-    if event.kind == Kind::GiftWrap {
+    if event.kind == Kind::LongFormTextNote {
+        app.articles.receive(&event);
+        return None;
+    }
+
+    // Gift wraps are signed with throwaway keys, so those get checked once unwrapped.
+    if app.is_blocked(&event_author) {
+        debug!("Skipping event {} from blocked pubkey", event.id);
+        return None;
+    }
+
+    if let Some(unwrapped) = unwrapped {
         if let Ok(true) = app.db.gift_wrap_exists(&event.id.to_string()) {
             debug!("Skipping already stored gift wrap: {}", event.id);
-            return;
+            return None;
         }
         if let Ok(true) = app.db.is_deleted(&event.id.to_string(), None) {
             debug!("Skipping deleted gift wrap: {}", event.id);
-            return;
+            return None;
         }
-        match app.account_manager.unwrap_gift_wrap(&event) {
+        match unwrapped {
             Ok(unwrapped) => {
                 if unwrapped.sender != unwrapped.rumor.pubkey {
                     warn!("Gift wrap seal signer mismatch for event {}", event.id);
-                    return;
+                    return None;
+                }
+                if app.only_distrusted(sources.iter().map(String::as_str)) {
+                    let rumor = &unwrapped.rumor;
+                    if let Err(e) = relay::check_distrusted_wrap(&event, rumor, clock::now()) {
+                        warn!("Distrusted gift wrap {} failed checks: {}", event.id, e);
+                        return None;
+                    }
+                }
+                if app.is_blocked(&unwrapped.rumor.pubkey.to_string()) {
+                    debug!("Skipping gift wrap {} from blocked pubkey", event.id);
+                    return None;
+                }
+
+                if unwrapped.rumor.kind == Kind::EventDeletion {
+                    let event_ids: Vec<String> = unwrapped
+                        .rumor
+                        .tags
+                        .event_ids()
+                        .map(|id| id.to_hex())
+                        .collect();
+                    let author_pubkey = unwrapped.rumor.pubkey.to_string();
+                    return match apply_deletions(
+                        app,
+                        event_ids,
+                        Some(author_pubkey.as_str()),
+                        Some(event.id.to_string().as_str()),
+                    ) {
+                        Ok(true) => Some(Stored::Deletion),
+                        Ok(false) => None,
+                        Err(e) => {
+                            error!("Failed to apply wrapped deletion {}: {}", event.id, e);
+                            None
+                        }
+                    };
                 }
 
                 let mut rumor = unwrapped.rumor.clone();
                 rumor.ensure_id();
                 if let Err(e) = rumor.verify_id() {
                     error!("Invalid rumor id for gift wrap {}: {}", event.id, e);
-                    return;
+                    return None;
+                }
+                // Anyone can put a relay next to a pubkey, so only people we know get to
+                // tell us where others read.
+                let trusted_author = app
+                    .contacts_manager
+                    .find_contact(&rumor.pubkey.to_hex())
+                    .is_some()
+                    || app
+                        .account_manager
+                        .loaded_keys
+                        .iter()
+                        .any(|keys| keys.public_key() == rumor.pubkey);
+                if trusted_author {
+                    app.relay_hints.learn_hints(&rumor.tags);
                 }
                 let rumor_id = rumor
                     .id
@@ -429,7 +1306,7 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
                     ) {
                         error!("Failed to record gift wrap deletion {}: {}", event.id, e);
                     }
-                    return;
+                    return None;
                 }
                 if let Ok(true) = app.db.is_trashed(&rumor_id) {
                     let recipient = event
@@ -442,10 +1319,11 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
                         &rumor_id,
                         recipient.as_deref(),
                         event.created_at.as_u64() as i64,
+                        Some(encryption::Encryption::of_payload(&event.content)),
                     ) {
                         error!("Failed to save gift wrap map for trashed rumor: {}", e);
                     }
-                    return;
+                    return None;
                 }
 
                 let recipient = event
@@ -455,35 +1333,43 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event_json: &str) {
                     .map(|val| val.to_string());
 
                 app.events.push(event.clone());
+                if rumor.kind == Kind::Custom(MAIL_EVENT_KIND) {
+                    ui::verification::check_answer(app, &rumor);
+                }
 
-                if let Err(e) = app
-                    .db
-                    .store_event(&event, Some(&unwrapped), recipient.as_deref())
-                {
-                    error!("Failed to store event in database: {}", e);
+                let to_author = rumor
+                    .tags
+                    .filter(TagKind::p())
+                    .any(|tag| tag.content() == Some(author_pubkey.as_str()));
+                let stored = if recipient.as_deref() != Some(author_pubkey.as_str()) {
+                    Stored::Mail
+                } else if to_author {
+                    Stored::Note
                 } else {
-                    debug!("Successfully stored event with id {} in database", event.id);
-                }
+                    Stored::Sent
+                };
+                batch.push(db::EventToStore {
+                    event,
+                    unwrapped: Some(unwrapped),
+                    recipient,
+                });
+                return Some(stored);
             }
             Err(e) => {
                 error!("Failed to unwrap gift wrap {}: {}", event.id, e);
             }
         }
-        return;
+        return None;
     }
 
     if let Ok(true) = app.db.has_event(&event.id.to_string()) {
         debug!("Skipping already stored event: {}", event.id);
-        return;
+        return None;
     }
 
     app.events.push(event.clone());
-
-    if let Err(e) = app.db.store_event(&event, None, None) {
-        error!("Failed to store event in database: {}", e);
-    } else {
-        debug!("Successfully stored event with id {} in database", event.id);
-    }
+    batch.push(event.into());
+    None
 }
 
 fn get_account_display_text(app: &Hoot) -> String {
@@ -496,25 +1382,16 @@ fn get_account_display_text(app: &Hoot) -> String {
 
 fn get_key_display_text(app: &Hoot, key: &nostr::Keys) -> String {
     let pubkey = key.public_key().to_string();
-    if let Some(name) = app.resolve_name(&pubkey) {
-        return name;
-    }
-    // Fallback: truncated npub
-    use nostr::ToBech32;
-    let npub = key
-        .public_key()
-        .to_bech32()
-        .unwrap_or_else(|_| pubkey.clone());
-    if npub.len() > 16 {
-        format!("{}...", &npub[..16])
-    } else {
-        npub
-    }
+    app.resolve_name(&pubkey)
+        .unwrap_or_else(|| name_resolver::short_npub(&pubkey))
 }
 
 fn render_nav_item(ui: &mut egui::Ui, label: &str, is_selected: bool) -> egui::Response {
     let desired_size = egui::vec2(ui.available_width(), 30.0);
     let (rect, response) = ui.allocate_exact_size(desired_size, Sense::click());
+    response.widget_info(|| {
+        egui::WidgetInfo::selected(egui::WidgetType::SelectableLabel, is_selected, label)
+    });
 
     if is_selected {
         ui.painter()
@@ -574,29 +1451,63 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
                     )
                     .clicked()
                 {
-                    let state = ui::compose_window::ComposeWindowState {
-                        subject: String::new(),
-                        to_field: String::new(),
-                        content: String::new(),
-                        parent_events: Vec::new(),
-                        selected_account: None,
-                        minimized: false,
-                        draft_id: None,
-                    };
-                    app.state
-                        .compose_window
-                        .insert(egui::Id::new(rand::random::<u32>()), state);
+                    ComposeWindow::open(app, ComposeWindowState::default());
                 }
 
                 ui.add_space(16.0);
 
-                // Navigation items
+                // Mailboxes: every account together, then each account's own inbox
+                let unified_text = if app.unread_total > 0 {
+                    format!("📥 Unified Inbox {}", app.unread_total)
+                } else {
+                    "📥 Unified Inbox".to_string()
+                };
+                let unified_selected =
+                    app.page == Page::Inbox && app.mailbox.is_none() && app.active_search.is_none();
+                if render_nav_item(ui, &unified_text, unified_selected).clicked() {
+                    app.select_mailbox(None);
+                    app.page = Page::Inbox;
+                }
+                for key in app.account_manager.loaded_keys.clone() {
+                    let pubkey = key.public_key().to_hex();
+                    let name = get_key_display_text(app, &key);
+                    let mut text = match app.unread_counts.get(&pubkey) {
+                        Some(count) if *count > 0 => format!("      {} {}", name, count),
+                        _ => format!("      {}", name),
+                    };
+                    let progress = app.sync_progress(&pubkey);
+                    if let Some((stored, total)) = progress {
+                        text.push_str(&format!(" 🔄 {}/{}", stored, total));
+                    }
+                    let is_selected = app.page == Page::Inbox
+                        && app.mailbox.as_ref() == Some(&pubkey)
+                        && app.active_search.is_none();
+                    let mut response = render_nav_item(ui, &text, is_selected);
+                    let dot = response.rect.left_center() + egui::vec2(20.0, 0.0);
+                    ui.painter()
+                        .circle_filled(dot, 4.0, app.account_color(&pubkey));
+                    if let Some((stored, total)) = progress {
+                        response = response.on_hover_text(format!(
+                            "Syncing: {} of the {} messages relays have for this account",
+                            stored, total
+                        ));
+                    }
+                    if response.clicked() {
+                        app.select_mailbox(Some(pubkey));
+                        app.page = Page::Inbox;
+                    }
+                }
+
+                ui.add_space(4.0);
+
+                // Folders of the selected mailbox
                 let nav_items: Vec<(&str, Page, usize)> = vec![
-                    ("📥 Inbox", Page::Inbox, app.events.len()),
                     ("📝 Drafts", Page::Drafts, app.drafts.len()),
+                    ("📤 Sent", Page::Sent, app.sending_count()),
                     ("⭐ Starred", Page::Starred, 0),
-                    ("📁 Archived", Page::Archived, 0),
+                    ("📁 Archived", Page::Archived, app.archived_entries.len()),
                     ("🗑 Trash", Page::Trash, app.trash_entries.len()),
+                    ("🗒 Notes", Page::Notes, app.notes_entries.len()),
                 ];
 
                 for (label, page, count) in &nav_items {
@@ -607,10 +1518,41 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
                     };
                     let is_selected = app.page == *page;
                     if render_nav_item(ui, &text, is_selected).clicked() {
+                        if *page == Page::Notes {
+                            app.refresh_notes();
+                        }
                         app.page = page.clone();
                     }
                 }
 
+                // Saved searches
+                if !app.saved_searches.is_empty() {
+                    ui.add_space(4.0);
+                    let mut delete = None;
+                    for search in app.saved_searches.clone() {
+                        let text = match app.saved_search_counts.get(&search.id) {
+                            Some(count) if *count > 0 => format!("🔍 {} {}", search.name, count),
+                            _ => format!("🔍 {}", search.name),
+                        };
+                        let is_selected =
+                            app.page == Page::Inbox && app.active_search == Some(search.id);
+                        let response =
+                            render_nav_item(ui, &text, is_selected).on_hover_text(&search.query);
+                        if response.clicked() {
+                            app.open_saved_search(Some(search.id));
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Delete folder").clicked() {
+                                delete = Some(search.id);
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                    if let Some(id) = delete {
+                        app.delete_saved_search(id);
+                    }
+                }
+
                 ui.add_space(4.0);
                 ui.separator();
                 ui.add_space(4.0);
@@ -620,9 +1562,20 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
                     app.page = Page::Contacts;
                 }
 
-                ui.add_space(8.0);
-
-                // Show onboarding for first-time users, or Add Account button for existing users
+                let unread = app.notifications.unread();
+                let notifications_text = if unread > 0 {
+                    format!("🔔 Notifications {}", unread)
+                } else {
+                    "🔔 Notifications".to_string()
+                };
+                let is_selected = app.page == Page::Notifications;
+                if render_nav_item(ui, &notifications_text, is_selected).clicked() {
+                    app.page = Page::Notifications;
+                }
+
+                ui.add_space(8.0);
+
+                // Show onboarding for first-time users, or Add Account button for existing users
                 if app.account_manager.loaded_keys.is_empty() {
                     if ui.button("onboarding").clicked() {
                         app.page = Page::OnboardingNewUser;
@@ -657,6 +1610,7 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
                                             == Some(key.public_key());
                                     if ui.selectable_label(is_selected, display_text).clicked() {
                                         app.active_account = Some(key.clone());
+                                        app.refresh_notes();
                                     }
                                 }
                             });
@@ -664,7 +1618,11 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
 
                     ui.add_space(4.0);
 
-                    if ui.add_sized([32.0, 32.0], egui::Button::new("⚙")).clicked() {
+                    let settings_button = ui.add_sized([32.0, 32.0], egui::Button::new("⚙"));
+                    settings_button.widget_info(|| {
+                        egui::WidgetInfo::labeled(egui::WidgetType::Button, "Settings")
+                    });
+                    if settings_button.clicked() {
                         app.page = Page::Settings;
                     }
                 });
@@ -672,6 +1630,112 @@ fn render_left_panel(app: &mut Hoot, ctx: &egui::Context) {
         });
 }
 
+/// A send still missing relay answers after this was cut short, e.g. by quitting.
+const SENT_UNCONFIRMED_AFTER_SECS: i64 = 60;
+
+fn still_sending(sent: &db::SentMessage) -> bool {
+    let age = nostr::Timestamp::now().as_u64() as i64 - sent.entry.created_at;
+    sent.pending > 0 && age < SENT_UNCONFIRMED_AFTER_SECS
+}
+
+/// How far a sent message got, counting each recipient's copy once.
+fn sent_status(sent: &db::SentMessage) -> RichText {
+    let total = sent.recipients.len();
+    if sent.elsewhere {
+        RichText::new("Sent from another device")
+            .color(style::TEXT_MUTED)
+            .small()
+    } else if still_sending(sent) {
+        RichText::new("Sending…").color(style::TEXT_MUTED).small()
+    } else if sent.pending > 0 {
+        RichText::new("Unconfirmed")
+            .color(style::TEXT_MUTED)
+            .small()
+    } else if sent.delivered == 0 {
+        RichText::new("Not delivered").color(Color32::RED).small()
+    } else if sent.dropped > 0 {
+        // Relays said OK, then didn't have it when asked.
+        RichText::new(format!("Dropped for {} of {}", sent.dropped, total))
+            .color(Color32::RED)
+            .small()
+    } else if sent.confirmed == total {
+        RichText::new("Stored").color(style::TEXT_MUTED).small()
+    } else if sent.delivered == total {
+        RichText::new("Delivered").color(style::TEXT_MUTED).small()
+    } else {
+        RichText::new(format!("Delivered to {} of {}", sent.delivered, total))
+            .color(Color32::RED)
+            .small()
+    }
+}
+
+/// One cell of a message list row.
+fn inbox_cell(
+    app: &mut Hoot,
+    ui: &mut egui::Ui,
+    column: InboxColumn,
+    event: &TableEntry,
+    sender: &str,
+) {
+    match column {
+        InboxColumn::Select => {
+            ui.checkbox(&mut false, "").widget_info(|| {
+                egui::WidgetInfo::selected(egui::WidgetType::Checkbox, false, "Select message")
+            });
+        }
+        InboxColumn::Star => {
+            let mut starred = app.starred_ids.contains(&event.id);
+            let star = ui.checkbox(&mut starred, "");
+            star.widget_info(|| {
+                egui::WidgetInfo::selected(egui::WidgetType::Checkbox, starred, "Star message")
+            });
+            if star.changed() {
+                app.toggle_star(&event.id);
+            }
+        }
+        InboxColumn::Avatar => {
+            if let Some(ProfileOption::Some(meta)) = app.profile_metadata.get(&event.pubkey) {
+                if let Some(url) = meta.picture.as_deref().filter(|url| !url.is_empty()) {
+                    app.contacts_manager.request_image(&event.pubkey, url);
+                }
+            }
+            style::avatar(
+                ui,
+                app.contacts_manager.get_contact_image(&event.pubkey),
+                sender,
+            );
+        }
+        InboxColumn::From => {
+            ui.label(RichText::new(sender).strong());
+        }
+        InboxColumn::Subject => {
+            ui.horizontal(|ui| {
+                ui::thread_subject::subject_label(app, ui, &event.id, &event.subject);
+                ui::thread_notes::note_mark(app, ui, &event.id);
+                if event.thread_count > 1 {
+                    ui.label(
+                        RichText::new(format!("{}", event.thread_count))
+                            .small()
+                            .color(style::TEXT_MUTED),
+                    );
+                }
+            });
+        }
+        InboxColumn::Snippet => {
+            ui.add(
+                egui::Label::new(RichText::new(&event.snippet).color(style::TEXT_MUTED))
+                    .truncate(true),
+            );
+        }
+        InboxColumn::Labels => {
+            ui::labels::label_chips(ui, &event.labels);
+        }
+        InboxColumn::Time => {
+            style::timestamp_label(ui, event.created_at, app.preferences.clock_24h);
+        }
+    }
+}
+
 fn render_app(app: &mut Hoot, ctx: &egui::Context) {
     // Render add account windows, collecting closed ones for removal
     let closed_account_windows: Vec<egui::Id> = app
@@ -695,11 +1759,20 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
         .copied()
         .collect::<Vec<_>>()
         .into_iter()
-        .filter(|&id| !ui::compose_window::ComposeWindow::show_window(app, ctx, id))
+        .filter(|&id| !ComposeWindow::show_window(app, ctx, id))
         .collect();
     for id in closed_compose_windows {
         app.state.compose_window.remove(&id);
     }
+    ui::quick_reply::settle(app, ctx);
+    ui::verification::settle(app, ctx);
+    if app.page != Page::Post {
+        ui::thread_notes::save_draft(app);
+    }
+
+    ui::delete_dialog::DeleteDialog::show_window(app, ctx);
+    ui::report_dialog::ReportDialog::show_window(app, ctx);
+    ui::mail_merge_window::MailMergeWindow::show_window(app, ctx);
 
     match app.page {
         Page::Unlock => {}
@@ -707,10 +1780,18 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
         | Page::OnboardingNewUser
         | Page::OnboardingNewShowKey
         | Page::OnboardingReturning => {}
-        _ => render_left_panel(app, ctx),
+        _ => {
+            render_left_panel(app, ctx);
+            // Keep relative times like "5 min ago" current while nothing else happens, as long
+            // as someone is looking.
+            if app.repaint.is_focused() {
+                app.repaint.after(style::TIMESTAMP_REFRESH);
+            }
+        }
     }
 
-    egui::CentralPanel::default().show(ctx, |ui| {
+    // Thread windows draw their thread with this too.
+    let render_page = |app: &mut Hoot, ui: &mut egui::Ui| {
         match app.page {
             Page::Inbox => {
                 ui.add_space(8.0);
@@ -718,125 +1799,227 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                 // Top bar with search
                 ui.horizontal(|ui| {
                     if ui.button("Refresh").clicked() {
-                        match app.db.get_top_level_messages() {
-                            Ok(msgs) => app.table_entries = msgs,
-                            Err(e) => {
-                                error!("Could not fetch table entries to display from DB: {}", e)
-                            }
-                        }
+                        app.refresh_table_entries();
                     }
                     ui.add_space(16.0);
                     let search_width = ui.available_width() - 100.0;
-                    ui.add_sized(
+                    let search = ui.add_sized(
                         [search_width, 32.0],
-                        egui::TextEdit::singleline(&mut String::new())
+                        egui::TextEdit::singleline(&mut app.inbox_filter.query)
                             .hint_text("Search")
                             .margin(egui::vec2(8.0, 4.0)),
                     );
+                    search.widget_info(|| {
+                        egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Search messages")
+                    });
+                    if search.changed() {
+                        app.active_search = None;
+                        app.refresh_table_entries();
+                    }
+                    let can_save =
+                        !app.inbox_filter.query.trim().is_empty() && app.active_search.is_none();
+                    if ui
+                        .add_enabled(can_save, egui::Button::new("💾"))
+                        .on_hover_text("Save this search as a folder")
+                        .clicked()
+                    {
+                        app.state.new_search_name = Some(String::new());
+                    }
+                });
+
+                let mut save_as = None;
+                if let Some(name) = app.state.new_search_name.as_mut() {
+                    ui.horizontal(|ui| {
+                        let name_label = ui.label("Folder name:");
+                        let field = ui.text_edit_singleline(name).labelled_by(name_label.id);
+                        let entered =
+                            field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        let valid = !name.trim().is_empty();
+                        if ui.add_enabled(valid, egui::Button::new("Save")).clicked()
+                            || (entered && valid)
+                        {
+                            save_as = Some(Some(name.trim().to_string()));
+                        }
+                        if ui.button("Cancel").clicked() {
+                            save_as = Some(None);
+                        }
+                    });
+                }
+                if let Some(name) = save_as {
+                    app.state.new_search_name = None;
+                    if let Some(name) = name {
+                        app.save_search(&name);
+                    }
+                }
+
+                if app.inbox_filter.focused.is_some() {
+                    ui.add_space(4.0);
+                    ui.horizontal(|ui| {
+                        let mut focused = app.inbox_filter.focused;
+                        ui.selectable_value(&mut focused, Some(true), "Focused");
+                        ui.selectable_value(&mut focused, Some(false), "Other");
+                        if focused != app.inbox_filter.focused {
+                            app.inbox_filter.focused = focused;
+                            app.refresh_table_entries();
+                        }
+                    });
+                }
+
+                // Filter chips
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    let filter = &mut app.inbox_filter;
+                    let chips = [
+                        ("Unread", &mut filter.unread),
+                        ("⭐ Starred", &mut filter.starred),
+                        ("📎 Has attachment", &mut filter.has_attachment),
+                        ("👤 From contacts", &mut filter.from_contacts),
+                    ];
+                    let mut changed = false;
+                    for (label, enabled) in chips {
+                        changed |= ui.toggle_value(enabled, label).changed();
+                    }
+                    if changed {
+                        app.active_search = None;
+                        app.refresh_table_entries();
+                    }
                 });
 
                 ui.add_space(4.0);
                 ui.separator();
                 ui.add_space(4.0);
 
-                if app.table_entries.is_empty() {
+                if app.inbox.is_empty() {
+                    let unfiltered = db::MessageFilter {
+                        focused: app.inbox_filter.focused,
+                        ..Default::default()
+                    };
+                    let empty_text = if app.inbox_filter == unfiltered {
+                        "No messages yet"
+                    } else {
+                        "No messages match these filters"
+                    };
                     ui.add_space(40.0);
                     ui.vertical_centered(|ui| {
                         ui.label(
-                            RichText::new("No messages yet")
+                            RichText::new(empty_text)
                                 .size(16.0)
                                 .color(style::TEXT_MUTED),
                         );
                     });
                 } else {
-                    // Email list using TableBuilder
-                    TableBuilder::new(ui)
-                        .column(Column::auto()) // Checkbox
-                        .column(Column::auto()) // Star
-                        .column(Column::initial(160.0).at_least(100.0)) // Sender
-                        .column(Column::remainder()) // Subject
-                        .column(Column::initial(100.0).at_least(70.0)) // Time
+                    #[cfg(feature = "profiling")]
+                    puffin::profile_scope!("inbox_table");
+                    // Email list using TableBuilder, with the columns the user picked.
+                    let columns = app.inbox_columns.table_columns();
+                    let mut visible: Option<std::ops::Range<usize>> = None;
+                    let mut table = TableBuilder::new(ui);
+                    for (_, width) in &columns {
+                        table = table.column(*width);
+                    }
+                    table
                         .striped(true)
                         .sense(Sense::click())
                         .auto_shrink(Vec2b { x: false, y: false })
                         .header(28.0, |mut header| {
-                            header.col(|ui| {
-                                ui.checkbox(&mut false, "");
-                            });
-                            header.col(|ui| {
-                                ui.label(RichText::new("⭐").size(12.0));
-                            });
-                            header.col(|ui| {
-                                ui.label(RichText::new("From").small().color(style::TEXT_MUTED));
-                            });
-                            header.col(|ui| {
-                                ui.label(RichText::new("Subject").small().color(style::TEXT_MUTED));
-                            });
-                            header.col(|ui| {
-                                ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
-                            });
+                            for (column, _) in &columns {
+                                header.col(|ui| {
+                                    let title = match column {
+                                        InboxColumn::Select => {
+                                            ui.checkbox(&mut false, "");
+                                            return;
+                                        }
+                                        InboxColumn::Star => {
+                                            ui.label(RichText::new("⭐").size(12.0));
+                                            return;
+                                        }
+                                        InboxColumn::Avatar => return,
+                                        column => column.title(),
+                                    };
+                                    ui.label(RichText::new(title).small().color(style::TEXT_MUTED));
+                                });
+                            }
                         })
                         .body(|body| {
-                            let events: Vec<TableEntry> = app.table_entries.to_vec();
-                            body.rows(style::INBOX_ROW_HEIGHT, events.len(), |mut row| {
-                                let event = &events[row.index()];
+                            // Mark whose each conversation is when accounts share the list.
+                            let show_accounts =
+                                app.mailbox.is_none() && app.account_manager.loaded_keys.len() > 1;
+                            let total = app.inbox.total();
+                            body.rows(style::INBOX_ROW_HEIGHT, total, |mut row| {
+                                let index = row.index();
+                                visible = Some(visible.clone().map_or(index..index + 1, |v| {
+                                    v.start.min(index)..v.end.max(index + 1)
+                                }));
+                                let Some(event) = app.inbox.get(index).cloned() else {
+                                    row.col(|ui| {
+                                        ui.label(
+                                            RichText::new("Loading…").color(style::TEXT_MUTED),
+                                        );
+                                    });
+                                    return;
+                                };
+                                let event = &event;
+                                let _ = get_profile_metadata(app, event.pubkey.clone());
+                                let sender = app.display_name(&event.pubkey);
+                                let accent = show_accounts
+                                    .then(|| app.thread_account(&event.id))
+                                    .flatten()
+                                    .map(|account| app.account_color(&account));
 
-                                row.col(|ui| {
-                                    ui.checkbox(&mut false, "");
-                                });
-                                row.col(|ui| {
-                                    ui.checkbox(&mut false, "");
-                                });
-                                row.col(|ui| {
-                                    let _ = get_profile_metadata(app, event.pubkey.clone());
-                                    let label = app
-                                        .resolve_name(&event.pubkey)
-                                        .unwrap_or_else(|| event.pubkey.to_string());
-                                    ui.label(RichText::new(label).strong());
-                                });
-                                row.col(|ui| {
-                                    ui.horizontal(|ui| {
-                                        ui.label(&event.subject);
-                                        if event.thread_count > 1 {
-                                            ui.label(
-                                                RichText::new(format!("{}", event.thread_count))
-                                                    .small()
-                                                    .color(style::TEXT_MUTED),
-                                            );
+                                for (i, (column, _)) in columns.iter().enumerate() {
+                                    row.col(|ui| {
+                                        if let Some(color) = accent.filter(|_| i == 0) {
+                                            style::account_stripe(ui, color);
                                         }
+                                        inbox_cell(app, ui, *column, event, &sender)
                                     });
-                                });
-                                row.col(|ui| {
-                                    ui.label(
-                                        RichText::new(style::format_timestamp(event.created_at))
-                                            .color(style::TEXT_MUTED)
-                                            .small(),
-                                    );
-                                });
+                                }
 
-                                if row.response().clicked() {
+                                let row_response = row.response();
+                                row_response.widget_info(|| {
+                                    egui::WidgetInfo::labeled(
+                                        egui::WidgetType::Button,
+                                        format!(
+                                            "Message from {}, subject {}, {}",
+                                            sender,
+                                            event.subject,
+                                            style::format_timestamp(
+                                                event.created_at,
+                                                app.preferences.clock_24h
+                                            )
+                                        ),
+                                    )
+                                });
+                                if row_response.clicked() {
                                     app.focused_post = event.id.clone();
                                     app.page = Page::Post;
                                     app.show_trashed_post = false;
                                 }
+                                app.hover_preview.row(event, &row_response);
                             });
                         });
+                    if let Some(page) = visible.and_then(|visible| app.inbox.wants(visible)) {
+                        app.load_inbox_page(page);
+                    }
+                    ui::hover_preview::show(app, ui.ctx());
                 } // else (has table entries)
             }
             Page::Contacts => {
                 ui::contacts::render_contacts_page(app, ui);
             }
+            Page::Notifications => {
+                ui::notifications::render_notifications_page(app, ui);
+            }
             Page::Settings => {
                 ui::settings::SettingsScreen::ui(app, ui);
             }
             Page::Post => {
-                let events = if app.show_trashed_post {
-                    app.db.get_email_thread_including_trash(&app.focused_post)
-                } else {
-                    app.db.get_email_thread(&app.focused_post)
-                };
-                let events = match events {
-                    Ok(events) => events,
+                let events = match app.focused_thread() {
+                    Ok(Some(events)) => events,
+                    Ok(None) => {
+                        ui.label(RichText::new("Loading…").color(style::TEXT_MUTED));
+                        return;
+                    }
                     Err(e) => {
                         error!("Failed to load thread for {}: {}", app.focused_post, e);
                         app.page = Page::Inbox;
@@ -845,8 +2028,30 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                     }
                 };
 
+                // Pair each message with the subject it switched to, if it did. When threads are
+                // split on subject changes only the focused conversation is shown.
+                let Some(store) = app.thread_snapshot.as_ref().map(|s| s.store()) else {
+                    return;
+                };
+                let subject_changes = threading::subject_changes(store, &events);
+                let conversation_starts = threading::split_on_subject_change(store, &events);
+                let focused_start = events
+                    .iter()
+                    .position(|ev| ev.id.is_some_and(|id| id.to_hex() == app.focused_post))
+                    .map(|i| conversation_starts[i]);
+                let split_threads = app.split_threads;
+                let events: Vec<_> = events
+                    .into_iter()
+                    .zip(subject_changes)
+                    .zip(conversation_starts)
+                    .filter(|(_, start)| {
+                        !split_threads || focused_start.is_none_or(|f| f == *start)
+                    })
+                    .map(|(pair, _)| pair)
+                    .collect();
+
                 let mut event_ids: Vec<String> = Vec::new();
-                for ev in &events {
+                for (ev, _) in &events {
                     if let Some(event_id) = ev.id.as_ref() {
                         event_ids.push(event_id.to_hex());
                     }
@@ -859,18 +2064,148 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                     }
                 };
 
+                // Messages we wrote ourselves never count as new.
+                if app
+                    .state
+                    .thread_unread
+                    .as_ref()
+                    .is_none_or(|thread| thread.root_id != app.focused_post)
+                {
+                    app.state.pending_read.clear();
+                    app.state.thread_unread = Some(ThreadUnread {
+                        root_id: app.focused_post.clone(),
+                        checked: HashSet::new(),
+                        unread: HashSet::new(),
+                        seen: HashSet::new(),
+                        scrolled: false,
+                    });
+                }
+                let ours: Vec<nostr::PublicKey> = app
+                    .account_manager
+                    .loaded_keys
+                    .iter()
+                    .map(|k| k.public_key())
+                    .collect();
+                if let Some(thread) = app.state.thread_unread.as_mut() {
+                    let unchecked: Vec<String> = events
+                        .iter()
+                        .filter(|(ev, _)| ev.author.is_some_and(|author| !ours.contains(&author)))
+                        .filter_map(|(ev, _)| ev.id.map(|id| id.to_hex()))
+                        .filter(|id| !thread.checked.contains(id))
+                        .collect();
+                    if !unchecked.is_empty() {
+                        match app.db.get_unread_event_ids(&unchecked) {
+                            Ok(unread) => thread.unread.extend(unread),
+                            Err(e) => error!("Failed to load unread messages: {}", e),
+                        }
+                        thread.checked.extend(unchecked);
+                    }
+                }
+                let (unread_ids, scroll_to_unread) = app
+                    .state
+                    .thread_unread
+                    .as_ref()
+                    .map(|thread| (thread.unread.clone(), !thread.scrolled))
+                    .unwrap_or_default();
+                let first_unread = events
+                    .iter()
+                    .position(|(ev, _)| ev.id.is_some_and(|id| unread_ids.contains(&id.to_hex())));
+                let mut on_screen_unread: Vec<String> = Vec::new();
+
+                if let Some((root, _)) = events.first() {
+                    ui.add_space(8.0);
+                    ui::thread_subject::render_thread_subject(app, ui, &root.subject);
+                }
+                if !app.show_trashed_post {
+                    let archived = app
+                        .archived_entries
+                        .iter()
+                        .any(|entry| entry.id == app.focused_post);
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        if archived {
+                            if ui.button("📥 Move to Inbox").clicked() {
+                                let root_id = app.focused_post.clone();
+                                app.set_thread_archived(&root_id, false);
+                            }
+                        } else if ui.button("📁 Archive").clicked() {
+                            let root_id = app.focused_post.clone();
+                            app.set_thread_archived(&root_id, true);
+                            app.page = Page::Inbox;
+                        }
+                        if ui.ctx().viewport_id() == egui::ViewportId::ROOT
+                            && ui
+                                .button("⧉ New window")
+                                .on_hover_text("Open this thread in a window of its own")
+                                .clicked()
+                        {
+                            let root_id = app.focused_post.clone();
+                            ui::thread_window::open(app, ui.ctx(), &root_id);
+                        }
+                        ui::thread_notes::notes_button(app, ui);
+                        ui::labels::render_thread_labels(app, ui, &event_ids);
+                    });
+                }
+
+                if !app.show_trashed_post {
+                    ui::thread_notes::show(app, ui);
+                    if let Some((latest, _)) = events.last() {
+                        ui::quick_reply::show(app, ui, latest);
+                    }
+                }
+
+                let mut hovered_quote = None;
                 ScrollArea::vertical()
                     .auto_shrink([false; 2])
                     .show(ui, |ui| {
-                        for ev in events {
+                        for (i, (ev, subject_change)) in events.into_iter().enumerate() {
                             ui.add_space(8.0);
 
+                            if let Some(subject) = subject_change.filter(|_| i > 0) {
+                                ui.separator();
+                                ui.vertical_centered(|ui| {
+                                    ui.label(
+                                        RichText::new(format!("Subject changed to “{}”", subject))
+                                            .small()
+                                            .color(style::TEXT_MUTED),
+                                    );
+                                });
+                                ui.add_space(8.0);
+                            }
+
+                            if Some(i) == first_unread {
+                                let new_count = unread_ids.len();
+                                let divider = ui.vertical_centered(|ui| {
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "{} new message{}",
+                                            new_count,
+                                            if new_count == 1 { "" } else { "s" }
+                                        ))
+                                        .small()
+                                        .strong()
+                                        .color(style::ACCENT),
+                                    );
+                                });
+                                if scroll_to_unread {
+                                    divider.response.scroll_to_me(Some(egui::Align::Min));
+                                }
+                                ui.add_space(8.0);
+                            }
+
                             let event_id = ev.id;
                             let author = ev.author;
+                            let unread =
+                                event_id.is_some_and(|id| unread_ids.contains(&id.to_hex()));
+                            let card_stroke = if unread {
+                                style::ACCENT
+                            } else {
+                                style::CARD_STROKE
+                            };
 
-                            Frame::none()
+                            let card = Frame::none()
                                 .fill(style::CARD_BG)
-                                .stroke(Stroke::new(1.0, style::CARD_STROKE))
+                                .stroke(Stroke::new(1.0, card_stroke))
                                 .inner_margin(Margin::same(16.0))
                                 .rounding(8.0)
                                 .show(ui, |ui| {
@@ -895,6 +2230,11 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                                         );
                                         ui.add_space(6.0);
                                     }
+                                    if unread {
+                                        ui.label(
+                                            RichText::new("● New").small().color(style::ACCENT),
+                                        );
+                                    }
                                     ui.heading(&ev.subject);
                                     ui.add_space(4.0);
 
@@ -908,29 +2248,58 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                                                 RichText::new("From").color(style::TEXT_MUTED),
                                             );
                                             let _ = get_profile_metadata(app, author_pk.clone());
-                                            let from_label = app
-                                                .resolve_name(&author_pk)
-                                                .unwrap_or_else(|| author_pk.clone());
-                                            ui.label(RichText::new(from_label).strong());
+                                            let email_sender = app.bridge.email_sender(&ev);
+                                            let from_label = match email_sender {
+                                                Some(address) => address.to_string(),
+                                                None => app.display_name(&author_pk),
+                                            };
+                                            ui.horizontal(|ui| {
+                                                participant_avatar(
+                                                    app,
+                                                    ui,
+                                                    &author,
+                                                    &from_label,
+                                                    email_sender,
+                                                );
+                                                ui.label(RichText::new(from_label).strong());
+                                            });
                                             ui.end_row();
 
                                             ui.label(RichText::new("To").color(style::TEXT_MUTED));
-                                            let to_labels: Vec<String> = ev
+                                            // Bridged mail lists the real addresses instead of
+                                            // the gateway.
+                                            let hidden_gateway = if ev.email_to.is_empty() {
+                                                None
+                                            } else {
+                                                app.bridge.gateway()
+                                            };
+                                            let to: Vec<nostr::PublicKey> = ev
                                                 .to
                                                 .iter()
-                                                .map(|pk| {
-                                                    let pk_str = pk.to_string();
-                                                    let _ =
-                                                        get_profile_metadata(app, pk_str.clone());
-                                                    app.resolve_name(&pk_str).unwrap_or(pk_str)
-                                                })
+                                                .filter(|pk| Some(**pk) != hidden_gateway)
+                                                .copied()
                                                 .collect();
-                                            ui.label(to_labels.join(", "));
+                                            participant_list(app, ui, &to, &ev.email_to);
                                             ui.end_row();
+
+                                            if !ev.cc.is_empty() {
+                                                ui.label(
+                                                    RichText::new("Cc").color(style::TEXT_MUTED),
+                                                );
+                                                participant_list(app, ui, &ev.cc, &[]);
+                                                ui.end_row();
+                                            }
                                         });
 
                                     ui.add_space(8.0);
 
+                                    let (reply_to, reply_all) = reply_fields(app, &ev);
+                                    let mut reply_buttons =
+                                        [("↩️ Reply", reply_to), ("↩️ Reply all", reply_all)];
+                                    if app.preferences.reply_all_by_default {
+                                        reply_buttons.reverse();
+                                    }
+
                                     // Action buttons
                                     ui.horizontal(|ui| {
                                         if ui.button("📎 Attach").clicked() {
@@ -940,56 +2309,56 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                                             // TODO: Handle edit
                                         }
                                         if ui.button("🗑️ Delete").clicked() {
-                                            // TODO: broadcast NIP-09 EventDeletion to relays
-                                            let now = chrono::Utc::now().timestamp();
-                                            let purge_after = now + 30 * 24 * 60 * 60;
-                                            let event_id_hex = event_id.to_hex();
-                                            if let Err(e) = app
-                                                .db
-                                                .record_trash(&[event_id_hex.clone()], purge_after)
-                                            {
-                                                error!("Failed to move event to trash: {}", e);
-                                            } else {
-                                                app.events
-                                                    .retain(|ev| ev.id.to_string() != event_id_hex);
-                                                if app.focused_post == event_id_hex {
-                                                    app.page = Page::Inbox;
-                                                    app.focused_post.clear();
-                                                    app.show_trashed_post = false;
-                                                }
-                                                match app.db.get_top_level_messages() {
-                                                    Ok(msgs) => app.table_entries = msgs,
-                                                    Err(e) => error!(
-                                                        "Could not fetch table entries to display from DB: {}",
-                                                        e
-                                                    ),
-                                                }
-                                                app.refresh_trash();
-                                            }
-                                        }
-                                        if ui.button("↩️ Reply").clicked() {
-                                            let mut parent_events: Vec<EventId> =
-                                                ev.parent_events.unwrap_or(Vec::new());
-                                            parent_events.push(event_id);
-                                            let state = ui::compose_window::ComposeWindowState {
-                                                subject: format!("Re: {}", ev.subject),
-                                                to_field: author.to_string(),
-                                                content: String::new(),
-                                                parent_events,
-                                                selected_account: None,
-                                                minimized: false,
-                                                draft_id: None,
-                                            };
-                                            app.state.compose_window.insert(
-                                                egui::Id::new(rand::random::<u32>()),
-                                                state,
+                                            ui::delete_dialog::DeleteDialog::open(
+                                                app,
+                                                event_id.to_hex(),
+                                                trashed_ids.contains(&event_id.to_hex()),
                                             );
                                         }
+                                        for (label, to_field) in reply_buttons {
+                                            if ui.button(label).clicked() {
+                                                open_reply(app, &ev, to_field);
+                                            }
+                                        }
                                         if ui.button("↪️ Forward").clicked() {
                                             // TODO: Handle forward
                                         }
-                                        if ui.button("⭐ Star").clicked() {
-                                            // TODO: Handle star
+                                        let star = if app.starred_ids.contains(&event_id.to_hex()) {
+                                            "⭐ Unstar"
+                                        } else {
+                                            "⭐ Star"
+                                        };
+                                        if ui.button(star).clicked() {
+                                            app.toggle_star(&event_id.to_hex());
+                                        }
+                                        ui::translation::translate_button(app, ui, &ev);
+                                        ui.menu_button("ℹ Details", |ui| {
+                                            ui::message_details::message_details(app, ui, &ev);
+                                        });
+                                        ui::message_details::encryption_badge(app, ui, &ev);
+                                        ui::message_details::provenance_warning(app, ui, &ev);
+                                        let authored_by_us = app
+                                            .account_manager
+                                            .loaded_keys
+                                            .iter()
+                                            .any(|k| k.public_key() == author);
+                                        if !authored_by_us {
+                                            ui::zap::zap_button(app, ui, &ev, event_id, author);
+                                            if app.preferences.focused_inbox {
+                                                focus_button(app, ui, &author_pk);
+                                            }
+                                            if ui.button("🚫 Block sender").clicked() {
+                                                app.block_pubkey(&author_pk);
+                                                app.page = Page::Inbox;
+                                            }
+                                            if ui.button("⚑ Report").clicked() {
+                                                ui::report_dialog::ReportDialog::open(
+                                                    app,
+                                                    author_pk.clone(),
+                                                );
+                                            }
+                                        } else {
+                                            ui::zap::zap_summary(app, ui, event_id);
                                         }
                                     });
 
@@ -997,12 +2366,79 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                                     ui.separator();
                                     ui.add_space(12.0);
 
-                                    // Message content
-                                    ui.label(ev.content);
+                                    if ev.quote.is_some() {
+                                        ui.label(
+                                            RichText::new(
+                                                "❝ Quotes part of an earlier message, which is \
+                                                 highlighted while the pointer is over this one",
+                                            )
+                                            .small()
+                                            .color(style::TEXT_MUTED),
+                                        );
+                                        ui.add_space(8.0);
+                                    }
+
+                                    // Message content, with any embedded invite shown as a card
+                                    // instead of raw ICS.
+                                    match calendar::Invite::from_text(&ev.content) {
+                                        Some(invite) => {
+                                            ui.label(
+                                                ev.content.replace(&invite.raw, "").trim_end(),
+                                            );
+                                            ui.add_space(12.0);
+                                            ui::invite_card::invite_card(app, ui, &invite);
+                                        }
+                                        None => match app.bridge.html_body(&ev) {
+                                            Some(html) => ui::html_view::html_view(ui, html),
+                                            None => ui::quote::message_body(app, ui, &ev),
+                                        },
+                                    }
+                                    ui::translation::translation(app, ui, event_id);
+                                    if let Some(challenge) =
+                                        verification::find_challenge(&ev.content)
+                                    {
+                                        ui.add_space(12.0);
+                                        ui::verification::challenge_card(app, ui, &ev, challenge);
+                                    }
+
+                                    if let Some(payload) = &ev.payload {
+                                        ui.add_space(12.0);
+                                        ui::payload_card::payload_card(app, ui, event_id, payload);
+                                    }
+
+                                    if !ev.articles.is_empty() {
+                                        ui.add_space(12.0);
+                                        ui::articles::article_cards(app, ui, &ev.articles);
+                                    }
+
+                                    if !ev.attachments.is_empty() {
+                                        ui.add_space(12.0);
+                                        ui.separator();
+                                        ui::attachments::attachment_list(
+                                            app,
+                                            ui,
+                                            &ev.attachments,
+                                        );
+                                    }
                                 });
+                            if unread && ui.is_rect_visible(card.response.rect) {
+                                on_screen_unread.extend(event_id.map(|id| id.to_hex()));
+                            }
+                            if ev.quote.is_some() && ui.rect_contains_pointer(card.response.rect) {
+                                hovered_quote = ev.quote.clone();
+                            }
                         }
                     });
 
+                app.state.hovered_quote = hovered_quote;
+                if let Some(thread) = app.state.thread_unread.as_mut() {
+                    thread.scrolled = true;
+                    on_screen_unread.retain(|id| thread.seen.insert(id.clone()));
+                }
+                if !on_screen_unread.is_empty() {
+                    app.schedule_mark_read(on_screen_unread);
+                }
+
                 if let Some(event) = app
                     .events
                     .iter()
@@ -1098,10 +2534,10 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                                     ui.label(RichText::new(to).color(style::TEXT_MUTED));
                                 });
                                 row.col(|ui| {
-                                    ui.label(
-                                        RichText::new(style::format_timestamp(draft.updated_at))
-                                            .color(style::TEXT_MUTED)
-                                            .small(),
+                                    style::timestamp_label(
+                                        ui,
+                                        draft.updated_at,
+                                        app.preferences.clock_24h,
                                     );
                                 });
                                 row.col(|ui| {
@@ -1117,30 +2553,7 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                         });
 
                     if let Some(draft) = draft_to_open {
-                        let parent_events: Vec<EventId> = draft
-                            .parent_events
-                            .iter()
-                            .filter_map(|s| EventId::parse(s).ok())
-                            .collect();
-                        let selected_account = draft.selected_account.as_ref().and_then(|pk_str| {
-                            app.account_manager
-                                .loaded_keys
-                                .iter()
-                                .find(|k| k.public_key().to_string() == *pk_str)
-                                .cloned()
-                        });
-                        let state = ui::compose_window::ComposeWindowState {
-                            subject: draft.subject,
-                            to_field: draft.to_field,
-                            content: draft.content,
-                            parent_events,
-                            selected_account,
-                            minimized: false,
-                            draft_id: Some(draft.id),
-                        };
-                        app.state
-                            .compose_window
-                            .insert(egui::Id::new(rand::random::<u32>()), state);
+                        open_draft(app, draft);
                     }
 
                     if let Some(id) = draft_to_delete {
@@ -1151,14 +2564,14 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                     }
                 }
             }
-            Page::Trash => {
+            Page::Sent => {
                 ui.add_space(8.0);
 
                 ui.horizontal(|ui| {
-                    ui.heading("Trash");
+                    ui.heading("Sent");
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         if ui.button("Refresh").clicked() {
-                            app.refresh_trash();
+                            app.refresh_sent();
                         }
                     });
                 });
@@ -1167,109 +2580,365 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                 ui.separator();
                 ui.add_space(4.0);
 
-                if app.trash_entries.is_empty() {
+                if app.sent_entries.is_empty() {
                     ui.add_space(40.0);
                     ui.vertical_centered(|ui| {
                         ui.label(
-                            RichText::new("Trash is empty")
+                            RichText::new("Nothing sent yet")
                                 .size(16.0)
                                 .color(style::TEXT_MUTED),
                         );
                     });
                 } else {
-                    let mut to_restore: Option<String> = None;
-                    let mut to_delete: Option<String> = None;
-
+                    #[cfg(feature = "profiling")]
+                    puffin::profile_scope!("sent_table");
                     TableBuilder::new(ui)
-                        .column(Column::initial(160.0).at_least(100.0)) // Sender
+                        .column(Column::initial(200.0).at_least(100.0)) // Recipients
                         .column(Column::remainder()) // Subject
                         .column(Column::initial(100.0).at_least(70.0)) // Time
-                        .column(Column::initial(140.0).at_least(120.0)) // Actions
+                        .column(Column::initial(140.0).at_least(100.0)) // Delivery
                         .striped(true)
                         .sense(Sense::click())
                         .auto_shrink(Vec2b { x: false, y: false })
                         .header(28.0, |mut header| {
                             header.col(|ui| {
-                                ui.label(RichText::new("From").small().color(style::TEXT_MUTED));
+                                ui.label(RichText::new("To").small().color(style::TEXT_MUTED));
                             });
                             header.col(|ui| {
-                                ui.label(
-                                    RichText::new("Subject").small().color(style::TEXT_MUTED),
-                                );
+                                ui.label(RichText::new("Subject").small().color(style::TEXT_MUTED));
                             });
                             header.col(|ui| {
                                 ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
                             });
                             header.col(|ui| {
-                                ui.label(RichText::new("Actions").small().color(style::TEXT_MUTED));
+                                ui.label(
+                                    RichText::new("Delivery").small().color(style::TEXT_MUTED),
+                                );
                             });
                         })
                         .body(|body| {
-                            let events: Vec<TableEntry> = app.trash_entries.to_vec();
-                            body.rows(style::INBOX_ROW_HEIGHT, events.len(), |mut row| {
-                                let event = &events[row.index()];
+                            let entries: Vec<db::SentMessage> = app.sent_entries.to_vec();
+                            body.rows(style::INBOX_ROW_HEIGHT, entries.len(), |mut row| {
+                                let sent = &entries[row.index()];
+                                let recipients: Vec<String> = sent
+                                    .recipients
+                                    .iter()
+                                    .map(|pubkey| {
+                                        let _ = get_profile_metadata(app, pubkey.clone());
+                                        app.display_name(pubkey)
+                                    })
+                                    .collect();
 
                                 row.col(|ui| {
-                                    let _ = get_profile_metadata(app, event.pubkey.clone());
-                                    let label = app
-                                        .resolve_name(&event.pubkey)
-                                        .unwrap_or_else(|| event.pubkey.to_string());
-                                    ui.label(RichText::new(label).strong());
+                                    ui.label(RichText::new(recipients.join(", ")).strong());
                                 });
                                 row.col(|ui| {
-                                    ui.label(&event.subject);
+                                    ui::thread_subject::subject_label(
+                                        app,
+                                        ui,
+                                        &sent.entry.id,
+                                        &sent.entry.subject,
+                                    );
                                 });
                                 row.col(|ui| {
-                                    ui.label(
-                                        RichText::new(style::format_timestamp(event.created_at))
-                                            .color(style::TEXT_MUTED)
-                                            .small(),
+                                    style::timestamp_label(
+                                        ui,
+                                        sent.entry.created_at,
+                                        app.preferences.clock_24h,
                                     );
                                 });
                                 row.col(|ui| {
-                                    ui.horizontal(|ui| {
-                                        if ui.button("Restore").clicked() {
-                                            to_restore = Some(event.id.clone());
-                                        }
-                                        if ui.button("Delete now").clicked() {
-                                            // TODO: broadcast NIP-09 EventDeletion to relays
-                                            to_delete = Some(event.id.clone());
-                                        }
-                                    });
+                                    ui.label(sent_status(sent));
                                 });
 
                                 if row.response().clicked() {
-                                    app.focused_post = event.id.clone();
+                                    app.focused_post = sent.entry.id.clone();
                                     app.page = Page::Post;
-                                    app.show_trashed_post = true;
+                                    app.show_trashed_post = false;
                                 }
                             });
                         });
+                }
+            }
+            Page::Trash => {
+                ui.add_space(8.0);
 
-                    if let Some(event_id) = to_restore {
-                        if let Err(e) = app.db.restore_from_trash(&event_id) {
-                            error!("Failed to restore from trash: {}", e);
-                        } else {
-                            match app.db.get_top_level_messages() {
-                                Ok(msgs) => app.table_entries = msgs,
-                                Err(e) => error!(
-                                    "Could not fetch table entries to display from DB: {}",
-                                    e
-                                ),
-                            }
+                ui.horizontal(|ui| {
+                    ui.heading("Trash");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Refresh").clicked() {
                             app.refresh_trash();
                         }
-                    }
-
-                    if let Some(event_id) = to_delete {
-                        if let Err(e) = apply_deletions(app, vec![event_id.clone()], None, None) {
-                            error!("Failed to delete trashed event: {}", e);
+                    });
+                });
+
+                ui.add_space(4.0);
+                ui.separator();
+                ui.add_space(4.0);
+
+                if app.trash_entries.is_empty() {
+                    ui.add_space(40.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            RichText::new("Trash is empty")
+                                .size(16.0)
+                                .color(style::TEXT_MUTED),
+                        );
+                    });
+                } else {
+                    let mut to_restore: Option<String> = None;
+
+                    #[cfg(feature = "profiling")]
+                    puffin::profile_scope!("trash_table");
+                    TableBuilder::new(ui)
+                        .column(Column::initial(160.0).at_least(100.0)) // Sender
+                        .column(Column::remainder()) // Subject
+                        .column(Column::initial(100.0).at_least(70.0)) // Time
+                        .column(Column::initial(140.0).at_least(120.0)) // Actions
+                        .striped(true)
+                        .sense(Sense::click())
+                        .auto_shrink(Vec2b { x: false, y: false })
+                        .header(28.0, |mut header| {
+                            header.col(|ui| {
+                                ui.label(RichText::new("From").small().color(style::TEXT_MUTED));
+                            });
+                            header.col(|ui| {
+                                ui.label(
+                                    RichText::new("Subject").small().color(style::TEXT_MUTED),
+                                );
+                            });
+                            header.col(|ui| {
+                                ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
+                            });
+                            header.col(|ui| {
+                                ui.label(RichText::new("Actions").small().color(style::TEXT_MUTED));
+                            });
+                        })
+                        .body(|body| {
+                            let events: Vec<TableEntry> = app.trash_entries.to_vec();
+                            body.rows(style::INBOX_ROW_HEIGHT, events.len(), |mut row| {
+                                let event = &events[row.index()];
+
+                                row.col(|ui| {
+                                    let _ = get_profile_metadata(app, event.pubkey.clone());
+                                    let label = app.display_name(&event.pubkey);
+                                    ui.label(RichText::new(label).strong());
+                                });
+                                row.col(|ui| {
+                                    ui::thread_subject::subject_label(
+                                        app,
+                                        ui,
+                                        &event.id,
+                                        &event.subject,
+                                    );
+                                });
+                                row.col(|ui| {
+                                    style::timestamp_label(
+                                        ui,
+                                        event.created_at,
+                                        app.preferences.clock_24h,
+                                    );
+                                });
+                                row.col(|ui| {
+                                    ui.horizontal(|ui| {
+                                        if ui.button("Restore").clicked() {
+                                            to_restore = Some(event.id.clone());
+                                        }
+                                        if ui.button("Delete now").clicked() {
+                                            ui::delete_dialog::DeleteDialog::open(
+                                                app,
+                                                event.id.clone(),
+                                                true,
+                                            );
+                                        }
+                                    });
+                                });
+
+                                if row.response().clicked() {
+                                    app.focused_post = event.id.clone();
+                                    app.page = Page::Post;
+                                    app.show_trashed_post = true;
+                                }
+                            });
+                        });
+
+                    if let Some(event_id) = to_restore {
+                        if let Err(e) = app.db.restore_from_trash(&event_id) {
+                            error!("Failed to restore from trash: {}", e);
                         } else {
+                            app.refresh_table_entries();
                             app.refresh_trash();
+                            app.refresh_sent();
                         }
                     }
                 }
             }
+            Page::Archived => {
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    ui.heading("Archived");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Refresh").clicked() {
+                            app.refresh_archived();
+                        }
+                    });
+                });
+
+                ui.add_space(4.0);
+                ui.separator();
+                ui.add_space(4.0);
+
+                if app.archived_entries.is_empty() {
+                    ui.add_space(40.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            RichText::new("Nothing archived")
+                                .size(16.0)
+                                .color(style::TEXT_MUTED),
+                        );
+                    });
+                } else {
+                    #[cfg(feature = "profiling")]
+                    puffin::profile_scope!("archive_table");
+                    TableBuilder::new(ui)
+                        .column(Column::initial(160.0).at_least(100.0)) // Sender
+                        .column(Column::remainder()) // Subject
+                        .column(Column::initial(100.0).at_least(70.0)) // Time
+                        .striped(true)
+                        .sense(Sense::click())
+                        .auto_shrink(Vec2b { x: false, y: false })
+                        .header(28.0, |mut header| {
+                            header.col(|ui| {
+                                ui.label(RichText::new("From").small().color(style::TEXT_MUTED));
+                            });
+                            header.col(|ui| {
+                                ui.label(RichText::new("Subject").small().color(style::TEXT_MUTED));
+                            });
+                            header.col(|ui| {
+                                ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
+                            });
+                        })
+                        .body(|body| {
+                            let entries: Vec<TableEntry> = app.archived_entries.to_vec();
+                            body.rows(style::INBOX_ROW_HEIGHT, entries.len(), |mut row| {
+                                let entry = &entries[row.index()];
+                                let sender = app.display_name(&entry.pubkey);
+
+                                row.col(|ui| {
+                                    ui.label(RichText::new(&sender).strong());
+                                });
+                                row.col(|ui| {
+                                    ui::thread_subject::subject_label(
+                                        app,
+                                        ui,
+                                        &entry.id,
+                                        &entry.subject,
+                                    );
+                                });
+                                row.col(|ui| {
+                                    style::timestamp_label(
+                                        ui,
+                                        entry.created_at,
+                                        app.preferences.clock_24h,
+                                    );
+                                });
+
+                                if row.response().clicked() {
+                                    app.focused_post = entry.id.clone();
+                                    app.page = Page::Post;
+                                    app.show_trashed_post = false;
+                                }
+                            });
+                        });
+                }
+            }
+            Page::Notes => {
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    ui.heading("Notes");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Refresh").clicked() {
+                            app.refresh_notes();
+                        }
+                        if let Some(keys) = app.notes_account() {
+                            if ui.button("✏ New Note").clicked() {
+                                let state = ComposeWindowState {
+                                    to_field: keys.public_key().to_hex(),
+                                    selected_account: Some(keys),
+                                    ..Default::default()
+                                };
+                                ComposeWindow::open(app, state);
+                            }
+                        }
+                    });
+                });
+
+                ui.add_space(4.0);
+                ui.separator();
+                ui.add_space(4.0);
+
+                if app.notes_entries.is_empty() {
+                    ui.add_space(40.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            RichText::new("No notes yet")
+                                .size(16.0)
+                                .color(style::TEXT_MUTED),
+                        );
+                        ui.label(
+                            RichText::new("Messages you send only to yourself show up here.")
+                                .color(style::TEXT_MUTED),
+                        );
+                    });
+                } else {
+                    #[cfg(feature = "profiling")]
+                    puffin::profile_scope!("notes_table");
+                    TableBuilder::new(ui)
+                        .column(Column::remainder()) // Subject
+                        .column(Column::initial(100.0).at_least(70.0)) // Time
+                        .striped(true)
+                        .sense(Sense::click())
+                        .auto_shrink(Vec2b { x: false, y: false })
+                        .header(28.0, |mut header| {
+                            header.col(|ui| {
+                                ui.label(RichText::new("Subject").small().color(style::TEXT_MUTED));
+                            });
+                            header.col(|ui| {
+                                ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
+                            });
+                        })
+                        .body(|body| {
+                            let notes: Vec<TableEntry> = app.notes_entries.to_vec();
+                            body.rows(style::INBOX_ROW_HEIGHT, notes.len(), |mut row| {
+                                let note = &notes[row.index()];
+
+                                row.col(|ui| {
+                                    let subject = if note.subject.is_empty() {
+                                        "(no subject)"
+                                    } else {
+                                        &note.subject
+                                    };
+                                    ui.label(RichText::new(subject).strong());
+                                });
+                                row.col(|ui| {
+                                    style::timestamp_label(
+                                        ui,
+                                        note.created_at,
+                                        app.preferences.clock_24h,
+                                    );
+                                });
+
+                                if row.response().clicked() {
+                                    app.focused_post = note.id.clone();
+                                    app.page = Page::Post;
+                                    app.show_trashed_post = false;
+                                }
+                            });
+                        });
+                }
+            }
             Page::Unlock => {
                 ui::unlock_database::UnlockDatabase::ui(app, ui);
             }
@@ -1283,18 +2952,13 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                 ui.heading("This hasn't been implemented yet.");
             }
         }
-    });
+    };
+    egui::CentralPanel::default().show(ctx, |ui| render_page(app, ui));
+    ui::thread_window::show_all(app, ctx, render_page);
 }
 
-// it's just to determine where to store files and also for keystorage paths and such
-// y'know?????
-#[cfg(debug_assertions)]
-pub const STORAGE_NAME: &'static str = "systems.chakany.hoot-dev";
-#[cfg(not(debug_assertions))]
-pub const STORAGE_NAME: &'static str = "systems.chakany.hoot";
-
 impl Hoot {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Create storage directory if it doesn't exist
         let storage_dir = eframe::storage_dir(STORAGE_NAME).unwrap();
         std::fs::create_dir_all(&storage_dir).unwrap();
@@ -1314,6 +2978,96 @@ impl Hoot {
             }
         };
 
+        let relay_urls: Vec<String> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::RELAYS_KEY))
+            .unwrap_or_else(|| relay::DEFAULT_RELAYS.map(String::from).to_vec());
+
+        let relay_windows: HashMap<String, relay::SyncWindow> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::RELAY_WINDOWS_KEY))
+            .unwrap_or_default();
+        let mut relays = relay::RelayPool::new();
+        if let Some(settings) = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::PING_SETTINGS_KEY))
+        {
+            relays.set_ping_settings(settings);
+        }
+        for (url, window) in relay_windows {
+            relays.set_window(&url, window);
+        }
+        let relay_trust: HashMap<String, relay::RelayTrust> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::RELAY_TRUST_KEY))
+            .unwrap_or_default();
+        for (url, trust) in relay_trust {
+            relays.set_trust(&url, trust);
+        }
+        let relay_usage: HashMap<String, relay::RelayUsage> = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::RELAY_USAGE_KEY))
+            .unwrap_or_default();
+        for (url, usage) in relay_usage {
+            relays.set_usage(&url, usage);
+        }
+
+        let download_dir = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, downloads::DOWNLOAD_DIR_KEY))
+            .unwrap_or_else(|| storage_dir.join("downloads"));
+        let upload_server = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, uploads::UPLOAD_SERVER_KEY))
+            .unwrap_or_else(|| uploads::DEFAULT_UPLOAD_SERVER.to_string());
+        let paid_relays = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay::PAID_RELAYS_KEY))
+            .unwrap_or_default();
+        let saved_presets = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay_presets::RELAY_PRESETS_KEY));
+        let preset_choices = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, relay_presets::PRESET_CHOICES_KEY))
+            .unwrap_or_default();
+
+        let bridge = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, bridge::BRIDGE_CONFIG_KEY))
+            .unwrap_or_default();
+
+        let split_threads = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, threading::SPLIT_ON_SUBJECT_CHANGE_KEY))
+            .unwrap_or(false);
+
+        let preferences: preferences::Preferences = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, preferences::PREFERENCES_KEY))
+            .unwrap_or_default();
+        relays.set_max_pow(preferences.max_pow);
+
+        let retention = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, janitor::RETENTION_KEY))
+            .unwrap_or_default();
+
+        let translation = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, translate::TRANSLATION_KEY))
+            .unwrap_or_default();
+
+        let automation = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, automation::AUTOMATION_KEY))
+            .unwrap_or_default();
+
+        let frame_overlay = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, ui::frame_overlay::FRAME_OVERLAY_KEY))
+            .unwrap_or(false);
+
         // check if this is our first time loading
         let page = match std::fs::exists(storage_dir.join("done")) {
             Ok(true) => Page::Unlock,
@@ -1327,86 +3081,1118 @@ impl Hoot {
             show_trashed_post: false,
             status: HootStatus::PreUnlock,
             state: Default::default(),
-            relays: relay::RelayPool::new(),
+            relays,
             events: Vec::new(),
             account_manager: account_manager::AccountManager::new(),
             active_account: None,
             db,
-            table_entries: Vec::new(),
+            db_worker: None,
+            thread_snapshot: None,
+            thread_requested: None,
+            ingest: ingest::Ingest::new(cc.egui_ctx.clone()),
+            sightings: ingest::Sightings::default(),
+            inbox: Default::default(),
+            archived_entries: Vec::new(),
             trash_entries: Vec::new(),
+            sent_entries: Vec::new(),
+            notes_entries: Vec::new(),
             profile_metadata: HashMap::new(),
+            names: Default::default(),
             contacts_manager: ContactsManager::new(),
             drafts: Vec::new(),
+            downloads: downloads::DownloadManager::new(download_dir),
+            uploads: uploads::UploadManager::new(upload_server),
+            relay_info: relay_info::RelayInfoManager::new(paid_relays),
+            relay_presets: relay_presets::RelayPresets::new(saved_presets, preset_choices),
+            payments: payments::Payments::default(),
+            zaps: zaps::ZapManager::new(),
+            proof_of_work: proof_of_work::ProofOfWork::new(),
+            translator: translate::Translator::new(translation),
+            automations: automation::Automations::new(automation),
+            hover_preview: Default::default(),
+            external_editors: Default::default(),
+            notifications: Default::default(),
+            toasts: Default::default(),
+            actions: Default::default(),
+            relay_urls,
+            blocked_pubkeys: Vec::new(),
+            bridge,
+            mail_merge: Default::default(),
+            split_threads,
+            mailbox: None,
+            unread_counts: HashMap::new(),
+            unread_total: 0,
+            stored_counts: HashMap::new(),
+            relay_counts: HashMap::new(),
+            count_requests: HashMap::new(),
+            inbox_filter: Default::default(),
+            saved_searches: Vec::new(),
+            saved_search_counts: HashMap::new(),
+            active_search: None,
+            focus_ranked: None,
+            focused_senders: HashSet::new(),
+            _instance_lock: None,
+            instance: None,
+            pending_commands: Vec::new(),
+            starred_ids: HashSet::new(),
+            thread_subjects: HashMap::new(),
+            thread_notes: HashMap::new(),
+            paid_invoices: HashMap::new(),
+            sending_accounts: HashMap::new(),
+            inbox_columns: Default::default(),
+            integrity: None,
+            relay_hints: Default::default(),
+            repaint: Default::default(),
+            articles: Default::default(),
+            preferences,
+            account_colors: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, account_colors::ACCOUNT_COLORS_KEY))
+                .unwrap_or_default(),
+            thread_accounts: HashMap::new(),
+            janitor: janitor::Janitor::new(retention),
+            flag_publisher: Default::default(),
+            frame_overlay: ui::frame_overlay::FrameOverlay::new(frame_overlay),
+            last_folder: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, preferences::LAST_FOLDER_KEY)),
+            thread_windows: Vec::new(),
         }
     }
 
     fn refresh_drafts(&mut self) {
         match self.db.get_drafts() {
-            Ok(drafts) => self.drafts = drafts,
+            Ok(mut drafts) => {
+                if let Some(mailbox) = &self.mailbox {
+                    drafts.retain(|d| d.selected_account.as_ref().is_none_or(|a| a == mailbox));
+                }
+                self.drafts = drafts;
+            }
             Err(e) => error!("Failed to load drafts: {}", e),
         }
     }
 
-    fn refresh_trash(&mut self) {
-        match self.db.get_trash_messages() {
-            Ok(entries) => self.trash_entries = entries,
-            Err(e) => error!("Failed to load trash entries: {}", e),
+    fn refresh_table_entries(&mut self) {
+        self.thread_accounts.clear();
+        self.refresh_unread_counts();
+        self.refresh_saved_search_counts();
+        self.update_focus();
+        let filter = search_query::apply(&self.inbox_filter);
+        // Split threads are worked out from the whole inbox, so they can't be paged.
+        let page = self
+            .inbox
+            .reload(self.mailbox.as_deref(), &filter, !self.split_threads);
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::Inbox {
+                mailbox: self.mailbox.clone(),
+                filter,
+                split_threads: self.split_threads,
+                page,
+            });
+            // Whatever changed the inbox may have changed the open threads too.
+            if self.page == Page::Post {
+                self.request_thread();
+            }
+            for window in &self.thread_windows {
+                worker.request(db_worker::DbRequest::Thread {
+                    root_id: window.root_id.clone(),
+                    include_trash: false,
+                });
+            }
+            return;
+        }
+
+        match self
+            .db
+            .get_top_level_page(self.mailbox.as_deref(), &filter, &page)
+        {
+            Ok(msgs) if self.split_threads => {
+                let msgs = threading::split_entries(&self.db, msgs);
+                self.inbox.reset(&page, msgs)
+            }
+            Ok(msgs) => self.inbox.reset(&page, msgs),
+            Err(e) => error!("Could not fetch table entries to display from DB: {}", e),
+        }
+        self.show_pending_actions();
+        self.thread_snapshot = None;
+        for window in &mut self.thread_windows {
+            window.snapshot = None;
         }
     }
 
-    /// Update the gift-wrap subscription to include all loaded accounts.
-    pub fn update_gift_wrap_subscription(&mut self) {
-        if self.account_manager.loaded_keys.is_empty() {
+    /// Keeps the inbox on the Focused or Other tab while the split is on, and the ranking
+    /// fresh. Saved searches look through everything. The ranking reads every stored message,
+    /// so it runs on the worker and the inbox loads again once it's saved.
+    fn update_focus(&mut self) {
+        if !self.preferences.focused_inbox || self.active_search.is_some() {
+            self.inbox_filter.focused = None;
             return;
         }
+        self.inbox_filter.focused.get_or_insert(true);
+        if self
+            .focus_ranked
+            .is_some_and(|ranked| ranked.elapsed() < focus::RANK_INTERVAL)
+        {
+            return;
+        }
+        self.focus_ranked = Some(std::time::Instant::now());
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::RankSenders);
+            return;
+        }
+        if let Err(e) = focus::rank(&mut self.db) {
+            error!("Failed to rank senders for the Focused inbox: {}", e);
+        }
+        self.refresh_focused_senders();
+    }
 
-        let public_keys: Vec<nostr::PublicKey> = self
-            .account_manager
-            .loaded_keys
-            .iter()
-            .map(|k| k.public_key())
-            .collect();
-
-        let filter = nostr::Filter::new().kind(nostr::Kind::GiftWrap).custom_tag(
-            nostr::SingleLetterTag {
-                character: nostr::Alphabet::P,
-                uppercase: false,
-            },
-            public_keys,
-        );
-
-        let mut gw_sub = relay::Subscription::default();
-        gw_sub.filter(filter);
+    /// Saves the worker's ranking, loading the inbox again if it moved anyone.
+    fn senders_ranked(&mut self, senders: Vec<String>) {
+        if let Err(e) = self.db.set_ranked_senders(&senders) {
+            error!("Failed to save the Focused inbox ranking: {}", e);
+            return;
+        }
+        debug!("Ranked {} senders as Focused", senders.len());
+        let before = std::mem::take(&mut self.focused_senders);
+        self.refresh_focused_senders();
+        if self.focused_senders != before {
+            self.refresh_table_entries();
+        }
+    }
 
-        match self.relays.add_subscription(gw_sub) {
-            Ok(_) => debug!("Updated gift-wrap subscription"),
-            Err(e) => error!("Failed to update gift-wrap subscription: {}", e),
+    fn refresh_focused_senders(&mut self) {
+        match self.db.get_focused_senders() {
+            Ok(senders) => self.focused_senders = senders,
+            Err(e) => error!("Failed to load the Focused inbox senders: {}", e),
         }
     }
 
-    /// Resolve the best display name for a pubkey: petname > display_name > name > pubkey.
-    fn resolve_name(&self, pubkey: &str) -> Option<String> {
-        // Check contacts for petname first
-        if let Some(petname) = self.contacts_manager.find_petname(pubkey) {
-            return Some(petname.to_string());
-        }
-        // Fall back to profile metadata
-        if let Some(ProfileOption::Some(meta)) = self.profile_metadata.get(pubkey) {
-            if let Some(display_name) = &meta.display_name {
-                return Some(display_name.clone());
+    /// Loads more of the inbox as it's scrolled, through the worker when there is one.
+    fn load_inbox_page(&mut self, page: db::Page) {
+        let filter = search_query::apply(&self.inbox_filter);
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::InboxPage {
+                mailbox: self.mailbox.clone(),
+                filter,
+                page,
+            });
+            return;
+        }
+        match self
+            .db
+            .get_top_level_page(self.mailbox.as_deref(), &filter, &page)
+        {
+            Ok(msgs) => self.inbox.receive(&page, msgs),
+            Err(e) => error!("Could not fetch more of the inbox from DB: {}", e),
+        }
+    }
+
+    fn request_thread(&self) {
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::Thread {
+                root_id: self.focused_post.clone(),
+                include_trash: self.show_trashed_post,
+            });
+        }
+    }
+
+    /// Loads open threads again when `event_ids` touch them, like a reply to one of their
+    /// messages coming in, so they don't go stale while open. Until the worker answers, the
+    /// last snapshot stays on screen.
+    fn refresh_open_threads(&mut self, event_ids: &HashSet<String>) {
+        if event_ids.is_empty() {
+            return;
+        }
+        let touches = |snapshot: &db_worker::ThreadSnapshot| {
+            event_ids.contains(&snapshot.root_id)
+                || snapshot
+                    .messages
+                    .iter()
+                    .filter_map(|message| message.id)
+                    .any(|id| event_ids.contains(&id.to_hex()))
+        };
+        let Some(worker) = &self.db_worker else {
+            if self.thread_snapshot.as_ref().is_some_and(touches) {
+                self.thread_snapshot = None;
             }
-            if let Some(name) = &meta.name {
-                return Some(name.clone());
+            for window in &mut self.thread_windows {
+                if window.snapshot.as_ref().is_some_and(touches) {
+                    window.snapshot = None;
+                }
             }
+            return;
+        };
+        if self.page == Page::Post
+            && self
+                .thread_snapshot
+                .as_ref()
+                .is_some_and(|snapshot| snapshot.root_id == self.focused_post && touches(snapshot))
+        {
+            self.request_thread();
+        }
+        for window in &self.thread_windows {
+            if window.snapshot.as_ref().is_some_and(touches) {
+                worker.request(db_worker::DbRequest::Thread {
+                    root_id: window.root_id.clone(),
+                    include_trash: false,
+                });
+            }
+        }
+    }
+
+    /// The focused thread from the last snapshot, or `None` while the worker loads it.
+    fn focused_thread(&mut self) -> anyhow::Result<Option<Vec<mail_event::MailMessage>>> {
+        if let Some(snapshot) = &self.thread_snapshot {
+            if snapshot.root_id == self.focused_post
+                && snapshot.include_trash == self.show_trashed_post
+            {
+                return Ok(Some(snapshot.messages.clone()));
+            }
+        }
+
+        if self.db_worker.is_some() {
+            let key = (self.focused_post.clone(), self.show_trashed_post);
+            if self.thread_requested.as_ref() != Some(&key) {
+                self.request_thread();
+                self.thread_requested = Some(key);
+            }
+            return Ok(None);
+        }
+
+        let snapshot =
+            db_worker::ThreadSnapshot::load(&self.db, &self.focused_post, self.show_trashed_post)?;
+        let messages = snapshot.messages.clone();
+        self.thread_snapshot = Some(snapshot);
+        Ok(Some(messages))
+    }
+
+    fn process_db_responses(&mut self) {
+        let Some(worker) = &self.db_worker else {
+            return;
+        };
+        for response in worker.process_queue() {
+            match response {
+                db_worker::DbResponse::Inbox(page, entries) => {
+                    self.inbox.reset(&page, entries);
+                    self.show_pending_actions();
+                }
+                db_worker::DbResponse::InboxPage(page, entries) => {
+                    self.inbox.receive(&page, entries)
+                }
+                db_worker::DbResponse::Thread(snapshot) => {
+                    let mut for_window = false;
+                    for window in &mut self.thread_windows {
+                        if window.root_id == snapshot.root_id {
+                            window.snapshot = Some(snapshot.clone());
+                            for_window = true;
+                        }
+                    }
+                    if !for_window || snapshot.root_id == self.focused_post {
+                        self.thread_snapshot = Some(snapshot);
+                    }
+                }
+                db_worker::DbResponse::SavedSearchCounts(counts) => {
+                    self.saved_search_counts = counts
+                }
+                db_worker::DbResponse::IntegrityCheck(report) => self.integrity_checked(report),
+                db_worker::DbResponse::RankedSenders(senders) => self.senders_ranked(senders),
+                db_worker::DbResponse::RetentionPlanned {
+                    policy,
+                    plan,
+                    now,
+                    preview,
+                } => self.retention_planned(policy, plan, now, preview),
+                db_worker::DbResponse::Failed(db_worker::DbRequest::Thread { root_id, .. }) => {
+                    if self.page == Page::Post && self.focused_post == root_id {
+                        self.page = Page::Inbox;
+                        self.focused_post.clear();
+                    }
+                }
+                db_worker::DbResponse::Failed(_) => {}
+            }
+        }
+    }
+
+    /// Checks the database on the worker, or inline without one.
+    fn check_integrity(&mut self) {
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::IntegrityCheck);
+            return;
+        }
+        match self.db.integrity_check() {
+            Ok(report) => self.integrity_checked(report),
+            Err(e) => error!("Failed to check the database: {}", e),
+        }
+    }
+
+    fn integrity_checked(&mut self, report: db::IntegrityReport) {
+        // Only the check at unlock speaks up, later ones are asked for in Diagnostics.
+        if !report.is_clean() && self.integrity.is_none() {
+            warn!("The database needs repairs: {:?}", report);
+            self.toasts
+                .error("Some stored mail needs repairs. See Settings → Diagnostics.");
+        }
+        self.integrity = Some(report);
+    }
+
+    fn refresh_saved_searches(&mut self) {
+        match self.db.get_saved_searches() {
+            Ok(searches) => self.saved_searches = searches,
+            Err(e) => error!("Failed to load saved searches: {}", e),
+        }
+        self.refresh_saved_search_counts();
+    }
+
+    fn refresh_saved_search_counts(&mut self) {
+        if self.saved_searches.is_empty() {
+            self.saved_search_counts.clear();
+            return;
+        }
+        let searches: Vec<(i64, db::MessageFilter)> = self
+            .saved_searches
+            .iter()
+            .map(|search| (search.id, search_query::parse(&search.query)))
+            .collect();
+        match &self.db_worker {
+            Some(worker) => worker.request(db_worker::DbRequest::SavedSearchCounts {
+                mailbox: self.mailbox.clone(),
+                searches,
+            }),
+            None => {
+                self.saved_search_counts =
+                    db_worker::count_saved_searches(&self.db, self.mailbox.as_deref(), &searches)
+            }
+        }
+    }
+
+    /// Shows a saved search in the inbox, or the plain inbox again with `None`.
+    fn open_saved_search(&mut self, id: Option<i64>) {
+        let query = id
+            .and_then(|id| self.saved_searches.iter().find(|search| search.id == id))
+            .map(|search| search.query.clone());
+        self.active_search = id.filter(|_| query.is_some());
+        self.inbox_filter = db::MessageFilter {
+            query: query.unwrap_or_default(),
+            ..Default::default()
+        };
+        self.page = Page::Inbox;
+        self.refresh_table_entries();
+    }
+
+    /// Opens the page the preferences ask for, and the messages that were being written when
+    /// the app last closed if they ask for that too.
+    fn restore_session(&mut self) {
+        let folder = match self.preferences.startup_page {
+            preferences::StartupPage::Inbox => preferences::Folder::Inbox,
+            preferences::StartupPage::LastUsed => {
+                self.last_folder.unwrap_or(preferences::Folder::Inbox)
+            }
+            preferences::StartupPage::SavedSearch(id) => preferences::Folder::SavedSearch(id),
+        };
+        match folder {
+            preferences::Folder::Inbox => {}
+            preferences::Folder::SavedSearch(id) => self.open_saved_search(Some(id)),
+            preferences::Folder::Drafts => self.page = Page::Drafts,
+            preferences::Folder::Sent => self.page = Page::Sent,
+            preferences::Folder::Archived => self.page = Page::Archived,
+            preferences::Folder::Trash => self.page = Page::Trash,
+            preferences::Folder::Notes => self.page = Page::Notes,
+            preferences::Folder::Contacts => self.page = Page::Contacts,
+            preferences::Folder::Notifications => self.page = Page::Notifications,
+        }
+
+        let open_drafts = match self.db.take_open_drafts() {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Failed to load the drafts open last time: {}", e);
+                return;
+            }
+        };
+        if !self.preferences.reopen_compose_windows {
+            return;
+        }
+        let drafts: Vec<db::Draft> = self
+            .drafts
+            .iter()
+            .filter(|draft| open_drafts.contains(&draft.id))
+            .cloned()
+            .collect();
+        for draft in drafts {
+            open_draft(self, draft);
+        }
+    }
+
+    /// Keeps the inbox search as a folder called `name`.
+    fn save_search(&mut self, name: &str) {
+        let query = self.inbox_filter.query.trim().to_string();
+        match self.db.save_search(name, &query) {
+            Ok(id) => {
+                self.refresh_saved_searches();
+                self.active_search = Some(id);
+            }
+            Err(e) => error!("Failed to save search {}: {}", name, e),
+        }
+    }
+
+    fn delete_saved_search(&mut self, id: i64) {
+        if let Err(e) = self.db.delete_saved_search(id) {
+            error!("Failed to delete saved search {}: {}", id, e);
+            return;
+        }
+        if self.active_search == Some(id) {
+            self.active_search = None;
+        }
+        self.refresh_saved_searches();
+    }
+
+    fn account_color(&self, pubkey: &str) -> Color32 {
+        self.account_colors
+            .color(pubkey, &self.account_manager.loaded_keys)
+    }
+
+    /// The account of ours the inbox thread `root_id` is with, if we can tell.
+    fn thread_account(&mut self, root_id: &str) -> Option<String> {
+        if let Some(account) = self.thread_accounts.get(root_id) {
+            return account.clone();
+        }
+        let account = match self.db.get_thread_account(root_id) {
+            Ok(account) => account,
+            Err(e) => {
+                error!("Failed to find the account of thread {}: {}", root_id, e);
+                None
+            }
+        };
+        self.thread_accounts
+            .insert(root_id.to_string(), account.clone());
+        account
+    }
+
+    fn refresh_archived(&mut self) {
+        let filter = db::MessageFilter {
+            archived: true,
+            ..Default::default()
+        };
+        match self
+            .db
+            .get_top_level_messages(self.mailbox.as_deref(), &filter)
+        {
+            Ok(entries) => self.archived_entries = entries,
+            Err(e) => error!("Failed to load archived threads: {}", e),
+        }
+        self.show_pending_actions();
+    }
+
+    fn refresh_unread_counts(&mut self) {
+        let accounts: Vec<String> = self
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|k| k.public_key().to_hex())
+            .collect();
+        self.unread_counts.clear();
+        for account in &accounts {
+            match self.db.count_unread(std::slice::from_ref(account)) {
+                Ok(count) => {
+                    self.unread_counts.insert(account.clone(), count);
+                }
+                Err(e) => error!("Failed to count unread messages for {}: {}", account, e),
+            }
+        }
+        match self.db.count_unread(&accounts) {
+            Ok(count) => self.unread_total = count,
+            Err(e) => error!("Failed to count unread messages: {}", e),
+        }
+        match self.db.count_gift_wraps() {
+            Ok(counts) => self.stored_counts = counts,
+            Err(e) => error!("Failed to count stored gift wraps: {}", e),
+        }
+    }
+
+    fn record_relay_count(&mut self, sub_id: &str, count: u64) {
+        let Some(account) = self.count_requests.get(sub_id) else {
+            return;
+        };
+        let total = self.relay_counts.entry(account.clone()).or_default();
+        *total = (*total).max(count);
+    }
+
+    /// (stored, total) while relays report more messages for `account` than we have yet.
+    fn sync_progress(&self, account: &str) -> Option<(i64, u64)> {
+        let total = *self.relay_counts.get(account)?;
+        let stored = self.stored_counts.get(account).copied().unwrap_or_default();
+        ((stored as u64) < total).then_some((stored, total))
+    }
+
+    /// Show the folders of one account, or of all of them with `None`.
+    fn select_mailbox(&mut self, mailbox: Option<String>) {
+        if let Some(pubkey) = &mailbox {
+            if let Some(keys) = self
+                .account_manager
+                .loaded_keys
+                .iter()
+                .find(|k| k.public_key().to_hex() == *pubkey)
+            {
+                self.active_account = Some(keys.clone());
+            }
+        }
+        self.mailbox = mailbox;
+        if self.active_search.take().is_some() {
+            self.inbox_filter = Default::default();
+        }
+        self.refresh_table_entries();
+        self.refresh_archived();
+        self.refresh_trash();
+        self.refresh_sent();
+        self.refresh_drafts();
+        self.refresh_notes();
+    }
+
+    fn set_thread_archived(&mut self, root_id: &str, archived: bool) {
+        self.perform(actions::Action::Archive {
+            root_id: root_id.to_string(),
+            archived,
+        });
+    }
+
+    fn toggle_star(&mut self, id: &str) {
+        self.perform(actions::Action::Star {
+            event_id: id.to_string(),
+            starred: !self.starred_ids.contains(id),
+        });
+    }
+
+    /// Shows `action` done and saves it in the background.
+    fn perform(&mut self, action: actions::Action) {
+        self.show_action(&action);
+        self.actions.perform(action, &mut self.db);
+    }
+
+    /// Changes what's on screen the way `action` will change the database.
+    fn show_action(&mut self, action: &actions::Action) {
+        match action {
+            actions::Action::Star { event_id, starred } => {
+                if *starred {
+                    self.starred_ids.insert(event_id.clone());
+                } else {
+                    self.starred_ids.remove(event_id);
+                }
+            }
+            actions::Action::Archive {
+                root_id,
+                archived: true,
+            } => {
+                if let Some(entry) = self.inbox.remove(root_id) {
+                    self.archived_entries.retain(|e| e.id != *root_id);
+                    self.archived_entries.insert(0, entry);
+                }
+            }
+            actions::Action::Archive { root_id, .. } => {
+                self.archived_entries.retain(|e| e.id != *root_id);
+            }
+            actions::Action::Trash { event_id, .. } => {
+                self.events.retain(|ev| ev.id.to_string() != *event_id);
+                self.inbox.remove(event_id);
+            }
+        }
+    }
+
+    /// Shows the actions still being saved again, over lists just reloaded without them.
+    fn show_pending_actions(&mut self) {
+        for action in self.actions.pending().to_vec() {
+            self.show_action(&action);
+        }
+    }
+
+    /// Picks up saved actions, and takes back the ones that failed by reloading what the
+    /// database has.
+    fn process_actions(&mut self) {
+        for outcome in self.actions.process() {
+            let failed = match outcome.result {
+                Ok(changed) => {
+                    if !changed.is_empty() {
+                        self.flag_publisher.changed(changed);
+                    }
+                    false
+                }
+                Err(e) => {
+                    let message = format!("{}: {}", outcome.action.failure(), e);
+                    error!("{}", message);
+                    self.toasts.error(message);
+                    true
+                }
+            };
+            match outcome.action {
+                actions::Action::Star { .. } if failed => self.refresh_flags(),
+                actions::Action::Star { .. } => {
+                    if self.inbox_filter.starred {
+                        self.refresh_table_entries();
+                    }
+                }
+                actions::Action::Archive { .. } => {
+                    self.refresh_table_entries();
+                    self.refresh_archived();
+                }
+                actions::Action::Trash { .. } => {
+                    self.refresh_table_entries();
+                    self.refresh_trash();
+                    self.refresh_sent();
+                }
+            }
+        }
+    }
+
+    /// Marks messages read now, or once the delay set in the preferences has passed.
+    fn schedule_mark_read(&mut self, event_ids: Vec<String>) {
+        match self.preferences.mark_read_delay() {
+            Some(delay) => {
+                let due = std::time::Instant::now() + delay;
+                for event_id in event_ids {
+                    self.state.pending_read.entry(event_id).or_insert(due);
+                }
+            }
+            None => self.mark_read(&event_ids),
+        }
+    }
+
+    fn process_pending_read(&mut self) {
+        if self.state.pending_read.is_empty() {
+            return;
+        }
+        // Leaving the thread early keeps the rest unread.
+        if self.page != Page::Post && self.thread_windows.is_empty() {
+            self.state.pending_read.clear();
+            return;
+        }
+        let now = std::time::Instant::now();
+        let due: Vec<String> = self
+            .state
+            .pending_read
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(event_id, _)| event_id.clone())
+            .collect();
+        self.state.pending_read.retain(|_, due| *due > now);
+        if let Some(next) = self.state.pending_read.values().min() {
+            self.repaint.at(*next);
+        }
+        if !due.is_empty() {
+            self.mark_read(&due);
+        }
+    }
+
+    /// Runs once as the app quits: sends whatever is still queued and waits a moment for the
+    /// relays to take it, keeps open compose windows as drafts and closes relays and the
+    /// database cleanly.
+    fn shutdown(&mut self) {
+        info!("Shutting down");
+        // Before unlocking there's nothing queued and no database to write to.
+        if self.status == HootStatus::Ready {
+            self.mail_merge.flush(&mut self.relays);
+            if !self.relays.wait_for_acks(SHUTDOWN_ACK_WAIT) {
+                warn!("Quitting before a relay took every message sent");
+            }
+            for state in self.state.compose_window.values_mut() {
+                // A send no relay took stays a draft, to send again next time.
+                let unsent = state.sending.as_ref().is_some_and(|pending| {
+                    !pending.event_ids.iter().any(|event_id| {
+                        self.relays.acks(event_id).is_some_and(|acks| {
+                            acks.values().any(|ack| *ack == relay::Ack::Accepted)
+                        })
+                    })
+                });
+                if unsent {
+                    state.sending = None;
+                }
+                if let Err(e) = state.save_draft(&self.db) {
+                    error!("Failed to save open message as a draft: {}", e);
+                }
+            }
+            let open_drafts: Vec<i64> = self
+                .state
+                .compose_window
+                .values()
+                .filter(|state| state.sending.is_none())
+                .filter_map(|state| state.draft_id)
+                .collect();
+            if let Err(e) = self.db.set_open_drafts(&open_drafts) {
+                error!("Failed to remember the open drafts: {}", e);
+            }
+            if let Err(e) = self.db.checkpoint() {
+                error!("Failed to checkpoint the database: {}", e);
+            }
+        }
+        self.relays.close_all();
+        self.payments.close();
+    }
+
+    fn mark_read(&mut self, event_ids: &[String]) {
+        if let Err(e) = self.db.mark_read(event_ids) {
+            error!("Failed to mark {} messages as read: {}", event_ids.len(), e);
+            return;
+        }
+        self.flag_publisher.changed(event_ids.iter().cloned());
+        self.refresh_unread_counts();
+    }
+
+    /// The account whose notes to self are shown: the selected mailbox, the active account,
+    /// or the first one loaded.
+    fn notes_account(&self) -> Option<nostr::Keys> {
+        let loaded = &self.account_manager.loaded_keys;
+        self.mailbox
+            .as_ref()
+            .and_then(|pubkey| {
+                loaded
+                    .iter()
+                    .find(|k| k.public_key().to_hex() == *pubkey)
+                    .cloned()
+            })
+            .or_else(|| self.active_account.clone())
+            .or_else(|| loaded.first().cloned())
+    }
+
+    fn refresh_notes(&mut self) {
+        let Some(keys) = self.notes_account() else {
+            self.notes_entries.clear();
+            return;
+        };
+        match self.db.get_notes_to_self(&keys.public_key().to_hex()) {
+            Ok(entries) => self.notes_entries = entries,
+            Err(e) => error!("Failed to load notes to self: {}", e),
+        }
+    }
+
+    fn is_blocked(&self, pubkey: &str) -> bool {
+        self.blocked_pubkeys.iter().any(|pk| pk == pubkey)
+    }
+
+    /// Block a pubkey and hide everything they've already sent us.
+    fn block_pubkey(&mut self, pubkey: &str) {
+        if let Err(e) = self.db.block_pubkey(pubkey) {
+            error!("Failed to block {}: {}", pubkey, e);
+            return;
+        }
+        if !self.is_blocked(pubkey) {
+            self.blocked_pubkeys.insert(0, pubkey.to_string());
+        }
+        self.refresh_after_blocking();
+    }
+
+    fn unblock_pubkey(&mut self, pubkey: &str) {
+        if let Err(e) = self.db.unblock_pubkey(pubkey) {
+            error!("Failed to unblock {}: {}", pubkey, e);
+            return;
+        }
+        self.blocked_pubkeys.retain(|pk| pk != pubkey);
+        self.refresh_after_blocking();
+    }
+
+    /// Reloads everything that leaves blocked senders out.
+    fn refresh_after_blocking(&mut self) {
+        self.refresh_table_entries();
+        if let Err(e) = self
+            .contacts_manager
+            .load_from_db(&self.db, &mut self.profile_metadata)
+        {
+            error!("Failed to reload contacts: {}", e);
+        }
+        self.notifications.refresh(&self.db);
+    }
+
+    fn refresh_trash(&mut self) {
+        match self.db.get_trash_messages(self.mailbox.as_deref()) {
+            Ok(entries) => self.trash_entries = entries,
+            Err(e) => error!("Failed to load trash entries: {}", e),
+        }
+    }
+
+    /// Picks up flags changed behind our back, like ones synced from relays.
+    fn refresh_flags(&mut self) {
+        match self.db.get_starred_ids() {
+            Ok(ids) => self.starred_ids = ids,
+            Err(e) => error!("Failed to load starred messages: {}", e),
+        }
+        self.show_pending_actions();
+        // This takes in the unread counts too.
+        self.refresh_table_entries();
+        self.refresh_archived();
+    }
+
+    /// Works out what the retention rules do, on the worker when there is one, and shows or
+    /// carries it out.
+    fn run_retention(&mut self, preview: bool) {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(worker) = &self.db_worker {
+            worker.request(db_worker::DbRequest::PlanRetention {
+                policy: self.janitor.policy.clone(),
+                now,
+                preview,
+            });
+            return;
+        }
+        if let Some(plan) = self.janitor.plan(&self.db, now) {
+            self.retention_planned(self.janitor.policy.clone(), plan, now, preview);
+        }
+    }
+
+    fn retention_planned(
+        &mut self,
+        policy: hoot_core::retention::RetentionPolicy,
+        plan: hoot_core::retention::RetentionPlan,
+        now: i64,
+        preview: bool,
+    ) {
+        if policy != self.janitor.policy {
+            // The rules changed while the plan was worked out; a preview of the old ones is no
+            // use, and a run goes by the new ones.
+            if !preview {
+                self.run_retention(false);
+            }
+            return;
+        }
+        if preview {
+            self.janitor.preview = Some(plan);
+        } else if self.janitor.apply(&mut self.db, &plan, now) {
+            self.refresh_after_retention();
+        }
+    }
+
+    /// Reloads the folders the retention rules may have changed.
+    fn refresh_after_retention(&mut self) {
+        self.refresh_table_entries();
+        self.refresh_archived();
+        self.refresh_trash();
+        self.refresh_sent();
+    }
+
+    fn refresh_sent(&mut self) {
+        match self.db.get_sent_messages(self.mailbox.as_deref()) {
+            Ok(entries) => self.sent_entries = entries,
+            Err(e) => error!("Failed to load sent messages: {}", e),
+        }
+    }
+
+    /// Sent messages some relay still has to confirm.
+    fn sending_count(&self) -> usize {
+        self.sent_entries
+            .iter()
+            .filter(|sent| still_sending(sent))
+            .count()
+    }
+
+    /// Relays that delivered a message, with when each did first. See
+    /// [`db::Db::get_message_sources`].
+    fn event_sources(&self, event_id: &str) -> Vec<(String, i64)> {
+        self.db.get_message_sources(event_id).unwrap_or_else(|e| {
+            error!("Failed to load relays for {}: {}", event_id, e);
+            Vec::new()
+        })
+    }
+
+    /// Whether every relay that delivered a message or gift wrap, `urls`, is one the user
+    /// distrusts. Mail no relay delivered, like what we sent, doesn't count.
+    fn only_distrusted<'a>(&self, urls: impl IntoIterator<Item = &'a str>) -> bool {
+        if !self.relays.has_distrusted() {
+            return false;
+        }
+        let mut urls = urls.into_iter().peekable();
+        urls.peek().is_some()
+            && urls.all(|url| self.relays.trust(url) == relay::RelayTrust::Distrusted)
+    }
+
+    /// Asks the relays how many gift wraps they hold for `pubkey`, so the user can tell whether
+    /// they picked the right relays before the account is added.
+    pub fn start_account_preflight(&mut self, pubkey: &nostr::PublicKey) {
+        let p_tag = nostr::SingleLetterTag {
+            character: nostr::Alphabet::P,
+            uppercase: false,
+        };
+        let filter = nostr::Filter::new()
+            .kind(nostr::Kind::GiftWrap)
+            .custom_tag(p_tag, [pubkey.to_hex()]);
+        self.relays.start_preflight(pubkey.to_hex(), filter);
+    }
+
+    /// Update the gift-wrap subscription to include all loaded accounts.
+    pub fn update_gift_wrap_subscription(&mut self) {
+        if self.account_manager.loaded_keys.is_empty() {
+            return;
+        }
+
+        let public_keys: Vec<nostr::PublicKey> = self
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|k| k.public_key())
+            .collect();
+
+        let p_tag = nostr::SingleLetterTag {
+            character: nostr::Alphabet::P,
+            uppercase: false,
+        };
+        let filter = nostr::Filter::new()
+            .kind(nostr::Kind::GiftWrap)
+            .custom_tag(p_tag, public_keys.clone());
+
+        // Ask for each account's total so the sidebar can show sync progress.
+        self.count_requests.clear();
+        let counts = public_keys
+            .iter()
+            .map(|pk| {
+                let mut count = relay::Subscription::default();
+                count.filter(
+                    nostr::Filter::new()
+                        .kind(nostr::Kind::GiftWrap)
+                        .custom_tag(p_tag, [pk.to_hex()]),
+                );
+                self.count_requests.insert(count.id.clone(), pk.to_hex());
+                count
+            })
+            .collect();
+        if let Err(e) = self.relays.set_counts(counts) {
+            error!("Failed to request message counts: {}", e);
+        }
+
+        // Relays that support NIP-77 sync just the wraps we're missing. Everyone else gets a
+        // REQ from a bit before our newest wrap, as wraps are backdated by up to two days.
+        let mut live_filter = filter.clone();
+        match self.db.get_gift_wrap_items() {
+            Ok(items) => {
+                if let Some(newest) = items.iter().map(|(created_at, _)| *created_at).max() {
+                    let tweak = nostr::nips::nip59::RANGE_RANDOM_TIMESTAMP_TWEAK.end;
+                    let since = nostr::Timestamp::from_secs(newest.saturating_sub(tweak));
+                    live_filter = live_filter.since(since);
+                }
+                self.relays.set_sync(filter, items);
+            }
+            Err(e) => error!("Failed to load stored gift wraps for sync: {}", e),
+        }
+
+        let mut gw_sub = relay::Subscription::default();
+        gw_sub.filter(live_filter);
+        gw_sub.filter(zap::receipts(public_keys.clone()));
+        // Our thread flags come back with the mail, so a new install gets both.
+        let newest_flags = self.db.newest_flag_sync().unwrap_or_else(|e| {
+            error!("Failed to look up the newest synced flags: {}", e);
+            None
+        });
+        gw_sub.filter(flag_sync::filter(public_keys, newest_flags));
+
+        match self.relays.add_subscription(gw_sub) {
+            Ok(_) => debug!("Updated gift-wrap subscription"),
+            Err(e) => error!("Failed to update gift-wrap subscription: {}", e),
+        }
+    }
+
+    /// Resolve the best display name for a pubkey: petname > display_name > name.
+    fn resolve_name(&self, pubkey: &str) -> Option<String> {
+        self.names
+            .resolve(pubkey, &self.contacts_manager, &self.profile_metadata)
+    }
+
+    /// The name to show for `pubkey`, or the key itself without one, with a badge for
+    /// verified contacts.
+    fn display_name(&self, pubkey: &str) -> String {
+        let name = self
+            .resolve_name(pubkey)
+            .unwrap_or_else(|| pubkey.to_string());
+        if self.contacts_manager.is_verified(pubkey) {
+            format!("{} {}", name, style::VERIFIED_BADGE)
+        } else {
+            name
         }
-        None
     }
 }
 
 impl eframe::App for Hoot {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let _frame_timer = metrics::start_timer(metrics::FRAME_TIME);
+        self.frame_overlay.on_new_frame(
+            ctx.input(|i| i.time),
+            frame.info().cpu_usage.map(|seconds| seconds * 1000.0),
+        );
+        self.repaint.begin_frame(ctx);
         update_app(self, ctx);
         render_app(self, ctx);
+        self.toasts.show(ctx);
+        self.frame_overlay.show(ctx);
+        if let Some(next) = self.relays.next_keepalive() {
+            self.repaint.at(next);
+        }
+        self.repaint.end_frame(ctx);
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(
+            storage,
+            style::HIGH_CONTRAST_KEY,
+            &self.state.settings.high_contrast,
+        );
+        eframe::set_value(
+            storage,
+            metrics::METRICS_ENABLED_KEY,
+            &metrics::is_enabled(),
+        );
+        eframe::set_value(storage, logging::LOG_FILTER_KEY, &logging::filter());
+        eframe::set_value(
+            storage,
+            downloads::DOWNLOAD_DIR_KEY,
+            &self.downloads.download_dir,
+        );
+        eframe::set_value(storage, uploads::UPLOAD_SERVER_KEY, &self.uploads.server);
+        eframe::set_value(
+            storage,
+            relay_presets::RELAY_PRESETS_KEY,
+            &self.relay_presets.list,
+        );
+        eframe::set_value(
+            storage,
+            relay_presets::PRESET_CHOICES_KEY,
+            &self.relay_presets.choices,
+        );
+        eframe::set_value(storage, bridge::BRIDGE_CONFIG_KEY, &self.bridge);
+        eframe::set_value(storage, preferences::PREFERENCES_KEY, &self.preferences);
+        eframe::set_value(storage, translate::TRANSLATION_KEY, &self.translator.config);
+        eframe::set_value(
+            storage,
+            automation::AUTOMATION_KEY,
+            &self.automations.config,
+        );
+        eframe::set_value(
+            storage,
+            account_colors::ACCOUNT_COLORS_KEY,
+            &self.account_colors,
+        );
+        if let Some(folder) = preferences::Folder::of(&self.page, self.active_search) {
+            eframe::set_value(storage, preferences::LAST_FOLDER_KEY, &folder);
+        }
+        eframe::set_value(storage, janitor::RETENTION_KEY, &self.janitor.policy);
+        eframe::set_value(
+            storage,
+            ui::frame_overlay::FRAME_OVERLAY_KEY,
+            &self.frame_overlay.visible,
+        );
+        eframe::set_value(
+            storage,
+            threading::SPLIT_ON_SUBJECT_CHANGE_KEY,
+            &self.split_threads,
+        );
+        // Until the first frame runs the pool is still empty, don't wipe the saved list.
+        if self.status != HootStatus::PreUnlock {
+            eframe::set_value(storage, relay::RELAYS_KEY, &self.relays.urls());
+            eframe::set_value(storage, relay::RELAY_WINDOWS_KEY, self.relays.windows());
+            eframe::set_value(storage, relay::RELAY_TRUST_KEY, self.relays.trusts());
+            eframe::set_value(storage, relay::RELAY_USAGE_KEY, &self.relays.usages());
+            eframe::set_value(
+                storage,
+                relay::PING_SETTINGS_KEY,
+                &self.relays.ping_settings(),
+            );
+            eframe::set_value(storage, relay::PAID_RELAYS_KEY, &self.relay_info.paid);
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.shutdown();
     }
 }
 
@@ -0,0 +1,105 @@
+//! The one place names for pubkeys are worked out, so a petname shows the same everywhere:
+//! compose, threads, notifications and search. Names are cached until the contacts or the
+//! profile metadata they came from change.
+
+use crate::profile_metadata::{ProfileMetadata, ProfileOption};
+use crate::ui::contacts::ContactsManager;
+use nostr::{PublicKey, ToBech32};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// The name someone goes by: the petname we gave them, else their display name, else their
+/// name.
+pub fn best_name<'a>(petname: Option<&'a str>, metadata: &'a ProfileMetadata) -> Option<&'a str> {
+    petname
+        .or(metadata.display_name.as_deref())
+        .or(metadata.name.as_deref())
+}
+
+/// The start of `pubkey`'s npub, for someone without a name.
+pub fn short_npub(pubkey: &str) -> String {
+    PublicKey::parse(pubkey)
+        .ok()
+        .and_then(|pk| pk.to_bech32().ok())
+        .map(|npub| format!("{}…", &npub[..16]))
+        .unwrap_or_else(|| pubkey.to_string())
+}
+
+#[derive(Default)]
+pub struct NameResolver {
+    /// Names by pubkey in hex, `None` for the ones we don't have a name for.
+    names: RefCell<HashMap<String, Option<String>>>,
+    /// The contacts version the names are from.
+    contacts: Cell<u64>,
+}
+
+impl NameResolver {
+    /// `pubkey`'s name, from their contact if they're one of ours, else from their profile.
+    pub fn resolve(
+        &self,
+        pubkey: &str,
+        contacts: &ContactsManager,
+        profiles: &HashMap<String, ProfileOption>,
+    ) -> Option<String> {
+        if self.contacts.replace(contacts.version()) != contacts.version() {
+            self.names.borrow_mut().clear();
+        }
+        if let Some(name) = self.names.borrow().get(pubkey) {
+            return name.clone();
+        }
+        let name = match (contacts.find_contact(pubkey), profiles.get(pubkey)) {
+            (Some(contact), _) => best_name(contact.petname.as_deref(), &contact.metadata),
+            (None, Some(ProfileOption::Some(metadata))) => best_name(None, metadata),
+            (None, _) => None,
+        }
+        .map(str::to_string);
+        self.names
+            .borrow_mut()
+            .insert(pubkey.to_string(), name.clone());
+        name
+    }
+
+    /// Forgets `pubkey`'s name, as their profile metadata changes.
+    pub fn forget(&self, pubkey: &str) {
+        self.names.borrow_mut().remove(pubkey);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    #[test]
+    fn petnames_win_and_changes_show() -> anyhow::Result<()> {
+        let db = Db::new_in_memory()?;
+        let names = NameResolver::default();
+        let mut contacts = ContactsManager::new();
+        let mut profiles = HashMap::new();
+        let pubkey = nostr::Keys::generate().public_key().to_hex();
+        assert_eq!(names.resolve(&pubkey, &contacts, &profiles), None);
+
+        let metadata = ProfileMetadata {
+            name: Some("alice".into()),
+            ..Default::default()
+        };
+        profiles.insert(pubkey.clone(), ProfileOption::Some(metadata.clone()));
+        names.forget(&pubkey);
+        assert_eq!(
+            names.resolve(&pubkey, &contacts, &profiles).as_deref(),
+            Some("alice")
+        );
+
+        contacts.add_contact(&db, pubkey.clone(), Some("Al".into()), metadata)?;
+        assert_eq!(
+            names.resolve(&pubkey, &contacts, &profiles).as_deref(),
+            Some("Al")
+        );
+        contacts.update_petname(&db, &pubkey, None)?;
+        assert_eq!(
+            names.resolve(&pubkey, &contacts, &profiles).as_deref(),
+            Some("alice")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! `nostr:` links (NIP-21), opened from a browser or another app.
+//!
+//! Profiles open a compose window addressed to them, events open their thread.
+
+use anyhow::{bail, Context, Result};
+use nostr::nips::nip19::{Nip19Event, Nip19Profile};
+use nostr::{EventId, FromBech32, PublicKey};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UriAction {
+    Compose(PublicKey),
+    OpenThread(EventId),
+}
+
+pub fn parse(uri: &str) -> Result<UriAction> {
+    let uri = uri.trim();
+    let entity = uri
+        .get(..6)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("nostr:"))
+        .map(|_| &uri[6..])
+        .context("Not a nostr: link")?
+        .trim_start_matches("//");
+
+    let action = if entity.starts_with("npub1") {
+        UriAction::Compose(PublicKey::from_bech32(entity)?)
+    } else if entity.starts_with("nprofile1") {
+        UriAction::Compose(Nip19Profile::from_bech32(entity)?.public_key)
+    } else if entity.starts_with("note1") {
+        UriAction::OpenThread(EventId::from_bech32(entity)?)
+    } else if entity.starts_with("nevent1") {
+        UriAction::OpenThread(Nip19Event::from_bech32(entity)?.event_id)
+    } else {
+        bail!("Hoot can't open this kind of nostr: link");
+    };
+    Ok(action)
+}
+
+/// The `nostr:` link Hoot was started with, if any.
+pub fn from_args() -> Option<String> {
+    std::env::args().skip(1).find(|arg| {
+        arg.get(..6)
+            .is_some_and(|s| s.eq_ignore_ascii_case("nostr:"))
+    })
+}
+
+/// Makes the system open `nostr:` links with this copy of Hoot.
+pub fn register_handler() -> Result<()> {
+    let exe = std::env::current_exe().context("Couldn't find the Hoot executable")?;
+
+    #[cfg(target_os = "linux")]
+    {
+        let applications = std::env::var_os("XDG_DATA_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| std::path::PathBuf::from(home).join(".local/share"))
+            })
+            .context("Couldn't find the applications folder")?
+            .join("applications");
+        std::fs::create_dir_all(&applications)?;
+        let desktop_entry = format!(
+            "[Desktop Entry]\nType=Application\nName=Hoot\nExec=\"{}\" %u\n\
+             MimeType=x-scheme-handler/nostr;\nNoDisplay=true\n",
+            exe.display()
+        );
+        std::fs::write(applications.join("hoot-nostr.desktop"), desktop_entry)?;
+        run(
+            "xdg-mime",
+            &["default", "hoot-nostr.desktop", "x-scheme-handler/nostr"],
+        )
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let key = r"HKCU\Software\Classes\nostr";
+        let command = format!("\"{}\" \"%1\"", exe.display());
+        run("reg", &["add", key, "/ve", "/d", "URL:nostr", "/f"])?;
+        run("reg", &["add", key, "/v", "URL Protocol", "/d", "", "/f"])?;
+        run(
+            "reg",
+            &[
+                "add",
+                &format!(r"{}\shell\open\command", key),
+                "/ve",
+                "/d",
+                &command,
+                "/f",
+            ],
+        )
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        let _ = exe;
+        bail!("On this system the Hoot app bundle declares the nostr: scheme itself")
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Couldn't run {}", program))?;
+    if !status.success() {
+        bail!("{} failed ({})", program, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{Keys, ToBech32};
+
+    #[test]
+    fn turns_links_into_actions() -> Result<()> {
+        let pubkey = Keys::generate().public_key();
+        assert_eq!(
+            parse(&format!("nostr:{}", pubkey.to_bech32()?))?,
+            UriAction::Compose(pubkey)
+        );
+        assert_eq!(
+            parse(&format!("NOSTR://{}", pubkey.to_bech32()?))?,
+            UriAction::Compose(pubkey)
+        );
+
+        let event_id = EventId::from_hex("ab".repeat(32))?;
+        assert_eq!(
+            parse(&format!("nostr:{}", event_id.to_bech32()?))?,
+            UriAction::OpenThread(event_id)
+        );
+
+        assert!(parse(&pubkey.to_bech32()?).is_err());
+        assert!(parse("nostr:nsec1whatever").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Pays relay and upload fees and zaps through the wallet the user connected in Settings, and
+//! hands the proofs of payment back to whatever asked for them.
+
+use crate::db::Db;
+use crate::uploads::UploadId;
+use crate::wallet::{Outcome, Request, Wallet, WalletConnection};
+use anyhow::{anyhow, Result};
+use eframe::egui;
+use nostr::Keys;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// What a payment was for.
+#[derive(Clone)]
+pub enum Purpose {
+    /// The fee of the relay at this url.
+    RelayFee(String),
+    /// An upload the media host wants paying for, with the keys it was started with.
+    Upload { id: UploadId, keys: Keys },
+    /// A zap on the message with this id.
+    Zap(String),
+    /// The invoice sent as the payload of the message with this id.
+    Invoice { message: String, invoice: String },
+}
+
+/// A payment the wallet made.
+pub struct Paid {
+    pub purpose: Purpose,
+    pub preimage: String,
+}
+
+#[derive(Default)]
+pub struct Payments {
+    wallet: Option<Wallet>,
+    /// Payments sent to the wallet by request id, `None` for balance requests.
+    pending: HashMap<String, Option<Purpose>>,
+    /// Payments the wallet didn't answer in time. It may still make them, so a late answer
+    /// is still handed back.
+    unanswered: HashMap<String, Option<Purpose>>,
+    /// The wallet's balance in millisatoshis, once it told us.
+    pub balance: Option<u64>,
+    /// Why the last request failed, until the next one succeeds.
+    pub error: Option<String>,
+}
+
+impl Payments {
+    /// Connects to the wallet saved in `db`, if there is one.
+    pub fn load(&mut self, db: &Db, ctx: &egui::Context) {
+        let uri = match db.get_wallet_connection() {
+            Ok(Some(uri)) => uri,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to load the wallet connection: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = WalletConnection::parse(&uri).and_then(|c| self.start(c, ctx)) {
+            error!("Failed to connect to the saved wallet: {}", e);
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Connects to the wallet in `uri` and saves it for next time.
+    pub fn connect(&mut self, db: &Db, uri: &str, ctx: &egui::Context) -> Result<()> {
+        let connection = WalletConnection::parse(uri)?;
+        db.set_wallet_connection(&connection.uri())?;
+        self.disconnect_wallet();
+        self.start(connection, ctx)
+    }
+
+    /// Forgets the connected wallet.
+    pub fn disconnect(&mut self, db: &Db) -> Result<()> {
+        db.delete_wallet_connection()?;
+        self.disconnect_wallet();
+        info!("Disconnected the wallet");
+        Ok(())
+    }
+
+    pub fn connection(&self) -> Option<&WalletConnection> {
+        self.wallet.as_ref().map(|wallet| &wallet.connection)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.wallet
+            .as_ref()
+            .is_some_and(|wallet| wallet.is_connected())
+    }
+
+    /// Asks the wallet to pay `invoice` for `purpose`.
+    pub fn pay(&mut self, invoice: &str, purpose: Purpose) {
+        let request = Request::PayInvoice {
+            invoice: invoice.trim().to_string(),
+        };
+        self.send(&request, Some(purpose));
+    }
+
+    pub fn refresh_balance(&mut self) {
+        self.send(&Request::GetBalance {}, None);
+    }
+
+    /// Whether the fee of the relay at `url` is being paid.
+    pub fn paying_relay(&self, url: &str) -> bool {
+        self.pending
+            .values()
+            .any(|purpose| matches!(purpose, Some(Purpose::RelayFee(u)) if u == url))
+    }
+
+    /// Whether the upload `id` is being paid for.
+    pub fn paying_upload(&self, id: UploadId) -> bool {
+        self.pending
+            .values()
+            .any(|purpose| matches!(purpose, Some(Purpose::Upload { id: i, .. }) if *i == id))
+    }
+
+    /// Whether the zap on the message `id` is being paid.
+    pub fn paying_zap(&self, id: &str) -> bool {
+        self.pending
+            .values()
+            .any(|purpose| matches!(purpose, Some(Purpose::Zap(z)) if z == id))
+    }
+
+    /// Whether the wallet went quiet while paying the zap on the message `id`, so it may or
+    /// may not have paid.
+    pub fn unanswered_zap(&self, id: &str) -> bool {
+        self.unanswered
+            .values()
+            .any(|purpose| matches!(purpose, Some(Purpose::Zap(z)) if z == id))
+    }
+
+    /// Whether the invoice in the message `id` is being paid.
+    pub fn paying_invoice(&self, id: &str) -> bool {
+        self.pending.values().any(
+            |purpose| matches!(purpose, Some(Purpose::Invoice { message, .. }) if message == id),
+        )
+    }
+
+    /// Takes in the wallet's answers and returns the payments it made.
+    pub fn process_queue(&mut self, ctx: &egui::Context) -> Vec<Paid> {
+        let Some(wallet) = &mut self.wallet else {
+            return Vec::new();
+        };
+        let wake_ctx = ctx.clone();
+        let outcomes = wallet.poll(move || wake_ctx.request_repaint());
+        // Requests time out without the relay waking us up.
+        if wallet.is_waiting() {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+
+        let mut paid = Vec::new();
+        for (request_id, outcome) in outcomes {
+            let purpose = self
+                .pending
+                .remove(&request_id)
+                .or_else(|| self.unanswered.remove(&request_id));
+            let Some(purpose) = purpose else {
+                continue;
+            };
+            match (outcome, purpose) {
+                (Outcome::Paid { preimage }, Some(purpose)) => {
+                    info!("The wallet paid request {}", request_id);
+                    self.error = None;
+                    paid.push(Paid { purpose, preimage });
+                    // Paying changed it.
+                    self.refresh_balance();
+                }
+                (Outcome::Balance { msats }, _) => {
+                    self.error = None;
+                    self.balance = Some(msats);
+                }
+                (Outcome::Failed(e), _) => {
+                    error!("The wallet failed request {}: {}", request_id, e);
+                    self.error = Some(e);
+                }
+                (Outcome::Unanswered, purpose) => {
+                    warn!("The wallet hasn't answered request {}", request_id);
+                    if purpose.is_some() {
+                        self.error = Some(
+                            "The wallet didn't answer in time. The payment may still go \
+                             through, check your wallet before paying again."
+                                .to_string(),
+                        );
+                    }
+                    self.unanswered.insert(request_id, purpose);
+                }
+                (outcome, None) => error!("Unexpected answer to a balance request: {:?}", outcome),
+            }
+        }
+        paid
+    }
+
+    pub fn close(&mut self) {
+        if let Some(wallet) = &mut self.wallet {
+            wallet.close();
+        }
+    }
+
+    fn start(&mut self, connection: WalletConnection, ctx: &egui::Context) -> Result<()> {
+        let wake_ctx = ctx.clone();
+        self.wallet = Some(Wallet::connect(connection, move || {
+            wake_ctx.request_repaint()
+        })?);
+        self.refresh_balance();
+        Ok(())
+    }
+
+    fn send(&mut self, request: &Request, purpose: Option<Purpose>) {
+        let result = match &mut self.wallet {
+            Some(wallet) => wallet.send(request),
+            None => Err(anyhow!("no wallet is connected")),
+        };
+        match result {
+            Ok(request_id) => {
+                self.pending.insert(request_id, purpose);
+            }
+            Err(e) => {
+                error!("Couldn't ask the wallet: {}", e);
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn disconnect_wallet(&mut self) {
+        self.close();
+        self.wallet = None;
+        self.pending.clear();
+        self.unanswered.clear();
+        self.balance = None;
+        self.error = None;
+    }
+}
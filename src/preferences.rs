@@ -0,0 +1,124 @@
+//! Workflow preferences that change what the compose and post actions do, how times are shown
+//! and what Hoot opens on.
+
+use crate::mail_event::MAX_WRAP_FUZZ;
+use crate::Page;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const PREFERENCES_KEY: &str = "workflow_preferences";
+/// The folder open when the app last saved its state.
+pub const LAST_FOLDER_KEY: &str = "last_folder";
+
+/// What to show once the database is unlocked.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StartupPage {
+    Inbox,
+    /// Wherever we were when the app closed.
+    LastUsed,
+    SavedSearch(i64),
+}
+
+/// A folder the app can open on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Folder {
+    Inbox,
+    SavedSearch(i64),
+    Drafts,
+    Sent,
+    Archived,
+    Trash,
+    Notes,
+    Contacts,
+    Notifications,
+}
+
+impl Folder {
+    /// The folder on show, `None` for pages that aren't one, like a thread or Settings.
+    pub fn of(page: &Page, active_search: Option<i64>) -> Option<Self> {
+        let folder = match page {
+            Page::Inbox => active_search.map_or(Folder::Inbox, Folder::SavedSearch),
+            Page::Drafts => Folder::Drafts,
+            Page::Sent => Folder::Sent,
+            Page::Archived => Folder::Archived,
+            Page::Trash => Folder::Trash,
+            Page::Notes => Folder::Notes,
+            Page::Contacts => Folder::Contacts,
+            Page::Notifications => Folder::Notifications,
+            _ => return None,
+        };
+        Some(folder)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Move a thread to Archived once we've replied to it.
+    pub archive_on_reply: bool,
+    /// Seconds a thread has to stay open before it counts as read, 0 to mark it read right away.
+    pub mark_read_delay_secs: u32,
+    /// Make "Reply all" the first reply button on a message.
+    pub reply_all_by_default: bool,
+    /// Ask before sending a message that has no subject.
+    pub confirm_empty_subject: bool,
+    /// Show who a message goes to and from, and what's attached, before it's sent.
+    pub confirm_recipients: bool,
+    /// Show times on a 24-hour clock instead of with AM/PM.
+    pub clock_24h: bool,
+    pub startup_page: StartupPage,
+    /// Reopen the messages that were being written when the app closed.
+    pub reopen_compose_windows: bool,
+    /// How many hours back gift wraps may be dated, so relays can't tell when we send.
+    pub wrap_fuzz_hours: u32,
+    /// Labels whose mail notifies. When there are any, mail without one of them doesn't.
+    pub notify_labels: Vec<String>,
+    /// Split the inbox into Focused and Other by who the mail is from.
+    pub focused_inbox: bool,
+    /// The program to write messages in, with any arguments. Empty uses `$VISUAL` or `$EDITOR`.
+    pub external_editor: String,
+    /// Accounts that wrap a copy of what they send for themselves, so their other devices
+    /// see it in Sent.
+    pub copy_to_self: Vec<String>,
+    /// The most proof of work (NIP-13) to do for a relay that won't take a message without,
+    /// in bits.
+    pub max_pow: u8,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            archive_on_reply: false,
+            mark_read_delay_secs: 0,
+            reply_all_by_default: false,
+            confirm_empty_subject: true,
+            confirm_recipients: false,
+            clock_24h: false,
+            startup_page: StartupPage::Inbox,
+            reopen_compose_windows: false,
+            wrap_fuzz_hours: 48,
+            notify_labels: Vec::new(),
+            focused_inbox: false,
+            external_editor: String::new(),
+            copy_to_self: Vec::new(),
+            max_pow: 16,
+        }
+    }
+}
+
+impl Preferences {
+    /// How long to wait before marking an opened thread read, `None` to do it on open.
+    pub fn mark_read_delay(&self) -> Option<Duration> {
+        (self.mark_read_delay_secs > 0)
+            .then(|| Duration::from_secs(self.mark_read_delay_secs.into()))
+    }
+
+    pub fn copies_to_self(&self, account: &str) -> bool {
+        self.copy_to_self.iter().any(|pubkey| pubkey == account)
+    }
+
+    /// How far back to date gift wraps, never more than NIP-59 suggests.
+    pub fn wrap_fuzz(&self) -> Duration {
+        Duration::from_secs(u64::from(self.wrap_fuzz_hours) * 60 * 60).min(MAX_WRAP_FUZZ)
+    }
+}
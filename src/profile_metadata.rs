@@ -10,15 +10,9 @@ use crate::{
 };
 use anyhow::{Context, Result};
 use nostr::PublicKey;
-use serde::{Deserialize, Serialize};
 use tracing::error;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
-pub struct ProfileMetadata {
-    pub name: Option<String>,
-    pub display_name: Option<String>,
-    pub picture: Option<String>,
-}
+pub use hoot_core::profile_metadata::ProfileMetadata;
 
 /// This is our own little option type just for checking if we have a profile's
 /// metadata within our own `Hoot::profile_metadata` struct's HashMap
@@ -65,6 +59,7 @@ pub fn get_profile_metadata(app: &mut Hoot, public_key: String) -> &ProfileOptio
         if let Some(meta) = db_metadata_opt {
             let val = ProfileOption::Some(meta);
             app.profile_metadata.insert(public_key.clone(), val);
+            app.names.forget(&public_key);
             return app
                 .profile_metadata
                 .get(&public_key)
@@ -91,6 +86,7 @@ pub fn update_logged_in_profile_metadata(
         public_key.to_string(),
         ProfileOption::Some(metadata.to_owned()),
     );
+    app.names.forget(&public_key.to_string());
     app.contacts_manager
         .upsert_metadata(public_key.to_string(), metadata.clone());
 
@@ -102,8 +98,9 @@ pub fn update_logged_in_profile_metadata(
         .iter()
         .find(|v| v.public_key() == public_key)
         .context("Could not update our own account's metadata because we can't find the keys.")?;
-    let event =
-        nostr::EventBuilder::new(nostr::Kind::Metadata, serialized).sign_with_keys(our_key)?;
+    let event = nostr::EventBuilder::new(nostr::Kind::Metadata, serialized)
+        .custom_created_at(crate::clock::now())
+        .sign_with_keys(our_key)?;
 
     // write to db
     // TODO: serializing and then deserialzing is retarded. fix.
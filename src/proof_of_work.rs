@@ -0,0 +1,105 @@
+//! Wraps sent mail again with the proof of work (NIP-13) a relay asked for. Mining takes
+//! seconds at the difficulties relays want, so each copy is made on a thread of its own and
+//! handed back to the send it's for, which keeps polling while it waits.
+
+use crate::mail_event;
+use nostr::{Event, Keys, PublicKey, UnsignedEvent};
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// A copy of a published wrap, made for one relay.
+struct Mined {
+    /// The wrap it stands in for.
+    original_id: String,
+    url: String,
+    result: Result<Event, String>,
+}
+
+pub struct ProofOfWork {
+    /// Copies being mined, by the wrap they stand in for.
+    running: HashMap<String, usize>,
+    /// Copies mined for sends that haven't taken them yet.
+    done: Vec<Mined>,
+    sender: Sender<Mined>,
+    receiver: Receiver<Mined>,
+}
+
+impl ProofOfWork {
+    pub fn new() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            running: HashMap::new(),
+            done: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Starts wrapping `rumor` again from `keys` to `recipient` with `difficulty` bits of work,
+    /// for `url` in place of the wrap `original_id`.
+    pub fn mine(
+        &mut self,
+        keys: &Keys,
+        recipient: PublicKey,
+        rumor: UnsignedEvent,
+        fuzz: Duration,
+        difficulty: u8,
+        original_id: &str,
+        url: String,
+    ) {
+        info!("Adding {} bits of proof of work for {}", difficulty, url);
+        *self.running.entry(original_id.to_string()).or_default() += 1;
+        let keys = keys.clone();
+        let original_id = original_id.to_string();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = mail_event::gift_wrap_with_pow(&keys, &recipient, rumor, fuzz, difficulty)
+                .map_err(|e| e.to_string());
+            let mined = Mined {
+                original_id,
+                url,
+                result,
+            };
+            if sender.send(mined).is_err() {
+                debug!("Proof of work receiver dropped before a copy was mined");
+            }
+        });
+    }
+
+    /// Whether a copy of any of `event_ids` is still being mined.
+    pub fn is_mining(&self, event_ids: &[String]) -> bool {
+        event_ids.iter().any(|id| self.running.contains_key(id))
+    }
+
+    /// The copies of `event_ids` mined since the last call, with the wrap each stands in for
+    /// and the relay it's for.
+    pub fn take_finished(&mut self, event_ids: &[String]) -> Vec<(String, String, Event)> {
+        for mined in self.receiver.try_iter() {
+            if let Some(running) = self.running.get_mut(&mined.original_id) {
+                *running -= 1;
+                if *running == 0 {
+                    self.running.remove(&mined.original_id);
+                }
+            }
+            self.done.push(mined);
+        }
+
+        let (finished, others): (Vec<Mined>, Vec<Mined>) = std::mem::take(&mut self.done)
+            .into_iter()
+            .partition(|mined| event_ids.contains(&mined.original_id));
+        self.done = others;
+        finished
+            .into_iter()
+            .filter_map(|mined| match mined.result {
+                Ok(wrap) => Some((mined.original_id, mined.url, wrap)),
+                Err(e) => {
+                    error!("Failed to wrap a message again for {}: {}", mined.url, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+//! QR codes for moving keys between devices: an npub or an encrypted key backup shown for a
+//! phone to scan, and an nsec read from a picture of one.
+
+use anyhow::{bail, Context, Result};
+use eframe::egui::{self, Color32, Sense, Ui};
+use std::path::Path;
+
+/// Blank modules around the code, so scanners can find its edges.
+const QUIET_ZONE: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Qr {
+    width: usize,
+    /// Row by row, whether each module is dark.
+    dark: Vec<bool>,
+}
+
+impl Qr {
+    pub fn encode(text: &str) -> Result<Self> {
+        let code = qrcode::QrCode::new(text).context("Too long for a QR code")?;
+        Ok(Self {
+            width: code.width(),
+            dark: code
+                .to_colors()
+                .into_iter()
+                .map(|color| color == qrcode::Color::Dark)
+                .collect(),
+        })
+    }
+
+    /// Paints the code `size` points wide, always dark on light so it scans in dark mode too.
+    pub fn show(&self, ui: &mut Ui, size: f32) -> egui::Response {
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(size, size), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::WHITE);
+        let module = size / (self.width + 2 * QUIET_ZONE) as f32;
+        for (i, _) in self.dark.iter().enumerate().filter(|(_, dark)| **dark) {
+            let (x, y) = (i % self.width + QUIET_ZONE, i / self.width + QUIET_ZONE);
+            let min = rect.min + egui::vec2(x as f32, y as f32) * module;
+            painter.rect_filled(
+                egui::Rect::from_min_size(min, egui::vec2(module, module)),
+                0.0,
+                Color32::BLACK,
+            );
+        }
+        response
+    }
+}
+
+/// What the QR codes in the picture at `path` say.
+pub fn read_file(path: &Path) -> Result<Vec<String>> {
+    let image = image::open(path)
+        .with_context(|| format!("Couldn't open {}", path.display()))?
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    Ok(prepared
+        .detect_grids()
+        .iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect())
+}
+
+/// The nsec among what QR codes said, as mobile signers show it, maybe as a `nostr:` link.
+pub fn find_nsec(contents: &[String]) -> Result<String> {
+    if contents.is_empty() {
+        bail!("No QR code found in the picture");
+    }
+    let keys = contents.iter().map(|content| {
+        let content = content.trim();
+        content.strip_prefix("nostr:").unwrap_or(content)
+    });
+    let mut encrypted = false;
+    for key in keys {
+        if key.starts_with("nsec1") {
+            crate::account_manager::validate_nsec(key).map_err(anyhow::Error::msg)?;
+            return Ok(key.to_string());
+        }
+        encrypted |= key.starts_with("ncryptsec1");
+    }
+    if encrypted {
+        bail!("This QR code holds a password protected key, which can't be imported yet");
+    }
+    bail!("The QR code doesn't hold a private key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{Keys, ToBech32};
+
+    #[test]
+    fn finds_the_nsec_a_signer_shows() -> Result<()> {
+        let nsec = Keys::generate().secret_key().to_bech32()?;
+        assert_eq!(find_nsec(&[format!("nostr:{}\n", nsec)])?, nsec);
+        assert!(find_nsec(&[]).is_err());
+        assert!(find_nsec(&["ncryptsec1qgg9947rlpvqu76p".to_string()]).is_err());
+        assert!(find_nsec(&["https://example.com".to_string()]).is_err());
+
+        let qr = Qr::encode(&nsec)?;
+        assert_eq!(qr.dark.len(), qr.width * qr.width);
+        Ok(())
+    }
+}
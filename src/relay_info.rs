@@ -0,0 +1,156 @@
+//! Fetches the information document (NIP-11) of every relay in the pool in the background, to
+//! tell which ones want paying before they take our mail. The answers' `Date` headers tell how
+//! far our clock is off theirs, see `hoot_core::clock`.
+
+use crate::clock::SkewEstimator;
+use crate::relay::{self, RelayInfo, RelayPool};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// The `Date` header of an answer, with when the request went out and the answer came in.
+struct DateSample {
+    date: String,
+    sent: SystemTime,
+    received: SystemTime,
+}
+
+type Fetched = (String, Option<RelayInfo>, Option<DateSample>);
+
+pub struct RelayInfoManager {
+    /// Documents by relay url, `None` while one is being fetched or if the relay has none.
+    infos: HashMap<String, Option<RelayInfo>>,
+    /// Relays charging a fee that the user told us they've paid.
+    pub paid: HashSet<String>,
+    skew: SkewEstimator,
+    sender: Sender<Fetched>,
+    receiver: Receiver<Fetched>,
+}
+
+impl RelayInfoManager {
+    pub fn new(paid: HashSet<String>) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            infos: HashMap::new(),
+            paid,
+            skew: SkewEstimator::default(),
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn info(&self, url: &str) -> Option<&RelayInfo> {
+        self.infos.get(url)?.as_ref()
+    }
+
+    /// Whether `url` wants paying and the user hasn't said they have.
+    pub fn awaiting_payment(&self, url: &str) -> bool {
+        self.info(url).is_some_and(|info| info.payment_required()) && !self.paid.contains(url)
+    }
+
+    /// Starts fetching the document of every relay in `pool` we haven't asked yet.
+    pub fn request_missing(&mut self, pool: &RelayPool) {
+        // Relays added again are asked again.
+        self.infos.retain(|url, _| pool.relays.contains_key(url));
+        for url in pool.relays.keys() {
+            if self.infos.contains_key(url) {
+                continue;
+            }
+            self.infos.insert(url.clone(), None);
+            let sender = self.sender.clone();
+            let url = url.clone();
+            thread::spawn(move || {
+                let (info, date) = fetch(&url);
+                let info = match info {
+                    Ok(info) => Some(info),
+                    Err(e) => {
+                        debug!("No relay information from {}: {}", url, e);
+                        None
+                    }
+                };
+                if sender.send((url, info, date)).is_err() {
+                    debug!("Relay info receiver dropped before the fetch finished");
+                }
+            });
+        }
+    }
+
+    /// Asks `url` for its document again, e.g. to measure its clock once more.
+    pub fn refetch(&mut self, url: &str) {
+        self.infos.remove(url);
+    }
+
+    /// Takes in the fetched documents and holds off asking relays that want paying. Returns
+    /// the new clock offset if the answers changed it.
+    pub fn process_queue(&mut self, pool: &mut RelayPool) -> Option<i64> {
+        let mut new_offset = None;
+        while let Ok((url, info, date)) = self.receiver.try_recv() {
+            if let Some(DateSample {
+                date,
+                sent,
+                received,
+            }) = date
+            {
+                if let Some(offset) = self.skew.record_date(&url, &date, sent, received) {
+                    info!("Our clock is {} seconds behind the relays'", offset);
+                    new_offset = Some(offset);
+                }
+            }
+            self.infos.insert(url.clone(), info);
+            let awaiting = self.awaiting_payment(&url);
+            if awaiting {
+                warn!("{} wants paying before it takes our mail", url);
+            }
+            pool.set_awaiting_payment(&url, awaiting);
+        }
+        new_offset
+    }
+
+    /// Remembers the user paid `url` and starts asking it for mail.
+    pub fn mark_paid(&mut self, pool: &mut RelayPool, url: &str) {
+        info!("Marked {} as paid", url);
+        self.paid.insert(url.to_string());
+        pool.set_awaiting_payment(url, false);
+    }
+}
+
+/// Fetches the document of the relay at `url`, and the `Date` it was answered with, which is
+/// worth having even if the relay has no document.
+fn fetch(url: &str) -> (Result<RelayInfo, String>, Option<DateSample>) {
+    let Some(info_url) = relay::info_url(url) else {
+        return (Err(format!("unsupported url {}", url)), None);
+    };
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return (Err(format!("could not build HTTP client: {}", e)), None),
+    };
+    let sent = SystemTime::now();
+    let response = match client
+        .get(&info_url)
+        .header(reqwest::header::ACCEPT, relay::INFO_ACCEPT)
+        .send()
+    {
+        Ok(response) => response,
+        Err(e) => return (Err(format!("could not fetch {}: {}", info_url, e)), None),
+    };
+    let date = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .map(|date| DateSample {
+            date: date.to_string(),
+            sent,
+            received: SystemTime::now(),
+        });
+    let info = response
+        .error_for_status()
+        .and_then(|response| response.text())
+        .map_err(|e| format!("could not fetch {}: {}", info_url, e))
+        .and_then(|body| RelayInfo::from_json(&body).map_err(|e| e.to_string()));
+    (info, date)
+}
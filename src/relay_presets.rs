@@ -0,0 +1,224 @@
+//! Curated bundles of relays that Settings adds or removes in one go. The list ships with the
+//! app in `relay_presets.json` and is refreshed from the project's copy of that file. A fetched
+//! copy is only used when its SHA-256 is one this release pins, so a changed file on GitHub can't
+//! hand out relays nobody vetted; a list newer than the release is taken once a release pins it.
+//! Fetching can be turned off, leaving the bundled list.
+
+use eframe::egui;
+use nostr::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+pub const RELAY_PRESETS_KEY: &str = "relay_presets";
+pub const PRESET_CHOICES_KEY: &str = "relay_preset_choices";
+const PRESETS_URL: &str =
+    "https://raw.githubusercontent.com/chakanysystems/hoot/main/src/relay_presets.json";
+const BUNDLED: &str = include_str!("relay_presets.json");
+/// SHA-256 of every version of `relay_presets.json` this release accepts from `PRESETS_URL`.
+/// Add the hash of a new list here when changing it.
+const PINNED: &[&str] = &["627c8c60da535ac6435d6bf0d7024de9d1c39b560e3dc9a56b23e0b5ff9ffe53"];
+/// How old the list gets before Settings fetches it again, in seconds.
+const REFRESH_AFTER: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelayPreset {
+    pub name: String,
+    pub description: String,
+    pub relays: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetList {
+    pub presets: Vec<RelayPreset>,
+    /// When the list was fetched, 0 for the one that ships with the app.
+    #[serde(default)]
+    pub fetched_at: i64,
+}
+
+impl PresetList {
+    fn bundled() -> Self {
+        parse(BUNDLED).expect("the bundled relay presets are valid")
+    }
+}
+
+/// What the user chose about the bundles, kept apart from the list so a refresh can't lose it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetChoices {
+    /// Stick to the bundled list instead of fetching one.
+    pub skip_remote: bool,
+    /// The relays each bundle added, by bundle name, so taking it away leaves the rest.
+    pub added: BTreeMap<String, Vec<String>>,
+}
+
+pub struct RelayPresets {
+    pub list: PresetList,
+    pub choices: PresetChoices,
+    pub refreshing: bool,
+    /// Why the last refresh failed, until the next one.
+    pub error: Option<String>,
+    sender: Sender<Result<PresetList, String>>,
+    receiver: Receiver<Result<PresetList, String>>,
+}
+
+impl RelayPresets {
+    /// Starts from the list saved last time, or the bundled one.
+    pub fn new(saved: Option<PresetList>, choices: PresetChoices) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let saved = saved.filter(|_| !choices.skip_remote);
+        Self {
+            list: saved.unwrap_or_else(PresetList::bundled),
+            choices,
+            refreshing: false,
+            error: None,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Fetches the list again in the background.
+    pub fn refresh(&mut self) {
+        if self.refreshing || self.choices.skip_remote {
+            return;
+        }
+        self.refreshing = true;
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            if sender.send(fetch()).is_err() {
+                debug!("Relay preset receiver dropped before the fetch finished");
+            }
+        });
+    }
+
+    /// Stops or starts fetching the list, going back to the bundled one when stopping.
+    pub fn set_skip_remote(&mut self, skip: bool) {
+        self.choices.skip_remote = skip;
+        if skip {
+            self.list = PresetList::bundled();
+            self.error = None;
+        }
+    }
+
+    /// The relays `preset` added that are still to be taken away.
+    pub fn added_by(&self, preset: &str) -> &[String] {
+        self.choices.added.get(preset).map_or(&[], Vec::as_slice)
+    }
+
+    /// Remembers relays `preset` added, leaving out ones that were there already.
+    pub fn record_added(&mut self, preset: &str, urls: Vec<String>) {
+        let added = self.choices.added.entry(preset.to_string()).or_default();
+        for url in urls {
+            if !added.contains(&url) {
+                added.push(url);
+            }
+        }
+    }
+
+    pub fn forget(&mut self, preset: &str) {
+        self.choices.added.remove(preset);
+    }
+
+    /// Refreshes the list when it's more than a day old.
+    pub fn refresh_if_stale(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        if self.error.is_none() && now - self.list.fetched_at > REFRESH_AFTER {
+            self.refresh();
+        }
+    }
+
+    pub fn process_queue(&mut self, ctx: &egui::Context) {
+        while let Ok(result) = self.receiver.try_recv() {
+            self.refreshing = false;
+            match result {
+                // Fetching was turned off while this one ran.
+                Ok(_) if self.choices.skip_remote => {}
+                Ok(list) => {
+                    info!("Fetched {} relay presets", list.presets.len());
+                    self.list = list;
+                    self.error = None;
+                }
+                Err(e) => {
+                    error!("Failed to refresh the relay presets: {}", e);
+                    self.error = Some(e);
+                }
+            }
+        }
+
+        // The fetch thread can't wake us up, so keep polling while it runs.
+        if self.refreshing {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+    }
+}
+
+/// Reads a preset list, dropping relays that aren't websocket URLs and presets left empty.
+fn parse(json: &str) -> Result<PresetList, String> {
+    let mut list: PresetList =
+        serde_json::from_str(json).map_err(|e| format!("not a preset list: {}", e))?;
+    for preset in &mut list.presets {
+        preset
+            .relays
+            .retain(|url| url.starts_with("wss://") || url.starts_with("ws://"));
+    }
+    list.presets.retain(|preset| !preset.relays.is_empty());
+    if list.presets.is_empty() {
+        return Err("no presets in the list".to_string());
+    }
+    Ok(list)
+}
+
+fn pinned(body: &str) -> bool {
+    let hash = sha256::Hash::hash(body.as_bytes()).to_string();
+    PINNED.contains(&hash.as_str())
+}
+
+fn fetch() -> Result<PresetList, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+        .map_err(|e| format!("could not build HTTP client: {}", e))?;
+    let body = client
+        .get(PRESETS_URL)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| format!("could not fetch {}: {}", PRESETS_URL, e))?;
+    if !pinned(&body) {
+        return Err("the fetched list isn't one this version of Hoot trusts".to_string());
+    }
+    let mut list = parse(&body)?;
+    list.fetched_at = chrono::Utc::now().timestamp();
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_relays_that_are_not_websockets() {
+        assert!(!PresetList::bundled().presets.is_empty());
+
+        let list = parse(
+            r#"{"presets": [
+                {"name": "Mixed", "description": "", "relays": ["wss://a.example.com", "https://b.example.com"]},
+                {"name": "Broken", "description": "", "relays": ["b.example.com"]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(list.presets.len(), 1);
+        assert_eq!(list.presets[0].relays, vec!["wss://a.example.com"]);
+        assert_eq!(list.fetched_at, 0);
+        assert!(parse(r#"{"presets": []}"#).is_err());
+    }
+
+    #[test]
+    fn only_takes_pinned_lists() {
+        assert!(pinned(BUNDLED));
+        assert!(!pinned(&BUNDLED.replace("wss://", "wss://evil.")));
+    }
+}
@@ -0,0 +1,86 @@
+//! When to draw the next frame. egui only draws on input or when asked to, so whatever changes
+//! on its own asks here: relay sockets when messages come in, and countdowns like relay
+//! reconnects and relative timestamps.
+//!
+//! Asking twice before the next frame wakes the event loop once, and while the window is in the
+//! background wakeups are held back and batched, so an idle Hoot stays idle.
+
+use eframe::egui;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long wakeups wait while the window is in the background, to be handled together.
+const BACKGROUND_DELAY: Duration = Duration::from_secs(1);
+
+pub struct RepaintScheduler {
+    /// Set once a socket asked for a frame, until that frame starts.
+    pending: Arc<AtomicBool>,
+    /// Whether the window had focus last frame, for wakeups from other threads.
+    focused: Arc<AtomicBool>,
+    /// The earliest countdown that ends after this frame.
+    next: Option<Instant>,
+}
+
+impl Default for RepaintScheduler {
+    fn default() -> Self {
+        Self {
+            pending: Arc::new(AtomicBool::new(false)),
+            focused: Arc::new(AtomicBool::new(true)),
+            next: None,
+        }
+    }
+}
+
+impl RepaintScheduler {
+    /// Wakes the UI for relay messages. Safe to call from any thread, as often as messages come.
+    pub fn waker(&self, ctx: &egui::Context) -> impl Fn() + Send + Sync + Clone + 'static {
+        let pending = self.pending.clone();
+        let focused = self.focused.clone();
+        let ctx = ctx.clone();
+        move || {
+            if pending.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            if focused.load(Ordering::Relaxed) {
+                ctx.request_repaint();
+            } else {
+                ctx.request_repaint_after(BACKGROUND_DELAY);
+            }
+        }
+    }
+
+    /// Call before anything else in a frame.
+    pub fn begin_frame(&mut self, ctx: &egui::Context) {
+        self.pending.store(false, Ordering::Release);
+        self.focused
+            .store(ctx.input(|i| i.focused), Ordering::Relaxed);
+        self.next = None;
+    }
+
+    /// Whether the window has focus, so things only worth watching can skip their countdowns.
+    pub fn is_focused(&self) -> bool {
+        self.focused.load(Ordering::Relaxed)
+    }
+
+    /// Draws a frame at `when`, or sooner if something else comes up.
+    pub fn at(&mut self, when: Instant) {
+        self.next = Some(self.next.map_or(when, |next| next.min(when)));
+    }
+
+    pub fn after(&mut self, delay: Duration) {
+        self.at(Instant::now() + delay);
+    }
+
+    /// Call once the frame is drawn, to ask egui for the earliest countdown.
+    pub fn end_frame(&mut self, ctx: &egui::Context) {
+        let Some(next) = self.next.take() else {
+            return;
+        };
+        let mut delay = next.saturating_duration_since(Instant::now());
+        if !self.is_focused() {
+            delay = delay.max(BACKGROUND_DELAY);
+        }
+        ctx.request_repaint_after(delay);
+    }
+}
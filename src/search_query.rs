@@ -0,0 +1,81 @@
+//! The search box language, shared by the inbox search and saved searches.
+//!
+//! Plain words match subjects and bodies. On top of that:
+//! - `subject:word` only matches the subject.
+//! - `note:word` matches the notes kept on threads.
+//! - `has:attachment`, `is:unread`, `is:starred`, `from:contacts` and `in:archive` work like
+//!   the filter chips.
+//!
+//! Operators we don't know are searched for as plain words, so a typo never hides everything.
+
+use crate::db::MessageFilter;
+
+/// Turns a query into the filter it stands for.
+pub fn parse(query: &str) -> MessageFilter {
+    let mut filter = MessageFilter::default();
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        let Some((operator, value)) = word.split_once(':') else {
+            words.push(word);
+            continue;
+        };
+        match (
+            operator.to_lowercase().as_str(),
+            value.to_lowercase().as_str(),
+        ) {
+            ("subject", "") => {}
+            ("subject", _) => filter.subject_terms.push(value.to_string()),
+            ("note", "") => {}
+            ("note", _) => filter.note_terms.push(value.to_string()),
+            ("has", "attachment" | "attachments") => filter.has_attachment = true,
+            ("is", "unread") => filter.unread = true,
+            ("is", "starred") => filter.starred = true,
+            ("from", "contacts") => filter.from_contacts = true,
+            ("in", "archive" | "archived") => filter.archived = true,
+            _ => words.push(word),
+        }
+    }
+    filter.query = words.join(" ");
+    filter
+}
+
+/// `filter` with the operators in its query applied on top of what's already set.
+pub fn apply(filter: &MessageFilter) -> MessageFilter {
+    let parsed = parse(&filter.query);
+    MessageFilter {
+        query: parsed.query,
+        subject_terms: parsed.subject_terms,
+        note_terms: parsed.note_terms,
+        unread: filter.unread || parsed.unread,
+        starred: filter.starred || parsed.starred,
+        has_attachment: filter.has_attachment || parsed.has_attachment,
+        from_contacts: filter.from_contacts || parsed.from_contacts,
+        archived: filter.archived || parsed.archived,
+        focused: filter.focused,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_operators_from_words() {
+        let filter = parse("Subject:invoice has:attachment march is:unread to:bob note:call");
+        assert_eq!(filter.query, "march to:bob");
+        assert_eq!(filter.subject_terms, vec!["invoice".to_string()]);
+        assert_eq!(filter.note_terms, vec!["call".to_string()]);
+        assert!(filter.has_attachment);
+        assert!(filter.unread);
+        assert!(!filter.starred);
+
+        let chips = MessageFilter {
+            query: "is:starred".to_string(),
+            from_contacts: true,
+            ..Default::default()
+        };
+        let applied = apply(&chips);
+        assert!(applied.starred && applied.from_contacts);
+        assert!(applied.query.is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+//! Keeps Hoot to one running copy, so two never open the same database. The first copy holds
+//! a lock file in the storage folder and listens on a localhost socket, whose port it writes
+//! next to the lock. A later launch hands its command to that copy and exits.
+
+use crate::nostr_uri;
+use eframe::egui;
+use std::fs::{File, TryLockError};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Starts every request, so a stale port taken over by something else isn't mistaken for us.
+const GREETING: &str = "hoot";
+const REPLY: &str = "ok";
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a later launch waits for the running copy to start listening.
+const FORWARD_PATIENCE: Duration = Duration::from_secs(3);
+
+/// What a launch asks of Hoot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Just bring the window up.
+    Show,
+    /// Open a compose window, addressed to these recipients if any.
+    Compose(String),
+    /// Open a `nostr:` link.
+    Open(String),
+}
+
+impl Command {
+    /// `hoot nostr:…` opens the link, `hoot --compose [recipients]` a compose window.
+    pub fn from_args() -> Self {
+        if let Some(link) = nostr_uri::from_args() {
+            return Command::Open(link);
+        }
+        let mut args = std::env::args().skip(1);
+        if args.any(|arg| arg == "--compose") {
+            return Command::Compose(
+                args.next()
+                    .filter(|a| !a.starts_with("--"))
+                    .unwrap_or_default(),
+            );
+        }
+        Command::Show
+    }
+
+    fn to_line(&self) -> String {
+        match self {
+            Command::Show => format!("{} show", GREETING),
+            Command::Compose(to) => format!("{} compose {}", GREETING, to),
+            Command::Open(link) => format!("{} open {}", GREETING, link),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.trim().splitn(3, ' ');
+        if parts.next() != Some(GREETING) {
+            return None;
+        }
+        let command = parts.next()?;
+        let argument = parts.next().unwrap_or_default().trim().to_string();
+        match command {
+            "show" => Some(Command::Show),
+            "compose" => Some(Command::Compose(argument)),
+            "open" if !argument.is_empty() => Some(Command::Open(argument)),
+            _ => None,
+        }
+    }
+}
+
+fn storage_file(name: &str) -> Option<PathBuf> {
+    eframe::storage_dir(crate::STORAGE_NAME).map(|dir| dir.join(name))
+}
+
+/// Held for as long as this copy runs.
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Takes the instance lock. `None` if another copy holds it.
+pub fn acquire() -> std::io::Result<Option<InstanceLock>> {
+    let Some(path) = storage_file("instance.lock") else {
+        return Err(std::io::Error::other("no storage folder"));
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = File::create(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(InstanceLock { _file: file })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// Hands `command` to the running Hoot. Returns false if it didn't answer in time.
+pub fn forward(command: &Command) -> bool {
+    let deadline = Instant::now() + FORWARD_PATIENCE;
+    loop {
+        if try_forward(command) {
+            return true;
+        }
+        // It may still be starting up and not listening yet.
+        if Instant::now() > deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn try_forward(command: &Command) -> bool {
+    let Some(port) = storage_file("instance-port")
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|port| port.trim().parse::<u16>().ok())
+    else {
+        return false;
+    };
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let Ok(mut stream) = TcpStream::connect_timeout(&address, TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    if writeln!(stream, "{}", command.to_line()).is_err() {
+        return false;
+    }
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).is_ok() && reply.trim() == REPLY
+}
+
+/// Commands handed over by later launches.
+pub struct Instance {
+    commands: Receiver<Command>,
+}
+
+impl Instance {
+    /// Listens for later launches. They bring this window to the front, and their commands
+    /// come out of `next_command`.
+    pub fn listen(ctx: egui::Context) -> Option<Self> {
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Couldn't listen for other Hoot launches: {}", e);
+                return None;
+            }
+        };
+        let port = listener.local_addr().ok()?.port();
+        if let Err(e) =
+            storage_file("instance-port").map(|path| std::fs::write(path, port.to_string()))?
+        {
+            error!("Couldn't record the instance port: {}", e);
+            return None;
+        }
+
+        let (sender, commands) = std::sync::mpsc::channel();
+        thread::Builder::new()
+            .name("single-instance".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if !handle(stream, &sender, &ctx) {
+                        return;
+                    }
+                }
+            })
+            .ok()?;
+        Some(Self { commands })
+    }
+
+    pub fn next_command(&self) -> Option<Command> {
+        self.commands.try_recv().ok()
+    }
+}
+
+/// Answers one later launch. Returns false once the app is gone.
+fn handle(stream: TcpStream, commands: &Sender<Command>, ctx: &egui::Context) -> bool {
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let mut request = String::new();
+    let mut reader = BufReader::new(&stream);
+    if reader.read_line(&mut request).is_err() {
+        return true;
+    }
+    let Some(command) = Command::from_line(&request) else {
+        warn!("Ignoring an unexpected connection on the instance port");
+        return true;
+    };
+    if let Err(e) = writeln!(&stream, "{}", REPLY) {
+        warn!("Couldn't answer another Hoot launch: {}", e);
+    }
+
+    info!("Another Hoot launch handed over to this one: {:?}", command);
+    if command != Command::Show && commands.send(command).is_err() {
+        return false;
+    }
+    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    ctx.request_repaint();
+    true
+}
+
+/// Shown instead of the app when another copy holds the lock but doesn't answer, rather than
+/// letting the database fail with a busy error.
+pub struct AlreadyRunning;
+
+impl eframe::App for AlreadyRunning {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(60.0);
+                ui.heading("Hoot is already running");
+                ui.add_space(10.0);
+                ui.label(
+                    "Another copy of Hoot has your mailbox open but isn't responding. \
+                     Wait for it to finish starting, or close it, and then try again.",
+                );
+                ui.add_space(20.0);
+                if ui.button("Quit").clicked() {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_survive_the_socket() {
+        let commands = [
+            Command::Show,
+            Command::Compose(String::new()),
+            Command::Compose("npub1abc, bob@example.com".to_string()),
+            Command::Open("nostr:npub1abc".to_string()),
+        ];
+        for command in commands {
+            assert_eq!(Command::from_line(&command.to_line()), Some(command));
+        }
+        assert_eq!(Command::from_line("GET / HTTP/1.1"), None);
+        assert_eq!(Command::from_line("hoot open"), None);
+    }
+}
@@ -1,5 +1,6 @@
-use eframe::egui::{self, Color32, Rounding, Stroke, Vec2};
+use eframe::egui::{self, Color32, RichText, Rounding, Stroke, Vec2};
 use eframe::epaint::Shadow;
+use std::time::Duration;
 
 // ── Colors ──────────────────────────────────────────────────────────────
 
@@ -10,15 +11,31 @@ pub const TEXT_MUTED: Color32 = Color32::from_rgb(140, 140, 150);
 pub const CARD_BG: Color32 = Color32::WHITE;
 pub const CARD_STROKE: Color32 = Color32::from_rgb(220, 218, 225);
 
+pub const HC_ACCENT: Color32 = Color32::from_rgb(75, 0, 160);
+pub const HC_TEXT: Color32 = Color32::BLACK;
+
 // ── Layout ──────────────────────────────────────────────────────────────
 
 pub const SIDEBAR_WIDTH: f32 = 220.0;
 pub const INBOX_ROW_HEIGHT: f32 = 40.0;
 pub const AVATAR_SIZE: f32 = 48.0;
+/// Follows the names of contacts who proved their key is theirs.
+pub const VERIFIED_BADGE: &str = "✔";
 
 // ── Theme ───────────────────────────────────────────────────────────────
 
-pub fn apply_theme(ctx: &egui::Context) {
+/// Storage key for the high-contrast preference, read before the database is unlocked.
+pub const HIGH_CONTRAST_KEY: &str = "high_contrast";
+
+pub fn apply_theme(ctx: &egui::Context, high_contrast: bool) {
+    if high_contrast {
+        apply_high_contrast_theme(ctx);
+    } else {
+        apply_default_theme(ctx);
+    }
+}
+
+fn apply_default_theme(ctx: &egui::Context) {
     let mut visuals = egui::Visuals::light();
     visuals.dark_mode = false;
 
@@ -59,9 +76,54 @@ pub fn apply_theme(ctx: &egui::Context) {
     });
 }
 
+/// Black-on-white variant with heavy borders and a dark accent, for low-vision users.
+fn apply_high_contrast_theme(ctx: &egui::Context) {
+    let mut visuals = egui::Visuals::light();
+    visuals.dark_mode = false;
+    visuals.override_text_color = Some(HC_TEXT);
+
+    let rounding = Rounding::same(2.0);
+    let border = Stroke::new(2.0, HC_TEXT);
+    for widget in [
+        &mut visuals.widgets.noninteractive,
+        &mut visuals.widgets.inactive,
+        &mut visuals.widgets.hovered,
+        &mut visuals.widgets.active,
+        &mut visuals.widgets.open,
+    ] {
+        widget.rounding = rounding;
+        widget.bg_stroke = border;
+        widget.fg_stroke = Stroke::new(1.5, HC_TEXT);
+    }
+    visuals.widgets.inactive.weak_bg_fill = Color32::WHITE;
+    visuals.widgets.inactive.bg_fill = Color32::WHITE;
+    visuals.widgets.hovered.weak_bg_fill = Color32::from_rgb(255, 240, 130);
+    visuals.widgets.hovered.bg_stroke = Stroke::new(3.0, HC_ACCENT);
+    visuals.widgets.active.bg_stroke = Stroke::new(3.0, HC_ACCENT);
+
+    visuals.selection.bg_fill = HC_ACCENT;
+    visuals.selection.stroke = Stroke::new(2.0, Color32::WHITE);
+    visuals.hyperlink_color = HC_ACCENT;
+    visuals.window_stroke = border;
+    visuals.window_shadow = Shadow::NONE;
+    visuals.panel_fill = Color32::WHITE;
+    visuals.window_fill = Color32::WHITE;
+    visuals.extreme_bg_color = Color32::WHITE;
+    visuals.faint_bg_color = Color32::from_rgb(235, 235, 235);
+
+    ctx.set_visuals(visuals);
+
+    ctx.style_mut(|style| {
+        style.spacing.button_padding = Vec2::new(8.0, 4.0);
+    });
+}
+
 // ── Helpers ──────────────────────────────────────────────────────────────
 
-pub fn format_timestamp(epoch_secs: i64) -> String {
+/// How often relative timestamps like "5 min ago" are brought up to date.
+pub const TIMESTAMP_REFRESH: Duration = Duration::from_secs(30);
+
+pub fn format_timestamp(epoch_secs: i64, clock_24h: bool) -> String {
     use chrono::{DateTime, Datelike, Local};
 
     let dt: DateTime<Local> = match DateTime::from_timestamp(epoch_secs, 0) {
@@ -72,9 +134,16 @@ pub fn format_timestamp(epoch_secs: i64) -> String {
     let now: DateTime<Local> = Local::now();
     let today = now.date_naive();
     let msg_date = dt.date_naive();
-
-    if msg_date == today {
-        dt.format("%-I:%M %p").to_string()
+    // Clocks disagree a little, so something from the near future is "just now" too.
+    let age = now.timestamp() - epoch_secs;
+
+    if (-60..60).contains(&age) {
+        "Just now".to_string()
+    } else if (60..60 * 60).contains(&age) {
+        format!("{} min ago", age / 60)
+    } else if msg_date == today {
+        dt.format(if clock_24h { "%H:%M" } else { "%-I:%M %p" })
+            .to_string()
     } else if msg_date == today.pred_opt().unwrap_or(today) {
         "Yesterday".to_string()
     } else if (today - msg_date).num_days() < 7 {
@@ -85,3 +154,67 @@ pub fn format_timestamp(epoch_secs: i64) -> String {
         dt.format("%b %-d, %Y").to_string() // "Jan 15, 2024"
     }
 }
+
+/// The date and local time down to the second, with the UTC offset, e.g.
+/// "Mon, Jan 15, 2024, 3:04:05 PM (UTC+01:00)".
+pub fn format_full_timestamp(epoch_secs: i64, clock_24h: bool) -> String {
+    use chrono::{DateTime, Local};
+
+    let dt: DateTime<Local> = match DateTime::from_timestamp(epoch_secs, 0) {
+        Some(utc) => utc.with_timezone(&Local),
+        None => return epoch_secs.to_string(),
+    };
+    let time = if clock_24h {
+        "%H:%M:%S"
+    } else {
+        "%-I:%M:%S %p"
+    };
+    dt.format(&format!("%a, %b %-d, %Y, {} (UTC%:z)", time))
+        .to_string()
+}
+
+/// A muted timestamp for list rows, with the full date and time on hover.
+pub fn timestamp_label(ui: &mut egui::Ui, epoch_secs: i64, clock_24h: bool) -> egui::Response {
+    ui.label(
+        RichText::new(format_timestamp(epoch_secs, clock_24h))
+            .color(TEXT_MUTED)
+            .small(),
+    )
+    .on_hover_text(format_full_timestamp(epoch_secs, clock_24h))
+}
+
+/// A dot in an account's color, next to its name.
+pub fn account_dot(ui: &mut egui::Ui, color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(10.0), egui::Sense::hover());
+    ui.painter().circle_filled(rect.center(), 4.0, color);
+}
+
+/// A bar in an account's color down the left edge of `ui`, marking a row as that account's.
+pub fn account_stripe(ui: &egui::Ui, color: Color32) {
+    let rect = ui.max_rect();
+    let stripe = egui::Rect::from_min_size(rect.left_top(), Vec2::new(3.0, rect.height()));
+    ui.painter().rect_filled(stripe, 1.5, color);
+}
+
+/// A round profile picture, or the first letter of `name` until one has loaded.
+pub fn avatar(
+    ui: &mut egui::Ui,
+    texture: Option<&egui::TextureHandle>,
+    name: &str,
+) -> egui::Response {
+    let size = Vec2::splat(24.0);
+    if let Some(texture) = texture {
+        return ui.add(egui::Image::new((texture.id(), size)).rounding(12.0));
+    }
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+    ui.painter()
+        .circle_filled(rect.center(), size.x / 2.0, ACCENT);
+    ui.painter().text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        name.chars().next().unwrap_or('?').to_uppercase(),
+        egui::FontId::proportional(12.0),
+        Color32::WHITE,
+    );
+    response
+}
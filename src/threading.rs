@@ -0,0 +1,166 @@
+use crate::db::Db;
+use crate::mail_event::{self, MailMessage};
+use crate::threaded_event::ThreadStore;
+use crate::TableEntry;
+use tracing::error;
+
+pub const SPLIT_ON_SUBJECT_CHANGE_KEY: &str = "split_threads_on_subject_change";
+
+/// Strips reply and forward prefixes, so "Re: Lunch" and "lunch" count as the same subject.
+pub fn normalize_subject(subject: &str) -> String {
+    let mut rest = subject.trim();
+    loop {
+        let lower = rest.to_lowercase();
+        let Some(prefix_len) = ["re:", "fwd:", "fw:"]
+            .iter()
+            .find(|prefix| lower.starts_with(*prefix))
+            .map(|prefix| prefix.len())
+        else {
+            break;
+        };
+        rest = rest[prefix_len..].trim_start();
+    }
+    rest.to_lowercase()
+}
+
+/// For every message, the new subject if it differs from the message it replies to. `store`
+/// holds `messages`, each in the slot of its index.
+pub fn subject_changes(store: &ThreadStore, messages: &[MailMessage]) -> Vec<Option<String>> {
+    (0..messages.len())
+        .map(|i| {
+            let parent = store.parent(i)?;
+            let subject = &messages[i].subject;
+            (normalize_subject(subject) != normalize_subject(&messages[parent].subject))
+                .then(|| subject.clone())
+        })
+        .collect()
+}
+
+/// Groups a thread into conversations that start wherever a reply changes the subject.
+/// Returns, for every message, the index of the message its conversation starts with.
+pub fn split_on_subject_change(store: &ThreadStore, messages: &[MailMessage]) -> Vec<usize> {
+    let changes = subject_changes(store, messages);
+
+    (0..messages.len())
+        .map(|start| {
+            let mut current = start;
+            while let (Some(parent), None) = (store.parent(current), &changes[current]) {
+                current = parent;
+            }
+            current
+        })
+        .collect()
+}
+
+/// Splits inbox entries whose threads change subject into one entry per conversation.
+pub fn split_entries(db: &Db, entries: Vec<TableEntry>) -> Vec<TableEntry> {
+    let roots: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.thread_count >= 2)
+        .map(|entry| entry.id.clone())
+        .collect();
+    if roots.is_empty() {
+        return entries;
+    }
+    let mut threads = match db.get_email_threads(&roots) {
+        Ok(threads) => threads,
+        Err(e) => {
+            error!("Failed to load threads for splitting: {}", e);
+            return entries;
+        }
+    };
+
+    let mut split = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let Some(thread) = threads.remove(&entry.id) else {
+            split.push(entry);
+            continue;
+        };
+
+        let starts = split_on_subject_change(&ThreadStore::from_messages(&thread), &thread);
+        let mut conversations: Vec<(usize, Vec<usize>)> = Vec::new();
+        for (i, start) in starts.into_iter().enumerate() {
+            match conversations.iter_mut().find(|(s, _)| *s == start) {
+                Some((_, members)) => members.push(i),
+                None => conversations.push((start, vec![i])),
+            }
+        }
+        if conversations.len() < 2 {
+            split.push(entry);
+            continue;
+        }
+
+        for (start, members) in conversations {
+            let (Some(root_id), Some(latest)) = (
+                thread[start].id,
+                members
+                    .iter()
+                    .map(|&i| &thread[i])
+                    .max_by_key(|msg| msg.created_at),
+            ) else {
+                continue;
+            };
+            split.push(TableEntry {
+                id: root_id.to_hex(),
+                snippet: mail_event::snippet(&latest.content),
+                subject: latest.subject.clone(),
+                pubkey: latest.author.map(|pk| pk.to_hex()).unwrap_or_default(),
+                created_at: latest.created_at.unwrap_or_default(),
+                thread_count: members.len() as i64,
+                labels: entry.labels.clone(),
+            });
+        }
+    }
+
+    split.sort_by_key(|entry| std::cmp::Reverse(entry.created_at));
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::EventId;
+
+    fn message(id: u8, parents: &[u8], subject: &str) -> MailMessage {
+        let event_id = |n: u8| EventId::from_slice(&[n; 32]).unwrap();
+        MailMessage {
+            id: Some(event_id(id)),
+            created_at: Some(id as i64),
+            author: None,
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            parent_events: Some(parents.iter().map(|&p| event_id(p)).collect()),
+            subject: subject.to_string(),
+            content: String::new(),
+            attachments: vec![],
+            articles: vec![],
+            quote: None,
+            payload: None,
+            email_to: vec![],
+            email_from: None,
+            tag_relays: Default::default(),
+        }
+    }
+
+    #[test]
+    fn splits_where_subject_changes() {
+        let thread = vec![
+            message(1, &[], "Lunch"),
+            message(2, &[1], "Re: lunch"),
+            message(3, &[1, 2], "Dinner instead?"),
+            message(4, &[1, 2, 3], "RE: Fwd: Dinner instead?"),
+            message(5, &[1], "Re: Lunch"),
+        ];
+        let store = ThreadStore::from_messages(&thread);
+
+        assert_eq!(
+            subject_changes(&store, &thread),
+            vec![None, None, Some("Dinner instead?".to_string()), None, None]
+        );
+        assert_eq!(
+            split_on_subject_change(&store, &thread),
+            vec![0, 0, 2, 2, 0]
+        );
+    }
+}
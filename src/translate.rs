@@ -0,0 +1,306 @@
+//! Translates messages through a web service the user picks, to show under the original in
+//! the thread view. Translations are kept in the database, so a message is only sent off once
+//! per language.
+//!
+//! Services plug in through [`Provider`], picked by [`ProviderKind`] in the config.
+//! LibreTranslate, which can be self-hosted, is the one built in.
+//!
+//! Translating sends the decrypted text of private mail to the service, so it only goes out
+//! over https, or in plain http to a server on this computer.
+
+use crate::automation;
+use crate::db::Db;
+use anyhow::{bail, Context, Result};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+pub const TRANSLATION_KEY: &str = "translation";
+
+/// The translation services Hoot can talk to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    LibreTranslate,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 1] = [ProviderKind::LibreTranslate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProviderKind::LibreTranslate => "LibreTranslate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranslationConfig {
+    pub provider: ProviderKind,
+    /// The service's server, empty while translating is off.
+    pub endpoint: String,
+    /// Only needed by servers that ask for one.
+    pub api_key: String,
+    /// The language messages are translated into, as a code like `en`.
+    pub language: String,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        Self {
+            provider: ProviderKind::default(),
+            endpoint: String::new(),
+            api_key: String::new(),
+            language: "en".to_string(),
+        }
+    }
+}
+
+/// A service that translates text.
+pub trait Provider: Send {
+    /// `text` in `language`, a code like `en`. The service works out what it's written in.
+    fn translate(&self, text: &str, language: &str) -> Result<String>;
+}
+
+/// Checks that messages may go to `endpoint`: over https, or to this computer.
+pub fn check_endpoint(endpoint: &str) -> Result<()> {
+    let url = reqwest::Url::parse(endpoint.trim()).context("The server isn't a web address")?;
+    if url.scheme() != "https" && !automation::is_local(endpoint) {
+        bail!("Use an https:// address. Only a server on this computer may use plain http.");
+    }
+    Ok(())
+}
+
+/// The service `config` picks, once its server checks out.
+fn provider(config: &TranslationConfig) -> Result<Box<dyn Provider>> {
+    check_endpoint(&config.endpoint)?;
+    Ok(match config.provider {
+        ProviderKind::LibreTranslate => Box::new(LibreTranslate::new(config)),
+    })
+}
+
+/// A LibreTranslate server, see <https://libretranslate.com/docs>.
+pub struct LibreTranslate {
+    endpoint: String,
+    api_key: Option<String>,
+}
+
+impl LibreTranslate {
+    pub fn new(config: &TranslationConfig) -> Self {
+        let api_key = config.api_key.trim();
+        Self {
+            endpoint: config.endpoint.trim().trim_end_matches('/').to_string(),
+            api_key: (!api_key.is_empty()).then(|| api_key.to_string()),
+        }
+    }
+
+    fn request_body(&self, text: &str, language: &str) -> serde_json::Value {
+        let mut body = json!({
+            "q": text,
+            "source": "auto",
+            "target": language,
+            "format": "text",
+        });
+        if let Some(api_key) = &self.api_key {
+            body["api_key"] = json!(api_key);
+        }
+        body
+    }
+}
+
+/// What a LibreTranslate server answers with.
+#[derive(Deserialize)]
+struct LibreTranslateResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: Option<String>,
+    error: Option<String>,
+}
+
+fn parse_response(body: &str) -> Result<String> {
+    let response: LibreTranslateResponse =
+        serde_json::from_str(body).context("Unexpected answer from the translation service")?;
+    match (response.translated_text, response.error) {
+        (_, Some(error)) => bail!(error),
+        (Some(text), None) => Ok(text),
+        (None, None) => bail!("The translation service didn't send a translation"),
+    }
+}
+
+impl Provider for LibreTranslate {
+    fn translate(&self, text: &str, language: &str) -> Result<String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let body = client
+            .post(format!("{}/translate", self.endpoint))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(self.request_body(text, language).to_string())
+            .send()
+            .with_context(|| format!("Couldn't reach {}", self.endpoint))?
+            .text()?;
+        parse_response(&body)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranslationStatus {
+    Translating,
+    Done(String),
+    Failed(String),
+}
+
+/// A finished translation: the message, the language and the text or why there's none.
+type Translated = (String, String, Result<String, String>);
+
+pub struct Translator {
+    pub config: TranslationConfig,
+    /// Translations this session, by message id and language.
+    translations: HashMap<(String, String), TranslationStatus>,
+    /// Messages with their translation open under them.
+    shown: HashSet<String>,
+    sender: Sender<Translated>,
+    receiver: Receiver<Translated>,
+}
+
+impl Translator {
+    pub fn new(config: TranslationConfig) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            config,
+            translations: HashMap::new(),
+            shown: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Whether a translation service is set up.
+    pub fn enabled(&self) -> bool {
+        !self.config.endpoint.trim().is_empty()
+    }
+
+    /// The translation of `message` if it's open.
+    pub fn shown(&self, message: &str) -> Option<&TranslationStatus> {
+        if !self.shown.contains(message) {
+            return None;
+        }
+        let key = (message.to_string(), self.config.language.clone());
+        self.translations.get(&key)
+    }
+
+    /// Opens the translation of `message`, translating its `text` if it hasn't been yet, or
+    /// closes it.
+    pub fn toggle(&mut self, db: &Db, message: &str, text: &str) {
+        if !self.shown.insert(message.to_string()) {
+            self.shown.remove(message);
+            return;
+        }
+        let language = self.config.language.clone();
+        let key = (message.to_string(), language.clone());
+        if matches!(
+            self.translations.get(&key),
+            Some(TranslationStatus::Translating | TranslationStatus::Done(_))
+        ) {
+            return;
+        }
+        match db.get_translation(message, &language) {
+            Ok(Some(text)) => {
+                self.translations.insert(key, TranslationStatus::Done(text));
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to load the translation of {}: {}", message, e),
+        }
+
+        let provider = match provider(&self.config) {
+            Ok(provider) => provider,
+            Err(e) => {
+                self.translations
+                    .insert(key, TranslationStatus::Failed(e.to_string()));
+                return;
+            }
+        };
+        self.translations
+            .insert(key, TranslationStatus::Translating);
+        let sender = self.sender.clone();
+        let message = message.to_string();
+        let text = text.to_string();
+        thread::spawn(move || {
+            let translated = provider
+                .translate(&text, &language)
+                .map_err(|e| e.to_string());
+            if sender.send((message, language, translated)).is_err() {
+                debug!("Translation receiver dropped before the translation came back");
+            }
+        });
+    }
+
+    /// Takes in finished translations and keeps them.
+    pub fn process_queue(&mut self, db: &Db, ctx: &egui::Context) {
+        while let Ok((message, language, translated)) = self.receiver.try_recv() {
+            let status = match translated {
+                Ok(text) => {
+                    info!("Translated {} into {}", message, language);
+                    if let Err(e) = db.save_translation(&message, &language, &text) {
+                        error!("Failed to save the translation of {}: {}", message, e);
+                    }
+                    TranslationStatus::Done(text)
+                }
+                Err(e) => {
+                    error!("Failed to translate {}: {}", message, e);
+                    TranslationStatus::Failed(e)
+                }
+            };
+            self.translations.insert((message, language), status);
+        }
+
+        // The translation threads can't wake us up, so keep polling while they run.
+        if self
+            .translations
+            .values()
+            .any(|status| *status == TranslationStatus::Translating)
+        {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn talks_libretranslate() -> Result<()> {
+        let config = TranslationConfig {
+            endpoint: "https://translate.example.com/ ".to_string(),
+            api_key: "secret".to_string(),
+            ..Default::default()
+        };
+        let provider = LibreTranslate::new(&config);
+        assert_eq!(provider.endpoint, "https://translate.example.com");
+        let body = provider.request_body("Hola", "en");
+        assert_eq!(body["q"], "Hola");
+        assert_eq!(body["target"], "en");
+        assert_eq!(body["api_key"], "secret");
+
+        assert_eq!(parse_response(r#"{"translatedText": "Hello"}"#)?, "Hello");
+        assert!(parse_response(r#"{"error": "Invalid API key"}"#).is_err());
+        assert!(parse_response("<html>").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn only_sends_over_https() {
+        assert!(check_endpoint("https://translate.example.com").is_ok());
+        assert!(check_endpoint("http://localhost:5000").is_ok());
+        assert!(check_endpoint("http://translate.example.com").is_err());
+        assert!(check_endpoint("translate.example.com").is_err());
+    }
+}
@@ -2,7 +2,7 @@ use crate::profile_metadata::{
     get_profile_metadata, update_logged_in_profile_metadata, ProfileMetadata, ProfileOption,
 };
 use eframe::egui::{self, RichText};
-use nostr::{Keys, ToBech32};
+use nostr::{Keys, PublicKey, ToBech32};
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,8 +25,16 @@ pub struct AddAccountWindowState {
     pub step: AccountCreationStep,
 
     // Import fields
+    /// An nsec, or backup words (NIP-06).
     pub nsec_input: String,
+    /// Which account to derive from backup words, 0 unless the words made several.
+    pub account_index: u32,
+    /// Picture of a QR code holding the nsec, e.g. a screenshot from a mobile signer.
+    pub qr_path: String,
     pub imported_key: Option<Keys>,
+    /// The last input and account index checked and what came of it, since deriving keys from
+    /// backup words is too slow to do every frame.
+    validated: Option<((String, u32), Result<Keys, String>)>,
 
     // Generated key
     pub generated_key: Option<Keys>,
@@ -48,7 +56,10 @@ impl Default for AddAccountWindowState {
             mode: None,
             step: AccountCreationStep::ModeSelection,
             nsec_input: String::new(),
+            account_index: 0,
+            qr_path: String::new(),
             imported_key: None,
+            validated: None,
             generated_key: None,
             display_name: String::new(),
             name: String::new(),
@@ -186,7 +197,7 @@ impl AddAccountWindow {
 
     fn render_import_step(app: &mut crate::Hoot, ui: &mut egui::Ui, id: egui::Id) {
         ui.add_space(10.0);
-        ui.label("Enter your private key (nsec):");
+        ui.label("Enter your private key (nsec) or backup words:");
         ui.add_space(5.0);
 
         // Clone data we need before entering closures
@@ -202,20 +213,72 @@ impl AddAccountWindow {
         ui.add_sized(
             [ui.available_width(), 24.0],
             egui::TextEdit::singleline(&mut nsec_input)
-                .hint_text("nsec1...")
+                .hint_text("nsec1... or word word word ...")
                 .password(true),
         );
 
+        let state = app.state.add_account_window.get_mut(&id).unwrap();
+        let mnemonic = crate::account_manager::looks_like_mnemonic(&nsec_input);
+        if mnemonic {
+            ui.horizontal(|ui| {
+                let label = ui.label("Account number:");
+                ui.add(egui::DragValue::new(&mut state.account_index).clamp_range(0..=999))
+                    .labelled_by(label.id)
+                    .on_hover_text(
+                        "Backup words can make several accounts. The first one is 0, leave it \
+                         unless the client you used before made more than one.",
+                    );
+            });
+        }
+        let account_index = state.account_index;
+
+        ui.add_space(5.0);
+        ui.label("Or read it from a picture of a QR code:");
+        let state = app.state.add_account_window.get_mut(&id).unwrap();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut state.qr_path)
+                    .hint_text("/path/to/screenshot.png")
+                    .desired_width(330.0),
+            );
+            if ui.button("Read QR").clicked() {
+                let path = std::path::PathBuf::from(state.qr_path.trim());
+                match crate::qr::read_file(&path).and_then(|codes| crate::qr::find_nsec(&codes)) {
+                    Ok(nsec) => {
+                        nsec_input = nsec;
+                        state.error_message = None;
+                    }
+                    Err(e) => state.error_message = Some(format!("{:#}", e)),
+                }
+            }
+        });
+
         // Update the state with the new input
-        app.state
-            .add_account_window
-            .get_mut(&id)
-            .unwrap()
-            .nsec_input = nsec_input.clone();
+        let state = app.state.add_account_window.get_mut(&id).unwrap();
+        state.nsec_input = nsec_input.clone();
 
         // Validation indicator
-        let validation_result = Self::validate_nsec(&nsec_input);
+        let checked = (nsec_input.clone(), account_index);
+        let validation_result = match &state.validated {
+            Some((input, result)) if *input == checked => result.clone(),
+            _ => {
+                let result = if mnemonic {
+                    crate::account_manager::validate_mnemonic(&nsec_input, account_index)
+                } else {
+                    Self::validate_nsec(&nsec_input)
+                };
+                state.validated = Some((checked, result.clone()));
+                result
+            }
+        };
         ui.horizontal(|ui| match &validation_result {
+            Ok(keys) if mnemonic => {
+                let npub = keys.public_key().to_bech32().unwrap_or_default();
+                ui.colored_label(
+                    egui::Color32::GREEN,
+                    format!("✓ Valid backup words for {}", npub),
+                );
+            }
             Ok(_) => {
                 ui.colored_label(egui::Color32::GREEN, "✓ Valid nsec format");
             }
@@ -250,6 +313,7 @@ impl AddAccountWindow {
                             .error_message = Some("This account is already added".to_string());
                     } else {
                         let pubkey_str = keys.public_key().to_string();
+                        app.start_account_preflight(&keys.public_key());
 
                         // Update state with imported key
                         let state = app.state.add_account_window.get_mut(&id).unwrap();
@@ -287,6 +351,8 @@ impl AddAccountWindow {
                 state.step = AccountCreationStep::ModeSelection;
                 state.error_message = None;
                 state.nsec_input.clear();
+                state.account_index = 0;
+                state.qr_path.clear();
             }
         });
     }
@@ -429,6 +495,7 @@ impl AddAccountWindow {
         };
         ui.label(format!("Type: {}", account_type));
         ui.add_space(5.0);
+        let imported = state.mode == Some(AccountCreationMode::Import);
 
         // Show public key
         ui.label("Public Key:");
@@ -475,6 +542,11 @@ impl AddAccountWindow {
             ui.label("No metadata configured");
         }
 
+        if imported {
+            ui.add_space(10.0);
+            Self::preflight_report(app, ui, &key.public_key());
+        }
+
         ui.add_space(15.0);
 
         // Navigation buttons
@@ -487,6 +559,7 @@ impl AddAccountWindow {
                 match Self::save_account(app, &state_clone, &key) {
                     Ok(_) => {
                         info!("Account saved successfully");
+                        app.relays.end_preflight(&key.public_key().to_hex());
                         should_close = true;
                     }
                     Err(e) => {
@@ -514,6 +587,44 @@ impl AddAccountWindow {
         should_close
     }
 
+    /// What the relays already hold for an imported key, see `Hoot::start_account_preflight`.
+    pub fn preflight_report(app: &crate::Hoot, ui: &mut egui::Ui, pubkey: &PublicKey) {
+        let Some(preflight) = app.relays.preflight(&pubkey.to_hex()) else {
+            return;
+        };
+        let done = preflight.is_done();
+        let (total, _, _) = preflight.total();
+        ui.horizontal(|ui| {
+            if !done {
+                ui.spinner();
+            }
+            let summary = RichText::new(preflight.summary());
+            if done && total == 0 {
+                ui.colored_label(egui::Color32::YELLOW, summary)
+                    .on_hover_text(
+                        "If you expected mail here, this key may have used other relays",
+                    );
+            } else {
+                ui.label(summary);
+            }
+        });
+
+        let mut relays: Vec<_> = preflight.relays.iter().collect();
+        relays.sort_by(|a, b| a.0.cmp(b.0));
+        if !relays.is_empty() {
+            ui.collapsing("Per relay", |ui| {
+                for (url, status) in relays {
+                    ui.label(format!("{}: {}", url, status));
+                }
+            });
+        }
+
+        if !done {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(500));
+        }
+    }
+
     fn validate_nsec(input: &str) -> Result<Keys, String> {
         crate::account_manager::validate_nsec(input)
     }
@@ -554,6 +665,7 @@ impl AddAccountWindow {
                     } else {
                         None
                     },
+                    lud16: None,
                 };
 
                 match update_logged_in_profile_metadata(app, key.public_key(), metadata) {
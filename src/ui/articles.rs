@@ -0,0 +1,114 @@
+//! Long-form notes shared in mail: cards under a message in the thread view, and chips for the
+//! ones being shared in compose.
+
+use crate::article::{ArticleState, Articles};
+use crate::relay::RelayPool;
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, Frame, Margin, RichText, Stroke, Ui};
+use nostr::EventId;
+use tracing::error;
+
+/// Asks our relays for whichever of `ids` we haven't fetched yet.
+pub fn fetch(articles: &mut Articles, relays: &mut RelayPool, ids: &[EventId]) {
+    if let Some(subscription) = articles.fetch(ids) {
+        if let Err(e) = relays.add_subscription(subscription) {
+            error!("Failed to fetch shared notes: {}", e);
+        }
+    }
+}
+
+/// Shows each note in `ids` as a card with its title and summary, and the text to expand.
+pub fn article_cards(app: &mut Hoot, ui: &mut Ui, ids: &[EventId]) {
+    fetch(&mut app.articles, &mut app.relays, ids);
+    for id in ids {
+        Frame::none()
+            .fill(style::CARD_BG)
+            .stroke(Stroke::new(1.0, style::CARD_STROKE))
+            .inner_margin(Margin::same(12.0))
+            .rounding(8.0)
+            .show(ui, |ui| match app.articles.get(id) {
+                ArticleState::Fetching => {
+                    let text = RichText::new("Fetching a shared note…").color(style::TEXT_MUTED);
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Spinner::new().size(12.0));
+                        ui.label(text);
+                    });
+                }
+                ArticleState::Missing => {
+                    ui.label(
+                        RichText::new("📰 A shared note that none of your relays have")
+                            .color(style::TEXT_MUTED),
+                    )
+                    .on_hover_text(id.to_hex());
+                }
+                ArticleState::Found(article) => {
+                    ui.label(RichText::new(format!("📰 {}", article.title)).strong());
+                    ui.label(
+                        RichText::new(format!(
+                            "By {} · {}",
+                            app.display_name(&article.author.to_hex()),
+                            style::format_full_timestamp(
+                                article.published_at as i64,
+                                app.preferences.clock_24h
+                            )
+                        ))
+                        .small()
+                        .color(style::TEXT_MUTED),
+                    );
+                    if let Some(summary) = &article.summary {
+                        ui.add_space(4.0);
+                        ui.label(summary);
+                    }
+                    egui::CollapsingHeader::new("Read")
+                        .id_source(("article", id))
+                        .show(ui, |ui| {
+                            ui.label(&article.content);
+                        });
+                }
+            });
+        ui.add_space(8.0);
+    }
+}
+
+/// The notes shared in a message being written, each with a button to take it out again.
+/// [`fetch`] them first.
+pub fn article_chips(ui: &mut Ui, articles: &Articles, ids: &mut Vec<EventId>) {
+    let mut removed = None;
+    ui.horizontal_wrapped(|ui| {
+        for id in ids.iter() {
+            Frame::group(ui.style())
+                .rounding(12.0)
+                .inner_margin(Margin::symmetric(6.0, 2.0))
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        match articles.get(id) {
+                            ArticleState::Found(article) => {
+                                ui.label(format!("📰 {}", article.title))
+                            }
+                            ArticleState::Fetching => {
+                                ui.add(egui::Spinner::new().size(12.0));
+                                ui.label("📰 Shared note")
+                            }
+                            ArticleState::Missing => ui
+                                .colored_label(egui::Color32::RED, "📰 Shared note ⚠")
+                                .on_hover_text(
+                                    "None of your relays have this note, so recipients may \
+                                     not find it either",
+                                ),
+                        };
+                        if ui
+                            .small_button("✖")
+                            .on_hover_text("Stop sharing this note")
+                            .clicked()
+                        {
+                            removed = Some(*id);
+                        }
+                    });
+                });
+        }
+    });
+    if let Some(removed) = removed {
+        ids.retain(|id| *id != removed);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::calendar::Invite;
+use crate::downloads::DownloadStatus;
+use crate::mail_event::Attachment;
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText, Ui};
+
+/// Lists a message's attachments with a download button and progress for each.
+pub fn attachment_list(app: &mut Hoot, ui: &mut Ui, attachments: &[Attachment]) {
+    if attachments.is_empty() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new(format!("Attachments ({})", attachments.len())).color(style::TEXT_MUTED),
+        );
+        if ui.button("Save all").clicked() {
+            for attachment in attachments {
+                app.downloads.request(attachment);
+            }
+        }
+    });
+
+    for attachment in attachments {
+        ui.horizontal(|ui| {
+            ui.label(format!("📎 {}", attachment.file_name()));
+            if let Some(size) = attachment.size {
+                ui.label(
+                    RichText::new(format_size(size))
+                        .small()
+                        .color(style::TEXT_MUTED),
+                );
+            }
+
+            match app.downloads.status(attachment).cloned() {
+                None => {
+                    if ui.button("Save").clicked() {
+                        app.downloads.request(attachment);
+                    }
+                }
+                Some(DownloadStatus::InProgress { received, total }) => {
+                    let progress = match total {
+                        Some(total) if total > 0 => received as f32 / total as f32,
+                        _ => 0.0,
+                    };
+                    ui.add(
+                        egui::ProgressBar::new(progress)
+                            .desired_width(160.0)
+                            .text(format_size(received)),
+                    );
+                }
+                Some(DownloadStatus::Complete(path)) => {
+                    ui.label(
+                        RichText::new(format!("Saved to {}", path.display()))
+                            .small()
+                            .color(style::TEXT_MUTED),
+                    );
+                }
+                Some(DownloadStatus::Failed(e)) => {
+                    ui.label(RichText::new(e).small().color(Color32::RED));
+                    if ui.button("Retry").clicked() {
+                        app.downloads.request(attachment);
+                    }
+                }
+            }
+        });
+    }
+
+    // Saved invites get a card, we don't fetch them on our own since that would tell the host
+    // we opened the message.
+    for attachment in attachments.iter().filter(|a| a.is_calendar()) {
+        let Some(DownloadStatus::Complete(path)) = app.downloads.status(attachment).cloned() else {
+            continue;
+        };
+        let invite = app
+            .state
+            .calendar_invites
+            .entry(attachment.sha256.clone())
+            .or_insert_with(|| {
+                std::fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|ics| Invite::parse(&ics))
+            })
+            .clone();
+        if let Some(invite) = invite {
+            ui.add_space(8.0);
+            super::invite_card::invite_card(app, ui, &invite);
+        }
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,476 @@
+//! Code in message bodies: fenced blocks (```) shown in a monospace font with their language
+//! highlighted, and unified diffs, fenced or pasted as they are, shown as patches that can be
+//! saved for `git apply`.
+
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, text::LayoutJob, Color32, FontId, RichText, TextFormat, Ui};
+use egui_extras::syntax_highlighting::{self, CodeTheme};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+/// A part of a message body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    /// Prose, starting `offset` bytes into the body.
+    Text {
+        text: &'a str,
+        offset: usize,
+    },
+    Code {
+        language: &'a str,
+        code: &'a str,
+    },
+    Patch(&'a str),
+}
+
+/// Splits `body` into prose, code blocks and patches. A body with neither is one [`Segment::Text`].
+pub fn segments(body: &str) -> Vec<Segment<'_>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+        offset += line.len();
+    }
+    let start_of = |i: usize| lines.get(i).map_or(body.len(), |(offset, _)| *offset);
+    let end_of = |i: usize| {
+        let (offset, line) = lines[i];
+        offset + line.len()
+    };
+    let texts: Vec<&str> = lines.iter().map(|(_, line)| *line).collect();
+
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = texts[i];
+        let fence = line
+            .trim_start()
+            .strip_prefix("```")
+            .filter(|info| !info.contains('`'));
+        let (segment, next) = if let Some(info) = fence {
+            let close = (i + 1..lines.len()).find(|&j| texts[j].trim() == "```");
+            let last = close.unwrap_or(lines.len());
+            let code = if last > i + 1 {
+                &body[start_of(i + 1)..end_of(last - 1)]
+            } else {
+                ""
+            };
+            let language = info.split_whitespace().next().unwrap_or("");
+            let is_patch = matches!(language, "diff" | "patch")
+                || (language.is_empty() && diff_end(&texts[i + 1..last], 0) == Some(last - i - 1));
+            let segment = if is_patch {
+                Segment::Patch(code)
+            } else {
+                Segment::Code { language, code }
+            };
+            (segment, last + 1)
+        } else if starts_diff(&texts, i) {
+            match diff_end(&texts, i) {
+                Some(end) => (Segment::Patch(&body[start_of(i)..end_of(end - 1)]), end),
+                None => {
+                    i += 1;
+                    continue;
+                }
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+
+        let text = &body[text_start..start_of(i)];
+        if !text.trim().is_empty() {
+            segments.push(Segment::Text {
+                text,
+                offset: text_start,
+            });
+        }
+        segments.push(segment);
+        i = next;
+        text_start = start_of(next);
+    }
+    let text = &body[text_start.min(body.len())..];
+    if !text.trim().is_empty() || segments.is_empty() {
+        segments.push(Segment::Text {
+            text,
+            offset: text_start.min(body.len()),
+        });
+    }
+    segments
+}
+
+fn starts_diff(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("diff --git ")
+        || (lines[i].starts_with("--- ")
+            && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+            && lines.get(i + 2).is_some_and(|l| hunk_header(l).is_some()))
+}
+
+/// The line after the unified diff starting at `lines[start]`, if one with at least a hunk
+/// starts there. Hunks are read for as many lines as their headers say, so prose after the
+/// diff isn't taken for part of it.
+fn diff_end(lines: &[&str], start: usize) -> Option<usize> {
+    let mut i = start;
+    let mut hunks = 0;
+    loop {
+        let file_start = i;
+        if lines.get(i).is_some_and(|l| l.starts_with("diff ")) {
+            i += 1;
+            while lines.get(i).is_some_and(|l| is_extended_header(l)) {
+                i += 1;
+            }
+        }
+        if lines.get(i).is_some_and(|l| l.starts_with("--- "))
+            && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+        {
+            i += 2;
+        }
+        if i == file_start {
+            break;
+        }
+        while let Some((mut old, mut new)) = lines.get(i).and_then(|l| hunk_header(l)) {
+            i += 1;
+            hunks += 1;
+            while old > 0 || new > 0 {
+                let Some(line) = lines.get(i) else {
+                    break;
+                };
+                match line.chars().next() {
+                    Some('+') if new > 0 => new -= 1,
+                    Some('-') if old > 0 => old -= 1,
+                    // Mail often loses the space that starts an empty context line.
+                    Some(' ') | None if old > 0 && new > 0 => {
+                        old -= 1;
+                        new -= 1;
+                    }
+                    Some('\\') => {}
+                    _ => break,
+                }
+                i += 1;
+            }
+            while lines.get(i).is_some_and(|l| l.starts_with('\\')) {
+                i += 1;
+            }
+        }
+    }
+    (hunks > 0).then_some(i)
+}
+
+fn is_extended_header(line: &str) -> bool {
+    [
+        "index ",
+        "old mode ",
+        "new mode ",
+        "new file mode ",
+        "deleted file mode ",
+        "similarity index ",
+        "dissimilarity index ",
+        "rename from ",
+        "rename to ",
+        "copy from ",
+        "copy to ",
+        "Binary files ",
+    ]
+    .iter()
+    .any(|prefix| line.starts_with(prefix))
+}
+
+/// How many lines a hunk takes away and adds, from a header like `@@ -1,4 +1,5 @@`.
+fn hunk_header(line: &str) -> Option<(usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split_whitespace();
+    let count = |range: &str| match range.split_once(',') {
+        Some((_, count)) => count.parse().ok(),
+        None => range.parse::<usize>().ok().map(|_| 1),
+    };
+    let old = count(ranges.next()?.strip_prefix('-')?)?;
+    let new = count(ranges.next()?.strip_prefix('+')?)?;
+    (ranges.next()? == "@@").then_some((old, new))
+}
+
+/// The files a patch changes, and how many lines it adds and takes away.
+#[derive(Debug, Default, PartialEq)]
+pub struct PatchSummary {
+    pub files: Vec<String>,
+    pub added: usize,
+    pub removed: usize,
+}
+
+pub fn summary(patch: &str) -> PatchSummary {
+    let mut summary = PatchSummary::default();
+    let mut old_name = None;
+    for line in patch.lines() {
+        if let Some(name) = line.strip_prefix("--- ") {
+            old_name = Some(name);
+        } else if let Some(name) = line.strip_prefix("+++ ") {
+            let name = match (name, old_name.take()) {
+                ("/dev/null", Some(old)) => old,
+                _ => name,
+            };
+            let name = name.split('\t').next().unwrap_or(name);
+            let name = name
+                .strip_prefix("a/")
+                .or_else(|| name.strip_prefix("b/"))
+                .unwrap_or(name);
+            summary.files.push(name.to_string());
+        } else if line.starts_with('+') {
+            summary.added += 1;
+        } else if line.starts_with('-') {
+            summary.removed += 1;
+        }
+    }
+    summary
+}
+
+/// A fenced code block, highlighted for its language when it's one we know.
+pub fn code_block(ui: &mut Ui, language: &str, code: &str) {
+    block_frame(ui).show(ui, |ui| {
+        ui.horizontal(|ui| {
+            if !language.is_empty() {
+                ui.label(RichText::new(language).small().color(style::TEXT_MUTED));
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("Copy").clicked() {
+                    ui.ctx().copy_text(code.to_string());
+                }
+            });
+        });
+        let theme = CodeTheme::from_style(ui.style());
+        let job = syntax_highlighting::highlight(ui.ctx(), &theme, code, language);
+        egui::ScrollArea::horizontal()
+            .id_source(ui.next_auto_id())
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(job).selectable(true).wrap(false));
+            });
+    });
+}
+
+/// A unified diff with what it changes, its lines coloured, and buttons to save or copy it.
+/// `name` is what the saved file is called.
+pub fn patch_view(app: &mut Hoot, ui: &mut Ui, id: egui::Id, name: &str, patch: &str) {
+    let summary = summary(patch);
+    block_frame(ui).show(ui, |ui| {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Patch").strong());
+            ui.label(RichText::new(format!("+{}", summary.added)).color(added_color(ui)));
+            ui.label(RichText::new(format!("−{}", summary.removed)).color(removed_color(ui)));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("Copy").clicked() {
+                    ui.ctx().copy_text(patch.to_string());
+                }
+                if ui.small_button("Save as .patch").clicked() {
+                    let status = match save_patch(&app.downloads.download_dir, name, patch) {
+                        Ok(path) => {
+                            info!("Saved a patch to {:?}", path);
+                            format!("Saved to {}", path.display())
+                        }
+                        Err(e) => {
+                            error!("Failed to save a patch: {}", e);
+                            app.toasts.error(format!("Couldn't save the patch: {}", e));
+                            String::new()
+                        }
+                    };
+                    ui.data_mut(|d| d.insert_temp(id, status));
+                }
+            });
+        });
+        let files = match summary.files.len() {
+            1 => summary.files[0].clone(),
+            n => format!("{} files: {}", n, summary.files.join(", ")),
+        };
+        ui.label(RichText::new(files).small().color(style::TEXT_MUTED));
+        if let Some(status) = ui.data(|d| d.get_temp::<String>(id)) {
+            if !status.is_empty() {
+                ui.label(RichText::new(status).small().color(style::TEXT_MUTED));
+            }
+        }
+        ui.separator();
+        let job = patch_layout(ui, patch);
+        egui::ScrollArea::horizontal()
+            .id_source(id.with("scroll"))
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(job).selectable(true).wrap(false));
+            });
+    });
+}
+
+fn block_frame(ui: &Ui) -> egui::Frame {
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .stroke(ui.visuals().widgets.noninteractive.bg_stroke)
+        .rounding(6.0)
+        .inner_margin(8.0)
+        .outer_margin(egui::Margin::symmetric(0.0, 4.0))
+}
+
+fn added_color(ui: &Ui) -> Color32 {
+    if ui.visuals().dark_mode {
+        Color32::from_rgb(126, 231, 135)
+    } else {
+        Color32::from_rgb(26, 127, 55)
+    }
+}
+
+fn removed_color(ui: &Ui) -> Color32 {
+    if ui.visuals().dark_mode {
+        Color32::from_rgb(255, 161, 152)
+    } else {
+        Color32::from_rgb(207, 34, 46)
+    }
+}
+
+fn patch_layout(ui: &Ui, patch: &str) -> LayoutJob {
+    let format = TextFormat {
+        font_id: FontId::monospace(12.0),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let mut job = LayoutJob::default();
+    for line in patch.split_inclusive('\n') {
+        let format = if line.starts_with("+++ ") || line.starts_with("--- ") {
+            TextFormat {
+                color: ui.visuals().strong_text_color(),
+                ..format.clone()
+            }
+        } else if line.starts_with('+') {
+            TextFormat {
+                color: added_color(ui),
+                background: added_color(ui).gamma_multiply(0.12),
+                ..format.clone()
+            }
+        } else if line.starts_with('-') {
+            TextFormat {
+                color: removed_color(ui),
+                background: removed_color(ui).gamma_multiply(0.12),
+                ..format.clone()
+            }
+        } else if line.starts_with("@@") {
+            TextFormat {
+                color: style::ACCENT,
+                ..format.clone()
+            }
+        } else if line.starts_with("diff ") || is_extended_header(line) {
+            TextFormat {
+                color: style::TEXT_MUTED,
+                ..format.clone()
+            }
+        } else {
+            format.clone()
+        };
+        job.append(line, 0.0, format);
+    }
+    job
+}
+
+/// Writes `patch` to `dir`, named after `name`, without overwriting a different file.
+fn save_patch(dir: &Path, name: &str, patch: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let stem: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect();
+    let stem = if stem.is_empty() {
+        "message".to_string()
+    } else {
+        stem
+    };
+
+    let mut contents = patch.to_string();
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    let mut path = dir.join(format!("{}.patch", stem));
+    let mut n = 1;
+    while path.exists() && fs::read_to_string(&path).ok().as_deref() != Some(&contents) {
+        path = dir.join(format!("{}-{}.patch", stem, n));
+        n += 1;
+    }
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/main.rs b/src/main.rs\n\
+                        index 83db48f..bf269f4 100644\n\
+                        --- a/src/main.rs\n\
+                        +++ b/src/main.rs\n\
+                        @@ -1,3 +1,4 @@\n\
+                        \x20fn main() {\n\
+                        -    println!(\"hi\");\n\
+                        +    println!(\"hello\");\n\
+                        +    println!(\"world\");\n\
+                        \n\
+                        @@ -10 +11 @@\n\
+                        -old\n\
+                        +new";
+
+    #[test]
+    fn finds_code_and_patches() {
+        let body = format!("Here's the fix:\n\n{}\n\nThanks,\nAda\n", DIFF);
+        let parts = segments(&body);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1], Segment::Patch(DIFF));
+        assert_eq!(
+            parts[2],
+            Segment::Text {
+                text: "\nThanks,\nAda\n",
+                offset: body.find("\nThanks").unwrap()
+            }
+        );
+
+        let body =
+            "Try\n```rust\nfn main() {}\n```\nor\n```\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n```";
+        assert_eq!(
+            segments(body),
+            vec![
+                Segment::Text {
+                    text: "Try\n",
+                    offset: 0
+                },
+                Segment::Code {
+                    language: "rust",
+                    code: "fn main() {}"
+                },
+                Segment::Text {
+                    text: "or\n",
+                    offset: 29
+                },
+                Segment::Patch("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b"),
+            ]
+        );
+
+        // Lists and signatures aren't diffs.
+        let body = "Changes:\n- one\n+ two\n-- \nAda";
+        assert_eq!(
+            segments(body),
+            vec![Segment::Text {
+                text: body,
+                offset: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn summarizes_patches() {
+        assert_eq!(
+            summary(DIFF),
+            PatchSummary {
+                files: vec!["src/main.rs".to_string()],
+                added: 3,
+                removed: 2,
+            }
+        );
+    }
+}
@@ -1,11 +1,50 @@
-use crate::mail_event::MailMessage;
-use crate::relay::ClientMessage;
+use crate::article;
+use crate::bolt11;
+use crate::bridge;
+use crate::db::Db;
+use crate::mail_event::{Fragment, MailMessage, TagRelays};
+use crate::payload::{self, Payload};
+use crate::payments::{Payments, Purpose};
+use crate::profile_metadata::ProfileOption;
+use crate::relay::{
+    Ack, Presence, Rejection, RelayHints, RelayPool, SendReport, CONFIRM_DELAY,
+};
 use crate::style;
+use crate::ui::contact_picker::{self, ContactPicker, PickerOutcome};
+use crate::ui::detached;
+use crate::uploads::{self, Source, Upload, UploadId, UploadStatus};
 use eframe::egui::{self, Color32, RichText};
-use nostr::{EventId, Keys, PublicKey};
-use tracing::{debug, error, info};
+use nostr::{EventId, Keys, PublicKey, ToBech32, UnsignedEvent};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
+/// How long relays get to confirm a send before the silent ones count as failed.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A send waiting for the relays to confirm it.
 #[derive(Debug, Clone)]
+pub struct PendingSend {
+    /// The message sent, for the notification if it doesn't get through.
+    pub message_id: Option<String>,
+    pub event_ids: Vec<String>,
+    pub started: Instant,
+    /// Who sent the message and what it was, to wrap it again with proof of work for relays
+    /// that want some.
+    pub rewrap: Option<(Keys, UnsignedEvent)>,
+    /// Who each wrap is for, by event id.
+    pub recipients: HashMap<String, PublicKey>,
+}
+
+/// What one relay made of a send so far.
+#[derive(Debug, Clone, PartialEq)]
+enum RelayProgress {
+    Waiting,
+    Accepted,
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ComposeWindowState {
     pub subject: String,
     pub to_field: String,
@@ -14,6 +53,93 @@ pub struct ComposeWindowState {
     pub selected_account: Option<Keys>,
     pub minimized: bool,
     pub draft_id: Option<i64>,
+    /// Send was pressed without a subject and we're asking whether to go ahead.
+    pub confirming_empty_subject: bool,
+    /// Send was pressed with recipients no relay seems to know and we're asking whether to
+    /// go ahead.
+    pub confirming_no_relays: bool,
+    /// Send was pressed and we're showing who the message goes to, and as whom, before it does.
+    pub confirming_recipients: bool,
+    /// Send was pressed with attachments not uploaded yet, and we're asking whether they may
+    /// go to the upload server.
+    pub confirming_upload: bool,
+    /// The upload server wants paying for this upload, and we're asking whether to.
+    pub confirming_payment: Option<UploadId>,
+    /// The attachments are uploading, and the message is sent once they're all up.
+    pub send_after_upload: bool,
+    /// Where the last send went, shown in the delivery details.
+    pub delivery: Option<SendReport>,
+    pub sending: Option<PendingSend>,
+    /// Why the last send failed, shown above the send button until the next try.
+    pub send_error: Option<String>,
+    /// Uploads attached to the message, in the order they were added.
+    pub attachments: Vec<UploadId>,
+    /// Long-form notes shared with the message.
+    pub articles: Vec<EventId>,
+    /// The link to a note to share, while it's being typed.
+    pub article_input: Option<String>,
+    /// The passage of the message being answered that the reply quotes.
+    pub quote: Option<Fragment>,
+    /// Data for apps going with the message, while it's being written.
+    pub payload: Option<PayloadDraft>,
+    /// Shown in an OS window of its own rather than floating over the main one.
+    pub detached: bool,
+    /// Contacts being picked from with the To button.
+    pub contact_picker: Option<ContactPicker>,
+}
+
+/// A [`Payload`] being written: the schema it follows and its JSON as typed.
+#[derive(Debug, Clone)]
+pub struct PayloadDraft {
+    pub schema: String,
+    pub json: String,
+}
+
+impl Default for PayloadDraft {
+    fn default() -> Self {
+        Self {
+            schema: payload::INVOICE.to_string(),
+            json: payload::example(payload::INVOICE),
+        }
+    }
+}
+
+impl ComposeWindowState {
+    /// Keeps an unsent message as a draft, e.g. when the app quits with the window still open.
+    pub fn save_draft(&mut self, db: &Db) -> anyhow::Result<()> {
+        let empty = self.subject.trim().is_empty()
+            && self.to_field.trim().is_empty()
+            && self.content.trim().is_empty();
+        if self.sending.is_some() || empty {
+            return Ok(());
+        }
+
+        let parent_events: Vec<String> = self.parent_events.iter().map(|e| e.to_hex()).collect();
+        let selected_account = self
+            .selected_account
+            .as_ref()
+            .map(|k| k.public_key().to_string());
+        match self.draft_id {
+            Some(draft_id) => db.update_draft(
+                draft_id,
+                &self.subject,
+                &self.to_field,
+                &self.content,
+                &parent_events,
+                selected_account.as_deref(),
+            )?,
+            None => {
+                self.draft_id = Some(db.save_draft(
+                    &self.subject,
+                    &self.to_field,
+                    &self.content,
+                    &parent_events,
+                    selected_account.as_deref(),
+                )?)
+            }
+        }
+        Ok(())
+    }
 }
 
 enum DraftAction {
@@ -26,12 +152,19 @@ enum DraftAction {
         selected_account: Option<String>,
         existing_id: Option<i64>,
     },
-    Delete(i64),
 }
 
 pub struct ComposeWindow {}
 
 impl ComposeWindow {
+    /// Opens a compose window for `state`. Everything that starts a message goes through here,
+    /// so new windows all come up the same way.
+    pub fn open(app: &mut crate::Hoot, state: ComposeWindowState) -> egui::Id {
+        let id = egui::Id::new(rand::random::<u32>());
+        app.state.compose_window.insert(id, state);
+        id
+    }
+
     /// Returns `false` when the window has been closed and should be removed.
     pub fn show_window(app: &mut crate::Hoot, ctx: &egui::Context, id: egui::Id) -> bool {
         let screen_rect = ctx.screen_rect();
@@ -40,88 +173,492 @@ impl ComposeWindow {
 
         // Pre-resolve account display names before borrowing state,
         // since resolve_name borrows app immutably and state borrows app.state mutably.
-        let account_options: Vec<(Keys, String)> = app
-            .account_manager
-            .loaded_keys
-            .iter()
-            .map(|k| {
-                let pk_hex = k.public_key().to_hex();
-                let name = app.resolve_name(&pk_hex).unwrap_or(pk_hex);
-                (k.clone(), name)
-            })
+        let (account_options, account_colors) = account_options(app);
+        let default_account = app
+            .state
+            .compose_window
+            .get(&id)
+            .and_then(|state| default_account(app, &state.to_field));
+        let recipient_names: HashMap<PublicKey, String> = app
+            .state
+            .compose_window
+            .get(&id)
+            .map(|state| bridge::parse_recipients(&state.to_field).pubkeys)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|pubkey| Some((pubkey, app.resolve_name(&pubkey.to_hex())?)))
             .collect();
 
+        let edited = app.external_editors.poll(id);
+        let editing = app.external_editors.is_editing(id);
+        if editing {
+            // Saves in the editor show up without the user touching the window.
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+
+        if let Some(mut state) = app.state.compose_window.remove(&id) {
+            if settle_send(app, ctx, &mut state) {
+                app.external_editors.forget(id);
+                finish_send(app, state);
+                return false;
+            }
+            app.state.compose_window.insert(id, state);
+        }
+
         let state = app
             .state
             .compose_window
             .get_mut(&id)
             .expect("no state found for id");
+        if let Some(content) = edited {
+            state.content = content;
+        }
+        // Until an account is picked, we send as the default one.
+        let account = state.selected_account.clone().or(default_account);
+
+        let progress = match (&state.sending, &state.delivery) {
+            (Some(pending), Some(report)) => send_progress(&app.relays, report, pending),
+            _ => Vec::new(),
+        };
 
         let mut open = true;
         let mut draft_action = DraftAction::None;
+        let mut open_picker = false;
+        let mut open_editor = false;
+        let mut send_now = false;
+        let mut upload_now = false;
+        // Uploads are signed by the account we send as.
+        let upload_keys = account.clone();
 
-        egui::Window::new("New Message")
-            .id(id)
-            .open(&mut open)
-            .default_size([min_width, min_height])
-            .min_width(300.0)
-            .min_height(200.0)
-            .default_pos([
-                screen_rect.right() - min_width - 20.0,
-                screen_rect.bottom() - min_height - 20.0,
-            ])
-            .show(ctx, |ui| {
-                ui.vertical(|ui| {
-                    // Header section
-                    ui.horizontal(|ui| {
-                        ui.label(RichText::new("To:").color(style::TEXT_MUTED));
-                        ui.add_sized(
-                            [ui.available_width(), 24.0],
-                            egui::TextEdit::singleline(&mut state.to_field)
-                                .hint_text("Recipient public key"),
-                        );
-                    });
+        // The message follows its attachments once they're all up.
+        if state.send_after_upload {
+            let statuses: Vec<&UploadStatus> = state
+                .attachments
+                .iter()
+                .filter_map(|id| app.uploads.get(*id))
+                .map(|upload| &upload.status)
+                .collect();
+            if statuses
+                .iter()
+                .all(|status| matches!(status, UploadStatus::Complete(_)))
+            {
+                state.send_after_upload = false;
+                send_now = true;
+            } else if !statuses.contains(&&UploadStatus::InProgress) {
+                state.send_after_upload = false;
+                state.send_error =
+                    Some("Not every attachment was uploaded, so the message wasn't sent.".into());
+            }
+        }
 
-                    ui.add_space(2.0);
+        let detached = state.detached;
+        let title = if state.subject.trim().is_empty() {
+            "New Message".to_string()
+        } else {
+            state.subject.clone()
+        };
+        let contents = |ui: &mut egui::Ui| {
+            ui.vertical(|ui| {
+                let selected_index = account.as_ref().and_then(|keys| {
+                    account_options
+                        .iter()
+                        .position(|(key, _)| key.public_key() == keys.public_key())
+                });
+                // The color of the account we write as, so it's hard to send as the wrong one.
+                if let Some(i) = selected_index {
+                    let size = egui::vec2(ui.available_width(), 3.0);
+                    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                    ui.painter().rect_filled(rect, 1.5, account_colors[i]);
+                    ui.add_space(4.0);
+                }
 
-                    ui.horizontal(|ui| {
+                // Header section
+                ui.horizontal(|ui| {
+                    let from_label = ui.label(RichText::new("From:").color(style::TEXT_MUTED));
+                    let selected = selected_index.map(|i| &account_options[i]);
+                    if let Some((keys, name)) = selected {
+                        let pubkey = keys.public_key().to_hex();
+                        style::avatar(ui, app.contacts_manager.get_contact_image(&pubkey), name);
+                    }
+                    account_picker(
+                        ui,
+                        id.with("from"),
+                        &account_options,
+                        &account_colors,
+                        account.as_ref(),
+                        &mut state.selected_account,
+                    )
+                    .labelled_by(from_label.id);
+                });
+
+                ui.add_space(2.0);
+
+                ui.horizontal(|ui| {
+                    let to_label = ui
+                        .button(RichText::new("To: …").color(style::TEXT_MUTED))
+                        .on_hover_text("Choose from your contacts");
+                    if to_label.clicked() {
+                        open_picker = true;
+                    }
+                    let note_to_self = ui
+                        .add_enabled(account.is_some(), egui::Button::new("Me"))
+                        .on_hover_text("Send as a note to yourself");
+                    if note_to_self.clicked() {
+                        if let Some(keys) = &account {
+                            state.to_field = keys.public_key().to_hex();
+                        }
+                    }
+                    ui.add_sized(
+                        [ui.available_width(), 24.0],
+                        egui::TextEdit::singleline(&mut state.to_field)
+                            .hint_text("Recipient public key or email address"),
+                    )
+                    .labelled_by(to_label.id);
+                });
+
+                let recipients = bridge::parse_recipients(&state.to_field);
+                if !recipients.pubkeys.is_empty() {
+                    let removed =
+                        contact_picker::recipient_chips(ui, &recipients.pubkeys, &recipient_names);
+                    if let Some(removed) = removed {
+                        let remaining: Vec<String> = recipients
+                            .pubkeys
+                            .iter()
+                            .filter(|pubkey| **pubkey != removed)
+                            .map(PublicKey::to_hex)
+                            .collect();
+                        state.to_field =
+                            contact_picker::write_recipients(&state.to_field, &remaining);
+                    }
+                }
+                let summary = state.confirming_recipients.then(|| SendSummary {
+                    sender: selected_index.map(|i| {
+                        let (keys, name) = &account_options[i];
+                        (name.clone(), npub(&keys.public_key()), account_colors[i])
+                    }),
+                    recipients: recipients
+                        .pubkeys
+                        .iter()
+                        .map(|pubkey| (recipient_names.get(pubkey).cloned(), npub(pubkey)))
+                        .collect(),
+                    emails: recipients.emails.clone(),
+                    attachments: state
+                        .attachments
+                        .iter()
+                        .filter_map(|id| app.uploads.get(*id))
+                        .map(|upload| upload.name.clone())
+                        .collect(),
+                    articles: state.articles.len(),
+                });
+                recipient_notes(ui, &recipients, |pubkey| {
+                    let hex = pubkey.to_hex();
+                    app.contacts_manager.find_contact(&hex).is_some()
+                        || account_options
+                            .iter()
+                            .any(|(key, _)| key.public_key() == *pubkey)
+                });
+
+                if let Some(lookup) = app.relay_hints.lookup(&recipients.pubkeys) {
+                    if let Err(e) = app.relays.add_subscription(lookup) {
+                        error!("Failed to look up where recipients read: {}", e);
+                    }
+                }
+                // Our own accounts read from our relays.
+                let others = recipients.pubkeys.iter().filter(|pubkey| {
+                    !account_options
+                        .iter()
+                        .any(|(key, _)| key.public_key() == **pubkey)
+                });
+                let mut looking = false;
+                let mut unreachable = Vec::new();
+                for pubkey in others {
+                    match app.relay_hints.presence(&pubkey.to_hex()) {
+                        Presence::Looking => looking = true,
+                        Presence::Unknown => unreachable.push(*pubkey),
+                        Presence::Known(_) => {}
+                    }
+                }
+                if looking {
+                    ctx.request_repaint_after(Duration::from_secs(1));
+                }
+                for pubkey in &unreachable {
+                    let npub = npub(pubkey);
+                    ui.label(
+                        RichText::new(format!("No relays known for {}, delivery may fail", npub))
+                            .small()
+                            .color(Color32::from_rgb(200, 120, 0)),
+                    );
+                }
+
+                ui.add_space(2.0);
+
+                ui.horizontal(|ui| {
+                    let subject_label =
                         ui.label(RichText::new("Subject:").color(style::TEXT_MUTED));
-                        ui.add_sized(
-                            [ui.available_width(), 24.0],
-                            egui::TextEdit::singleline(&mut state.subject)
-                                .hint_text("Message subject"),
+                    ui.add_sized(
+                        [ui.available_width(), 24.0],
+                        egui::TextEdit::singleline(&mut state.subject).hint_text("Message subject"),
+                    )
+                    .labelled_by(subject_label.id);
+                });
+
+                ui.add_space(2.0);
+
+                // Toolbar
+                ui.horizontal(|ui| {
+                    ui.style_mut().spacing.button_padding = egui::vec2(4.0, 4.0);
+                    if toolbar_button(ui, "B", "Bold").clicked() {}
+                    if toolbar_button(ui, "I", "Italic").clicked() {}
+                    if toolbar_button(ui, "U", "Underline").clicked() {}
+                    ui.separator();
+                    if toolbar_button(ui, "🔗", "Insert link").clicked() {}
+                    if toolbar_button(ui, "📎", "Attach file").clicked() {}
+                    if toolbar_button(ui, "📋", "Paste image").clicked() {
+                        paste_image(&mut app.uploads, &mut state.attachments);
+                    }
+                    if toolbar_button(ui, "📰", "Share a long-form note").clicked() {
+                        state.article_input = Some(String::new());
+                    }
+                    if toolbar_button(ui, "{ }", "Add data for apps, like an invoice").clicked()
+                        && state.payload.is_none()
+                    {
+                        state.payload = Some(PayloadDraft::default());
+                    }
+                    if toolbar_button(ui, "😀", "Insert emoji").clicked() {}
+                    if toolbar_button(ui, "📝", "Edit in external editor").clicked() && !editing {
+                        open_editor = true;
+                    }
+                    if editing {
+                        ui.weak("Editing in your editor…");
+                    }
+                    ui.separator();
+                    let pop_out = if state.detached {
+                        "Move back into the main window"
+                    } else {
+                        "Open in its own window"
+                    };
+                    if toolbar_button(ui, "⧉", pop_out).clicked() {
+                        state.detached = !state.detached;
+                    }
+                    if toolbar_button(ui, "⌄", "More options").clicked() {}
+                });
+
+                // Message content
+                // Reserve space for the bottom bar, and the attachments above it.
+                let mut footer_height = if state.attachments.is_empty() {
+                    40.0
+                } else {
+                    84.0
+                };
+                if !state.articles.is_empty() {
+                    footer_height += 32.0;
+                }
+                if state.article_input.is_some() {
+                    footer_height += 32.0;
+                }
+                if state.payload.is_some() {
+                    footer_height += 180.0;
+                }
+                if let Some(summary) = &summary {
+                    footer_height += summary.height();
+                }
+                let available_height = ui.available_height() - footer_height;
+                egui::ScrollArea::vertical()
+                    .max_height(available_height)
+                    .show(ui, |ui| {
+                        let body = ui.add_sized(
+                            [ui.available_width(), available_height - 20.0],
+                            egui::TextEdit::multiline(&mut state.content),
                         );
+                        body.widget_info(|| {
+                            egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Message body")
+                        });
+                        body.context_menu(|ui| {
+                            if ui.button("Paste image").clicked() {
+                                paste_image(&mut app.uploads, &mut state.attachments);
+                                ui.close_menu();
+                            }
+                        });
                     });
 
-                    ui.add_space(2.0);
+                if !state.attachments.is_empty() {
+                    let mut action = None;
+                    ui.horizontal_wrapped(|ui| {
+                        for upload_id in &state.attachments {
+                            if let Some(upload) = app.uploads.get(*upload_id) {
+                                if let Some(chosen) =
+                                    attachment_chip(ui, *upload_id, upload, &app.payments)
+                                {
+                                    action = Some((*upload_id, chosen));
+                                }
+                            }
+                        }
+                    });
+                    match action {
+                        Some((upload_id, ChipAction::Remove)) => {
+                            state.attachments.retain(|id| *id != upload_id);
+                            app.uploads.forget(upload_id);
+                        }
+                        Some((upload_id, ChipAction::Pay)) => {
+                            state.confirming_payment = Some(upload_id);
+                        }
+                        None => {}
+                    }
+                }
+
+                if let Some(upload_id) = state.confirming_payment {
+                    let unpaid = app.uploads.get(upload_id).and_then(|upload| {
+                        match &upload.status {
+                            UploadStatus::PaymentRequired { invoice } => {
+                                Some((upload.name.clone(), invoice.clone()))
+                            }
+                            _ => None,
+                        }
+                    });
+                    // Gone or paid already counts as cancelled.
+                    let decision = match &unpaid {
+                        Some((name, invoice)) => confirm_upload_payment(ui, name, invoice),
+                        None => Some(false),
+                    };
+                    if decision.is_some() {
+                        state.confirming_payment = None;
+                    }
+                    if let (Some(true), Some((_, invoice)), Some(keys)) =
+                        (decision, &unpaid, &upload_keys)
+                    {
+                        let purpose = Purpose::Upload {
+                            id: upload_id,
+                            keys: keys.clone(),
+                        };
+                        app.payments.pay(invoice, purpose);
+                    }
+                }
 
-                    // Toolbar
+                if let Some(input) = state.article_input.as_mut() {
+                    let mut done = false;
                     ui.horizontal(|ui| {
-                        ui.style_mut().spacing.button_padding = egui::vec2(4.0, 4.0);
-                        if ui.button("B").clicked() {}
-                        if ui.button("I").clicked() {}
-                        if ui.button("U").clicked() {}
-                        ui.separator();
-                        if ui.button("🔗").clicked() {}
-                        if ui.button("📎").clicked() {}
-                        if ui.button("😀").clicked() {}
-                        ui.separator();
-                        if ui.button("⌄").clicked() {}
+                        let field = ui.add(
+                            egui::TextEdit::singleline(input)
+                                .hint_text("nevent1…, note1… or a note id")
+                                .desired_width(260.0),
+                        );
+                        field.widget_info(|| {
+                            egui::WidgetInfo::labeled(
+                                egui::WidgetType::TextEdit,
+                                "Long-form note to share",
+                            )
+                        });
+                        let parsed = article::parse_reference(input);
+                        let entered =
+                            field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        let share = ui
+                            .add_enabled(parsed.is_ok(), egui::Button::new("Share"))
+                            .clicked();
+                        if let Ok(id) = parsed {
+                            if share || entered {
+                                if !state.articles.contains(&id) {
+                                    state.articles.push(id);
+                                }
+                                done = true;
+                            }
+                        } else if !input.trim().is_empty() {
+                            ui.colored_label(Color32::RED, "Not a link to a note");
+                        }
+                        if ui.small_button("✖").on_hover_text("Cancel").clicked() {
+                            done = true;
+                        }
                     });
+                    if done {
+                        state.article_input = None;
+                    }
+                }
+                if !state.articles.is_empty() {
+                    super::articles::fetch(&mut app.articles, &mut app.relays, &state.articles);
+                    super::articles::article_chips(ui, &app.articles, &mut state.articles);
+                }
+                if let Some(draft) = state.payload.as_mut() {
+                    if payload_editor(ui, id, draft) {
+                        state.payload = None;
+                    }
+                }
 
-                    // Message content
-                    let available_height = ui.available_height() - 40.0; // Reserve space for bottom bar
-                    egui::ScrollArea::vertical()
-                        .max_height(available_height)
-                        .show(ui, |ui| {
-                            ui.add_sized(
-                                [ui.available_width(), available_height - 20.0],
-                                egui::TextEdit::multiline(&mut state.content),
-                            );
-                        });
+                if let Some(summary) = &summary {
+                    summary.show(ui);
+                }
+                if let Some(error) = &state.send_error {
+                    ui.colored_label(Color32::RED, format!("⚠ {}", error));
+                }
 
-                    // Bottom bar with account selector and send button
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                // Bottom bar with the send button.
+                // Laid out left to right so keyboard focus moves draft -> send.
+                ui.horizontal(|ui| {
+                    if state.sending.is_some() {
+                        sending_details(ui, &progress);
+                    }
+
+                    let mut send = false;
+                    // The last check before sending, when the user wants one.
+                    let confirm_recipients = app.preferences.confirm_recipients;
+                    if state.confirming_empty_subject {
+                        ui.label(RichText::new("No subject.").color(style::TEXT_MUTED));
+                        if ui.button("Send anyway").clicked() {
+                            state.confirming_empty_subject = false;
+                            if !unreachable.is_empty() {
+                                state.confirming_no_relays = true;
+                            } else if confirm_recipients {
+                                state.confirming_recipients = true;
+                            } else {
+                                send = true;
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            state.confirming_empty_subject = false;
+                        }
+                    } else if state.confirming_no_relays {
+                        let count = unreachable.len();
+                        ui.label(
+                            RichText::new(format!(
+                                "No relays known for {} recipient{}.",
+                                count,
+                                if count == 1 { "" } else { "s" }
+                            ))
+                            .color(style::TEXT_MUTED),
+                        );
+                        if ui
+                            .button("Send to my relays anyway")
+                            .on_hover_text(
+                                "They'll only get it if they read from one of your relays",
+                            )
+                            .clicked()
+                        {
+                            state.confirming_no_relays = false;
+                            if confirm_recipients {
+                                state.confirming_recipients = true;
+                            } else {
+                                send = true;
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            state.confirming_no_relays = false;
+                        }
+                    } else if state.confirming_upload {
+                        ui.label(
+                            RichText::new(format!(
+                                "Attachments go unencrypted to {}, anyone with the link can \
+                                 open them.",
+                                app.uploads.server
+                            ))
+                            .color(style::TEXT_MUTED),
+                        );
+                        if ui.button("Upload and send").clicked() {
+                            state.confirming_upload = false;
+                            upload_now = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            state.confirming_upload = false;
+                        }
+                    } else if state.confirming_recipients {
+                        ui.label(RichText::new("Is this right?").color(style::TEXT_MUTED));
                         if ui
                             .add(
                                 egui::Button::new(RichText::new("Send").color(Color32::WHITE))
@@ -130,59 +667,16 @@ impl ComposeWindow {
                             )
                             .clicked()
                         {
-                            if state.selected_account.is_none() {
-                                error!("No Account Selected!");
-                                return;
-                            }
-                            // convert to field into PublicKey object
-                            let to_field = state.to_field.clone();
-
-                            let mut recipient_keys: Vec<PublicKey> = Vec::new();
-                            for key_string in to_field.split_whitespace() {
-                                use nostr::FromBech32;
-                                match PublicKey::from_bech32(key_string) {
-                                    Ok(k) => recipient_keys.push(k),
-                                    Err(e) => debug!("could not parse public key as bech32: {}", e),
-                                };
-
-                                match PublicKey::from_hex(key_string) {
-                                    Ok(k) => recipient_keys.push(k),
-                                    Err(e) => debug!("could not parse public key as hex: {}", e),
-                                };
-                            }
-
-                            let mut msg = MailMessage {
-                                id: None,
-                                created_at: None,
-                                author: None,
-                                to: recipient_keys,
-                                cc: vec![],
-                                bcc: vec![],
-                                parent_events: Some(state.parent_events.clone()),
-                                subject: state.subject.clone(),
-                                content: state.content.clone(),
-                            };
-                            let events_to_send =
-                                msg.to_events(&state.selected_account.clone().unwrap());
-
-                            // send over wire
-                            for event in events_to_send {
-                                match serde_json::to_string(&ClientMessage::Event {
-                                    event: event.1,
-                                }) {
-                                    Ok(v) => match app.relays.send(ewebsock::WsMessage::Text(v)) {
-                                        Ok(r) => r,
-                                        Err(e) => error!("could not send event to relays: {}", e),
-                                    },
-                                    Err(e) => error!("could not serialize event: {}", e),
-                                };
-                            }
-
-                            // Delete the draft after sending
-                            if let Some(draft_id) = state.draft_id {
-                                draft_action = DraftAction::Delete(draft_id);
-                            }
+                            state.confirming_recipients = false;
+                            send = true;
                         }
+                        if ui.button("Back").clicked() {
+                            state.confirming_recipients = false;
+                        }
+                    } else {
+                        // Right-align the actions while keeping them last in the focus order.
+                        let actions_width = 150.0;
+                        ui.add_space((ui.available_width() - actions_width).max(0.0));
 
                         // Save Draft button
                         if ui
@@ -206,37 +700,156 @@ impl ComposeWindow {
                             };
                         }
 
-                        // Account selector
-                        let selected_text = state
-                            .selected_account
-                            .as_ref()
-                            .and_then(|k| {
-                                let pk = k.public_key().to_hex();
-                                account_options
-                                    .iter()
-                                    .find(|(key, _)| key.public_key().to_hex() == pk)
-                                    .map(|(_, name)| name.clone())
-                            })
-                            .unwrap_or_default();
-
-                        ui.horizontal(|ui| {
-                            egui::ComboBox::from_id_source("account_selector")
-                                .selected_text(selected_text)
-                                .show_ui(ui, |ui| {
-                                    for (key, name) in &account_options {
-                                        ui.selectable_value(
-                                            &mut state.selected_account,
-                                            Some(key.clone()),
-                                            name,
-                                        );
-                                    }
-                                });
-                            ui.label("Send as:");
+                        let has_recipients =
+                            !recipients.pubkeys.is_empty() || !recipients.emails.is_empty();
+                        let sending = state.sending.is_some();
+                        let send_label = if sending { "Sending…" } else { "Send" };
+                        let upload_status = |wanted: fn(&UploadStatus) -> bool| {
+                            state
+                                .attachments
+                                .iter()
+                                .filter_map(|id| app.uploads.get(*id))
+                                .any(|upload| wanted(&upload.status))
+                        };
+                        let uploading = upload_status(|status| *status == UploadStatus::InProgress);
+                        let upload_failed =
+                            upload_status(|status| matches!(status, UploadStatus::Failed(_)));
+                        let upload_unpaid = upload_status(|status| {
+                            matches!(status, UploadStatus::PaymentRequired { .. })
                         });
-                    });
+                        if ui
+                            .add_enabled(
+                                has_recipients
+                                    && !sending
+                                    && !uploading
+                                    && !upload_failed
+                                    && !upload_unpaid,
+                                egui::Button::new(RichText::new(send_label).color(Color32::WHITE))
+                                    .fill(style::ACCENT)
+                                    .rounding(6.0),
+                            )
+                            .on_disabled_hover_text(if sending {
+                                "Waiting for the relays to confirm"
+                            } else if uploading {
+                                "Waiting for the attachments to upload"
+                            } else if upload_failed {
+                                "Remove the attachments that failed to upload"
+                            } else if upload_unpaid {
+                                "Pay for or remove the attachments the server wants paying for"
+                            } else {
+                                "Add at least one valid recipient"
+                            })
+                            .clicked()
+                        {
+                            if app.preferences.confirm_empty_subject
+                                && state.subject.trim().is_empty()
+                            {
+                                state.confirming_empty_subject = true;
+                            } else if !unreachable.is_empty() {
+                                state.confirming_no_relays = true;
+                            } else if confirm_recipients {
+                                state.confirming_recipients = true;
+                            } else {
+                                send = true;
+                            }
+                        }
+                    }
+
+                    if send {
+                        // Nothing leaves for the upload server until the user says so.
+                        let staged = state
+                            .attachments
+                            .iter()
+                            .filter_map(|id| app.uploads.get(*id))
+                            .any(|upload| upload.status == UploadStatus::Staged);
+                        if staged {
+                            state.confirming_upload = true;
+                        } else {
+                            send_now = true;
+                        }
+                    }
                 });
             });
 
+            // A window of its own gets files dropped anywhere on it.
+            if detached {
+                accept_dropped_files(
+                    ui.ctx(),
+                    id,
+                    ui.max_rect(),
+                    &mut app.uploads,
+                    &mut state.attachments,
+                );
+            }
+        };
+
+        let window_rect = if detached {
+            open = detached::show(ctx, egui::ViewportId::from_hash_of(id), &title, contents);
+            None
+        } else {
+            egui::Window::new("New Message")
+                .id(id)
+                .open(&mut open)
+                .default_size([min_width, min_height])
+                .min_width(300.0)
+                .min_height(200.0)
+                .default_pos([
+                    screen_rect.right() - min_width - 20.0,
+                    screen_rect.bottom() - min_height - 20.0,
+                ])
+                .show(ctx, contents)
+                .map(|window| window.response.rect)
+        };
+
+        if let Some(rect) = window_rect {
+            accept_dropped_files(ctx, id, rect, &mut app.uploads, &mut state.attachments);
+        }
+
+        if upload_now {
+            if let (Some(state), Some(keys)) = (app.state.compose_window.get_mut(&id), &account) {
+                for upload_id in &state.attachments {
+                    app.uploads.upload_staged(keys, *upload_id);
+                }
+                state.send_after_upload = true;
+            }
+        }
+        if send_now {
+            if let Some(mut state) = app.state.compose_window.remove(&id) {
+                match &account {
+                    Some(keys) => send(app, &mut state, keys),
+                    None => error!("No Account Selected!"),
+                }
+                app.state.compose_window.insert(id, state);
+            }
+        }
+        if open_editor {
+            let content = app.state.compose_window[&id].content.clone();
+            let configured = app.preferences.external_editor.clone();
+            if let Err(e) = app.external_editors.open(id, &content, &configured) {
+                error!("Failed to open an external editor: {}", e);
+                app.toasts.error(format!("{:#}", e));
+            }
+        }
+        if open_picker {
+            let to_field = app.state.compose_window[&id].to_field.clone();
+            let picker = ContactPicker::new(app, &to_field);
+            if let Some(state) = app.state.compose_window.get_mut(&id) {
+                state.contact_picker = Some(picker);
+            }
+        }
+        if let Some(state) = app.state.compose_window.get_mut(&id) {
+            if let Some(mut picker) = state.contact_picker.take() {
+                match picker.show(ctx, id.with("contact_picker")) {
+                    PickerOutcome::Open => state.contact_picker = Some(picker),
+                    PickerOutcome::Cancelled => {}
+                    PickerOutcome::Picked(pubkeys) => {
+                        state.to_field =
+                            contact_picker::write_recipients(&state.to_field, &pubkeys);
+                    }
+                }
+            }
+        }
+
         // Apply deferred draft actions (outside the borrow of state)
         match draft_action {
             DraftAction::Save {
@@ -278,15 +891,899 @@ impl ComposeWindow {
                 }
                 app.refresh_drafts();
             }
-            DraftAction::Delete(draft_id) => {
-                if let Err(e) = app.db.delete_draft(draft_id) {
-                    error!("Failed to delete draft after send: {}", e);
-                }
-                app.refresh_drafts();
-            }
             DraftAction::None => {}
         }
 
+        if !open {
+            app.external_editors.forget(id);
+            if let Some(state) = app.state.compose_window.get_mut(&id) {
+                for upload_id in std::mem::take(&mut state.attachments) {
+                    app.uploads.forget(upload_id);
+                }
+            }
+        }
         open
     }
 }
+
+/// Our accounts with their display names, and the color each is marked with, for picking
+/// which one a message goes out as.
+pub fn account_options(app: &mut crate::Hoot) -> (Vec<(Keys, String)>, Vec<Color32>) {
+    let account_options: Vec<(Keys, String)> = app
+        .account_manager
+        .loaded_keys
+        .iter()
+        .map(|k| (k.clone(), crate::get_key_display_text(app, k)))
+        .collect();
+    let account_colors: Vec<Color32> = account_options
+        .iter()
+        .map(|(keys, _)| {
+            app.account_colors.color(
+                &keys.public_key().to_hex(),
+                &app.account_manager.loaded_keys,
+            )
+        })
+        .collect();
+    for (keys, _) in &account_options {
+        let pubkey = keys.public_key().to_hex();
+        if let Some(ProfileOption::Some(meta)) = app.profile_metadata.get(&pubkey) {
+            if let Some(url) = meta.picture.as_deref().filter(|url| !url.is_empty()) {
+                app.contacts_manager.request_image(&pubkey, url);
+            }
+        }
+    }
+    (account_options, account_colors)
+}
+
+/// A dropdown of our accounts, each with its color, setting `selected` to the one picked.
+/// `account` is who the message goes out as now.
+pub fn account_picker(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    options: &[(Keys, String)],
+    colors: &[Color32],
+    account: Option<&Keys>,
+    selected: &mut Option<Keys>,
+) -> egui::Response {
+    let current = account.and_then(|keys| {
+        options
+            .iter()
+            .find(|(key, _)| key.public_key() == keys.public_key())
+    });
+    egui::ComboBox::from_id_source(id)
+        .selected_text(current.map(|(_, name)| name.as_str()).unwrap_or_default())
+        .width(ui.available_width().min(320.0))
+        .show_ui(ui, |ui| {
+            for ((key, name), color) in options.iter().zip(colors) {
+                let is_selected = account.is_some_and(|k| k.public_key() == key.public_key());
+                ui.horizontal(|ui| {
+                    style::account_dot(ui, *color);
+                    if ui.selectable_label(is_selected, name).clicked() {
+                        *selected = Some(key.clone());
+                    }
+                });
+            }
+        })
+        .response
+}
+
+/// Sends `state`'s message as `keys` to everyone in its To field, keeping a copy in Sent and
+/// noting the send so it's settled once the relays answer.
+pub fn send(app: &mut crate::Hoot, state: &mut ComposeWindowState, keys: &Keys) {
+    let payload = match &state.payload {
+        Some(draft) => match Payload::new(&draft.schema, &draft.json) {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                state.send_error = Some(format!("The data for apps isn't right: {:#}", e));
+                return;
+            }
+        },
+        None => None,
+    };
+    let recipients = bridge::parse_recipients(&state.to_field);
+    for entry in &recipients.invalid {
+        debug!("could not parse recipient {}", entry);
+    }
+
+    let recipient_hexes: Vec<String> = recipients.pubkeys.iter().map(|pk| pk.to_hex()).collect();
+    let mut recipient_keys = recipients.pubkeys;
+    if !recipients.emails.is_empty() {
+        // Email goes to the gateway, which reads the real addresses
+        // from the email-to tags.
+        match app.bridge.gateway() {
+            Some(gateway) => {
+                if !recipient_keys.contains(&gateway) {
+                    recipient_keys.push(gateway);
+                }
+            }
+            None => {
+                error!(
+                    "Can't send to {}: no email bridge is set up",
+                    recipients.emails.join(", ")
+                );
+                return;
+            }
+        }
+    }
+    if recipient_keys.is_empty() {
+        error!("Not sending: no valid recipients in {:?}", state.to_field);
+        return;
+    }
+
+    let tag_relays = tag_relays(
+        &app.db,
+        &app.relay_hints,
+        &state.parent_events,
+        &recipient_keys,
+    );
+    let mut msg = MailMessage {
+        id: None,
+        created_at: None,
+        author: None,
+        to: recipient_keys,
+        cc: vec![],
+        bcc: vec![],
+        parent_events: Some(state.parent_events.clone()),
+        subject: state.subject.clone(),
+        content: state.content.clone(),
+        attachments: state
+            .attachments
+            .iter()
+            .filter_map(|id| match &app.uploads.get(*id)?.status {
+                UploadStatus::Complete(attachment) => Some(attachment.clone()),
+                _ => None,
+            })
+            .collect(),
+        articles: state.articles.clone(),
+        quote: state.quote.clone(),
+        payload,
+        email_to: recipients.emails,
+        email_from: None,
+        tag_relays,
+    };
+    let mut events_to_send = msg.to_events(keys, app.preferences.wrap_fuzz());
+    if let (Some(gateway), false) = (app.bridge.gateway(), msg.email_to.is_empty()) {
+        match msg.wrap_for_gateway(keys, &gateway, app.preferences.wrap_fuzz()) {
+            Some(wrap) => {
+                events_to_send.insert(gateway, wrap);
+            }
+            None => {
+                events_to_send.remove(&gateway);
+            }
+        }
+    }
+    let account_hex = keys.public_key().to_hex();
+    if app.preferences.copies_to_self(&account_hex)
+        && !events_to_send.contains_key(&keys.public_key())
+    {
+        if let Some(copy) = msg.copy_to_self(keys, app.preferences.wrap_fuzz()) {
+            events_to_send.insert(keys.public_key(), copy);
+        }
+    }
+    if let Err(e) = app
+        .db
+        .remember_sending_account(&account_hex, &recipient_hexes)
+    {
+        error!("Failed to remember the account we sent as: {}", e);
+    }
+    for recipient in recipient_hexes {
+        app.sending_accounts.insert(recipient, account_hex.clone());
+    }
+    let wraps: Vec<(String, String)> = events_to_send
+        .iter()
+        .map(|(recipient, wrap)| (wrap.id.to_hex(), recipient.to_hex()))
+        .collect();
+    let message_id = msg.id.map(|id| id.to_hex());
+    let rumor = msg.to_rumor();
+    if let Some(rumor) = &rumor {
+        if let Err(e) = app.db.record_sent(rumor, &wraps) {
+            error!("Failed to keep a copy of the sent message: {}", e);
+        }
+        app.refresh_sent();
+    }
+
+    // send over wire
+    state.send_error = None;
+    let mut event_ids = Vec::new();
+    let mut recipients = HashMap::new();
+    for (recipient, event) in events_to_send {
+        let event_id = event.id.to_hex();
+        match app.relays.publish(&event) {
+            Ok(report) => {
+                event_ids.push(event_id.clone());
+                recipients.insert(event_id, recipient);
+                state.delivery = Some(report);
+            }
+            Err(e) => {
+                if let Err(e) = app.db.record_sent_delivery(&event_id, 0, 0) {
+                    error!("Failed to record delivery of {}: {}", event_id, e);
+                }
+                error!("could not send event to relays: {}", e);
+                state.send_error = Some(format!("Couldn't reach any relay: {}", e));
+            }
+        }
+    }
+
+    if !event_ids.is_empty() {
+        state.sending = Some(PendingSend {
+            message_id,
+            event_ids,
+            started: Instant::now(),
+            rewrap: rumor.map(|rumor| (keys.clone(), rumor)),
+            recipients,
+        });
+    }
+}
+
+/// Wraps the message again with the proof of work relays asked for (NIP-13), for each relay
+/// that refused it for want of some, and sends the copies that are ready.
+fn add_proof_of_work(app: &mut crate::Hoot, pending: &mut PendingSend) {
+    if let Some((keys, rumor)) = &pending.rewrap {
+        for (event_id, recipient) in &pending.recipients {
+            for (url, difficulty) in app.relays.take_pow_requests(event_id) {
+                let fuzz = app.preferences.wrap_fuzz();
+                app.proof_of_work.mine(
+                    keys,
+                    *recipient,
+                    rumor.clone(),
+                    fuzz,
+                    difficulty,
+                    event_id,
+                    url,
+                );
+            }
+        }
+    }
+    let mut worked = false;
+    for (event_id, url, wrap) in app.proof_of_work.take_finished(&pending.event_ids) {
+        app.relays.publish_copy(&event_id, &url, &wrap);
+        worked = true;
+    }
+    // The work took a while, so the relays get their full time to answer after it.
+    if worked {
+        pending.started = Instant::now();
+    }
+}
+
+/// Checks on the send `state` is waiting for, recording how it went once every relay has
+/// answered or had long enough to. Returns whether the message got through.
+pub fn settle_send(
+    app: &mut crate::Hoot,
+    ctx: &egui::Context,
+    state: &mut ComposeWindowState,
+) -> bool {
+    let Some(pending) = &mut state.sending else {
+        return false;
+    };
+    add_proof_of_work(app, pending);
+    let progress = match &state.delivery {
+        Some(report) => send_progress(&app.relays, report, pending),
+        None => Vec::new(),
+    };
+    let waiting = progress
+        .iter()
+        .any(|(_, _, progress)| *progress == RelayProgress::Waiting);
+    // Relays waiting on a copy with more work don't time out until it's sent.
+    let mining = app.proof_of_work.is_mining(&pending.event_ids);
+    if waiting && (mining || pending.started.elapsed() <= SEND_TIMEOUT) {
+        ctx.request_repaint_after(Duration::from_secs(1));
+        return false;
+    }
+    for event_id in &pending.event_ids {
+        let acks: Vec<&Ack> = app
+            .relays
+            .acks(event_id)
+            .map(|acks| acks.values().collect())
+            .unwrap_or_default();
+        let accepted = acks.iter().filter(|ack| ***ack == Ack::Accepted).count();
+        if let Err(e) = app
+            .db
+            .record_sent_delivery(event_id, accepted, acks.len() - accepted)
+        {
+            error!("Failed to record delivery of {}: {}", event_id, e);
+        }
+        // An OK isn't a promise to keep it, so ask the relays for it back.
+        let took: Vec<String> = app
+            .relays
+            .acks(event_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, ack)| **ack == Ack::Accepted)
+            .map(|(url, _)| url.clone())
+            .collect();
+        if !took.is_empty() {
+            app.relays.confirm_stored(event_id.clone(), &took, CONFIRM_DELAY);
+        }
+        app.relays.forget_acks(event_id);
+    }
+    let delivered = progress
+        .iter()
+        .any(|(_, _, progress)| *progress == RelayProgress::Accepted);
+    if !delivered {
+        state.send_error = Some(send_failure(&progress));
+        if let Some(message_id) = &pending.message_id {
+            app.notifications
+                .delivery_failed(&app.db, message_id, &state.subject);
+        }
+    } else if let Some(refused) = refused_as_invalid(&progress) {
+        let subject = if state.subject.trim().is_empty() {
+            "(No Subject)"
+        } else {
+            &state.subject
+        };
+        warn!("Sent with relays refusing it: {}", refused);
+        app.toasts.error(format!("“{}” was sent, but {}", subject, refused));
+    }
+    state.sending = None;
+    // The Sent folder shows delivery status, so it's reloaded whenever a send settles.
+    app.refresh_sent();
+    delivered
+}
+
+/// Cleans up after a message that got through: its uploads, its draft, and the thread it
+/// answered when replies archive.
+pub fn finish_send(app: &mut crate::Hoot, mut state: ComposeWindowState) {
+    for upload_id in std::mem::take(&mut state.attachments) {
+        app.uploads.forget(upload_id);
+    }
+    if let Some(draft_id) = state.draft_id {
+        if let Err(e) = app.db.delete_draft(draft_id) {
+            error!("Failed to delete draft after send: {}", e);
+        }
+        app.refresh_drafts();
+    }
+    if app.preferences.archive_on_reply {
+        if let Some(root) = state.parent_events.first() {
+            app.set_thread_archived(&root.to_hex(), true);
+        }
+    }
+}
+
+/// The account to send as until one is picked: the one we last wrote to a recipient as, else
+/// the active account, else the first one.
+pub fn default_account(app: &crate::Hoot, to_field: &str) -> Option<Keys> {
+    let loaded = &app.account_manager.loaded_keys;
+    bridge::parse_recipients(to_field)
+        .pubkeys
+        .iter()
+        .find_map(|pubkey| {
+            let account = app.sending_accounts.get(&pubkey.to_hex())?;
+            loaded
+                .iter()
+                .find(|keys| keys.public_key().to_hex() == *account)
+                .cloned()
+        })
+        .or_else(|| app.active_account.clone())
+        .or_else(|| loaded.first().cloned())
+}
+
+/// The first relay each parent came in on and the best relay each recipient reads from, for
+/// the hints in the message's tags.
+fn tag_relays(
+    db: &Db,
+    hints: &RelayHints,
+    parents: &[EventId],
+    recipients: &[PublicKey],
+) -> TagRelays {
+    let mut relays = TagRelays::default();
+    for parent in parents {
+        let parent_hex = parent.to_hex();
+        // Mail comes in gift wraps, so the relays that delivered those count for the parent.
+        let mut ids = db.get_wrap_ids_for_inner(&parent_hex).unwrap_or_else(|e| {
+            error!("Failed to look up gift wraps of {}: {}", parent_hex, e);
+            Vec::new()
+        });
+        ids.push(parent_hex);
+        match db.get_event_sources(&ids) {
+            Ok(sources) => {
+                if let Some((url, _)) = sources.into_iter().next() {
+                    relays.events.insert(*parent, url);
+                }
+            }
+            Err(e) => error!("Failed to load relays for {}: {}", parent, e),
+        }
+    }
+    for pubkey in recipients {
+        if let Some(url) = hints.relays(&pubkey.to_hex()).into_iter().next() {
+            relays.pubkeys.insert(*pubkey, url);
+        }
+    }
+    relays
+}
+
+/// The schema picker and JSON field for data for apps. True when the data is taken off.
+fn payload_editor(ui: &mut egui::Ui, id: egui::Id, draft: &mut PayloadDraft) -> bool {
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        let label = ui.label(RichText::new("Data for apps").color(style::TEXT_MUTED));
+        let known = [(payload::INVOICE, "Invoice"), (payload::RECEIPT, "Receipt")];
+        let selected = known
+            .iter()
+            .find(|(schema, _)| *schema == draft.schema)
+            .map_or("Other", |(_, name)| name);
+        let mut schema = None;
+        egui::ComboBox::from_id_source(id.with("payload_schema"))
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (known_schema, name) in known {
+                    if ui
+                        .selectable_label(draft.schema == known_schema, name)
+                        .clicked()
+                    {
+                        schema = Some(known_schema.to_string());
+                    }
+                }
+                if ui.selectable_label(selected == "Other", "Other").clicked() {
+                    schema = Some(String::new());
+                }
+            })
+            .response
+            .labelled_by(label.id);
+        if let Some(schema) = schema.filter(|schema| *schema != draft.schema) {
+            // Start from the new schema's example unless something was written.
+            if draft.json.trim() == payload::example(&draft.schema) || draft.json.trim().is_empty()
+            {
+                draft.json = payload::example(&schema);
+            }
+            draft.schema = schema;
+        }
+        if selected == "Other" {
+            ui.add(
+                egui::TextEdit::singleline(&mut draft.schema)
+                    .hint_text("Schema, like order")
+                    .desired_width(140.0),
+            );
+        }
+        if ui
+            .small_button("✖")
+            .on_hover_text("Take the data off")
+            .clicked()
+        {
+            remove = true;
+        }
+    });
+    egui::ScrollArea::vertical()
+        .id_source(id.with("payload_json"))
+        .max_height(120.0)
+        .show(ui, |ui| {
+            let field = ui.add(
+                egui::TextEdit::multiline(&mut draft.json)
+                    .code_editor()
+                    .desired_rows(6)
+                    .desired_width(f32::INFINITY),
+            );
+            field.widget_info(|| {
+                egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Data for apps, as JSON")
+            });
+        });
+    match Payload::new(&draft.schema, &draft.json) {
+        Ok(payload) if payload.bill().is_some() => {
+            ui.small("Hoot shows it as a card under the message. Other apps can read it too.");
+        }
+        Ok(_) => {
+            ui.small("Apps that know this schema can read it. Hoot shows it as data.");
+        }
+        Err(e) => {
+            ui.colored_label(Color32::RED, format!("{:#}", e));
+        }
+    }
+    remove
+}
+
+/// Attaches the image on the clipboard, if there is one.
+fn paste_image(uploads: &mut uploads::UploadManager, attachments: &mut Vec<UploadId>) {
+    match uploads::clipboard_image() {
+        Some(png) => {
+            let name = format!(
+                "Pasted image {}.png",
+                chrono::Local::now().format("%Y-%m-%d %H.%M.%S")
+            );
+            attachments.push(uploads.stage(name, Source::Bytes(png)));
+        }
+        None => debug!("No image on the clipboard to paste"),
+    }
+}
+
+/// Attaches files dropped onto the window at `rect`, and highlights it while files hover over
+/// it. The files go to the first compose window under the pointer.
+fn accept_dropped_files(
+    ctx: &egui::Context,
+    id: egui::Id,
+    rect: egui::Rect,
+    uploads: &mut uploads::UploadManager,
+    attachments: &mut Vec<UploadId>,
+) {
+    let (hovering, dropped) = ctx.input(|i| {
+        // Some platforms don't report the pointer while dragging files in.
+        let over = i.pointer.hover_pos().is_none_or(|pos| rect.contains(pos));
+        if !over {
+            return (false, Vec::new());
+        }
+        (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone())
+    });
+
+    if hovering {
+        let painter =
+            ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, id.with("drop")));
+        painter.rect_filled(rect, 6.0, style::ACCENT.gamma_multiply(0.3));
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop to attach",
+            egui::FontId::proportional(18.0),
+            Color32::WHITE,
+        );
+    }
+
+    if dropped.is_empty() {
+        return;
+    }
+    ctx.input_mut(|i| i.raw.dropped_files.clear());
+    for file in dropped {
+        let (name, source) = match (file.path, file.bytes) {
+            (Some(path), _) => {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| file.name.clone());
+                (name, Source::File(path))
+            }
+            (None, Some(bytes)) => (file.name, Source::Bytes(bytes.to_vec())),
+            (None, None) => continue,
+        };
+        attachments.push(uploads.stage(name, source));
+    }
+}
+
+/// What was clicked on an attachment in the compose footer.
+enum ChipAction {
+    Remove,
+    /// Ask whether to pay the invoice the upload server sent.
+    Pay,
+}
+
+/// Asks before paying an upload server's `invoice` for the file `name`, saying what it costs
+/// and who it pays. Invoices without an amount or over [`uploads::MAX_PAYMENT_SATS`] are
+/// refused. Returns whether to pay once the user has decided.
+fn confirm_upload_payment(ui: &mut egui::Ui, name: &str, invoice: &str) -> Option<bool> {
+    let checked = bolt11::Invoice::decode(invoice)
+        .map_err(|e| format!("Can't read the server's invoice: {}", e))
+        .and_then(|decoded| match decoded.msats {
+            None => Err("The server's invoice doesn't say how much it asks for.".to_string()),
+            Some(msats) if msats > uploads::MAX_PAYMENT_SATS * 1000 => Err(format!(
+                "The server asks {} sats, more than the {} Hoot pays for an upload.",
+                msats as f64 / 1000.0,
+                uploads::MAX_PAYMENT_SATS
+            )),
+            Some(msats) => Ok((decoded, msats)),
+        });
+
+    let mut decision = None;
+    ui.horizontal_wrapped(|ui| {
+        match &checked {
+            Ok((decoded, msats)) => {
+                ui.label(
+                    RichText::new(format!(
+                        "Pay {} sats to node {} from your wallet to upload {}?",
+                        *msats as f64 / 1000.0,
+                        super::payload_card::short_key(&decoded.payee),
+                        name
+                    ))
+                    .color(style::TEXT_MUTED),
+                )
+                .on_hover_text(&decoded.payee);
+                if ui.button("⚡ Pay").clicked() {
+                    decision = Some(true);
+                }
+            }
+            Err(e) => {
+                ui.colored_label(Color32::RED, format!("⚠ {}", e));
+            }
+        }
+        if ui.button("Cancel").clicked() {
+            decision = Some(false);
+        }
+    });
+    decision
+}
+
+/// One attachment in the compose footer.
+fn attachment_chip(
+    ui: &mut egui::Ui,
+    id: UploadId,
+    upload: &Upload,
+    payments: &Payments,
+) -> Option<ChipAction> {
+    let mut action = None;
+    egui::Frame::group(ui.style())
+        .rounding(12.0)
+        .inner_margin(egui::Margin::symmetric(6.0, 2.0))
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                match &upload.thumbnail {
+                    Some(texture) => {
+                        ui.add(egui::Image::new(texture).max_size(egui::vec2(32.0, 32.0)));
+                    }
+                    None => {
+                        ui.label("📎");
+                    }
+                }
+                ui.label(&upload.name);
+                match &upload.status {
+                    UploadStatus::Staged => {}
+                    UploadStatus::InProgress => {
+                        ui.add(egui::Spinner::new().size(12.0))
+                            .on_hover_text("Uploading");
+                    }
+                    UploadStatus::Complete(attachment) => {
+                        if let Some(size) = attachment.size {
+                            ui.label(
+                                RichText::new(super::attachments::format_size(size))
+                                    .small()
+                                    .color(style::TEXT_MUTED),
+                            );
+                        }
+                    }
+                    UploadStatus::Failed(e) => {
+                        ui.colored_label(Color32::RED, "⚠")
+                            .on_hover_text(format!("Upload failed: {}", e));
+                    }
+                    UploadStatus::PaymentRequired { .. } => {
+                        if payments.paying_upload(id) {
+                            ui.add(egui::Spinner::new().size(12.0))
+                                .on_hover_text("Paying from your wallet");
+                        } else if ui
+                            .add_enabled(
+                                payments.connection().is_some(),
+                                egui::Button::new("⚡ Pay").small(),
+                            )
+                            .on_hover_text("The upload server wants paying to take this file")
+                            .on_disabled_hover_text(
+                                "The upload server wants paying. Connect a wallet in Settings \
+                                 to pay it.",
+                            )
+                            .clicked()
+                        {
+                            action = Some(ChipAction::Pay);
+                        }
+                    }
+                }
+                if ui
+                    .small_button("✖")
+                    .on_hover_text("Remove attachment")
+                    .clicked()
+                {
+                    action = Some(ChipAction::Remove);
+                }
+            });
+        });
+    action
+}
+
+/// Who a message is about to go out as and to, and what it carries, for a last look before
+/// the gift wraps leave.
+struct SendSummary {
+    /// The name, npub and color of the account sending.
+    sender: Option<(String, String, Color32)>,
+    /// The name we know each recipient by, if any, and their npub.
+    recipients: Vec<(Option<String>, String)>,
+    emails: Vec<String>,
+    /// File names of the attachments.
+    attachments: Vec<String>,
+    articles: usize,
+}
+
+impl SendSummary {
+    const LINE_HEIGHT: f32 = 18.0;
+
+    /// The room [`SendSummary::show`] takes.
+    fn height(&self) -> f32 {
+        let lines = 1
+            + self.recipients.len()
+            + self.emails.len()
+            + usize::from(!self.attachments.is_empty())
+            + usize::from(self.articles > 0);
+        lines as f32 * Self::LINE_HEIGHT + 20.0
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        let key = |ui: &mut egui::Ui, npub: &str| {
+            ui.label(
+                RichText::new(npub)
+                    .small()
+                    .monospace()
+                    .color(style::TEXT_MUTED),
+            );
+        };
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.label("From");
+                match &self.sender {
+                    Some((name, npub, color)) => {
+                        style::account_dot(ui, *color);
+                        ui.label(RichText::new(name).strong());
+                        key(ui, npub);
+                    }
+                    None => {
+                        ui.colored_label(Color32::RED, "no account");
+                    }
+                }
+            });
+            for (name, npub) in &self.recipients {
+                ui.horizontal(|ui| {
+                    ui.label("To");
+                    match name {
+                        Some(name) => {
+                            ui.label(RichText::new(name).strong());
+                        }
+                        None => {
+                            ui.label(RichText::new("someone you haven't named").italics());
+                        }
+                    }
+                    key(ui, npub);
+                });
+            }
+            for email in &self.emails {
+                ui.horizontal(|ui| {
+                    ui.label("To");
+                    ui.label(RichText::new(email).strong());
+                    ui.label(RichText::new("by email, through the bridge").small());
+                });
+            }
+            if !self.attachments.is_empty() {
+                ui.label(format!(
+                    "With {}: {}",
+                    plural(self.attachments.len(), "attachment"),
+                    self.attachments.join(", ")
+                ));
+            }
+            if self.articles > 0 {
+                ui.label(format!(
+                    "Sharing {}",
+                    plural(self.articles, "long-form note")
+                ));
+            }
+        });
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn npub(pubkey: &PublicKey) -> String {
+    pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_hex())
+}
+
+/// Flags recipients that won't parse and keys we've never talked to, under the To field.
+fn recipient_notes(
+    ui: &mut egui::Ui,
+    recipients: &bridge::Recipients,
+    is_known: impl Fn(&PublicKey) -> bool,
+) {
+    for entry in &recipients.invalid {
+        ui.colored_label(
+            Color32::RED,
+            format!("⚠ {}: {}", entry, bridge::invalid_recipient_reason(entry)),
+        );
+    }
+    for pubkey in recipients.pubkeys.iter().filter(|pubkey| !is_known(pubkey)) {
+        let npub = npub(pubkey);
+        ui.label(
+            RichText::new(format!("{} isn't in your contacts", npub))
+                .small()
+                .color(style::TEXT_MUTED),
+        );
+    }
+}
+
+/// Where each relay stands on the pending send, in the order they were sent to.
+fn send_progress(
+    relays: &RelayPool,
+    report: &SendReport,
+    pending: &PendingSend,
+) -> Vec<(String, Option<Duration>, RelayProgress)> {
+    report
+        .order
+        .iter()
+        .map(|(url, rtt)| {
+            let progress = if report.failed.contains(url) {
+                RelayProgress::Rejected("couldn't send".to_string())
+            } else {
+                let acks: Vec<Option<&Ack>> = pending
+                    .event_ids
+                    .iter()
+                    .map(|id| relays.acks(id).and_then(|acks| acks.get(url)))
+                    .collect();
+                if let Some(Ack::Rejected(reason)) = acks
+                    .iter()
+                    .flatten()
+                    .find(|ack| matches!(ack, Ack::Rejected(_)))
+                {
+                    RelayProgress::Rejected(reason.clone())
+                } else if acks.iter().all(Option::is_some) {
+                    RelayProgress::Accepted
+                } else {
+                    RelayProgress::Waiting
+                }
+            };
+            (url.clone(), *rtt, progress)
+        })
+        .collect()
+}
+
+/// The error banner for a send no relay accepted.
+fn send_failure(progress: &[(String, Option<Duration>, RelayProgress)]) -> String {
+    if progress.is_empty() {
+        return "Not sent: no relays are connected".to_string();
+    }
+    let reasons: Vec<String> = progress
+        .iter()
+        .map(|(url, _, progress)| match progress {
+            RelayProgress::Rejected(reason) if reason.is_empty() => format!("{} rejected it", url),
+            RelayProgress::Rejected(reason) => format!("{} rejected it ({})", url, reason),
+            _ => format!("{} didn't answer", url),
+        })
+        .collect();
+    warn!("Send failed: {}", reasons.join(", "));
+    format!("Not sent: {}", reasons.join(", "))
+}
+
+/// The relays that refused a send as invalid, which sending it again won't change, for the
+/// send result when other relays took it.
+fn refused_as_invalid(progress: &[(String, Option<Duration>, RelayProgress)]) -> Option<String> {
+    let refused: Vec<String> = progress
+        .iter()
+        .filter_map(|(url, _, progress)| match progress {
+            RelayProgress::Rejected(reason) if Rejection::parse(reason) == Rejection::Invalid => {
+                Some(format!("{} refused it as invalid ({})", url, reason))
+            }
+            _ => None,
+        })
+        .collect();
+    (!refused.is_empty()).then(|| refused.join(", "))
+}
+
+fn sending_details(ui: &mut egui::Ui, progress: &[(String, Option<Duration>, RelayProgress)]) {
+    let confirmed = progress
+        .iter()
+        .filter(|(_, _, progress)| *progress == RelayProgress::Accepted)
+        .count();
+    ui.spinner();
+    ui.label(
+        RichText::new(format!(
+            "{} of {} relays confirmed",
+            confirmed,
+            progress.len()
+        ))
+        .small()
+        .color(style::TEXT_MUTED),
+    )
+    .on_hover_ui(|ui| {
+        ui.label(RichText::new("Delivery details").strong());
+        ui.small("Sent to the fastest relays first.");
+        for (i, (url, rtt, progress)) in progress.iter().enumerate() {
+            let latency = match rtt {
+                Some(rtt) => format!("{} ms", rtt.as_millis()),
+                None => "latency unknown".to_string(),
+            };
+            let status = match progress {
+                RelayProgress::Waiting => "…".to_string(),
+                RelayProgress::Accepted => "✔".to_string(),
+                RelayProgress::Rejected(reason) => format!("✖ {}", reason),
+            };
+            ui.label(format!("{}. {} {} ({})", i + 1, status, url, latency));
+        }
+    });
+}
+
+fn toolbar_button(ui: &mut egui::Ui, text: &str, label: &str) -> egui::Response {
+    let response = ui.button(text).on_hover_text(label);
+    response.widget_info(|| egui::WidgetInfo::labeled(egui::WidgetType::Button, label));
+    response
+}
@@ -0,0 +1,358 @@
+//! Picking recipients by name for people who don't keep npubs around: recent correspondents
+//! first, then contact groups that open up to their members, then every contact.
+
+use crate::bridge;
+use crate::name_resolver::short_npub;
+use crate::Hoot;
+use eframe::egui::{self, RichText};
+use nostr::{PublicKey, ToBech32};
+use std::collections::HashMap;
+use tracing::error;
+
+/// How many recent correspondents are listed above the groups.
+const RECENT_COUNT: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct ContactPicker {
+    pub search: String,
+    /// Hex keys of the people picked, in the order they were.
+    pub selected: Vec<String>,
+    recent: Vec<String>,
+    /// Each group's id, name and members.
+    groups: Vec<(i64, String, Vec<String>)>,
+    contacts: Vec<String>,
+    names: HashMap<String, String>,
+}
+
+pub enum PickerOutcome {
+    Open,
+    Cancelled,
+    Picked(Vec<String>),
+}
+
+impl ContactPicker {
+    /// A picker with whoever `to_field` already names ticked.
+    pub fn new(app: &Hoot, to_field: &str) -> Self {
+        let ours: Vec<String> = app
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|keys| keys.public_key().to_hex())
+            .collect();
+        let recent: Vec<String> = app
+            .db
+            .get_recent_correspondents(RECENT_COUNT + ours.len())
+            .unwrap_or_else(|e| {
+                error!("Failed to load recent correspondents: {}", e);
+                Vec::new()
+            })
+            .into_iter()
+            .filter(|pubkey| !ours.contains(pubkey))
+            .take(RECENT_COUNT)
+            .collect();
+        let groups = app
+            .db
+            .get_contact_groups()
+            .and_then(|groups| {
+                groups
+                    .into_iter()
+                    .map(|(id, name)| Ok((id, name, app.db.get_contact_group_members(id)?)))
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("Failed to load contact groups: {}", e);
+                Vec::new()
+            });
+
+        let mut names = HashMap::new();
+        let mut contacts = Vec::new();
+        for contact in app.contacts_manager.get_contacts() {
+            names.insert(contact.pubkey.clone(), contact.display_name());
+            contacts.push(contact.pubkey.clone());
+        }
+        contacts.sort_by_cached_key(|pubkey| names[pubkey].to_lowercase());
+        let others = groups.iter().flat_map(|(_, _, members)| members);
+        for pubkey in recent.iter().chain(others) {
+            if !names.contains_key(pubkey) {
+                let name = app
+                    .resolve_name(pubkey)
+                    .unwrap_or_else(|| short_npub(pubkey));
+                names.insert(pubkey.clone(), name);
+            }
+        }
+
+        Self {
+            search: String::new(),
+            selected: bridge::parse_recipients(to_field)
+                .pubkeys
+                .iter()
+                .map(PublicKey::to_hex)
+                .collect(),
+            recent,
+            groups,
+            contacts,
+            names,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, id: egui::Id) -> PickerOutcome {
+        let mut outcome = PickerOutcome::Open;
+        let mut open = true;
+        egui::Window::new("Choose recipients")
+            .id(id)
+            .open(&mut open)
+            .collapsible(false)
+            .default_size([320.0, 420.0])
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.search)
+                        .hint_text("Search contacts")
+                        .desired_width(f32::INFINITY),
+                );
+                ui.add_space(4.0);
+
+                egui::ScrollArea::vertical()
+                    .max_height(ui.available_height() - 36.0)
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| self.lists(ui, id));
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} selected", self.selected.len()));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Add").clicked() {
+                            outcome = PickerOutcome::Picked(self.selected.clone());
+                        }
+                        if ui.button("Cancel").clicked() {
+                            outcome = PickerOutcome::Cancelled;
+                        }
+                    });
+                });
+            });
+        if !open {
+            outcome = PickerOutcome::Cancelled;
+        }
+        outcome
+    }
+
+    fn lists(&mut self, ui: &mut egui::Ui, id: egui::Id) {
+        let recent: Vec<String> = self
+            .recent
+            .iter()
+            .filter(|pubkey| self.matches(pubkey))
+            .cloned()
+            .collect();
+        if !recent.is_empty() {
+            ui.label(RichText::new("Recent").strong());
+            for pubkey in &recent {
+                self.checkbox(ui, pubkey);
+            }
+            ui.add_space(6.0);
+        }
+
+        let query = self.search.trim().to_lowercase();
+        let groups: Vec<(i64, String, Vec<String>)> = self
+            .groups
+            .iter()
+            .filter(|(_, name, members)| {
+                name.to_lowercase().contains(&query)
+                    || members.iter().any(|pubkey| self.matches(pubkey))
+            })
+            .cloned()
+            .collect();
+        if !groups.is_empty() {
+            ui.label(RichText::new("Groups").strong());
+            for (group_id, name, members) in &groups {
+                let header_id = id.with(("group", group_id));
+                egui::collapsing_header::CollapsingState::load_with_default_open(
+                    ui.ctx(),
+                    header_id,
+                    false,
+                )
+                .show_header(ui, |ui| {
+                    let mut all = !members.is_empty()
+                        && members.iter().all(|pubkey| self.selected.contains(pubkey));
+                    let label = format!("{} ({})", name, members.len());
+                    if ui.checkbox(&mut all, label).changed() {
+                        for pubkey in members {
+                            self.set_selected(pubkey, all);
+                        }
+                    }
+                })
+                .body(|ui| {
+                    for pubkey in members {
+                        self.checkbox(ui, pubkey);
+                    }
+                });
+            }
+            ui.add_space(6.0);
+        }
+
+        ui.label(RichText::new("Contacts").strong());
+        let contacts: Vec<String> = self
+            .contacts
+            .iter()
+            .filter(|pubkey| self.matches(pubkey))
+            .cloned()
+            .collect();
+        if contacts.is_empty() {
+            ui.weak("No contacts match");
+        }
+        for pubkey in &contacts {
+            self.checkbox(ui, pubkey);
+        }
+    }
+
+    fn checkbox(&mut self, ui: &mut egui::Ui, pubkey: &str) {
+        let mut selected = self.selected.iter().any(|picked| picked == pubkey);
+        let name = self.names.get(pubkey).cloned().unwrap_or_default();
+        if ui.checkbox(&mut selected, name).changed() {
+            self.set_selected(pubkey, selected);
+        }
+    }
+
+    fn set_selected(&mut self, pubkey: &str, selected: bool) {
+        self.selected.retain(|picked| picked != pubkey);
+        if selected {
+            self.selected.push(pubkey.to_string());
+        }
+    }
+
+    /// Whether `pubkey`'s name or key has what was searched for in it.
+    fn matches(&self, pubkey: &str) -> bool {
+        let query = self.search.trim().to_lowercase();
+        query.is_empty()
+            || pubkey.contains(&query)
+            || self
+                .names
+                .get(pubkey)
+                .is_some_and(|name| name.to_lowercase().contains(&query))
+    }
+}
+
+/// `to_field` with the people in it replaced by `picked`. Only the people who weren't picked
+/// are taken out and the new ones added at the end, everything else stays as the user wrote
+/// it, so they can still fix what didn't parse.
+pub fn write_recipients(to_field: &str, picked: &[String]) -> String {
+    let is_separator = |c: char| [' ', ',', ';'].contains(&c);
+    let mut written = String::new();
+    let mut present = Vec::new();
+    let mut rest = to_field;
+    let mut first = true;
+    while !rest.is_empty() {
+        let start = rest.find(|c| !is_separator(c)).unwrap_or(rest.len());
+        let (separator, tail) = rest.split_at(start);
+        let end = tail.find(is_separator).unwrap_or(tail.len());
+        let (entry, tail) = tail.split_at(end);
+        rest = tail;
+
+        let pubkeys = bridge::parse_recipients(entry).pubkeys;
+        if let Some(pubkey) = pubkeys.first().map(PublicKey::to_hex) {
+            if !picked.contains(&pubkey) {
+                first = false;
+                continue;
+            }
+            present.push(pubkey);
+        }
+        // The separator goes with the entry after it, so taking out the first one doesn't
+        // leave the field starting with a comma.
+        if first || !written.is_empty() {
+            written.push_str(separator);
+        }
+        written.push_str(entry);
+        first = false;
+    }
+
+    let npubs = picked
+        .iter()
+        .filter(|pubkey| !present.contains(pubkey))
+        .filter_map(|pubkey| {
+            PublicKey::from_hex(pubkey)
+                .ok()
+                .and_then(|pubkey| pubkey.to_bech32().ok())
+        });
+    for npub in npubs {
+        if !written.is_empty() && !written.ends_with(is_separator) {
+            written.push_str(", ");
+        }
+        written.push_str(&npub);
+    }
+    written
+}
+
+/// The people in the To field as chips with their names, returning whoever's ✖ was clicked.
+pub fn recipient_chips(
+    ui: &mut egui::Ui,
+    pubkeys: &[PublicKey],
+    names: &HashMap<PublicKey, String>,
+) -> Option<PublicKey> {
+    let mut removed = None;
+    ui.horizontal_wrapped(|ui| {
+        for pubkey in pubkeys {
+            let name = names
+                .get(pubkey)
+                .cloned()
+                .unwrap_or_else(|| short_npub(&pubkey.to_hex()));
+            egui::Frame::none()
+                .fill(ui.visuals().faint_bg_color)
+                .rounding(10.0)
+                .inner_margin(egui::Margin::symmetric(6.0, 1.0))
+                .show(ui, |ui| {
+                    ui.spacing_mut().item_spacing.x = 2.0;
+                    ui.label(RichText::new(name).small());
+                    let remove = ui.small_button("✖");
+                    remove.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            format!("Remove {} from the recipients", pubkey),
+                        )
+                    });
+                    if remove.clicked() {
+                        removed = Some(*pubkey);
+                    }
+                });
+        }
+    });
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    #[test]
+    fn picked_recipients_replace_the_people_in_the_field() {
+        let old = Keys::generate().public_key();
+        let kept = Keys::generate().public_key();
+        let new = Keys::generate().public_key();
+        let field = format!(
+            "{}, bob@example.com {}; npub1typo",
+            old.to_hex(),
+            kept.to_hex()
+        );
+
+        let written = write_recipients(&field, &[kept.to_hex(), new.to_hex()]);
+        assert_eq!(
+            written,
+            format!(
+                "bob@example.com {}; npub1typo, {}",
+                kept.to_hex(),
+                new.to_bech32().unwrap()
+            )
+        );
+        assert_eq!(bridge::parse_recipients(&written).pubkeys, vec![kept, new]);
+
+        // Taking out one chip leaves the rest alone.
+        let written = write_recipients(&field, &[old.to_hex()]);
+        assert_eq!(
+            written,
+            format!("{}, bob@example.com; npub1typo", old.to_hex())
+        );
+        assert_eq!(write_recipients("", &[]), "");
+        assert_eq!(
+            write_recipients("", &[new.to_hex()]),
+            new.to_bech32().unwrap()
+        );
+    }
+}
@@ -1,14 +1,19 @@
-use crate::db::Db;
+use crate::db::{Db, DuplicateContacts, DuplicateReason};
 use crate::image_loader::ImageLoader;
 use crate::profile_metadata::ProfileMetadata;
 use crate::profile_metadata::ProfileOption;
+use crate::TableEntry;
 use eframe::egui::{
     self, Align2, Color32, FontId, Frame, Margin, RichText, ScrollArea, Sense, Stroke,
-    TextureHandle, Vec2,
+    TextureHandle, Vec2, Vec2b,
 };
-use std::collections::HashMap;
+use egui_extras::{Column, TableBuilder};
+use std::collections::{HashMap, HashSet};
 use tracing::error;
 
+/// How many messages the conversation view loads at a time.
+const CONVERSATION_PAGE_SIZE: usize = 50;
+
 #[derive(Clone)]
 pub struct Contact {
     pub pubkey: String,
@@ -19,10 +24,7 @@ pub struct Contact {
 impl Contact {
     /// Best display name: petname > display_name > name > pubkey, without cloning.
     fn best_name(&self) -> &str {
-        self.petname
-            .as_deref()
-            .or(self.metadata.display_name.as_deref())
-            .or(self.metadata.name.as_deref())
+        crate::name_resolver::best_name(self.petname.as_deref(), &self.metadata)
             .unwrap_or(&self.pubkey)
     }
 
@@ -61,14 +63,20 @@ impl Contact {
 
 pub struct ContactsManager {
     contacts: Vec<Contact>,
+    /// Contacts that answered a verification challenge.
+    verified: HashSet<String>,
     image_loader: ImageLoader,
+    /// Goes up with every change to the contacts, see [`ContactsManager::version`].
+    version: u64,
 }
 
 impl ContactsManager {
     pub fn new() -> Self {
         Self {
             contacts: Vec::new(),
+            verified: HashSet::new(),
             image_loader: ImageLoader::new(),
+            version: 0,
         }
     }
 
@@ -78,6 +86,7 @@ impl ContactsManager {
         profile_cache: &mut HashMap<String, ProfileOption>,
     ) -> anyhow::Result<()> {
         let contacts_data = db.get_user_contacts()?;
+        self.verified = db.get_verified_contacts()?;
 
         self.contacts = contacts_data
             .into_iter()
@@ -90,6 +99,7 @@ impl ContactsManager {
 
         self.contacts
             .sort_by(|a, b| contact_sort_key(a).cmp(&contact_sort_key(b)));
+        self.version += 1;
 
         // Cache metadata in profile_cache
         for contact in &self.contacts {
@@ -122,6 +132,7 @@ impl ContactsManager {
         });
         self.contacts
             .sort_by(|a, b| contact_sort_key(a).cmp(&contact_sort_key(b)));
+        self.version += 1;
 
         Ok(())
     }
@@ -130,7 +141,9 @@ impl ContactsManager {
         db.delete_contact(pubkey)?;
 
         self.contacts.retain(|c| c.pubkey != pubkey);
+        self.verified.remove(pubkey);
         self.image_loader.invalidate(pubkey);
+        self.version += 1;
 
         Ok(())
     }
@@ -148,6 +161,7 @@ impl ContactsManager {
         }
         self.contacts
             .sort_by(|a, b| contact_sort_key(a).cmp(&contact_sort_key(b)));
+        self.version += 1;
 
         Ok(())
     }
@@ -161,9 +175,16 @@ impl ContactsManager {
             }
             self.contacts
                 .sort_by(|a, b| contact_sort_key(a).cmp(&contact_sort_key(b)));
+            self.version += 1;
         }
     }
 
+    /// Goes up whenever a contact changes, so whatever was worked out from them can tell it's
+    /// out of date.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     pub fn get_contacts(&self) -> &[Contact] {
         &self.contacts
     }
@@ -176,6 +197,20 @@ impl ContactsManager {
         self.find_contact(pubkey).and_then(|c| c.petname.as_deref())
     }
 
+    pub fn is_verified(&self, pubkey: &str) -> bool {
+        self.verified.contains(pubkey)
+    }
+
+    /// Records that `pubkey` answered our challenge. Only contacts can be verified.
+    pub fn mark_verified(&mut self, db: &mut Db, pubkey: &str) -> anyhow::Result<()> {
+        if self.find_contact(pubkey).is_none() {
+            return Ok(());
+        }
+        db.mark_contact_verified(pubkey)?;
+        self.verified.insert(pubkey.to_string());
+        Ok(())
+    }
+
     pub fn ensure_contact_images_loaded(&mut self) {
         for contact in &self.contacts {
             if let Some(url) = contact.picture_url() {
@@ -185,6 +220,12 @@ impl ContactsManager {
         }
     }
 
+    /// Loads the picture of someone who isn't a contact, like one of our own accounts.
+    pub fn request_image(&mut self, pubkey: &str, url: &str) {
+        self.image_loader
+            .request(pubkey.to_string(), url.to_string());
+    }
+
     pub fn process_image_queue(&mut self, ctx: &egui::Context) {
         self.image_loader.process_queue(ctx);
     }
@@ -194,6 +235,154 @@ impl ContactsManager {
     }
 }
 
+/// A set of duplicates in the maintenance panel, with the choices made so far.
+pub struct DuplicateMerge {
+    pub group: DuplicateContacts,
+    /// The pubkey that stays.
+    pub keep: String,
+    pub petname: String,
+}
+
+impl DuplicateMerge {
+    fn new(group: DuplicateContacts, manager: &ContactsManager) -> Self {
+        let keep = match &group.reason {
+            DuplicateReason::SamePubkey(hex) => hex.clone(),
+            DuplicateReason::SameNip05(_) => group.pubkeys[0].clone(),
+        };
+        let petname = group
+            .pubkeys
+            .iter()
+            .find_map(|pubkey| manager.find_petname(pubkey))
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            group,
+            keep,
+            petname,
+        }
+    }
+}
+
+fn find_duplicates(app: &mut crate::Hoot) {
+    match app.db.find_duplicate_contacts() {
+        Ok(groups) => {
+            app.state.contacts.duplicates = Some(
+                groups
+                    .into_iter()
+                    .map(|group| DuplicateMerge::new(group, &app.contacts_manager))
+                    .collect(),
+            )
+        }
+        Err(e) => error!("Failed to look for duplicate contacts: {}", e),
+    }
+}
+
+fn render_duplicates(app: &mut crate::Hoot, ui: &mut egui::Ui) {
+    use crate::style;
+
+    let Some(duplicates) = app.state.contacts.duplicates.as_mut() else {
+        return;
+    };
+    let contacts = &app.contacts_manager;
+    let mut to_merge: Option<usize> = None;
+    let mut close = false;
+
+    Frame::none()
+        .fill(style::CARD_BG)
+        .stroke(Stroke::new(1.0, style::CARD_STROKE))
+        .inner_margin(Margin::symmetric(16.0, 12.0))
+        .rounding(8.0)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Possible duplicates").strong());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
+            });
+            if duplicates.is_empty() {
+                ui.label(RichText::new("No duplicates found.").color(style::TEXT_MUTED));
+                return;
+            }
+
+            for (i, merge) in duplicates.iter_mut().enumerate() {
+                ui.add_space(6.0);
+                ui.separator();
+                let count = merge.group.pubkeys.len();
+                match &merge.group.reason {
+                    DuplicateReason::SamePubkey(hex) => {
+                        let name = contacts
+                            .find_contact(hex)
+                            .map(|c| c.display_name())
+                            .unwrap_or_else(|| short_key(hex));
+                        ui.label(format!("{} is saved {} times", name, count));
+                    }
+                    DuplicateReason::SameNip05(address) => {
+                        ui.label(format!("{} keys claim {}", count, address));
+                        ui.label(
+                            RichText::new("Only keep one if they really are the same person.")
+                                .small()
+                                .color(style::TEXT_MUTED),
+                        );
+                        for pubkey in &merge.group.pubkeys {
+                            let name = contacts
+                                .find_contact(pubkey)
+                                .map(|c| c.display_name())
+                                .unwrap_or_default();
+                            ui.radio_value(
+                                &mut merge.keep,
+                                pubkey.clone(),
+                                format!("Keep {} ({})", name, short_key(pubkey)),
+                            );
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    let petname_label = ui.label("Petname:");
+                    ui.text_edit_singleline(&mut merge.petname)
+                        .labelled_by(petname_label.id);
+                    if ui.button("Merge").clicked() {
+                        to_merge = Some(i);
+                    }
+                });
+            }
+        });
+    ui.add_space(8.0);
+
+    if close {
+        app.state.contacts.duplicates = None;
+        return;
+    }
+    let Some(i) = to_merge else {
+        return;
+    };
+    let merge = duplicates.remove(i);
+    let petname = Some(merge.petname.trim()).filter(|p| !p.is_empty());
+    if let Err(e) = app
+        .db
+        .merge_contacts(&merge.keep, &merge.group.pubkeys, petname)
+    {
+        error!("Failed to merge contacts: {}", e);
+        return;
+    }
+    if let Err(e) = app
+        .contacts_manager
+        .load_from_db(&app.db, &mut app.profile_metadata)
+    {
+        error!("Failed to reload contacts: {}", e);
+    }
+    // Merging can settle other sets too, look again.
+    find_duplicates(app);
+}
+
+fn short_key(pubkey: &str) -> String {
+    match pubkey.get(..16) {
+        Some(start) if start.len() < pubkey.len() => format!("{}…", start),
+        _ => pubkey.to_string(),
+    }
+}
+
 fn contact_sort_key(contact: &Contact) -> String {
     contact.best_name().to_lowercase()
 }
@@ -201,6 +390,11 @@ fn contact_sort_key(contact: &Contact) -> String {
 pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
     use crate::style;
 
+    if app.state.contacts.conversation_with.is_some() {
+        render_conversation(app, ui);
+        return;
+    }
+
     ui.horizontal(|ui| {
         ui.heading("Contacts");
         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -208,6 +402,12 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
                 app.state.contacts.show_add_form = !app.state.contacts.show_add_form;
                 app.state.contacts.add_error = None;
             }
+            if ui.button("✉ Mail Merge").clicked() {
+                crate::ui::mail_merge_window::MailMergeWindow::open(app);
+            }
+            if ui.button("🧹 Find duplicates").clicked() {
+                find_duplicates(app);
+            }
         });
     });
 
@@ -313,6 +513,8 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
         ui.add_space(8.0);
     }
 
+    render_duplicates(app, ui);
+
     if app.contacts_manager.get_contacts().is_empty() {
         ui.label("No contacts yet. Add one above!");
         return;
@@ -321,6 +523,10 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
     // Track actions to apply after the loop (can't mutate app while iterating)
     let mut contact_to_remove: Option<String> = None;
     let mut petname_to_save: Option<(String, Option<String>)> = None;
+    let mut conversation_to_open: Option<String> = None;
+    let mut contact_to_block: Option<String> = None;
+    let mut contact_to_report: Option<String> = None;
+    let mut contact_to_verify: Option<String> = None;
 
     ScrollArea::vertical()
         .auto_shrink([false; 2])
@@ -333,6 +539,7 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
 
                 let is_editing =
                     app.state.contacts.editing_pubkey.as_ref() == Some(&contact.pubkey);
+                let is_verified = app.contacts_manager.is_verified(&contact.pubkey);
 
                 Frame::none()
                     .fill(style::CARD_BG)
@@ -373,8 +580,20 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
                                         }
                                     });
                                 } else {
-                                    let display = contact.display_name();
-                                    ui.label(RichText::new(&display).strong());
+                                    let mut display = contact.display_name();
+                                    if is_verified {
+                                        display = format!("{} {}", display, style::VERIFIED_BADGE);
+                                    }
+                                    if ui
+                                        .add(
+                                            egui::Label::new(RichText::new(&display).strong())
+                                                .sense(Sense::click()),
+                                        )
+                                        .on_hover_text("Show messages")
+                                        .clicked()
+                                    {
+                                        conversation_to_open = Some(contact.pubkey.clone());
+                                    }
 
                                     if let Some(petname) = &contact.petname {
                                         // Show the nostr name underneath the petname
@@ -427,6 +646,38 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
                                             app.state.contacts.editing_petname_buf =
                                                 contact.petname.clone().unwrap_or_default();
                                         }
+
+                                        if ui
+                                            .button("Messages")
+                                            .on_hover_text("Show messages with this contact")
+                                            .clicked()
+                                        {
+                                            conversation_to_open = Some(contact.pubkey.clone());
+                                        }
+
+                                        if ui
+                                            .button("Report")
+                                            .on_hover_text("Report this contact to your relays")
+                                            .clicked()
+                                        {
+                                            contact_to_report = Some(contact.pubkey.clone());
+                                        }
+
+                                        if !is_verified
+                                            && ui
+                                                .button("Verify")
+                                                .on_hover_text(
+                                                    "Mail them a challenge to answer with their key",
+                                                )
+                                                .clicked()
+                                        {
+                                            contact_to_verify = Some(contact.pubkey.clone());
+                                        }
+
+                                        // Blocked contacts are left out, Settings unblocks them.
+                                        if ui.button("Block").clicked() {
+                                            contact_to_block = Some(contact.pubkey.clone());
+                                        }
                                     },
                                 );
                             }
@@ -438,6 +689,18 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
         });
 
     // Apply deferred mutations
+    if let Some(pubkey) = conversation_to_open {
+        open_conversation(app, pubkey);
+    }
+    if let Some(pubkey) = contact_to_verify {
+        crate::ui::verification::send_challenge(app, &pubkey);
+    }
+    if let Some(pubkey) = contact_to_report {
+        crate::ui::report_dialog::ReportDialog::open(app, pubkey);
+    }
+    if let Some(pubkey) = contact_to_block {
+        app.block_pubkey(&pubkey);
+    }
     if let Some(pubkey) = contact_to_remove {
         if let Err(e) = app.contacts_manager.remove_contact(&app.db, &pubkey) {
             error!("Failed to remove contact: {}", e);
@@ -453,6 +716,130 @@ pub fn render_contacts_page(app: &mut crate::Hoot, ui: &mut egui::Ui) {
     }
 }
 
+pub fn open_conversation(app: &mut crate::Hoot, pubkey: String) {
+    let contacts = &mut app.state.contacts;
+    contacts.conversation_entries.clear();
+    contacts.conversation_has_more = false;
+    contacts.conversation_with = Some(pubkey);
+    load_conversation_page(app);
+}
+
+fn load_conversation_page(app: &mut crate::Hoot) {
+    let contacts = &mut app.state.contacts;
+    let Some(pubkey) = contacts.conversation_with.as_ref() else {
+        return;
+    };
+
+    let offset = contacts.conversation_entries.len();
+    match app
+        .db
+        .get_messages_with(pubkey, CONVERSATION_PAGE_SIZE, offset)
+    {
+        Ok(entries) => {
+            contacts.conversation_has_more = entries.len() == CONVERSATION_PAGE_SIZE;
+            contacts.conversation_entries.extend(entries);
+        }
+        Err(e) => error!("Failed to load messages with {}: {}", pubkey, e),
+    }
+}
+
+fn render_conversation(app: &mut crate::Hoot, ui: &mut egui::Ui) {
+    use crate::style;
+
+    let pubkey = app
+        .state
+        .contacts
+        .conversation_with
+        .clone()
+        .unwrap_or_default();
+    let name = app.display_name(&pubkey);
+
+    let mut load_more = false;
+    ui.horizontal(|ui| {
+        if ui.button("← Contacts").clicked() {
+            app.state.contacts.conversation_with = None;
+            app.state.contacts.conversation_entries.clear();
+        }
+        ui.heading(format!("Messages with {}", name));
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if app.state.contacts.conversation_has_more && ui.button("Load older").clicked() {
+                load_more = true;
+            }
+        });
+    });
+
+    if load_more {
+        load_conversation_page(app);
+    }
+
+    ui.add_space(4.0);
+    ui.separator();
+    ui.add_space(4.0);
+
+    if app.state.contacts.conversation_entries.is_empty() {
+        ui.add_space(40.0);
+        ui.vertical_centered(|ui| {
+            ui.label(
+                RichText::new("No messages with this contact yet")
+                    .size(16.0)
+                    .color(style::TEXT_MUTED),
+            );
+        });
+        return;
+    }
+
+    let mut to_open: Option<String> = None;
+    let entries: Vec<TableEntry> = app.state.contacts.conversation_entries.to_vec();
+    let clock_24h = app.preferences.clock_24h;
+    #[cfg(feature = "profiling")]
+    puffin::profile_scope!("conversation_table");
+    TableBuilder::new(ui)
+        .column(Column::initial(160.0).at_least(100.0)) // Sender
+        .column(Column::remainder()) // Subject
+        .column(Column::initial(100.0).at_least(70.0)) // Time
+        .striped(true)
+        .sense(Sense::click())
+        .auto_shrink(Vec2b { x: false, y: false })
+        .header(28.0, |mut header| {
+            header.col(|ui| {
+                ui.label(RichText::new("From").small().color(style::TEXT_MUTED));
+            });
+            header.col(|ui| {
+                ui.label(RichText::new("Subject").small().color(style::TEXT_MUTED));
+            });
+            header.col(|ui| {
+                ui.label(RichText::new("Date").small().color(style::TEXT_MUTED));
+            });
+        })
+        .body(|body| {
+            body.rows(style::INBOX_ROW_HEIGHT, entries.len(), |mut row| {
+                let entry = &entries[row.index()];
+
+                row.col(|ui| {
+                    let _ = crate::get_profile_metadata(app, entry.pubkey.clone());
+                    let label = app.display_name(&entry.pubkey);
+                    ui.label(RichText::new(label).strong());
+                });
+                row.col(|ui| {
+                    ui.label(&entry.subject);
+                });
+                row.col(|ui| {
+                    style::timestamp_label(ui, entry.created_at, clock_24h);
+                });
+
+                if row.response().clicked() {
+                    to_open = Some(entry.id.clone());
+                }
+            });
+        });
+
+    if let Some(id) = to_open {
+        app.focused_post = id;
+        app.show_trashed_post = false;
+        app.page = crate::Page::Post;
+    }
+}
+
 fn draw_contact_avatar(manager: &ContactsManager, ui: &mut egui::Ui, contact: &Contact) {
     use crate::style;
 
@@ -0,0 +1,108 @@
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText};
+use tracing::error;
+
+pub struct DeleteDialogState {
+    pub event_id: String,
+    /// Messages already in the Trash can only be deleted permanently.
+    pub in_trash: bool,
+    /// Whether one of our accounts wrote the message, so we can ask recipients to delete it.
+    pub authored_by_us: bool,
+    pub request_deletion: bool,
+}
+
+pub struct DeleteDialog {}
+
+impl DeleteDialog {
+    pub fn open(app: &mut Hoot, event_id: String, in_trash: bool) {
+        let authored_by_us = match app.db.get_event_kind_pubkey(&event_id) {
+            Ok(Some((_, author))) => app
+                .account_manager
+                .loaded_keys
+                .iter()
+                .any(|k| k.public_key().to_string() == author),
+            Ok(None) => false,
+            Err(e) => {
+                error!("Failed to look up author of {}: {}", event_id, e);
+                false
+            }
+        };
+
+        app.state.delete_dialog = Some(DeleteDialogState {
+            event_id,
+            in_trash,
+            authored_by_us,
+            request_deletion: false,
+        });
+    }
+
+    pub fn show_window(app: &mut Hoot, ctx: &egui::Context) {
+        let Some(state) = app.state.delete_dialog.as_mut() else {
+            return;
+        };
+
+        let mut move_to_trash = false;
+        let mut delete_permanently = false;
+        let mut cancelled = false;
+        egui::Window::new("Delete message")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if state.in_trash {
+                    ui.label("This message will be deleted permanently.");
+                } else {
+                    ui.label("Move this message to the Trash, or delete it permanently?");
+                }
+
+                if state.authored_by_us {
+                    ui.add_space(4.0);
+                    ui.checkbox(
+                        &mut state.request_deletion,
+                        "Also ask recipients to delete their copies",
+                    );
+                    ui.small("Relays and other apps may ignore this request.");
+                }
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                    if !state.in_trash && ui.button("Move to Trash").clicked() {
+                        move_to_trash = true;
+                    }
+                    if ui
+                        .button(RichText::new("Delete Permanently").color(Color32::RED))
+                        .clicked()
+                    {
+                        delete_permanently = true;
+                    }
+                });
+            });
+
+        if !(move_to_trash || delete_permanently || cancelled) {
+            return;
+        }
+        let Some(state) = app.state.delete_dialog.take() else {
+            return;
+        };
+
+        // The request has to go out before the local delete removes the message we read the
+        // recipients from.
+        if state.request_deletion && (move_to_trash || delete_permanently) {
+            if let Err(e) = crate::publish_deletion_request(app, &state.event_id) {
+                error!("Failed to publish deletion request: {}", e);
+            }
+        }
+
+        if move_to_trash {
+            crate::move_to_trash(app, &state.event_id);
+        } else if delete_permanently {
+            if let Err(e) = crate::apply_deletions(app, vec![state.event_id.clone()], None, None) {
+                error!("Failed to delete message: {}", e);
+            }
+            crate::refresh_after_deletions(app);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Showing part of the app in an OS window of its own.
+//!
+//! The windows are immediate viewports: they're drawn during the main window's frame, on the
+//! same thread and with the same `&mut Hoot`, so no state has to be shared between threads.
+
+use eframe::egui;
+
+const DEFAULT_SIZE: [f32; 2] = [720.0, 640.0];
+
+/// Shows `add_contents` in the OS window `id`, or in a floating window where the platform can't
+/// open more than one. Returns `false` once the user closes it.
+pub fn show(
+    ctx: &egui::Context,
+    id: egui::ViewportId,
+    title: &str,
+    add_contents: impl FnOnce(&mut egui::Ui),
+) -> bool {
+    let builder = egui::ViewportBuilder::default()
+        .with_title(title)
+        .with_inner_size(DEFAULT_SIZE);
+    ctx.show_viewport_immediate(id, builder, |ctx, class| {
+        if class == egui::ViewportClass::Embedded {
+            let mut open = true;
+            egui::Window::new(title)
+                .id(id.0)
+                .open(&mut open)
+                .default_size(DEFAULT_SIZE)
+                .show(ctx, add_contents);
+            return open;
+        }
+        egui::CentralPanel::default().show(ctx, add_contents);
+        !ctx.input(|i| i.viewport().close_requested())
+    })
+}
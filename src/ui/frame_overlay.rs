@@ -0,0 +1,97 @@
+//! A small frame time readout drawn over the app, for spotting slow frames without running
+//! the puffin viewer.
+
+use eframe::egui::{self, util::History, Color32, Pos2, Rect, Sense, Stroke, Vec2};
+
+pub const FRAME_OVERLAY_KEY: &str = "frame_overlay";
+
+/// A frame slower than this misses a 60 Hz refresh.
+const FRAME_BUDGET_MS: f32 = 1000.0 / 60.0;
+const GRAPH_SIZE: Vec2 = Vec2::new(240.0, 48.0);
+
+pub struct FrameOverlay {
+    pub visible: bool,
+    /// CPU time of recent frames, in milliseconds.
+    history: History<f32>,
+}
+
+impl FrameOverlay {
+    pub fn new(visible: bool) -> Self {
+        Self {
+            visible,
+            history: History::new(2..300, 5.0),
+        }
+    }
+
+    /// Records how long the previous frame took. Call once per frame.
+    pub fn on_new_frame(&mut self, now: f64, previous_frame_ms: Option<f32>) {
+        if let Some(ms) = previous_frame_ms {
+            self.history.add(now, ms);
+        } else {
+            self.history.flush(now);
+        }
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        if !self.visible {
+            return;
+        }
+        egui::Area::new(egui::Id::new("frame_overlay"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, Vec2::new(-8.0, -8.0))
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(self.summary());
+                    self.graph(ui);
+                });
+            });
+        // Keep the numbers moving even when nothing else asks for a repaint.
+        ctx.request_repaint_after(std::time::Duration::from_millis(250));
+    }
+
+    /// "4.2 ms mean · 11.8 ms max · 60 fps"
+    fn summary(&self) -> String {
+        let Some(mean) = self.history.average() else {
+            return "Waiting for frames…".to_string();
+        };
+        let max = self.history.values().fold(0.0_f32, f32::max);
+        let fps = self.history.rate().unwrap_or_default();
+        format!("{:.1} ms mean · {:.1} ms max · {:.0} fps", mean, max, fps)
+    }
+
+    /// One bar per frame, red when it blew the budget, with the budget as a line.
+    fn graph(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(GRAPH_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let scale_ms = self.history.values().fold(FRAME_BUDGET_MS * 2.0, f32::max);
+        let y_for = |ms: f32| rect.bottom() - rect.height() * (ms / scale_ms).min(1.0);
+
+        let bar_width = rect.width() / self.history.max_len() as f32;
+        for (i, ms) in self.history.values().enumerate() {
+            let left = rect.left() + i as f32 * bar_width;
+            let color = if ms > FRAME_BUDGET_MS {
+                Color32::from_rgb(220, 80, 80)
+            } else {
+                ui.visuals().text_color()
+            };
+            painter.rect_filled(
+                Rect::from_min_max(
+                    Pos2::new(left, y_for(ms)),
+                    Pos2::new(left + bar_width, rect.bottom()),
+                ),
+                0.0,
+                color,
+            );
+        }
+
+        let budget = y_for(FRAME_BUDGET_MS);
+        painter.hline(
+            rect.x_range(),
+            budget,
+            Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+    }
+}
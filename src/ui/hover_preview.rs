@@ -0,0 +1,180 @@
+//! The card shown after resting the pointer on an inbox row for a moment: who the latest
+//! message in the conversation is from, how it starts, and buttons to archive or answer it
+//! without opening the thread.
+
+use crate::mail_event::MailMessage;
+use crate::style;
+use crate::{Hoot, TableEntry};
+use eframe::egui::{self, Pos2, Rect, RichText};
+use std::collections::{HashMap, VecDeque};
+use tracing::error;
+
+/// How long the pointer rests on a row before its card shows, in seconds.
+const HOVER_DELAY: f64 = 0.6;
+/// How many lines of the message the card shows.
+const PREVIEW_LINES: usize = 4;
+/// How many conversations' latest messages are kept for showing again.
+const CACHED: usize = 64;
+const CARD_WIDTH: f32 = 360.0;
+
+/// The row the card is about and where the pointer came onto it.
+struct Target {
+    entry: TableEntry,
+    since: f64,
+    pos: Pos2,
+}
+
+#[derive(Default)]
+pub struct HoverPreview {
+    target: Option<Target>,
+    /// The row under the pointer this frame, and where the card would go.
+    hovered: Option<(TableEntry, Pos2)>,
+    card: Option<Rect>,
+    /// Latest messages of the conversations previewed, by thread id and the time of the
+    /// row's latest message, so a new reply is loaded again.
+    messages: HashMap<(String, i64), Option<MailMessage>>,
+    loaded: VecDeque<(String, i64)>,
+}
+
+impl HoverPreview {
+    /// Notes that the pointer is on `entry`'s row, called for every row of the inbox table.
+    pub fn row(&mut self, entry: &TableEntry, response: &egui::Response) {
+        if !response.hovered() {
+            return;
+        }
+        let x = response
+            .hover_pos()
+            .map_or(response.rect.left(), |pos| pos.x);
+        self.hovered = Some((entry.clone(), Pos2::new(x, response.rect.bottom())));
+    }
+
+    fn message(&mut self, db: &crate::db::Db, entry: &TableEntry) -> Option<MailMessage> {
+        let key = (entry.id.clone(), entry.created_at);
+        if let Some(message) = self.messages.get(&key) {
+            return message.clone();
+        }
+        let message = match db.get_email_thread(&entry.id) {
+            Ok(mut thread) => thread.pop(),
+            Err(e) => {
+                error!("Failed to load {} for its preview: {}", entry.id, e);
+                None
+            }
+        };
+        if self.loaded.len() == CACHED {
+            if let Some(oldest) = self.loaded.pop_front() {
+                self.messages.remove(&oldest);
+            }
+        }
+        self.loaded.push_back(key.clone());
+        self.messages.insert(key, message.clone());
+        message
+    }
+}
+
+/// Shows the card for the row the pointer rests on, once it has for long enough. Called after
+/// the inbox table each frame.
+pub fn show(app: &mut Hoot, ctx: &egui::Context) {
+    let preview = &mut app.hover_preview;
+    let now = ctx.input(|i| i.time);
+    let over_card = preview.card.is_some_and(|card| {
+        ctx.pointer_hover_pos()
+            .is_some_and(|pos| card.expand(4.0).contains(pos))
+    });
+    match preview.hovered.take() {
+        // Moving onto the card over other rows keeps it.
+        _ if over_card => {}
+        Some((entry, pos)) => {
+            if preview.target.as_ref().map(|target| &target.entry.id) != Some(&entry.id) {
+                preview.target = Some(Target {
+                    entry,
+                    since: now,
+                    pos,
+                });
+                preview.card = None;
+            }
+        }
+        None => {
+            preview.target = None;
+            preview.card = None;
+        }
+    }
+    let Some(target) = &preview.target else {
+        return;
+    };
+    let waited = now - target.since;
+    if waited < HOVER_DELAY {
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64(HOVER_DELAY - waited));
+        return;
+    }
+
+    let entry = target.entry.clone();
+    let pos = target.pos;
+    let Some(message) = preview.message(&app.db, &entry) else {
+        return;
+    };
+    let pubkey = message
+        .author
+        .map_or_else(|| entry.pubkey.clone(), |author| author.to_hex());
+    let name = app.display_name(&pubkey);
+    let known = app.contacts_manager.find_contact(&pubkey).is_some();
+
+    let mut archive = false;
+    let mut reply = false;
+    let area = egui::Area::new(egui::Id::new("inbox_hover_preview"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(pos)
+        .constrain(true)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_width(CARD_WIDTH);
+                ui.horizontal(|ui| {
+                    style::avatar(ui, app.contacts_manager.get_contact_image(&pubkey), &name);
+                    ui.vertical(|ui| {
+                        ui.label(RichText::new(&name).strong());
+                        let about = if known {
+                            "In your contacts".to_string()
+                        } else {
+                            crate::name_resolver::short_npub(&pubkey)
+                        };
+                        ui.label(RichText::new(about).small().color(style::TEXT_MUTED));
+                    });
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                        style::timestamp_label(ui, entry.created_at, app.preferences.clock_24h);
+                    });
+                });
+                ui.add_space(4.0);
+                ui.label(RichText::new(&message.subject).strong());
+                let mut lines = message
+                    .content
+                    .lines()
+                    .filter(|line| !line.trim().is_empty());
+                for line in lines.by_ref().take(PREVIEW_LINES) {
+                    ui.add(egui::Label::new(line).truncate(true));
+                }
+                if lines.next().is_some() {
+                    ui.label(RichText::new("…").color(style::TEXT_MUTED));
+                }
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    archive = ui.button("📥 Archive").clicked();
+                    reply = ui.button("↩️ Reply").clicked();
+                });
+            });
+        });
+    app.hover_preview.card = Some(area.response.rect);
+
+    if archive || reply {
+        app.hover_preview.target = None;
+        app.hover_preview.card = None;
+    }
+    if archive {
+        app.set_thread_archived(&entry.id, true);
+    }
+    if reply {
+        let to_field = app
+            .bridge
+            .reply_address(&message)
+            .unwrap_or_else(|| pubkey.clone());
+        crate::open_reply(app, &message, to_field);
+    }
+}
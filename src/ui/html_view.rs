@@ -0,0 +1,565 @@
+//! HTML email from the bridge, shown with plain egui widgets instead of a webview.
+//!
+//! Only a handful of tags are understood and everything else is reduced to its text. Scripts,
+//! styles and the like are dropped, and nothing is ever fetched: images show up as links to
+//! where they live, and only http, https and mailto links are kept.
+
+use crate::style;
+use eframe::egui::{RichText, Ui};
+use std::borrow::Cow;
+
+/// Tags whose content is never shown.
+const HIDDEN_TAGS: [&str; 9] = [
+    "head", "title", "style", "script", "noscript", "template", "iframe", "object", "svg",
+];
+/// Tags that start a new line of text.
+const BLOCK_TAGS: [&str; 16] = [
+    "p", "div", "br", "table", "tr", "section", "article", "header", "footer", "main", "center",
+    "dl", "dt", "dd", "form", "address",
+];
+/// How far each level of list is indented.
+const INDENT: f32 = 16.0;
+
+/// A run of text that's formatted the same way.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Span {
+    text: String,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockKind {
+    Paragraph,
+    Heading(u8),
+    /// A list item, with its bullet or number.
+    Item(String),
+    Preformatted,
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    /// How many lists the block is in.
+    depth: usize,
+    quoted: bool,
+    spans: Vec<Span>,
+}
+
+impl Block {
+    fn new(kind: BlockKind, depth: usize, quoted: bool) -> Self {
+        Self {
+            kind,
+            depth,
+            quoted,
+            spans: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.spans.iter().all(|span| span.text.trim().is_empty())
+    }
+}
+
+/// Shows a sanitized rendering of `html`.
+pub fn html_view(ui: &mut Ui, html: &str) {
+    for block in parse(html) {
+        if block.kind == BlockKind::Rule {
+            ui.separator();
+            continue;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            ui.add_space(block.depth as f32 * INDENT);
+            if block.quoted {
+                ui.label(RichText::new("▎ ").color(style::TEXT_MUTED));
+            }
+            match &block.kind {
+                BlockKind::Preformatted => {
+                    let text: String = block.spans.iter().map(|span| span.text.as_str()).collect();
+                    ui.label(RichText::new(text).code());
+                    return;
+                }
+                BlockKind::Item(marker) => {
+                    ui.label(format!("{} ", marker));
+                }
+                _ => {}
+            }
+            for span in &block.spans {
+                let text = span_text(span, &block.kind);
+                match &span.link {
+                    Some(url) => {
+                        ui.hyperlink_to(text, url).on_hover_text(url);
+                    }
+                    None => {
+                        ui.label(text);
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn span_text(span: &Span, kind: &BlockKind) -> RichText {
+    let mut text = RichText::new(&span.text);
+    if let BlockKind::Heading(level) = kind {
+        text = if *level <= 2 {
+            text.heading()
+        } else {
+            text.strong()
+        };
+    }
+    if span.bold {
+        text = text.strong();
+    }
+    if span.italic {
+        text = text.italics();
+    }
+    if span.underline {
+        text = text.underline();
+    }
+    if span.strike {
+        text = text.strikethrough();
+    }
+    if span.code {
+        text = text.code();
+    }
+    text
+}
+
+fn parse(html: &str) -> Vec<Block> {
+    let mut parser = Parser::default();
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.split_once("-->").map_or("", |(_, after)| after);
+            continue;
+        }
+        let is_tag = rest.starts_with('<')
+            && rest[1..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?');
+        if is_tag {
+            match tag_end(rest) {
+                Some(end) => {
+                    parser.tag(&rest[1..end]);
+                    rest = &rest[end + 1..];
+                }
+                // An unfinished tag at the end, there's nothing after it to show.
+                None => break,
+            }
+        } else {
+            // Step past the first character whole, it may be more than one byte.
+            let first = rest.chars().next().map_or(0, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |i| i + first);
+            parser.text(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    parser.finish()
+}
+
+/// Where the tag at the start of `s` ends, skipping over `>` in quoted attributes.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Default)]
+struct Parser {
+    blocks: Vec<Block>,
+    current: Option<Block>,
+    /// How deep we are in each kind of tag.
+    hidden: u32,
+    bold: u32,
+    italic: u32,
+    underline: u32,
+    strike: u32,
+    code: u32,
+    pre: u32,
+    quote: u32,
+    heading: Option<u8>,
+    links: Vec<Option<String>>,
+    /// The lists we're in, with the next number for numbered ones.
+    lists: Vec<Option<usize>>,
+}
+
+impl Parser {
+    fn tag(&mut self, tag: &str) {
+        if tag.starts_with(['!', '?']) {
+            return;
+        }
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let attrs = &tag[name_end..];
+        let self_closing = attrs.trim_end().ends_with('/');
+
+        if HIDDEN_TAGS.contains(&name.as_str()) {
+            if closing {
+                self.hidden = self.hidden.saturating_sub(1);
+            } else if !self_closing {
+                self.hidden += 1;
+            }
+            return;
+        }
+        if self.hidden > 0 {
+            return;
+        }
+
+        let nest = |depth: &mut u32| {
+            if closing {
+                *depth = depth.saturating_sub(1);
+            } else if !self_closing {
+                *depth += 1;
+            }
+        };
+        match name.as_str() {
+            "b" | "strong" => nest(&mut self.bold),
+            "i" | "em" | "cite" => nest(&mut self.italic),
+            "u" | "ins" => nest(&mut self.underline),
+            "s" | "strike" | "del" => nest(&mut self.strike),
+            "code" | "tt" | "kbd" | "samp" => nest(&mut self.code),
+            "pre" => {
+                self.end_block();
+                nest(&mut self.pre);
+            }
+            "blockquote" => {
+                self.end_block();
+                nest(&mut self.quote);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.end_block();
+                self.heading = (!closing).then(|| name.as_bytes()[1] - b'0');
+            }
+            "ul" | "ol" => {
+                self.end_block();
+                if closing {
+                    self.lists.pop();
+                } else {
+                    self.lists.push((name == "ol").then_some(1));
+                }
+            }
+            "li" => {
+                self.end_block();
+                if !closing {
+                    let marker = match self.lists.last_mut() {
+                        Some(Some(number)) => {
+                            *number += 1;
+                            format!("{}.", *number - 1)
+                        }
+                        _ => "•".to_string(),
+                    };
+                    self.current = Some(Block::new(
+                        BlockKind::Item(marker),
+                        self.lists.len().saturating_sub(1),
+                        self.quote > 0,
+                    ));
+                }
+            }
+            "a" if closing => {
+                self.links.pop();
+            }
+            "a" => self
+                .links
+                .push(attribute(attrs, "href").filter(|href| is_safe_link(href))),
+            "img" => {
+                let alt = attribute(attrs, "alt").filter(|alt| !alt.trim().is_empty());
+                let text = format!("🖼 {}", alt.as_deref().unwrap_or("image"));
+                match attribute(attrs, "src").filter(|src| is_safe_link(src)) {
+                    Some(src) => {
+                        self.links.push(Some(src));
+                        self.push(text);
+                        self.links.pop();
+                    }
+                    None => self.push(text),
+                }
+            }
+            "hr" => {
+                self.end_block();
+                self.blocks
+                    .push(Block::new(BlockKind::Rule, self.lists.len(), false));
+            }
+            "td" | "th" if !closing => self.push(" ".to_string()),
+            name if BLOCK_TAGS.contains(&name) => self.end_block(),
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden > 0 {
+            return;
+        }
+        let text = decode_entities(text);
+        let text = if self.pre > 0 {
+            text.into_owned()
+        } else {
+            collapse_whitespace(&text)
+        };
+        self.push(text);
+    }
+
+    /// Adds text to the current block with the formatting we're in.
+    fn push(&mut self, mut text: String) {
+        let style = Span {
+            text: String::new(),
+            bold: self.bold > 0,
+            italic: self.italic > 0,
+            underline: self.underline > 0,
+            strike: self.strike > 0,
+            code: self.code > 0,
+            link: self.links.iter().rev().find_map(Clone::clone),
+        };
+        let kind = if self.pre > 0 {
+            BlockKind::Preformatted
+        } else if let Some(level) = self.heading {
+            BlockKind::Heading(level)
+        } else {
+            BlockKind::Paragraph
+        };
+        let (depth, quoted) = (self.lists.len(), self.quote > 0);
+        let block = self
+            .current
+            .get_or_insert_with(|| Block::new(kind, depth, quoted));
+
+        if block.kind != BlockKind::Preformatted {
+            let after_space = block
+                .spans
+                .last()
+                .is_none_or(|span| span.text.ends_with(' '));
+            if after_space {
+                text = text.trim_start().to_string();
+            }
+        }
+        if text.is_empty() {
+            return;
+        }
+        match block.spans.last_mut() {
+            Some(last)
+                if Span {
+                    text: String::new(),
+                    ..last.clone()
+                } == style =>
+            {
+                last.text.push_str(&text);
+            }
+            _ => block.spans.push(Span { text, ..style }),
+        }
+    }
+
+    fn end_block(&mut self) {
+        let Some(mut block) = self.current.take() else {
+            return;
+        };
+        if block.is_empty() {
+            return;
+        }
+        if let Some(last) = block.spans.last_mut() {
+            last.text.truncate(last.text.trim_end().len());
+        }
+        self.blocks.push(block);
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        self.end_block();
+        self.blocks
+    }
+}
+
+/// Links we're willing to open in the browser.
+fn is_safe_link(url: &str) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+}
+
+/// The value of the attribute `name` among a tag's attributes.
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        after[1..].split_once(quote).unwrap_or((&after[1..], ""))
+                    }
+                    _ => after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+                };
+                rest = after;
+                Some(value)
+            }
+            None => None,
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return value.map(|value| decode_entities(value).into_owned());
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        // A non-breaking space stays, it's often there on purpose.
+        if c.is_whitespace() && c != '\u{a0}' {
+            space = true;
+            continue;
+        }
+        if space {
+            out.push(' ');
+            space = false;
+        }
+        out.push(c);
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+fn decode_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let decoded = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((entity(&rest[..end])?, end + 1)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+fn entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "euro" => '€',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            return char::from_u32(code);
+        }
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(block: &Block) -> String {
+        block.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    #[test]
+    fn keeps_the_text_and_drops_anything_active() {
+        let html = r#"<!DOCTYPE html><html><head><title>News</title>
+            <style>p { color: red }</style><script>alert("hi")</script></head>
+            <body><h1>Weekly &amp; news</h1>
+            <p>Hello <b>there</b>,  read <a href="https://example.com/a?x=1&amp;y=2">more</a>
+            or <a href="javascript:alert(1)">this</a>.</p>
+            <img src="https://tracker.example.com/pixel.gif" alt="">
+            <ol><li>One</li><li>Two &#8212; <i>ok</i></li></ol><hr><p>Über <b>Ärger</b></p></body></html>"#;
+        let blocks = parse(html);
+
+        let texts: Vec<String> = blocks.iter().map(text).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Weekly & news",
+                "Hello there, read more or this.",
+                "🖼 image",
+                "One",
+                "Two — ok",
+                "",
+                "Über Ärger",
+            ]
+        );
+        assert_eq!(blocks[0].kind, BlockKind::Heading(1));
+        assert_eq!(blocks[3].kind, BlockKind::Item("1.".to_string()));
+        assert_eq!(blocks[4].kind, BlockKind::Item("2.".to_string()));
+        assert_eq!(blocks[5].kind, BlockKind::Rule);
+
+        let links: Vec<Option<&str>> = blocks[1]
+            .spans
+            .iter()
+            .map(|span| span.link.as_deref())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                None,
+                None,
+                None,
+                Some("https://example.com/a?x=1&y=2"),
+                None
+            ]
+        );
+        assert!(blocks[1].spans[1].bold);
+        // Images are never loaded, only linked to.
+        assert_eq!(
+            blocks[2].spans[0].link.as_deref(),
+            Some("https://tracker.example.com/pixel.gif")
+        );
+    }
+}
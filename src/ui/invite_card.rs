@@ -0,0 +1,56 @@
+use crate::calendar::Invite;
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, Frame, Margin, RichText, Stroke, Ui};
+use tracing::{error, info};
+
+/// Shows a calendar invite with an "Add to calendar" button that opens it in the default
+/// calendar app.
+pub fn invite_card(app: &mut Hoot, ui: &mut Ui, invite: &Invite) {
+    Frame::none()
+        .fill(style::CARD_BG)
+        .stroke(Stroke::new(1.0, style::CARD_STROKE))
+        .inner_margin(Margin::same(12.0))
+        .rounding(8.0)
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(format!(
+                    "📅 {}",
+                    invite.summary.as_deref().unwrap_or("Calendar invite")
+                ))
+                .strong(),
+            );
+            ui.add_space(4.0);
+
+            egui::Grid::new(ui.next_auto_id())
+                .num_columns(2)
+                .spacing([8.0, 4.0])
+                .show(ui, |ui| {
+                    let rows = [
+                        ("When", invite.when()),
+                        ("Where", invite.location.clone()),
+                        ("Organizer", invite.organizer.clone()),
+                    ];
+                    for (label, value) in rows {
+                        if let Some(value) = value {
+                            ui.label(RichText::new(label).color(style::TEXT_MUTED));
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    }
+                });
+
+            ui.add_space(4.0);
+            if ui.button("Add to calendar").clicked() {
+                match invite.export(&app.downloads.download_dir) {
+                    Ok(path) => {
+                        info!("Exported invite to {:?}", path);
+                        if let Err(e) = crate::downloads::open_path(&path) {
+                            error!("Failed to open {:?}: {}", path, e);
+                        }
+                    }
+                    Err(e) => error!("Failed to export invite: {}", e),
+                }
+            }
+        });
+}
@@ -0,0 +1,97 @@
+//! Labels on the open thread. A label goes on every message in the thread, and replies that
+//! come in later pick it up from the messages they answer.
+
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, RichText};
+use tracing::error;
+
+/// The labels of an inbox row.
+pub fn label_chips(ui: &mut egui::Ui, labels: &[String]) {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 4.0;
+        for label in labels {
+            egui::Frame::none()
+                .fill(style::ACCENT_LIGHT)
+                .rounding(8.0)
+                .inner_margin(egui::Margin::symmetric(6.0, 0.0))
+                .show(ui, |ui| {
+                    ui.label(RichText::new(label).small());
+                });
+        }
+    });
+}
+
+/// Draws the labels on the thread made of `event_ids`, with a menu to add and remove them.
+pub fn render_thread_labels(app: &mut Hoot, ui: &mut egui::Ui, event_ids: &[String]) {
+    let Some(labels) = app
+        .thread_snapshot
+        .as_ref()
+        .map(|snapshot| snapshot.labels(event_ids))
+    else {
+        return;
+    };
+
+    let mut change = None;
+    for label in &labels {
+        egui::Frame::none()
+            .fill(style::ACCENT_LIGHT)
+            .rounding(8.0)
+            .inner_margin(egui::Margin::symmetric(6.0, 2.0))
+            .show(ui, |ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+                ui.label(RichText::new(label).small());
+                if ui
+                    .small_button("✕")
+                    .on_hover_text(format!("Remove the label “{}”", label))
+                    .clicked()
+                {
+                    change = Some((label.clone(), false));
+                }
+            });
+    }
+
+    ui.menu_button("🏷 Label", |ui| {
+        let all = app
+            .thread_snapshot
+            .as_ref()
+            .map(|snapshot| snapshot.all_labels.clone())
+            .unwrap_or_default();
+        for label in all {
+            let mut on = labels.contains(&label);
+            if ui.checkbox(&mut on, &label).changed() {
+                change = Some((label, on));
+            }
+        }
+        ui.horizontal(|ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut app.state.new_label)
+                    .hint_text("New label")
+                    .desired_width(120.0),
+            );
+            let name = app.state.new_label.trim().to_string();
+            let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let add = ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Add"))
+                .clicked();
+            if (add || entered) && !name.is_empty() {
+                change = Some((name, true));
+                app.state.new_label.clear();
+                ui.close_menu();
+            }
+        });
+    });
+
+    if let Some((label, on)) = change {
+        match app.db.set_thread_label(event_ids, &label, on) {
+            Ok(()) => {
+                if let Some(snapshot) = app.thread_snapshot.as_mut() {
+                    snapshot.set_label(event_ids, &label, on);
+                }
+                // For the inbox's Labels column.
+                app.refresh_table_entries();
+            }
+            Err(e) => error!("Failed to label thread {}: {}", app.focused_post, e),
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use crate::mail_merge::{self, MergedMessage};
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText};
+use nostr::{Keys, PublicKey, ToBech32};
+use tracing::error;
+
+#[derive(Default)]
+pub struct MailMergeState {
+    pub subject: String,
+    pub body: String,
+    pub selected_account: Option<Keys>,
+    pub groups: Vec<(i64, String)>,
+    /// Group the recipients were picked from, `None` for all contacts.
+    pub group: Option<i64>,
+    pub recipients: Vec<String>,
+    pub preview_index: usize,
+    pub new_group_name: String,
+}
+
+pub struct MailMergeWindow {}
+
+impl MailMergeWindow {
+    pub fn open(app: &mut Hoot) {
+        let mut state = MailMergeState {
+            selected_account: app
+                .active_account
+                .clone()
+                .or_else(|| app.account_manager.loaded_keys.first().cloned()),
+            ..Default::default()
+        };
+        state.recipients = all_contacts(app);
+        app.state.mail_merge = Some(state);
+        reload_groups(app);
+    }
+
+    pub fn show_window(app: &mut Hoot, ctx: &egui::Context) {
+        if app.state.mail_merge.is_none() {
+            return;
+        }
+
+        // Resolve everything that needs `app` before borrowing the window state.
+        let account_options: Vec<(Keys, String)> = app
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|k| {
+                let pk_hex = k.public_key().to_hex();
+                let name = app.resolve_name(&pk_hex).unwrap_or(pk_hex);
+                (k.clone(), name)
+            })
+            .collect();
+        let contacts: Vec<(String, String)> = app
+            .contacts_manager
+            .get_contacts()
+            .iter()
+            .map(|c| (c.pubkey.clone(), c.display_name()))
+            .collect();
+        let recipient_names: Vec<(String, String)> = app
+            .state
+            .mail_merge
+            .as_ref()
+            .map(|state| state.recipients.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|pk| {
+                let name = recipient_name(app, &pk);
+                (pk, name)
+            })
+            .collect();
+        let progress = app.mail_merge.progress();
+        let last_result = (app.mail_merge.sent, app.mail_merge.failed);
+        let near_cap = app.relays.near_cap();
+
+        let state = app.state.mail_merge.as_mut().unwrap();
+        let mut open = true;
+        let mut group_to_load: Option<Option<i64>> = None;
+        let mut group_to_save: Option<String> = None;
+        let mut group_to_delete: Option<i64> = None;
+        let mut start = false;
+        let mut cancel = false;
+
+        egui::Window::new("Mail Merge")
+            .open(&mut open)
+            .default_size([640.0, 520.0])
+            .show(ctx, |ui| {
+                // Recipients
+                ui.horizontal(|ui| {
+                    let group_label = ui.label("Recipients:");
+                    let selected_group = state
+                        .group
+                        .and_then(|id| state.groups.iter().find(|(gid, _)| *gid == id))
+                        .map(|(_, name)| name.clone())
+                        .unwrap_or_else(|| "All contacts".to_string());
+                    egui::ComboBox::from_id_source("mail_merge_group")
+                        .selected_text(selected_group)
+                        .show_ui(ui, |ui| {
+                            if ui
+                                .selectable_label(state.group.is_none(), "All contacts")
+                                .clicked()
+                            {
+                                group_to_load = Some(None);
+                            }
+                            for (id, name) in &state.groups {
+                                if ui
+                                    .selectable_label(state.group == Some(*id), name)
+                                    .clicked()
+                                {
+                                    group_to_load = Some(Some(*id));
+                                }
+                            }
+                        })
+                        .response
+                        .labelled_by(group_label.id);
+
+                    if let Some(id) = state.group {
+                        if ui.button("Delete group").clicked() {
+                            group_to_delete = Some(id);
+                        }
+                    }
+                });
+
+                egui::CollapsingHeader::new(format!(
+                    "{} of {} contacts selected",
+                    state.recipients.len(),
+                    contacts.len()
+                ))
+                .id_source("mail_merge_recipients")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(120.0)
+                        .show(ui, |ui| {
+                            for (pubkey, name) in &contacts {
+                                let mut selected = state.recipients.contains(pubkey);
+                                if ui.checkbox(&mut selected, name).changed() {
+                                    if selected {
+                                        state.recipients.push(pubkey.clone());
+                                    } else {
+                                        state.recipients.retain(|pk| pk != pubkey);
+                                    }
+                                }
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut state.new_group_name)
+                                .hint_text("Group name")
+                                .desired_width(160.0),
+                        );
+                        let can_save =
+                            !state.new_group_name.trim().is_empty() && !state.recipients.is_empty();
+                        if ui
+                            .add_enabled(can_save, egui::Button::new("Save as group"))
+                            .clicked()
+                        {
+                            group_to_save = Some(state.new_group_name.trim().to_string());
+                        }
+                    });
+                });
+
+                ui.add_space(4.0);
+
+                // Template
+                ui.horizontal(|ui| {
+                    let subject_label =
+                        ui.label(RichText::new("Subject:").color(style::TEXT_MUTED));
+                    ui.add_sized(
+                        [ui.available_width(), 24.0],
+                        egui::TextEdit::singleline(&mut state.subject),
+                    )
+                    .labelled_by(subject_label.id);
+                });
+                ui.small("Use {{name}} for each recipient's name and {{npub}} for their key.");
+                ui.add_sized(
+                    [ui.available_width(), 140.0],
+                    egui::TextEdit::multiline(&mut state.body),
+                )
+                .widget_info(|| {
+                    egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Message template")
+                });
+
+                ui.add_space(4.0);
+                ui.separator();
+
+                // Preview
+                if recipient_names.is_empty() {
+                    ui.label(
+                        RichText::new("Pick some recipients to preview.").color(style::TEXT_MUTED),
+                    );
+                } else {
+                    let index = state.preview_index.min(recipient_names.len() - 1);
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("Preview").strong());
+                        if ui.add_enabled(index > 0, egui::Button::new("<")).clicked() {
+                            state.preview_index = index - 1;
+                        }
+                        ui.label(format!(
+                            "{} ({} of {})",
+                            recipient_names[index].1,
+                            index + 1,
+                            recipient_names.len()
+                        ));
+                        if ui
+                            .add_enabled(index + 1 < recipient_names.len(), egui::Button::new(">"))
+                            .clicked()
+                        {
+                            state.preview_index = index + 1;
+                        }
+                    });
+                    if let Some(preview) = merge(state, &recipient_names[index..=index]).pop() {
+                        ui.label(RichText::new(&preview.subject).strong());
+                        egui::ScrollArea::vertical()
+                            .id_source("mail_merge_preview")
+                            .max_height(120.0)
+                            .show(ui, |ui| {
+                                ui.label(&preview.content);
+                            });
+                    }
+                }
+
+                ui.separator();
+
+                // Send
+                ui.horizontal(|ui| {
+                    let selected_text = state
+                        .selected_account
+                        .as_ref()
+                        .and_then(|k| {
+                            account_options
+                                .iter()
+                                .find(|(key, _)| key.public_key() == k.public_key())
+                                .map(|(_, name)| name.clone())
+                        })
+                        .unwrap_or_default();
+                    let send_as_label = ui.label("Send as:");
+                    egui::ComboBox::from_id_source("mail_merge_account")
+                        .selected_text(selected_text)
+                        .show_ui(ui, |ui| {
+                            for (key, name) in &account_options {
+                                ui.selectable_value(
+                                    &mut state.selected_account,
+                                    Some(key.clone()),
+                                    name,
+                                );
+                            }
+                        })
+                        .response
+                        .labelled_by(send_as_label.id);
+
+                    match progress {
+                        Some((done, total)) => {
+                            ui.add(
+                                egui::ProgressBar::new(done as f32 / total as f32)
+                                    .desired_width(160.0)
+                                    .text(format!("{} / {}", done, total)),
+                            );
+                            if ui.button("Cancel").clicked() {
+                                cancel = true;
+                            }
+                        }
+                        None => {
+                            let can_send = state.selected_account.is_some()
+                                && !recipient_names.is_empty()
+                                && !state.subject.trim().is_empty();
+                            if ui
+                                .add_enabled(
+                                    can_send,
+                                    egui::Button::new(
+                                        RichText::new(format!(
+                                            "Send {} messages",
+                                            recipient_names.len()
+                                        ))
+                                        .color(Color32::WHITE),
+                                    )
+                                    .fill(style::ACCENT)
+                                    .rounding(6.0),
+                                )
+                                .clicked()
+                            {
+                                start = true;
+                            }
+                            let (sent, failed) = last_result;
+                            if sent > 0 || failed > 0 {
+                                ui.label(
+                                    RichText::new(format!(
+                                        "Last merge: {} sent, {} failed",
+                                        sent, failed
+                                    ))
+                                    .small()
+                                    .color(style::TEXT_MUTED),
+                                );
+                            }
+                        }
+                    }
+                });
+                if progress.is_none() && !near_cap.is_empty() {
+                    let relays = match near_cap.len() {
+                        1 => format!("{} is close to its monthly cap", near_cap[0]),
+                        _ => format!("{} are close to their monthly caps", near_cap.join(", ")),
+                    };
+                    ui.label(
+                        RichText::new(format!(
+                            "⚠ {}, and each message is sent to every relay.",
+                            relays
+                        ))
+                        .small()
+                        .color(Color32::from_rgb(200, 120, 0)),
+                    );
+                }
+            });
+
+        if start {
+            let messages = merge(state, &recipient_names);
+            if let Some(keys) = state.selected_account.clone() {
+                app.mail_merge
+                    .start(keys, messages, app.preferences.wrap_fuzz());
+            }
+        }
+        if cancel {
+            app.mail_merge.cancel();
+        }
+
+        if let Some(group) = group_to_load {
+            load_group(app, group);
+        }
+        if let Some(name) = group_to_save {
+            let recipients = app
+                .state
+                .mail_merge
+                .as_ref()
+                .map(|state| state.recipients.clone())
+                .unwrap_or_default();
+            match app.db.save_contact_group(&name, &recipients) {
+                Ok(id) => {
+                    reload_groups(app);
+                    if let Some(state) = app.state.mail_merge.as_mut() {
+                        state.group = Some(id);
+                        state.new_group_name.clear();
+                    }
+                }
+                Err(e) => error!("Failed to save contact group {}: {}", name, e),
+            }
+        }
+        if let Some(id) = group_to_delete {
+            if let Err(e) = app.db.delete_contact_group(id) {
+                error!("Failed to delete contact group: {}", e);
+            }
+            reload_groups(app);
+            load_group(app, None);
+        }
+
+        // Closing the window doesn't stop a merge that is already sending.
+        if !open {
+            app.state.mail_merge = None;
+        }
+    }
+}
+
+fn merge(state: &MailMergeState, recipients: &[(String, String)]) -> Vec<MergedMessage> {
+    recipients
+        .iter()
+        .filter_map(|(pubkey, name)| {
+            let recipient = PublicKey::parse(pubkey).ok()?;
+            let npub = recipient.to_bech32().ok()?;
+            let lookup = |field: &str| match field {
+                "name" => Some(name.clone()),
+                "npub" => Some(npub.clone()),
+                _ => None,
+            };
+            Some(MergedMessage {
+                recipient,
+                subject: mail_merge::render(&state.subject, lookup),
+                content: mail_merge::render(&state.body, lookup),
+            })
+        })
+        .collect()
+}
+
+fn recipient_name(app: &Hoot, pubkey: &str) -> String {
+    app.resolve_name(pubkey)
+        .unwrap_or_else(|| crate::name_resolver::short_npub(pubkey))
+}
+
+fn all_contacts(app: &Hoot) -> Vec<String> {
+    app.contacts_manager
+        .get_contacts()
+        .iter()
+        .map(|c| c.pubkey.clone())
+        .collect()
+}
+
+fn reload_groups(app: &mut Hoot) {
+    let groups = match app.db.get_contact_groups() {
+        Ok(groups) => groups,
+        Err(e) => {
+            error!("Failed to load contact groups: {}", e);
+            return;
+        }
+    };
+    if let Some(state) = app.state.mail_merge.as_mut() {
+        state.groups = groups;
+    }
+}
+
+fn load_group(app: &mut Hoot, group: Option<i64>) {
+    let recipients = match group {
+        None => all_contacts(app),
+        Some(id) => match app.db.get_contact_group_members(id) {
+            Ok(members) => members,
+            Err(e) => {
+                error!("Failed to load contact group: {}", e);
+                return;
+            }
+        },
+    };
+    if let Some(state) = app.state.mail_merge.as_mut() {
+        state.group = group;
+        state.recipients = recipients;
+        state.preview_index = 0;
+    }
+}
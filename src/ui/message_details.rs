@@ -0,0 +1,341 @@
+use crate::mail_event::{MailMessage, MAIL_EVENT_KIND};
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText, Ui};
+use hoot_core::db::WrapCopy;
+use hoot_core::encryption::Encryption;
+use nostr::{JsonUtil, UnsignedEvent};
+use tracing::error;
+
+/// Everything we know about where a message came from, like "Show original" elsewhere: its
+/// ids, kinds, timestamps, recipients, the relays that delivered it and what we checked.
+pub fn message_details(app: &Hoot, ui: &mut Ui, ev: &MailMessage) {
+    let Some(event_id) = ev.id.map(|id| id.to_hex()) else {
+        return;
+    };
+    // A stored message never changes, so it's read once when the menu first opens.
+    let raw_id = egui::Id::new(("message_details", &event_id));
+    let raw = ui
+        .data(|d| d.get_temp::<Option<String>>(raw_id))
+        .unwrap_or_else(|| {
+            let raw = app.db.get_event_raw(&event_id).unwrap_or_else(|e| {
+                error!("Failed to load {}: {}", event_id, e);
+                None
+            });
+            ui.data_mut(|d| d.insert_temp(raw_id, raw.clone()));
+            raw
+        });
+    let (wraps, sources) = match &app.thread_snapshot {
+        Some(snapshot) => (snapshot.wraps(&event_id), snapshot.sources(&event_id)),
+        None => (&[][..], &[][..]),
+    };
+    let sent = wraps.iter().any(|wrap| wrap.sent);
+    let received = wraps.iter().any(|wrap| !wrap.sent);
+    let clock_24h = app.preferences.clock_24h;
+
+    egui::Grid::new(format!("message_details-{}", event_id))
+        .num_columns(2)
+        .spacing([8.0, 4.0])
+        .show(ui, |ui| {
+            label(ui, "Message ID");
+            ui.label(RichText::new(&event_id).monospace().small());
+            ui.end_row();
+
+            label(ui, "Kind");
+            ui.label(if wraps.is_empty() {
+                format!("{} (mail)", MAIL_EVENT_KIND)
+            } else {
+                format!(
+                    "{} (mail), sealed (13) in gift wraps (1059)",
+                    MAIL_EVENT_KIND
+                )
+            });
+            ui.end_row();
+
+            // The sender picks created_at, so it can differ from when it actually arrived.
+            label(ui, "Written");
+            ui.label(
+                ev.created_at
+                    .map(|created_at| style::format_full_timestamp(created_at, clock_24h))
+                    .unwrap_or_default(),
+            );
+            ui.end_row();
+            label(ui, "Received");
+            match sources.iter().map(|(_, seen_at)| *seen_at).min() {
+                Some(seen_at) => ui.label(style::format_full_timestamp(seen_at, clock_24h)),
+                None if sent => ui.label("Sent from here"),
+                None => ui.label(RichText::new("Unknown").color(style::TEXT_MUTED)),
+            };
+            ui.end_row();
+
+            if let Some(author) = ev.author {
+                label(ui, "From");
+                pubkey_label(app, ui, &author.to_hex());
+                ui.end_row();
+            }
+            for (role, pubkey) in recipients(raw.as_deref()) {
+                label(ui, role);
+                pubkey_label(app, ui, &pubkey);
+                ui.end_row();
+            }
+            for address in &ev.email_to {
+                label(ui, "To");
+                ui.label(address);
+                ui.end_row();
+            }
+
+            if !wraps.is_empty() {
+                label(ui, "Gift wraps");
+                ui.vertical(|ui| {
+                    for wrap in wraps {
+                        let recipient = wrap
+                            .recipient
+                            .as_deref()
+                            .map(|pubkey| app.resolve_name(pubkey).unwrap_or(pubkey.to_string()));
+                        let mut text = match (wrap.sent, recipient) {
+                            (true, Some(recipient)) => {
+                                format!("{} · sent to {}", wrap.wrap_id, recipient)
+                            }
+                            (false, Some(recipient)) => {
+                                format!("{} · for {}", wrap.wrap_id, recipient)
+                            }
+                            (_, None) => wrap.wrap_id.clone(),
+                        };
+                        if let Some(created_at) = wrap.created_at {
+                            text.push_str(&format!(
+                                " · dated {}",
+                                style::format_full_timestamp(created_at, clock_24h)
+                            ));
+                        }
+                        ui.label(RichText::new(text).monospace().small())
+                            .on_hover_text(
+                                "Gift wraps are dated a random time back, so this isn't \
+                                 when the message was sent",
+                            );
+                    }
+                });
+                ui.end_row();
+            }
+
+            label(ui, "Encryption");
+            ui.label(Protection::of(app, ev, wraps).title());
+            ui.end_row();
+
+            label(ui, "Relays");
+            ui.vertical(|ui| {
+                if sources.is_empty() {
+                    ui.label(RichText::new("No relay recorded").color(style::TEXT_MUTED));
+                }
+                for (url, seen_at) in sources {
+                    ui.label(format!(
+                        "{} · {}",
+                        url,
+                        style::format_full_timestamp(*seen_at, clock_24h)
+                    ));
+                }
+            });
+            ui.end_row();
+
+            label(ui, "Verification");
+            ui.vertical(|ui| {
+                match raw.as_deref().map(UnsignedEvent::from_json) {
+                    Some(Ok(rumor)) if rumor.verify_id().is_ok() => {
+                        check(ui, true, "ID matches the content")
+                    }
+                    Some(Ok(_)) => check(ui, false, "ID doesn't match the content"),
+                    _ => check(ui, false, "Couldn't read the stored message"),
+                }
+                if sent {
+                    check(ui, true, "Written and sent from here");
+                } else if received {
+                    check(ui, true, "Seal signed by the sender, checked on arrival");
+                } else {
+                    check(
+                        ui,
+                        false,
+                        "No gift wrap on record, so the sender is unverified",
+                    );
+                }
+                if app.only_distrusted(sources.iter().map(|(url, _)| url.as_str())) {
+                    check(ui, false, "Only delivered by relays you distrust");
+                }
+                if let Some(address) = app.bridge.email_sender(ev) {
+                    check(
+                        ui,
+                        false,
+                        &format!(
+                            "Came in through the email bridge, {} is unverified",
+                            address
+                        ),
+                    );
+                }
+            });
+            ui.end_row();
+        });
+}
+
+/// How a message was protected on its way here, as a badge that explains itself when clicked.
+pub fn encryption_badge(app: &Hoot, ui: &mut Ui, ev: &MailMessage) {
+    let Some(event_id) = ev.id.map(|id| id.to_hex()) else {
+        return;
+    };
+    let wraps = app
+        .thread_snapshot
+        .as_ref()
+        .map_or(&[][..], |snapshot| snapshot.wraps(&event_id));
+    let protection = Protection::of(app, ev, wraps);
+    ui.menu_button(protection.badge(), |ui| {
+        ui.set_max_width(320.0);
+        ui.label(RichText::new(protection.title()).strong());
+        ui.label(protection.explainer());
+    });
+}
+
+/// A warning on mail only relays the user distrusts delivered. It passed the extra checks for
+/// those, but nothing trusted vouches for it.
+pub fn provenance_warning(app: &Hoot, ui: &mut Ui, ev: &MailMessage) {
+    let Some(event_id) = ev.id.map(|id| id.to_hex()) else {
+        return;
+    };
+    let sources = app
+        .thread_snapshot
+        .as_ref()
+        .map_or(&[][..], |snapshot| snapshot.sources(&event_id));
+    if app.only_distrusted(sources.iter().map(|(url, _)| url.as_str())) {
+        ui.label(RichText::new("⚠ Distrusted relay").color(ui.visuals().warn_fg_color))
+            .on_hover_text(
+                "Only relays you marked as distrusted delivered this message. Its seal is \
+                 signed by the sender, but be careful with what it asks of you.",
+            );
+    }
+}
+
+enum Protection {
+    /// Stored as it was published, without a gift wrap.
+    NotWrapped,
+    /// Gift wrapped with this version of NIP-44, the only scheme gift wraps we can open use.
+    GiftWrapped(u8),
+    /// Came in through the email bridge, whatever carried it from there.
+    Bridge,
+    /// Gift wrapped before we kept track of the scheme.
+    Unknown,
+}
+
+impl Protection {
+    fn of(app: &Hoot, ev: &MailMessage, wraps: &[WrapCopy]) -> Self {
+        if app.bridge.email_sender(ev).is_some() {
+            return Protection::Bridge;
+        }
+        if wraps.is_empty() {
+            return Protection::NotWrapped;
+        }
+        if let Some(version) = wraps.iter().find_map(|wrap| match wrap.encryption {
+            Some(Encryption::Nip44(version)) => Some(version),
+            _ => None,
+        }) {
+            return Protection::GiftWrapped(version);
+        }
+        // What we send is always gift wrapped with NIP-44 v2.
+        if wraps.iter().any(|wrap| wrap.sent) {
+            return Protection::GiftWrapped(2);
+        }
+        Protection::Unknown
+    }
+
+    fn badge(&self) -> String {
+        match self {
+            Protection::NotWrapped => "🔓 Not encrypted".to_string(),
+            Protection::GiftWrapped(version) => format!("🔒 {}", Encryption::Nip44(*version)),
+            Protection::Bridge => "✉ Email".to_string(),
+            Protection::Unknown => "🔒 Gift wrapped".to_string(),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Protection::NotWrapped => "Not encrypted".to_string(),
+            Protection::GiftWrapped(version) => {
+                format!("Gift wrapped with {}", Encryption::Nip44(*version))
+            }
+            Protection::Bridge => "Regular email through the bridge".to_string(),
+            Protection::Unknown => "Gift wrapped".to_string(),
+        }
+    }
+
+    fn explainer(&self) -> &'static str {
+        match self {
+            Protection::NotWrapped => {
+                "This message wasn't gift wrapped, so anyone reading the relays it was \
+                 published to can read it too."
+            }
+            Protection::GiftWrapped(version) if Encryption::Nip44(*version).is_current() => {
+                "Sealed and gift wrapped (NIP-59) with NIP-44 v2 encryption. Only the sender \
+                 and the recipients can read it, and relays can't tell who it's from or what \
+                 it's about."
+            }
+            Protection::GiftWrapped(_) => {
+                "Gift wrapped with a version of NIP-44 encryption Hoot doesn't know yet."
+            }
+            Protection::Bridge => {
+                "It's encrypted between the email gateway and you, but before that it travelled \
+                 as regular email, which the gateway and the mail servers along the way can \
+                 read."
+            }
+            Protection::Unknown => {
+                "Arrived gift wrapped before Hoot kept track of which encryption gift wraps \
+                 used."
+            }
+        }
+    }
+}
+
+fn label(ui: &mut Ui, text: &str) {
+    ui.label(RichText::new(text).color(style::TEXT_MUTED));
+}
+
+fn pubkey_label(app: &Hoot, ui: &mut Ui, pubkey: &str) {
+    ui.horizontal(|ui| {
+        if let Some(name) = app.resolve_name(pubkey) {
+            ui.label(name);
+        }
+        if app.contacts_manager.is_verified(pubkey) {
+            ui.label(style::VERIFIED_BADGE)
+                .on_hover_text("Verified: answered a challenge with this key");
+        }
+        ui.label(RichText::new(pubkey).monospace().small());
+    });
+}
+
+fn check(ui: &mut Ui, passed: bool, text: &str) {
+    let (mark, color) = if passed {
+        ("✔", Color32::DARK_GREEN)
+    } else {
+        ("⚠", ui.visuals().warn_fg_color)
+    };
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(mark).color(color));
+        ui.label(text);
+    });
+}
+
+/// The recipients in the stored message's `p` tags with their role, "To" or "Cc".
+fn recipients(raw: Option<&str>) -> Vec<(&'static str, String)> {
+    let Some(rumor) = raw.and_then(|raw| UnsignedEvent::from_json(raw).ok()) else {
+        return Vec::new();
+    };
+    rumor
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, pubkey, rest @ ..] if name == "p" => {
+                let role = if rest.iter().any(|value| value == "cc") {
+                    "Cc"
+                } else {
+                    "To"
+                };
+                Some((role, pubkey.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
@@ -1,6 +1,31 @@
 pub mod add_account_window;
+pub mod articles;
+pub mod attachments;
+pub mod code_view;
 pub mod compose_window;
+pub mod contact_picker;
 pub mod contacts;
+pub mod delete_dialog;
+pub mod detached;
+pub mod frame_overlay;
+pub mod hover_preview;
+pub mod html_view;
+pub mod invite_card;
+pub mod labels;
+pub mod mail_merge_window;
+pub mod message_details;
+pub mod notifications;
 pub mod onboarding;
+pub mod payload_card;
+pub mod quick_reply;
+pub mod quote;
+pub mod report_dialog;
 pub mod settings;
+pub mod thread_notes;
+pub mod thread_subject;
+pub mod thread_window;
+pub mod toasts;
+pub mod translation;
 pub mod unlock_database;
+pub mod verification;
+pub mod zap;
@@ -0,0 +1,321 @@
+//! The Notifications page: what happened while the user was looking elsewhere, like messages
+//! no relay took, mail from people who aren't contacts yet, relays asking us to sign in and
+//! subjects that mention one of our accounts.
+
+use crate::db::{Db, Notification, NotificationKind};
+use crate::style;
+use crate::{Hoot, Page};
+use chrono::Timelike;
+use eframe::egui::{self, RichText};
+use hoot_core::notification_rules::{NotificationRules, Verdict};
+use nostr::{TagKind, Timestamp, ToBech32, UnsignedEvent};
+use tracing::{error, info};
+
+/// How many notifications the page shows.
+const LIMIT: usize = 200;
+/// Mail older than this doesn't notify, so catching up on old mail doesn't flood the page.
+const RECENT_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Default)]
+pub struct Notifications {
+    list: Vec<Notification>,
+    unread: usize,
+    pub rules: NotificationRules,
+}
+
+impl Notifications {
+    /// Loads the notifications and the rules for new ones once the database is open.
+    pub fn load(&mut self, db: &Db) {
+        match NotificationRules::load(db) {
+            Ok(rules) => self.rules = rules,
+            Err(e) => error!("Failed to load notification rules: {}", e),
+        }
+        self.refresh(db);
+    }
+
+    pub fn refresh(&mut self, db: &Db) {
+        match db.get_notifications(LIMIT) {
+            Ok(list) => self.list = list,
+            Err(e) => error!("Failed to load notifications: {}", e),
+        }
+        match db.get_unread_notification_count() {
+            Ok(unread) => self.unread = unread,
+            Err(e) => error!("Failed to count unread notifications: {}", e),
+        }
+    }
+
+    pub fn unread(&self) -> usize {
+        self.unread
+    }
+
+    /// Notifies about `key`, unless that was done before.
+    pub fn notify(
+        &mut self,
+        db: &Db,
+        kind: NotificationKind,
+        key: &str,
+        summary: &str,
+        target: &str,
+    ) {
+        self.add(db, kind, key, summary, target, false);
+    }
+
+    fn add(
+        &mut self,
+        db: &Db,
+        kind: NotificationKind,
+        key: &str,
+        summary: &str,
+        target: &str,
+        quiet: bool,
+    ) -> bool {
+        match db.add_notification(kind, key, summary, target, quiet) {
+            Ok(true) => {
+                info!("Notification: {}", summary);
+                self.refresh(db);
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                error!("Failed to add a notification: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Notifies that no relay took the message `message_id`.
+    pub fn delivery_failed(&mut self, db: &Db, message_id: &str, subject: &str) {
+        let subject = if subject.trim().is_empty() {
+            "(No Subject)"
+        } else {
+            subject
+        };
+        self.notify(
+            db,
+            NotificationKind::DeliveryFailed,
+            message_id,
+            &format!("“{}” wasn't delivered", subject),
+            message_id,
+        );
+    }
+}
+
+/// Notifies about recent mail from someone who isn't a contact yet, and about mail with one of
+/// our npubs in its subject. When the user picked labels to be notified about, only mail in a
+/// thread with one of them notifies, and every such message does. The rules for `account`, the
+/// one the mail came to, and for the thread's labels go first, and every message one of them
+/// lets through notifies.
+pub fn check_mail(app: &mut Hoot, rumor: &UnsignedEvent, rumor_id: &str, account: Option<&str>) {
+    let ours = &app.account_manager.loaded_keys;
+    if ours.iter().any(|keys| keys.public_key() == rumor.pubkey) {
+        return;
+    }
+    let age = Timestamp::now()
+        .as_u64()
+        .saturating_sub(rumor.created_at.as_u64());
+    if age > RECENT_SECS {
+        return;
+    }
+
+    let author = rumor.pubkey.to_hex();
+    let subject = rumor
+        .tags
+        .find(TagKind::Subject)
+        .and_then(|tag| tag.content())
+        .unwrap_or_default()
+        .to_string();
+    let mentioned = ours.iter().any(|keys| {
+        keys.public_key()
+            .to_bech32()
+            .is_ok_and(|npub| subject.contains(&npub))
+    });
+
+    let name = app.display_name(&author);
+    let labels =
+        if app.preferences.notify_labels.is_empty() && !app.notifications.rules.has_label_rules() {
+            Vec::new()
+        } else {
+            // A reply carries the labels of the messages it answers, so those are the thread's.
+            let parents: Vec<String> = rumor
+                .tags
+                .filter(TagKind::e())
+                .filter_map(|tag| tag.content())
+                .map(str::to_string)
+                .collect();
+            match app.db.get_labels(&parents) {
+                Ok(labels) => labels,
+                Err(e) => {
+                    error!("Failed to load the labels of {}: {}", rumor_id, e);
+                    Vec::new()
+                }
+            }
+        };
+    let contact = app.contacts_manager.find_contact(&author).is_some();
+    let hour = chrono::Local::now().hour() as u8;
+    let verdict = app
+        .notifications
+        .rules
+        .verdict(account, &labels, contact, hour);
+    let quiet = match verdict {
+        None | Some(Verdict::Notify) => false,
+        Some(Verdict::Quiet) => true,
+        Some(Verdict::Skip) => return,
+    };
+    let mut told = false;
+
+    let notifications = &mut app.notifications;
+    if !app.preferences.notify_labels.is_empty() {
+        let Some(label) = labels
+            .into_iter()
+            .find(|label| app.preferences.notify_labels.contains(label))
+        else {
+            return;
+        };
+        told |= notifications.add(
+            &app.db,
+            NotificationKind::Labeled,
+            rumor_id,
+            &format!("{} wrote in “{}”, labeled {}", name, subject, label),
+            rumor_id,
+            quiet,
+        );
+    }
+    if !contact {
+        // One per sender: it's them that's new, not each of their messages.
+        told |= notifications.add(
+            &app.db,
+            NotificationKind::ContactRequest,
+            &author,
+            &format!("New message from {}, who isn't in your contacts", name),
+            rumor_id,
+            quiet,
+        );
+    }
+    if mentioned {
+        told |= notifications.add(
+            &app.db,
+            NotificationKind::Mention,
+            rumor_id,
+            &format!("{} mentioned you in “{}”", name, subject),
+            rumor_id,
+            quiet,
+        );
+    }
+    if verdict.is_some() && !told {
+        notifications.add(
+            &app.db,
+            NotificationKind::Mail,
+            rumor_id,
+            &format!("{} wrote “{}”", name, subject),
+            rumor_id,
+            quiet,
+        );
+    }
+}
+
+/// Notifies that the relay at `url` asked us to sign in (NIP-42).
+pub fn relay_auth(app: &mut Hoot, url: &str) {
+    app.notifications.notify(
+        &app.db,
+        NotificationKind::RelayAuth,
+        url,
+        &format!(
+            "{} asked Hoot to sign in, which Hoot can't do yet. It may refuse some mail.",
+            url
+        ),
+        url,
+    );
+}
+
+pub fn render_notifications_page(app: &mut Hoot, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.heading("Notifications");
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if ui.button("Clear read").clicked() {
+                if let Err(e) = app.db.clear_read_notifications() {
+                    error!("Failed to clear read notifications: {}", e);
+                }
+                app.notifications.refresh(&app.db);
+            }
+            if ui
+                .add_enabled(
+                    app.notifications.unread > 0,
+                    egui::Button::new("Mark all read"),
+                )
+                .clicked()
+            {
+                if let Err(e) = app.db.mark_all_notifications_read() {
+                    error!("Failed to mark notifications read: {}", e);
+                }
+                app.notifications.refresh(&app.db);
+            }
+        });
+    });
+    ui.add_space(8.0);
+
+    if app.notifications.list.is_empty() {
+        ui.label(RichText::new("Nothing new.").color(style::TEXT_MUTED));
+        return;
+    }
+
+    let clock_24h = app.preferences.clock_24h;
+    let mut to_open = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for notification in &app.notifications.list {
+            let icon = match notification.kind {
+                NotificationKind::DeliveryFailed => "⚠",
+                NotificationKind::ContactRequest => "👤",
+                NotificationKind::RelayAuth => "🔑",
+                NotificationKind::Mention => "@",
+                NotificationKind::Labeled => "🏷",
+                NotificationKind::Mail => "✉",
+            };
+            let mut text = RichText::new(format!("{} {}", icon, notification.summary));
+            if !notification.read {
+                text = text.strong();
+            }
+            ui.horizontal(|ui| {
+                let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    style::timestamp_label(ui, notification.created_at, clock_24h);
+                });
+                if response
+                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                    .clicked()
+                {
+                    to_open = Some(notification.clone());
+                }
+            });
+            ui.separator();
+        }
+    });
+
+    if let Some(notification) = to_open {
+        open(app, &notification);
+    }
+}
+
+/// Marks `notification` read and goes to what it's about.
+fn open(app: &mut Hoot, notification: &Notification) {
+    if !notification.read {
+        if let Err(e) = app.db.mark_notification_read(notification.id) {
+            error!(
+                "Failed to mark notification {} read: {}",
+                notification.id, e
+            );
+        }
+        app.notifications.refresh(&app.db);
+    }
+    match notification.kind {
+        NotificationKind::RelayAuth => app.page = Page::Settings,
+        NotificationKind::DeliveryFailed
+        | NotificationKind::ContactRequest
+        | NotificationKind::Mention
+        | NotificationKind::Labeled
+        | NotificationKind::Mail => {
+            app.focused_post = notification.target.clone();
+            app.show_trashed_post = false;
+            app.page = Page::Post;
+        }
+    }
+}
@@ -1,6 +1,8 @@
+use crate::client_import::{self, ClientImport};
 use crate::profile_metadata::{
     get_profile_metadata, update_logged_in_profile_metadata, ProfileMetadata, ProfileOption,
 };
+use crate::ui::add_account_window::AddAccountWindow;
 use crate::{Hoot, Page};
 use eframe::egui;
 use nostr::key::Keys;
@@ -11,6 +13,8 @@ use tracing::{debug, error, info, warn};
 pub enum AccountCreationMode {
     Generate,
     Import,
+    /// Take the key and relays from another client's export file.
+    ImportFromClient,
 }
 
 pub struct OnboardingState {
@@ -26,6 +30,21 @@ pub struct OnboardingState {
     pub metadata_fetched: bool,
     pub publish_metadata: bool,
     pub error_string: String,
+    /// Export files of other clients found in the usual places, looked up once.
+    pub client_exports: Option<Vec<std::path::PathBuf>>,
+    pub client_export_path: String,
+    pub client_import: Option<ClientImport>,
+    /// Mouse movements over the entropy pad, mixed into the new key.
+    pub entropy: Vec<u8>,
+    /// The latest points of the pointer's trail over the pad, newest last.
+    pub entropy_trail: Vec<egui::Pos2>,
+    /// Backup words of the generated key, until the user has written them down.
+    pub mnemonic: Option<String>,
+    pub mnemonic_saved: bool,
+    /// The last secret checked and what came of it, since deriving keys from backup words is
+    /// too slow to do every frame. Onboarding always takes the first account, so the input is
+    /// all it depends on.
+    validated: Option<(String, Result<Keys, String>)>,
 }
 
 impl Default for OnboardingState {
@@ -43,6 +62,14 @@ impl Default for OnboardingState {
             metadata_fetched: false,
             publish_metadata: true,
             error_string: String::new(),
+            client_exports: None,
+            client_export_path: String::new(),
+            client_import: None,
+            entropy: Vec::new(),
+            entropy_trail: Vec::new(),
+            mnemonic: None,
+            mnemonic_saved: false,
+            validated: None,
         }
     }
 }
@@ -163,10 +190,18 @@ impl OnboardingScreen {
             return;
         }
 
+        if app.state.onboarding.mode == Some(AccountCreationMode::ImportFromClient)
+            && app.state.onboarding.imported_key.is_none()
+        {
+            Self::render_client_import_step(app, ui);
+            return;
+        }
+
         if app.state.onboarding.mode == Some(AccountCreationMode::Generate)
             && app.state.onboarding.generated_keys.is_none()
         {
-            app.state.onboarding.generated_keys = Some(Keys::generate());
+            Self::render_generate_step(app, ui);
+            return;
         }
 
         Self::render_metadata_step(app, ui);
@@ -320,6 +355,20 @@ impl OnboardingScreen {
             },
         );
 
+        ui.add_space(15.0);
+
+        Self::option_card(
+            ui,
+            "Import From Another Client",
+            "Bring your key and relays over from nos2x or Amethyst",
+            "Import From Client",
+            card_button_width,
+            || {
+                app.state.onboarding.mode = Some(AccountCreationMode::ImportFromClient);
+                app.state.onboarding.error_string.clear();
+            },
+        );
+
         ui.add_space(30.0);
 
         if ui.button("← Back").clicked() {
@@ -329,6 +378,156 @@ impl OnboardingScreen {
         }
     }
 
+    // ── Step: Generate a key and its backup words ───────────────────────
+
+    fn render_generate_step(app: &mut Hoot, ui: &mut egui::Ui) {
+        Self::page_header(
+            ui,
+            "Generate Your Key",
+            "Your key is made from 12 backup words that can restore it anywhere",
+        );
+        Self::show_error(ui, &app.state.onboarding.error_string);
+
+        let Some(words) = app.state.onboarding.mnemonic.clone() else {
+            Self::entropy_pad(app, ui);
+            return;
+        };
+
+        ui.label("Write these words down, in order, and keep them somewhere safe:");
+        ui.add_space(10.0);
+        egui::Grid::new("onboarding_mnemonic")
+            .num_columns(4)
+            .spacing([20.0, 8.0])
+            .show(ui, |ui| {
+                for (i, word) in words.split(' ').enumerate() {
+                    ui.label(egui::RichText::new(format!("{:>2}. {}", i + 1, word)).monospace());
+                    if i % 4 == 3 {
+                        ui.end_row();
+                    }
+                }
+            });
+        ui.add_space(10.0);
+        ui.label(
+            egui::RichText::new(
+                "Anyone with these words can read your mail and write as you. Hoot won't \
+                 show them again.",
+            )
+            .color(ui.visuals().weak_text_color()),
+        );
+        ui.add_space(10.0);
+        ui.checkbox(
+            &mut app.state.onboarding.mnemonic_saved,
+            "I've written the words down",
+        );
+        ui.add_space(20.0);
+
+        ui.horizontal(|ui| {
+            if ui.button("← Back").clicked() {
+                app.state.onboarding.mnemonic = None;
+                app.state.onboarding.mnemonic_saved = false;
+                app.state.onboarding.error_string.clear();
+            }
+            let saved = app.state.onboarding.mnemonic_saved;
+            if ui
+                .add_enabled(saved, egui::Button::new("Continue →"))
+                .clicked()
+            {
+                match crate::account_manager::keys_from_mnemonic(&words, 0) {
+                    Ok(keys) => {
+                        app.state.onboarding.generated_keys = Some(keys);
+                        app.state.onboarding.mnemonic = None;
+                        app.state.onboarding.error_string.clear();
+                    }
+                    Err(e) => app.state.onboarding.error_string = format!("{:#}", e),
+                }
+            }
+        });
+    }
+
+    /// A pad to move the mouse over, each move adding to the randomness of the new key. The
+    /// system's randomness is enough on its own, so it's up to the user how long they play.
+    fn entropy_pad(app: &mut Hoot, ui: &mut egui::Ui) {
+        const ENOUGH: usize = 512;
+        const TRAIL: usize = 48;
+
+        ui.label("Move your mouse around the box to add your own randomness to the key.");
+        ui.add_space(10.0);
+        let (rect, response) =
+            ui.allocate_exact_size(egui::vec2(400.0, 160.0), egui::Sense::hover());
+        let state = &mut app.state.onboarding;
+        if let Some(pos) = response.hover_pos() {
+            if state.entropy_trail.last() != Some(&pos) {
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|since| since.subsec_nanos())
+                    .unwrap_or_default();
+                state.entropy.extend_from_slice(&pos.x.to_le_bytes());
+                state.entropy.extend_from_slice(&pos.y.to_le_bytes());
+                state.entropy.extend_from_slice(&nanos.to_le_bytes());
+                state.entropy_trail.push(pos);
+                if state.entropy_trail.len() > TRAIL {
+                    state.entropy_trail.remove(0);
+                }
+            }
+        }
+
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 6.0, visuals.extreme_bg_color);
+        painter.rect_stroke(rect, 6.0, visuals.widgets.noninteractive.bg_stroke);
+        for (i, pos) in state.entropy_trail.iter().enumerate() {
+            let age = (i + 1) as f32 / state.entropy_trail.len() as f32;
+            painter.circle_filled(
+                *pos,
+                2.0 + 4.0 * age,
+                crate::style::ACCENT.gamma_multiply(age),
+            );
+        }
+
+        // Twelve bytes per sample.
+        let samples = state.entropy.len() / 12;
+        ui.add_space(5.0);
+        ui.add(
+            egui::ProgressBar::new((samples as f32 / ENOUGH as f32).min(1.0))
+                .desired_width(400.0)
+                .text(if samples >= ENOUGH {
+                    "Plenty of randomness".to_string()
+                } else {
+                    format!("{} moves", samples)
+                }),
+        );
+        ui.add_space(5.0);
+        ui.label(
+            egui::RichText::new(
+                "This is optional. Your computer's own randomness already makes a safe key, \
+                 your moves are mixed in on top so you don't have to trust it alone.",
+            )
+            .color(ui.visuals().weak_text_color()),
+        );
+        ui.add_space(20.0);
+
+        ui.horizontal(|ui| {
+            if ui.button("← Back").clicked() {
+                app.state.onboarding.mode = None;
+                app.state.onboarding.entropy.clear();
+                app.state.onboarding.entropy_trail.clear();
+                app.state.onboarding.error_string.clear();
+            }
+            if ui.button("Generate Key →").clicked() {
+                let state = &mut app.state.onboarding;
+                match crate::account_manager::new_mnemonic(&state.entropy) {
+                    Ok(words) => {
+                        state.mnemonic = Some(words);
+                        state.entropy.clear();
+                        state.entropy_trail.clear();
+                        state.error_string.clear();
+                    }
+                    Err(e) => state.error_string = format!("{:#}", e),
+                }
+            }
+        });
+    }
+
     fn option_card(
         ui: &mut egui::Ui,
         title: &str,
@@ -370,11 +569,11 @@ impl OnboardingScreen {
         Self::page_header(
             ui,
             "Import Private Key",
-            "Enter your Nostr private key (nsec) to import your identity",
+            "Enter your Nostr private key (nsec) or backup words to import your identity",
         );
         Self::show_error(ui, &app.state.onboarding.error_string);
 
-        ui.label("Private Key (nsec):");
+        ui.label("Private Key (nsec) or backup words:");
         ui.add_space(5.0);
         ui.add(
             egui::TextEdit::singleline(&mut app.state.onboarding.nsec_input)
@@ -384,10 +583,18 @@ impl OnboardingScreen {
         );
         ui.add_space(5.0);
 
-        let validation = Self::validate_nsec(&app.state.onboarding.nsec_input);
+        let onboarding = &mut app.state.onboarding;
+        let validation = match &onboarding.validated {
+            Some((input, result)) if *input == onboarding.nsec_input => result.clone(),
+            _ => {
+                let result = Self::validate_secret(&onboarding.nsec_input);
+                onboarding.validated = Some((onboarding.nsec_input.clone(), result.clone()));
+                result
+            }
+        };
         match &validation {
             Ok(_) => {
-                ui.colored_label(egui::Color32::GREEN, "Valid nsec format");
+                ui.colored_label(egui::Color32::GREEN, "Valid private key");
             }
             Err(e) if !app.state.onboarding.nsec_input.is_empty() => {
                 ui.colored_label(egui::Color32::RED, e.as_str());
@@ -411,6 +618,113 @@ impl OnboardingScreen {
         });
     }
 
+    // ── Step: Import from another client's export ──────────────────────
+
+    fn render_client_import_step(app: &mut Hoot, ui: &mut egui::Ui) {
+        Self::page_header(
+            ui,
+            "Import From Another Client",
+            "Pick the export file of the client you've been using",
+        );
+        Self::show_error(ui, &app.state.onboarding.error_string);
+
+        let exports = app
+            .state
+            .onboarding
+            .client_exports
+            .get_or_insert_with(client_import::find_exports)
+            .clone();
+        let mut to_read: Option<std::path::PathBuf> = None;
+        if !exports.is_empty() {
+            ui.label("Found on this computer:");
+            for path in &exports {
+                if ui.link(path.display().to_string()).clicked() {
+                    to_read = Some(path.clone());
+                }
+            }
+            ui.add_space(10.0);
+        }
+
+        ui.label("Export file:");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut app.state.onboarding.client_export_path)
+                    .hint_text("/path/to/nos2x-export.json")
+                    .desired_width(330.0),
+            );
+            if ui.button("Read").clicked() {
+                to_read = Some(app.state.onboarding.client_export_path.trim().into());
+            }
+        });
+
+        if let Some(path) = to_read {
+            app.state.onboarding.client_export_path = path.display().to_string();
+            match client_import::read_export(&path) {
+                Ok(import) => {
+                    app.state.onboarding.client_import = Some(import);
+                    app.state.onboarding.error_string.clear();
+                }
+                Err(e) => {
+                    app.state.onboarding.client_import = None;
+                    app.state.onboarding.error_string = format!("{:#}", e);
+                }
+            }
+        }
+        ui.add_space(10.0);
+
+        if let Some(import) = &app.state.onboarding.client_import {
+            let npub = import
+                .keys
+                .public_key()
+                .to_bech32()
+                .unwrap_or_else(|_| import.keys.public_key().to_string());
+            ui.label(format!("{} export with the key {}", import.source, npub));
+            if import.relays.is_empty() {
+                ui.label(
+                    egui::RichText::new("No relays in this file, Hoot's defaults will be used.")
+                        .color(ui.visuals().weak_text_color()),
+                );
+            } else {
+                ui.label(format!("Relays to add ({}):", import.relays.len()));
+                for relay in &import.relays {
+                    ui.label(egui::RichText::new(relay).monospace());
+                }
+            }
+        }
+        ui.add_space(20.0);
+
+        ui.horizontal(|ui| {
+            if ui.button("← Back").clicked() {
+                app.state.onboarding.mode = None;
+                app.state.onboarding.client_import = None;
+                app.state.onboarding.error_string.clear();
+            }
+            if ui
+                .add_enabled(
+                    app.state.onboarding.client_import.is_some(),
+                    egui::Button::new("Continue →"),
+                )
+                .clicked()
+            {
+                if let Some(import) = app.state.onboarding.client_import.take() {
+                    let known = app.relays.urls();
+                    for url in import.relays {
+                        if known.contains(&url) {
+                            continue;
+                        }
+                        let ctx = ui.ctx().clone();
+                        if let Err(e) = app.relays.add_url(url.clone(), move || {
+                            ctx.request_repaint();
+                        }) {
+                            warn!("Couldn't add imported relay {}: {}", url, e);
+                        }
+                    }
+                    Self::handle_import(app, import.keys);
+                }
+            }
+        });
+    }
+
     fn handle_import(app: &mut Hoot, keys: Keys) {
         let already_exists = app
             .account_manager
@@ -424,6 +738,7 @@ impl OnboardingScreen {
         }
 
         let pubkey_str = keys.public_key().to_string();
+        app.start_account_preflight(&keys.public_key());
         app.state.onboarding.imported_key = Some(keys);
         app.state.onboarding.error_string.clear();
 
@@ -476,6 +791,9 @@ impl OnboardingScreen {
                 ui.label(pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_string()));
             });
         });
+        if app.state.onboarding.imported_key.is_some() {
+            AddAccountWindow::preflight_report(app, ui, &pubkey);
+        }
         ui.add_space(15.0);
 
         ui.label("Display Name:");
@@ -515,8 +833,10 @@ impl OnboardingScreen {
                     Some(AccountCreationMode::Generate) => {
                         app.state.onboarding.mode = None;
                         app.state.onboarding.generated_keys = None;
+                        app.state.onboarding.mnemonic_saved = false;
                     }
-                    Some(AccountCreationMode::Import) => {
+                    Some(AccountCreationMode::Import)
+                    | Some(AccountCreationMode::ImportFromClient) => {
                         app.state.onboarding.imported_key = None;
                     }
                     None => {
@@ -627,6 +947,14 @@ impl OnboardingScreen {
         crate::account_manager::validate_nsec(input)
     }
 
+    /// An nsec, or backup words restoring the first account derived from them.
+    fn validate_secret(input: &str) -> Result<Keys, String> {
+        if crate::account_manager::looks_like_mnemonic(input) {
+            return crate::account_manager::validate_mnemonic(input, 0);
+        }
+        Self::validate_nsec(input)
+    }
+
     fn save_account(app: &mut Hoot) -> bool {
         let key = match app.state.onboarding.active_keys() {
             Some(k) => k.clone(),
@@ -643,6 +971,7 @@ impl OnboardingScreen {
         }
 
         app.active_account = Some(key.clone());
+        app.relays.end_preflight(&key.public_key().to_hex());
 
         if app.state.onboarding.publish_metadata {
             Self::publish_metadata(app, key.public_key());
@@ -659,6 +988,7 @@ impl OnboardingScreen {
             display_name: non_empty(&s.display_name),
             name: non_empty(&s.name),
             picture: non_empty(&s.picture_url),
+            lud16: None,
         };
 
         if metadata.display_name.is_none() && metadata.name.is_none() && metadata.picture.is_none()
@@ -0,0 +1,231 @@
+use crate::bolt11;
+use crate::payload::{Bill, Payload, INVOICE};
+use crate::payments::Purpose;
+use crate::style;
+use crate::ui::code_view;
+use crate::Hoot;
+use eframe::egui::{self, Color32, Frame, Margin, RichText, Stroke, Ui};
+use nostr::EventId;
+
+/// Shows the data for apps a message carries: invoices and receipts as a card with their
+/// items, and anything else as the JSON it is.
+pub fn payload_card(app: &mut Hoot, ui: &mut Ui, event_id: EventId, payload: &Payload) {
+    Frame::none()
+        .fill(style::CARD_BG)
+        .stroke(Stroke::new(1.0, style::CARD_STROKE))
+        .inner_margin(Margin::same(12.0))
+        .rounding(8.0)
+        .show(ui, |ui| match payload.bill() {
+            Some(bill) => bill_card(app, ui, event_id, &payload.schema, &bill),
+            None => {
+                ui.label(RichText::new(format!("🗂 Data for apps: {}", payload.schema)).strong());
+                let json = serde_json::to_string_pretty(&payload.data).unwrap_or_default();
+                egui::CollapsingHeader::new("Show the data")
+                    .id_source(("payload", event_id))
+                    .show(ui, |ui| code_view::code_block(ui, "json", &json));
+            }
+        });
+}
+
+fn bill_card(app: &mut Hoot, ui: &mut Ui, event_id: EventId, schema: &str, bill: &Bill) {
+    let invoice = schema == INVOICE;
+    let title = match (invoice, &bill.number) {
+        (true, Some(number)) => format!("🧾 Invoice {}", number),
+        (true, None) => "🧾 Invoice".to_string(),
+        (false, Some(number)) => format!("🧾 Receipt {}", number),
+        (false, None) => "🧾 Receipt".to_string(),
+    };
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(title).strong());
+        if let Some(issuer) = &bill.issuer {
+            ui.label(RichText::new(format!("from {}", issuer)).color(style::TEXT_MUTED));
+        }
+    });
+    ui.add_space(4.0);
+
+    egui::Grid::new(("bill", event_id))
+        .num_columns(3)
+        .spacing([16.0, 4.0])
+        .show(ui, |ui| {
+            for item in &bill.items {
+                ui.label(&item.description);
+                if item.quantity == 1.0 {
+                    ui.label("");
+                } else {
+                    ui.label(
+                        RichText::new(format!(
+                            "{} × {}",
+                            item.quantity,
+                            bill.format_amount(item.price)
+                        ))
+                        .color(style::TEXT_MUTED),
+                    );
+                }
+                ui.label(bill.format_amount(item.amount()));
+                ui.end_row();
+            }
+            ui.label(RichText::new("Total").strong());
+            ui.label("");
+            ui.label(RichText::new(bill.format_amount(bill.total())).strong());
+            ui.end_row();
+        });
+
+    let date = if invoice {
+        bill.due.as_ref().map(|due| format!("Due {}", due))
+    } else {
+        bill.paid.as_ref().map(|paid| format!("Paid {}", paid))
+    };
+    if let Some(date) = date {
+        ui.label(RichText::new(date).color(style::TEXT_MUTED));
+    }
+
+    let Some(lightning) = bill.lightning().filter(|_| invoice) else {
+        return;
+    };
+    let message = event_id.to_hex();
+    // Checking the signature each frame adds up, so remember what the invoice says.
+    let decoded_id = egui::Id::new(("bolt11", event_id));
+    let decoded = ui
+        .data(|d| d.get_temp::<Result<bolt11::Invoice, String>>(decoded_id))
+        .unwrap_or_else(|| {
+            let decoded = bolt11::Invoice::decode(lightning).map_err(|e| e.to_string());
+            ui.data_mut(|d| d.insert_temp(decoded_id, decoded.clone()));
+            decoded
+        });
+    // What to pay in millisatoshis, once the invoice is known to ask for the bill's total.
+    let checked = decoded
+        .as_ref()
+        .map_err(|e| e.to_string())
+        .and_then(|decoded| {
+            bill.check_invoice(decoded)
+                .map(|msats| (decoded, msats))
+                .map_err(|e| e.to_string())
+        });
+
+    ui.add_space(4.0);
+    match &decoded {
+        Ok(decoded) => {
+            let amount = decoded.msats.map_or("any amount".to_string(), |msats| {
+                format!("{} sats", msats as f64 / 1000.0)
+            });
+            let mut text = format!(
+                "⚡ Lightning invoice for {} to node {}",
+                amount,
+                short_key(&decoded.payee)
+            );
+            if let Some(description) = decoded.description.as_ref().filter(|d| !d.is_empty()) {
+                text.push_str(&format!(", \"{}\"", description));
+            }
+            ui.label(RichText::new(text).color(style::TEXT_MUTED))
+                .on_hover_text(&decoded.payee);
+        }
+        Err(e) => {
+            ui.colored_label(
+                Color32::RED,
+                format!("⚠ Can't read the Lightning invoice: {}", e),
+            );
+        }
+    }
+    if let (Ok(_), Err(e)) = (&decoded, &checked) {
+        ui.colored_label(Color32::RED, format!("⚠ Hoot won't pay it. {}", e));
+    }
+
+    let paid = app.paid_invoices.contains_key(&message)
+        || app.paid_invoices.values().any(|paid| paid == lightning);
+    ui.horizontal(|ui| {
+        if paid {
+            ui.label(RichText::new("✔ Paid").color(style::ACCENT));
+        } else if app.payments.paying_invoice(&message) {
+            ui.add(egui::Spinner::new().size(12.0))
+                .on_hover_text("Paying from your wallet");
+        } else {
+            let reason = if !app.payments.is_connected() {
+                Some("Connect a wallet in Settings to pay from Hoot.")
+            } else if checked.is_err() {
+                Some("Only invoices that ask for the bill's total can be paid from Hoot.")
+            } else {
+                None
+            };
+            let button = ui
+                .add_enabled(reason.is_none(), egui::Button::new("⚡ Pay"))
+                .on_disabled_hover_text(reason.unwrap_or_default());
+            if button.clicked() {
+                app.state.confirm_invoice = Some(message.clone());
+            }
+        }
+        if ui.button("Copy Lightning invoice").clicked() {
+            ui.ctx().copy_text(lightning.to_string());
+        }
+    });
+
+    if app.state.confirm_invoice.as_ref() == Some(&message) {
+        match checked {
+            Ok((decoded, msats)) if !paid => {
+                confirm_payment(app, ui, bill, &message, lightning, decoded, msats)
+            }
+            _ => app.state.confirm_invoice = None,
+        }
+    }
+}
+
+/// Asks before paying `lightning` from the wallet, saying what it costs and who it pays.
+fn confirm_payment(
+    app: &mut Hoot,
+    ui: &mut Ui,
+    bill: &Bill,
+    message: &str,
+    lightning: &str,
+    decoded: &bolt11::Invoice,
+    msats: u64,
+) {
+    let mut confirmed = false;
+    let mut cancelled = false;
+    egui::Window::new("Pay this invoice?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ui.ctx(), |ui| {
+            ui.label(format!(
+                "Pay {} sats to node {} from your wallet?",
+                msats as f64 / 1000.0,
+                short_key(&decoded.payee)
+            ));
+            if let Some(issuer) = &bill.issuer {
+                ui.label(
+                    RichText::new(format!(
+                        "The invoice says it's from {}. Only pay it if you expected it.",
+                        issuer
+                    ))
+                    .color(style::TEXT_MUTED),
+                );
+            }
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                if ui.button("Cancel").clicked() {
+                    cancelled = true;
+                }
+                if ui.button("⚡ Pay").clicked() {
+                    confirmed = true;
+                }
+            });
+        });
+
+    if confirmed {
+        let purpose = Purpose::Invoice {
+            message: message.to_string(),
+            invoice: lightning.to_string(),
+        };
+        app.payments.pay(lightning, purpose);
+    }
+    if confirmed || cancelled {
+        app.state.confirm_invoice = None;
+    }
+}
+
+/// The start and end of a hex key, enough to tell it apart.
+pub fn short_key(key: &str) -> String {
+    match (key.get(..8), key.get(key.len().saturating_sub(8)..)) {
+        (Some(start), Some(end)) if key.len() > 16 => format!("{}…{}", start, end),
+        _ => key.to_string(),
+    }
+}
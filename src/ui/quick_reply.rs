@@ -0,0 +1,171 @@
+//! A box pinned under an open thread for answering it in a line or two without opening a
+//! compose window. The reply goes out the way a compose window's would, with the same choice of
+//! account, and can be moved into one when it grows.
+
+use crate::bridge;
+use crate::mail_event::MailMessage;
+use crate::style;
+use crate::ui::compose_window::{self, ComposeWindow, ComposeWindowState};
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText};
+
+#[derive(Debug, Clone, Default)]
+pub struct QuickReply {
+    pub state: ComposeWindowState,
+    /// Answer everyone on the latest message rather than just its sender.
+    pub reply_all: bool,
+}
+
+/// Settles the quick replies waiting on relays, whether or not their thread is still open.
+/// Called every frame.
+pub fn settle(app: &mut Hoot, ctx: &egui::Context) {
+    let sending: Vec<String> = app
+        .state
+        .quick_replies
+        .iter()
+        .filter(|(_, reply)| reply.state.sending.is_some())
+        .map(|(root_id, _)| root_id.clone())
+        .collect();
+    for root_id in sending {
+        let Some(mut reply) = app.state.quick_replies.remove(&root_id) else {
+            continue;
+        };
+        if compose_window::settle_send(app, ctx, &mut reply.state) {
+            compose_window::finish_send(app, reply.state);
+        } else {
+            app.state.quick_replies.insert(root_id, reply);
+        }
+    }
+}
+
+/// The reply box for the open thread, answering `latest`, its newest message. Has to come
+/// before the thread's messages so they fill the space left above it.
+pub fn show(app: &mut Hoot, ui: &mut egui::Ui, latest: &MailMessage) {
+    let Some(event_id) = latest.id else {
+        return;
+    };
+    let root_id = app.focused_post.clone();
+    let id = egui::Id::new(("quick_reply", &root_id));
+    let (reply_to, reply_all) = crate::reply_fields(app, latest);
+    let (account_options, account_colors) = compose_window::account_options(app);
+
+    let mut reply = app.state.quick_replies.remove(&root_id).unwrap_or_default();
+    // The reply follows the thread as new messages come in, until it's sent.
+    if reply.state.sending.is_none() {
+        let mut parent_events = latest.parent_events.clone().unwrap_or_default();
+        parent_events.push(event_id);
+        reply.state.parent_events = parent_events;
+        reply.state.subject = format!("Re: {}", latest.subject);
+        reply.state.to_field = if reply.reply_all {
+            reply_all.clone()
+        } else {
+            reply_to.clone()
+        };
+    }
+    let account = reply
+        .state
+        .selected_account
+        .clone()
+        .or_else(|| compose_window::default_account(app, &reply.state.to_field));
+    let recipients = bridge::parse_recipients(&reply.state.to_field);
+    let names: Vec<String> = recipients
+        .pubkeys
+        .iter()
+        .map(|pubkey| app.display_name(&pubkey.to_hex()))
+        .chain(recipients.emails)
+        .collect();
+    let sending = reply.state.sending.is_some();
+
+    let mut send = false;
+    let mut expand = false;
+    egui::TopBottomPanel::bottom(id)
+        .frame(egui::Frame::none().inner_margin(egui::Margin::symmetric(0.0, 8.0)))
+        .show_separator_line(true)
+        .show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Reply to").color(style::TEXT_MUTED));
+                ui.label(RichText::new(names.join(", ")).strong());
+                if reply_all != reply_to {
+                    ui.add_enabled_ui(!sending, |ui| {
+                        ui.checkbox(&mut reply.reply_all, "Everyone");
+                    });
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.add_enabled_ui(!sending, |ui| {
+                        let picker = compose_window::account_picker(
+                            ui,
+                            id.with("from"),
+                            &account_options,
+                            &account_colors,
+                            account.as_ref(),
+                            &mut reply.state.selected_account,
+                        );
+                        let as_label = ui.label(RichText::new("As").color(style::TEXT_MUTED));
+                        picker.labelled_by(as_label.id);
+                    });
+                });
+            });
+
+            let input = ui.add_enabled(
+                !sending,
+                egui::TextEdit::multiline(&mut reply.state.content)
+                    .hint_text("Write a quick reply…")
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+            input.widget_info(|| {
+                egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Quick reply")
+            });
+            let shortcut = input.has_focus()
+                && ui.input(|i| i.modifiers.command && i.key_pressed(egui::Key::Enter));
+
+            if let Some(error) = &reply.state.send_error {
+                ui.colored_label(Color32::RED, format!("⚠ {}", error));
+            }
+            ui.horizontal(|ui| {
+                let empty = reply.state.content.trim().is_empty();
+                if reply.state.confirming_recipients {
+                    ui.label(RichText::new("Is this right?").color(style::TEXT_MUTED));
+                    if ui.button("Send").clicked() {
+                        reply.state.confirming_recipients = false;
+                        send = true;
+                    }
+                    if ui.button("Back").clicked() {
+                        reply.state.confirming_recipients = false;
+                    }
+                } else {
+                    let label = if sending { "Sending…" } else { "Send" };
+                    let button = ui
+                        .add_enabled(
+                            !sending && !empty && account.is_some(),
+                            egui::Button::new(RichText::new(label).color(Color32::WHITE))
+                                .fill(style::ACCENT)
+                                .rounding(6.0),
+                        )
+                        .on_hover_text("Ctrl+Enter");
+                    if (button.clicked() || (shortcut && !empty)) && account.is_some() {
+                        if app.preferences.confirm_recipients {
+                            reply.state.confirming_recipients = true;
+                        } else {
+                            send = true;
+                        }
+                    }
+                }
+                let full = ui
+                    .add_enabled(!sending, egui::Button::new("⤢ Open in a window"))
+                    .on_hover_text("Keep writing with attachments and formatting");
+                expand = full.clicked();
+            });
+        });
+
+    if send {
+        if let Some(keys) = &account {
+            compose_window::send(app, &mut reply.state, keys);
+        }
+    }
+    if expand {
+        ComposeWindow::open(app, reply.state);
+        return;
+    }
+    app.state.quick_replies.insert(root_id, reply);
+}
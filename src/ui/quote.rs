@@ -0,0 +1,129 @@
+//! Replying to part of a message: text selected in a message is what Reply quotes, and the
+//! reply names that passage so hovering it highlights the passage in the message it answers.
+
+use crate::mail_event::{Fragment, MailMessage};
+use crate::style;
+use crate::ui::code_view::{self, Segment};
+use crate::Hoot;
+use eframe::egui::{self, text::LayoutJob, Color32, TextFormat, Ui};
+use std::ops::Range;
+
+/// `text` as quoted lines to start a reply with.
+pub fn quote_lines(text: &str) -> String {
+    let mut quoted: String = text
+        .trim()
+        .lines()
+        .map(|line| format!("> {}\n", line))
+        .collect();
+    quoted.push('\n');
+    quoted
+}
+
+/// The body of `message`, as text that can be selected for [`quote_lines`], with the passage
+/// quoted by the reply under the pointer highlighted. Code and patches in it get their own
+/// views, between the selectable stretches of prose.
+pub fn message_body(app: &mut Hoot, ui: &mut Ui, message: &MailMessage) {
+    let Some(event_id) = message.id else {
+        ui.label(&message.content);
+        return;
+    };
+    let highlight = app
+        .state
+        .hovered_quote
+        .as_ref()
+        .filter(|quote| quote.event_id == event_id)
+        .and_then(|quote| quote.byte_range(&message.content));
+
+    let mut focused = false;
+    for (n, segment) in code_view::segments(&message.content)
+        .into_iter()
+        .enumerate()
+    {
+        match segment {
+            Segment::Text { text, offset } => {
+                let trimmed = text.trim_start_matches(['\n', '\r']);
+                let offset = offset + text.len() - trimmed.len();
+                let text = trimmed.trim_end();
+                let chars_before = message.content[..offset].chars().count();
+                // Where the highlighted passage falls in this stretch, if it does.
+                let highlight = highlight
+                    .clone()
+                    .map(|range| {
+                        range.start.clamp(offset, offset + text.len()) - offset
+                            ..range.end.clamp(offset, offset + text.len()) - offset
+                    })
+                    .filter(|range| !range.is_empty());
+                let output = selectable_text(ui, (event_id, n), text, highlight);
+                if output.response.has_focus() {
+                    focused = true;
+                    app.state.selection = output
+                        .cursor_range
+                        .map(|range| range.as_sorted_char_range())
+                        .filter(|range| !range.is_empty())
+                        .map(|range| Fragment {
+                            event_id,
+                            start: chars_before + range.start,
+                            end: chars_before + range.end,
+                        });
+                }
+            }
+            Segment::Code { language, code } => code_view::code_block(ui, language, code),
+            Segment::Patch(patch) => {
+                let id = egui::Id::new(("patch", event_id, n));
+                code_view::patch_view(app, ui, id, &message.subject, patch);
+            }
+        }
+    }
+
+    if !focused
+        && app
+            .state
+            .selection
+            .as_ref()
+            .is_some_and(|selection| selection.event_id == event_id)
+        // Pressing Reply takes the focus away, so hold on until the click is over.
+        && !ui.input(|i| i.pointer.any_down())
+    {
+        app.state.selection = None;
+    }
+}
+
+fn selectable_text(
+    ui: &mut Ui,
+    id: impl std::hash::Hash,
+    text: &str,
+    highlight: Option<Range<usize>>,
+) -> egui::text_edit::TextEditOutput {
+    let mut layouter = |ui: &Ui, text: &str, wrap_width: f32| {
+        let format = TextFormat {
+            font_id: egui::TextStyle::Body.resolve(ui.style()),
+            color: ui.visuals().text_color(),
+            ..Default::default()
+        };
+        let mut job = LayoutJob::default();
+        match highlight.clone().filter(|range| range.end <= text.len()) {
+            Some(range) => {
+                job.append(&text[..range.start], 0.0, format.clone());
+                let marked = TextFormat {
+                    background: style::ACCENT_LIGHT,
+                    color: Color32::BLACK,
+                    ..format.clone()
+                };
+                job.append(&text[range.clone()], 0.0, marked);
+                job.append(&text[range.end..], 0.0, format);
+            }
+            None => job.append(text, 0.0, format),
+        }
+        job.wrap.max_width = wrap_width;
+        ui.fonts(|fonts| fonts.layout_job(job))
+    };
+
+    let mut text = text;
+    egui::TextEdit::multiline(&mut text)
+        .id_source(("message_body", id))
+        .frame(false)
+        .desired_rows(1)
+        .desired_width(f32::INFINITY)
+        .layouter(&mut layouter)
+        .show(ui)
+}
@@ -0,0 +1,147 @@
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText};
+use nostr::nips::nip56::Report;
+use tracing::{error, info};
+
+const REPORT_TYPES: [(Report, &str); 7] = [
+    (Report::Spam, "Spam"),
+    (Report::Impersonation, "Impersonation"),
+    (Report::Illegal, "Illegal content"),
+    (Report::Malware, "Malware"),
+    (Report::Nudity, "Nudity"),
+    (Report::Profanity, "Profanity"),
+    (Report::Other, "Other"),
+];
+
+pub struct ReportDialogState {
+    pub pubkey: String,
+    pub report_type: Report,
+    pub comment: String,
+    pub also_block: bool,
+    pub error: Option<String>,
+}
+
+pub struct ReportDialog {}
+
+impl ReportDialog {
+    pub fn open(app: &mut Hoot, pubkey: String) {
+        app.state.report_dialog = Some(ReportDialogState {
+            pubkey,
+            report_type: Report::Spam,
+            comment: String::new(),
+            also_block: true,
+            error: None,
+        });
+    }
+
+    pub fn show_window(app: &mut Hoot, ctx: &egui::Context) {
+        let name = match app.state.report_dialog.as_ref() {
+            Some(state) => app
+                .resolve_name(&state.pubkey)
+                .unwrap_or_else(|| state.pubkey.clone()),
+            None => return,
+        };
+        let Some(state) = app.state.report_dialog.as_mut() else {
+            return;
+        };
+
+        let mut submitted = false;
+        let mut cancelled = false;
+        egui::Window::new("Report sender")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("Report {} to your relays?", name));
+                ui.add_space(4.0);
+
+                egui::ComboBox::from_label("Reason")
+                    .selected_text(
+                        REPORT_TYPES
+                            .iter()
+                            .find(|(report, _)| *report == state.report_type)
+                            .map(|(_, label)| *label)
+                            .unwrap_or_default(),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (report, label) in REPORT_TYPES {
+                            ui.selectable_value(&mut state.report_type, report, label);
+                        }
+                    });
+
+                ui.add(
+                    egui::TextEdit::multiline(&mut state.comment)
+                        .hint_text("Additional details (optional)")
+                        .desired_rows(3),
+                );
+                ui.checkbox(&mut state.also_block, "Also block this sender");
+                ui.small("Reports are public and signed by your account.");
+
+                if let Some(e) = &state.error {
+                    ui.label(RichText::new(e).color(Color32::RED));
+                }
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                    if ui.button("Report").clicked() {
+                        submitted = true;
+                    }
+                });
+            });
+
+        if cancelled {
+            app.state.report_dialog = None;
+            return;
+        }
+        if !submitted {
+            return;
+        }
+
+        let Some(state) = app.state.report_dialog.take() else {
+            return;
+        };
+        if let Err(e) = publish_report(app, &state) {
+            error!("Failed to publish report: {}", e);
+            app.state.report_dialog = Some(ReportDialogState {
+                error: Some(e.to_string()),
+                ..state
+            });
+            return;
+        }
+        info!("Reported {} as {}", state.pubkey, state.report_type);
+
+        if state.also_block {
+            app.block_pubkey(&state.pubkey);
+        }
+    }
+}
+
+/// Publishes a NIP-56 report about `state.pubkey`. Only the pubkey is tagged, since the
+/// message itself was private and its id means nothing to anyone else.
+fn publish_report(app: &mut Hoot, state: &ReportDialogState) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let keys = app
+        .active_account
+        .clone()
+        .or_else(|| app.account_manager.loaded_keys.first().cloned())
+        .context("No account to sign the report with")?;
+    let reported = nostr::PublicKey::parse(&state.pubkey)?;
+
+    let tag = nostr::Tag::from_standardized(nostr::TagStandard::PublicKeyReport(
+        reported,
+        state.report_type,
+    ));
+    let event = nostr::EventBuilder::report([tag], state.comment.trim())
+        .custom_created_at(crate::clock::now())
+        .sign_with_keys(&keys)?;
+    let payload = serde_json::to_string(&crate::relay::ClientMessage::Event { event })?;
+    app.relays
+        .send(ewebsock::WsMessage::Text(payload))
+        .map_err(|e| anyhow::anyhow!("Could not send report: {}", e))?;
+
+    Ok(())
+}
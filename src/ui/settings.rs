@@ -1,13 +1,48 @@
 use crate::{
+    automation::{self, Action},
+    payments::Purpose,
+    preferences::StartupPage,
     profile_metadata::{ProfileMetadata, ProfileOption},
-    Hoot,
+    relay::{RelayTrust, SyncWindow},
+    translate, Hoot,
 };
 use eframe::egui::{self, Color32, Direction, Layout, Sense, Ui, Vec2};
 use egui_tabs::Tabs;
+use hoot_core::notification_rules::{
+    NotificationRule, NotificationRules, NotifyMode, QuietHours, RuleScope,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
 use tracing::{error, info};
 
+/// The choices for how far back to read mail from a relay.
+const SYNC_WINDOWS: [SyncWindow; 5] = [
+    SyncWindow::FullHistory,
+    SyncWindow::LastDays(7),
+    SyncWindow::LastDays(30),
+    SyncWindow::LastDays(90),
+    SyncWindow::LastDays(365),
+];
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// The cap a relay gets when the user first asks to be warned about its traffic.
+const DEFAULT_CAP: u64 = 1024 * MEGABYTE;
+
+const NOTIFY_MODES: [NotifyMode; 3] = [
+    NotifyMode::Always,
+    NotifyMode::ContactsOnly,
+    NotifyMode::Never,
+];
+
+const RELAY_TRUSTS: [RelayTrust; 3] = [
+    RelayTrust::Trusted,
+    RelayTrust::OwnMailOnly,
+    RelayTrust::Distrusted,
+];
+
 #[derive(Debug, Default)]
 pub struct ProfileMetadataEditingStatus {
     display_name: String,
@@ -20,12 +55,67 @@ pub struct SettingsState {
     pub editing_display_name: bool,
     pub new_display_name: String,
     pub metadata_state: HashMap<String, RefCell<ProfileMetadataEditingStatus>>,
+    pub high_contrast: bool,
+    pub trace_relay: Option<String>,
+    pub trace_export_status: Option<String>,
+    pub metrics_export_status: Option<String>,
+    /// How the last database repair went.
+    pub integrity_status: Option<String>,
+    pub download_dir_input: Option<String>,
+    /// Log levels being typed in, applied when saved.
+    pub log_filter_input: Option<String>,
+    pub log_filter_error: Option<String>,
+    pub upload_server_input: Option<String>,
+    /// Relay waiting for the user to confirm its removal.
+    pub confirm_remove_relay: Option<String>,
+    /// Relay bundle whose relays are waiting for the user to confirm their removal.
+    pub confirm_remove_preset: Option<String>,
+    /// Wider sync window for a relay near its cap, waiting for the user to confirm it.
+    pub confirm_window: Option<(String, SyncWindow)>,
+    /// Bridge settings being edited, applied when saved.
+    pub bridge_draft: Option<crate::bridge::BridgeConfig>,
+    pub bridge_error: Option<String>,
+    pub uri_handler_status: Option<String>,
+    /// Event id typed into the debug inspector.
+    pub inspect_event_id: String,
+    /// Wallet connection string being typed in, cleared once connected.
+    pub wallet_uri_input: String,
+    pub wallet_error: Option<String>,
+    /// Invoices pasted from relays' payment pages, by relay url.
+    pub relay_invoices: HashMap<String, String>,
+    /// The QR code shown on the Keys tab, if any.
+    pub key_qr: Option<KeyQr>,
+    /// Passwords for the encrypted key backups by account, cleared once the code is shown.
+    pub backup_passwords: HashMap<String, String>,
+    /// The account whose encrypted backup is being made off the UI thread, and where it lands.
+    pub backup_task: Option<(String, Receiver<anyhow::Result<String>>)>,
+    pub key_qr_error: Option<String>,
+}
+
+/// A key shown as a QR code for another device to scan.
+#[derive(Debug)]
+pub struct KeyQr {
+    /// The account the key is of, in hex.
+    pub pubkey: String,
+    pub title: &'static str,
+    pub text: String,
+    pub qr: crate::qr::Qr,
 }
 
 enum Tab {
     Profile = 0,
     Relays = 1,
     Identity = 2,
+    Appearance = 3,
+    Debug = 4,
+    Diagnostics = 5,
+    Downloads = 6,
+    Blocked = 7,
+    Bridge = 8,
+    Preferences = 9,
+    Data = 10,
+    Wallet = 11,
+    Automation = 12,
 }
 
 impl From<i32> for Tab {
@@ -34,6 +124,16 @@ impl From<i32> for Tab {
             0 => Tab::Profile,
             1 => Tab::Relays,
             2 => Tab::Identity,
+            3 => Tab::Appearance,
+            4 => Tab::Debug,
+            5 => Tab::Diagnostics,
+            6 => Tab::Downloads,
+            7 => Tab::Blocked,
+            8 => Tab::Bridge,
+            9 => Tab::Preferences,
+            10 => Tab::Data,
+            11 => Tab::Wallet,
+            12 => Tab::Automation,
             _ => Tab::Profile, // Default to Profile for invalid values
         }
     }
@@ -49,7 +149,7 @@ pub struct SettingsScreen {}
 
 impl SettingsScreen {
     pub fn ui(app: &mut Hoot, ui: &mut Ui) {
-        let tabs_response = Tabs::new(3)
+        let tabs_response = Tabs::new(13)
             .height(16.0)
             .selected(0)
             .layout(Layout::centered_and_justified(Direction::TopDown))
@@ -60,6 +160,16 @@ impl SettingsScreen {
                     Profile => "My Profile",
                     Relays => "Relays",
                     Identity => "Keys",
+                    Appearance => "Appearance",
+                    Debug => "Debug",
+                    Diagnostics => "Diagnostics",
+                    Downloads => "Attachments",
+                    Blocked => "Blocked",
+                    Bridge => "Email Bridge",
+                    Preferences => "Preferences",
+                    Data => "Data",
+                    Wallet => "Wallet",
+                    Automation => "Automation",
                 };
                 ui.add(egui::Label::new(tab_label).selectable(false));
             });
@@ -70,6 +180,16 @@ impl SettingsScreen {
             Profile => Self::profile(app, ui),
             Relays => Self::relays(app, ui),
             Identity => Self::identity(app, ui),
+            Appearance => Self::appearance(app, ui),
+            Debug => Self::debug(app, ui),
+            Diagnostics => Self::diagnostics(app, ui),
+            Downloads => Self::downloads(app, ui),
+            Blocked => Self::blocked(app, ui),
+            Bridge => Self::bridge(app, ui),
+            Preferences => Self::preferences(app, ui),
+            Data => Self::data(app, ui),
+            Wallet => Self::wallet(app, ui),
+            Automation => Self::automation(app, ui),
         }
     }
 
@@ -188,15 +308,13 @@ impl SettingsScreen {
         ui.heading("Relays");
         ui.small("A relay is a server that Hoot connects with to send & receive messages.");
 
-        ui.label("Add New Relay:");
+        let new_relay_label = ui.label("Add New Relay:");
         ui.horizontal(|ui| {
             let new_relay = &mut app.state.settings.new_relay_url;
-            ui.text_edit_singleline(new_relay);
+            ui.text_edit_singleline(new_relay)
+                .labelled_by(new_relay_label.id);
             if ui.button("Add Relay").clicked() && !new_relay.is_empty() {
-                let ctx = ui.ctx().clone();
-                let wake_up = move || {
-                    ctx.request_repaint();
-                };
+                let wake_up = app.repaint.waker(ui.ctx());
                 app.relays.add_url(new_relay.clone(), wake_up);
                 app.state.settings.new_relay_url = String::new(); // clears field
             }
@@ -207,6 +325,11 @@ impl SettingsScreen {
         ui.label("Your Relays:");
         ui.vertical(|ui| {
             let mut relay_to_remove: Option<String> = None;
+            let mut window_change: Option<(String, SyncWindow)> = None;
+            let mut trust_change: Option<(String, RelayTrust)> = None;
+            let mut cap_change: Option<(String, Option<u64>)> = None;
+            let mut marked_paid: Option<String> = None;
+            let mut wallet_payment: Option<(String, String)> = None;
             let last_ping = app.relays.get_last_reconnect_attempt();
             for (url, relay) in app.relays.relays.iter() {
                 ui.horizontal(|ui| {
@@ -225,6 +348,13 @@ impl SettingsScreen {
                     painter.circle_filled(c, r, conn_fill);
 
                     ui.label(url);
+                    if let Some(rtt) = relay.rtt {
+                        ui.label(
+                            egui::RichText::new(format!("{} ms", rtt.as_millis()))
+                                .small()
+                                .color(crate::style::TEXT_MUTED),
+                        );
+                    }
                     // TODO: this only updates when next frame is rendered, which can be more than
                     // a few seconds between renders. Make it so it updates every second.
                     if relay.status == crate::relay::RelayStatus::Disconnected {
@@ -233,24 +363,464 @@ impl SettingsScreen {
 
                         ui.label(format!("(Attempting reconnect in {} seconds)", next_ping));
                     }
-                    if ui.button("Remove Relay").clicked() {
+                    if app.relay_info.awaiting_payment(url) {
+                        ui.label(
+                            egui::RichText::new("💳 Payment required")
+                                .color(Color32::from_rgb(200, 120, 0)),
+                        )
+                        .on_hover_text("Hoot won't ask it for mail until you've paid");
+                        let page = app
+                            .relay_info
+                            .info(url)
+                            .and_then(|info| info.payment_page());
+                        if let Some(page) = page {
+                            if ui.button("Pay").on_hover_text(page).clicked() {
+                                ui.ctx().open_url(egui::OpenUrl::new_tab(page));
+                            }
+                        }
+                        let paid = ui.button("I've paid");
+                        paid.widget_info(|| {
+                            egui::WidgetInfo::labeled(
+                                egui::WidgetType::Button,
+                                format!("Mark {} as paid", url),
+                            )
+                        });
+                        if paid.clicked() {
+                            marked_paid = Some(url.to_string());
+                        }
+                        if app.payments.paying_relay(url) {
+                            ui.add(egui::Spinner::new().size(12.0))
+                                .on_hover_text("Paying from your wallet");
+                        } else if app.payments.connection().is_some() {
+                            let invoice = app
+                                .state
+                                .settings
+                                .relay_invoices
+                                .entry(url.to_string())
+                                .or_default();
+                            let field = ui.add(
+                                egui::TextEdit::singleline(invoice)
+                                    .hint_text("Invoice (lnbc…)")
+                                    .desired_width(140.0),
+                            );
+                            field.widget_info(|| {
+                                egui::WidgetInfo::labeled(
+                                    egui::WidgetType::TextEdit,
+                                    format!("Invoice from {}", url),
+                                )
+                            });
+                            if ui
+                                .add_enabled(
+                                    !invoice.trim().is_empty(),
+                                    egui::Button::new("⚡ Pay with wallet"),
+                                )
+                                .on_disabled_hover_text("Paste the invoice from the payment page")
+                                .clicked()
+                            {
+                                wallet_payment = Some((url.to_string(), invoice.clone()));
+                            }
+                        }
+                    }
+                    let mut window = app.relays.window(url);
+                    let combo = egui::ComboBox::from_id_source(("sync_window", url))
+                        .selected_text(window.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in SYNC_WINDOWS {
+                                ui.selectable_value(&mut window, option, option.to_string());
+                            }
+                        });
+                    combo.response.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::ComboBox,
+                            format!("Mail to read from {}", url),
+                        )
+                    });
+                    if window != app.relays.window(url) {
+                        window_change = Some((url.to_string(), window));
+                    }
+                    let mut trust = app.relays.trust(url);
+                    let combo = egui::ComboBox::from_id_source(("relay_trust", url))
+                        .selected_text(trust.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in RELAY_TRUSTS {
+                                ui.selectable_value(&mut trust, option, option.to_string());
+                            }
+                        });
+                    combo.response.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::ComboBox,
+                            format!("How far to trust {}", url),
+                        )
+                    });
+                    if trust != app.relays.trust(url) {
+                        trust_change = Some((url.to_string(), trust));
+                    }
+                    let usage = relay.usage.current();
+                    let mut cap = usage.cap;
+                    let mut text = egui::RichText::new(format!(
+                        "📊 {}",
+                        crate::ui::attachments::format_size(usage.total())
+                    ));
+                    if usage.near_cap() {
+                        text = text.color(Color32::from_rgb(200, 120, 0));
+                    }
+                    let menu = ui.menu_button(text, |ui| {
+                        ui.label(format!(
+                            "This month: {} sent, {} received",
+                            crate::ui::attachments::format_size(usage.sent),
+                            crate::ui::attachments::format_size(usage.received)
+                        ));
+                        let mut capped = cap.is_some();
+                        if ui
+                            .checkbox(&mut capped, "Warn me near a monthly cap")
+                            .changed()
+                        {
+                            cap = capped.then_some(DEFAULT_CAP);
+                        }
+                        if let Some(bytes) = &mut cap {
+                            let mut megabytes = *bytes / MEGABYTE;
+                            ui.horizontal(|ui| {
+                                let label = ui.label("Cap");
+                                ui.add(
+                                    egui::DragValue::new(&mut megabytes)
+                                        .clamp_range(1..=1_000_000)
+                                        .suffix(" MB"),
+                                )
+                                .labelled_by(label.id);
+                            });
+                            *bytes = megabytes * MEGABYTE;
+                        }
+                    });
+                    menu.response.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            format!("Traffic with {}", url),
+                        )
+                    });
+                    if cap != usage.cap {
+                        cap_change = Some((url.to_string(), cap));
+                    }
+                    let remove = ui.button("Remove Relay");
+                    remove.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            format!("Remove relay {}", url),
+                        )
+                    });
+                    if remove.clicked() {
                         relay_to_remove = Some(url.to_string());
                     }
                 });
             }
 
-            if relay_to_remove.is_some() {
-                app.relays.remove_url(&relay_to_remove.unwrap());
+            if let Some((url, window)) = window_change {
+                // Reading further back downloads that stretch of history again.
+                let now = nostr::Timestamp::now().as_u64();
+                let wider = window.since(now) < app.relays.window(&url).since(now);
+                if wider && app.relays.usage(&url).near_cap() {
+                    app.state.settings.confirm_window = Some((url, window));
+                } else {
+                    app.relays.set_window(&url, window);
+                }
+            }
+            if let Some((url, trust)) = trust_change {
+                app.relays.set_trust(&url, trust);
+            }
+            if let Some((url, cap)) = cap_change {
+                app.relays.set_cap(&url, cap);
+            }
+            if let Some(url) = marked_paid {
+                app.relay_info.mark_paid(&mut app.relays, &url);
+            }
+            if let Some((url, invoice)) = wallet_payment {
+                app.state.settings.relay_invoices.remove(&url);
+                app.payments.pay(&invoice, Purpose::RelayFee(url));
+            }
+            if let Some(url) = relay_to_remove {
+                let is_last_connected =
+                    app.relays.connected_count() == 1
+                        && app.relays.relays.get(&url).is_some_and(|relay| {
+                            relay.status == crate::relay::RelayStatus::Connected
+                        });
+                if is_last_connected {
+                    app.state.settings.confirm_remove_relay = Some(url);
+                } else {
+                    app.relays.remove_url(&url);
+                }
+            }
+        });
+
+        ui.small("Relays set to recent mail only are asked for messages from that far back.");
+        ui.small(
+            "Relays for your mail only are just asked for messages to you and never get what \
+             you publish. Mail that only distrusted relays deliver is checked more closely and \
+             marked with a warning.",
+        );
+        ui.small(
+            "📊 shows each relay's traffic this month. Give metered relays a cap to be warned \
+             before reading a lot more mail from them or sending a mail merge.",
+        );
+
+        ui.add_space(10.0);
+        let mut ping = app.relays.ping_settings();
+        ui.horizontal(|ui| {
+            let label = ui.label("Ping relays every");
+            ui.add(
+                egui::DragValue::new(&mut ping.interval_secs)
+                    .clamp_range(5..=600)
+                    .suffix(" s"),
+            )
+            .labelled_by(label.id);
+            let label = ui.label("and reconnect when one takes longer than");
+            ui.add(
+                egui::DragValue::new(&mut ping.timeout_secs)
+                    .clamp_range(1..=120)
+                    .suffix(" s"),
+            )
+            .labelled_by(label.id);
+            ui.label("to answer");
+        });
+        if ping != app.relays.ping_settings() {
+            app.relays.set_ping_settings(ping);
+        }
+        ui.small(
+            "Pings find connections that broke without closing, like after changing networks.",
+        );
+
+        ui.add_space(10.0);
+        Self::relay_presets(app, ui);
+
+        Self::confirm_remove_relay(app, ui);
+        Self::confirm_remove_preset(app, ui);
+        Self::confirm_sync_window(app, ui);
+    }
+
+    fn relay_presets(app: &mut Hoot, ui: &mut Ui) {
+        app.relay_presets.refresh_if_stale();
+
+        ui.horizontal(|ui| {
+            ui.label("Relay bundles:");
+            let mut fetch = !app.relay_presets.choices.skip_remote;
+            if ui
+                .checkbox(&mut fetch, "Fetch updated bundles")
+                .on_hover_text("Off keeps the bundles that came with Hoot")
+                .changed()
+            {
+                app.relay_presets.set_skip_remote(!fetch);
+            }
+            if fetch && app.relay_presets.refreshing {
+                ui.spinner();
+            } else if fetch && ui.small_button("Refresh").clicked() {
+                app.relay_presets.refresh();
             }
         });
+        if let Some(error) = &app.relay_presets.error {
+            ui.colored_label(
+                Color32::RED,
+                format!("Couldn't refresh the bundles: {}", error),
+            );
+        }
+
+        let mut add = None;
+        let mut remove = None;
+        for preset in &app.relay_presets.list.presets {
+            let added = preset
+                .relays
+                .iter()
+                .filter(|url| app.relays.relays.contains_key(*url))
+                .count();
+            // Only the relays the bundle added go; ones added by hand stay.
+            let removable = app
+                .relay_presets
+                .added_by(&preset.name)
+                .iter()
+                .filter(|url| app.relays.relays.contains_key(*url))
+                .count();
+            // Taking the bundle away mustn't leave no relays at all.
+            let others = app.relays.relays.len() - removable;
+            ui.horizontal(|ui| {
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new(&preset.name).strong());
+                    ui.small(&preset.description);
+                    ui.small(preset.relays.join(", "));
+                });
+                ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                    if removable > 0
+                        && ui
+                            .add_enabled(others > 0, egui::Button::new("Remove"))
+                            .on_hover_text("Remove the relays this bundle added")
+                            .on_disabled_hover_text("Add another relay first")
+                            .clicked()
+                    {
+                        remove = Some(preset.name.clone());
+                    }
+                    if added < preset.relays.len() && ui.button("Add all").clicked() {
+                        add = Some((preset.name.clone(), preset.relays.clone()));
+                    }
+                });
+            });
+        }
+
+        if let Some((name, urls)) = add {
+            let mut added = Vec::new();
+            for url in urls {
+                if app.relays.relays.contains_key(&url) {
+                    continue;
+                }
+                let wake_up = app.repaint.waker(ui.ctx());
+                match app.relays.add_url(url.clone(), wake_up) {
+                    Ok(_) => added.push(url),
+                    Err(e) => error!("Failed to add relay {}: {}", url, e),
+                }
+            }
+            app.relay_presets.record_added(&name, added);
+        }
+        if remove.is_some() {
+            app.state.settings.confirm_remove_preset = remove;
+        }
+    }
+
+    fn confirm_remove_preset(app: &mut Hoot, ui: &mut Ui) {
+        let Some(name) = app.state.settings.confirm_remove_preset.clone() else {
+            return;
+        };
+        let urls: Vec<String> = app
+            .relay_presets
+            .added_by(&name)
+            .iter()
+            .filter(|url| app.relays.relays.contains_key(*url))
+            .cloned()
+            .collect();
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Remove relays?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!(
+                    "This removes the relays {} added. Mail that only they carry stops arriving.",
+                    name
+                ));
+                for url in &urls {
+                    ui.small(url);
+                }
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                    if ui
+                        .button(egui::RichText::new("Remove").color(Color32::RED))
+                        .clicked()
+                    {
+                        confirmed = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            for url in &urls {
+                app.relays.remove_url(url);
+            }
+            app.relay_presets.forget(&name);
+        }
+        if confirmed || cancelled {
+            app.state.settings.confirm_remove_preset = None;
+        }
+    }
+
+    fn confirm_remove_relay(app: &mut Hoot, ui: &mut Ui) {
+        let Some(url) = app.state.settings.confirm_remove_relay.clone() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Remove relay?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!(
+                    "{} is the only relay you're connected to. Without it you won't be able to send or receive mail.",
+                    url
+                ));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                    if ui
+                        .button(egui::RichText::new("Remove").color(Color32::RED))
+                        .clicked()
+                    {
+                        confirmed = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            app.relays.remove_url(&url);
+        }
+        if confirmed || cancelled {
+            app.state.settings.confirm_remove_relay = None;
+        }
+    }
+
+    fn confirm_sync_window(app: &mut Hoot, ui: &mut Ui) {
+        let Some((url, window)) = app.state.settings.confirm_window.clone() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        let usage = app.relays.usage(&url);
+        egui::Window::new("Read more mail?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ui.ctx(), |ui| {
+                ui.label(format!(
+                    "{} has used {} of its {} cap this month. Reading {} from it downloads \
+                     that mail again.",
+                    url,
+                    crate::ui::attachments::format_size(usage.total()),
+                    crate::ui::attachments::format_size(usage.cap.unwrap_or_default()),
+                    window.to_string().to_lowercase()
+                ));
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                    if ui.button("Read it anyway").clicked() {
+                        confirmed = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            app.relays.set_window(&url, window);
+        }
+        if confirmed || cancelled {
+            app.state.settings.confirm_window = None;
+        }
     }
 
     fn identity(app: &mut Hoot, ui: &mut Ui) {
+        Self::receive_backup(app, ui);
         ui.vertical(|ui| {
             use nostr::ToBech32;
             for key in app.account_manager.loaded_keys.clone() {
+                let npub = key.public_key().to_bech32().unwrap();
+                let pubkey = key.public_key().to_hex();
                 ui.horizontal(|ui| {
-                    ui.label(format!("Key ID: {}", key.public_key().to_bech32().unwrap()));
+                    ui.label(format!("Key ID: {}", npub));
+                    if ui.button("Show QR").clicked() {
+                        Self::show_key_qr(app, pubkey.clone(), "Public key", Ok(npub.clone()));
+                    }
                     if ui.button("Remove Key").clicked() {
                         match app.account_manager.delete_key(&app.db, &key) {
                             Ok(..) => {}
@@ -258,7 +828,1503 @@ impl SettingsScreen {
                         }
                     }
                 });
+                ui.horizontal(|ui| {
+                    let settings = &mut app.state.settings;
+                    let label = ui.label("Backup password:");
+                    let password = settings.backup_passwords.entry(pubkey.clone()).or_default();
+                    ui.add(
+                        egui::TextEdit::singleline(password)
+                            .password(true)
+                            .desired_width(160.0),
+                    )
+                    .labelled_by(label.id);
+                    match &settings.backup_task {
+                        Some((encrypting, _)) if *encrypting == pubkey => {
+                            ui.spinner();
+                        }
+                        busy => {
+                            if ui
+                                .add_enabled(
+                                    busy.is_none(),
+                                    egui::Button::new("Show encrypted key QR"),
+                                )
+                                .clicked()
+                            {
+                                let password = std::mem::take(password);
+                                settings.backup_task =
+                                    Some((pubkey.clone(), Self::encrypt_backup(&key, password)));
+                            }
+                        }
+                    }
+                });
+
+                let settings = &app.state.settings;
+                if let Some(shown) = settings.key_qr.as_ref().filter(|qr| qr.pubkey == pubkey) {
+                    ui.label(egui::RichText::new(shown.title).strong());
+                    shown.qr.show(ui, 240.0);
+                    ui.label(egui::RichText::new(&shown.text).monospace().small());
+                    if ui.button("Hide").clicked() {
+                        app.state.settings.key_qr = None;
+                    }
+                }
+                ui.add_space(10.0);
+            }
+            if let Some(error) = &app.state.settings.key_qr_error {
+                ui.colored_label(Color32::RED, error);
+            }
+            ui.small(
+                "Scan the encrypted key with a mobile signer to use this account there. It \
+                 asks for the backup password before it can use the key.",
+            );
+        });
+    }
+
+    /// Encrypts `key` on another thread, since the key derivation takes a moment.
+    fn encrypt_backup(key: &nostr::Keys, password: String) -> Receiver<anyhow::Result<String>> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let key = key.clone();
+        std::thread::spawn(move || {
+            let backup = crate::account_manager::encrypted_backup(&key, &password);
+            // Settings may have closed while this ran.
+            let _ = sender.send(backup);
+        });
+        receiver
+    }
+
+    fn receive_backup(app: &mut Hoot, ui: &mut Ui) {
+        let Some((pubkey, receiver)) = &app.state.settings.backup_task else {
+            return;
+        };
+        let backup = match receiver.try_recv() {
+            Ok(backup) => backup,
+            Err(TryRecvError::Empty) => {
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("Couldn't encrypt the key")),
+        };
+        let pubkey = pubkey.clone();
+        app.state.settings.backup_task = None;
+        Self::show_key_qr(app, pubkey, "Encrypted private key", backup);
+    }
+
+    fn show_key_qr(
+        app: &mut Hoot,
+        pubkey: String,
+        title: &'static str,
+        text: anyhow::Result<String>,
+    ) {
+        let shown = text.and_then(|text| {
+            let qr = crate::qr::Qr::encode(&text)?;
+            Ok(KeyQr {
+                pubkey,
+                title,
+                text,
+                qr,
+            })
+        });
+        match shown {
+            Ok(shown) => {
+                app.state.settings.key_qr = Some(shown);
+                app.state.settings.key_qr_error = None;
+            }
+            Err(e) => {
+                app.state.settings.key_qr = None;
+                app.state.settings.key_qr_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    fn appearance(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Appearance");
+        if ui
+            .checkbox(&mut app.state.settings.high_contrast, "High contrast theme")
+            .changed()
+        {
+            crate::style::apply_theme(ui.ctx(), app.state.settings.high_contrast);
+        }
+        ui.small("Uses stronger colors and thicker outlines to make controls easier to see.");
+
+        ui.add_space(10.0);
+        ui.heading("Account colors");
+        for key in app.account_manager.loaded_keys.clone() {
+            let pubkey = key.public_key().to_hex();
+            let name = crate::get_key_display_text(app, &key);
+            ui.horizontal(|ui| {
+                let [r, g, b, _] = app.account_color(&pubkey).to_array();
+                let mut rgb = [r, g, b];
+                if ui.color_edit_button_srgb(&mut rgb).changed() {
+                    app.account_colors.set(&pubkey, Some(rgb));
+                }
+                ui.label(&name);
+                if app.account_colors.is_chosen(&pubkey)
+                    && ui
+                        .small_button("Automatic")
+                        .on_hover_text("Go back to the color Hoot picked")
+                        .clicked()
+                {
+                    app.account_colors.set(&pubkey, None);
+                }
+            });
+        }
+        ui.small(
+            "Marks the account you write as in new messages, and whose each conversation is in \
+             the unified inbox.",
+        );
+
+        ui.add_space(10.0);
+        ui.heading("Threads");
+        if ui
+            .checkbox(
+                &mut app.split_threads,
+                "Start a new conversation when a reply changes the subject",
+            )
+            .changed()
+        {
+            app.refresh_table_entries();
+        }
+        ui.small("Otherwise subject changes are marked inside the conversation.");
+    }
+
+    fn preferences(app: &mut Hoot, ui: &mut Ui) {
+        let accounts: Vec<(String, String)> = app
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|keys| {
+                (
+                    keys.public_key().to_hex(),
+                    crate::get_key_display_text(app, keys),
+                )
+            })
+            .collect();
+        let prefs = &mut app.preferences;
+
+        ui.heading("Reading");
+        let mut delay_read = prefs.mark_read_delay_secs > 0;
+        if ui
+            .checkbox(&mut delay_read, "Wait before marking messages as read")
+            .changed()
+        {
+            prefs.mark_read_delay_secs = if delay_read { 5 } else { 0 };
+        }
+        if delay_read {
+            ui.horizontal(|ui| {
+                let label = ui.label("Mark as read after");
+                ui.add(
+                    egui::DragValue::new(&mut prefs.mark_read_delay_secs)
+                        .clamp_range(1..=300)
+                        .suffix(" s"),
+                )
+                .labelled_by(label.id);
+            });
+        }
+        ui.small("Otherwise a thread is marked as read as soon as you open it.");
+        let split_toggled = ui
+            .checkbox(
+                &mut prefs.focused_inbox,
+                "Split the inbox into Focused and Other",
+            )
+            .changed();
+        ui.small(
+            "Mail from contacts and people you write back and forth with goes to Focused. \
+             Moving a message to the other tab moves its sender for good.",
+        );
+
+        ui.add_space(10.0);
+        ui.heading("Starting up");
+        let startup_name = |page: StartupPage| match page {
+            StartupPage::Inbox => "Inbox".to_string(),
+            StartupPage::LastUsed => "The folder I was last in".to_string(),
+            StartupPage::SavedSearch(id) => app
+                .saved_searches
+                .iter()
+                .find(|search| search.id == id)
+                .map_or_else(|| "Inbox".to_string(), |search| search.name.clone()),
+        };
+        ui.horizontal(|ui| {
+            let label = ui.label("Open at startup");
+            let mut choices = vec![StartupPage::Inbox, StartupPage::LastUsed];
+            choices.extend(
+                app.saved_searches
+                    .iter()
+                    .map(|search| StartupPage::SavedSearch(search.id)),
+            );
+            egui::ComboBox::from_id_source("startup_page")
+                .selected_text(startup_name(prefs.startup_page))
+                .show_ui(ui, |ui| {
+                    for choice in choices {
+                        ui.selectable_value(&mut prefs.startup_page, choice, startup_name(choice));
+                    }
+                })
+                .response
+                .labelled_by(label.id);
+        });
+        ui.checkbox(
+            &mut prefs.reopen_compose_windows,
+            "Reopen the messages I was writing when Hoot closed",
+        );
+        ui.small("They're kept as drafts either way.");
+
+        ui.add_space(10.0);
+        ui.heading("Replying");
+        ui.checkbox(
+            &mut prefs.reply_all_by_default,
+            "Reply to everyone on a message by default",
+        );
+        ui.checkbox(
+            &mut prefs.archive_on_reply,
+            "Archive a thread after replying to it",
+        );
+        ui.small("Archived threads come back to the Inbox when someone answers.");
+
+        ui.add_space(10.0);
+        ui.heading("Sending");
+        ui.checkbox(
+            &mut prefs.confirm_empty_subject,
+            "Ask before sending a message without a subject",
+        );
+        ui.checkbox(
+            &mut prefs.confirm_recipients,
+            "Check recipients and the sending account before each message goes out",
+        );
+        ui.small("Catches replying as the wrong account or a mistyped npub while it can be fixed.");
+        if !accounts.is_empty() {
+            ui.label("Keep a copy of what I send for my other devices");
+            for (pubkey, name) in &accounts {
+                let mut on = prefs.copies_to_self(pubkey);
+                if ui.checkbox(&mut on, name).changed() {
+                    prefs.copy_to_self.retain(|account| account != pubkey);
+                    if on {
+                        prefs.copy_to_self.push(pubkey.clone());
+                    }
+                }
+            }
+            ui.small(
+                "Each message is also wrapped for the account itself, so other apps signed in \
+                 with it show it in Sent.",
+            );
+        }
+        ui.horizontal(|ui| {
+            let label = ui.label("Editor for long messages");
+            let hint =
+                crate::external_editor::command("").unwrap_or_else(|| "code --wait".to_string());
+            ui.add(egui::TextEdit::singleline(&mut prefs.external_editor).hint_text(hint))
+                .labelled_by(label.id);
+        });
+        ui.small(
+            "Used by 📝 in the message window. Empty uses $VISUAL or $EDITOR. Hoot has no \
+             terminal to give it, so pick a windowed editor, or start a terminal one in its own \
+             window, like `xterm -e vim`. Quote paths with spaces.",
+        );
+        let pow_name = |bits: u8| match bits {
+            0 => "None".to_string(),
+            bits => format!("Up to {} bits", bits),
+        };
+        let max_pow = prefs.max_pow;
+        ui.horizontal(|ui| {
+            let label = ui.label("Proof of work for relays that want it");
+            egui::ComboBox::from_id_source("max_pow")
+                .selected_text(pow_name(prefs.max_pow))
+                .show_ui(ui, |ui| {
+                    for bits in [0, 8, 16, 20, 24] {
+                        ui.selectable_value(&mut prefs.max_pow, bits, pow_name(bits));
+                    }
+                })
+                .response
+                .labelled_by(label.id);
+        });
+        ui.small(
+            "Some relays only take a message that took work to make (NIP-13). Each bit doubles \
+             the work, and Hoot waits while it's done.",
+        );
+        if prefs.max_pow != max_pow {
+            app.relays.set_max_pow(prefs.max_pow);
+        }
+
+        ui.add_space(10.0);
+        ui.heading("Notifications");
+        let mut labels = match app.db.get_all_labels() {
+            Ok(labels) => labels,
+            Err(e) => {
+                error!("Failed to load labels: {}", e);
+                Vec::new()
+            }
+        };
+        for label in &prefs.notify_labels {
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+        let rule_scopes: Vec<(RuleScope, String)> = accounts
+            .into_iter()
+            .map(|(pubkey, name)| (RuleScope::Account(pubkey), name))
+            .chain(
+                labels
+                    .iter()
+                    .map(|label| (RuleScope::Label(label.clone()), format!("🏷 {}", label))),
+            )
+            .collect();
+        if labels.is_empty() {
+            ui.small("Label a thread to get notified only about mail with that label.");
+        } else {
+            ui.label("Only notify about mail labeled");
+            for label in labels {
+                let mut on = prefs.notify_labels.contains(&label);
+                if ui.checkbox(&mut on, &label).changed() {
+                    if on {
+                        prefs.notify_labels.push(label);
+                    } else {
+                        prefs.notify_labels.retain(|l| *l != label);
+                    }
+                }
+            }
+            ui.small(
+                "With none picked every new sender and mention notifies. With some picked, \
+                 every reply in a thread with one of them does, and other mail doesn't.",
+            );
+        }
+
+        ui.add_space(6.0);
+        ui.label("Notify about mail for");
+        egui::Grid::new("notification_rules")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (scope, name) in rule_scopes {
+                    Self::notification_rule(
+                        ui,
+                        &app.db,
+                        &mut app.notifications.rules,
+                        scope,
+                        &name,
+                    );
+                    ui.end_row();
+                }
+            });
+        ui.small(
+            "\"As usual\" notifies about new senders and mentions, a rule about every message \
+             it lets through. A label's rule goes before the account's, and the strictest \
+             label wins. Notifications during quiet hours are still listed, but don't count \
+             as unread.",
+        );
+
+        ui.add_space(10.0);
+        ui.heading("Privacy");
+        let fuzz_name = |hours: u32| match hours {
+            0 => "Don't backdate".to_string(),
+            1 => "1 hour".to_string(),
+            24 => "1 day".to_string(),
+            hours if hours % 24 == 0 => format!("{} days", hours / 24),
+            hours => format!("{} hours", hours),
+        };
+        ui.horizontal(|ui| {
+            let label = ui.label("Date gift wraps back by up to");
+            egui::ComboBox::from_id_source("wrap_fuzz")
+                .selected_text(fuzz_name(prefs.wrap_fuzz_hours))
+                .show_ui(ui, |ui| {
+                    for hours in [0, 1, 6, 24, 48] {
+                        ui.selectable_value(&mut prefs.wrap_fuzz_hours, hours, fuzz_name(hours));
+                    }
+                })
+                .response
+                .labelled_by(label.id);
+        });
+        ui.small(
+            "Relays see a random time in this window instead of when you sent a message. \
+             Conversations are still shown in the order messages were written.",
+        );
+
+        ui.add_space(10.0);
+        ui.heading("Message list");
+        ui.small("Pick the columns to show and move them into the order you like.");
+        let mut layout = app.inbox_columns.clone();
+        let shown = layout.visible().len();
+        let last = layout.columns.len() - 1;
+        let mut move_down = None;
+        for (i, (column, visible)) in layout.columns.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                // Keep at least one column, or there'd be no way to open a message.
+                ui.add_enabled(
+                    !*visible || shown > 1,
+                    egui::Checkbox::new(visible, column.title()),
+                );
+                ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                    let down = ui.add_enabled(i < last, egui::Button::new("⬇").small());
+                    down.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            format!("Move {} down", column.title()),
+                        )
+                    });
+                    if down.clicked() {
+                        move_down = Some(i);
+                    }
+                    let up = ui.add_enabled(i > 0, egui::Button::new("⬆").small());
+                    up.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            format!("Move {} up", column.title()),
+                        )
+                    });
+                    if up.clicked() {
+                        move_down = Some(i - 1);
+                    }
+                });
+            });
+        }
+        if let Some(i) = move_down {
+            layout.move_down(i);
+        }
+        if layout != app.inbox_columns {
+            if let Err(e) = layout.save(&mut app.db) {
+                error!("Failed to save the message list columns: {}", e);
+            }
+            app.inbox_columns = layout;
+        }
+
+        ui.add_space(10.0);
+        ui.heading("Dates and times");
+        ui.checkbox(&mut prefs.clock_24h, "Use a 24-hour clock");
+        ui.small("Hover over a time to see the full date in your time zone.");
+
+        ui.add_space(10.0);
+        ui.heading("Translation");
+        ui.small(
+            "Messages go to this server when you press Translate on them. Leave it empty to \
+             turn translating off.",
+        );
+        ui.label(
+            egui::RichText::new(
+                "The server gets the decrypted message, so whoever runs it can read it. Use one \
+                 you run yourself or trust with your mail. The API key is kept unencrypted with \
+                 Hoot's settings.",
+            )
+            .small()
+            .color(Color32::YELLOW),
+        );
+        let config = &mut app.translator.config;
+        egui::Grid::new("translation")
+            .num_columns(2)
+            .show(ui, |ui| {
+                let label = ui.label("Service");
+                egui::ComboBox::from_id_source("translation_provider")
+                    .selected_text(config.provider.name())
+                    .show_ui(ui, |ui| {
+                        for kind in translate::ProviderKind::ALL {
+                            ui.selectable_value(&mut config.provider, kind, kind.name());
+                        }
+                    })
+                    .response
+                    .labelled_by(label.id);
+                ui.end_row();
+                let label = ui.label("Server");
+                ui.add(
+                    egui::TextEdit::singleline(&mut config.endpoint)
+                        .hint_text("https://translate.example.com"),
+                )
+                .labelled_by(label.id);
+                ui.end_row();
+                let label = ui.label("API key");
+                ui.add(
+                    egui::TextEdit::singleline(&mut config.api_key)
+                        .password(true)
+                        .hint_text("If the server asks for one"),
+                )
+                .labelled_by(label.id);
+                ui.end_row();
+                let label = ui.label("Translate into");
+                ui.add(
+                    egui::TextEdit::singleline(&mut config.language)
+                        .desired_width(60.0)
+                        .hint_text("en"),
+                )
+                .labelled_by(label.id);
+                ui.end_row();
+            });
+        if !config.endpoint.trim().is_empty() {
+            if let Err(e) = translate::check_endpoint(&config.endpoint) {
+                ui.colored_label(Color32::RED, format!("⚠ {}", e));
+            }
+        }
+
+        ui.add_space(10.0);
+        ui.heading("Links");
+        ui.small("Profile links start a message to that person, event links open the thread.");
+        if ui.button("Open nostr: links with Hoot").clicked() {
+            app.state.settings.uri_handler_status =
+                Some(match crate::nostr_uri::register_handler() {
+                    Ok(()) => "Hoot now opens nostr: links.".to_string(),
+                    Err(e) => {
+                        error!("Could not register the nostr: link handler: {}", e);
+                        format!("Couldn't set that up: {}", e)
+                    }
+                });
+        }
+        if let Some(status) = &app.state.settings.uri_handler_status {
+            ui.label(status);
+        }
+        if split_toggled {
+            app.refresh_table_entries();
+        }
+    }
+
+    fn notification_rule(
+        ui: &mut Ui,
+        db: &crate::db::Db,
+        rules: &mut NotificationRules,
+        scope: RuleScope,
+        name: &str,
+    ) {
+        ui.label(name);
+        let current = rules.get(&scope).copied();
+        let mut rule = current;
+        ui.horizontal(|ui| {
+            let selected = rule.map_or("As usual".to_string(), |rule| rule.mode.to_string());
+            let combo = egui::ComboBox::from_id_source(("notification_rule", name))
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rule, None, "As usual");
+                    for mode in NOTIFY_MODES {
+                        let is_selected = rule.is_some_and(|rule| rule.mode == mode);
+                        if ui.selectable_label(is_selected, mode.to_string()).clicked() {
+                            let quiet = rule.and_then(|rule| rule.quiet);
+                            rule = Some(NotificationRule { mode, quiet });
+                        }
+                    }
+                });
+            combo.response.widget_info(|| {
+                egui::WidgetInfo::labeled(
+                    egui::WidgetType::ComboBox,
+                    format!("When mail for {} notifies", name),
+                )
+            });
+            let Some(rule) = rule.as_mut().filter(|rule| rule.mode != NotifyMode::Never) else {
+                return;
+            };
+            let mut quiet = rule.quiet.is_some();
+            if ui.checkbox(&mut quiet, "Quiet hours").changed() {
+                rule.quiet = quiet.then(QuietHours::default);
+            }
+            if let Some(hours) = &mut rule.quiet {
+                let label = ui.label("from");
+                ui.add(
+                    egui::DragValue::new(&mut hours.start)
+                        .clamp_range(0..=23)
+                        .suffix(":00"),
+                )
+                .labelled_by(label.id);
+                let label = ui.label("to");
+                ui.add(
+                    egui::DragValue::new(&mut hours.end)
+                        .clamp_range(0..=23)
+                        .suffix(":00"),
+                )
+                .labelled_by(label.id);
             }
         });
+        if rule != current {
+            if let Err(e) = rules.set(db, scope, rule) {
+                error!("Failed to save the notification rule for {}: {}", name, e);
+            }
+        }
+    }
+
+    fn data(app: &mut Hoot, ui: &mut Ui) {
+        let before = app.janitor.policy.clone();
+        let policy = &mut app.janitor.policy;
+
+        ui.heading("Old mail");
+        let mut trash_old = policy.trash_after_months.is_some();
+        if ui
+            .checkbox(&mut trash_old, "Move old threads to the Trash")
+            .changed()
+        {
+            policy.trash_after_months = trash_old.then_some(12);
+        }
+        if let Some(months) = &mut policy.trash_after_months {
+            ui.horizontal(|ui| {
+                let label = ui.label("When nothing in them is newer than");
+                ui.add(
+                    egui::DragValue::new(months)
+                        .clamp_range(1..=120)
+                        .suffix(" months"),
+                )
+                .labelled_by(label.id);
+            });
+            ui.horizontal(|ui| {
+                ui.label("From");
+                ui.checkbox(&mut policy.inbox, "Inbox");
+                ui.checkbox(&mut policy.archived, "Archived");
+                ui.checkbox(&mut policy.sent, "Sent");
+            });
+            ui.checkbox(
+                &mut policy.keep_starred,
+                "Keep threads with a starred message",
+            );
+        }
+
+        ui.add_space(10.0);
+        ui.heading("Trash");
+        ui.horizontal(|ui| {
+            let label = ui.label("Delete messages in the Trash after");
+            ui.add(
+                egui::DragValue::new(&mut policy.purge_trash_after_days)
+                    .clamp_range(1..=365)
+                    .suffix(" days"),
+            )
+            .labelled_by(label.id);
+        });
+        ui.small("Messages already in the Trash keep the days set when they were trashed.");
+
+        if app.janitor.policy != before {
+            app.janitor.preview = None;
+        }
+
+        ui.add_space(10.0);
+        ui.small("These rules run when Hoot starts and every hour after that.");
+        ui.horizontal(|ui| {
+            if ui.button("Preview").clicked() {
+                app.run_retention(true);
+            }
+            if ui.button("Run now").clicked() {
+                app.run_retention(false);
+            }
+        });
+        if let Some(plan) = &app.janitor.preview {
+            if plan.is_empty() {
+                ui.label("Nothing to do right now.");
+            } else {
+                ui.label(format!(
+                    "{} threads ({} messages) would move to the Trash, and {} messages in the \
+                     Trash would be deleted for good.",
+                    plan.threads,
+                    plan.trash.len(),
+                    plan.purge.len()
+                ));
+            }
+        }
+    }
+
+    fn debug(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Logs");
+        ui.small("Hoot keeps its recent logs on disk. Attach them when reporting a bug.");
+        if ui.button("Open Logs Folder").clicked() {
+            if let Err(e) = crate::logging::open_logs_folder() {
+                error!("Could not open logs folder: {}", e);
+            }
+        }
+        let current = crate::logging::filter();
+        let settings = &mut app.state.settings;
+        let input = settings
+            .log_filter_input
+            .get_or_insert_with(|| current.clone());
+        ui.horizontal(|ui| {
+            let label = ui.label("Log levels:");
+            ui.add(
+                egui::TextEdit::singleline(input)
+                    .hint_text("info,hoot::relay=debug")
+                    .desired_width(240.0),
+            )
+            .labelled_by(label.id);
+            if ui
+                .add_enabled(input.trim() != current, egui::Button::new("Save"))
+                .clicked()
+            {
+                settings.log_filter_error = crate::logging::set_filter(input).err();
+            }
+        });
+        ui.small(
+            "A level for everything, then levels for parts of Hoot. Debug and trace name the \
+             events and relay messages Hoot handles, so turn them back down after. Empty keeps \
+             the defaults.",
+        );
+        if let Some(error) = &settings.log_filter_error {
+            ui.colored_label(Color32::RED, format!("Couldn't use those levels: {}", error));
+        }
+
+        ui.add_space(10.0);
+
+        Self::event_inspector(app, ui);
+
+        ui.add_space(10.0);
+
+        ui.heading("Profiling");
+        ui.checkbox(&mut app.frame_overlay.visible, "Show frame times")
+            .on_hover_text("Draws how long each frame takes in the corner of the window.");
+        if cfg!(feature = "profiling") {
+            ui.small("Detailed timings are served to puffin_viewer on 127.0.0.1:8585.");
+        } else {
+            ui.small("Build with the profiling feature for detailed timings in puffin_viewer.");
+        }
+
+        ui.add_space(10.0);
+
+        ui.heading("Relay Traffic");
+        ui.small(
+            "Records the raw messages exchanged with each relay. Useful when mail isn't arriving.",
+        );
+
+        let mut trace_enabled = app.relays.trace_enabled();
+        if ui
+            .checkbox(&mut trace_enabled, "Record relay traffic")
+            .changed()
+        {
+            app.relays.set_trace_enabled(trace_enabled);
+        }
+
+        let mut urls: Vec<String> = app.relays.relays.keys().cloned().collect();
+        urls.sort();
+        let settings = &mut app.state.settings;
+        if !settings
+            .trace_relay
+            .as_ref()
+            .is_some_and(|url| urls.contains(url))
+        {
+            settings.trace_relay = urls.first().cloned();
+        }
+
+        let mut export_clicked = false;
+        ui.horizontal(|ui| {
+            let relay_label = ui.label("Relay:");
+            egui::ComboBox::from_id_source("trace_relay_selector")
+                .selected_text(settings.trace_relay.clone().unwrap_or_default())
+                .show_ui(ui, |ui| {
+                    for url in &urls {
+                        ui.selectable_value(&mut settings.trace_relay, Some(url.clone()), url);
+                    }
+                })
+                .response
+                .labelled_by(relay_label.id);
+
+            if ui.button("Clear").clicked() {
+                app.relays.clear_traces();
+            }
+
+            export_clicked = ui.button("Export to File").clicked();
+        });
+
+        if export_clicked {
+            app.state.settings.trace_export_status = Some(Self::export_traces(app));
+        }
+
+        if let Some(status) = &app.state.settings.trace_export_status {
+            ui.label(status);
+        }
+
+        ui.add_space(8.0);
+
+        let Some(relay) = app
+            .state
+            .settings
+            .trace_relay
+            .as_ref()
+            .and_then(|url| app.relays.relays.get(url))
+        else {
+            ui.label("No relays configured.");
+            return;
+        };
+
+        if relay.trace.is_empty() {
+            ui.label("Nothing recorded yet.");
+            return;
+        }
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in relay.trace.entries() {
+                    ui.add(
+                        egui::Label::new(egui::RichText::new(entry.to_string()).monospace())
+                            .wrap(true),
+                    );
+                }
+            });
+    }
+
+    fn event_inspector(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Event Inspector");
+        ui.small("Shows what we stored for an event and which relays sent it.");
+        let id_label = ui.label("Event id:");
+        ui.text_edit_singleline(&mut app.state.settings.inspect_event_id)
+            .labelled_by(id_label.id);
+
+        let event_id = app.state.settings.inspect_event_id.trim().to_lowercase();
+        if event_id.len() != 64 || !event_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return;
+        }
+
+        match app.db.get_event_kind_pubkey(&event_id) {
+            Ok(Some((kind, pubkey))) => {
+                ui.label(format!("Kind {} by {}", kind, pubkey));
+            }
+            Ok(None) => {
+                ui.label("Not stored (gift wraps are kept by the message inside).");
+            }
+            Err(e) => error!("Could not look up event {}: {}", event_id, e),
+        }
+
+        let sources = app.event_sources(&event_id);
+        let clock_24h = app.preferences.clock_24h;
+        if sources.is_empty() {
+            ui.label("No relay recorded for this event.");
+            return;
+        }
+        egui::Grid::new("event_inspector_sources")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Relay");
+                ui.strong("First seen");
+                ui.end_row();
+                for (url, seen_at) in sources {
+                    ui.label(url);
+                    ui.label(crate::style::format_timestamp(seen_at, clock_24h))
+                        .on_hover_text(crate::style::format_full_timestamp(seen_at, clock_24h));
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn downloads(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Downloads");
+        ui.small("Attachments you save are checked against their hash and stored in this folder.");
+
+        let current_dir = app.downloads.download_dir.display().to_string();
+        let input = app
+            .state
+            .settings
+            .download_dir_input
+            .get_or_insert(current_dir.clone());
+
+        let folder_label = ui.label("Download folder:");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(input).labelled_by(folder_label.id);
+            let changed = input.trim() != current_dir;
+            if ui
+                .add_enabled(
+                    changed && !input.trim().is_empty(),
+                    egui::Button::new("Save"),
+                )
+                .clicked()
+            {
+                let dir = std::path::PathBuf::from(input.trim());
+                match std::fs::create_dir_all(&dir) {
+                    Ok(()) => {
+                        info!("Download folder set to {:?}", dir);
+                        app.downloads.download_dir = dir;
+                    }
+                    Err(e) => error!("Could not use {:?} as download folder: {}", dir, e),
+                }
+            }
+        });
+
+        ui.add_space(16.0);
+        ui.heading("Uploads");
+        ui.small(
+            "Files you attach go to this Blossom server as they are when you send, so anyone \
+             with the link can open them. Hoot asks first.",
+        );
+
+        let current_server = app.uploads.server.clone();
+        let input = app
+            .state
+            .settings
+            .upload_server_input
+            .get_or_insert(current_server.clone());
+
+        let server_label = ui.label("Upload server:");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(input).labelled_by(server_label.id);
+            let server = input.trim().trim_end_matches('/');
+            let valid = server.starts_with("https://") || server.starts_with("http://");
+            if ui
+                .add_enabled(valid && server != current_server, egui::Button::new("Save"))
+                .clicked()
+            {
+                info!("Upload server set to {}", server);
+                app.uploads.server = server.to_string();
+            }
+        });
+    }
+
+    fn blocked(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Blocked Senders");
+        ui.small("Messages from blocked senders are hidden and new ones are ignored.");
+        ui.add_space(8.0);
+
+        if app.blocked_pubkeys.is_empty() {
+            ui.label("You haven't blocked anyone.");
+            return;
+        }
+
+        let mut to_unblock: Option<String> = None;
+        for pubkey in &app.blocked_pubkeys {
+            ui.horizontal(|ui| {
+                match app.resolve_name(pubkey) {
+                    Some(name) => {
+                        ui.label(name);
+                        ui.label(egui::RichText::new(pubkey).monospace().small());
+                    }
+                    None => {
+                        ui.label(egui::RichText::new(pubkey).monospace());
+                    }
+                }
+                if ui.button("Unblock").clicked() {
+                    to_unblock = Some(pubkey.clone());
+                }
+            });
+        }
+
+        if let Some(pubkey) = to_unblock {
+            app.unblock_pubkey(&pubkey);
+        }
+    }
+
+    fn bridge(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Email Bridge");
+        ui.small(
+            "A nostr-to-email gateway lets you send to regular email addresses and get replies \
+             as mail. The gateway can read everything you send through it.",
+        );
+        ui.add_space(8.0);
+
+        let draft = app
+            .state
+            .settings
+            .bridge_draft
+            .get_or_insert_with(|| app.bridge.clone());
+
+        ui.checkbox(&mut draft.enabled, "Send email through a gateway");
+        egui::Grid::new("bridge_settings")
+            .num_columns(2)
+            .spacing([8.0, 4.0])
+            .show(ui, |ui| {
+                let gateway_label = ui.label("Gateway public key:");
+                ui.add_enabled(
+                    draft.enabled,
+                    egui::TextEdit::singleline(&mut draft.gateway_pubkey).hint_text("npub..."),
+                )
+                .labelled_by(gateway_label.id);
+                ui.end_row();
+
+                let address_label = ui.label("Your email address:");
+                ui.add_enabled(
+                    draft.enabled,
+                    egui::TextEdit::singleline(&mut draft.address)
+                        .hint_text("Given to you by the gateway"),
+                )
+                .labelled_by(address_label.id);
+                ui.end_row();
+            });
+
+        let changed = *draft != app.bridge;
+        let mut save = false;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(changed, egui::Button::new("Save")).clicked() {
+                save = true;
+            }
+            if ui
+                .add_enabled(changed, egui::Button::new("Revert"))
+                .clicked()
+            {
+                *draft = app.bridge.clone();
+                app.state.settings.bridge_error = None;
+            }
+        });
+
+        if save {
+            let mut config = draft.clone();
+            config.gateway_pubkey = config.gateway_pubkey.trim().to_string();
+            config.address = config.address.trim().to_string();
+
+            if config.enabled && nostr::PublicKey::parse(&config.gateway_pubkey).is_err() {
+                app.state.settings.bridge_error =
+                    Some("The gateway public key isn't a valid npub or hex key.".to_string());
+            } else if !config.address.is_empty()
+                && !crate::bridge::is_email_address(&config.address)
+            {
+                app.state.settings.bridge_error =
+                    Some(format!("{} isn't an email address.", config.address));
+            } else {
+                info!("Email bridge settings saved");
+                app.state.settings.bridge_draft = Some(config.clone());
+                app.state.settings.bridge_error = None;
+                app.bridge = config;
+            }
+        }
+
+        if let Some(e) = &app.state.settings.bridge_error {
+            ui.label(egui::RichText::new(e).color(Color32::RED));
+        }
+
+        if app.bridge.gateway().is_some() && !app.bridge.address.is_empty() {
+            ui.add_space(8.0);
+            ui.label(format!(
+                "People outside nostr can reach you at {}.",
+                app.bridge.address
+            ));
+        }
+    }
+
+    fn automation(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Automation");
+        ui.small(
+            "Let other programs act on new mail, like making a task for it. When new mail matches \
+             a hook's search, Hoot runs its command with the mail as JSON on stdin and in \
+             HOOT_ID, HOOT_SENDER, HOOT_SENDER_NAME and HOOT_SUBJECT, or posts the JSON to its \
+             webhook.",
+        );
+        ui.add_space(4.0);
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            "⚠ Hooks run without asking, with your permissions. The subject is whatever the \
+             sender wrote, so don't hand it to a shell. Only add commands you trust.",
+        );
+        ui.add_space(8.0);
+
+        let automations = &mut app.automations;
+        let mut remove = None;
+        let mut try_out = None;
+        for (i, hook) in automations.config.hooks.iter_mut().enumerate() {
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut hook.enabled, format!("Hook {}", i + 1));
+                    ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        if ui.small_button("Try it").clicked() {
+                            try_out = Some(i);
+                        }
+                    });
+                });
+                egui::Grid::new(("automation_hook", i))
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        let label = ui.label("When mail matches");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut hook.query)
+                                .hint_text("All new mail, or a search like subject:invoice"),
+                        )
+                        .labelled_by(label.id);
+                        ui.end_row();
+
+                        let webhook = matches!(hook.action, Action::Webhook(_));
+                        let label = ui.label(if webhook { "Post to" } else { "Run" });
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source(("automation_action", i))
+                                .selected_text(if webhook { "Webhook" } else { "Command" })
+                                .show_ui(ui, |ui| {
+                                    let target = match &hook.action {
+                                        Action::Command(target) | Action::Webhook(target) => {
+                                            target.clone()
+                                        }
+                                    };
+                                    if ui.selectable_label(!webhook, "Command").clicked() {
+                                        hook.action = Action::Command(target.clone());
+                                    }
+                                    if ui.selectable_label(webhook, "Webhook").clicked() {
+                                        hook.action = Action::Webhook(target);
+                                    }
+                                });
+                            let (target, hint) = match &mut hook.action {
+                                Action::Command(command) => (command, "notify-send New mail"),
+                                Action::Webhook(url) => (url, "http://localhost:8080/mail"),
+                            };
+                            ui.add(egui::TextEdit::singleline(target).hint_text(hint))
+                                .labelled_by(label.id);
+                        });
+                        ui.end_row();
+                    });
+                if let Action::Webhook(url) = &hook.action {
+                    if !url.trim().is_empty() && !automation::is_local(url) {
+                        ui.colored_label(
+                            Color32::RED,
+                            "Webhooks have to be on this computer, like http://localhost:8080.",
+                        );
+                    }
+                }
+                if let Some((at, result)) = automations.last_runs.get(&i) {
+                    let when = crate::style::format_timestamp(
+                        at.as_u64() as i64,
+                        app.preferences.clock_24h,
+                    );
+                    match result {
+                        Ok(()) => ui.small(format!("Last ran {}", when)),
+                        Err(e) => ui.colored_label(Color32::RED, format!("Failed {}: {}", when, e)),
+                    };
+                }
+            });
+            ui.add_space(4.0);
+        }
+        if let Some(i) = try_out {
+            automations.run(i, automation::Payload::example());
+        }
+        if let Some(i) = remove {
+            automations.remove(i);
+        }
+        if ui.button("➕ Add hook").clicked() {
+            automations.config.hooks.push(automation::Hook::default());
+        }
+    }
+
+    fn wallet(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Wallet");
+        ui.small(
+            "Connect a wallet with Nostr Wallet Connect to pay relays and media hosts that charge \
+             without leaving Hoot. Anyone with the connection string can spend from the wallet, so \
+             give the connection a budget.",
+        );
+        ui.add_space(8.0);
+
+        let mut disconnect = false;
+        match app.payments.connection() {
+            Some(connection) => {
+                let name = connection
+                    .lud16
+                    .clone()
+                    .unwrap_or_else(|| connection.wallet.to_hex());
+                ui.label(format!("Connected to {}", name));
+                ui.label(format!("Through {}", connection.relay));
+                if !app.payments.is_connected() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Connecting…");
+                    });
+                } else if let Some(msats) = app.payments.balance {
+                    ui.label(format!("Balance: {} sats", msats / 1000));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Refresh balance").clicked() {
+                        app.payments.refresh_balance();
+                    }
+                    disconnect = ui.button("Disconnect").clicked();
+                });
+            }
+            None => {
+                let settings = &mut app.state.settings;
+                let uri_label = ui.label("Connection string:");
+                let mut connect = false;
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut settings.wallet_uri_input)
+                            .password(true)
+                            .hint_text("nostr+walletconnect://…"),
+                    )
+                    .labelled_by(uri_label.id);
+                    connect = ui
+                        .add_enabled(
+                            !settings.wallet_uri_input.trim().is_empty(),
+                            egui::Button::new("Connect"),
+                        )
+                        .clicked();
+                });
+                if connect {
+                    match app
+                        .payments
+                        .connect(&app.db, &settings.wallet_uri_input, ui.ctx())
+                    {
+                        Ok(()) => {
+                            info!("Connected a wallet");
+                            settings.wallet_uri_input.clear();
+                            settings.wallet_error = None;
+                        }
+                        Err(e) => settings.wallet_error = Some(e.to_string()),
+                    }
+                }
+            }
+        }
+        if disconnect {
+            if let Err(e) = app.payments.disconnect(&app.db) {
+                error!("Failed to disconnect the wallet: {}", e);
+                app.state.settings.wallet_error = Some(e.to_string());
+            }
+        }
+
+        let error = app
+            .state
+            .settings
+            .wallet_error
+            .as_ref()
+            .or(app.payments.error.as_ref());
+        if let Some(error) = error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    /// How much NIP-77 sync saved us, per relay.
+    fn sync_stats(app: &Hoot, ui: &mut Ui) {
+        let stats = &app.relays.sync_stats;
+        if stats.is_empty() {
+            return;
+        }
+
+        let skipped: usize = stats.values().map(|s| s.skipped).sum();
+        let fetched: usize = stats.values().map(|s| s.fetched).sum();
+        ui.strong("Relay sync");
+        ui.label(format!(
+            "Skipped downloading {} stored events, fetched {} missing ones.",
+            skipped, fetched
+        ));
+
+        let mut urls: Vec<&String> = stats.keys().collect();
+        urls.sort();
+        egui::Grid::new("sync_stats")
+            .num_columns(5)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Relay");
+                ui.strong("Status");
+                ui.strong("Rounds");
+                ui.strong("Skipped");
+                ui.strong("Fetched");
+                ui.end_row();
+                for url in urls {
+                    let relay_stats = &stats[url];
+                    ui.label(url.as_str());
+                    ui.label(relay_stats.status.to_string());
+                    ui.label(relay_stats.rounds.to_string());
+                    ui.label(relay_stats.skipped.to_string());
+                    ui.label(relay_stats.fetched.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Which relays deliver events first, and how many copies we dropped.
+    fn event_stats(app: &Hoot, ui: &mut Ui) {
+        let stats = &app.relays.event_stats;
+        if stats.is_empty() {
+            return;
+        }
+
+        ui.add_space(8.0);
+        ui.strong("Events by relay");
+        let mut urls: Vec<&String> = stats.keys().collect();
+        urls.sort();
+        egui::Grid::new("event_stats")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Relay");
+                ui.strong("First");
+                ui.strong("Duplicates dropped");
+                ui.end_row();
+                for url in urls {
+                    ui.label(url.as_str());
+                    ui.label(stats[url].first_seen.to_string());
+                    ui.label(stats[url].duplicates.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// What an integrity report found, a line for each kind of problem.
+    fn integrity_lines(report: &crate::db::IntegrityReport) -> Vec<String> {
+        [
+            (report.orphaned_states, "marks left by deleted messages"),
+            (report.broken_events.len(), "unreadable messages"),
+            (report.unindexed, "messages missing from search"),
+            (report.stale_index, "search entries for deleted messages"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}", count, what))
+        .collect()
+    }
+
+    /// What the database check found at unlock, with buttons to check again and to repair.
+    fn database_integrity(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Database");
+        match &app.integrity {
+            None => {
+                ui.label("Not checked yet.");
+            }
+            Some(report) if report.is_clean() => {
+                ui.label("No problems found.");
+            }
+            Some(report) => {
+                ui.label("Found:");
+                for line in Self::integrity_lines(report) {
+                    ui.label(format!("• {}", line));
+                }
+            }
+        }
+        if let Some(status) = &app.state.settings.integrity_status {
+            ui.label(status);
+        }
+
+        let needs_repair = app
+            .integrity
+            .as_ref()
+            .is_some_and(|report| !report.is_clean());
+        let mut check = false;
+        let mut repair = false;
+        ui.horizontal(|ui| {
+            check = ui.button("Check again").clicked();
+            if needs_repair {
+                repair = ui
+                    .button("Repair")
+                    .on_hover_text("Unreadable messages are removed so relays can send them again")
+                    .clicked();
+            }
+        });
+
+        if repair {
+            let status = match app.db.repair_integrity() {
+                Ok(fixed) => {
+                    info!("Repaired the database: {:?}", fixed);
+                    app.refresh_table_entries();
+                    app.refresh_archived();
+                    app.refresh_trash();
+                    app.refresh_sent();
+                    format!("Repaired {}.", Self::integrity_lines(&fixed).join(", "))
+                }
+                Err(e) => {
+                    error!("Failed to repair the database: {}", e);
+                    format!("Couldn't repair the database: {}", e)
+                }
+            };
+            app.state.settings.integrity_status = Some(status);
+            check = true;
+        }
+        if check {
+            app.check_integrity();
+        }
+    }
+
+    fn diagnostics(app: &mut Hoot, ui: &mut Ui) {
+        use crate::metrics;
+
+        ui.heading("Performance Metrics");
+        ui.small("Metrics are kept on this device only and are never sent anywhere.");
+
+        let mut enabled = metrics::is_enabled();
+        if ui
+            .checkbox(&mut enabled, "Collect performance metrics")
+            .changed()
+        {
+            metrics::set_enabled(enabled);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked() {
+                metrics::reset();
+            }
+            if ui.button("Export as JSON").clicked() {
+                app.state.settings.metrics_export_status = Some(Self::export_metrics());
+            }
+        });
+
+        if let Some(status) = &app.state.settings.metrics_export_status {
+            ui.label(status);
+        }
+
+        ui.add_space(8.0);
+        Self::sync_stats(app, ui);
+        Self::event_stats(app, ui);
+
+        ui.add_space(8.0);
+        ui.heading("Clock");
+        match crate::clock::offset() {
+            0 => ui.label("Your clock agrees with the relays'."),
+            offset => ui.label(format!(
+                "Your clock is {}. Messages are dated by the relays' clock instead.",
+                crate::clock::describe(offset)
+            )),
+        };
+
+        ui.add_space(8.0);
+        Self::database_integrity(app, ui);
+        ui.add_space(8.0);
+
+        let snapshot = metrics::snapshot();
+        if snapshot.counters.is_empty() && snapshot.histograms.is_empty() {
+            ui.label("Nothing recorded yet.");
+            return;
+        }
+
+        egui::Grid::new("metrics_counters")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (name, value) in &snapshot.counters {
+                    ui.label(*name);
+                    ui.label(value.to_string());
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(8.0);
+
+        egui::Grid::new("metrics_histograms")
+            .num_columns(5)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Timing");
+                ui.strong("Count");
+                ui.strong("Mean");
+                ui.strong("Min");
+                ui.strong("Max");
+                ui.end_row();
+                for (name, histogram) in &snapshot.histograms {
+                    ui.label(*name);
+                    ui.label(histogram.count.to_string());
+                    ui.label(format!("{:.2} ms", histogram.mean_ms()));
+                    ui.label(format!("{:.2} ms", histogram.min_ms));
+                    ui.label(format!("{:.2} ms", histogram.max_ms));
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn export_metrics() -> String {
+        let storage_dir = match eframe::storage_dir(crate::STORAGE_NAME) {
+            Some(dir) => dir,
+            None => return "Could not find a directory to export to.".to_string(),
+        };
+        let file_name = format!(
+            "metrics-{}.json",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let path = storage_dir.join(file_name);
+
+        let result = serde_json::to_string_pretty(&crate::metrics::snapshot())
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&path, json));
+        match result {
+            Ok(()) => {
+                info!("Exported metrics to {:?}", path);
+                format!("Exported to {}", path.display())
+            }
+            Err(e) => {
+                error!("Failed to export metrics: {}", e);
+                format!("Export failed: {}", e)
+            }
+        }
+    }
+
+    fn export_traces(app: &Hoot) -> String {
+        let storage_dir = match eframe::storage_dir(crate::STORAGE_NAME) {
+            Some(dir) => dir,
+            None => return "Could not find a directory to export to.".to_string(),
+        };
+        let file_name = format!(
+            "relay-trace-{}.log",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        let path = storage_dir.join(file_name);
+
+        match app.relays.export_traces(&path) {
+            Ok(()) => {
+                info!("Exported relay traces to {:?}", path);
+                format!("Exported to {}", path.display())
+            }
+            Err(e) => {
+                error!("Failed to export relay traces: {}", e);
+                format!("Export failed: {}", e)
+            }
+        }
     }
 }
@@ -0,0 +1,115 @@
+//! Notes the user keeps on a thread for themselves, like what to follow up on and by when.
+//! They're kept in the database only and never sent. `note:` in the search box finds threads by
+//! them.
+
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, RichText};
+use tracing::error;
+
+/// A mark next to a thread's subject when it has a note, showing the note on hover.
+pub fn note_mark(app: &Hoot, ui: &mut egui::Ui, root_id: &str) {
+    if let Some(note) = app.thread_notes.get(root_id) {
+        ui.label(RichText::new("📝").small().color(style::TEXT_MUTED))
+            .on_hover_text(note);
+    }
+}
+
+/// The button that shows or hides the notes sidebar.
+pub fn notes_button(app: &mut Hoot, ui: &mut egui::Ui) {
+    let label = if app.thread_notes.contains_key(&app.focused_post) {
+        "📝 Notes •"
+    } else {
+        "📝 Notes"
+    };
+    let button = ui
+        .selectable_label(app.state.notes_open, label)
+        .on_hover_text("Private notes on this thread, never sent");
+    if button.clicked() {
+        app.state.notes_open = !app.state.notes_open;
+    }
+}
+
+/// The notes sidebar for the open thread, when it's shown. Has to come before the thread's
+/// messages so they fill the space left beside it.
+pub fn show(app: &mut Hoot, ui: &mut egui::Ui) {
+    let root_id = app.focused_post.clone();
+    let opened_elsewhere = app
+        .state
+        .note_draft
+        .as_ref()
+        .is_some_and(|(id, _)| *id != root_id);
+    if opened_elsewhere {
+        save_draft(app);
+    }
+    if !app.state.notes_open {
+        save_draft(app);
+        return;
+    }
+    if app.state.note_draft.is_none() {
+        let note = app.thread_notes.get(&root_id).cloned().unwrap_or_default();
+        app.state.note_draft = Some((root_id.clone(), note));
+    }
+
+    let mut save = false;
+    egui::SidePanel::right(egui::Id::new(("thread_notes", &root_id)))
+        .resizable(true)
+        .default_width(220.0)
+        .show_inside(ui, |ui| {
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Notes").strong());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("✕").on_hover_text("Hide notes").clicked() {
+                        app.state.notes_open = false;
+                    }
+                });
+            });
+            ui.label(
+                RichText::new("Only for you. Find them with note: in search.")
+                    .small()
+                    .color(style::TEXT_MUTED),
+            );
+            ui.add_space(4.0);
+            let Some((_, note)) = app.state.note_draft.as_mut() else {
+                return;
+            };
+            let input = ui.add(
+                egui::TextEdit::multiline(note)
+                    .hint_text("Follow up on Friday…")
+                    .desired_rows(8)
+                    .desired_width(f32::INFINITY),
+            );
+            input.widget_info(|| {
+                egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Notes on this thread")
+            });
+            save = input.lost_focus();
+        });
+    if save || !app.state.notes_open {
+        save_draft(app);
+    }
+}
+
+/// Keeps the note being written, if it changed, and lets go of it. Called once the thread is
+/// left too.
+pub fn save_draft(app: &mut Hoot) {
+    let Some((root_id, note)) = app.state.note_draft.take() else {
+        return;
+    };
+    let note = note.trim();
+    if app.thread_notes.get(&root_id).map_or("", String::as_str) == note {
+        return;
+    }
+    let note = Some(note).filter(|note| !note.is_empty());
+    match app.db.set_thread_note(&root_id, note) {
+        Ok(()) => match note {
+            Some(note) => {
+                app.thread_notes.insert(root_id, note.to_string());
+            }
+            None => {
+                app.thread_notes.remove(&root_id);
+            }
+        },
+        Err(e) => error!("Failed to save the note on {}: {}", root_id, e),
+    }
+}
@@ -0,0 +1,105 @@
+//! Subjects the user gives threads to keep them organized, like fixing a typo or a pile of
+//! "Re: Fwd:". They're kept in the database only: the messages, and what everyone else on the
+//! thread sees, keep the subject they were sent with.
+
+use crate::style;
+use crate::Hoot;
+use eframe::egui::{self, RichText};
+use tracing::error;
+
+/// The subject of the thread at `root_id`, with a mark when the user changed it.
+pub fn subject_label(app: &Hoot, ui: &mut egui::Ui, root_id: &str, subject: &str) {
+    match app.thread_subjects.get(root_id) {
+        Some(edited) => {
+            ui.label(edited);
+            edited_mark(ui, subject);
+        }
+        None => {
+            ui.label(subject);
+        }
+    }
+}
+
+fn edited_mark(ui: &mut egui::Ui, original: &str) {
+    ui.label(RichText::new("✏ edited").small().color(style::TEXT_MUTED))
+        .on_hover_text(format!("Changed by you. The messages say “{}”", original));
+}
+
+/// The open thread's subject above its messages once the user changed it, and a button to
+/// change it, or a field to type the new one in.
+pub fn render_thread_subject(app: &mut Hoot, ui: &mut egui::Ui, original: &str) {
+    let root_id = app.focused_post.clone();
+    let editing = app
+        .state
+        .subject_edit
+        .as_ref()
+        .is_some_and(|(id, _)| *id == root_id);
+    if !editing {
+        ui.horizontal(|ui| {
+            if let Some(edited) = app.thread_subjects.get(&root_id) {
+                ui.heading(edited);
+                edited_mark(ui, original);
+            }
+            let rename = ui
+                .small_button("✏ Rename")
+                .on_hover_text("Change the subject shown for this thread, only for you");
+            if rename.clicked() {
+                let subject = app
+                    .thread_subjects
+                    .get(&root_id)
+                    .cloned()
+                    .unwrap_or_else(|| original.to_string());
+                app.state.subject_edit = Some((root_id.clone(), subject));
+            }
+        });
+        return;
+    }
+
+    let mut done = false;
+    let mut change = None;
+    ui.horizontal(|ui| {
+        let Some((_, subject)) = app.state.subject_edit.as_mut() else {
+            return;
+        };
+        let input = ui.add(
+            egui::TextEdit::singleline(subject)
+                .hint_text(original)
+                .desired_width(320.0),
+        );
+        input.widget_info(|| {
+            egui::WidgetInfo::labeled(egui::WidgetType::TextEdit, "Subject for this thread")
+        });
+        let subject = subject.trim().to_string();
+        let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Save").clicked() || entered {
+            // Saving the subject the messages have, or none, goes back to it.
+            let edited = Some(subject).filter(|subject| !subject.is_empty() && subject != original);
+            change = Some(edited);
+            done = true;
+        }
+        if app.thread_subjects.contains_key(&root_id) && ui.button("Use the original").clicked() {
+            change = Some(None);
+            done = true;
+        }
+        if ui.button("Cancel").clicked() {
+            done = true;
+        }
+    });
+
+    if let Some(subject) = change {
+        match app.db.set_thread_subject(&root_id, subject.as_deref()) {
+            Ok(()) => match subject {
+                Some(subject) => {
+                    app.thread_subjects.insert(root_id, subject);
+                }
+                None => {
+                    app.thread_subjects.remove(&root_id);
+                }
+            },
+            Err(e) => error!("Failed to change the subject of {}: {}", root_id, e),
+        }
+    }
+    if done {
+        app.state.subject_edit = None;
+    }
+}
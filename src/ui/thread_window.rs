@@ -0,0 +1,91 @@
+//! Threads opened in OS windows of their own, to read beside the main window or a reply.
+//!
+//! Each window keeps its own copy of what the main window tracks about the open thread. While
+//! it's drawn that copy is swapped into the app, so the thread view doesn't need to know which
+//! window it's in.
+
+use crate::db_worker::ThreadSnapshot;
+use crate::ui::detached;
+use crate::{Hoot, Page, ThreadUnread};
+use eframe::egui;
+
+pub struct ThreadWindow {
+    viewport: egui::ViewportId,
+    pub root_id: String,
+    show_trashed: bool,
+    pub snapshot: Option<ThreadSnapshot>,
+    pub requested: Option<(String, bool)>,
+    unread: Option<ThreadUnread>,
+}
+
+impl ThreadWindow {
+    pub fn new(root_id: String) -> Self {
+        Self {
+            viewport: egui::ViewportId::from_hash_of(("thread", &root_id)),
+            root_id,
+            show_trashed: false,
+            snapshot: None,
+            requested: None,
+            unread: None,
+        }
+    }
+
+    fn title(&self) -> String {
+        self.snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.messages.first())
+            .map(|message| message.subject.trim())
+            .filter(|subject| !subject.is_empty())
+            .unwrap_or("Hoot")
+            .to_string()
+    }
+}
+
+/// Opens `root_id` in a window of its own, or brings its window forward if it has one.
+pub fn open(app: &mut Hoot, ctx: &egui::Context, root_id: &str) {
+    if let Some(window) = app.thread_windows.iter().find(|w| w.root_id == root_id) {
+        ctx.send_viewport_cmd_to(window.viewport, egui::ViewportCommand::Focus);
+        return;
+    }
+    app.thread_windows
+        .push(ThreadWindow::new(root_id.to_string()));
+}
+
+/// Draws every thread window with `render_page`, the main window's page drawing, and forgets the
+/// ones that were closed or whose thread went away.
+pub fn show_all(
+    app: &mut Hoot,
+    ctx: &egui::Context,
+    render_page: impl Fn(&mut Hoot, &mut egui::Ui),
+) {
+    let mut windows = std::mem::take(&mut app.thread_windows);
+    windows.retain_mut(|window| show(app, ctx, window, &render_page));
+    // Keep any opened while drawing.
+    windows.append(&mut app.thread_windows);
+    app.thread_windows = windows;
+}
+
+fn show(
+    app: &mut Hoot,
+    ctx: &egui::Context,
+    window: &mut ThreadWindow,
+    render_page: &impl Fn(&mut Hoot, &mut egui::Ui),
+) -> bool {
+    let title = window.title();
+    let page = std::mem::replace(&mut app.page, Page::Post);
+    swap(app, window);
+    let open = detached::show(ctx, window.viewport, &title, |ui| render_page(app, ui));
+    // Trashing or archiving the thread, or failing to load it, leaves the thread view.
+    let still_showing = app.page == Page::Post && !app.focused_post.is_empty();
+    swap(app, window);
+    app.page = page;
+    open && still_showing
+}
+
+fn swap(app: &mut Hoot, window: &mut ThreadWindow) {
+    std::mem::swap(&mut app.focused_post, &mut window.root_id);
+    std::mem::swap(&mut app.show_trashed_post, &mut window.show_trashed);
+    std::mem::swap(&mut app.thread_snapshot, &mut window.snapshot);
+    std::mem::swap(&mut app.thread_requested, &mut window.requested);
+    std::mem::swap(&mut app.state.thread_unread, &mut window.unread);
+}
@@ -0,0 +1,76 @@
+//! Short messages shown over the bottom of the window for a few seconds, for things the user
+//! should know about but doesn't have to act on right away, like a relay refusing us.
+
+use eframe::egui::{self, Color32, RichText, Stroke, Vec2};
+use std::time::{Duration, Instant};
+
+/// How long a toast stays up.
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+/// Older toasts make way for new ones past this many.
+const MAX_TOASTS: usize = 4;
+
+struct Toast {
+    text: String,
+    shown_at: Instant,
+}
+
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    pub fn error(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        // The same thing happening again just keeps its toast up longer.
+        if let Some(toast) = self.toasts.iter_mut().find(|toast| toast.text == text) {
+            toast.shown_at = Instant::now();
+            return;
+        }
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.toasts.push(Toast {
+            text,
+            shown_at: Instant::now(),
+        });
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.toasts
+            .retain(|toast| toast.shown_at.elapsed() < TOAST_DURATION);
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::CENTER_BOTTOM, Vec2::new(0.0, -16.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, toast) in self.toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style())
+                        .stroke(Stroke::new(1.0, Color32::RED))
+                        .show(ui, |ui| {
+                            ui.set_max_width(420.0);
+                            ui.horizontal(|ui| {
+                                ui.add(egui::Label::new(RichText::new(&toast.text)).wrap(true));
+                                if ui.small_button("✕").on_hover_text("Dismiss").clicked() {
+                                    dismissed = Some(i);
+                                }
+                            });
+                        });
+                    ui.add_space(4.0);
+                }
+            });
+        if let Some(i) = dismissed {
+            self.toasts.remove(i);
+        }
+
+        // Wake up again to take down the oldest one.
+        let oldest = self.toasts.iter().map(|toast| toast.shown_at).min();
+        if let Some(shown_at) = oldest {
+            ctx.request_repaint_after(TOAST_DURATION.saturating_sub(shown_at.elapsed()));
+        }
+    }
+}
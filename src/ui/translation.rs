@@ -0,0 +1,69 @@
+//! The Translate button on messages, and the translation shown under the original.
+
+use crate::mail_event::MailMessage;
+use crate::style;
+use crate::translate::TranslationStatus;
+use crate::Hoot;
+use eframe::egui::{self, Color32, Frame, Margin, RichText};
+use nostr::EventId;
+
+/// Shows or hides the translation of `message`, once a translation service is set up.
+pub fn translate_button(app: &mut Hoot, ui: &mut egui::Ui, message: &MailMessage) {
+    let Some(event_id) = message.id.map(|id| id.to_hex()) else {
+        return;
+    };
+    if !app.translator.enabled() || message.content.trim().is_empty() {
+        return;
+    }
+    let label = if app.translator.shown(&event_id).is_some() {
+        "🌐 Hide translation"
+    } else {
+        "🌐 Translate"
+    };
+    let hint = format!(
+        "Sends the message to {} to translate into {}",
+        app.translator.config.endpoint.trim(),
+        app.translator.config.language
+    );
+    if ui.button(label).on_hover_text(hint).clicked() {
+        app.translator.toggle(&app.db, &event_id, &message.content);
+    }
+}
+
+/// The translation of the message `event_id`, if it's open.
+pub fn translation(app: &Hoot, ui: &mut egui::Ui, event_id: EventId) {
+    let Some(status) = app.translator.shown(&event_id.to_hex()) else {
+        return;
+    };
+    ui.add_space(12.0);
+    Frame::none()
+        .fill(style::CARD_BG)
+        .stroke(egui::Stroke::new(1.0, style::CARD_STROKE))
+        .inner_margin(Margin::same(12.0))
+        .rounding(6.0)
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(format!(
+                    "Translated into {}",
+                    app.translator.config.language
+                ))
+                .small()
+                .color(style::TEXT_MUTED),
+            );
+            ui.add_space(4.0);
+            match status {
+                TranslationStatus::Translating => {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Spinner::new().size(12.0));
+                        ui.label(RichText::new("Translating…").color(style::TEXT_MUTED));
+                    });
+                }
+                TranslationStatus::Done(text) => {
+                    ui.label(text);
+                }
+                TranslationStatus::Failed(e) => {
+                    ui.colored_label(Color32::RED, format!("Couldn't translate this: {}", e));
+                }
+            }
+        });
+}
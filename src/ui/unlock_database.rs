@@ -60,19 +60,16 @@ impl UnlockDatabase {
     }
 
     fn attempt_unlock(app: &mut Hoot) {
-        match app
-            .db
-            .unlock_with_password(app.state.unlock_database.secret_input.clone())
-        {
+        // Taken rather than copied, so the database's copy is the only one left to wipe.
+        let password = std::mem::take(&mut app.state.unlock_database.secret_input);
+        match app.db.unlock_with_password(password) {
             Ok(_) => {
-                app.state.unlock_database.secret_input.clear();
                 app.state.unlock_database.error_string.clear();
                 app.status = HootStatus::Initializing;
                 app.page = crate::Page::Inbox;
             }
             Err(e) => {
                 error!("Error when trying to load database: {}", e);
-                app.state.unlock_database.secret_input.clear();
                 app.state.unlock_database.error_string = crate::db::format_unlock_error(&e);
             }
         }
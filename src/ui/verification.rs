@@ -0,0 +1,205 @@
+//! Verifying contacts: mailing them a challenge, answering the ones we get and checking the
+//! answers as they arrive. See `hoot_core::verification` for how it works.
+
+use crate::mail_event::MailMessage;
+use crate::style;
+use crate::ui::compose_window::{self, ComposeWindowState};
+use crate::Hoot;
+use eframe::egui::{self, Frame, Margin, RichText, Stroke, Ui};
+use hoot_core::verification;
+use nostr::{EventId, Keys, PublicKey, UnsignedEvent};
+use tracing::{error, info, warn};
+
+/// Mails a new challenge to the contact `pubkey`, from the account we last wrote to them with.
+pub fn send_challenge(app: &mut Hoot, pubkey: &str) {
+    let Ok(recipient) = PublicKey::from_hex(pubkey) else {
+        error!("Can't verify {}: not a valid public key", pubkey);
+        return;
+    };
+    let keys = app
+        .sending_accounts
+        .get(pubkey)
+        .and_then(|account| {
+            app.account_manager
+                .loaded_keys
+                .iter()
+                .find(|keys| keys.public_key().to_hex() == *account)
+        })
+        .or(app.active_account.as_ref())
+        .cloned();
+    let Some(keys) = keys else {
+        error!("Can't verify {}: no account to send from", pubkey);
+        return;
+    };
+
+    let challenge = verification::new_challenge();
+    if let Err(e) = app.db.save_verification_challenge(pubkey, &challenge) {
+        error!("Failed to save the verification challenge: {}", e);
+        return;
+    }
+    let body = verification::challenge_body(&challenge);
+    match send(app, &keys, recipient, verification::SUBJECT, body, None) {
+        Ok(()) => info!("Sent a verification challenge to {}", pubkey),
+        Err(e) => error!("Failed to send the verification challenge: {}", e),
+    }
+}
+
+/// Marks the sender of `rumor` verified when it answers the challenge we sent them.
+pub fn check_answer(app: &mut Hoot, rumor: &UnsignedEvent) {
+    if !verification::is_response(&rumor.content) {
+        return;
+    }
+    let sender = rumor.pubkey.to_hex();
+    let challenge = match app.db.get_verification_challenge(&sender) {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to load the challenge sent to {}: {}", sender, e);
+            return;
+        }
+    };
+    if !verification::check_response(&rumor.content, &rumor.pubkey, &challenge) {
+        warn!("Verification answer from {} doesn't hold up", sender);
+        return;
+    }
+    match app.contacts_manager.mark_verified(&mut app.db, &sender) {
+        Ok(()) => info!("Verified contact {}", sender),
+        Err(e) => error!("Failed to mark {} verified: {}", sender, e),
+    }
+}
+
+/// Shows a challenge found in a message: a button to answer it if it's for us, whether it's
+/// been answered if we sent it.
+pub fn challenge_card(app: &mut Hoot, ui: &mut Ui, message: &MailMessage, challenge: &str) {
+    let (Some(id), Some(author)) = (message.id, message.author) else {
+        return;
+    };
+    let author_hex = author.to_hex();
+    let ours = app
+        .account_manager
+        .loaded_keys
+        .iter()
+        .any(|keys| keys.public_key() == author);
+    // The account the challenge was sent to answers it.
+    let answering_keys = app
+        .account_manager
+        .loaded_keys
+        .iter()
+        .find(|keys| message.to.contains(&keys.public_key()))
+        .cloned();
+
+    Frame::none()
+        .fill(style::CARD_BG)
+        .stroke(Stroke::new(1.0, style::CARD_STROKE))
+        .inner_margin(Margin::same(12.0))
+        .rounding(8.0)
+        .show(ui, |ui| {
+            if ours {
+                let recipient = message
+                    .to
+                    .first()
+                    .map(|pubkey| pubkey.to_hex())
+                    .unwrap_or_default();
+                let name = app.display_name(&recipient);
+                if app.contacts_manager.is_verified(&recipient) {
+                    ui.label(format!(
+                        "{} {} confirmed their key",
+                        style::VERIFIED_BADGE,
+                        name
+                    ));
+                } else {
+                    ui.label(format!("🔑 Waiting for {} to confirm their key", name));
+                }
+                return;
+            }
+
+            let name = app.display_name(&author_hex);
+            ui.label(
+                RichText::new(format!("🔑 {} asks you to confirm this key is yours", name))
+                    .strong(),
+            );
+            ui.label(
+                RichText::new(
+                    "Confirming signs their challenge with your key and mails it back to them.",
+                )
+                .small()
+                .color(style::TEXT_MUTED),
+            );
+            ui.add_space(4.0);
+
+            let id_hex = id.to_hex();
+            if app.state.answered_challenges.contains(&id_hex) {
+                ui.label(RichText::new("✔ Answered").color(style::TEXT_MUTED));
+                return;
+            }
+            let Some(keys) = answering_keys else {
+                ui.label(
+                    RichText::new("None of your accounts can answer it.").color(style::TEXT_MUTED),
+                );
+                return;
+            };
+            if ui.button("Confirm it's me").clicked() {
+                let result = verification::response_body(&keys, challenge).and_then(|body| {
+                    let subject = format!("Re: {}", message.subject);
+                    send(app, &keys, author, &subject, body, Some(id))
+                });
+                match result {
+                    Ok(()) => {
+                        app.state.answered_challenges.insert(id_hex);
+                    }
+                    Err(e) => error!("Failed to answer the verification challenge: {}", e),
+                }
+            }
+        });
+}
+
+/// Sends a short message to one recipient the way a compose window would, keeping a copy in
+/// Sent and following it until the relays have answered, see [`settle`].
+fn send(
+    app: &mut Hoot,
+    keys: &Keys,
+    recipient: PublicKey,
+    subject: &str,
+    content: String,
+    parent: Option<EventId>,
+) -> anyhow::Result<()> {
+    let mut state = ComposeWindowState {
+        subject: subject.to_string(),
+        to_field: recipient.to_hex(),
+        parent_events: parent.into_iter().collect(),
+        content,
+        selected_account: Some(keys.clone()),
+        ..Default::default()
+    };
+    compose_window::send(app, &mut state, keys);
+    if let Some(e) = state.send_error {
+        anyhow::bail!(e);
+    }
+    if state.sending.is_none() {
+        anyhow::bail!("nothing was sent");
+    }
+    app.state.verification_sends.push(state);
+    Ok(())
+}
+
+/// Follows the challenges and answers waiting on relays. An answer that didn't get through can
+/// be sent again. Called every frame.
+pub fn settle(app: &mut Hoot, ctx: &egui::Context) {
+    for mut state in std::mem::take(&mut app.state.verification_sends) {
+        if compose_window::settle_send(app, ctx, &mut state) {
+            continue;
+        }
+        if state.sending.is_some() {
+            app.state.verification_sends.push(state);
+            continue;
+        }
+        error!(
+            "Verification message {:?} didn't get through: {}",
+            state.subject,
+            state.send_error.unwrap_or_default()
+        );
+        if let Some(parent) = state.parent_events.first() {
+            app.state.answered_challenges.remove(&parent.to_hex());
+        }
+    }
+}
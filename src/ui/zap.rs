@@ -0,0 +1,139 @@
+//! The Zap button on messages from others, and the zaps others sent on ours.
+
+use crate::mail_event::MailMessage;
+use crate::profile_metadata::{get_profile_metadata, ProfileOption};
+use crate::style;
+use crate::zaps::{ZapStatus, AMOUNTS};
+use crate::Hoot;
+use eframe::egui::{self, Color32, RichText};
+use nostr::{EventId, PublicKey};
+
+/// Zaps the sender of `message` from the account it was sent to.
+pub fn zap_button(
+    app: &mut Hoot,
+    ui: &mut egui::Ui,
+    message: &MailMessage,
+    event_id: EventId,
+    author: PublicKey,
+) {
+    match app.zaps.status(&event_id) {
+        Some(ZapStatus::Fetching | ZapStatus::Paying) => {
+            ui.add(egui::Spinner::new().size(12.0))
+                .on_hover_text("Zapping");
+            return;
+        }
+        Some(ZapStatus::Sent) => {
+            ui.label(RichText::new("⚡ Zapped").color(style::ACCENT));
+            return;
+        }
+        Some(ZapStatus::Unanswered) => {
+            ui.colored_label(Color32::YELLOW, "⚡?").on_hover_text(
+                "The wallet didn't answer in time, the zap may still go through. Check your \
+                 wallet before zapping again.",
+            );
+        }
+        Some(ZapStatus::Failed(e)) => {
+            ui.colored_label(Color32::RED, "⚠")
+                .on_hover_text(format!("Zap failed: {}", e));
+        }
+        None => {}
+    }
+
+    let lud16 = match get_profile_metadata(app, author.to_string()) {
+        ProfileOption::Some(metadata) => metadata.lud16.clone(),
+        ProfileOption::Waiting => None,
+    };
+    let keys = app
+        .account_manager
+        .loaded_keys
+        .iter()
+        .find(|keys| {
+            let ours = keys.public_key();
+            message.to.contains(&ours) || message.cc.contains(&ours) || message.bcc.contains(&ours)
+        })
+        .or(app.account_manager.loaded_keys.first())
+        .cloned();
+    let (Some(lud16), Some(keys), Some(_)) = (lud16, keys, app.payments.connection()) else {
+        let reason = if app.payments.connection().is_none() {
+            "Connect a wallet in Settings to zap"
+        } else {
+            "The sender hasn't set a lightning address"
+        };
+        ui.add_enabled(false, egui::Button::new("⚡ Zap"))
+            .on_disabled_hover_text(reason);
+        return;
+    };
+
+    // Gift-wrapped mail stays unnamed in the zap, and so does any message we can't tell.
+    let private = app.thread_snapshot.as_ref().map_or(true, |snapshot| {
+        !snapshot.wraps(&event_id.to_hex()).is_empty()
+    });
+    let mut chosen = None;
+    ui.menu_button("⚡ Zap", |ui| {
+        let note_label = ui.label("Note (optional):");
+        ui.text_edit_singleline(&mut app.zaps.comment)
+            .labelled_by(note_label.id);
+        ui.horizontal(|ui| {
+            for sats in AMOUNTS {
+                if ui.button(format!("{} sats", sats)).clicked() {
+                    chosen = Some(sats);
+                    ui.close_menu();
+                }
+            }
+        });
+        ui.checkbox(&mut app.zaps.anonymous, "Zap anonymously")
+            .on_hover_text("Signs the zap with a throwaway key, so it doesn't name you");
+        ui.label(
+            RichText::new(format!("Paid to {} from your wallet", lud16))
+                .small()
+                .color(style::TEXT_MUTED),
+        );
+        let public = if private {
+            "Zaps are public: relays and the lightning address's server see who zapped whom, \
+             though not for which message."
+        } else {
+            "Zaps are public: relays and the lightning address's server see who zapped whom, \
+             and for which message."
+        };
+        ui.label(RichText::new(public).small().color(Color32::YELLOW));
+    });
+    if let Some(sats) = chosen {
+        let relays = app.relays.urls();
+        app.zaps
+            .zap(&keys, author, &lud16, event_id, private, sats, relays);
+    }
+}
+
+/// What others zapped on one of our messages, if anything.
+pub fn zap_summary(app: &mut Hoot, ui: &mut egui::Ui, event_id: EventId) {
+    let zaps = app.zaps.zaps_on(&app.db, &event_id).to_vec();
+    if zaps.is_empty() {
+        return;
+    }
+    let sats: u64 = zaps.iter().map(|zap| zap.msats / 1000).sum();
+    let details: Vec<String> = zaps
+        .iter()
+        .map(|zap| {
+            let line = format!(
+                "{}: {} sats",
+                app.display_name(&zap.sender),
+                zap.msats / 1000
+            );
+            if zap.comment.is_empty() {
+                line
+            } else {
+                format!("{} \"{}\"", line, zap.comment)
+            }
+        })
+        .collect();
+    ui.label(
+        RichText::new(format!(
+            "⚡ {} sats from {} zap{}",
+            sats,
+            zaps.len(),
+            if zaps.len() == 1 { "" } else { "s" }
+        ))
+        .color(style::ACCENT),
+    )
+    .on_hover_text(details.join("\n"));
+}
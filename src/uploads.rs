@@ -0,0 +1,409 @@
+//! Uploads attachments to a Blossom server (BUD-02) in the background, so the compose window
+//! can show them while they go up.
+//!
+//! Blobs go up as they are and are public to anyone with the link, so attachments are only
+//! staged when they're added, and uploaded once the user sends the message and agrees to it.
+
+use crate::clock;
+use crate::mail_event::Attachment;
+use base64::Engine;
+use eframe::egui;
+use nostr::hashes::{sha256, Hash};
+use nostr::{EventBuilder, JsonUtil, Keys, Kind, Tag, TagKind};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+pub const UPLOAD_SERVER_KEY: &str = "upload_server";
+pub const DEFAULT_UPLOAD_SERVER: &str = "https://blossom.primal.net";
+
+/// The kind of the event that authorizes an upload, from BUD-01.
+const AUTH_KIND: u16 = 24242;
+/// How long the server may use our authorization for.
+const AUTH_EXPIRY: Duration = Duration::from_secs(5 * 60);
+/// The most an upload server may ask for one file. Hoot won't pay invoices for more.
+pub const MAX_PAYMENT_SATS: u64 = 5_000;
+/// Thumbnails in the compose footer fit in a square this many pixels wide.
+const THUMBNAIL_SIZE: u32 = 64;
+/// Carries the invoice of a server that wants paying, and the preimage that proves we did (BUD-07).
+const PAYMENT_HEADER: &str = "X-Lightning";
+
+pub type UploadId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadStatus {
+    /// Attached, but kept here until the message is sent.
+    Staged,
+    InProgress,
+    Complete(Attachment),
+    Failed(String),
+    /// The server wants `invoice` paid before it takes the file (BUD-07).
+    PaymentRequired {
+        invoice: String,
+    },
+}
+
+pub struct Upload {
+    pub name: String,
+    pub status: UploadStatus,
+    /// A preview of images, once the worker has decoded one.
+    pub thumbnail: Option<egui::TextureHandle>,
+}
+
+/// Where the bytes of an upload come from.
+#[derive(Clone)]
+pub enum Source {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+enum UploadUpdate {
+    Thumbnail(egui::ColorImage),
+    Finished(Result<Attachment, UploadError>),
+}
+
+enum UploadError {
+    Failed(String),
+    /// Kept with the file's bytes, to send again once paid.
+    PaymentRequired {
+        invoice: String,
+        data: Vec<u8>,
+    },
+}
+
+impl From<String> for UploadError {
+    fn from(e: String) -> Self {
+        UploadError::Failed(e)
+    }
+}
+
+struct UploadMessage {
+    id: UploadId,
+    update: UploadUpdate,
+}
+
+/// What a Blossom server answers an upload with.
+#[derive(Deserialize)]
+struct BlobDescriptor {
+    url: String,
+    sha256: String,
+}
+
+pub struct UploadManager {
+    /// The Blossom server attachments go to.
+    pub server: String,
+    uploads: HashMap<UploadId, Upload>,
+    /// Where the bytes of staged uploads are.
+    staged: HashMap<UploadId, Source>,
+    /// The bytes of uploads waiting for their invoice to be paid.
+    unpaid: HashMap<UploadId, Vec<u8>>,
+    next_id: UploadId,
+    sender: Sender<UploadMessage>,
+    receiver: Receiver<UploadMessage>,
+}
+
+impl UploadManager {
+    pub fn new(server: String) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self {
+            server,
+            uploads: HashMap::new(),
+            staged: HashMap::new(),
+            unpaid: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn get(&self, id: UploadId) -> Option<&Upload> {
+        self.uploads.get(&id)
+    }
+
+    /// Attaches `source` as `name`, to upload with [`Self::upload_staged`] when the message is
+    /// sent. Images get a preview made in the background meanwhile.
+    pub fn stage(&mut self, name: String, source: Source) -> UploadId {
+        let id = self.next_id;
+        self.next_id += 1;
+        if mime_type(&name).starts_with("image/") {
+            self.preview(id, source.clone());
+        }
+        self.uploads.insert(
+            id,
+            Upload {
+                name,
+                status: UploadStatus::Staged,
+                thumbnail: None,
+            },
+        );
+        self.staged.insert(id, source);
+        id
+    }
+
+    /// Starts uploading the staged upload `id`, with the server's authorization signed by
+    /// `keys`.
+    pub fn upload_staged(&mut self, keys: &Keys, id: UploadId) {
+        let (Some(upload), Some(source)) = (self.uploads.get_mut(&id), self.staged.remove(&id))
+        else {
+            return;
+        };
+        upload.status = UploadStatus::InProgress;
+        let name = upload.name.clone();
+        self.start(id, keys, name, source, None);
+    }
+
+    fn preview(&self, id: UploadId, source: Source) {
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let data = match source {
+                Source::File(path) => match fs::read(&path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        debug!("Couldn't read {:?} for a preview: {}", path, e);
+                        return;
+                    }
+                },
+                Source::Bytes(data) => data,
+            };
+            if let Some(image) = thumbnail(&data) {
+                let _ = sender.send(UploadMessage {
+                    id,
+                    update: UploadUpdate::Thumbnail(image),
+                });
+            }
+        });
+    }
+
+    /// Sends an upload the server wanted paying for again, with `preimage` as the proof its
+    /// invoice was paid.
+    pub fn retry_paid(&mut self, keys: &Keys, id: UploadId, preimage: String) {
+        let (Some(upload), Some(data)) = (self.uploads.get_mut(&id), self.unpaid.remove(&id))
+        else {
+            return;
+        };
+        upload.status = UploadStatus::InProgress;
+        let name = upload.name.clone();
+        self.start(id, keys, name, Source::Bytes(data), Some(preimage));
+    }
+
+    fn start(
+        &self,
+        id: UploadId,
+        keys: &Keys,
+        name: String,
+        source: Source,
+        preimage: Option<String>,
+    ) {
+        let sender = self.sender.clone();
+        let server = self.server.clone();
+        let keys = keys.clone();
+        thread::spawn(move || {
+            let result = upload(&server, &keys, &name, source, preimage);
+            match &result {
+                Ok(attachment) => info!("Uploaded {} to {}", name, attachment.url),
+                Err(UploadError::Failed(e)) => {
+                    error!("Failed to upload {} to {}: {}", name, server, e)
+                }
+                Err(UploadError::PaymentRequired { .. }) => {
+                    info!("{} wants paying to take {}", server, name)
+                }
+            }
+            if sender
+                .send(UploadMessage {
+                    id,
+                    update: UploadUpdate::Finished(result),
+                })
+                .is_err()
+            {
+                debug!("Upload receiver dropped before upload finished");
+            }
+        });
+    }
+
+    /// Drops an upload the compose window no longer wants. One still going carries on, but
+    /// nothing refers to it.
+    pub fn forget(&mut self, id: UploadId) {
+        self.uploads.remove(&id);
+        self.staged.remove(&id);
+        self.unpaid.remove(&id);
+    }
+
+    pub fn process_queue(&mut self, ctx: &egui::Context) {
+        while let Ok(message) = self.receiver.try_recv() {
+            let Some(upload) = self.uploads.get_mut(&message.id) else {
+                continue;
+            };
+            match message.update {
+                UploadUpdate::Thumbnail(image) => {
+                    upload.thumbnail = Some(ctx.load_texture(
+                        format!("upload-{}", message.id),
+                        image,
+                        Default::default(),
+                    ));
+                }
+                UploadUpdate::Finished(Ok(attachment)) => {
+                    upload.status = UploadStatus::Complete(attachment)
+                }
+                UploadUpdate::Finished(Err(UploadError::Failed(e))) => {
+                    upload.status = UploadStatus::Failed(e)
+                }
+                UploadUpdate::Finished(Err(UploadError::PaymentRequired { invoice, data })) => {
+                    upload.status = UploadStatus::PaymentRequired { invoice };
+                    self.unpaid.insert(message.id, data);
+                }
+            }
+        }
+
+        // The worker threads can't wake us up, so keep polling while anything is in flight.
+        if self
+            .uploads
+            .values()
+            .any(|upload| upload.status == UploadStatus::InProgress)
+        {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
+
+fn upload(
+    server: &str,
+    keys: &Keys,
+    name: &str,
+    source: Source,
+    preimage: Option<String>,
+) -> Result<Attachment, UploadError> {
+    if !(server.starts_with("https://") || server.starts_with("http://")) {
+        return Err(format!("unsupported server {}", server).into());
+    }
+    let data = match source {
+        Source::File(path) => {
+            fs::read(&path).map_err(|e| format!("could not read {:?}: {}", path, e))?
+        }
+        Source::Bytes(data) => data,
+    };
+    let mime_type = mime_type(name);
+
+    let sha256 = sha256::Hash::hash(&data).to_string();
+    let auth = EventBuilder::new(Kind::Custom(AUTH_KIND), format!("Upload {}", name))
+        .tags([
+            Tag::hashtag("upload"),
+            Tag::custom(TagKind::custom("x"), [sha256.clone()]),
+            Tag::expiration(clock::now() + AUTH_EXPIRY),
+        ])
+        .custom_created_at(clock::now())
+        .sign_with_keys(keys)
+        .map_err(|e| format!("could not sign the authorization: {}", e))?;
+    let authorization = format!(
+        "Nostr {}",
+        base64::engine::general_purpose::STANDARD.encode(auth.as_json())
+    );
+
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let size = data.len() as u64;
+    let mut request = client
+        .put(format!("{}/upload", server.trim_end_matches('/')))
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .header(reqwest::header::CONTENT_TYPE, mime_type);
+    if let Some(preimage) = preimage {
+        request = request.header(PAYMENT_HEADER, preimage);
+    }
+    let response = request
+        .body(data.clone())
+        .send()
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if status == reqwest::StatusCode::PAYMENT_REQUIRED {
+        if let Some(invoice) = response
+            .headers()
+            .get(PAYMENT_HEADER)
+            .and_then(|invoice| invoice.to_str().ok())
+        {
+            return Err(UploadError::PaymentRequired {
+                invoice: invoice.to_string(),
+                data,
+            });
+        }
+    }
+    if !status.is_success() {
+        // Blossom servers say why in a header.
+        let reason = response
+            .headers()
+            .get("X-Reason")
+            .and_then(|reason| reason.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        return Err(format!("server responded with {} {}", status, reason)
+            .trim_end()
+            .to_string()
+            .into());
+    }
+    let body = response.text().map_err(|e| e.to_string())?;
+
+    let descriptor: BlobDescriptor =
+        serde_json::from_str(&body).map_err(|e| format!("unexpected response: {}", e))?;
+    if descriptor.sha256 != sha256 {
+        return Err(format!("server stored {} instead of {}", descriptor.sha256, sha256).into());
+    }
+    Ok(Attachment {
+        url: descriptor.url,
+        sha256,
+        mime_type: Some(mime_type.to_string()),
+        size: Some(size),
+        name: Some(name.to_string()),
+    })
+}
+
+fn thumbnail(data: &[u8]) -> Option<egui::ColorImage> {
+    let image = image::load_from_memory(data)
+        .ok()?
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    Some(egui::ColorImage::from_rgba_unmultiplied(
+        size,
+        image.as_raw(),
+    ))
+}
+
+/// Guesses the MIME type from the file extension, for the server and the `imeta` tag.
+fn mime_type(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "ics" => crate::calendar::CALENDAR_MIME_TYPE,
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Encodes the image on the clipboard as a PNG, `None` if there isn't one.
+pub fn clipboard_image() -> Option<Vec<u8>> {
+    let image = arboard::Clipboard::new().ok()?.get_image().ok()?;
+    let image = image::RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )?;
+    let mut png = std::io::Cursor::new(Vec::new());
+    if let Err(e) = image.write_to(&mut png, image::ImageFormat::Png) {
+        error!("Failed to encode the pasted image: {}", e);
+        return None;
+    }
+    Some(png.into_inner())
+}
@@ -0,0 +1,375 @@
+//! Zaps sent from the thread view and zaps received on our messages, see `hoot_core::zap`.
+//! Invoices are fetched in the background and paid through the connected wallet.
+//!
+//! Receipts for zaps on our messages wait until we know the key our own lightning address signs
+//! receipts with, fetched from its pay endpoint, and only count if they're signed with it.
+
+use crate::db::Db;
+use crate::payments::{Payments, Purpose};
+use crate::zap::{self, PayEndpoint, Zap};
+use eframe::egui;
+use nostr::{Event, EventId, Keys, PublicKey, TagKind};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+/// The amounts offered in the Zap menu, in sats.
+pub const AMOUNTS: [u64; 4] = [21, 100, 1_000, 5_000];
+/// Receipts kept while their recipient's lightning address is looked up. Past this the oldest
+/// are dropped, so a relay flooding receipts can't fill up memory.
+const MAX_UNCHECKED: usize = 500;
+/// How long to wait before looking up a receipt key again after a failed lookup, doubled for
+/// each failure in a row up to `MAX_KEY_RETRY`.
+const KEY_RETRY: Duration = Duration::from_secs(30);
+const MAX_KEY_RETRY: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZapStatus {
+    /// Asking the lightning address for an invoice.
+    Fetching,
+    Paying,
+    Sent,
+    /// The wallet didn't say whether it paid.
+    Unanswered,
+    Failed(String),
+}
+
+/// What a pay endpoint's callback answers with.
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    pr: Option<String>,
+    reason: Option<String>,
+}
+
+/// The key a lightning address signs zap receipts with, as far as we know it.
+enum ReceiptKey {
+    /// Looking it up, after `failures` lookups that failed.
+    Fetching {
+        failures: u32,
+    },
+    Known(PublicKey),
+    /// The last `failures` lookups failed, the next one is at `retry_at`.
+    Failed {
+        failures: u32,
+        retry_at: Instant,
+    },
+}
+
+pub struct ZapManager {
+    /// Zaps we're sending or sent this session, by message id.
+    sending: HashMap<String, ZapStatus>,
+    /// Zaps on our messages by message id, loaded when the thread view asks.
+    received: HashMap<String, Vec<Zap>>,
+    /// The note typed in the Zap menu.
+    pub comment: String,
+    /// Whether the Zap menu signs with a throwaway key instead of the account's.
+    pub anonymous: bool,
+    sender: Sender<(String, Result<String, String>)>,
+    receiver: Receiver<(String, Result<String, String>)>,
+    /// Receipts for zaps on our messages not checked yet, with the account they're for in hex.
+    unchecked: Vec<(String, Event)>,
+    /// By lightning address.
+    receipt_keys: HashMap<String, ReceiptKey>,
+    key_sender: Sender<(String, Result<PublicKey, String>)>,
+    key_receiver: Receiver<(String, Result<PublicKey, String>)>,
+}
+
+impl ZapManager {
+    pub fn new() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (key_sender, key_receiver) = std::sync::mpsc::channel();
+        Self {
+            sending: HashMap::new(),
+            received: HashMap::new(),
+            comment: String::new(),
+            anonymous: false,
+            sender,
+            receiver,
+            unchecked: Vec::new(),
+            receipt_keys: HashMap::new(),
+            key_sender,
+            key_receiver,
+        }
+    }
+
+    pub fn status(&self, message: &EventId) -> Option<&ZapStatus> {
+        self.sending.get(&message.to_hex())
+    }
+
+    /// Zaps `sats` to `recipient` at `lud16` for `message`, signed by `keys` unless zapping
+    /// anonymously, with the receipt going to `relays`. Private messages aren't named in the
+    /// public zap request, see [`zap::request`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn zap(
+        &mut self,
+        keys: &Keys,
+        recipient: PublicKey,
+        lud16: &str,
+        message: EventId,
+        private: bool,
+        sats: u64,
+        relays: Vec<String>,
+    ) {
+        let id = message.to_hex();
+        self.sending.insert(id.clone(), ZapStatus::Fetching);
+        let comment = std::mem::take(&mut self.comment);
+        let keys = if self.anonymous {
+            Keys::generate()
+        } else {
+            keys.clone()
+        };
+        let tagged = (!private).then_some(message);
+        let lud16 = lud16.to_string();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let msats = sats * 1000;
+            let invoice = zap::request(&keys, recipient, tagged, msats, &relays, &comment)
+                .map_err(|e| e.to_string())
+                .and_then(|request| fetch_invoice(&lud16, &request, msats));
+            if sender.send((id, invoice)).is_err() {
+                debug!("Zap receiver dropped before the invoice came back");
+            }
+        });
+    }
+
+    /// The wallet paid the zap for `message`.
+    pub fn paid(&mut self, message: &str) {
+        info!("Zapped {}", message);
+        self.sending.insert(message.to_string(), ZapStatus::Sent);
+    }
+
+    /// Pays the invoices that came back, and notices zaps the wallet failed to pay.
+    pub fn process_queue(&mut self, payments: &mut Payments, ctx: &egui::Context) {
+        while let Ok((message, invoice)) = self.receiver.try_recv() {
+            match invoice {
+                Ok(invoice) => {
+                    payments.pay(&invoice, Purpose::Zap(message.clone()));
+                    self.sending.insert(message, ZapStatus::Paying);
+                }
+                Err(e) => {
+                    error!("Failed to get a zap invoice for {}: {}", message, e);
+                    self.sending.insert(message, ZapStatus::Failed(e));
+                }
+            }
+        }
+
+        for (message, status) in &mut self.sending {
+            if *status == ZapStatus::Paying && !payments.paying_zap(message) {
+                *status = if payments.unanswered_zap(message) {
+                    ZapStatus::Unanswered
+                } else {
+                    let reason = payments.error.clone();
+                    ZapStatus::Failed(reason.unwrap_or_else(|| "not paid".to_string()))
+                };
+            }
+        }
+
+        // The fetch threads can't wake us up, so keep polling while they run.
+        if self.sending.values().any(|s| *s == ZapStatus::Fetching) {
+            ctx.request_repaint_after(Duration::from_millis(200));
+        }
+    }
+
+    /// Takes in a zap receipt that came from a relay, to check once we know who signs the
+    /// receipts of the account it's for. Receipts for others are ignored.
+    pub fn receive(&mut self, receipt: &Event, accounts: &[Keys]) {
+        let recipient = receipt
+            .tags
+            .find(TagKind::p())
+            .and_then(|tag| tag.content())
+            .filter(|recipient| {
+                accounts
+                    .iter()
+                    .any(|keys| keys.public_key().to_hex() == *recipient)
+            });
+        let Some(recipient) = recipient else {
+            debug!("Ignoring zap receipt {} for someone else", receipt.id);
+            return;
+        };
+        if self.unchecked.len() >= MAX_UNCHECKED {
+            debug!("Too many zap receipts waiting, dropping the oldest");
+            self.unchecked.remove(0);
+        }
+        self.unchecked
+            .push((recipient.to_string(), receipt.clone()));
+    }
+
+    /// The accounts with receipts waiting, whose lightning addresses [`Self::check_receipts`]
+    /// needs.
+    pub fn unchecked_recipients(&self) -> Vec<String> {
+        let mut recipients: Vec<String> = self
+            .unchecked
+            .iter()
+            .map(|(recipient, _)| recipient.clone())
+            .collect();
+        recipients.sort();
+        recipients.dedup();
+        recipients
+    }
+
+    /// Stores the waiting receipts signed by their recipient's lightning address, given the
+    /// addresses of the accounts whose profile is loaded. Receipts for accounts without one
+    /// are dropped, and addresses we don't know the key of yet are looked up.
+    pub fn check_receipts(
+        &mut self,
+        db: &Db,
+        addresses: &HashMap<String, Option<String>>,
+        ctx: &egui::Context,
+    ) {
+        while let Ok((lud16, key)) = self.key_receiver.try_recv() {
+            let key = match key {
+                Ok(key) => ReceiptKey::Known(key),
+                Err(e) => {
+                    let failures = match self.receipt_keys.get(&lud16) {
+                        Some(ReceiptKey::Fetching { failures }) => failures + 1,
+                        _ => 1,
+                    };
+                    let backoff = KEY_RETRY
+                        .saturating_mul(1 << (failures - 1).min(16))
+                        .min(MAX_KEY_RETRY);
+                    error!(
+                        "Failed to look up who signs zap receipts for {}, retrying in {}s: {}",
+                        lud16,
+                        backoff.as_secs(),
+                        e
+                    );
+                    ReceiptKey::Failed {
+                        failures,
+                        retry_at: Instant::now() + backoff,
+                    }
+                }
+            };
+            self.receipt_keys.insert(lud16, key);
+        }
+
+        let mut waiting = Vec::new();
+        for (recipient, receipt) in std::mem::take(&mut self.unchecked) {
+            let Some(lud16) = addresses.get(&recipient) else {
+                waiting.push((recipient, receipt));
+                continue;
+            };
+            let Some(lud16) = lud16 else {
+                debug!("Ignoring zap receipt {}: no lightning address", receipt.id);
+                continue;
+            };
+            match self.receipt_keys.get(lud16) {
+                Some(&ReceiptKey::Known(key)) => self.store(db, &receipt, &key),
+                // Kept until the lookup is retried, `MAX_UNCHECKED` bounds how many wait.
+                Some(&ReceiptKey::Failed { failures, retry_at }) => {
+                    if Instant::now() >= retry_at {
+                        self.fetch_receipt_key(lud16, failures);
+                    }
+                    waiting.push((recipient, receipt));
+                }
+                Some(ReceiptKey::Fetching { .. }) => waiting.push((recipient, receipt)),
+                None => {
+                    self.fetch_receipt_key(lud16, 0);
+                    waiting.push((recipient, receipt));
+                }
+            }
+        }
+        self.unchecked = waiting;
+
+        // The fetch threads can't wake us up, so keep polling while they run, and wake up for
+        // the next retry of a failed lookup.
+        let mut next_retry = None;
+        for key in self.receipt_keys.values() {
+            match key {
+                ReceiptKey::Fetching { .. } => next_retry = Some(Duration::from_millis(200)),
+                ReceiptKey::Failed { retry_at, .. } if !self.unchecked.is_empty() => {
+                    let wait = retry_at.saturating_duration_since(Instant::now());
+                    next_retry = Some(next_retry.map_or(wait, |next: Duration| next.min(wait)));
+                }
+                _ => {}
+            }
+        }
+        if let Some(wait) = next_retry {
+            ctx.request_repaint_after(wait);
+        }
+    }
+
+    fn fetch_receipt_key(&mut self, lud16: &str, failures: u32) {
+        self.receipt_keys
+            .insert(lud16.to_string(), ReceiptKey::Fetching { failures });
+        let lud16 = lud16.to_string();
+        let sender = self.key_sender.clone();
+        thread::spawn(move || {
+            let key = fetch_endpoint(&lud16)
+                .and_then(|endpoint| endpoint.receipt_key().map_err(|e| e.to_string()));
+            if sender.send((lud16, key)).is_err() {
+                debug!("Zap receiver dropped before the receipt key came back");
+            }
+        });
+    }
+
+    fn store(&mut self, db: &Db, receipt: &Event, receipt_key: &PublicKey) {
+        let zap = match Zap::from_receipt(receipt, receipt_key) {
+            Ok(zap) => zap,
+            Err(e) => {
+                debug!("Ignoring zap receipt {}: {}", receipt.id, e);
+                return;
+            }
+        };
+        match db.save_zap(&zap) {
+            Ok(true) => {
+                info!("Got a zap of {} sats on {}", zap.msats / 1000, zap.event_id);
+                self.received.remove(&zap.event_id);
+            }
+            Ok(false) => {}
+            Err(e) => error!("Failed to save zap receipt {}: {}", receipt.id, e),
+        }
+    }
+
+    /// The zaps on `message`.
+    pub fn zaps_on(&mut self, db: &Db, message: &EventId) -> &[Zap] {
+        let id = message.to_hex();
+        self.received
+            .entry(id)
+            .or_insert_with_key(|id| match db.get_zaps(id) {
+                Ok(zaps) => zaps,
+                Err(e) => {
+                    error!("Failed to load the zaps on {}: {}", id, e);
+                    Vec::new()
+                }
+            })
+    }
+}
+
+fn get(lud16: &str, url: &str) -> Result<String, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+        .map_err(|e| format!("could not build HTTP client: {}", e))?
+        .get(url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| format!("could not reach {}: {}", lud16, e))
+}
+
+fn fetch_endpoint(lud16: &str) -> Result<PayEndpoint, String> {
+    let endpoint_url =
+        zap::endpoint_url(lud16).ok_or_else(|| format!("{} isn't a lightning address", lud16))?;
+    PayEndpoint::from_json(&get(lud16, &endpoint_url)?).map_err(|e| e.to_string())
+}
+
+fn fetch_invoice(lud16: &str, request: &Event, msats: u64) -> Result<String, String> {
+    let endpoint = fetch_endpoint(lud16)?;
+    let invoice_url = endpoint
+        .invoice_url(request, msats)
+        .map_err(|e| e.to_string())?;
+    let response: InvoiceResponse = serde_json::from_str(&get(lud16, &invoice_url)?)
+        .map_err(|e| format!("unexpected answer from {}: {}", lud16, e))?;
+    let invoice = response.pr.ok_or_else(|| {
+        response
+            .reason
+            .unwrap_or_else(|| format!("{} sent no invoice", lud16))
+    })?;
+    // Never pay what the endpoint made up, only the zap we asked for.
+    zap::check_invoice(&invoice, request, msats)
+        .map_err(|e| format!("refusing the invoice from {}: {}", lud16, e))?;
+    Ok(invoice)
+}